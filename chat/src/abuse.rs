@@ -0,0 +1,235 @@
+use crate::{auth::AuthUser, AppError, AppState};
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+use uuid::Uuid;
+
+// Signals older than this no longer count towards the score
+pub const ABUSE_WINDOW_SECONDS: i64 = 7 * 24 * 60 * 60;
+// Messages per minute allowed across all rooms before any penalty applies
+pub const BASE_USER_RATE_LIMIT: u32 = 30;
+
+/// Something that counts against a user's standing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbuseSignal {
+    ReportReceived,
+    FilterHit,
+    Muted,
+}
+
+impl AbuseSignal {
+    pub const ALL: [AbuseSignal; 3] = [AbuseSignal::ReportReceived, AbuseSignal::FilterHit, AbuseSignal::Muted];
+
+    pub fn weight(self) -> u32 {
+        match self {
+            AbuseSignal::FilterHit => 1,
+            AbuseSignal::ReportReceived => 3,
+            AbuseSignal::Muted => 10,
+        }
+    }
+
+    fn key(self, user_id: &str) -> String {
+        let name = match self {
+            AbuseSignal::ReportReceived => "reports",
+            AbuseSignal::FilterHit => "filter_hits",
+            AbuseSignal::Muted => "mutes",
+        };
+        format!("abuse:{}:{}", user_id, name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AbuseTier {
+    Normal,
+    Elevated,
+    High,
+}
+
+impl AbuseTier {
+    pub fn from_score(score: u32) -> Self {
+        match score {
+            0..=9 => AbuseTier::Normal,
+            10..=29 => AbuseTier::Elevated,
+            _ => AbuseTier::High,
+        }
+    }
+
+    /// Scales a messages-per-minute limit down for users with a poor record.
+    pub fn apply_to_rate_limit(self, per_minute: u32) -> u32 {
+        match self {
+            AbuseTier::Normal => per_minute,
+            AbuseTier::Elevated => (per_minute / 2).max(1),
+            AbuseTier::High => (per_minute / 5).max(1),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AbuseScore {
+    pub user_id: String,
+    pub score: u32,
+    pub reports: u32,
+    pub filter_hits: u32,
+    pub mutes: u32,
+}
+
+impl AbuseScore {
+    pub fn from_counts(user_id: &str, reports: u32, filter_hits: u32, mutes: u32) -> Self {
+        let score = reports * AbuseSignal::ReportReceived.weight()
+            + filter_hits * AbuseSignal::FilterHit.weight()
+            + mutes * AbuseSignal::Muted.weight();
+        AbuseScore {
+            user_id: user_id.to_string(),
+            score,
+            reports,
+            filter_hits,
+            mutes,
+        }
+    }
+
+    pub fn tier(&self) -> AbuseTier {
+        AbuseTier::from_score(self.score)
+    }
+}
+
+pub async fn record_signal(pool: &deadpool_redis::Pool, user_id: &str, signal: AbuseSignal) {
+    let key = signal.key(user_id);
+    let now = Utc::now().timestamp();
+    match pool.get().await {
+        Ok(mut conn) => {
+            let result: redis::RedisResult<()> = redis::pipe()
+                .zadd(&key, Uuid::new_v4().to_string(), now)
+                .expire(&key, ABUSE_WINDOW_SECONDS)
+                .query_async(&mut conn)
+                .await;
+            if let Err(e) = result {
+                error!("Failed to record abuse signal for {}: {}", user_id, e);
+            }
+        }
+        Err(e) => error!("Failed to get Redis connection for abuse signal: {}", e),
+    }
+}
+
+/// Current rolling score; Redis errors count as a clean record.
+pub async fn get_score(pool: &deadpool_redis::Pool, user_id: &str) -> AbuseScore {
+    let cutoff = Utc::now().timestamp() - ABUSE_WINDOW_SECONDS;
+    let mut pipe = redis::pipe();
+    for signal in AbuseSignal::ALL {
+        let key = signal.key(user_id);
+        pipe.zrembyscore(&key, "-inf", cutoff).ignore().zcard(&key);
+    }
+
+    let counts: Vec<u32> = match pool.get().await {
+        Ok(mut conn) => match pipe.query_async(&mut conn).await {
+            Ok(counts) => counts,
+            Err(e) => {
+                error!("Failed to read abuse score for {}: {}", user_id, e);
+                return AbuseScore::from_counts(user_id, 0, 0, 0);
+            }
+        },
+        Err(e) => {
+            error!("Failed to get Redis connection for abuse score: {}", e);
+            return AbuseScore::from_counts(user_id, 0, 0, 0);
+        }
+    };
+
+    AbuseScore::from_counts(user_id, counts[0], counts[1], counts[2])
+}
+
+/// Cross-room limit for users with a poor record. Returns the seconds until
+/// the user may send again when they are over it.
+pub async fn check_rate_limit(pool: &deadpool_redis::Pool, user_id: &str) -> Result<(), u64> {
+    let tier = get_score(pool, user_id).await.tier();
+    if tier == AbuseTier::Normal {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp();
+    let key = format!("abuse_rl:{}:{}", user_id, now / 60);
+    let count: u32 = match pool.get().await {
+        Ok(mut conn) => {
+            let result: redis::RedisResult<(u32,)> = redis::pipe()
+                .incr(&key, 1)
+                .expire(&key, 60)
+                .ignore()
+                .query_async(&mut conn)
+                .await;
+            match result {
+                Ok((count,)) => count,
+                Err(e) => {
+                    error!("Failed to update abuse rate limit for {}: {}", user_id, e);
+                    return Ok(());
+                }
+            }
+        }
+        Err(_) => return Ok(()),
+    };
+
+    if count > tier.apply_to_rate_limit(BASE_USER_RATE_LIMIT) {
+        warn!("Rate limiting user {} ({:?} abuse tier)", user_id, tier);
+        Err((60 - now % 60) as u64)
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+pub struct AbuseScoreResponse {
+    #[serde(flatten)]
+    score: AbuseScore,
+    tier: AbuseTier,
+    rate_limit_per_minute: u32,
+}
+
+impl From<AbuseScore> for AbuseScoreResponse {
+    fn from(score: AbuseScore) -> Self {
+        let tier = score.tier();
+        AbuseScoreResponse {
+            score,
+            tier,
+            rate_limit_per_minute: tier.apply_to_rate_limit(BASE_USER_RATE_LIMIT),
+        }
+    }
+}
+
+// GET /api/moderation/users/:user_id/abuse-score
+pub async fn get_abuse_score_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    moderator: AuthUser,
+) -> Result<Json<AbuseScoreResponse>, AppError> {
+    if !moderator.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+
+    let score = get_score(&state.redis_pool, &user_id).await;
+    Ok(Json(score.into()))
+}
+
+#[derive(Deserialize)]
+pub struct RecordSignalRequest {
+    signal: AbuseSignal,
+}
+
+// POST /api/moderation/users/:user_id/abuse-signals
+pub async fn record_signal_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    moderator: AuthUser,
+    Json(req): Json<RecordSignalRequest>,
+) -> Result<Json<AbuseScoreResponse>, AppError> {
+    if !moderator.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+
+    tracing::info!("Moderator {} recorded {:?} for user {}", moderator.username, req.signal, user_id);
+    record_signal(&state.redis_pool, &user_id, req.signal).await;
+    let score = get_score(&state.redis_pool, &user_id).await;
+    Ok(Json(score.into()))
+}
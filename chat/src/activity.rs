@@ -0,0 +1,78 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// Repeated "still typing" updates inside this window are not rebroadcast
+pub const ACTIVITY_DEBOUNCE: Duration = Duration::from_secs(3);
+// An activity with no refresh for this long is considered stopped
+pub const ACTIVITY_TIMEOUT: Duration = Duration::from_secs(6);
+// How often sockets check for expired activities
+pub const ACTIVITY_TICK: Duration = Duration::from_secs(1);
+
+/// What a user is doing in the composer area.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivityKind {
+    Typing,
+    RecordingAudio,
+    UploadingPhoto,
+    ChoosingGif,
+}
+
+/// Per-socket activity state deciding which client updates are worth
+/// broadcasting to the room.
+#[derive(Debug, Default)]
+pub struct ActivityTracker {
+    // kind -> (last refresh from the client, last broadcast to the room)
+    active: HashMap<ActivityKind, (Instant, Instant)>,
+}
+
+impl ActivityTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a client update and returns the state to broadcast, if any.
+    pub fn update(&mut self, kind: ActivityKind, active: bool, now: Instant) -> Option<bool> {
+        if !active {
+            return self.active.remove(&kind).map(|_| false);
+        }
+
+        match self.active.get_mut(&kind) {
+            Some((last_refresh, last_broadcast)) => {
+                *last_refresh = now;
+                // Rebroadcast occasionally so receivers' own expiry doesn't fire
+                if now.duration_since(*last_broadcast) >= ACTIVITY_DEBOUNCE {
+                    *last_broadcast = now;
+                    Some(true)
+                } else {
+                    None
+                }
+            }
+            None => {
+                self.active.insert(kind, (now, now));
+                Some(true)
+            }
+        }
+    }
+
+    /// Removes and returns activities the client stopped refreshing.
+    pub fn expire(&mut self, now: Instant) -> Vec<ActivityKind> {
+        let expired: Vec<ActivityKind> = self
+            .active
+            .iter()
+            .filter(|(_, (last_refresh, _))| now.duration_since(*last_refresh) >= ACTIVITY_TIMEOUT)
+            .map(|(kind, _)| *kind)
+            .collect();
+
+        for kind in &expired {
+            self.active.remove(kind);
+        }
+        expired
+    }
+
+    /// Clears everything, e.g. when the socket closes.
+    pub fn drain(&mut self) -> Vec<ActivityKind> {
+        self.active.drain().map(|(kind, _)| kind).collect()
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Timelike, Utc};
+use serde::Serialize;
+use tracing::error;
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+const TOP_ROOMS: usize = 10;
+// How often this instance adds its messages to the shared counter
+const PUBLISH_INTERVAL: Duration = Duration::from_secs(5);
+// Per-minute buckets outlive the two minutes the rolling rate reads
+const BUCKET_TTL_SECONDS: i64 = 180;
+
+/// Shared count of messages sent in the UTC minute `minute` (Unix minutes),
+/// across all instances.
+fn messages_key(minute: i64) -> String {
+    format!("stats:messages:{}", minute)
+}
+
+/// Messages over the last 60 seconds, estimated from the previous and current
+/// minute buckets: the previous one is weighted by how much of it still
+/// falls inside the window.
+pub fn rolling_rate(previous: u64, current: u64, now: DateTime<Utc>) -> f64 {
+    let elapsed = now.second() as f64 + now.nanosecond().min(999_999_999) as f64 / 1e9;
+    previous as f64 * (60.0 - elapsed) / 60.0 + current as f64
+}
+
+/// Adds this instance's recent messages to the shared per-minute counter.
+pub fn spawn_publish(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(PUBLISH_INTERVAL);
+        loop {
+            tick.tick().await;
+            let count = state.room_metrics.take_unpublished();
+            if count == 0 {
+                continue;
+            }
+            let Ok(mut conn) = state.redis_pool.get().await else {
+                continue;
+            };
+            let key = messages_key(Utc::now().timestamp() / 60);
+            let result: redis::RedisResult<()> = redis::pipe()
+                .incr(&key, count)
+                .ignore()
+                .expire(&key, BUCKET_TTL_SECONDS)
+                .ignore()
+                .query_async(&mut conn)
+                .await;
+            if let Err(e) = result {
+                error!("Failed to publish message counts: {}", e);
+            }
+        }
+    })
+}
+
+async fn messages_per_minute(state: &AppState, now: DateTime<Utc>) -> redis::RedisResult<f64> {
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    let minute = now.timestamp() / 60;
+    let (previous, current): (Option<u64>, Option<u64>) = redis::cmd("MGET")
+        .arg(messages_key(minute - 1))
+        .arg(messages_key(minute))
+        .query_async(&mut conn)
+        .await?;
+    Ok(rolling_rate(previous.unwrap_or(0), current.unwrap_or(0), now))
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomStats {
+    pub room_id: String,
+    // Sockets on the answering instance
+    pub local_sockets: usize,
+    // Sockets across all instances, from presence
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sockets: Option<usize>,
+}
+
+/// The busiest `limit` rooms by socket count, busiest first.
+pub fn busiest_rooms(mut socket_counts: Vec<(String, usize)>, limit: usize) -> Vec<(String, usize)> {
+    socket_counts.sort_by(|(a_id, a), (b_id, b)| b.cmp(a).then_with(|| a_id.cmp(b_id)));
+    socket_counts.truncate(limit);
+    socket_counts
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminStats {
+    instance_id: String,
+    // Rooms and sockets on the answering instance
+    active_rooms: usize,
+    total_sockets: usize,
+    // Sockets on every registered instance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cluster_sockets: Option<usize>,
+    // Rolling, across all instances
+    #[serde(skip_serializing_if = "Option::is_none")]
+    messages_per_minute: Option<f64>,
+    // Channels the answering instance listens to through Redis
+    redis_channels: usize,
+    top_rooms: Vec<RoomStats>,
+}
+
+// GET /api/admin/stats - live totals and the busiest rooms; admins only.
+// Cluster-wide figures are left out while Redis is unreachable.
+pub async fn admin_stats_handler(State(state): State<AppState>, user: AuthUser) -> Result<Json<AdminStats>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    let socket_counts = state.connections.read().await.socket_counts();
+    let active_rooms = socket_counts.len();
+    let total_sockets = socket_counts.iter().map(|(_, sockets)| sockets).sum();
+    let busiest = busiest_rooms(socket_counts, TOP_ROOMS);
+
+    let room_ids: Vec<String> = busiest.iter().map(|(room_id, _)| room_id.clone()).collect();
+    let cluster_counts = match crate::presence::room_counts(&state, &room_ids).await {
+        Ok(counts) => Some(counts),
+        Err(e) => {
+            error!("Failed to load room counts for stats: {}", e);
+            None
+        }
+    };
+    let top_rooms = busiest
+        .into_iter()
+        .enumerate()
+        .map(|(index, (room_id, local_sockets))| RoomStats {
+            room_id,
+            local_sockets,
+            sockets: cluster_counts.as_ref().and_then(|counts| counts.get(index)).map(|counts| counts.participants + counts.spectators),
+        })
+        .collect();
+
+    let messages_per_minute = match messages_per_minute(&state, Utc::now()).await {
+        Ok(rate) => Some(rate),
+        Err(e) => {
+            error!("Failed to load message rate: {}", e);
+            None
+        }
+    };
+    let cluster_sockets = crate::instances::registered(&state)
+        .await
+        .ok()
+        .filter(|instances| !instances.is_empty())
+        .map(|instances| instances.iter().map(|instance| instance.sockets).sum());
+
+    Ok(Json(AdminStats {
+        instance_id: state.instance_id.clone(),
+        active_rooms,
+        total_sockets,
+        cluster_sockets,
+        messages_per_minute,
+        redis_channels: state.pubsub.channel_count(),
+        top_rooms,
+    }))
+}
@@ -0,0 +1,282 @@
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use h3o::{CellIndex, LatLng};
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Document},
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    auth::AuthUser,
+    hex::lat_lng,
+    models::{Message, MessageKind},
+    websocket::broadcast_new_message,
+    AppError, AppState,
+};
+
+// One announcement per author this often, so a mistake can't flood every room
+pub const ANNOUNCEMENT_COOLDOWN_SECONDS: u64 = 5 * 60;
+const MAX_CONTENT_LENGTH: usize = 2000;
+const MAX_POLYGON_POINTS: usize = 1000;
+// Hex rooms with no messages for longer aren't worth waking up
+const TARGET_ROOM_WINDOW_DAYS: i64 = 30;
+const MAX_TARGET_ROOMS: usize = 5000;
+const AUDIT_PAGE_SIZE: i64 = 50;
+
+/// Whether a user may post announcements: moderators and city accounts.
+pub fn can_announce(user: &AuthUser) -> bool {
+    user.is_moderator() || user.roles.iter().any(|role| role == "city")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AreaPoint {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Where an announcement goes: every hex room inside a parent cell, or
+/// every hex room whose centre lies inside a polygon.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnnouncementArea {
+    Cell { h3_index: String },
+    Polygon { points: Vec<AreaPoint> },
+}
+
+impl AnnouncementArea {
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            AnnouncementArea::Cell { h3_index } => h3_index
+                .parse::<CellIndex>()
+                .map(|_| ())
+                .map_err(|_| format!("Invalid H3 cell: {}", h3_index)),
+            AnnouncementArea::Polygon { points } => {
+                if points.len() < 3 {
+                    return Err("A polygon needs at least 3 points".to_string());
+                }
+                if points.len() > MAX_POLYGON_POINTS {
+                    return Err(format!("A polygon can have at most {} points", MAX_POLYGON_POINTS));
+                }
+                if points.iter().any(|point| lat_lng(point.latitude, point.longitude).is_err()) {
+                    return Err("Polygon points must be valid coordinates".to_string());
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the hex room for `cell` is in the area.
+    pub fn covers(&self, cell: CellIndex) -> bool {
+        match self {
+            AnnouncementArea::Cell { h3_index } => match h3_index.parse::<CellIndex>() {
+                Ok(parent) => cell.parent(parent.resolution()) == Some(parent),
+                Err(_) => false,
+            },
+            AnnouncementArea::Polygon { points } => {
+                let centre = LatLng::from(cell);
+                polygon_contains(points, centre.lat(), centre.lng())
+            }
+        }
+    }
+}
+
+/// Even-odd test of a point against a polygon in plain degrees. Areas are
+/// city-sized, so neither the antimeridian nor curvature matters.
+pub fn polygon_contains(points: &[AreaPoint], latitude: f64, longitude: f64) -> bool {
+    let mut inside = false;
+    let mut previous = match points.last() {
+        Some(point) => point,
+        None => return false,
+    };
+    for point in points {
+        if (point.latitude > latitude) != (previous.latitude > latitude) {
+            let crossing = point.longitude
+                + (latitude - point.latitude) / (previous.latitude - point.latitude) * (previous.longitude - point.longitude);
+            if longitude < crossing {
+                inside = !inside;
+            }
+        }
+        previous = point;
+    }
+    inside
+}
+
+/// Audit record of an announcement.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Announcement {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub author_id: String,
+    pub author_username: String,
+    pub content: String,
+    pub area: AnnouncementArea,
+    // Rooms the announcement was posted to, filled in once fan-out ends
+    #[serde(default)]
+    pub room_count: u64,
+    // Instance that handled the request
+    #[serde(default)]
+    pub instance_id: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn announcements(state: &AppState) -> Collection<Announcement> {
+    state.database.collection("announcements")
+}
+
+/// Takes the author's announcement slot for the cooldown. Returns the
+/// seconds left when it is taken. Fails open, since notices can be urgent.
+async fn claim_announcement(pool: &deadpool_redis::Pool, user_id: &str) -> Result<(), u64> {
+    let key = format!("announcement:{}", user_id);
+    let Ok(mut conn) = pool.get().await else {
+        return Ok(());
+    };
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(&key)
+        .arg(1)
+        .arg("NX")
+        .arg("EX")
+        .arg(ANNOUNCEMENT_COOLDOWN_SECONDS)
+        .query_async(&mut conn)
+        .await;
+    match claimed {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => {
+            let ttl: i64 = redis::cmd("TTL").arg(&key).query_async(&mut conn).await.unwrap_or(0);
+            Err(ttl.max(1) as u64)
+        }
+        Err(e) => {
+            error!("Failed to check announcement cooldown of {}: {}", user_id, e);
+            Ok(())
+        }
+    }
+}
+
+// Recently active hex rooms in the area
+async fn target_rooms(state: &AppState, area: &AnnouncementArea) -> mongodb::error::Result<Vec<String>> {
+    let rooms: Collection<Document> = state.database.collection("rooms");
+    let since = bson::DateTime::from_millis((Utc::now() - Duration::days(TARGET_ROOM_WINDOW_DAYS)).timestamp_millis());
+    let filter = doc! {
+        "_id": { "$regex": "^[0-9a-f]{15}$" },
+        "last_message_at": { "$gte": since },
+    };
+    let options = FindOptions::builder().projection(doc! { "_id": 1 }).build();
+    let mut cursor = rooms.find(filter, options).await?;
+
+    let mut targets = Vec::new();
+    while let Some(room) = cursor.try_next().await? {
+        let Ok(room_id) = room.get_str("_id") else {
+            continue;
+        };
+        if room_id.parse::<CellIndex>().is_ok_and(|cell| area.covers(cell)) {
+            targets.push(room_id.to_string());
+            if targets.len() == MAX_TARGET_ROOMS {
+                warn!("Announcement area has more than {} active rooms; the rest are skipped", MAX_TARGET_ROOMS);
+                break;
+            }
+        }
+    }
+    Ok(targets)
+}
+
+async fn fan_out(state: &AppState, announcement: &Announcement) {
+    let targets = match target_rooms(state, &announcement.area).await {
+        Ok(targets) => targets,
+        Err(e) => {
+            error!("Failed to find rooms for announcement {:?}: {}", announcement.id, e);
+            return;
+        }
+    };
+
+    let mut posted = 0;
+    for room_id in targets {
+        let mut message = Message::new(
+            room_id,
+            announcement.author_id.clone(),
+            announcement.author_username.clone(),
+            announcement.content.clone(),
+        );
+        message.kind = MessageKind::Announcement;
+        match state.db.create_message(&message).await {
+            Ok(persisted) => {
+                message.id = Some(persisted.id());
+                crate::event_log::append(state, crate::event_log::LogEvent::room_message(&message)).await;
+                broadcast_new_message(state, message).await;
+                posted += 1;
+            }
+            Err(e) => error!("Failed to post announcement to room {}: {}", message.room_id, e),
+        }
+    }
+
+    if let Some(id) = announcement.id {
+        let result = announcements(state)
+            .update_one(doc! { "_id": id }, doc! { "$set": { "room_count": posted as i64 } }, None)
+            .await;
+        if let Err(e) = result {
+            error!("Failed to record room count of announcement {}: {}", id, e);
+        }
+    }
+    info!("Announcement by {} posted to {} rooms", announcement.author_username, posted);
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAnnouncementRequest {
+    pub content: String,
+    pub area: AnnouncementArea,
+}
+
+// POST /api/announcements - posts to every active hex room in the area in
+// the background, moderators and city accounts only
+pub async fn create_announcement_handler(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateAnnouncementRequest>,
+) -> Result<Json<Announcement>, AppError> {
+    if !can_announce(&user) {
+        return Err(AppError::Forbidden);
+    }
+    let content = req.content.trim().to_string();
+    if content.is_empty() || content.chars().count() > MAX_CONTENT_LENGTH {
+        return Err(AppError::BadRequest(format!("Announcements must be 1 to {} characters", MAX_CONTENT_LENGTH)));
+    }
+    req.area.validate().map_err(AppError::BadRequest)?;
+    claim_announcement(&state.redis_pool, &user.user_id)
+        .await
+        .map_err(|retry_after| AppError::TooManyRequests { retry_after })?;
+
+    let mut announcement = Announcement {
+        id: None,
+        author_id: user.user_id.clone(),
+        author_username: user.username.clone(),
+        content,
+        area: req.area,
+        room_count: 0,
+        instance_id: state.instance_id.clone(),
+        created_at: Utc::now(),
+    };
+    let result = announcements(&state).insert_one(&announcement, None).await?;
+    announcement.id = result.inserted_id.as_object_id();
+    info!("User {} announced to {:?}", user.username, announcement.area);
+
+    let fan_out_state = state.clone();
+    let queued = announcement.clone();
+    tokio::spawn(async move { fan_out(&fan_out_state, &queued).await });
+    Ok(Json(announcement))
+}
+
+// GET /api/announcements - latest announcements, for auditing; moderators only
+pub async fn list_announcements_handler(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<Announcement>>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let options = FindOptions::builder().sort(doc! { "_id": -1 }).limit(AUDIT_PAGE_SIZE).build();
+    let latest = announcements(&state).find(doc! {}, options).await?.try_collect().await?;
+    Ok(Json(latest))
+}
@@ -4,8 +4,10 @@ use axum::{
     response::{IntoResponse, Response},
     Json,
 };
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{decode, DecodingKey, Validation};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -17,6 +19,15 @@ pub struct Claims {
     pub iat: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub jti: Option<String>,
+    // Service-wide roles such as "moderator" or "admin"
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    // Set by the auth service once the user's age has been verified
+    #[serde(default)]
+    pub age_verified: bool,
+    // Set by the auth service for verified and official accounts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub badge: Option<crate::badges::Badge>,
 }
 
 #[derive(Debug, Clone)]
@@ -24,6 +35,32 @@ pub struct AuthUser {
     pub user_id: String,
     pub email: String,
     pub username: String,
+    pub roles: Vec<String>,
+    pub age_verified: bool,
+    pub badge: Option<crate::badges::Badge>,
+}
+
+impl From<Claims> for AuthUser {
+    fn from(claims: Claims) -> Self {
+        AuthUser {
+            user_id: claims.user_id,
+            email: claims.email,
+            username: claims.username,
+            roles: claims.roles,
+            age_verified: claims.age_verified,
+            badge: claims.badge,
+        }
+    }
+}
+
+impl AuthUser {
+    pub fn is_admin(&self) -> bool {
+        self.roles.iter().any(|role| role == "admin")
+    }
+
+    pub fn is_moderator(&self) -> bool {
+        self.is_admin() || self.roles.iter().any(|role| role == "moderator")
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -78,14 +115,61 @@ where
         let token_data = decode::<Claims>(token, &decoding_key, &Validation::default())
             .map_err(|_| AuthError::InvalidToken)?;
 
-        Ok(AuthUser {
-            user_id: token_data.claims.user_id.clone(),
-            email: token_data.claims.email,
-            username: token_data.claims.username,
-        })
+        Ok(AuthUser::from(token_data.claims))
+    }
+}
+
+/// A trusted internal service (e.g. compliance tooling) calling with the
+/// shared `INTERNAL_API_TOKEN`. Requests are refused when it is unset.
+#[derive(Debug, Clone)]
+pub struct InternalCaller {
+    // Who is acting, from `X-Internal-Caller`, for audit trails
+    pub name: String,
+}
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for InternalCaller
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let expected = std::env::var("INTERNAL_API_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+            .ok_or(AuthError::InvalidToken)?;
+        let token = parts
+            .headers
+            .get("x-internal-token")
+            .and_then(|value| value.to_str().ok())
+            .ok_or(AuthError::MissingToken)?;
+        if !secrets_match(token, &expected) {
+            return Err(AuthError::InvalidToken);
+        }
+
+        let name = parts
+            .headers
+            .get("x-internal-caller")
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("unknown")
+            .to_string();
+        Ok(InternalCaller { name })
     }
 }
 
+/// Compares a presented shared secret with the expected one in constant
+/// time. Both are MACed first, so neither the contents nor the length of the
+/// expected secret show in the timing.
+pub fn secrets_match(presented: &str, expected: &str) -> bool {
+    let mac = |value: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(expected.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(value.as_bytes());
+        mac
+    };
+    mac(presented).verify_slice(&mac(expected).finalize().into_bytes()).is_ok()
+}
+
 pub fn verify_token(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key-here".to_string());
     let decoding_key = DecodingKey::from_secret(secret.as_bytes());
@@ -0,0 +1,55 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, models::Message, AppState};
+
+/// Shown next to an account's name on its messages and in rosters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Badge {
+    // The person behind the account has been confirmed
+    Verified,
+    // Speaks for an organisation, e.g. the city's alerts account
+    Official,
+}
+
+/// How a room treats messages from official accounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OfficialMessages {
+    // Shown like any other message, badge aside
+    Plain,
+    #[default]
+    Highlight,
+    // Highlighted, pinned above the feed by clients and pushed as urgent
+    Prioritize,
+}
+
+/// Broadcast metadata telling clients how to set a message apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Highlight {
+    Highlight,
+    Priority,
+}
+
+/// Stamps the sender's badge on a message, and marks official messages the
+/// way the room asks for.
+pub fn apply(message: &mut Message, badge: Option<Badge>, setting: OfficialMessages) {
+    message.badge = badge;
+    message.highlight = match (badge, setting) {
+        (Some(Badge::Official), OfficialMessages::Highlight) => Some(Highlight::Highlight),
+        (Some(Badge::Official), OfficialMessages::Prioritize) => Some(Highlight::Priority),
+        _ => None,
+    };
+}
+
+/// The badge `user_id` is shown with. Only a signed-in caller acting as
+/// themselves gets one, so a claimed id can't borrow an official account's
+/// badge. The token's claim wins; otherwise the user service profile says.
+pub async fn badge_for(state: &AppState, caller: Option<&AuthUser>, user_id: &str) -> Option<Badge> {
+    let caller = caller.filter(|caller| caller.user_id == user_id)?;
+    if caller.badge.is_some() {
+        return caller.badge;
+    }
+    state.user_service.profile(user_id).await.and_then(|lookup| lookup.profile.badge)
+}
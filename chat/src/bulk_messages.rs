@@ -0,0 +1,204 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, oid::ObjectId, Document},
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    auth::AuthUser,
+    event_log::LogEvent,
+    handlers::MessageResponse,
+    models::{Message, WsMessage},
+    room_roles::require_moderator,
+    user_history::HistorySource,
+    websocket::publish_to_room,
+    AppError, AppState,
+};
+
+// Messages deleted per update_many, so one request never holds a huge write
+pub const BULK_BATCH_SIZE: usize = 500;
+pub const MAX_EXPORT_MESSAGES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkAction {
+    Delete,
+    Export,
+}
+
+/// Which of a room's messages a bulk operation covers: those by `user_id`,
+/// sent within `from..=to`, or both.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BulkSelection {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl BulkSelection {
+    /// Deletes must name a user or a time range so a typo can't wipe a room.
+    pub fn validate(&self, action: BulkAction) -> Result<(), String> {
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err("`from` must not be after `to`".to_string());
+            }
+        }
+        let unbounded = self.user_id.is_none() && self.from.is_none() && self.to.is_none();
+        if action == BulkAction::Delete && unbounded {
+            return Err("Bulk deletes need a user_id, from or to".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn filter(&self, room_id: &str) -> Document {
+        let mut filter = doc! { "room_id": room_id, "deleted": false };
+        if let Some(user_id) = &self.user_id {
+            filter.insert("user_id", user_id);
+        }
+        let mut timestamp = Document::new();
+        if let Some(from) = self.from {
+            timestamp.insert("$gte", bson::DateTime::from_millis(from.timestamp_millis()));
+        }
+        if let Some(to) = self.to {
+            timestamp.insert("$lte", bson::DateTime::from_millis(to.timestamp_millis()));
+        }
+        if !timestamp.is_empty() {
+            filter.insert("timestamp", timestamp);
+        }
+        filter
+    }
+
+    /// Whether a message is covered, as clients check it for `BulkDelete`.
+    pub fn matches(&self, message: &Message) -> bool {
+        self.user_id.as_ref().is_none_or(|user_id| *user_id == message.user_id)
+            && self.from.is_none_or(|from| message.timestamp >= from)
+            && self.to.is_none_or(|to| message.timestamp <= to)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkRequest {
+    pub action: BulkAction,
+    #[serde(flatten)]
+    pub selection: BulkSelection,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BulkResponse {
+    pub action: BulkAction,
+    pub room_id: String,
+    pub count: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub messages: Option<Vec<MessageResponse>>,
+    // Export stopped at MAX_EXPORT_MESSAGES
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+fn messages(state: &AppState) -> Collection<Message> {
+    state.database.collection("messages")
+}
+
+// Soft-deletes in batches of ids, so the room's other writes interleave
+async fn delete_matching(state: &AppState, room_id: &str, filter: Document, deleted_by: &str) -> mongodb::error::Result<u64> {
+    let options = FindOptions::builder()
+        .projection(doc! { "_id": 1 })
+        .batch_size(BULK_BATCH_SIZE as u32)
+        .build();
+    let mut cursor = state.database.collection::<Document>("messages").find(filter, options).await?;
+
+    let mut deleted = 0;
+    let mut batch: Vec<ObjectId> = Vec::with_capacity(BULK_BATCH_SIZE);
+    loop {
+        let next = cursor.try_next().await?;
+        if let Some(id) = next.as_ref().and_then(|message| message.get_object_id("_id").ok()) {
+            batch.push(id);
+        }
+        if batch.len() == BULK_BATCH_SIZE || (next.is_none() && !batch.is_empty()) {
+            let result = messages(state)
+                .update_many(
+                    doc! { "_id": { "$in": &batch }, "deleted": false },
+                    doc! { "$set": { "deleted": true, "deleted_by": deleted_by } },
+                    None,
+                )
+                .await?;
+            deleted += result.modified_count;
+            crate::event_log::append(state, LogEvent::MessageDeleted {
+                source: HistorySource::Room,
+                room_id: room_id.to_string(),
+                message_ids: batch.drain(..).map(|id| id.to_hex()).collect(),
+            }).await;
+        }
+        if next.is_none() {
+            return Ok(deleted);
+        }
+    }
+}
+
+async fn export_matching(state: &AppState, filter: Document) -> mongodb::error::Result<(Vec<MessageResponse>, bool)> {
+    let options = FindOptions::builder()
+        .sort(doc! { "timestamp": 1 })
+        .limit(MAX_EXPORT_MESSAGES as i64 + 1)
+        .batch_size(BULK_BATCH_SIZE as u32)
+        .build();
+    let mut found: Vec<Message> = messages(state).find(filter, options).await?.try_collect().await?;
+    let truncated = found.len() > MAX_EXPORT_MESSAGES;
+    found.truncate(MAX_EXPORT_MESSAGES);
+    Ok((found.into_iter().map(MessageResponse::from).collect(), truncated))
+}
+
+// POST /api/admin/rooms/:location_id/messages/bulk - moderators only
+// Deletes broadcast one BulkDelete carrying the selection, which clients
+// apply to the messages they have loaded
+pub async fn bulk_messages_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<BulkRequest>,
+) -> Result<Json<BulkResponse>, AppError> {
+    require_moderator(&state, &location_id, &user, req.selection.user_id.as_deref()).await?;
+    req.selection.validate(req.action).map_err(AppError::BadRequest)?;
+    let filter = req.selection.filter(&location_id);
+
+    let response = match req.action {
+        BulkAction::Delete => {
+            let deleted = delete_matching(&state, &location_id, filter, &user.user_id).await?;
+            info!(
+                "Moderator {} bulk deleted {} messages in {} ({:?})",
+                user.username, deleted, location_id, req.selection
+            );
+            if deleted > 0 {
+                let event = WsMessage::BulkDelete {
+                    room_id: location_id.clone(),
+                    selection: req.selection,
+                    deleted_count: deleted,
+                };
+                publish_to_room(&state, &location_id, event).await;
+            }
+            BulkResponse { action: req.action, room_id: location_id, count: deleted, messages: None, truncated: false }
+        }
+        BulkAction::Export => {
+            let (exported, truncated) = export_matching(&state, filter).await?;
+            info!("Moderator {} exported {} messages from {}", user.username, exported.len(), location_id);
+            BulkResponse {
+                action: req.action,
+                room_id: location_id,
+                count: exported.len() as u64,
+                messages: Some(exported),
+                truncated,
+            }
+        }
+    };
+    Ok(Json(response))
+}
@@ -0,0 +1,190 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use sha2::{Digest, Sha256};
+
+use crate::{streams::BroadcastBackend, wire_format::WireFormat};
+
+/// Which rollout group a connection belongs to. Canary connections take the
+/// code paths being soft-launched; everyone else stays on the stable ones.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cohort {
+    #[default]
+    Stable,
+    Canary,
+}
+
+impl Cohort {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Cohort::Stable => "stable",
+            Cohort::Canary => "canary",
+        }
+    }
+}
+
+/// The user's rollout bucket, 0 to 99. Hashed rather than random so a user
+/// lands in the same cohort on every connection and every instance.
+pub fn bucket(user_id: &str) -> u8 {
+    let digest = Sha256::digest(user_id.as_bytes());
+    let value = u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]]);
+    (value % 100) as u8
+}
+
+/// Which connections are canaries and what they get, from the environment.
+/// Nothing changes unless `CANARY_PERCENT` is above zero and a canary path
+/// is turned on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CanaryConfig {
+    // Share of users in the canary cohort, from CANARY_PERCENT (0 to 100)
+    pub percent: u8,
+    // Backend canary sockets publish through, from CANARY_BROADCAST_BACKEND
+    pub broadcast_backend: Option<BroadcastBackend>,
+    // CANARY_MSGPACK=true keeps MessagePack frames to canary sockets
+    pub msgpack_only: bool,
+}
+
+impl CanaryConfig {
+    pub fn from_env() -> Self {
+        CanaryConfig {
+            percent: std::env::var("CANARY_PERCENT")
+                .ok()
+                .and_then(|v| v.parse::<u8>().ok())
+                .map_or(0, |percent| percent.min(100)),
+            broadcast_backend: std::env::var("CANARY_BROADCAST_BACKEND")
+                .ok()
+                .and_then(|v| BroadcastBackend::parse(&v)),
+            msgpack_only: std::env::var("CANARY_MSGPACK").is_ok_and(|v| v == "true"),
+        }
+    }
+
+    /// Sockets that don't say who they are stay stable.
+    pub fn cohort(&self, user_id: Option<&str>) -> Cohort {
+        match user_id {
+            Some(user_id) if bucket(user_id) < self.percent => Cohort::Canary,
+            _ => Cohort::Stable,
+        }
+    }
+
+    pub fn broadcast_backend(&self, cohort: Cohort, stable: BroadcastBackend) -> BroadcastBackend {
+        match (cohort, self.broadcast_backend) {
+            (Cohort::Canary, Some(backend)) => backend,
+            _ => stable,
+        }
+    }
+
+    /// The negotiated format, unless MessagePack is still canary-only.
+    pub fn wire_format(&self, cohort: Cohort, negotiated: WireFormat) -> WireFormat {
+        if self.msgpack_only && cohort == Cohort::Stable {
+            WireFormat::Json
+        } else {
+            negotiated
+        }
+    }
+
+    /// Whether any instance may publish to the Redis stream, so it must be read.
+    pub fn uses_streams(&self, stable: BroadcastBackend) -> bool {
+        stable == BroadcastBackend::Streams || (self.percent > 0 && self.broadcast_backend == Some(BroadcastBackend::Streams))
+    }
+}
+
+/// Counters kept separately for each cohort.
+#[derive(Debug, Default)]
+pub struct CohortMetrics {
+    pub connections: AtomicU64,
+    pub open_connections: AtomicU64,
+    pub frames_sent: AtomicU64,
+    pub encode_failures: AtomicU64,
+    pub publishes: AtomicU64,
+    pub publish_failures: AtomicU64,
+}
+
+// A counter's name, type, help text and where each cohort keeps it
+type Series = (&'static str, &'static str, &'static str, fn(&CohortMetrics) -> &AtomicU64);
+
+#[derive(Debug, Default)]
+pub struct Canary {
+    pub config: CanaryConfig,
+    stable: CohortMetrics,
+    canary: CohortMetrics,
+    // Canary sockets on this instance, to route what they publish
+    sockets: Mutex<HashSet<String>>,
+}
+
+impl Canary {
+    pub fn new(config: CanaryConfig) -> Self {
+        Canary { config, ..Self::default() }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(CanaryConfig::from_env())
+    }
+
+    pub fn metrics(&self, cohort: Cohort) -> &CohortMetrics {
+        match cohort {
+            Cohort::Stable => &self.stable,
+            Cohort::Canary => &self.canary,
+        }
+    }
+
+    pub fn connect(&self, socket_id: &str, cohort: Cohort) {
+        if cohort == Cohort::Canary {
+            self.sockets.lock().unwrap().insert(socket_id.to_string());
+        }
+        let metrics = self.metrics(cohort);
+        metrics.connections.fetch_add(1, Ordering::Relaxed);
+        metrics.open_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn disconnect(&self, socket_id: &str, cohort: Cohort) {
+        if cohort == Cohort::Canary {
+            self.sockets.lock().unwrap().remove(socket_id);
+        }
+        self.metrics(cohort).open_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// The cohort of the socket a broadcast comes from. Server-originated
+    /// broadcasts have none and are stable.
+    pub fn cohort_of(&self, socket_id: Option<&str>) -> Cohort {
+        match socket_id {
+            Some(socket_id) if self.sockets.lock().unwrap().contains(socket_id) => Cohort::Canary,
+            _ => Cohort::Stable,
+        }
+    }
+
+    pub fn record_publish(&self, cohort: Cohort, ok: bool) {
+        let metrics = self.metrics(cohort);
+        metrics.publishes.fetch_add(1, Ordering::Relaxed);
+        if !ok {
+            metrics.publish_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Prometheus text with a `cohort` label on every series, so canary
+    /// regressions show up next to the stable baseline.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let series: [Series; 6] = [
+            ("chat_cohort_connections_total", "counter", "Sockets opened", |m| &m.connections),
+            ("chat_cohort_open_connections", "gauge", "Sockets open now", |m| &m.open_connections),
+            ("chat_cohort_frames_sent_total", "counter", "Frames written to sockets", |m| &m.frames_sent),
+            ("chat_cohort_encode_failures_total", "counter", "Messages that couldn't be encoded for a socket", |m| &m.encode_failures),
+            ("chat_cohort_publishes_total", "counter", "Broadcasts published to Redis", |m| &m.publishes),
+            ("chat_cohort_publish_failures_total", "counter", "Broadcasts that failed to publish to Redis", |m| &m.publish_failures),
+        ];
+        for (name, kind, help, counter) in series {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for cohort in [Cohort::Stable, Cohort::Canary] {
+                let value = counter(self.metrics(cohort)).load(Ordering::Relaxed);
+                let _ = writeln!(out, "{}{{cohort=\"{}\"}} {}", name, cohort.as_str(), value);
+            }
+        }
+        let _ = writeln!(out, "# HELP chat_canary_percent Share of users in the canary cohort");
+        let _ = writeln!(out, "# TYPE chat_canary_percent gauge");
+        let _ = writeln!(out, "chat_canary_percent {}", self.config.percent);
+        out
+    }
+}
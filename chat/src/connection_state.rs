@@ -0,0 +1,111 @@
+use thiserror::Error;
+
+use crate::models::WsMessage;
+
+/// Where a room or hex socket is in the chat protocol. Sockets start
+/// unauthenticated, are authenticated once a join's token (or a resumed
+/// session) checks out, and are joined once registered in their room.
+/// A socket joins once; only hex sockets move on, by changing resolution.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    #[default]
+    Unauthenticated,
+    Authenticated { user_id: String },
+    Joined { user_id: String, room_id: String },
+}
+
+/// A frame the socket's current state doesn't allow. The message is sent
+/// back to the client as-is.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ProtocolError {
+    #[error("Join a room before sending {0}")]
+    NotJoined(&'static str),
+    #[error("Already joined {0}")]
+    AlreadyJoined(String),
+    #[error("Authenticate before joining {0}")]
+    NotAuthenticated(String),
+    #[error("{0} can't be sent by clients")]
+    NotAClientFrame(String),
+}
+
+impl ConnectionState {
+    pub fn user_id(&self) -> Option<&str> {
+        match self {
+            ConnectionState::Unauthenticated => None,
+            ConnectionState::Authenticated { user_id } | ConnectionState::Joined { user_id, .. } => Some(user_id),
+        }
+    }
+
+    pub fn room_id(&self) -> Option<&str> {
+        match self {
+            ConnectionState::Joined { room_id, .. } => Some(room_id),
+            _ => None,
+        }
+    }
+
+    /// Whether a frame from the client is legal now, checked before it's handled.
+    pub fn admit(&self, message: &WsMessage) -> Result<(), ProtocolError> {
+        let needs_join = match message {
+            WsMessage::Join { .. } | WsMessage::JoinHex { .. } | WsMessage::Resume { .. } => {
+                return match self.room_id() {
+                    Some(room_id) => Err(ProtocolError::AlreadyJoined(room_id.to_string())),
+                    None => Ok(()),
+                };
+            }
+            // Filters can be set up front, so history already arrives filtered
+            WsMessage::SetFilter { .. } => return Ok(()),
+            WsMessage::Message { .. } => "messages",
+            WsMessage::Typing { .. } | WsMessage::Activity { .. } => "activity",
+            WsMessage::Ack { .. } => "acks",
+            WsMessage::Rsvp { .. } => "RSVPs",
+            WsMessage::React { .. } => "reactions",
+            WsMessage::ChangeResolution { .. } => "resolution changes",
+            other => return Err(ProtocolError::NotAClientFrame(frame_type(other))),
+        };
+        match self {
+            ConnectionState::Joined { .. } => Ok(()),
+            _ => Err(ProtocolError::NotJoined(needs_join)),
+        }
+    }
+
+    /// Unauthenticated → Authenticated. A join that failed after its token
+    /// checked out may be retried, as the same user or another.
+    pub fn authenticate(&mut self, user_id: &str) -> Result<(), ProtocolError> {
+        if let Some(room_id) = self.room_id() {
+            return Err(ProtocolError::AlreadyJoined(room_id.to_string()));
+        }
+        *self = ConnectionState::Authenticated { user_id: user_id.to_string() };
+        Ok(())
+    }
+
+    /// Joined → Joined, for hex sockets zooming to another resolution.
+    pub fn move_to(&mut self, room_id: &str) -> Result<(), ProtocolError> {
+        match self {
+            ConnectionState::Joined { room_id: joined, .. } => {
+                *joined = room_id.to_string();
+                Ok(())
+            }
+            _ => Err(ProtocolError::NotJoined("resolution changes")),
+        }
+    }
+
+    /// Authenticated → Joined.
+    pub fn join(&mut self, room_id: &str) -> Result<(), ProtocolError> {
+        match self {
+            ConnectionState::Unauthenticated => Err(ProtocolError::NotAuthenticated(room_id.to_string())),
+            ConnectionState::Joined { room_id: joined, .. } => Err(ProtocolError::AlreadyJoined(joined.clone())),
+            ConnectionState::Authenticated { user_id } => {
+                *self = ConnectionState::Joined { user_id: std::mem::take(user_id), room_id: room_id.to_string() };
+                Ok(())
+            }
+        }
+    }
+}
+
+// The frame's `type` tag, as the client sent it
+fn frame_type(message: &WsMessage) -> String {
+    serde_json::to_value(message)
+        .ok()
+        .and_then(|value| value.get("type").and_then(|tag| tag.as_str()).map(str::to_string))
+        .unwrap_or_else(|| "This frame".to_string())
+}
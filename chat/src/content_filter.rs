@@ -0,0 +1,88 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::Message;
+
+// Mild language is only masked in rooms rated for everyone
+const MILD_WORDS: &[&str] = &["damn", "damned", "dammit", "hell", "crap", "crappy", "piss", "pissed", "bloody", "bugger"];
+// A trailing '*' also matches longer words, e.g. "fuck*" covers "fucking"
+const STRONG_WORDS: &[&str] = &[
+    "fuck*", "motherfuck*", "shit*", "bullshit", "bitch*", "asshole*", "bastard*", "cunt*", "dick", "dickhead*",
+];
+
+/// Audience a room is rated for. Sets how strictly messages are filtered,
+/// and mature rooms only admit users whose token carries `age_verified`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentRating {
+    #[default]
+    Everyone,
+    Teen,
+    Mature,
+}
+
+impl ContentRating {
+    pub fn requires_age_verification(self) -> bool {
+        self == ContentRating::Mature
+    }
+
+    fn blocked_words(self) -> impl Iterator<Item = &'static str> {
+        let mild: &[&str] = match self {
+            ContentRating::Everyone => MILD_WORDS,
+            ContentRating::Teen | ContentRating::Mature => &[],
+        };
+        let strong: &[&str] = match self {
+            ContentRating::Everyone | ContentRating::Teen => STRONG_WORDS,
+            ContentRating::Mature => &[],
+        };
+        mild.iter().chain(strong).copied()
+    }
+}
+
+fn is_blocked(word: &str, rating: ContentRating) -> bool {
+    let word = word.to_lowercase();
+    rating.blocked_words().any(|blocked| match blocked.strip_suffix('*') {
+        Some(prefix) => word.starts_with(prefix),
+        None => word == blocked,
+    })
+}
+
+/// Masks words the rating doesn't allow with asterisks, keeping the first
+/// letter. Returns the filtered text and how many words were masked.
+pub fn filter_content(content: &str, rating: ContentRating) -> (String, usize) {
+    let mut filtered = String::with_capacity(content.len());
+    let mut word = String::new();
+    let mut hits = 0;
+    let mut flush = |word: &mut String, filtered: &mut String| {
+        if !word.is_empty() && is_blocked(word, rating) {
+            hits += 1;
+            let mut chars = word.chars();
+            filtered.extend(chars.next());
+            filtered.extend(chars.map(|_| '*'));
+        } else {
+            filtered.push_str(word);
+        }
+        word.clear();
+    };
+
+    for c in content.chars() {
+        if c.is_alphanumeric() {
+            word.push(c);
+        } else {
+            flush(&mut word, &mut filtered);
+            filtered.push(c);
+        }
+    }
+    flush(&mut word, &mut filtered);
+    (filtered, hits)
+}
+
+/// Applies the room's rating to a message before it is stored. Returns
+/// whether anything was masked, so the sender can be recorded as a filter hit.
+pub fn filter_message(message: &mut Message, rating: ContentRating) -> bool {
+    let (filtered, hits) = filter_content(&message.content, rating);
+    if hits == 0 {
+        return false;
+    }
+    message.content = filtered;
+    true
+}
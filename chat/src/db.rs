@@ -1,23 +1,68 @@
-use crate::models::*;
+use crate::{message_body::VersionedBody, models::*};
 use chrono::{DateTime, Utc};
 use mongodb::{
-    bson::{self, doc, oid::ObjectId, Bson},
-    error::Result as MongoResult,
-    options::{FindOptions, UpdateOptions},
-    Collection, Database,
+    bson::{self, doc, oid::ObjectId, Bson, Document},
+    error::{Error as MongoError, ErrorKind, Result as MongoResult, WriteFailure, RETRYABLE_WRITE_ERROR},
+    options::{FindOneAndUpdateOptions, FindOptions, IndexOptions, ReturnDocument, UpdateOptions},
+    Collection, Database, IndexModel,
 };
 use futures::stream::TryStreamExt;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+// Retries for a single operation that hit a transient error
+const TRANSIENT_RETRIES: u32 = 3;
+const RETRY_BACKOFF: Duration = Duration::from_millis(100);
+// Consecutive failed sends before messages are queued instead of written
+const CIRCUIT_FAILURE_THRESHOLD: u32 = 3;
+const CIRCUIT_OPEN_FOR: Duration = Duration::from_secs(5);
+const MAX_PENDING_WRITES: usize = 10_000;
+const PENDING_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+// Server codes for elections, stepdowns and shutdowns (SDAM retryable codes)
+const TRANSIENT_CODES: [i32; 12] = [6, 7, 89, 91, 189, 262, 9001, 10107, 11600, 11602, 13435, 13436];
+const DUPLICATE_KEY_CODE: i32 = 11000;
 
 pub struct MongoDb {
+    database: Database,
     messages: Collection<Message>,
     rooms: Collection<ChatRoom>,
+    circuit: WriteCircuit,
+}
+
+/// What `create_message` did with a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Persisted {
+    Stored(ObjectId),
+    // Held in memory until MongoDB is back, so not yet durable
+    Queued(ObjectId),
+}
+
+impl Persisted {
+    pub fn id(self) -> ObjectId {
+        match self {
+            Persisted::Stored(id) | Persisted::Queued(id) => id,
+        }
+    }
+
+    pub fn status(self) -> SendStatus {
+        match self {
+            Persisted::Stored(_) => SendStatus::Sent,
+            Persisted::Queued(_) => SendStatus::Queued,
+        }
+    }
 }
 
 impl MongoDb {
     pub fn new(db: Database) -> Self {
         Self {
+            database: db.clone(),
             messages: db.collection("messages"),
             rooms: db.collection("rooms"),
+            circuit: WriteCircuit::default(),
         }
     }
     
@@ -25,20 +70,182 @@ impl MongoDb {
         self.messages.namespace().db.clone()
     }
 
-    pub async fn create_message(&self, message: &Message) -> MongoResult<ObjectId> {
-        let result = self.messages.insert_one(message, None).await?;
-        Ok(result.inserted_id.as_object_id().unwrap())
+    // (collection, index name, keys) the queries in this module rely on
+    fn expected_indexes() -> Vec<(&'static str, &'static str, bson::Document)> {
+        vec![
+            ("messages", "room_timestamp", doc! { "room_id": 1, "timestamp": -1 }),
+            ("messages", "parent_id", doc! { "parent_id": 1 }),
+            ("messages", "parent_thread", doc! { "parent_id": 1, "_id": 1 }),
+            ("messages", "room_id", doc! { "room_id": 1, "_id": 1 }),
+            ("messages", "user_id", doc! { "user_id": 1 }),
+            ("messages", "user_timestamp", doc! { "user_id": 1, "timestamp": -1 }),
+            ("messages", "expires_at", doc! { "expires_at": 1 }),
+            ("messages", "content_text", doc! { "content": "text" }),
+            ("flagged_messages", "room_queue", doc! { "room_id": 1, "flagged_at": 1 }),
+            ("message_reports", "status_queue", doc! { "status": 1, "created_at": 1 }),
+            ("room_invitations", "room_created", doc! { "room_id": 1, "created_at": -1 }),
+            ("room_invitations", "user_memberships", doc! { "user_id": 1, "accepted_at": 1 }),
+            ("user_blocks", "blocker", doc! { "blocker_id": 1, "created_at": -1 }),
+            ("rooms", "activity", doc! { "active_users": -1, "last_message_at": -1 }),
+            ("rooms", "last_message_at", doc! { "last_message_at": -1 }),
+            ("rooms", "review_queue", doc! { "review": 1, "created_at": 1 }),
+            ("room_read_cursors", "user_recent", doc! { "user_id": 1, "last_read_at": -1 }),
+            ("room_read_cursors", "room_recent", doc! { "room_id": 1, "last_read_at": -1 }),
+            ("dm_key_events", "conversation_catch_up", doc! { "conversation_id": 1, "_id": 1 }),
+            ("direct_messages", "conversation_history", doc! { "conversation_id": 1, "_id": -1 }),
+            ("direct_messages", "sender_timestamp", doc! { "sender_id": 1, "timestamp": -1 }),
+            ("dm_conversations", "participant_activity", doc! { "participants": 1, "updated_at": -1 }),
+        ]
+    }
+
+    pub async fn ping(&self) -> MongoResult<()> {
+        self.database.run_command(doc! { "ping": 1 }, None).await?;
+        Ok(())
+    }
+
+    pub async fn ensure_indexes(&self) -> MongoResult<()> {
+        for (collection, name, keys) in Self::expected_indexes() {
+            let index = IndexModel::builder()
+                .keys(keys)
+                .options(IndexOptions::builder().name(name.to_string()).build())
+                .build();
+            self.database.collection::<bson::Document>(collection).create_index(index, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Expected indexes that don't exist yet, as "collection.name".
+    pub async fn missing_indexes(&self) -> Vec<String> {
+        let mut missing = Vec::new();
+        for (collection, name, _) in Self::expected_indexes() {
+            // Listing indexes on a collection that doesn't exist yet fails
+            let existing = self.database
+                .collection::<bson::Document>(collection)
+                .list_index_names()
+                .await
+                .unwrap_or_default();
+            if !existing.iter().any(|existing| existing == name) {
+                missing.push(format!("{}.{}", collection, name));
+            }
+        }
+        missing
+    }
+
+    /// Persists a message. While MongoDB is failing over the message is
+    /// queued instead and `Persisted::Queued` returned; it is written once
+    /// the primary is back.
+    #[tracing::instrument(skip_all, fields(room_id = %message.room_id))]
+    pub async fn create_message(&self, message: &Message) -> MongoResult<Persisted> {
+        // Ids are assigned here so queued messages and retried inserts keep them
+        let mut message = message.clone();
+        let id = *message.id.get_or_insert_with(ObjectId::new);
+        if message.body.is_none() {
+            message.body = Some(VersionedBody::new(message.body()));
+        }
+        
+        // Queue behind earlier pending messages so history stays in order
+        if self.circuit.is_open() || self.circuit.has_pending() {
+            if let Some(message) = self.circuit.enqueue(message.clone()) {
+                return self.insert_message(&message).await.map(|_| Persisted::Stored(id));
+            }
+            return Ok(Persisted::Queued(id));
+        }
+        
+        match self.insert_message(&message).await {
+            Ok(()) => {
+                self.circuit.record_success();
+                Ok(Persisted::Stored(id))
+            }
+            Err(e) if is_transient(&e) => {
+                warn!("Queueing message {} after transient MongoDB error: {}", id, e);
+                self.circuit.record_failure();
+                match self.circuit.enqueue(message) {
+                    Some(_) => Err(e),
+                    None => Ok(Persisted::Queued(id)),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn insert_message(&self, message: &Message) -> MongoResult<()> {
+        let started = Instant::now();
+        let result = with_retries(|| self.messages.insert_one(message, None)).await;
+        crate::service_metrics::mongo_insert(started.elapsed());
+        match result {
+            Ok(_) => Ok(()),
+            // An earlier attempt landed before its acknowledgement was lost
+            Err(e) if is_duplicate_key(&e) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes queued messages in order whenever the circuit allows it.
+    pub fn spawn_pending_flush(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(PENDING_FLUSH_INTERVAL);
+            loop {
+                tick.tick().await;
+                self.flush_pending().await;
+            }
+        })
+    }
+
+    /// Flushes the queue until it is empty or `timeout` passes, for
+    /// shutdown. Returns how many messages are still queued.
+    pub async fn flush_queued(&self, timeout: Duration) -> usize {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.circuit.has_pending() && tokio::time::Instant::now() < deadline {
+            self.flush_pending().await;
+            if self.circuit.has_pending() {
+                tokio::time::sleep(PENDING_FLUSH_INTERVAL).await;
+            }
+        }
+        self.circuit.pending_len()
+    }
+
+    async fn flush_pending(&self) {
+        let mut flushed = 0;
+        while !self.circuit.is_open() {
+            let Some(message) = self.circuit.front() else {
+                break;
+            };
+            match self.insert_message(&message).await {
+                Ok(()) => {
+                    self.circuit.pop_front();
+                    self.circuit.record_success();
+                    flushed += 1;
+                }
+                Err(e) => {
+                    warn!("Failed to flush queued message: {}", e);
+                    if is_transient(&e) {
+                        self.circuit.record_failure();
+                    } else {
+                        // Not going to succeed on retry; don't block the queue
+                        self.circuit.pop_front();
+                    }
+                    break;
+                }
+            }
+        }
+        if flushed > 0 {
+            info!("Flushed {} queued messages to MongoDB ({} still pending)", flushed, self.circuit.pending_len());
+        }
     }
 
+    #[tracing::instrument(skip_all, fields(room_id = %location_id))]
     pub async fn get_messages(
         &self,
         location_id: &str,
         limit: i64,
         before: Option<DateTime<Utc>>,
+        viewer: Option<&str>,
     ) -> MongoResult<Vec<Message>> {
         tracing::info!("Getting messages for room: {}, limit: {}", location_id, limit);
         
         let mut filter = doc! { "room_id": location_id };
+        filter.extend(crate::shadow_bans::visible_to("user_id", viewer));
         
         if let Some(before_time) = before {
             filter.insert("timestamp", doc! { "$lt": Bson::DateTime(mongodb::bson::DateTime::from_millis(before_time.timestamp_millis())) });
@@ -59,59 +266,368 @@ impl MongoDb {
         
         tracing::info!("Retrieved {} messages for room {}", messages.len(), location_id);
         messages.reverse(); // Return in chronological order
+        crate::shadow_bans::unmark(&mut messages);
         Ok(messages)
     }
 
-    pub async fn get_or_create_room(&self, location_id: &str) -> MongoResult<ChatRoom> {
-        let filter = doc! { "_id": location_id };
-        
-        if let Some(room) = self.rooms.find_one(filter.clone(), None).await? {
-            Ok(room)
-        } else {
-            let new_room = ChatRoom {
-                id: location_id.to_string(),
-                location_id: location_id.to_string(),
-                active_users: 0,
-                last_message_at: Utc::now(),
-                created_at: Utc::now(),
-                settings: RoomSettings {
-                    max_users: 1000,
-                    rate_limit: 10,
-                },
-            };
-            
-            self.rooms.insert_one(&new_room, None).await?;
-            Ok(new_room)
+    /// Up to `limit` messages newer than `since` that `viewer` may see,
+    /// oldest first.
+    #[tracing::instrument(skip_all, fields(room_id = %location_id))]
+    pub async fn get_messages_since(
+        &self,
+        location_id: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+        viewer: Option<&str>,
+    ) -> MongoResult<Vec<Message>> {
+        let mut filter = doc! {
+            "room_id": location_id,
+            "timestamp": { "$gt": Bson::DateTime(mongodb::bson::DateTime::from_millis(since.timestamp_millis())) },
+        };
+        filter.extend(crate::shadow_bans::visible_to("user_id", viewer));
+        let options = FindOptions::builder()
+            .sort(doc! { "timestamp": 1 })
+            .limit(limit)
+            .build();
+        let mut messages: Vec<Message> = self.messages.find(filter, options).await?.try_collect().await?;
+        crate::shadow_bans::unmark(&mut messages);
+        Ok(messages)
+    }
+
+    /// Up to `limit` messages posted after the message `after` that `viewer`
+    /// may see, oldest first.
+    #[tracing::instrument(skip_all, fields(room_id = %location_id))]
+    pub async fn get_messages_after(&self, location_id: &str, after: &ObjectId, limit: i64, viewer: Option<&str>) -> MongoResult<Vec<Message>> {
+        let mut filter = doc! { "room_id": location_id, "_id": { "$gt": after } };
+        filter.extend(crate::shadow_bans::visible_to("user_id", viewer));
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .limit(limit)
+            .build();
+        let mut messages: Vec<Message> = self.messages.find(filter, options).await?.try_collect().await?;
+        crate::shadow_bans::unmark(&mut messages);
+        Ok(messages)
+    }
+
+
+    #[tracing::instrument(skip_all, fields(room_id = %location_id))]
+    pub async fn get_room(&self, location_id: &str) -> MongoResult<Option<ChatRoom>> {
+        self.rooms.find_one(doc! { "_id": location_id }, None).await
+    }
+
+    /// Inserts a new room. If another request created it first, that room
+    /// is returned instead.
+    #[tracing::instrument(skip_all, fields(room_id = %room.id))]
+    pub async fn create_room(&self, room: ChatRoom) -> MongoResult<ChatRoom> {
+        match self.rooms.insert_one(&room, None).await {
+            Ok(_) => Ok(room),
+            Err(e) if is_duplicate_key(&e) => match self.get_room(&room.id).await? {
+                Some(existing) => Ok(existing),
+                None => Err(e),
+            },
+            Err(e) => Err(e),
         }
     }
 
+    /// Rooms awaiting review, oldest first.
+    pub async fn rooms_in_review(&self, limit: i64) -> MongoResult<Vec<ChatRoom>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .limit(limit)
+            .build();
+        self.rooms.find(doc! { "review": "pending" }, options).await?.try_collect().await
+    }
+
+    /// Takes a room out of review. False if it wasn't in review.
+    pub async fn approve_room(&self, location_id: &str) -> MongoResult<bool> {
+        let result = self.rooms.update_one(
+            doc! { "_id": location_id, "review": "pending" },
+            doc! { "$set": { "review": "approved" } },
+            None,
+        ).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Pins a marker unless the room already has `max` of them.
+    pub async fn add_marker(&self, location_id: &str, marker: &crate::room_markers::RoomMarker, max: usize) -> MongoResult<bool> {
+        let mut filter = doc! { "_id": location_id };
+        filter.insert(format!("markers.{}", max.saturating_sub(1)), doc! { "$exists": false });
+        let result = self.rooms.update_one(
+            filter,
+            doc! { "$push": { "markers": bson::to_bson(marker)? } },
+            None,
+        ).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    pub async fn replace_marker(&self, location_id: &str, marker: &crate::room_markers::RoomMarker) -> MongoResult<bool> {
+        let result = self.rooms.update_one(
+            doc! { "_id": location_id, "markers.id": &marker.id },
+            doc! { "$set": { "markers.$": bson::to_bson(marker)? } },
+            None,
+        ).await?;
+        Ok(result.matched_count > 0)
+    }
+
+    pub async fn remove_marker(&self, location_id: &str, marker_id: &str) -> MongoResult<bool> {
+        let result = self.rooms.update_one(
+            doc! { "_id": location_id },
+            doc! { "$pull": { "markers": { "id": marker_id } } },
+            None,
+        ).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Adds a room moderator unless the room already has `max` of them.
+    /// False when the room is full or doesn't exist.
+    pub async fn add_room_moderator(&self, location_id: &str, user_id: &str, max: usize) -> MongoResult<bool> {
+        let mut filter = doc! { "_id": location_id };
+        filter.insert(
+            "$or",
+            vec![doc! { "moderators": user_id }, doc! { format!("moderators.{}", max.saturating_sub(1)): { "$exists": false } }],
+        );
+        let result = self.rooms.update_one(filter, doc! { "$addToSet": { "moderators": user_id } }, None).await?;
+        Ok(result.matched_count > 0)
+    }
+
+    pub async fn set_room_owner(&self, location_id: &str, user_id: &str) -> MongoResult<bool> {
+        let result = self.rooms.update_one(
+            doc! { "_id": location_id },
+            doc! { "$set": { "owner": user_id } },
+            None,
+        ).await?;
+        Ok(result.matched_count > 0)
+    }
+
+    pub async fn remove_room_moderator(&self, location_id: &str, user_id: &str) -> MongoResult<bool> {
+        let result = self.rooms.update_one(
+            doc! { "_id": location_id },
+            doc! { "$pull": { "moderators": user_id } },
+            None,
+        ).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    #[tracing::instrument(skip_all, fields(room_id = %location_id))]
     pub async fn update_room_activity(
         &self,
         location_id: &str,
         active_users: i32,
     ) -> MongoResult<()> {
         let filter = doc! { "_id": location_id };
+        let now = Bson::DateTime(mongodb::bson::DateTime::from_millis(Utc::now().timestamp_millis()));
+        // Upserted rooms must still deserialize as a full ChatRoom
         let update = doc! {
             "$set": {
                 "active_users": active_users,
-                "last_message_at": Bson::DateTime(mongodb::bson::DateTime::from_millis(Utc::now().timestamp_millis())),
+                "last_message_at": now.clone(),
+            },
+            "$setOnInsert": {
+                "location_id": location_id,
+                "created_at": now,
+                "settings": bson::to_bson(&RoomSettings::default()).unwrap_or(Bson::Null),
+                "migrated_to_hex": false,
             }
         };
         
         let options = UpdateOptions::builder().upsert(true).build();
-        self.rooms.update_one(filter, update, options).await?;
+        with_retries(|| self.rooms.update_one(filter.clone(), update.clone(), options.clone())).await?;
+        Ok(())
+    }
+
+    /// Rooms for discovery, most recently active first, optionally limited to
+    /// rooms declaring the given primary language. Rooms in review are only
+    /// listed for their creator. Private rooms aren't listed.
+    pub async fn list_rooms(&self, language: Option<&str>, viewer: Option<&str>, limit: i64) -> MongoResult<Vec<ChatRoom>> {
+        let mut filter = match viewer {
+            Some(viewer) => doc! { "$or": [{ "review": { "$ne": "pending" } }, { "created_by": viewer }] },
+            None => doc! { "review": { "$ne": "pending" } },
+        };
+        filter.insert("visibility", doc! { "$ne": "private" });
+        if let Some(language) = language {
+            filter.insert("settings.language", language);
+        }
+        
+        let options = FindOptions::builder()
+            .sort(doc! { "active_users": -1, "last_message_at": -1 })
+            .limit(limit)
+            .build();
+        
+        self.rooms.find(filter, options).await?.try_collect().await
+    }
+
+    /// The rooms among `location_ids` that exist; missing ones are left out.
+    pub async fn get_rooms(&self, location_ids: &[String]) -> MongoResult<Vec<ChatRoom>> {
+        self.rooms.find(doc! { "_id": { "$in": location_ids } }, None).await?.try_collect().await
+    }
+
+    pub async fn set_room_language(&self, location_id: &str, language: Option<&str>) -> MongoResult<()> {
+        let update = match language {
+            Some(language) => doc! { "$set": { "settings.language": language } },
+            None => doc! { "$unset": { "settings.language": "" } },
+        };
+        self.rooms.update_one(doc! { "_id": location_id }, update, None).await?;
+        Ok(())
+    }
+
+    // `fields` are dotted paths such as "settings.rate_limit"
+    pub async fn update_room_settings(&self, location_id: &str, fields: Document) -> MongoResult<()> {
+        self.rooms.update_one(doc! { "_id": location_id }, doc! { "$set": fields }, None).await?;
+        Ok(())
+    }
+
+    /// Moves a legacy room's history into its hex room. Messages keep their
+    /// original room in `legacy_room_id` so the move can be audited or undone.
+    pub async fn migrate_room_to_hex(&self, location_id: &str, h3_index: &str) -> MongoResult<u64> {
+        let result = self.messages.update_many(
+            doc! { "room_id": location_id },
+            doc! { "$set": { "room_id": h3_index, "legacy_room_id": location_id } },
+            None,
+        ).await?;
+        
+        self.rooms.update_one(
+            doc! { "_id": location_id },
+            doc! { "$set": { "h3_index": h3_index, "migrated_to_hex": true } },
+            None,
+        ).await?;
+        
+        tracing::info!("Migrated {} messages from room {} to hex {}", result.modified_count, location_id, h3_index);
+        Ok(result.modified_count)
+    }
+
+    /// Messages in the window with the most reactions, highest first.
+    pub async fn top_reacted_messages(
+        &self,
+        location_id: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> MongoResult<Vec<(Message, i32)>> {
+        let pipeline = vec![
+            doc! { "$match": {
+                "room_id": location_id,
+                "deleted": false,
+                "shadow": { "$ne": true },
+                "timestamp": { "$gte": Bson::DateTime(mongodb::bson::DateTime::from_millis(since.timestamp_millis())) },
+                "reactions.0": { "$exists": true },
+            } },
+            doc! { "$addFields": { "reaction_count": { "$size": "$reactions" } } },
+            doc! { "$sort": { "reaction_count": -1, "timestamp": -1 } },
+            doc! { "$limit": limit },
+        ];
+        
+        let mut cursor = self.messages.aggregate(pipeline, None).await?;
+        let mut ranked = Vec::new();
+        while let Some(document) = cursor.try_next().await? {
+            let reaction_count = document.get_i32("reaction_count").unwrap_or(0);
+            ranked.push((bson::from_document::<Message>(document)?, reaction_count));
+        }
+        Ok(ranked)
+    }
+
+    /// Messages that received the most replies in the window, highest first.
+    pub async fn top_replied_messages(
+        &self,
+        location_id: &str,
+        since: DateTime<Utc>,
+        limit: i64,
+    ) -> MongoResult<Vec<(Message, i32)>> {
+        let pipeline = vec![
+            doc! { "$match": {
+                "room_id": location_id,
+                "deleted": false,
+                "shadow": { "$ne": true },
+                "timestamp": { "$gte": Bson::DateTime(mongodb::bson::DateTime::from_millis(since.timestamp_millis())) },
+                "parent_id": { "$type": "string" },
+            } },
+            doc! { "$group": { "_id": "$parent_id", "reply_count": { "$sum": 1 } } },
+            doc! { "$sort": { "reply_count": -1 } },
+            doc! { "$limit": limit },
+        ];
+        
+        let mut counts = Vec::new();
+        let mut cursor = self.messages.aggregate(pipeline, None).await?;
+        while let Some(document) = cursor.try_next().await? {
+            let parent_id = document.get_str("_id").ok().and_then(|id| ObjectId::parse_str(id).ok());
+            if let Some(parent_id) = parent_id {
+                counts.push((parent_id, document.get_i32("reply_count").unwrap_or(0)));
+            }
+        }
+        
+        let parent_ids: Vec<ObjectId> = counts.iter().map(|(id, _)| *id).collect();
+        let mut parents: Vec<Message> = self.messages
+            // A reply can't pull in a parent from another room
+            .find(doc! { "_id": { "$in": parent_ids }, "room_id": location_id, "deleted": false, "shadow": { "$ne": true } }, None)
+            .await?
+            .try_collect()
+            .await?;
+        
+        // Keep the ranking order from the aggregation
+        Ok(counts
+            .into_iter()
+            .filter_map(|(id, reply_count)| {
+                let index = parents.iter().position(|msg| msg.id == Some(id))?;
+                Some((parents.swap_remove(index), reply_count))
+            })
+            .collect())
+    }
+
+    #[tracing::instrument(skip_all, fields(message_id = %message_id))]
+    pub async fn get_message(&self, message_id: &ObjectId) -> MongoResult<Option<Message>> {
+        self.messages.find_one(doc! { "_id": message_id }, None).await
+    }
+
+    /// Up to `limit` live replies to `parent_id` posted after the reply
+    /// `after` that `viewer` may see, oldest first.
+    #[tracing::instrument(skip_all, fields(parent_id = %parent_id))]
+    pub async fn get_replies(&self, parent_id: &ObjectId, after: Option<&ObjectId>, limit: i64, viewer: Option<&str>) -> MongoResult<Vec<Message>> {
+        let mut filter = doc! { "parent_id": parent_id.to_hex(), "deleted": false };
+        if let Some(after) = after {
+            filter.insert("_id", doc! { "$gt": after });
+        }
+        filter.extend(crate::shadow_bans::visible_to("user_id", viewer));
+        let options = FindOptions::builder()
+            .sort(doc! { "_id": 1 })
+            .limit(limit)
+            .build();
+        let mut replies: Vec<Message> = self.messages.find(filter, options).await?.try_collect().await?;
+        crate::shadow_bans::unmark(&mut replies);
+        Ok(replies)
+    }
+
+    /// Counts a new reply on its parent and returns the parent's new count.
+    #[tracing::instrument(skip_all, fields(parent_id = %parent_id))]
+    pub async fn increment_reply_count(&self, parent_id: &ObjectId) -> MongoResult<Option<i32>> {
+        let options = FindOneAndUpdateOptions::builder().return_document(ReturnDocument::After).build();
+        let parent = self.messages.find_one_and_update(
+            doc! { "_id": parent_id },
+            doc! { "$inc": { "reply_count": 1 } },
+            options,
+        ).await?;
+        Ok(parent.map(|parent| parent.reply_count))
+    }
+
+    pub async fn set_rsvp_counts(&self, message_id: &ObjectId, counts: &RsvpCounts) -> MongoResult<()> {
+        self.messages.update_one(
+            doc! { "_id": message_id },
+            doc! { "$set": { "event.rsvp_counts": bson::to_bson(counts).unwrap_or(Bson::Null) } },
+            None,
+        ).await?;
         Ok(())
     }
 
+    /// Adds the reaction unless the user already made it. False when no
+    /// live message has that id in the room.
+    #[tracing::instrument(skip_all, fields(message_id = %message_id))]
     pub async fn add_reaction(
         &self,
         message_id: &ObjectId,
+        room_id: &str,
         user_id: &str,
         emoji: &str,
-    ) -> MongoResult<()> {
-        let filter = doc! { "_id": message_id };
+    ) -> MongoResult<bool> {
+        let filter = doc! { "_id": message_id, "room_id": room_id, "deleted": false };
         let update = doc! {
-            "$push": {
+            "$addToSet": {
                 "reactions": {
                     "user_id": user_id,
                     "emoji": emoji,
@@ -119,7 +635,119 @@ impl MongoDb {
             }
         };
         
-        self.messages.update_one(filter, update, None).await?;
-        Ok(())
+        Ok(self.messages.update_one(filter, update, None).await?.matched_count > 0)
+    }
+
+    #[tracing::instrument(skip_all, fields(message_id = %message_id))]
+    pub async fn remove_reaction(
+        &self,
+        message_id: &ObjectId,
+        room_id: &str,
+        user_id: &str,
+        emoji: &str,
+    ) -> MongoResult<bool> {
+        let filter = doc! { "_id": message_id, "room_id": room_id, "deleted": false };
+        let update = doc! { "$pull": { "reactions": { "user_id": user_id, "emoji": emoji } } };
+        Ok(self.messages.update_one(filter, update, None).await?.matched_count > 0)
     }
-}
\ No newline at end of file
+}
+/// Errors that are expected to clear up on their own, e.g. during a primary
+/// election or a dropped connection.
+pub fn is_transient(error: &MongoError) -> bool {
+    if error.contains_label(RETRYABLE_WRITE_ERROR) {
+        return true;
+    }
+    match error.kind.as_ref() {
+        ErrorKind::Io(_) | ErrorKind::ConnectionPoolCleared { .. } | ErrorKind::ServerSelection { .. } => true,
+        ErrorKind::Command(e) => TRANSIENT_CODES.contains(&e.code),
+        ErrorKind::Write(WriteFailure::WriteConcernError(e)) => TRANSIENT_CODES.contains(&e.code),
+        _ => false,
+    }
+}
+
+pub(crate) fn is_duplicate_key(error: &MongoError) -> bool {
+    matches!(error.kind.as_ref(), ErrorKind::Write(WriteFailure::WriteError(e)) if e.code == DUPLICATE_KEY_CODE)
+}
+
+// Runs `op` again with a short backoff while it fails transiently
+async fn with_retries<T, F, Fut>(mut op: F) -> MongoResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = MongoResult<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if attempt < TRANSIENT_RETRIES && is_transient(&e) => {
+                attempt += 1;
+                warn!("Transient MongoDB error (attempt {}/{}): {}", attempt, TRANSIENT_RETRIES, e);
+                tokio::time::sleep(RETRY_BACKOFF * 2u32.pow(attempt - 1)).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Tracks consecutive write failures and holds messages accepted while
+/// MongoDB is unavailable.
+#[derive(Default)]
+struct WriteCircuit {
+    state: Mutex<CircuitState>,
+}
+
+#[derive(Default)]
+struct CircuitState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    pending: VecDeque<Message>,
+}
+
+impl WriteCircuit {
+    fn is_open(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.open_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= CIRCUIT_FAILURE_THRESHOLD {
+            if state.open_until.is_none() {
+                warn!("MongoDB writes failing, queueing messages for {:?}", CIRCUIT_OPEN_FOR);
+            }
+            state.open_until = Some(Instant::now() + CIRCUIT_OPEN_FOR);
+        }
+    }
+
+    fn has_pending(&self) -> bool {
+        !self.state.lock().unwrap().pending.is_empty()
+    }
+
+    fn pending_len(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+
+    // Hands the message back when the queue is full
+    fn enqueue(&self, message: Message) -> Option<Message> {
+        let mut state = self.state.lock().unwrap();
+        if state.pending.len() >= MAX_PENDING_WRITES {
+            return Some(message);
+        }
+        state.pending.push_back(message);
+        None
+    }
+
+    fn front(&self) -> Option<Message> {
+        self.state.lock().unwrap().pending.front().cloned()
+    }
+
+    fn pop_front(&self) {
+        self.state.lock().unwrap().pending.pop_front();
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{BTreeMap, HashMap};
+use std::mem;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, TimeZone, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use tracing::error;
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+// Long enough to cover a "my message never arrived" ticket filed the same day
+const TRACE_TTL_SECONDS: i64 = 24 * 60 * 60;
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hash of the journey of one message, written by every instance that
+/// touches it.
+fn trace_key(message_id: &str) -> String {
+    format!("msg_trace:{}", message_id)
+}
+
+/// Buffers delivery milestones for new messages and writes them to Redis in
+/// batches, so tracing adds no round trips to the message path.
+pub struct DeliveryTracer {
+    instance_id: String,
+    // (message id, field, unix ms); the first value written for a field wins
+    stamps: Mutex<Vec<(String, String, i64)>>,
+    // message id -> sockets on this instance it was handed to
+    deliveries: Mutex<HashMap<String, u64>>,
+}
+
+impl DeliveryTracer {
+    pub fn new(instance_id: &str) -> Self {
+        DeliveryTracer {
+            instance_id: instance_id.to_string(),
+            stamps: Mutex::new(Vec::new()),
+            deliveries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn stamp(&self, message_id: &str, field: String) {
+        self.stamps
+            .lock()
+            .unwrap()
+            .push((message_id.to_string(), field, Utc::now().timestamp_millis()));
+    }
+
+    pub fn persisted(&self, message_id: &ObjectId) {
+        self.stamp(&message_id.to_hex(), "persisted_at".to_string());
+    }
+
+    pub fn published(&self, message_id: &str) {
+        self.stamp(message_id, format!("published_at:{}", self.instance_id));
+    }
+
+    pub fn delivered(&self, message_id: &str, sockets: u64) {
+        if sockets > 0 {
+            *self.deliveries.lock().unwrap().entry(message_id.to_string()).or_default() += sockets;
+        }
+    }
+
+    pub fn acked(&self, message_id: &str, user_id: &str) {
+        self.stamp(message_id, format!("ack:{}", user_id));
+    }
+
+    async fn flush(&self, pool: &deadpool_redis::Pool) {
+        let stamps = mem::take(&mut *self.stamps.lock().unwrap());
+        let deliveries = mem::take(&mut *self.deliveries.lock().unwrap());
+        if stamps.is_empty() && deliveries.is_empty() {
+            return;
+        }
+        let Ok(mut conn) = pool.get().await else {
+            return;
+        };
+
+        let mut pipe = redis::pipe();
+        for (message_id, field, at) in &stamps {
+            let key = trace_key(message_id);
+            pipe.cmd("HSETNX").arg(&key).arg(field).arg(at).ignore();
+            pipe.cmd("EXPIRE").arg(&key).arg(TRACE_TTL_SECONDS).ignore();
+        }
+        let delivered_field = format!("delivered:{}", self.instance_id);
+        for (message_id, count) in &deliveries {
+            let key = trace_key(message_id);
+            pipe.cmd("HINCRBY").arg(&key).arg(&delivered_field).arg(count).ignore();
+            pipe.cmd("EXPIRE").arg(&key).arg(TRACE_TTL_SECONDS).ignore();
+        }
+        if let Err(e) = pipe.query_async::<_, ()>(&mut conn).await {
+            error!("Failed to write {} delivery trace entries: {}", stamps.len() + deliveries.len(), e);
+        }
+    }
+
+    pub fn spawn_flush(self: Arc<Self>, pool: deadpool_redis::Pool) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                tick.tick().await;
+                self.flush(&pool).await;
+            }
+        })
+    }
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct InstanceTrace {
+    pub instance_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published_at: Option<DateTime<Utc>>,
+    // Sockets on that instance the message was handed to
+    pub delivered: u64,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct Ack {
+    pub user_id: String,
+    pub acked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Default, Serialize, PartialEq)]
+pub struct DeliveryTrace {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub persisted_at: Option<DateTime<Utc>>,
+    pub instances: Vec<InstanceTrace>,
+    pub delivered_total: u64,
+    pub acks: Vec<Ack>,
+}
+
+fn millis(value: &str) -> Option<DateTime<Utc>> {
+    value.parse::<i64>().ok().and_then(|ms| Utc.timestamp_millis_opt(ms).single())
+}
+
+/// Rebuilds a trace from its Redis hash.
+pub fn parse_trace(fields: &HashMap<String, String>) -> DeliveryTrace {
+    let mut trace = DeliveryTrace::default();
+    let mut instances: BTreeMap<&str, InstanceTrace> = BTreeMap::new();
+    let instance = |id: &str| InstanceTrace { instance_id: id.to_string(), ..Default::default() };
+
+    for (field, value) in fields {
+        if field == "persisted_at" {
+            trace.persisted_at = millis(value);
+        } else if let Some(id) = field.strip_prefix("published_at:") {
+            instances.entry(id).or_insert_with(|| instance(id)).published_at = millis(value);
+        } else if let Some(id) = field.strip_prefix("delivered:") {
+            instances.entry(id).or_insert_with(|| instance(id)).delivered = value.parse().unwrap_or(0);
+        } else if let Some(user_id) = field.strip_prefix("ack:") {
+            if let Some(acked_at) = millis(value) {
+                trace.acks.push(Ack { user_id: user_id.to_string(), acked_at });
+            }
+        }
+    }
+
+    trace.instances = instances.into_values().collect();
+    trace.delivered_total = trace.instances.iter().map(|instance| instance.delivered).sum();
+    trace.acks.sort_by_key(|ack| ack.acked_at);
+    trace
+}
+
+#[derive(Serialize)]
+pub struct MessageTraceResponse {
+    message_id: String,
+    room_id: String,
+    user_id: String,
+    created_at: DateTime<Utc>,
+    deleted: bool,
+    // The trace has expired or the message was never published
+    trace_missing: bool,
+    #[serde(flatten)]
+    trace: DeliveryTrace,
+}
+
+// GET /api/admin/messages/:message_id/trace - admins only
+pub async fn message_trace_handler(
+    Path(message_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<MessageTraceResponse>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    let oid = ObjectId::parse_str(&message_id).map_err(|_| AppError::BadRequest("Invalid message id".to_string()))?;
+    let message = state.db.get_message(&oid).await?.ok_or(AppError::NotFound)?;
+
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        error!("Redis unavailable for message trace: {}", e);
+        AppError::InternalServerError
+    })?;
+    let fields: HashMap<String, String> = redis::cmd("HGETALL")
+        .arg(trace_key(&message_id))
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to load trace for message {}: {}", message_id, e);
+            AppError::InternalServerError
+        })?;
+
+    Ok(Json(MessageTraceResponse {
+        message_id,
+        room_id: message.room_id,
+        user_id: message.user_id,
+        created_at: message.timestamp,
+        deleted: message.deleted,
+        trace_missing: fields.is_empty(),
+        trace: parse_trace(&fields),
+    }))
+}
@@ -1,23 +1,35 @@
 use axum::{
-    extract::{ws::WebSocket, Path, Query, State, WebSocketUpgrade},
-    http::StatusCode,
+    extract::{ws::WebSocket, ConnectInfo, Path, Query, State, WebSocketUpgrade},
+    http::{HeaderMap, StatusCode},
     response::IntoResponse,
     Json,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use futures::{SinkExt, StreamExt, TryStreamExt};
-use mongodb::{bson::doc, Collection};
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    Collection,
+};
 use redis::AsyncCommands;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tracing::{info, error, debug};
+use tracing::{error, info, warn};
 
 use crate::{
-    auth::verify_token,
-    models::{DirectMessage, WsMessage},
-    AppState,
+    auth::{verify_token, AuthUser},
+    dm_keys::record_key_event,
+    event_log::LogEvent,
+    models::{DMConversation, DirectMessage, WsMessage},
+    read_cursors::MAX_UNREAD_COUNT,
+    notifications::{NotificationHint, NotificationSettings},
+    user_history::HistorySource,
+    user_service::ConversationUpdate,
+    AppError, AppState,
 };
 
+// Group conversations stop accepting participants at this size
+pub const MAX_PARTICIPANTS: usize = 32;
+
 #[derive(Debug, Deserialize)]
 pub struct GetDMMessagesQuery {
     pub limit: Option<i64>,
@@ -35,6 +47,9 @@ pub struct DirectMessageResponse {
     pub edited_at: Option<String>,
     pub deleted: bool,
     pub read_by: Vec<String>,
+    pub delivered_to: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<crate::uploads::Attachment>,
 }
 
 impl From<DirectMessage> for DirectMessageResponse {
@@ -49,6 +64,8 @@ impl From<DirectMessage> for DirectMessageResponse {
             edited_at: msg.edited_at.map(|dt| dt.to_rfc3339()),
             deleted: msg.deleted,
             read_by: msg.read_by,
+            delivered_to: msg.delivered_to,
+            attachments: msg.attachments,
         }
     }
 }
@@ -63,9 +80,17 @@ pub async fn dm_websocket_handler(
     ws: WebSocketUpgrade,
     Path(conversation_id): Path<String>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<std::net::SocketAddr>>,
+) -> Result<impl IntoResponse, AppError> {
     info!("DM WebSocket connection request for conversation: {}", conversation_id);
-    ws.on_upgrade(move |socket| handle_dm_socket(socket, conversation_id, Arc::new(state)))
+    state.shutdown.admit()?;
+    let ip = state.tarpit.client_ip(&headers, peer.map(|ConnectInfo(peer)| peer));
+    crate::socket_abuse::admit(&state, ip).await?;
+    let limit = state.frame_limits.transport_limit();
+    Ok(ws.max_frame_size(limit)
+        .max_message_size(limit)
+        .on_upgrade(move |socket| handle_dm_socket(socket, conversation_id, Arc::new(state))))
 }
 
 async fn handle_dm_socket(
@@ -80,17 +105,20 @@ async fn handle_dm_socket(
     // Channel for this conversation
     let channel = format!("dm:{}", conversation_id);
     
-    // Create a separate connection for pubsub
-    let pubsub_conn = state.redis.get_async_connection().await.unwrap();
-    let mut pubsub = pubsub_conn.into_pubsub();
-    pubsub.subscribe(&channel).await.unwrap();
-    let mut pubsub_stream = pubsub.into_on_message();
     
     let mut user_id: Option<String> = None;
     let mut username: Option<String> = None;
+    let mut notification_settings = NotificationSettings::default();
+    // Users this one has blocked, whose messages aren't forwarded
+    let mut blocked = std::collections::HashSet::new();
 
     // Wait for join message
     if let Some(Ok(msg)) = receiver.next().await {
+        if let Err(violation) = state.frame_limits.check(&msg) {
+            warn!("Closing DM socket for {} after an invalid frame: {}", conversation_id, violation);
+            let _ = sender.send(axum::extract::ws::Message::Close(Some(violation.close_frame()))).await;
+            return;
+        }
         if let Ok(text) = msg.to_text() {
             info!("Received message: {}", text);
             match serde_json::from_str::<WsMessage>(text) {
@@ -118,43 +146,54 @@ async fn handle_dm_socket(
                                 }
 
                                 // Verify user has access to this conversation
-                                if !verify_conversation_access(&state, &conversation_id, &uid).await {
+                                let conversation = match find_conversation(&state, &conversation_id).await {
+                                    Some(conversation) if may_access(Some(&conversation), &uid) => conversation,
+                                    _ => {
+                                        let _ = sender.send(axum::extract::ws::Message::Text(
+                                            serde_json::to_string(&WsMessage::Error {
+                                                message: "Access denied".to_string(),
+                                            }).unwrap()
+                                        )).await;
+                                        return;
+                                    }
+                                };
+
+                                if crate::user_blocks::is_blocked_by_any(&state, &uid, &conversation.participants).await {
                                     let _ = sender.send(axum::extract::ws::Message::Text(
                                         serde_json::to_string(&WsMessage::Error {
-                                            message: "Access denied".to_string(),
+                                            message: "You have been blocked in this conversation".to_string(),
                                         }).unwrap()
                                     )).await;
                                     return;
                                 }
+                                blocked = crate::user_blocks::blocked_by(&state, &uid).await;
 
                                 user_id = Some(uid);
                                 username = Some(uname);
-                                
+                                let participant_count = conversation.participant_count();
+                                notification_settings = conversation.notifications;
+
                                 // Send joined confirmation
                                 let _ = sender.send(axum::extract::ws::Message::Text(
                                     serde_json::to_string(&WsMessage::DMJoined {
                                         conversation_id: conversation_id.clone(),
-                                        participant_count: 2, // For now, always 2 for DMs
+                                        participant_count,
                                     }).unwrap()
                                 )).await;
 
-                                // Send message history
-                                let messages = get_dm_messages(&state, &conversation_id, None, Some(50)).await;
+                                // Send message history, without messages from users this one blocked
+                                let mut messages = get_dm_messages(&state, &conversation_id, user_id.as_deref(), None, Some(50)).await;
+                                messages.retain(|message| !blocked.contains(&message.sender_id));
                                 let _ = sender.send(axum::extract::ws::Message::Text(
                                     serde_json::to_string(&WsMessage::MessageHistory {
-                                        messages: messages.into_iter().map(|dm| crate::models::Message {
-                                            id: dm.id,
-                                            room_id: dm.conversation_id,
-                                            user_id: dm.sender_id,
-                                            username: dm.sender_username,
-                                            content: dm.content,
-                                            timestamp: dm.timestamp,
-                                            edited_at: dm.edited_at,
-                                            deleted: dm.deleted,
-                                            reactions: vec![],
-                                        }).collect(),
+                                        messages: messages.into_iter().map(crate::models::Message::from).collect(),
+                                        since: None,
                                     }).unwrap()
                                 )).await;
+                                // The history just reached this socket
+                                if let Some(uid) = &user_id {
+                                    mark_delivered(&state, &conversation_id, uid, None).await;
+                                }
                             }
                             Err(_) => {
                                 let _ = sender.send(axum::extract::ws::Message::Text(
@@ -190,33 +229,126 @@ async fn handle_dm_socket(
 
     let Some(user_id) = user_id else { return };
     let Some(username) = username else { return };
+    let presence_id = format!("dm:{}", uuid::Uuid::new_v4());
+    crate::user_presence::connect(&state, &presence_id, &user_id).await;
 
+    // Listen through the shared pub/sub connection, including for this
+    // user's own messages while they are shadow-banned
+    let mut pubsub_messages = state.pubsub.subscribe(&channel);
+    let mut echo_messages = state.pubsub.subscribe(&crate::shadow_bans::dm_echo_channel(&conversation_id, &user_id));
+    
     // Spawn task to handle incoming Redis messages
     let (redis_tx, mut redis_rx) = tokio::sync::mpsc::channel::<String>(100);
     let redis_task = tokio::spawn(async move {
-        while let Some(msg) = pubsub_stream.next().await {
-            if let Ok(payload) = msg.get_payload::<String>() {
-                let _ = redis_tx.send(payload).await;
+        loop {
+            let received = tokio::select! {
+                received = pubsub_messages.recv() => received,
+                received = echo_messages.recv() => received,
+            };
+            match received {
+                Ok(payload) => {
+                    if redis_tx.send(payload.to_string()).await.is_err() {
+                        break;
+                    }
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
             }
         }
     });
     
-    // Spawn task to forward Redis messages to WebSocket
-    let forward_task = tokio::spawn(async move {
-        while let Some(payload) = redis_rx.recv().await {
-            let _ = sender.send(axum::extract::ws::Message::Text(payload)).await;
+    // Spawn task to forward Redis messages to WebSocket. It ends, closing
+    // the socket, once this user is removed from the conversation, or when
+    // the receive side below finishes, sending its close frame if it has one.
+    let member_id = user_id.clone();
+    let receipts_state = state.clone();
+    let receipts_conversation = conversation_id.clone();
+    let (close_tx, mut close_rx) = tokio::sync::oneshot::channel::<axum::extract::ws::CloseFrame<'static>>();
+    let mut close_tx = Some(close_tx);
+    let mut shutdown = state.shutdown.notice(&format!("{}:{}", conversation_id, user_id));
+    // Blocks made while the socket is open apply from the next refresh
+    let mut block_refresh = tokio::time::interval_at(
+        tokio::time::Instant::now() + crate::user_blocks::REFRESH_INTERVAL,
+        crate::user_blocks::REFRESH_INTERVAL,
+    );
+    let mut forward_task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = block_refresh.tick() => {
+                    blocked = crate::user_blocks::blocked_by(&receipts_state, &member_id).await;
+                }
+                close = &mut close_rx => {
+                    if let Ok(frame) = close {
+                        let _ = sender.send(axum::extract::ws::Message::Close(Some(frame))).await;
+                    }
+                    break;
+                }
+                _ = shutdown.draining() => {
+                    let notice = WsMessage::ServerShutdown { reconnect_after: shutdown.reconnect_after };
+                    if let Ok(text) = serde_json::to_string(&notice) {
+                        let _ = sender.send(axum::extract::ws::Message::Text(text)).await;
+                    }
+                    let frame = axum::extract::ws::CloseFrame {
+                        code: axum::extract::ws::close_code::RESTART,
+                        reason: "server shutting down".into(),
+                    };
+                    let _ = sender.send(axum::extract::ws::Message::Close(Some(frame))).await;
+                    break;
+                }
+                payload = redis_rx.recv() => {
+                    let Some(payload) = payload else { break };
+                    if crate::user_blocks::is_from_blocked(&payload, &blocked) {
+                        continue;
+                    }
+                    let removed = is_removal_of(&payload, &member_id);
+                    let delivered = delivered_message_id(&payload, &member_id);
+                    if sender.send(axum::extract::ws::Message::Text(payload)).await.is_ok() {
+                        if let Some(message_id) = delivered {
+                            let state = receipts_state.clone();
+                            let conversation_id = receipts_conversation.clone();
+                            let user_id = member_id.clone();
+                            tokio::spawn(async move {
+                                mark_delivered(&state, &conversation_id, &user_id, Some(message_id)).await;
+                            });
+                        }
+                    }
+                    if removed {
+                        let _ = sender.send(axum::extract::ws::Message::Close(None)).await;
+                        break;
+                    }
+                }
+            }
         }
     });
 
     // Handle incoming WebSocket messages
     while let Some(Ok(msg)) = receiver.next().await {
+        if forward_task.is_finished() {
+            break;
+        }
+        if let Err(violation) = state.frame_limits.check(&msg) {
+            warn!("Closing DM socket of {} after an invalid frame: {}", user_id, violation);
+            if let Some(close_tx) = close_tx.take() {
+                let _ = close_tx.send(violation.close_frame());
+            }
+            break;
+        }
         if let Ok(text) = msg.to_text() {
             if let Ok(ws_msg) = serde_json::from_str::<WsMessage>(text) {
                 match ws_msg {
-                    WsMessage::DMMessage { conversation_id: conv_id, content } => {
+                    WsMessage::DMMessage { conversation_id: conv_id, content, attachments } => {
                         if conv_id != conversation_id {
                             continue;
                         }
+                        if let Err(e) = crate::uploads::check_attachments(state.uploads.as_deref(), &attachments, &user_id) {
+                            warn!("Refused DM from {} in {}: {}", user_id, conversation_id, e);
+                            continue;
+                        }
+
+                        // Shadow-banned senders see their messages go out,
+                        // but they reach no one else and don't move the
+                        // conversation
+                        let shadow_banned = crate::shadow_bans::is_shadow_banned(&state.redis_pool, &user_id).await;
 
                         // Save message to database
                         let message = DirectMessage {
@@ -229,29 +361,29 @@ async fn handle_dm_socket(
                             edited_at: None,
                             deleted: false,
                             read_by: vec![user_id.clone()], // Sender has read their own message
+                            delivered_to: vec![],
+                            attachments,
+                            shadow: shadow_banned,
                         };
 
                         if let Ok(saved_msg) = save_dm_message(&state, message).await {
+                            let publish_channel = if shadow_banned {
+                                crate::shadow_bans::dm_echo_channel(&conversation_id, &user_id)
+                            } else {
+                                update_conversation_last_message(&state, &saved_msg).await;
+                                channel.clone()
+                            };
+
                             // Broadcast to all participants
-                            let broadcast_msg = WsMessage::NewMessage(crate::models::Message {
-                                id: saved_msg.id,
-                                room_id: saved_msg.conversation_id.clone(),
-                                user_id: saved_msg.sender_id,
-                                username: saved_msg.sender_username,
-                                content: saved_msg.content,
-                                timestamp: saved_msg.timestamp,
-                                edited_at: saved_msg.edited_at,
-                                deleted: saved_msg.deleted,
-                                reactions: vec![],
-                            });
+                            let notification = NotificationHint::for_direct_message(&saved_msg, &notification_settings);
+                            let mut message = crate::models::Message::from(saved_msg);
+                            message.notification = Some(notification);
+                            let broadcast_msg = WsMessage::NewMessage(message);
 
                             let _ = redis.publish::<_, _, ()>(
-                                &channel,
+                                &publish_channel,
                                 serde_json::to_string(&broadcast_msg).unwrap()
                             ).await;
-
-                            // Update conversation's last message in user service
-                            update_conversation_last_message(&state, &conversation_id, &content, &user_id).await;
                         }
                     }
                     WsMessage::DMTyping { conversation_id: conv_id, is_typing } => {
@@ -274,6 +406,46 @@ async fn handle_dm_socket(
                         // Mark messages as read
                         mark_messages_as_read(&state, &conversation_id, &user_id).await;
                     }
+                    WsMessage::DMEdit { conversation_id: conv_id, message_id, content } => {
+                        if conv_id != conversation_id {
+                            continue;
+                        }
+                        match change_dm_message(&state, &conversation_id, &message_id, &user_id, DMChange::Edit(content)).await {
+                            Ok(event) => {
+                                let _ = redis.publish::<_, _, ()>(&channel, serde_json::to_string(&event).unwrap()).await;
+                            }
+                            Err(e) => warn!("Refused edit of DM {} by {}: {:?}", message_id, user_id, e),
+                        }
+                    }
+                    WsMessage::DMDelete { conversation_id: conv_id, message_id } => {
+                        if conv_id != conversation_id {
+                            continue;
+                        }
+                        match change_dm_message(&state, &conversation_id, &message_id, &user_id, DMChange::Delete).await {
+                            Ok(event) => {
+                                let _ = redis.publish::<_, _, ()>(&channel, serde_json::to_string(&event).unwrap()).await;
+                            }
+                            Err(e) => warn!("Refused delete of DM {} by {}: {:?}", message_id, user_id, e),
+                        }
+                    }
+                    WsMessage::DMKeyAnnounce { conversation_id: conv_id, event } => {
+                        if conv_id != conversation_id {
+                            continue;
+                        }
+
+                        // Persisted first so participants who are offline can catch up
+                        match record_key_event(&state, &conversation_id, &user_id, event).await {
+                            Ok(key_event) => {
+                                let _ = redis.publish::<_, _, ()>(
+                                    &channel,
+                                    serde_json::to_string(&WsMessage::DMKeyEvent { event: key_event }).unwrap()
+                                ).await;
+                            }
+                            Err(e) => {
+                                error!("Rejected key event in conversation {}: {}", conversation_id, e);
+                            }
+                        }
+                    }
                     _ => {}
                 }
             }
@@ -281,33 +453,169 @@ async fn handle_dm_socket(
     }
 
     // Cleanup
+    crate::user_presence::disconnect(&state, &presence_id).await;
     redis_task.abort();
-    forward_task.abort();
+    close_tx.take();
+    if tokio::time::timeout(std::time::Duration::from_secs(1), &mut forward_task).await.is_err() {
+        forward_task.abort();
+    }
+}
+
+/// The id of a new message in a published DM payload, unless `user_id` sent
+/// it. Delivering it to their socket counts as a delivery receipt.
+pub fn delivered_message_id(payload: &str, user_id: &str) -> Option<ObjectId> {
+    if !payload.starts_with(r#"{"type":"NewMessage""#) {
+        return None;
+    }
+    match serde_json::from_str::<WsMessage>(payload) {
+        Ok(WsMessage::NewMessage(message)) if message.user_id != user_id => message.id,
+        _ => None,
+    }
 }
 
-async fn verify_conversation_access(
-    _state: &AppState,
-    _conversation_id: &str,
-    _user_id: &str,
+/// Whether a published DM payload removes `user_id` from the conversation.
+pub fn is_removal_of(payload: &str, user_id: &str) -> bool {
+    if !payload.contains("DMParticipantRemoved") {
+        return false;
+    }
+    matches!(
+        serde_json::from_str::<WsMessage>(payload),
+        Ok(WsMessage::DMParticipantRemoved { user_id: removed, .. }) if removed == user_id
+    )
+}
+
+fn conversations(state: &AppState) -> Collection<DMConversation> {
+    state.database.collection("dm_conversations")
+}
+
+async fn find_conversation(state: &AppState, conversation_id: &str) -> Option<DMConversation> {
+    match conversations(state).find_one(doc! { "_id": conversation_id }, None).await {
+        Ok(conversation) => conversation,
+        Err(e) => {
+            error!("Failed to load conversation {}: {}", conversation_id, e);
+            None
+        }
+    }
+}
+
+/// Whether `user_id` may read and post in a conversation. Conversations
+/// that don't exist here are denied, as is everyone when they couldn't be
+/// loaded.
+pub fn may_access(conversation: Option<&DMConversation>, user_id: &str) -> bool {
+    conversation.is_some_and(|conversation| conversation.is_participant(user_id))
+}
+
+/// Whether `user_id` may edit or delete a message: only its sender can, and
+/// deleted messages stay deleted.
+pub fn may_change(message: &DirectMessage, user_id: &str) -> bool {
+    message.sender_id == user_id && !message.deleted
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DMChange {
+    Edit(String),
+    Delete,
+}
+
+// Applies a sender's edit or delete and returns the event for the conversation
+async fn change_dm_message(
+    state: &AppState,
+    conversation_id: &str,
+    message_id: &str,
+    user_id: &str,
+    change: DMChange,
+) -> Result<WsMessage, AppError> {
+    let id = ObjectId::parse_str(message_id).map_err(|_| AppError::BadRequest("Invalid message id".to_string()))?;
+    if let DMChange::Edit(content) = &change {
+        if content.trim().is_empty() {
+            return Err(AppError::BadRequest("Message content cannot be empty".to_string()));
+        }
+    }
+    let collection: Collection<DirectMessage> = state.database.collection("direct_messages");
+    let message = collection
+        .find_one(doc! { "_id": id, "conversation_id": conversation_id }, None)
+        .await?
+        .filter(|message| !message.deleted)
+        .ok_or(AppError::NotFound)?;
+    if !may_change(&message, user_id) {
+        return Err(AppError::Forbidden);
+    }
+
+    // The sender and deleted checks are repeated so a concurrent delete wins
+    let filter = doc! { "_id": id, "sender_id": user_id, "deleted": false };
+    let last_message = doc! { "_id": conversation_id, "last_message._id": id };
+    let (update, last_message_update, logged, event) = match change {
+        DMChange::Edit(content) => {
+            let edited_at = Utc::now();
+            let at = mongodb::bson::DateTime::from_millis(edited_at.timestamp_millis());
+            (
+                doc! { "$set": { "content": &content, "edited_at": at } },
+                doc! { "$set": { "last_message.content": &content, "last_message.edited_at": at } },
+                LogEvent::MessageEdited {
+                    source: HistorySource::Dm,
+                    room_id: conversation_id.to_string(),
+                    message_id: message_id.to_string(),
+                    content: content.clone(),
+                    edited_at,
+                },
+                WsMessage::DMEdited {
+                    conversation_id: conversation_id.to_string(),
+                    message_id: message_id.to_string(),
+                    content,
+                    edited_at,
+                },
+            )
+        }
+        DMChange::Delete => (
+            doc! { "$set": { "deleted": true } },
+            doc! { "$set": { "last_message.deleted": true } },
+            LogEvent::MessageDeleted {
+                source: HistorySource::Dm,
+                room_id: conversation_id.to_string(),
+                message_ids: vec![message_id.to_string()],
+            },
+            WsMessage::DMDeleted { conversation_id: conversation_id.to_string(), message_id: message_id.to_string() },
+        ),
+    };
+    if collection.update_one(filter, update, None).await?.modified_count == 0 {
+        return Err(AppError::NotFound);
+    }
+    crate::event_log::append(state, logged).await;
+    if let Err(e) = conversations(state).update_one(last_message, last_message_update, None).await {
+        error!("Failed to update last message of conversation {}: {}", conversation_id, e);
+    }
+    Ok(event)
+}
+
+pub(crate) async fn verify_conversation_access(
+    state: &AppState,
+    conversation_id: &str,
+    user_id: &str,
 ) -> bool {
-    // TODO: Call user service to verify access
-    // For now, return true (implement actual verification)
-    true
+    let allowed = may_access(find_conversation(state, conversation_id).await.as_ref(), user_id);
+    if !allowed {
+        warn!("Denied user {} access to conversation {}", user_id, conversation_id);
+    }
+    allowed
 }
 
+// What `viewer` may see of the conversation; messages a shadow-banned
+// sender sent are only theirs to see
 async fn get_dm_messages(
     state: &AppState,
     conversation_id: &str,
+    viewer: Option<&str>,
     before: Option<String>,
     limit: Option<i64>,
 ) -> Vec<DirectMessage> {
     let collection: Collection<DirectMessage> = state.database.collection("direct_messages");
-    let limit = limit.unwrap_or(50).min(100) as i64;
+    let limit = limit.unwrap_or(50).min(100);
     
     let mut filter = doc! {
         "conversation_id": conversation_id,
         "deleted": false,
     };
+    filter.extend(crate::shadow_bans::visible_to("sender_id", viewer));
     
     if let Some(before_id) = before {
         if let Ok(oid) = mongodb::bson::oid::ObjectId::parse_str(&before_id) {
@@ -341,10 +649,59 @@ async fn save_dm_message(
     
     let result = collection.insert_one(&message, None).await?;
     message.id = Some(result.inserted_id.as_object_id().unwrap());
+    crate::event_log::append(state, LogEvent::dm_message(&message)).await;
     
     Ok(message)
 }
 
+async fn newest_message_id(state: &AppState, conversation_id: &str) -> Option<ObjectId> {
+    let collection: Collection<DirectMessage> = state.database.collection("direct_messages");
+    let newest = mongodb::options::FindOneOptions::builder().sort(doc! { "_id": -1 }).build();
+    match collection.find_one(doc! { "conversation_id": conversation_id }, newest).await {
+        Ok(message) => message.and_then(|message| message.id),
+        Err(e) => {
+            error!("Failed to load newest message of conversation {}: {}", conversation_id, e);
+            None
+        }
+    }
+}
+
+/// Records that messages from others, up to `up_to` or all of them, reached
+/// `user_id`, and tells the conversation when any had not yet.
+async fn mark_delivered(state: &AppState, conversation_id: &str, user_id: &str, up_to: Option<ObjectId>) {
+    let collection: Collection<DirectMessage> = state.database.collection("direct_messages");
+    let mut filter = doc! {
+        "conversation_id": conversation_id,
+        "sender_id": { "$ne": user_id },
+        "delivered_to": { "$ne": user_id },
+        "shadow": { "$ne": true },
+    };
+    if let Some(up_to) = up_to {
+        filter.insert("_id", doc! { "$lte": up_to });
+    }
+    let result = collection.update_many(filter, doc! { "$addToSet": { "delivered_to": user_id } }, None).await;
+    match result {
+        Ok(result) if result.modified_count > 0 => {}
+        Ok(_) => return,
+        Err(e) => {
+            error!("Failed to record delivery to {} in {}: {}", user_id, conversation_id, e);
+            return;
+        }
+    }
+
+    let up_to = match up_to {
+        Some(up_to) => Some(up_to),
+        None => newest_message_id(state, conversation_id).await,
+    };
+    if let Some(up_to) = up_to {
+        publish_to_conversation(state, conversation_id, &WsMessage::DMDelivered {
+            conversation_id: conversation_id.to_string(),
+            user_id: user_id.to_string(),
+            up_to: up_to.to_hex(),
+        }).await;
+    }
+}
+
 async fn mark_messages_as_read(
     state: &AppState,
     conversation_id: &str,
@@ -352,38 +709,91 @@ async fn mark_messages_as_read(
 ) {
     let collection: Collection<DirectMessage> = state.database.collection("direct_messages");
     
-    let _ = collection.update_many(
+    // Reading a message implies it was delivered
+    let result = collection.update_many(
         doc! {
             "conversation_id": conversation_id,
             "sender_id": doc! { "$ne": user_id },
             "read_by": doc! { "$ne": user_id },
+            "shadow": doc! { "$ne": true },
         },
         doc! {
-            "$addToSet": { "read_by": user_id }
+            "$addToSet": { "read_by": user_id, "delivered_to": user_id }
         },
         None,
     ).await;
+    let newly_read = match result {
+        Ok(result) => result.modified_count > 0,
+        Err(e) => {
+            error!("Failed to mark messages read by {} in {}: {}", user_id, conversation_id, e);
+            false
+        }
+    };
+
+    // Each participant's read position, so group members can be told apart
+    let Some(newest_id) = newest_message_id(state, conversation_id).await else {
+        return;
+    };
+    if newly_read {
+        publish_to_conversation(state, conversation_id, &WsMessage::DMReadUpdated {
+            conversation_id: conversation_id.to_string(),
+            user_id: user_id.to_string(),
+            up_to: newest_id.to_hex(),
+        }).await;
+    }
+    let result = conversations(state)
+        .update_one(
+            doc! { "_id": conversation_id, "participants": user_id },
+            doc! { "$set": { format!("last_read.{}", user_id): newest_id.to_hex() } },
+            None,
+        )
+        .await;
+    if let Err(e) = result {
+        error!("Failed to record read position of {} in {}: {}", user_id, conversation_id, e);
+    }
 }
 
-async fn update_conversation_last_message(
-    _state: &AppState,
-    conversation_id: &str,
-    _message: &str,
-    _sender_id: &str,
-) {
-    // TODO: Call user service to update conversation's last message
-    // This would be an HTTP call to the user service
-    // For now, just log it
-    info!("Would update conversation {} last message", conversation_id);
+// Moves the conversation to the top of its participants' lists
+async fn update_conversation_last_message(state: &AppState, message: &DirectMessage) {
+    let Ok(last_message) = mongodb::bson::to_bson(message) else {
+        return;
+    };
+    let result = conversations(state)
+        .update_one(
+            doc! { "_id": &message.conversation_id },
+            doc! { "$set": { "last_message": last_message, "updated_at": bson_now() } },
+            None,
+        )
+        .await;
+    if let Err(e) = result {
+        error!("Failed to update last message of conversation {}: {}", message.conversation_id, e);
+    }
+
+    // Clients that still read conversation lists from the user service
+    state.user_service.sync_conversation(ConversationUpdate {
+        conversation_id: message.conversation_id.clone(),
+        message_id: message.id.map(|id| id.to_hex()).unwrap_or_default(),
+        sender_id: message.sender_id.clone(),
+        content: message.content.clone(),
+        sent_at: message.timestamp,
+    }).await;
+}
+
+fn bson_now() -> mongodb::bson::DateTime {
+    mongodb::bson::DateTime::from_millis(Utc::now().timestamp_millis())
 }
 
-// REST endpoint to get DM messages
+// REST endpoint to get DM messages, participants only
 pub async fn get_dm_messages_handler(
     Path(conversation_id): Path<String>,
     Query(query): Query<GetDMMessagesQuery>,
     State(state): State<AppState>,
+    user: AuthUser,
 ) -> Result<Json<DMMessageResponse>, StatusCode> {
-    let messages = get_dm_messages(&state, &conversation_id, query.before, query.limit).await;
+    if !verify_conversation_access(&state, &conversation_id, &user.user_id).await {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let messages = get_dm_messages(&state, &conversation_id, Some(&user.user_id), query.before, query.limit).await;
     let has_more = messages.len() == query.limit.unwrap_or(50) as usize;
     
     let message_responses: Vec<DirectMessageResponse> = messages.into_iter()
@@ -394,4 +804,385 @@ pub async fn get_dm_messages_handler(
         messages: message_responses,
         has_more,
     }))
-}
\ No newline at end of file
+}
+#[derive(Debug, Deserialize)]
+pub struct EditDMRequest {
+    pub content: String,
+}
+
+// PATCH /api/dm/:conversation_id/messages/:message_id - sender only
+pub async fn edit_dm_message_handler(
+    Path((conversation_id, message_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<EditDMRequest>,
+) -> Result<Json<WsMessage>, AppError> {
+    if !verify_conversation_access(&state, &conversation_id, &user.user_id).await {
+        return Err(AppError::Forbidden);
+    }
+    let event = change_dm_message(&state, &conversation_id, &message_id, &user.user_id, DMChange::Edit(req.content)).await?;
+    publish_to_conversation(&state, &conversation_id, &event).await;
+    Ok(Json(event))
+}
+
+// DELETE /api/dm/:conversation_id/messages/:message_id - sender only
+pub async fn delete_dm_message_handler(
+    Path((conversation_id, message_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<WsMessage>, AppError> {
+    if !verify_conversation_access(&state, &conversation_id, &user.user_id).await {
+        return Err(AppError::Forbidden);
+    }
+    let event = change_dm_message(&state, &conversation_id, &message_id, &user.user_id, DMChange::Delete).await?;
+    publish_to_conversation(&state, &conversation_id, &event).await;
+    Ok(Json(event))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DMConversationResponse {
+    pub id: String,
+    pub participants: Vec<String>,
+    pub participant_count: i32,
+    pub last_message: Option<DirectMessageResponse>,
+    pub last_read: std::collections::HashMap<String, String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<DMConversation> for DMConversationResponse {
+    fn from(conversation: DMConversation) -> Self {
+        DMConversationResponse {
+            participant_count: conversation.participant_count(),
+            id: conversation.id,
+            participants: conversation.participants,
+            last_message: conversation.last_message.map(DirectMessageResponse::from),
+            last_read: conversation.last_read,
+            created_at: conversation.created_at.to_rfc3339(),
+            updated_at: conversation.updated_at.to_rfc3339(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateConversationRequest {
+    // Everyone but the caller, who is always a participant
+    pub participant_ids: Vec<String>,
+}
+
+/// The participants of a new conversation: the caller plus everyone
+/// requested, without duplicates.
+pub fn conversation_participants(creator_id: &str, requested: &[String]) -> Result<Vec<String>, String> {
+    let mut participants = vec![creator_id.to_string()];
+    for id in requested.iter().map(|id| id.trim()) {
+        if id.is_empty() {
+            return Err("Participant ids must not be empty".to_string());
+        }
+        if !participants.iter().any(|participant| participant == id) {
+            participants.push(id.to_string());
+        }
+    }
+    if participants.len() < 2 {
+        return Err("A conversation needs at least one other participant".to_string());
+    }
+    if participants.len() > MAX_PARTICIPANTS {
+        return Err(format!("Conversations are limited to {} participants", MAX_PARTICIPANTS));
+    }
+    Ok(participants)
+}
+
+/// One-to-one conversations get an id derived from the pair, so both users
+/// creating one at once end up in the same conversation. Groups get a new id.
+pub fn conversation_id_for(participants: &[String]) -> String {
+    match participants {
+        [a, b] if a < b => format!("dm_{}_{}", a, b),
+        [a, b] => format!("dm_{}_{}", b, a),
+        _ => format!("group_{}", uuid::Uuid::new_v4()),
+    }
+}
+
+// POST /api/dm/conversations - returns the existing one-to-one conversation
+// if there already is one
+pub async fn create_conversation_handler(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateConversationRequest>,
+) -> Result<Json<DMConversationResponse>, AppError> {
+    let participants = conversation_participants(&user.user_id, &req.participant_ids).map_err(AppError::BadRequest)?;
+    let now = Utc::now();
+    let conversation = DMConversation {
+        id: conversation_id_for(&participants),
+        participants,
+        last_message: None,
+        created_at: now,
+        updated_at: now,
+        last_read: Default::default(),
+        notifications: Default::default(),
+    };
+
+    match conversations(&state).insert_one(&conversation, None).await {
+        Ok(_) => {
+            info!("User {} created conversation {} with {} participants", user.user_id, conversation.id, conversation.participants.len());
+            for participant in &conversation.participants {
+                crate::event_log::append(&state, LogEvent::MemberJoined {
+                    conversation_id: conversation.id.clone(),
+                    user_id: participant.clone(),
+                }).await;
+            }
+            Ok(Json(conversation.into()))
+        }
+        Err(e) if crate::db::is_duplicate_key(&e) => {
+            let existing = find_conversation(&state, &conversation.id).await.ok_or(AppError::InternalServerError)?;
+            // Someone who left the pair's conversation doesn't get back in this way
+            if !existing.is_participant(&user.user_id) {
+                return Err(AppError::Forbidden);
+            }
+            Ok(Json(existing.into()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListConversationsQuery {
+    pub limit: Option<i64>,
+    // updated_at of the last conversation on the previous page
+    pub before: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationListResponse {
+    pub conversations: Vec<DMConversationResponse>,
+    pub has_more: bool,
+}
+
+// GET /api/dm/conversations?limit=&before= - the caller's conversations,
+// most recently active first
+pub async fn list_conversations_handler(
+    Query(query): Query<ListConversationsQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<ConversationListResponse>, AppError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+    let mut filter = doc! { "participants": &user.user_id };
+    if let Some(before) = query.before {
+        filter.insert("updated_at", doc! { "$lt": mongodb::bson::DateTime::from_millis(before.timestamp_millis()) });
+    }
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "updated_at": -1 })
+        .limit(limit)
+        .build();
+    let found: Vec<DMConversation> = conversations(&state).find(filter, options).await?.try_collect().await?;
+
+    Ok(Json(ConversationListResponse {
+        has_more: found.len() as i64 == limit,
+        conversations: found.into_iter().map(DMConversationResponse::from).collect(),
+    }))
+}
+
+// GET /api/dm/conversations/:conversation_id - participants only
+pub async fn get_conversation_handler(
+    Path(conversation_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<DMConversationResponse>, AppError> {
+    let conversation = find_conversation(&state, &conversation_id).await.ok_or(AppError::NotFound)?;
+    if !conversation.is_participant(&user.user_id) {
+        return Err(AppError::Forbidden);
+    }
+    Ok(Json(conversation.into()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddParticipantRequest {
+    pub user_id: String,
+}
+
+async fn publish_to_conversation(state: &AppState, conversation_id: &str, msg: &WsMessage) {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        error!("No Redis connection to announce a change to conversation {}", conversation_id);
+        return;
+    };
+    let channel = format!("dm:{}", conversation_id);
+    if let Err(e) = conn.publish::<_, _, ()>(&channel, serde_json::to_string(msg).unwrap()).await {
+        error!("Failed to publish to {}: {}", channel, e);
+    }
+}
+
+// POST /api/dm/:conversation_id/participants - any participant may add someone
+// to a group; one-to-one conversations are refused, start a group instead
+pub async fn add_participant_handler(
+    Path(conversation_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<AddParticipantRequest>,
+) -> Result<Json<DMConversationResponse>, AppError> {
+    let conversation = find_conversation(&state, &conversation_id).await.ok_or(AppError::NotFound)?;
+    if !conversation.is_participant(&user.user_id) {
+        return Err(AppError::Forbidden);
+    }
+    if conversation.is_participant(&req.user_id) {
+        return Err(AppError::Conflict(format!("{} is already in the conversation", req.user_id)));
+    }
+    if conversation.is_one_to_one() {
+        return Err(AppError::Conflict("One-to-one conversations can't be added to; start a group conversation instead".to_string()));
+    }
+
+    // The size checks are part of the filter so concurrent adds and leaves
+    // can't overshoot or reach into a one-to-one conversation
+    let options = mongodb::options::FindOneAndUpdateOptions::builder()
+        .return_document(mongodb::options::ReturnDocument::After)
+        .build();
+    let updated = conversations(&state)
+        .find_one_and_update(
+            doc! {
+                "_id": &conversation_id,
+                "participants": &user.user_id,
+                "participants.2": { "$exists": true },
+                format!("participants.{}", MAX_PARTICIPANTS - 1): { "$exists": false },
+            },
+            doc! {
+                "$addToSet": { "participants": &req.user_id },
+                "$set": { "updated_at": bson_now() },
+            },
+            options,
+        )
+        .await?
+        .ok_or_else(|| AppError::BadRequest(format!("Conversations are limited to {} participants", MAX_PARTICIPANTS)))?;
+
+    info!("User {} added {} to conversation {}", user.user_id, req.user_id, conversation_id);
+    crate::event_log::append(&state, LogEvent::MemberJoined {
+        conversation_id: conversation_id.clone(),
+        user_id: req.user_id.clone(),
+    }).await;
+    publish_to_conversation(&state, &conversation_id, &WsMessage::DMParticipantAdded {
+        conversation_id: conversation_id.clone(),
+        user_id: req.user_id,
+        added_by: user.user_id,
+        participant_count: updated.participant_count(),
+    }).await;
+    Ok(Json(updated.into()))
+}
+
+// DELETE /api/dm/:conversation_id/participants/:user_id - participants may
+// leave; only admins remove someone else
+pub async fn remove_participant_handler(
+    Path((conversation_id, participant_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<DMConversationResponse>, AppError> {
+    if participant_id != user.user_id && !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+
+    let options = mongodb::options::FindOneAndUpdateOptions::builder()
+        .return_document(mongodb::options::ReturnDocument::After)
+        .build();
+    let updated = conversations(&state)
+        .find_one_and_update(
+            doc! { "_id": &conversation_id, "participants": &participant_id },
+            doc! {
+                "$pull": { "participants": &participant_id },
+                "$unset": { format!("last_read.{}", participant_id): "" },
+                "$set": { "updated_at": bson_now() },
+            },
+            options,
+        )
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    info!("User {} removed {} from conversation {}", user.user_id, participant_id, conversation_id);
+    crate::event_log::append(&state, LogEvent::MemberLeft {
+        conversation_id: conversation_id.clone(),
+        user_id: participant_id.clone(),
+    }).await;
+    publish_to_conversation(&state, &conversation_id, &WsMessage::DMParticipantRemoved {
+        conversation_id: conversation_id.clone(),
+        user_id: participant_id,
+        removed_by: user.user_id,
+        participant_count: updated.participant_count(),
+    }).await;
+    Ok(Json(updated.into()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DMUnreadQuery {
+    pub user_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConversationUnread {
+    pub conversation_id: String,
+    // Capped at MAX_UNREAD_COUNT, like room badges
+    pub unread: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DMUnreadResponse {
+    pub user_id: String,
+    pub conversations: Vec<ConversationUnread>,
+    pub total: u64,
+}
+
+/// Per-conversation badges, most unread first, and their total. Counts are
+/// capped before summing so the total matches the badges shown.
+pub fn summarize_unread(counts: Vec<(String, u64)>) -> (Vec<ConversationUnread>, u64) {
+    let mut conversations: Vec<ConversationUnread> = counts
+        .into_iter()
+        .filter(|(_, unread)| *unread > 0)
+        .map(|(conversation_id, unread)| ConversationUnread { conversation_id, unread: unread.min(MAX_UNREAD_COUNT) })
+        .collect();
+    conversations.sort_by(|a, b| b.unread.cmp(&a.unread).then_with(|| a.conversation_id.cmp(&b.conversation_id)));
+    let total = conversations.iter().map(|conversation| conversation.unread).sum();
+    (conversations, total)
+}
+
+// GET /api/dm/unread?user_id=... - unread DMs across the caller's conversations
+pub async fn dm_unread_handler(
+    Query(query): Query<DMUnreadQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<DMUnreadResponse>, AppError> {
+    if query.user_id != user.user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    let options = mongodb::options::FindOptions::builder().projection(doc! { "_id": 1 }).build();
+    let conversation_ids: Vec<String> = state
+        .database
+        .collection::<mongodb::bson::Document>("dm_conversations")
+        .find(doc! { "participants": &user.user_id }, options)
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .into_iter()
+        .filter_map(|conversation| conversation.get_str("_id").ok().map(str::to_string))
+        .collect();
+
+    // Own messages never count as unread
+    let pipeline = vec![
+        doc! { "$match": {
+            "conversation_id": { "$in": &conversation_ids },
+            "deleted": false,
+            "sender_id": { "$ne": &user.user_id },
+            "read_by": { "$ne": &user.user_id },
+            "shadow": { "$ne": true },
+        } },
+        doc! { "$group": { "_id": "$conversation_id", "unread": { "$sum": 1 } } },
+    ];
+    let mut cursor = state
+        .database
+        .collection::<DirectMessage>("direct_messages")
+        .aggregate(pipeline, None)
+        .await?;
+    let mut counts = Vec::new();
+    while let Some(document) = cursor.try_next().await? {
+        if let Ok(conversation_id) = document.get_str("_id") {
+            let unread = document.get_i32("unread").map(|n| n as u64).unwrap_or(0);
+            counts.push((conversation_id.to_string(), unread));
+        }
+    }
+
+    let (conversations, total) = summarize_unread(counts);
+    Ok(Json(DMUnreadResponse { user_id: user.user_id, conversations, total }))
+}
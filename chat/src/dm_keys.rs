@@ -0,0 +1,165 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{auth::AuthUser, dm::verify_conversation_access, AppError, AppState};
+
+// Keys and ciphertexts are opaque to the server; these only bound storage
+const MAX_KEY_MATERIAL_LENGTH: usize = 8 * 1024;
+const MAX_DISTRIBUTIONS: usize = 64;
+const CATCH_UP_PAGE_SIZE: i64 = 200;
+
+/// Why an E2EE conversation's keys changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyEventKind {
+    // A participant's new device announces its public key
+    DeviceAdded,
+    // Participants were added or removed; a rotation should follow
+    MembersChanged,
+    // A new conversation key, sealed to each recipient device
+    Rotation,
+}
+
+/// The conversation key sealed to one recipient device by the sender.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyDistribution {
+    pub recipient_id: String,
+    pub recipient_device_id: String,
+    pub ciphertext: String,
+}
+
+/// What a client sends; the server adds the sender and id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewKeyEvent {
+    pub kind: KeyEventKind,
+    pub device_id: String,
+    // Identifies the conversation key generation this event refers to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub members: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub distributions: Vec<KeyDistribution>,
+}
+
+/// A persisted key event. The server only ever sees public keys and sealed
+/// ciphertexts, so it can relay these without being able to read messages.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub conversation_id: String,
+    pub sender_id: String,
+    #[serde(flatten)]
+    pub event: NewKeyEvent,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn key_events(state: &AppState) -> Collection<KeyEvent> {
+    state.database.collection("dm_key_events")
+}
+
+pub fn validate(event: &NewKeyEvent) -> Result<(), String> {
+    if event.device_id.trim().is_empty() {
+        return Err("device_id is required".to_string());
+    }
+    let oversized = event
+        .public_key
+        .iter()
+        .chain(event.distributions.iter().map(|d| &d.ciphertext))
+        .any(|material| material.len() > MAX_KEY_MATERIAL_LENGTH);
+    if oversized {
+        return Err(format!("Key material must be at most {} bytes", MAX_KEY_MATERIAL_LENGTH));
+    }
+    if event.distributions.len() > MAX_DISTRIBUTIONS {
+        return Err(format!("At most {} distributions per event", MAX_DISTRIBUTIONS));
+    }
+
+    match event.kind {
+        KeyEventKind::DeviceAdded if event.public_key.as_deref().is_none_or(str::is_empty) => {
+            Err("device_added needs the device's public_key".to_string())
+        }
+        KeyEventKind::MembersChanged if event.members.is_empty() => Err("members_changed needs the new member list".to_string()),
+        KeyEventKind::Rotation if event.key_id.is_none() || event.distributions.is_empty() => {
+            Err("rotation needs a key_id and at least one distribution".to_string())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Persists a key event so offline participants can catch up later.
+pub async fn record_key_event(
+    state: &AppState,
+    conversation_id: &str,
+    sender_id: &str,
+    event: NewKeyEvent,
+) -> Result<KeyEvent, AppError> {
+    validate(&event).map_err(AppError::BadRequest)?;
+
+    let mut key_event = KeyEvent {
+        id: None,
+        conversation_id: conversation_id.to_string(),
+        sender_id: sender_id.to_string(),
+        event,
+        created_at: Utc::now(),
+    };
+    let result = key_events(state).insert_one(&key_event, None).await?;
+    key_event.id = result.inserted_id.as_object_id();
+    info!("User {} recorded {:?} key event in conversation {}", sender_id, key_event.event.kind, conversation_id);
+    Ok(key_event)
+}
+
+#[derive(Deserialize)]
+pub struct KeyEventsQuery {
+    // Id of the last event the device has processed
+    after: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct KeyEventsResponse {
+    events: Vec<KeyEvent>,
+    has_more: bool,
+}
+
+// GET /api/dm/:conversation_id/key-events?after=<event_id>
+// Oldest first; rotations only carry the caller's own distributions
+pub async fn key_events_handler(
+    Path(conversation_id): Path<String>,
+    Query(query): Query<KeyEventsQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<KeyEventsResponse>, AppError> {
+    if !verify_conversation_access(&state, &conversation_id, &user.user_id).await {
+        return Err(AppError::Forbidden);
+    }
+
+    let mut filter = doc! { "conversation_id": &conversation_id };
+    if let Some(after) = query.after {
+        let after = ObjectId::parse_str(&after).map_err(|_| AppError::BadRequest("Invalid event id".to_string()))?;
+        filter.insert("_id", doc! { "$gt": after });
+    }
+    let options = FindOptions::builder().sort(doc! { "_id": 1 }).limit(CATCH_UP_PAGE_SIZE).build();
+    let mut events: Vec<KeyEvent> = key_events(&state).find(filter, options).await?.try_collect().await?;
+
+    for event in &mut events {
+        event.event.distributions.retain(|d| d.recipient_id == user.user_id);
+    }
+    Ok(Json(KeyEventsResponse {
+        has_more: events.len() as i64 == CATCH_UP_PAGE_SIZE,
+        events,
+    }))
+}
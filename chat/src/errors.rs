@@ -12,8 +12,26 @@ pub enum AppError {
     #[error("Not found")]
     NotFound,
     
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+    
+    #[error("Unauthorized")]
+    Unauthorized,
+    
+    #[error("Forbidden")]
+    Forbidden,
+    
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    
+    #[error("Too many requests")]
+    TooManyRequests { retry_after: u64 },
+    
     #[error("Internal server error")]
     InternalServerError,
+
+    #[error("Service unavailable")]
+    ServiceUnavailable { retry_after: u64 },
 }
 
 impl IntoResponse for AppError {
@@ -21,10 +39,28 @@ impl IntoResponse for AppError {
         let (status, error_message) = match &self {
             AppError::DatabaseError(e) => {
                 tracing::error!("Database error: {:?}", e);
-                (StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+                (StatusCode::INTERNAL_SERVER_ERROR, "Database error".to_string())
             },
-            AppError::NotFound => (StatusCode::NOT_FOUND, "Resource not found"),
-            AppError::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
+            AppError::NotFound => (StatusCode::NOT_FOUND, "Resource not found".to_string()),
+            AppError::BadRequest(reason) => (StatusCode::BAD_REQUEST, reason.clone()),
+            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+            AppError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden".to_string()),
+            AppError::Conflict(reason) => (StatusCode::CONFLICT, reason.clone()),
+            AppError::TooManyRequests { retry_after } => {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+                    "Too many requests",
+                ).into_response();
+            }
+            AppError::InternalServerError => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error".to_string()),
+            AppError::ServiceUnavailable { retry_after } => {
+                return (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+                    "Service unavailable",
+                ).into_response();
+            }
         };
         
         (status, error_message).into_response()
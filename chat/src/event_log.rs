@@ -0,0 +1,227 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use redis::streams::StreamRangeReply;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    auth::InternalCaller,
+    models::{DirectMessage, Message},
+    user_history::HistorySource,
+    AppError, AppState,
+};
+
+/// The stream downstream services replay from.
+pub const EVENT_STREAM: &str = "event_log";
+const DEFAULT_RETENTION_HOURS: u64 = 72;
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// How long events are kept. Consumers that fall further behind have to
+/// rebuild from MongoDB. A zero retention turns the log off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventLogConfig {
+    pub retention: Duration,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        EventLogConfig { retention: Duration::from_secs(DEFAULT_RETENTION_HOURS * 3600) }
+    }
+}
+
+impl EventLogConfig {
+    pub fn from_env() -> Self {
+        let hours = std::env::var("EVENT_LOG_RETENTION_HOURS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_RETENTION_HOURS);
+        EventLogConfig { retention: Duration::from_secs(hours * 3600) }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.retention.is_zero()
+    }
+
+    // Entries older than this are trimmed
+    fn oldest_kept(&self, now: DateTime<Utc>) -> i64 {
+        now.timestamp_millis() - self.retention.as_millis() as i64
+    }
+}
+
+/// A change downstream services mirror. Messages carry their content so an
+/// indexer needn't read it back.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum LogEvent {
+    MessageCreated {
+        source: HistorySource,
+        // Room id, or conversation id for DMs
+        room_id: String,
+        message_id: String,
+        user_id: String,
+        content: String,
+        timestamp: DateTime<Utc>,
+    },
+    MessageEdited {
+        source: HistorySource,
+        room_id: String,
+        message_id: String,
+        content: String,
+        edited_at: DateTime<Utc>,
+    },
+    MessageDeleted {
+        source: HistorySource,
+        room_id: String,
+        message_ids: Vec<String>,
+    },
+    MemberJoined {
+        conversation_id: String,
+        user_id: String,
+    },
+    MemberLeft {
+        conversation_id: String,
+        user_id: String,
+    },
+    // Mirrors should drop everything they hold about the user
+    UserErased {
+        user_id: String,
+    },
+}
+
+impl LogEvent {
+    pub fn room_message(message: &Message) -> Self {
+        LogEvent::MessageCreated {
+            source: HistorySource::Room,
+            room_id: message.room_id.clone(),
+            message_id: message.id.map(|id| id.to_hex()).unwrap_or_default(),
+            user_id: message.user_id.clone(),
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+        }
+    }
+
+    pub fn dm_message(message: &DirectMessage) -> Self {
+        LogEvent::MessageCreated {
+            source: HistorySource::Dm,
+            room_id: message.conversation_id.clone(),
+            message_id: message.id.map(|id| id.to_hex()).unwrap_or_default(),
+            user_id: message.sender_id.clone(),
+            content: message.content.clone(),
+            timestamp: message.timestamp,
+        }
+    }
+}
+
+/// Appends an event, trimming what has passed the retention. Failures are
+/// logged; the change itself has already been made.
+pub async fn append(state: &AppState, event: LogEvent) {
+    if !state.event_log.enabled() {
+        return;
+    }
+    let payload = match serde_json::to_string(&event) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("Failed to encode log event: {}", e);
+            return;
+        }
+    };
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        error!("Redis unavailable; dropped log event {}", payload);
+        return;
+    };
+    let result: redis::RedisResult<String> = redis::cmd("XADD")
+        .arg(EVENT_STREAM)
+        .arg("MINID")
+        .arg("~")
+        .arg(state.event_log.oldest_kept(Utc::now()))
+        .arg("*")
+        .arg("event")
+        .arg(&payload)
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to append log event {}: {}", payload, e);
+    }
+}
+
+/// The Unix millis of a stream entry id like "1700000000000-3".
+pub fn cursor_millis(cursor: &str) -> Option<i64> {
+    let (millis, sequence) = cursor.split_once('-').unwrap_or((cursor, "0"));
+    sequence.parse::<u64>().ok()?;
+    millis.parse().ok()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventLogQuery {
+    // Id of the last event the consumer processed; from the start without it
+    pub after: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventLogResponse {
+    // Each event with its stream `id` added
+    pub events: Vec<serde_json::Value>,
+    // Pass as `after` for the next page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
+    // Events after the cursor may already have been trimmed; rebuild from
+    // MongoDB instead of resuming
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cursor_expired: bool,
+}
+
+// GET /internal/events?after=&limit= - the event log, oldest first
+pub async fn event_log_handler(
+    Query(query): Query<EventLogQuery>,
+    State(state): State<AppState>,
+    _caller: InternalCaller,
+) -> Result<Json<EventLogResponse>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+    let (start, cursor_expired) = match &query.after {
+        Some(after) => {
+            let millis = cursor_millis(after).ok_or_else(|| AppError::BadRequest("Invalid cursor".to_string()))?;
+            (format!("({}", after), millis < state.event_log.oldest_kept(Utc::now()))
+        }
+        None => ("-".to_string(), false),
+    };
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        error!("Redis unavailable for the event log: {}", e);
+        AppError::ServiceUnavailable { retry_after: 5 }
+    })?;
+    let reply: StreamRangeReply = redis::cmd("XRANGE")
+        .arg(EVENT_STREAM)
+        .arg(&start)
+        .arg("+")
+        .arg("COUNT")
+        .arg(limit + 1)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to read the event log: {}", e);
+            AppError::ServiceUnavailable { retry_after: 5 }
+        })?;
+
+    let has_more = reply.ids.len() > limit;
+    let mut events = Vec::with_capacity(limit);
+    let mut next_cursor = query.after.clone();
+    for entry in reply.ids.into_iter().take(limit) {
+        let payload: Option<String> = entry.get("event");
+        let mut event = payload
+            .and_then(|payload| serde_json::from_str::<serde_json::Value>(&payload).ok())
+            .unwrap_or_else(|| serde_json::json!({ "type": "unreadable" }));
+        if let Some(fields) = event.as_object_mut() {
+            fields.insert("id".to_string(), serde_json::Value::String(entry.id.clone()));
+        }
+        next_cursor = Some(entry.id);
+        events.push(event);
+    }
+    Ok(Json(EventLogResponse { events, next_cursor, has_more, cursor_expired }))
+}
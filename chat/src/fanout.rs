@@ -0,0 +1,242 @@
+use crate::models::WsMessage;
+use crate::send_buffer::SocketSender;
+use crate::streams::BroadcastBackend;
+use crate::subscription_filter::SubscriptionFilter;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+// Publishes held for replay while Redis is down; the oldest are dropped first
+const MAX_REPLAY_QUEUE: usize = 10_000;
+const REPLAY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What happened to a message handed to one socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    // The socket's subscription filter doesn't want it
+    Filtered,
+    Closed,
+}
+
+/// The user a room-wide control message bans from the room or removes from
+/// it as a private room's member; their sockets stop listening to the room.
+pub fn removed_user(message: &WsMessage) -> Option<&str> {
+    match message {
+        WsMessage::RoomBanned { user_id, .. } | WsMessage::RoomAccessRevoked { user_id, .. } => Some(user_id),
+        _ => None,
+    }
+}
+
+/// A socket's sending half, with the filter its room subscription applies.
+/// Per-user channels are never filtered.
+#[derive(Clone)]
+pub struct Subscriber {
+    tx: SocketSender,
+    filter: Option<Arc<RwLock<SubscriptionFilter>>>,
+    // Set when subscribed to a neighbouring hex: only its new top-level
+    // messages are delivered, wrapped in NeighborMessage; replies stay in
+    // their own hex
+    neighbor_hex: Option<String>,
+}
+
+impl Subscriber {
+    pub fn unfiltered(tx: SocketSender) -> Self {
+        Subscriber { tx, filter: None, neighbor_hex: None }
+    }
+
+    pub fn filtered(tx: SocketSender, filter: Arc<RwLock<SubscriptionFilter>>) -> Self {
+        Subscriber { tx, filter: Some(filter), neighbor_hex: None }
+    }
+
+    pub fn neighbor(tx: SocketSender, filter: Arc<RwLock<SubscriptionFilter>>, h3_index: String) -> Self {
+        Subscriber { tx, filter: Some(filter), neighbor_hex: Some(h3_index) }
+    }
+
+    pub fn deliver(&self, mut message: WsMessage) -> Delivery {
+        if let Some(filter) = &self.filter {
+            let filter = filter.read().unwrap();
+            if !filter.allows(&message) {
+                return Delivery::Filtered;
+            }
+            // Mutes are the socket's own room's, not its neighbours'
+            if self.neighbor_hex.is_none() {
+                message = filter.mark(message);
+            }
+        }
+        if let Some(h3_index) = &self.neighbor_hex {
+            message = match message {
+                WsMessage::NewMessage(message) if message.parent_id.is_none() => {
+                    WsMessage::NeighborMessage { h3_index: h3_index.clone(), message }
+                }
+                _ => return Delivery::Filtered,
+            };
+        }
+        match self.tx.send(message) {
+            Ok(()) => Delivery::Sent,
+            Err(_) => Delivery::Closed,
+        }
+    }
+
+    /// Whether the message bans or removes this socket's user from the room
+    /// it is subscribed to. Neighbouring hexes don't count.
+    pub fn is_removed_by(&self, message: &WsMessage) -> bool {
+        self.neighbor_hex.is_none() && removed_user(message).is_some_and(|user_id| self.tx.user_id() == Some(user_id))
+    }
+
+    /// Filtered-out messages are dropped and count as sent; false only
+    /// once the socket is gone.
+    pub fn send(&self, message: WsMessage) -> bool {
+        self.deliver(message) != Delivery::Closed
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+}
+
+/// Sockets on this instance by channel, so messages still reach local users
+/// when Redis is unavailable. Publishes that could not reach Redis are queued
+/// and replayed for other instances once it returns.
+#[derive(Default)]
+pub struct LocalFanout {
+    // channel -> socket_id -> sender
+    subscribers: RwLock<HashMap<String, HashMap<String, Subscriber>>>,
+    replay_queue: Mutex<VecDeque<(String, String)>>,
+    redis_down: AtomicBool,
+    local_deliveries: AtomicU64,
+    queued: AtomicU64,
+    replayed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl LocalFanout {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, channel: &str, socket_id: &str, tx: Subscriber) {
+        self.subscribers
+            .write()
+            .unwrap()
+            .entry(channel.to_string())
+            .or_default()
+            .insert(socket_id.to_string(), tx);
+    }
+
+    pub fn unsubscribe(&self, channel: &str, socket_id: &str) {
+        let mut subscribers = self.subscribers.write().unwrap();
+        if let Some(sockets) = subscribers.get_mut(channel) {
+            sockets.remove(socket_id);
+            if sockets.is_empty() {
+                subscribers.remove(channel);
+            }
+        }
+    }
+
+    pub fn local_subscribers(&self, channel: &str) -> Vec<(String, Subscriber)> {
+        self.subscribers
+            .read()
+            .unwrap()
+            .get(channel)
+            .map(|sockets| sockets.iter().map(|(id, tx)| (id.clone(), tx.clone())).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn is_fallback_active(&self) -> bool {
+        self.redis_down.load(Ordering::Relaxed)
+    }
+
+    pub fn record_local_deliveries(&self, count: usize) {
+        self.local_deliveries.fetch_add(count as u64, Ordering::Relaxed);
+    }
+
+    pub fn mark_redis_up(&self) {
+        if self.redis_down.swap(false, Ordering::Relaxed) {
+            info!("Redis is reachable again, leaving local fan-out mode");
+        }
+    }
+
+    pub fn mark_redis_down(&self) {
+        if !self.redis_down.swap(true, Ordering::Relaxed) {
+            warn!("Redis unavailable, falling back to local fan-out");
+        }
+    }
+
+    pub fn queue_for_replay(&self, channel: &str, payload: String) {
+        let mut queue = self.replay_queue.lock().unwrap();
+        if queue.len() >= MAX_REPLAY_QUEUE {
+            queue.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back((channel.to_string(), payload));
+        self.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn replay_queue_len(&self) -> usize {
+        self.replay_queue.lock().unwrap().len()
+    }
+
+    /// Publishes queued messages in order, stopping at the first failure.
+    pub async fn replay(&self, redis: &redis::Client, backend: BroadcastBackend) {
+        if self.replay_queue_len() == 0 {
+            return;
+        }
+        let mut conn = match redis.get_async_connection().await {
+            Ok(conn) => conn,
+            Err(_) => return,
+        };
+
+        let mut replayed = 0;
+        loop {
+            let Some((channel, payload)) = self.replay_queue.lock().unwrap().front().cloned() else {
+                break;
+            };
+            if let Err(e) = backend.publish(&mut conn, &channel, &payload).await {
+                error!("Failed to replay publish to {}: {}", channel, e);
+                return;
+            }
+            self.replay_queue.lock().unwrap().pop_front();
+            replayed += 1;
+        }
+        self.replayed.fetch_add(replayed, Ordering::Relaxed);
+        info!("Replayed {} queued publishes to Redis", replayed);
+        self.mark_redis_up();
+    }
+
+    pub fn spawn_replay(self: std::sync::Arc<Self>, redis: std::sync::Arc<redis::Client>, backend: BroadcastBackend) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(REPLAY_INTERVAL);
+            loop {
+                tick.tick().await;
+                self.replay(&redis, backend).await;
+            }
+        })
+    }
+
+    /// Prometheus text exposition of the fallback state.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, u64); 6] = [
+            ("chat_fanout_fallback_active", "gauge", "1 while Redis is unavailable and fan-out is local only", self.is_fallback_active() as u64),
+            ("chat_fanout_local_deliveries_total", "counter", "Messages delivered to local sockets without Redis", self.local_deliveries.load(Ordering::Relaxed)),
+            ("chat_fanout_replay_queued_total", "counter", "Publishes queued for replay while Redis was down", self.queued.load(Ordering::Relaxed)),
+            ("chat_fanout_replayed_total", "counter", "Queued publishes replayed to Redis", self.replayed.load(Ordering::Relaxed)),
+            ("chat_fanout_replay_dropped_total", "counter", "Queued publishes dropped because the queue was full", self.dropped.load(Ordering::Relaxed)),
+            ("chat_fanout_replay_queue_depth", "gauge", "Publishes waiting for replay", self.replay_queue_len() as u64),
+        ];
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
@@ -0,0 +1,126 @@
+use axum::extract::ws::{close_code, CloseFrame, Message as WsMsg};
+use thiserror::Error;
+
+// Room messages are small; the default leaves room for DM key rotations,
+// which carry a sealed key per recipient device
+const DEFAULT_MAX_FRAME_BYTES: usize = 1024 * 1024;
+const DEFAULT_MAX_JSON_DEPTH: usize = 32;
+const DEFAULT_MAX_FIELD_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum FrameViolation {
+    #[error("Frame of {size} bytes is larger than {max} bytes")]
+    TooLarge { size: usize, max: usize },
+    #[error("JSON is nested deeper than {max} levels")]
+    TooDeep { max: usize },
+    #[error("JSON string is longer than {max} bytes")]
+    FieldTooLong { max: usize },
+}
+
+impl FrameViolation {
+    /// The close frame a socket is shut with after this violation.
+    pub fn close_frame(&self) -> CloseFrame<'static> {
+        CloseFrame {
+            code: close_code::PROTOCOL,
+            reason: self.to_string().into(),
+        }
+    }
+}
+
+/// Bounds on inbound client frames, checked before anything is
+/// deserialized so hostile clients can't make the server allocate for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameLimits {
+    pub max_frame_bytes: usize,
+    pub max_depth: usize,
+    pub max_field_bytes: usize,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        FrameLimits {
+            max_frame_bytes: DEFAULT_MAX_FRAME_BYTES,
+            max_depth: DEFAULT_MAX_JSON_DEPTH,
+            max_field_bytes: DEFAULT_MAX_FIELD_BYTES,
+        }
+    }
+}
+
+impl FrameLimits {
+    pub fn from_env() -> Self {
+        let limit = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .filter(|limit| *limit > 0)
+                .unwrap_or(default)
+        };
+        FrameLimits {
+            max_frame_bytes: limit("WS_MAX_FRAME_BYTES", DEFAULT_MAX_FRAME_BYTES),
+            max_depth: limit("WS_MAX_JSON_DEPTH", DEFAULT_MAX_JSON_DEPTH),
+            max_field_bytes: limit("WS_MAX_FIELD_BYTES", DEFAULT_MAX_FIELD_BYTES),
+        }
+    }
+
+    /// Cap for the WebSocket layer itself. Frames up to twice the limit are
+    /// still read so the client gets a close frame saying why; anything
+    /// bigger is cut off unread.
+    pub fn transport_limit(&self) -> usize {
+        self.max_frame_bytes.saturating_mul(2)
+    }
+
+    pub fn check(&self, frame: &WsMsg) -> Result<(), FrameViolation> {
+        match frame {
+            WsMsg::Text(text) => self.check_json(text),
+            WsMsg::Binary(bytes) => self.check_size(bytes.len()),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_size(&self, size: usize) -> Result<(), FrameViolation> {
+        if size > self.max_frame_bytes {
+            return Err(FrameViolation::TooLarge { size, max: self.max_frame_bytes });
+        }
+        Ok(())
+    }
+
+    /// Scans JSON text for nesting depth and string length without parsing
+    /// it. Malformed JSON is left for the deserializer to reject.
+    pub fn check_json(&self, text: &str) -> Result<(), FrameViolation> {
+        self.check_size(text.len())?;
+
+        let mut depth = 0usize;
+        // Bytes in the current string, when inside one
+        let mut string_len: Option<usize> = None;
+        let mut escaped = false;
+        for byte in text.bytes() {
+            if let Some(len) = string_len.as_mut() {
+                if escaped {
+                    escaped = false;
+                } else if byte == b'\\' {
+                    escaped = true;
+                } else if byte == b'"' {
+                    string_len = None;
+                    continue;
+                }
+                *len += 1;
+                if *len > self.max_field_bytes {
+                    return Err(FrameViolation::FieldTooLong { max: self.max_field_bytes });
+                }
+                continue;
+            }
+            match byte {
+                b'"' => string_len = Some(0),
+                b'{' | b'[' => {
+                    depth += 1;
+                    if depth > self.max_depth {
+                        return Err(FrameViolation::TooDeep { max: self.max_depth });
+                    }
+                }
+                b'}' | b']' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,11 +1,13 @@
-use crate::{models::*, websocket::*, AppState, AppError};
+use crate::{abuse::{check_rate_limit, record_signal, AbuseSignal}, auth::AuthUser, content_filter::*, identity::{is_impersonation, remember}, idempotency, language::*, models::*, presence::RoomCounts, rate_limit::check_room_rate_limit, room_bridge::*, room_ranking::{rank_rooms, RoomInfo}, room_mentions::*, subscription_filter::FanoutFilter, websocket::*, wire_format::{WireFormat, MSGPACK_PROTOCOL}, AppState, AppError};
 use axum::{
-    extract::{Path, Query, State, WebSocketUpgrade},
-    http::StatusCode,
+    extract::{ConnectInfo, Path, Query, State, WebSocketUpgrade},
+    http::HeaderMap,
     response::IntoResponse,
     Json,
 };
 use chrono::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize)]
@@ -19,6 +21,21 @@ pub struct MessageResponse {
     pub edited_at: Option<String>,
     pub deleted: bool,
     pub reactions: Vec<Reaction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub off_language: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    pub reply_count: i32,
+    #[serde(skip_serializing_if = "MessageKind::is_text")]
+    pub kind: MessageKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub event: Option<EventDetails>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub mentions_everyone: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<crate::uploads::Attachment>,
 }
 
 impl From<Message> for MessageResponse {
@@ -33,24 +50,106 @@ impl From<Message> for MessageResponse {
             edited_at: msg.edited_at.map(|dt| dt.to_rfc3339()),
             deleted: msg.deleted,
             reactions: msg.reactions,
+            language: msg.language,
+            off_language: msg.off_language,
+            parent_id: msg.parent_id,
+            reply_count: msg.reply_count,
+            kind: msg.kind,
+            event: msg.event,
+            mentions_everyone: msg.mentions_everyone,
+            attachments: msg.attachments,
         }
     }
 }
 
+#[derive(Deserialize)]
+pub struct SocketQuery {
+    filter: Option<FanoutFilter>,
+    // `msgpack` for binary MessagePack frames
+    encoding: Option<String>,
+    // The user the socket will join as, to place it in its rollout cohort
+    user_id: Option<String>,
+    // Join ticket from POST /api/rooms/:location_id/ws-ticket, in place of a JWT
+    ticket: Option<String>,
+}
+
+// What a socket handler needs to know from the upgrade request. `room_id` is
+// the room in the URL, which the join ticket must be for.
+async fn connection_info(state: &AppState, params: SocketQuery, headers: &HeaderMap, peer: Option<ConnectInfo<SocketAddr>>, room_id: Option<&str>) -> Result<ConnectionInfo, AppError> {
+    state.shutdown.admit()?;
+    let mut info = ConnectionInfo::from_headers(state, headers, peer.map(|ConnectInfo(peer)| peer));
+    crate::socket_abuse::admit(state, info.ip).await?;
+    info.ticket = crate::ws_ticket::admit(state, params.ticket.as_deref(), room_id).await?;
+    info.filter = params.filter.unwrap_or_default();
+    let user_id = params.user_id.as_deref().or(info.ticket.as_ref().map(|ticket| ticket.user_id.as_str()));
+    info.cohort = state.canary.config.cohort(user_id);
+    info.format = state.canary.config.wire_format(info.cohort, WireFormat::negotiate(params.encoding.as_deref(), headers));
+    Ok(info)
+}
+
+// Only sockets that will get MessagePack frames accept its subprotocol
+fn upgrade(ws: WebSocketUpgrade, state: &AppState, info: &ConnectionInfo) -> WebSocketUpgrade {
+    let limit = state.frame_limits.transport_limit();
+    let ws = if info.format == WireFormat::MessagePack { ws.protocols([MSGPACK_PROTOCOL]) } else { ws };
+    ws.max_frame_size(limit).max_message_size(limit)
+}
+
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     Path(location_id): Path<String>,
+    Query(params): Query<SocketQuery>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_socket(socket, location_id, state))
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+) -> Result<impl IntoResponse, AppError> {
+    let info = connection_info(&state, params, &headers, peer, Some(&location_id)).await?;
+    Ok(upgrade(ws, &state, &info).on_upgrade(move |socket| handle_socket(socket, location_id, state, info)))
 }
 
 pub async fn hex_websocket_handler(
     ws: WebSocketUpgrade,
     Path(h3_index): Path<String>,
+    Query(params): Query<SocketQuery>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle_hex_socket(socket, h3_index, state))
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+) -> Result<impl IntoResponse, AppError> {
+    crate::hex::parse_cell(&h3_index).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let info = connection_info(&state, params, &headers, peer, Some(&h3_index)).await?;
+    Ok(upgrade(ws, &state, &info).on_upgrade(move |socket| handle_hex_socket(socket, Some(h3_index), state, info)))
+}
+
+// Hex socket without a precomputed index; the hex is resolved from the GPS fix in JoinHex
+pub async fn hex_auto_websocket_handler(
+    ws: WebSocketUpgrade,
+    Query(params): Query<SocketQuery>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    peer: Option<ConnectInfo<SocketAddr>>,
+) -> Result<impl IntoResponse, AppError> {
+    let info = connection_info(&state, params, &headers, peer, None).await?;
+    Ok(upgrade(ws, &state, &info).on_upgrade(move |socket| handle_hex_socket(socket, None, state, info)))
+}
+
+#[derive(Deserialize)]
+pub struct TimeQuery {
+    // The client's clock when it sent the request, echoed back
+    client_ts: Option<i64>,
+}
+
+/// The server's clock, in epoch milliseconds. With the echoed `client_ts`
+/// and the time the response arrives, a client can estimate its clock skew
+/// and round trip the way NTP does.
+#[derive(Debug, Serialize)]
+pub struct TimeResponse {
+    pub server_ts: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ts: Option<i64>,
+}
+
+// GET /api/time - Server clock for correcting client clock skew
+pub async fn get_time(Query(params): Query<TimeQuery>) -> Json<TimeResponse> {
+    Json(TimeResponse { server_ts: Utc::now().timestamp_millis(), client_ts: params.client_ts })
 }
 
 #[derive(Deserialize)]
@@ -63,11 +162,14 @@ pub async fn get_messages(
     Path(location_id): Path<String>,
     Query(params): Query<GetMessagesQuery>,
     State(state): State<AppState>,
+    user: Option<AuthUser>,
 ) -> Result<Json<Vec<MessageResponse>>, AppError> {
     tracing::info!("GET /api/messages/{} - limit: {:?}, before: {:?}", location_id, params.limit, params.before);
+    crate::room_invites::require_read_access(&state, &location_id, user.as_ref()).await?;
     let limit = params.limit.unwrap_or(50).min(100);
     
-    match state.db.get_messages(&location_id, limit, params.before).await {
+    let viewer = user.as_ref().map(|user| user.user_id.as_str());
+    match state.db.get_messages(&location_id, limit, params.before, viewer).await {
         Ok(messages) => {
             tracing::info!("Successfully retrieved {} messages for location {}", messages.len(), location_id);
             let responses: Vec<MessageResponse> = messages.into_iter().map(MessageResponse::from).collect();
@@ -80,41 +182,473 @@ pub async fn get_messages(
     }
 }
 
+// Most messages one page of hex history returns
+pub const MAX_HEX_PAGE: i64 = 100;
+
+/// How many messages a page of hex history holds, 50 unless asked otherwise.
+pub fn hex_page_limit(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(50).clamp(1, MAX_HEX_PAGE)
+}
+
+/// The `before` for the next older page: the oldest message's time, when
+/// the page came back full.
+pub fn next_before(messages: &[Message], limit: i64) -> Option<DateTime<Utc>> {
+    match messages.first() {
+        Some(oldest) if messages.len() as i64 == limit => Some(oldest.timestamp),
+        _ => None,
+    }
+}
+
+#[derive(Serialize)]
+pub struct HexMessagesResponse {
+    h3_index: String,
+    messages: Vec<MessageResponse>,
+    // Pass as `before` for the next older page; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_before: Option<DateTime<Utc>>,
+}
+
+// GET /api/hex/:h3_index/messages?limit=&before= - a hex's history, oldest
+// first, paged backwards. Hex rooms are keyed by their index, so this reads
+// the (room_id, timestamp) index like any room.
+pub async fn get_hex_messages(
+    Path(h3_index): Path<String>,
+    Query(params): Query<GetMessagesQuery>,
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<HexMessagesResponse>, AppError> {
+    crate::hex::parse_cell(&h3_index).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    crate::room_invites::require_read_access(&state, &h3_index, user.as_ref()).await?;
+    let limit = hex_page_limit(params.limit);
+    let viewer = user.as_ref().map(|user| user.user_id.as_str());
+    let messages = state.db.get_messages(&h3_index, limit, params.before, viewer).await?;
+    let next_before = next_before(&messages, limit);
+    Ok(Json(HexMessagesResponse {
+        h3_index,
+        messages: messages.into_iter().map(MessageResponse::from).collect(),
+        next_before,
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ThreadQuery {
+    limit: Option<i64>,
+    // Id of the last reply the client has
+    after: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ThreadResponse {
+    parent: MessageResponse,
+    replies: Vec<MessageResponse>,
+    // Pass as `after` for the next page of replies; absent on the last page
+    #[serde(skip_serializing_if = "Option::is_none")]
+    next_after: Option<String>,
+}
+
+// GET /api/hex/:h3_index/threads/:message_id?limit=&after= - a message in
+// the hex and its replies, oldest first, paged forwards
+pub async fn get_hex_thread(
+    Path((h3_index, message_id)): Path<(String, String)>,
+    Query(params): Query<ThreadQuery>,
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<ThreadResponse>, AppError> {
+    crate::hex::parse_cell(&h3_index).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    crate::room_invites::require_read_access(&state, &h3_index, user.as_ref()).await?;
+    let parent_id = mongodb::bson::oid::ObjectId::parse_str(&message_id).map_err(|_| AppError::NotFound)?;
+    let viewer = user.as_ref().map(|user| user.user_id.as_str());
+    let parent = match state.db.get_message(&parent_id).await? {
+        Some(parent) if parent.room_id == h3_index && parent.parent_id.is_none() => parent,
+        _ => return Err(AppError::NotFound),
+    };
+    if parent.shadow && viewer != Some(parent.user_id.as_str()) {
+        return Err(AppError::NotFound);
+    }
+    let after = match params.after.as_deref() {
+        Some(after) => Some(mongodb::bson::oid::ObjectId::parse_str(after).map_err(|_| AppError::BadRequest("Invalid after".to_string()))?),
+        None => None,
+    };
+    let limit = params.limit.unwrap_or(50).clamp(1, MAX_HEX_PAGE);
+    let replies = state.db.get_replies(&parent_id, after.as_ref(), limit, viewer).await?;
+    let next_after = match replies.last() {
+        Some(newest) if replies.len() as i64 == limit => newest.id.map(|id| id.to_hex()),
+        _ => None,
+    };
+    Ok(Json(ThreadResponse {
+        parent: parent.into(),
+        replies: replies.into_iter().map(MessageResponse::from).collect(),
+        next_after,
+    }))
+}
+
 #[derive(Deserialize)]
 pub struct SendMessageRequest {
     location_id: String,
-    user_id: String,
-    username: String,
+    // Optional; the author always comes from the token and a different
+    // user_id or username is refused
+    user_id: Option<String>,
+    username: Option<String>,
     content: String,
+    parent_id: Option<String>,
+    event: Option<EventDetails>,
+    #[serde(default)]
+    attachments: Vec<crate::uploads::Attachment>,
+    // Alternative to the Idempotency-Key header
+    idempotency_key: Option<String>,
 }
 
+// POST /api/messages - retries with the same Idempotency-Key within a day
+// return the original message instead of posting it again
 pub async fn send_message(
     State(state): State<AppState>,
+    user: AuthUser,
+    headers: HeaderMap,
     Json(req): Json<SendMessageRequest>,
 ) -> Result<Json<MessageResponse>, AppError> {
-    let mut message = Message {
-        id: None,
-        room_id: req.location_id,
-        user_id: req.user_id,
-        username: req.username,
-        content: req.content,
-        timestamp: Utc::now(),
-        edited_at: None,
-        deleted: false,
-        reactions: vec![],
+    if is_impersonation(&user, req.user_id.as_deref(), req.username.as_deref()) {
+        tracing::warn!("Refusing message from {} sent as {:?}/{:?}", user.user_id, req.user_id, req.username);
+        return Err(AppError::Forbidden);
+    }
+    remember(&state, &user).await;
+
+    let Some(key) = idempotency::request_key(&headers, req.idempotency_key.as_deref()).map_err(AppError::BadRequest)? else {
+        return post_message(&state, &user, req).await.map(Json);
     };
+    match idempotency::claim(&state.redis_pool, &user.user_id, &key).await {
+        idempotency::Claim::New => {}
+        idempotency::Claim::InProgress => {
+            return Err(AppError::Conflict("A request with this Idempotency-Key is still in progress".to_string()));
+        }
+        idempotency::Claim::Completed(id) => {
+            tracing::info!("Replaying message {} for retried send by {}", id, user.user_id);
+            let message = state.db.get_message(&id).await?.ok_or(AppError::NotFound)?;
+            return Ok(Json(MessageResponse::from(message)));
+        }
+    }
+    match post_message(&state, &user, req).await {
+        Ok(response) => {
+            if let Ok(id) = mongodb::bson::oid::ObjectId::parse_str(&response.id) {
+                idempotency::complete(&state.redis_pool, &user.user_id, &key, &id).await;
+            }
+            Ok(Json(response))
+        }
+        Err(e) => {
+            idempotency::release(&state.redis_pool, &user.user_id, &key).await;
+            Err(e)
+        }
+    }
+}
+
+#[tracing::instrument(name = "message", skip_all, fields(room_id = %req.location_id))]
+async fn post_message(state: &AppState, user: &AuthUser, req: SendMessageRequest) -> Result<MessageResponse, AppError> {
+    check_rate_limit(&state.redis_pool, &user.user_id)
+        .await
+        .map_err(|retry_after| AppError::TooManyRequests { retry_after })?;
     
-    let id = state.db.create_message(&message).await?;
+    let room = crate::room_quota::room_for(state, &req.location_id, Some(user)).await?;
+    if crate::room_bans::find_ban(state, &req.location_id, &user.user_id).await.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    if crate::timeouts::muted_until(&state.redis_pool, &req.location_id, &user.user_id).await.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    crate::room_invites::require_access(state, &room, Some(user)).await?;
+    check_room_rate_limit(&state.redis_pool, &req.location_id, &user.user_id, room.settings.rate_limit)
+        .await
+        .map_err(|retry_after| AppError::TooManyRequests { retry_after })?;
+    let mut message = Message::new(req.location_id, user.user_id.clone(), user.username.clone(), req.content);
+    if let Some(parent_id) = &req.parent_id {
+        let thread_id = crate::threads::resolve_parent(state, &message.room_id, parent_id)
+            .await
+            .map_err(|e| AppError::BadRequest(e.to_string()))?;
+        message.parent_id = Some(thread_id);
+    }
+    crate::uploads::check_attachments(state.uploads.as_deref(), &req.attachments, &user.user_id).map_err(AppError::BadRequest)?;
+    message.attachments = req.attachments;
+    crate::rsvp::apply_event(&mut message, req.event)?;
+    let is_moderator = crate::room_roles::caller_is_moderator(Some(user), &user.user_id, crate::room_roles::role_in(&room, &user.user_id));
+    if let Err(action) = room.settings.permissions.check(&message, is_moderator) {
+        return Err(AppError::BadRequest(action.denied_reason().to_string()));
+    }
+    if mentions_everyone(&message.content) {
+        claim_room_mention(&state.redis_pool, &message.room_id, &message.user_id)
+            .await
+            .map_err(|retry_after| AppError::TooManyRequests { retry_after })?;
+        message.mentions_everyone = true;
+    }
+    let verdict = match crate::message_filters::screen(&mut message, &room.settings) {
+        Ok(verdict) => verdict,
+        Err(reason) => {
+            record_signal(&state.redis_pool, &message.user_id, AbuseSignal::FilterHit).await;
+            return Err(AppError::BadRequest(reason));
+        }
+    };
+    if verdict.hit() {
+        record_signal(&state.redis_pool, &message.user_id, AbuseSignal::FilterHit).await;
+    }
+    tag_message(&mut message, room.settings.language.as_deref());
+    let badge = crate::badges::badge_for(state, Some(user), &user.user_id).await;
+    crate::badges::apply(&mut message, badge, room.settings.official_messages);
+    crate::message_ttl::stamp(&mut message, room.settings.message_ttl_seconds);
+    message.shadow = crate::shadow_bans::is_shadow_banned(&state.redis_pool, &user.user_id).await;
+    
+    let id = state.db.create_message(&message).await?.id();
+    state.room_metrics.record_message(&message.room_id);
+    if let Some(creator) = crate::room_quota::pending_creator(&room) {
+        crate::room_quota::record_post(state, &message.room_id, &creator, &user.user_id).await;
+    }
     message.id = Some(id);
+    state.delivery_trace.persisted(&id);
+    crate::event_log::append(state, crate::event_log::LogEvent::room_message(&message)).await;
+    if !verdict.flags.is_empty() {
+        crate::message_filters::queue_flagged(state, &message, &verdict.flags).await;
+    }
+    spawn_room_mention_pushes(state, &message);
     
-    Ok(Json(MessageResponse::from(message)))
+    Ok(MessageResponse::from(message))
 }
 
 pub async fn get_room_info(
     Path(location_id): Path<String>,
     State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<RoomInfo>, AppError> {
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    crate::room_invites::require_access(&state, &room, user.as_ref()).await?;
+    let info = with_counts(&state, vec![room]).await.pop().ok_or(AppError::NotFound)?;
+    Ok(Json(info))
+}
+
+// Rooms with their participants and spectators. Without Redis everyone the
+// room last counted is taken to be watching.
+async fn with_counts(state: &AppState, rooms: Vec<ChatRoom>) -> Vec<RoomInfo> {
+    let room_ids: Vec<String> = rooms.iter().map(|room| room.id.clone()).collect();
+    let counts = match crate::presence::room_counts(state, &room_ids).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            tracing::error!("Failed to load participant counts: {}", e);
+            rooms.iter().map(|room| RoomCounts::new(room.active_users.max(0) as usize, 0)).collect()
+        }
+    };
+    rooms.into_iter().zip(counts).map(|(room, counts)| RoomInfo::new(room, counts)).collect()
+}
+
+// Rooms fetched per listed room before reranking
+const RANKING_CANDIDATES: i64 = 3;
+
+// Highlights never look further back than a week
+const MAX_HIGHLIGHT_WINDOW_HOURS: i64 = 24 * 7;
+
+/// Parses a window like "30m", "24h" or "7d".
+pub fn parse_window(window: &str) -> Option<chrono::Duration> {
+    let window = window.trim();
+    let unit = window.chars().last()?;
+    let amount: i64 = window[..window.len() - unit.len_utf8()].parse().ok()?;
+    if amount <= 0 {
+        return None;
+    }
+    
+    let duration = match unit {
+        'm' => chrono::Duration::minutes(amount),
+        'h' => chrono::Duration::hours(amount),
+        'd' => chrono::Duration::days(amount),
+        _ => return None,
+    };
+    Some(duration.min(chrono::Duration::hours(MAX_HIGHLIGHT_WINDOW_HOURS)))
+}
+
+#[derive(Deserialize)]
+pub struct TopMessagesQuery {
+    window: Option<String>,
+    limit: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ReactedMessage {
+    message: MessageResponse,
+    reaction_count: i32,
+}
+
+#[derive(Serialize)]
+pub struct RepliedMessage {
+    message: MessageResponse,
+    reply_count: i32,
+}
+
+#[derive(Serialize)]
+pub struct TopMessagesResponse {
+    since: String,
+    most_reacted: Vec<ReactedMessage>,
+    most_replied: Vec<RepliedMessage>,
+}
+
+pub async fn get_top_messages(
+    Path(location_id): Path<String>,
+    Query(params): Query<TopMessagesQuery>,
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<TopMessagesResponse>, AppError> {
+    crate::room_invites::require_read_access(&state, &location_id, user.as_ref()).await?;
+    let window = params.window.as_deref().unwrap_or("24h");
+    let window = parse_window(window).ok_or_else(|| AppError::BadRequest(format!("Invalid window: {}", window)))?;
+    let since = Utc::now() - window;
+    let limit = params.limit.unwrap_or(10).clamp(1, 50);
+    
+    let (most_reacted, most_replied) = futures::try_join!(
+        state.db.top_reacted_messages(&location_id, since, limit),
+        state.db.top_replied_messages(&location_id, since, limit),
+    )?;
+    
+    Ok(Json(TopMessagesResponse {
+        since: since.to_rfc3339(),
+        most_reacted: most_reacted
+            .into_iter()
+            .map(|(message, reaction_count)| ReactedMessage { message: message.into(), reaction_count })
+            .collect(),
+        most_replied: most_replied
+            .into_iter()
+            .map(|(message, reply_count)| RepliedMessage { message: message.into(), reply_count })
+            .collect(),
+    }))
+}
+
+#[derive(Deserialize)]
+pub struct ListRoomsQuery {
+    language: Option<String>,
+    limit: Option<i64>,
+}
+
+// GET /api/rooms?language=&limit= - busiest rooms, participants weighted
+// above spectators. Rooms in review are only listed for their creator.
+pub async fn list_rooms(
+    Query(params): Query<ListRoomsQuery>,
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<Vec<RoomInfo>>, AppError> {
+    let language = match params.language.as_deref() {
+        Some(code) => Some(normalize_language(code).ok_or_else(|| AppError::BadRequest(format!("Unknown language: {}", code)))?),
+        None => None,
+    };
+    let limit = params.limit.unwrap_or(50).clamp(1, 100);
+    
+    // Candidates by total occupancy, reranked once split into participants and spectators
+    let candidates = state.db.list_rooms(language.as_deref(), user.as_ref().map(|user| user.user_id.as_str()), limit * RANKING_CANDIDATES).await?;
+    let mut rooms = rank_rooms(with_counts(&state, candidates).await, state.room_ranking);
+    rooms.truncate(limit as usize);
+    Ok(Json(rooms))
+}
+
+#[derive(Deserialize)]
+pub struct SetRoomLanguageRequest {
+    // ISO 639-3 code, or null to clear
+    language: Option<String>,
+}
+
+// PUT /api/rooms/:location_id/language - the room's owner and moderators,
+// or service moderators
+pub async fn set_room_language(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<SetRoomLanguageRequest>,
 ) -> Result<Json<ChatRoom>, AppError> {
-    let room = state.db.get_or_create_room(&location_id).await?;
+    let language = match req.language.as_deref() {
+        Some(code) => Some(normalize_language(code).ok_or_else(|| AppError::BadRequest(format!("Unknown language: {}", code)))?),
+        None => None,
+    };
+    let room = crate::room_quota::room_for(&state, &location_id, Some(&user)).await?;
+    crate::room_roles::authorize(&room, &user, None)?;
+    tracing::info!("User {} set language of room {} to {:?}", user.username, location_id, language);
+    
+    state.db.set_room_language(&location_id, language.as_deref()).await?;
+    crate::room_cache::invalidate_room(&state, &location_id).await;
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(room))
+}
+
+#[derive(Deserialize)]
+pub struct UpdateRoomSettingsRequest {
+    exclude_location_mismatch: Option<bool>,
+    content_rating: Option<ContentRating>,
+    permissions: Option<RoomPermissionsUpdate>,
+    notifications: Option<crate::notifications::NotificationSettings>,
+    official_messages: Option<crate::badges::OfficialMessages>,
+    // 0 turns expiry off
+    message_ttl_seconds: Option<u32>,
+    // Days messages are kept; 0 keeps them forever
+    retention_days: Option<u32>,
+    // Replaces the room's filter rules as a whole
+    filters: Option<crate::message_filters::FilterRules>,
+}
+
+// Only the permissions present are changed
+#[derive(Deserialize)]
+pub struct RoomPermissionsUpdate {
+    post_links: Option<bool>,
+    post_media: Option<bool>,
+    create_polls: Option<bool>,
+    mention_everyone: Option<bool>,
+}
+
+// PATCH /api/rooms/:location_id/settings - room policy; the room's owner and
+// moderators, or service moderators
+pub async fn update_room_settings(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<UpdateRoomSettingsRequest>,
+) -> Result<Json<ChatRoom>, AppError> {
+    let room = crate::room_quota::room_for(&state, &location_id, Some(&user)).await?;
+    crate::room_roles::authorize(&room, &user, None)?;
+    
+    let mut update = mongodb::bson::Document::new();
+    if let Some(exclude) = req.exclude_location_mismatch {
+        update.insert("settings.exclude_location_mismatch", exclude);
+    }
+    if let Some(rating) = req.content_rating {
+        update.insert("settings.content_rating", mongodb::bson::to_bson(&rating).map_err(|_| AppError::InternalServerError)?);
+    }
+    if let Some(permissions) = req.permissions {
+        let changes = [
+            ("post_links", permissions.post_links),
+            ("post_media", permissions.post_media),
+            ("create_polls", permissions.create_polls),
+            ("mention_everyone", permissions.mention_everyone),
+        ];
+        for (name, allowed) in changes {
+            if let Some(allowed) = allowed {
+                update.insert(format!("settings.permissions.{}", name), allowed);
+            }
+        }
+    }
+    if let Some(notifications) = req.notifications {
+        update.insert("settings.notifications", mongodb::bson::to_bson(&notifications).map_err(|_| AppError::InternalServerError)?);
+    }
+    if let Some(official_messages) = req.official_messages {
+        update.insert("settings.official_messages", mongodb::bson::to_bson(&official_messages).map_err(|_| AppError::InternalServerError)?);
+    }
+    if let Some(ttl) = req.message_ttl_seconds {
+        match crate::message_ttl::validate_ttl(ttl).map_err(AppError::BadRequest)? {
+            Some(ttl) => update.insert("settings.message_ttl_seconds", ttl as i64),
+            None => update.insert("settings.message_ttl_seconds", mongodb::bson::Bson::Null),
+        };
+    }
+    if let Some(days) = req.retention_days {
+        let days = crate::message_retention::validate_retention(days).map_err(AppError::BadRequest)?;
+        update.insert("settings.retention_days", days as i64);
+    }
+    if let Some(filters) = req.filters {
+        filters.validate().map_err(AppError::BadRequest)?;
+        update.insert("settings.filters", mongodb::bson::to_bson(&filters).map_err(|_| AppError::InternalServerError)?);
+    }
+    tracing::info!("User {} updated settings of room {}: {:?}", user.username, location_id, update);
+    
+    if !update.is_empty() {
+        state.db.update_room_settings(&location_id, update).await?;
+        crate::room_cache::invalidate_room(&state, &location_id).await;
+    }
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
     Ok(Json(room))
 }
 
@@ -122,23 +656,205 @@ pub async fn get_room_info(
 pub struct JoinRoomRequest {
     user_id: String,
     username: String,
+    // Token of an invitation to a private room
+    #[serde(default)]
+    invite: Option<String>,
 }
 
 #[derive(Serialize)]
 pub struct JoinRoomResponse {
     success: bool,
     active_users: i32,
+    // Set when the legacy room has been redirected to its containing hex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect_h3_index: Option<String>,
 }
 
 pub async fn join_room(
     Path(location_id): Path<String>,
     State(state): State<AppState>,
-    Json(_req): Json<JoinRoomRequest>,
+    user: Option<AuthUser>,
+    Json(req): Json<JoinRoomRequest>,
 ) -> Result<Json<JoinRoomResponse>, AppError> {
-    let room = state.db.get_or_create_room(&location_id).await?;
+    tracing::info!("POST /api/rooms/{}/join - user: {} ({})", location_id, req.username, req.user_id);
+    let room = crate::room_quota::room_for(&state, &location_id, user.as_ref()).await?;
+    if crate::room_bans::find_ban(&state, &location_id, &req.user_id).await.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    let role = crate::room_roles::role_in(&room, &req.user_id);
+    if !crate::room_invites::may_join(&state, &location_id, room.visibility, role, user.as_ref(), &req.user_id, req.invite.as_deref()).await {
+        return Err(AppError::Forbidden);
+    }
+    
+    let redirect_h3_index = match state.room_bridge {
+        BridgeMode::Redirect => room.h3_index.clone(),
+        _ => None,
+    };
     
     Ok(Json(JoinRoomResponse {
-        success: true,
+        success: redirect_h3_index.is_none(),
         active_users: room.active_users,
+        redirect_h3_index,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct RoomHexMapping {
+    location_id: String,
+    #[serde(flatten)]
+    cell: crate::hex::HexCell,
+    mode: BridgeMode,
+    migrated_to_hex: bool,
+}
+
+#[derive(Deserialize)]
+pub struct ActiveHexQuery {
+    lat: f64,
+    lon: f64,
+    resolution: Option<u8>,
+    k: Option<u8>,
+}
+
+/// A hex around the requested point and how busy it is.
+#[derive(Debug, Serialize)]
+pub struct ActiveHex {
+    pub h3_index: String,
+    pub center: [f64; 2],
+    pub active_users: usize,
+    // The hex room's latest message, or its creation without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_activity_at: Option<DateTime<Utc>>,
+}
+
+// GET /api/hex/active?lat=&lon=&resolution=&k= - the hex at a point and the
+// rings around it, with who is in each, for highlighting busy areas on the map
+pub async fn active_hexes(
+    Query(params): Query<ActiveHexQuery>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ActiveHex>>, AppError> {
+    let bad_request = |e: crate::hex::HexError| AppError::BadRequest(e.to_string());
+    let resolution = match params.resolution {
+        Some(resolution) => crate::hex::chat_resolution(resolution).map_err(bad_request)?,
+        None => crate::hex::DEFAULT_RESOLUTION,
+    };
+    let cell = crate::hex::lat_lng(params.lat, params.lon).map_err(bad_request)?.to_cell(resolution);
+    let cells = crate::hex::disk(cell, params.k.unwrap_or(1));
+    let h3_indices: Vec<String> = cells.iter().map(ToString::to_string).collect();
+
+    let rooms: HashMap<String, ChatRoom> = state.db.get_rooms(&h3_indices).await?.into_iter().map(|room| (room.id.clone(), room)).collect();
+    let active_users: Vec<usize> = match crate::presence::room_counts(&state, &h3_indices).await {
+        Ok(counts) => counts.iter().map(|counts| counts.participants + counts.spectators).collect(),
+        Err(e) => {
+            tracing::error!("Failed to load hex presence: {}", e);
+            h3_indices.iter().map(|id| rooms.get(id).map_or(0, |room| room.active_users.max(0) as usize)).collect()
+        }
+    };
+
+    let hexes = cells
+        .into_iter()
+        .zip(h3_indices)
+        .zip(active_users)
+        .map(|((cell, h3_index), active_users)| {
+            let center = h3o::LatLng::from(cell);
+            ActiveHex {
+                center: [center.lng(), center.lat()],
+                active_users,
+                last_activity_at: rooms.get(&h3_index).map(|room| room.last_message_at),
+                h3_index,
+            }
+        })
+        .collect();
+    Ok(Json(hexes))
+}
+
+pub async fn get_room_hex_mapping(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<RoomHexMapping>, AppError> {
+    let cell = legacy_room_cell(&location_id).ok_or(AppError::NotFound)?;
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    
+    Ok(Json(RoomHexMapping {
+        location_id,
+        cell: cell.into(),
+        mode: state.room_bridge,
+        migrated_to_hex: room.migrated_to_hex,
+    }))
+}
+
+// POST /api/admin/rooms/:location_id/migrate-to-hex - moves a legacy room's
+// history into its hex while the bridge redirects; admins only
+pub async fn migrate_room_to_hex_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RoomHexMapping>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    if state.room_bridge != BridgeMode::Redirect {
+        return Err(AppError::BadRequest("Rooms are only migrated while LEGACY_ROOM_BRIDGE is redirect".to_string()));
+    }
+    let cell = legacy_room_cell(&location_id).ok_or(AppError::NotFound)?;
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    // The hex is public, so a private room's history stays where it is
+    if room.visibility != crate::room_invites::RoomVisibility::Public {
+        return Err(AppError::BadRequest("Private rooms can't be migrated to a hex".to_string()));
+    }
+    if !room.migrated_to_hex {
+        let h3_index = cell.to_string();
+        let moved = state.db.migrate_room_to_hex(&location_id, &h3_index).await?;
+        for room_id in [location_id.as_str(), h3_index.as_str()] {
+            crate::room_cache::invalidate_room(&state, room_id).await;
+            crate::room_cache::invalidate_history(&state, room_id).await;
+        }
+        tracing::info!("Admin {} migrated room {} to hex {} ({} messages)", user.user_id, location_id, h3_index, moved);
+    }
+    
+    Ok(Json(RoomHexMapping {
+        location_id,
+        cell: cell.into(),
+        mode: state.room_bridge,
+        migrated_to_hex: true,
     }))
 }
+
+// GET /metrics - sockets, message throughput and Redis/MongoDB latencies
+pub async fn metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
+
+// GET /metrics/rooms - per-room series bucketed to the busiest rooms
+pub async fn room_metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.room_metrics.render(),
+    )
+}
+
+// GET /metrics/canary - connection and broadcast series per rollout cohort
+pub async fn canary_metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.canary.render(),
+    )
+}
+
+// GET /metrics/fanout - Redis fallback and shared pub/sub state
+pub async fn fanout_metrics_handler(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        format!(
+            "{}{}{}{}{}{}",
+            state.fanout.render(),
+            state.pubsub.render(),
+            state.pubsub_lag.render(&state.instance_id),
+            state.stream_reader.as_ref().map(|reader| reader.render()).unwrap_or_default(),
+            state.send_buffers.render(),
+            state.user_service.render(),
+        ),
+    )
+}
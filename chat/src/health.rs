@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use tracing::warn;
+
+use crate::AppState;
+
+// A dependency slower than this to answer a probe counts as down
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DependencyStatus {
+    pub status: Status,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DependencyStatus {
+    pub fn up(latency: Duration) -> Self {
+        DependencyStatus { status: Status::Up, latency_ms: latency.as_millis() as u64, error: None }
+    }
+
+    pub fn down(latency: Duration, error: impl Into<String>) -> Self {
+        DependencyStatus { status: Status::Down, latency_ms: latency.as_millis() as u64, error: Some(error.into()) }
+    }
+}
+
+/// Each dependency's status, and the overall status: `up` only when every
+/// dependency is.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HealthReport {
+    pub status: Status,
+    pub dependencies: BTreeMap<&'static str, DependencyStatus>,
+}
+
+impl HealthReport {
+    pub fn new(dependencies: impl IntoIterator<Item = (&'static str, DependencyStatus)>) -> Self {
+        let dependencies: BTreeMap<_, _> = dependencies.into_iter().collect();
+        let status = if dependencies.values().all(|dependency| dependency.status == Status::Up) {
+            Status::Up
+        } else {
+            Status::Down
+        };
+        HealthReport { status, dependencies }
+    }
+
+    pub fn status_code(&self) -> StatusCode {
+        match self.status {
+            Status::Up => StatusCode::OK,
+            Status::Down => StatusCode::SERVICE_UNAVAILABLE,
+        }
+    }
+}
+
+async fn probe<E: std::fmt::Display>(check: impl Future<Output = Result<(), E>>) -> DependencyStatus {
+    let started = Instant::now();
+    match tokio::time::timeout(PROBE_TIMEOUT, check).await {
+        Ok(Ok(())) => DependencyStatus::up(started.elapsed()),
+        Ok(Err(e)) => DependencyStatus::down(started.elapsed(), e.to_string()),
+        Err(_) => DependencyStatus::down(started.elapsed(), format!("no answer within {}s", PROBE_TIMEOUT.as_secs())),
+    }
+}
+
+async fn ping_mongodb(state: &AppState) -> DependencyStatus {
+    probe(state.db.ping()).await
+}
+
+async fn ping_redis(state: &AppState) -> DependencyStatus {
+    probe(async {
+        let mut conn = state.redis_pool.get().await.map_err(|e| e.to_string())?;
+        redis::cmd("PING").query_async::<_, String>(&mut conn).await.map_err(|e| e.to_string())?;
+        Ok::<_, String>(())
+    })
+    .await
+}
+
+fn respond(probe: &str, report: HealthReport) -> (StatusCode, Json<HealthReport>) {
+    if report.status == Status::Down {
+        warn!("{} probe failing: {:?}", probe, report.dependencies);
+    }
+    (report.status_code(), Json(report))
+}
+
+// GET /health/live - MongoDB and Redis answer a ping
+pub async fn live_handler(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let (mongodb, redis) = tokio::join!(ping_mongodb(&state), ping_redis(&state));
+    respond("Liveness", HealthReport::new([("mongodb", mongodb), ("redis", redis)]))
+}
+
+// GET /health/ready - as /health/live, and the shared pub/sub connection
+// is subscribed, so broadcasts from other instances reach this one; down
+// while shutting down, so no new clients are sent here
+pub async fn ready_handler(State(state): State<AppState>) -> (StatusCode, Json<HealthReport>) {
+    let (mongodb, redis) = tokio::join!(ping_mongodb(&state), ping_redis(&state));
+    let pubsub = if state.pubsub.is_connected() {
+        DependencyStatus::up(Duration::ZERO)
+    } else {
+        DependencyStatus::down(Duration::ZERO, "shared pub/sub connection is reconnecting")
+    };
+    let accepting = if state.shutdown.is_draining() {
+        DependencyStatus::down(Duration::ZERO, "shutting down")
+    } else {
+        DependencyStatus::up(Duration::ZERO)
+    };
+    respond(
+        "Readiness",
+        HealthReport::new([("mongodb", mongodb), ("redis", redis), ("pubsub", pubsub), ("accepting", accepting)]),
+    )
+}
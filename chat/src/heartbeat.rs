@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+
+const DEFAULT_PING_INTERVAL_SECS: u64 = 30;
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 90;
+
+/// Server-initiated pings on room sockets. Clients that vanish without a
+/// close frame stop answering and are disconnected once idle for
+/// `idle_timeout`, which removes them from `ConnectionManager`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatConfig {
+    pub ping_interval: Duration,
+    pub idle_timeout: Duration,
+}
+
+impl HeartbeatConfig {
+    pub fn new(ping_interval: Duration, idle_timeout: Duration) -> Self {
+        let ping_interval = ping_interval.max(Duration::from_secs(1));
+        HeartbeatConfig {
+            ping_interval,
+            // A client always gets at least one ping to answer
+            idle_timeout: idle_timeout.max(ping_interval),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let secs = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            Duration::from_secs(secs("WS_PING_INTERVAL_SECS", DEFAULT_PING_INTERVAL_SECS)),
+            Duration::from_secs(secs("WS_IDLE_TIMEOUT_SECS", DEFAULT_IDLE_TIMEOUT_SECS)),
+        )
+    }
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self::new(
+            Duration::from_secs(DEFAULT_PING_INTERVAL_SECS),
+            Duration::from_secs(DEFAULT_IDLE_TIMEOUT_SECS),
+        )
+    }
+}
+
+/// When a socket last heard anything from its client, pongs included.
+#[derive(Debug, Clone)]
+pub struct Liveness(Arc<AtomicI64>);
+
+impl Liveness {
+    pub fn new() -> Self {
+        Liveness(Arc::new(AtomicI64::new(Utc::now().timestamp_millis())))
+    }
+
+    pub fn touch(&self) {
+        self.0.store(Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        let idle_ms = Utc::now().timestamp_millis() - self.0.load(Ordering::Relaxed);
+        Duration::from_millis(idle_ms.max(0) as u64)
+    }
+
+    pub fn is_idle(&self, config: &HeartbeatConfig) -> bool {
+        self.idle_for() >= config.idle_timeout
+    }
+}
+
+impl Default for Liveness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,134 @@
+use h3o::{CellIndex, LatLng, Resolution};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Resolution used when the client gives us no usable accuracy (neighborhood level)
+pub const DEFAULT_RESOLUTION: Resolution = Resolution::Eight;
+
+// Bounds for automatic selection: finer than 9 is smaller than a city block,
+// coarser than 5 stops being "local" chat.
+pub const FINEST_AUTO_RESOLUTION: Resolution = Resolution::Nine;
+pub const COARSEST_AUTO_RESOLUTION: Resolution = Resolution::Five;
+
+// Widest ring of neighbouring hexes a socket may listen to
+pub const MAX_NEIGHBOR_RING: u8 = 1;
+// Widest ring `GET /api/hex/active` reports on (37 hexes)
+pub const MAX_ACTIVE_RING: u8 = 3;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum HexError {
+    #[error("Invalid coordinates")]
+    InvalidCoordinates,
+    #[error("Invalid H3 index: {0}")]
+    InvalidIndex(String),
+    #[error("Resolution must be between {} and {}", u8::from(COARSEST_AUTO_RESOLUTION), u8::from(FINEST_AUTO_RESOLUTION))]
+    InvalidResolution(u8),
+}
+
+// Raw GPS fix sent by clients that don't compute an H3 index themselves
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GpsFix {
+    pub latitude: f64,
+    pub longitude: f64,
+    // Horizontal accuracy radius in meters, as reported by the device
+    #[serde(default)]
+    pub accuracy: Option<f64>,
+}
+
+/// Picks the finest resolution whose cell edge is at least as long as the
+/// reported accuracy radius, so a poor fix lands in a coarser hex instead of
+/// a neighboring one.
+pub fn resolution_for_accuracy(accuracy: Option<f64>) -> Resolution {
+    let accuracy = match accuracy {
+        Some(meters) if meters.is_finite() && meters > 0.0 => meters,
+        _ => return DEFAULT_RESOLUTION,
+    };
+
+    let mut resolution = FINEST_AUTO_RESOLUTION;
+    while resolution != COARSEST_AUTO_RESOLUTION && resolution.edge_length_m() < accuracy {
+        resolution = match resolution.pred() {
+            Some(coarser) => coarser,
+            None => break,
+        };
+    }
+    resolution
+}
+
+// h3o only rejects non-finite values, so out-of-range degrees are checked here
+pub fn lat_lng(latitude: f64, longitude: f64) -> Result<LatLng, HexError> {
+    if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+        return Err(HexError::InvalidCoordinates);
+    }
+    LatLng::new(latitude, longitude).map_err(|_| HexError::InvalidCoordinates)
+}
+
+pub fn cell_for_fix(fix: &GpsFix) -> Result<CellIndex, HexError> {
+    let coordinates = lat_lng(fix.latitude, fix.longitude)?;
+    Ok(coordinates.to_cell(resolution_for_accuracy(fix.accuracy)))
+}
+
+pub fn parse_cell(h3_index: &str) -> Result<CellIndex, HexError> {
+    h3_index.parse::<CellIndex>().map_err(|_| HexError::InvalidIndex(h3_index.to_string()))
+}
+
+/// A hex room's cell as clients draw it. Points are in GeoJSON order,
+/// `[longitude, latitude]`, like `Location`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HexCell {
+    pub h3_index: String,
+    pub resolution: u8,
+    pub center: [f64; 2],
+    pub boundary: Vec<[f64; 2]>,
+}
+
+impl From<CellIndex> for HexCell {
+    fn from(cell: CellIndex) -> Self {
+        let center = LatLng::from(cell);
+        HexCell {
+            h3_index: cell.to_string(),
+            resolution: u8::from(cell.resolution()),
+            center: [center.lng(), center.lat()],
+            boundary: cell.boundary().iter().map(|vertex| [vertex.lng(), vertex.lat()]).collect(),
+        }
+    }
+}
+
+/// A resolution clients may ask for, within the automatic bounds.
+pub fn chat_resolution(resolution: u8) -> Result<Resolution, HexError> {
+    let parsed = Resolution::try_from(resolution).map_err(|_| HexError::InvalidResolution(resolution))?;
+    if parsed < COARSEST_AUTO_RESOLUTION || parsed > FINEST_AUTO_RESOLUTION {
+        return Err(HexError::InvalidResolution(resolution));
+    }
+    Ok(parsed)
+}
+
+/// `cell` followed by the hexes within `k` steps of it, `k` capped at
+/// `MAX_ACTIVE_RING`.
+pub fn disk(cell: CellIndex, k: u8) -> Vec<CellIndex> {
+    let mut disk = vec![cell];
+    disk.extend(cell.grid_disk::<Vec<_>>(u32::from(k.min(MAX_ACTIVE_RING))).into_iter().filter(|other| *other != cell));
+    disk
+}
+
+/// The hexes within `k` steps of `cell`, without `cell` itself. `k` is
+/// capped at `MAX_NEIGHBOR_RING`.
+pub fn neighbors(cell: CellIndex, k: u8) -> Vec<CellIndex> {
+    let k = k.min(MAX_NEIGHBOR_RING);
+    if k == 0 {
+        return Vec::new();
+    }
+    cell.grid_disk::<Vec<_>>(u32::from(k)).into_iter().filter(|neighbor| *neighbor != cell).collect()
+}
+
+/// The cell a socket in `current` moves to when zooming to `resolution`:
+/// its parent when zooming out, and when zooming in the child holding the
+/// user's fix, or the centre child without one. Zooms stay within the
+/// automatic resolution bounds.
+pub fn zoom(current: CellIndex, resolution: u8, fix: Option<LatLng>) -> Result<CellIndex, HexError> {
+    let target = chat_resolution(resolution)?;
+    if target <= current.resolution() {
+        return current.parent(target).ok_or(HexError::InvalidResolution(resolution));
+    }
+    let inside = fix.map(|fix| fix.to_cell(target)).filter(|cell| cell.parent(current.resolution()) == Some(current));
+    inside.or_else(|| current.center_child(target)).ok_or(HexError::InvalidResolution(resolution))
+}
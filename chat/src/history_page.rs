@@ -0,0 +1,62 @@
+use chrono::{DateTime, Utc};
+
+use crate::models::Message;
+
+const DEFAULT_MIN_PAGE: usize = 20;
+const DEFAULT_MAX_PAGE: usize = 100;
+const DEFAULT_ACTIVITY_WINDOW_MINS: i64 = 15;
+
+/// How much history a joining socket gets. A room's page holds the messages
+/// sent in its last `activity_window`, but never fewer than `min_page` or
+/// more than `max_page`: a busy room shows the whole recent conversation, a
+/// quiet one just enough to pick it up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryPaging {
+    pub min_page: usize,
+    pub max_page: usize,
+    pub activity_window: chrono::Duration,
+}
+
+impl HistoryPaging {
+    pub fn new(min_page: usize, max_page: usize, activity_window: chrono::Duration) -> Self {
+        let min_page = min_page.max(1);
+        HistoryPaging { min_page, max_page: max_page.max(min_page), activity_window }
+    }
+
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: i64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        Self::new(
+            number("HISTORY_PAGE_MIN", DEFAULT_MIN_PAGE as i64).max(1) as usize,
+            number("HISTORY_PAGE_MAX", DEFAULT_MAX_PAGE as i64).max(1) as usize,
+            chrono::Duration::minutes(number("HISTORY_ACTIVITY_WINDOW_MINS", DEFAULT_ACTIVITY_WINDOW_MINS).max(0)),
+        )
+    }
+
+    /// Page size for a room whose latest messages, oldest first, are
+    /// `messages`. Only the newest `max_page` need to be passed.
+    pub fn page_size(&self, messages: &[Message], now: DateTime<Utc>) -> usize {
+        let since = now - self.activity_window;
+        let recent = messages.iter().rev().take_while(|message| message.timestamp >= since).count();
+        recent.clamp(self.min_page, self.max_page)
+    }
+
+    /// Keeps the newest messages that fit the room's page.
+    pub fn fit(&self, mut messages: Vec<Message>, now: DateTime<Utc>) -> Vec<Message> {
+        let size = self.page_size(&messages, now);
+        if messages.len() > size {
+            messages.drain(..messages.len() - size);
+        }
+        messages
+    }
+}
+
+impl Default for HistoryPaging {
+    fn default() -> Self {
+        Self::new(DEFAULT_MIN_PAGE, DEFAULT_MAX_PAGE, chrono::Duration::minutes(DEFAULT_ACTIVITY_WINDOW_MINS))
+    }
+}
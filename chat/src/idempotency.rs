@@ -0,0 +1,106 @@
+use axum::http::HeaderMap;
+use mongodb::bson::oid::ObjectId;
+use tracing::error;
+
+// How long a retry with the same key returns the original message
+pub const IDEMPOTENCY_WINDOW_SECONDS: u64 = 24 * 60 * 60;
+// A claim whose request died without finishing frees up after this long
+const PENDING_SECONDS: u64 = 60;
+const MAX_KEY_LEN: usize = 255;
+const PENDING: &str = "pending";
+
+fn idempotency_key(user_id: &str, key: &str) -> String {
+    format!("idem:{}:{}", user_id, key)
+}
+
+/// The client's key for a send, from the `Idempotency-Key` header or else
+/// the `idempotency_key` body field.
+pub fn request_key(headers: &HeaderMap, body_key: Option<&str>) -> Result<Option<String>, String> {
+    let key = match headers.get("idempotency-key") {
+        Some(value) => Some(value.to_str().map_err(|_| "Idempotency-Key must be ASCII".to_string())?),
+        None => body_key,
+    };
+    match key.map(str::trim) {
+        None => Ok(None),
+        Some("") => Err("Idempotency key must not be empty".to_string()),
+        Some(key) if key.len() > MAX_KEY_LEN => Err(format!("Idempotency key is longer than {} bytes", MAX_KEY_LEN)),
+        Some(key) => Ok(Some(key.to_string())),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Claim {
+    // First use of the key; the send goes ahead
+    New,
+    // An earlier request with the key is still being handled
+    InProgress,
+    // An earlier request with the key created this message
+    Completed(ObjectId),
+}
+
+pub fn parse_claim(stored: Option<&str>) -> Claim {
+    match stored {
+        None => Claim::New,
+        Some(value) => ObjectId::parse_str(value).map(Claim::Completed).unwrap_or(Claim::InProgress),
+    }
+}
+
+/// Claims a key for a user's send. Keys are scoped per user. Fails open, so
+/// without Redis sends go ahead undeduplicated.
+pub async fn claim(pool: &deadpool_redis::Pool, user_id: &str, key: &str) -> Claim {
+    let Ok(mut conn) = pool.get().await else {
+        return Claim::New;
+    };
+    let redis_key = idempotency_key(user_id, key);
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(&redis_key)
+        .arg(PENDING)
+        .arg("NX")
+        .arg("EX")
+        .arg(PENDING_SECONDS)
+        .query_async(&mut conn)
+        .await;
+    match claimed {
+        Ok(Some(_)) => Claim::New,
+        Ok(None) => {
+            let stored: Option<String> = redis::cmd("GET").arg(&redis_key).query_async(&mut conn).await.unwrap_or(None);
+            match parse_claim(stored.as_deref()) {
+                // Expired between the two commands
+                Claim::New => Claim::InProgress,
+                claim => claim,
+            }
+        }
+        Err(e) => {
+            error!("Failed to claim idempotency key for {}: {}", user_id, e);
+            Claim::New
+        }
+    }
+}
+
+/// Records the message a key created, for the rest of the window.
+pub async fn complete(pool: &deadpool_redis::Pool, user_id: &str, key: &str, message_id: &ObjectId) {
+    let Ok(mut conn) = pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::cmd("SET")
+        .arg(idempotency_key(user_id, key))
+        .arg(message_id.to_hex())
+        .arg("EX")
+        .arg(IDEMPOTENCY_WINDOW_SECONDS)
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to record idempotency key for {}: {}", user_id, e);
+    }
+}
+
+/// Frees a key whose send failed, so the client's retry can go through.
+pub async fn release(pool: &deadpool_redis::Pool, user_id: &str, key: &str) {
+    let Ok(mut conn) = pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::cmd("DEL").arg(idempotency_key(user_id, key)).query_async(&mut conn).await;
+    if let Err(e) = result {
+        error!("Failed to release idempotency key for {}: {}", user_id, e);
+    }
+}
@@ -0,0 +1,61 @@
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::{self, doc},
+    options::UpdateOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{auth::AuthUser, AppState};
+
+/// The username a user last authenticated with. Messages are attributed
+/// by these, never by the name a client claims.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KnownUser {
+    #[serde(rename = "_id")]
+    pub user_id: String,
+    pub username: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub seen_at: DateTime<Utc>,
+}
+
+pub(crate) fn known_users(state: &AppState) -> Collection<KnownUser> {
+    state.database.collection("usernames")
+}
+
+/// Whether a client's claimed author differs from who it authenticated as.
+/// Omitted fields aren't a mismatch; they're filled in from the token.
+pub fn is_impersonation(user: &AuthUser, user_id: Option<&str>, username: Option<&str>) -> bool {
+    user_id.is_some_and(|id| id != user.user_id) || username.is_some_and(|name| name != user.username)
+}
+
+/// Records the username from a verified token.
+pub async fn remember(state: &AppState, user: &AuthUser) {
+    let options = UpdateOptions::builder().upsert(true).build();
+    let result = known_users(state)
+        .update_one(
+            doc! { "_id": &user.user_id },
+            doc! { "$set": {
+                "username": &user.username,
+                "seen_at": bson::DateTime::from_millis(Utc::now().timestamp_millis()),
+            } },
+            options,
+        )
+        .await;
+    if let Err(e) = result {
+        error!("Failed to record username of {}: {}", user.user_id, e);
+    }
+}
+
+/// The username `user_id` last authenticated with, if it ever has. Fails
+/// open so a database hiccup doesn't block joins.
+pub async fn known_username(state: &AppState, user_id: &str) -> Option<String> {
+    match known_users(state).find_one(doc! { "_id": user_id }, None).await {
+        Ok(known) => known.map(|known| known.username),
+        Err(e) => {
+            error!("Failed to look up username of {}: {}", user_id, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+// Every instance id, scored by its last heartbeat in Unix millis
+const INSTANCES_KEY: &str = "instances";
+// Instances not heard from within this long are left out, so a crashed
+// instance drops off the list on its own
+const INSTANCE_TTL_MS: i64 = 90_000;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+fn instance_key(instance_id: &str) -> String {
+    format!("instance:{}", instance_id)
+}
+
+/// One running instance as it last reported itself.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    pub instance_id: String,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    // Room and hex sockets open on the instance
+    pub sockets: usize,
+    // Rooms with at least one of those sockets
+    pub rooms: usize,
+    #[serde(default)]
+    pub draining: bool,
+}
+
+impl InstanceInfo {
+    /// Summarises the instance from its per-room socket counts.
+    pub fn new(instance_id: &str, started_at: DateTime<Utc>, socket_counts: &[(String, usize)], now: DateTime<Utc>) -> Self {
+        InstanceInfo {
+            instance_id: instance_id.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at,
+            last_seen: now,
+            sockets: socket_counts.iter().map(|(_, sockets)| sockets).sum(),
+            rooms: socket_counts.iter().filter(|(_, sockets)| *sockets > 0).count(),
+            draining: false,
+        }
+    }
+
+    pub fn is_live(&self, now: DateTime<Utc>) -> bool {
+        (now - self.last_seen).num_milliseconds() < INSTANCE_TTL_MS
+    }
+}
+
+async fn local_info(state: &AppState) -> InstanceInfo {
+    let socket_counts = state.connections.read().await.socket_counts();
+    let mut info = InstanceInfo::new(&state.instance_id, state.started_at, &socket_counts, Utc::now());
+    info.draining = state.shutdown.is_draining();
+    info
+}
+
+async fn register(state: &AppState) -> redis::RedisResult<()> {
+    let info = local_info(state).await;
+    let payload = serde_json::to_string(&info).unwrap_or_default();
+    let now = info.last_seen.timestamp_millis();
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    redis::pipe()
+        .cmd("SET")
+        .arg(instance_key(&state.instance_id))
+        .arg(payload)
+        .arg("PX")
+        .arg(INSTANCE_TTL_MS)
+        .ignore()
+        .zadd(INSTANCES_KEY, &state.instance_id, now)
+        .ignore()
+        .zrembyscore(INSTANCES_KEY, "-inf", now - INSTANCE_TTL_MS)
+        .ignore()
+        .query_async(&mut conn)
+        .await
+}
+
+/// Records this instance and its connection counts in Redis every interval,
+/// starting right away.
+pub fn spawn_heartbeat(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        info!("Registering as instance {}", state.instance_id);
+        let mut tick = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tick.tick().await;
+            if let Err(e) = register(&state).await {
+                error!("Failed to refresh instance {} in the registry: {}", state.instance_id, e);
+            }
+        }
+    })
+}
+
+/// Takes this instance off the registry as it exits.
+pub async fn deregister(state: &AppState) {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::pipe()
+        .zrem(INSTANCES_KEY, &state.instance_id)
+        .ignore()
+        .del(instance_key(&state.instance_id))
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to remove instance {} from the registry: {}", state.instance_id, e);
+    }
+}
+
+/// Every live instance in the registry, oldest first.
+pub(crate) async fn registered(state: &AppState) -> redis::RedisResult<Vec<InstanceInfo>> {
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    let now = Utc::now();
+    let ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+        .arg(INSTANCES_KEY)
+        .arg(now.timestamp_millis() - INSTANCE_TTL_MS)
+        .arg("+inf")
+        .query_async(&mut conn)
+        .await?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let keys: Vec<String> = ids.iter().map(|id| instance_key(id)).collect();
+    let payloads: Vec<Option<String>> = redis::cmd("MGET").arg(&keys).query_async(&mut conn).await?;
+    let mut instances: Vec<InstanceInfo> = payloads
+        .into_iter()
+        .flatten()
+        .filter_map(|payload| serde_json::from_str::<InstanceInfo>(&payload).ok())
+        .filter(|instance| instance.is_live(now))
+        .collect();
+    instances.sort_by(|a, b| a.started_at.cmp(&b.started_at).then_with(|| a.instance_id.cmp(&b.instance_id)));
+    Ok(instances)
+}
+
+#[derive(Serialize)]
+pub struct InstancesResponse {
+    // The instance that answered
+    instance_id: String,
+    instances: Vec<InstanceInfo>,
+    // Sockets across every listed instance
+    total_sockets: usize,
+    // Redis was unavailable, so only this instance is listed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    local_only: bool,
+}
+
+// GET /api/admin/instances - running instances, oldest first, with their
+// connection counts; admins only
+pub async fn instances_handler(State(state): State<AppState>, user: AuthUser) -> Result<Json<InstancesResponse>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    let local = local_info(&state).await;
+    let (mut instances, local_only) = match registered(&state).await {
+        Ok(instances) => (instances, false),
+        Err(e) => {
+            error!("Failed to read the instance registry: {}", e);
+            (Vec::new(), true)
+        }
+    };
+    // This instance's own counts are current; its registry entry may lag
+    match instances.iter_mut().find(|instance| instance.instance_id == state.instance_id) {
+        Some(instance) => *instance = local,
+        None => instances.push(local),
+    }
+    let total_sockets = instances.iter().map(|instance| instance.sockets).sum();
+    Ok(Json(InstancesResponse { instance_id: state.instance_id.clone(), instances, total_sockets, local_only }))
+}
@@ -0,0 +1,43 @@
+use axum::http::HeaderMap;
+use h3o::LatLng;
+
+/// Compares a client's claimed GPS location with the coarse location of its
+/// IP address, as resolved by the edge proxy (e.g. Cloudflare's visitor
+/// location headers). Disabled unless `IP_GEO_CHECK=true`.
+#[derive(Debug, Clone)]
+pub struct IpGeoConfig {
+    pub enabled: bool,
+    pub latitude_header: String,
+    pub longitude_header: String,
+    // IP geolocation is city-level at best, so only large gaps are suspicious
+    pub max_distance_km: f64,
+}
+
+impl IpGeoConfig {
+    pub fn from_env() -> Self {
+        IpGeoConfig {
+            enabled: std::env::var("IP_GEO_CHECK").map(|v| v == "true").unwrap_or(false),
+            latitude_header: std::env::var("IP_GEO_LATITUDE_HEADER")
+                .unwrap_or_else(|_| "cf-iplatitude".to_string()),
+            longitude_header: std::env::var("IP_GEO_LONGITUDE_HEADER")
+                .unwrap_or_else(|_| "cf-iplongitude".to_string()),
+            max_distance_km: std::env::var("IP_GEO_MAX_DISTANCE_KM")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(500.0),
+        }
+    }
+
+    pub fn ip_location(&self, headers: &HeaderMap) -> Option<LatLng> {
+        if !self.enabled {
+            return None;
+        }
+        let header = |name: &str| headers.get(name)?.to_str().ok()?.trim().parse::<f64>().ok();
+        crate::hex::lat_lng(header(&self.latitude_header)?, header(&self.longitude_header)?).ok()
+    }
+
+    /// True when the claimed location is implausibly far from the IP location.
+    pub fn is_mismatch(&self, claimed: LatLng, ip_location: Option<LatLng>) -> bool {
+        ip_location.is_some_and(|ip_location| claimed.distance_km(ip_location) > self.max_distance_km)
+    }
+}
@@ -0,0 +1,35 @@
+use crate::models::Message;
+use whatlang::Lang;
+
+// Very short messages ("ok", "lol", emoji) are never reliably detectable
+const MIN_DETECTABLE_CHARS: usize = 12;
+
+/// Normalizes a room language to the ISO 639-3 code used for detection
+/// (e.g. "eng", "spa"). Returns `None` for unknown codes.
+pub fn normalize_language(code: &str) -> Option<String> {
+    Lang::from_code(code.trim().to_lowercase()).map(|lang| lang.code().to_string())
+}
+
+pub fn detect_language(content: &str) -> Option<String> {
+    if content.chars().count() < MIN_DETECTABLE_CHARS {
+        return None;
+    }
+
+    whatlang::detect(content)
+        .filter(|info| info.is_reliable())
+        .map(|info| info.lang().code().to_string())
+}
+
+/// Tags a message with its detected language and whether it differs from the
+/// room's primary language, so clients can dim or collapse it.
+pub fn tag_message(message: &mut Message, room_language: Option<&str>) {
+    let Some(room_language) = room_language else {
+        return;
+    };
+
+    message.language = detect_language(&message.content);
+    message.off_language = message
+        .language
+        .as_deref()
+        .is_some_and(|language| language != room_language);
+}
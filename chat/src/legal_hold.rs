@@ -0,0 +1,247 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    error::Result as MongoResult,
+    options::FindOptions,
+    Collection, Database,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{auth::InternalCaller, models::Message, AppError, AppState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldScope {
+    Room,
+    User,
+}
+
+/// Holds are append-only: releasing one stamps `released_at` instead of
+/// deleting the record.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LegalHold {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub scope: HoldScope,
+    pub target_id: String,
+    pub reason: String,
+    pub created_by: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub released_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub released_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HoldAccess {
+    pub hold_id: ObjectId,
+    pub accessed_by: String,
+    pub action: String,
+    // Instance that served the access
+    #[serde(default)]
+    pub instance_id: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub accessed_at: DateTime<Utc>,
+}
+
+fn holds(database: &Database) -> Collection<LegalHold> {
+    database.collection("legal_holds")
+}
+
+fn access_log(database: &Database) -> Collection<HoldAccess> {
+    database.collection("legal_hold_access")
+}
+
+pub async fn active_holds(database: &Database) -> MongoResult<Vec<LegalHold>> {
+    holds(database)
+        .find(doc! { "released_at": { "$exists": false } }, None)
+        .await?
+        .try_collect()
+        .await
+}
+
+/// Filter that retention and archival jobs must AND into their deletes so
+/// held messages survive.
+pub fn exclusion_filter(holds: &[LegalHold]) -> Document {
+    let targets = |scope: HoldScope| -> Vec<&str> {
+        holds
+            .iter()
+            .filter(|hold| hold.scope == scope && hold.released_at.is_none())
+            .map(|hold| hold.target_id.as_str())
+            .collect()
+    };
+    doc! {
+        "room_id": { "$nin": targets(HoldScope::Room) },
+        "user_id": { "$nin": targets(HoldScope::User) },
+    }
+}
+
+pub async fn retention_exclusion(database: &Database) -> MongoResult<Document> {
+    Ok(exclusion_filter(&active_holds(database).await?))
+}
+
+async fn record_access(state: &AppState, hold_id: ObjectId, caller: &InternalCaller, action: &str) {
+    let entry = HoldAccess {
+        hold_id,
+        accessed_by: caller.name.clone(),
+        action: action.to_string(),
+        instance_id: state.instance_id.clone(),
+        accessed_at: Utc::now(),
+    };
+    if let Err(e) = access_log(&state.database).insert_one(entry, None).await {
+        error!("Failed to record legal hold access for {}: {}", hold_id, e);
+    }
+}
+
+async fn find_hold(database: &Database, hold_id: &str) -> Result<LegalHold, AppError> {
+    let id = ObjectId::parse_str(hold_id).map_err(|_| AppError::BadRequest("Invalid hold id".to_string()))?;
+    holds(database)
+        .find_one(doc! { "_id": id }, None)
+        .await?
+        .ok_or(AppError::NotFound)
+}
+
+#[derive(Deserialize)]
+pub struct CreateHoldRequest {
+    scope: HoldScope,
+    target_id: String,
+    reason: String,
+}
+
+// POST /internal/legal-holds
+pub async fn create_hold_handler(
+    State(state): State<AppState>,
+    caller: InternalCaller,
+    Json(req): Json<CreateHoldRequest>,
+) -> Result<Json<LegalHold>, AppError> {
+    if req.target_id.is_empty() || req.reason.is_empty() {
+        return Err(AppError::BadRequest("target_id and reason are required".to_string()));
+    }
+
+    let mut hold = LegalHold {
+        id: None,
+        scope: req.scope,
+        target_id: req.target_id,
+        reason: req.reason,
+        created_by: caller.name.clone(),
+        created_at: Utc::now(),
+        released_at: None,
+        released_by: None,
+    };
+    let result = holds(&state.database).insert_one(&hold, None).await?;
+    hold.id = result.inserted_id.as_object_id();
+    info!("{} placed a legal hold on {:?} {}", caller.name, hold.scope, hold.target_id);
+
+    if let Some(id) = hold.id {
+        record_access(&state, id, &caller, "create").await;
+    }
+    Ok(Json(hold))
+}
+
+// GET /internal/legal-holds
+pub async fn list_holds_handler(
+    State(state): State<AppState>,
+    _caller: InternalCaller,
+) -> Result<Json<Vec<LegalHold>>, AppError> {
+    let all = holds(&state.database)
+        .find(None, FindOptions::builder().sort(doc! { "created_at": -1 }).build())
+        .await?
+        .try_collect()
+        .await?;
+    Ok(Json(all))
+}
+
+// POST /internal/legal-holds/:hold_id/release
+pub async fn release_hold_handler(
+    Path(hold_id): Path<String>,
+    State(state): State<AppState>,
+    caller: InternalCaller,
+) -> Result<Json<LegalHold>, AppError> {
+    let hold = find_hold(&state.database, &hold_id).await?;
+    let Some(id) = hold.id else {
+        return Err(AppError::NotFound);
+    };
+    if hold.released_at.is_some() {
+        return Err(AppError::BadRequest("Hold already released".to_string()));
+    }
+
+    holds(&state.database)
+        .update_one(
+            doc! { "_id": id },
+            doc! { "$set": { "released_at": mongodb::bson::DateTime::now(), "released_by": &caller.name } },
+            None,
+        )
+        .await?;
+    info!("{} released legal hold {}", caller.name, id);
+    record_access(&state, id, &caller, "release").await;
+
+    Ok(Json(find_hold(&state.database, &hold_id).await?))
+}
+
+#[derive(Deserialize)]
+pub struct HeldMessagesQuery {
+    limit: Option<i64>,
+    before: Option<DateTime<Utc>>,
+}
+
+// GET /internal/legal-holds/:hold_id/messages - every read is logged
+pub async fn held_messages_handler(
+    Path(hold_id): Path<String>,
+    Query(params): Query<HeldMessagesQuery>,
+    State(state): State<AppState>,
+    caller: InternalCaller,
+) -> Result<Json<Vec<Message>>, AppError> {
+    let hold = find_hold(&state.database, &hold_id).await?;
+    let Some(id) = hold.id else {
+        return Err(AppError::NotFound);
+    };
+
+    // Deleted messages are included; the hold covers everything
+    let mut filter = match hold.scope {
+        HoldScope::Room => doc! { "room_id": &hold.target_id },
+        HoldScope::User => doc! { "user_id": &hold.target_id },
+    };
+    if let Some(before) = params.before {
+        filter.insert("timestamp", doc! { "$lt": mongodb::bson::DateTime::from_millis(before.timestamp_millis()) });
+    }
+    let options = FindOptions::builder()
+        .sort(doc! { "timestamp": -1 })
+        .limit(params.limit.unwrap_or(500).min(5000))
+        .build();
+    let messages: Vec<Message> = state
+        .database
+        .collection::<Message>("messages")
+        .find(filter, options)
+        .await?
+        .try_collect()
+        .await?;
+
+    record_access(&state, id, &caller, "export_messages").await;
+    Ok(Json(messages))
+}
+
+// GET /internal/legal-holds/:hold_id/access-log
+pub async fn hold_access_log_handler(
+    Path(hold_id): Path<String>,
+    State(state): State<AppState>,
+    _caller: InternalCaller,
+) -> Result<Json<Vec<HoldAccess>>, AppError> {
+    let hold = find_hold(&state.database, &hold_id).await?;
+    let Some(id) = hold.id else {
+        return Err(AppError::NotFound);
+    };
+    let entries = access_log(&state.database)
+        .find(doc! { "hold_id": id }, FindOptions::builder().sort(doc! { "accessed_at": 1 }).build())
+        .await?
+        .try_collect()
+        .await?;
+    Ok(Json(entries))
+}
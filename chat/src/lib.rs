@@ -6,6 +6,83 @@ pub mod errors;
 pub mod local_chat;
 pub mod dm;
 pub mod auth;
+pub mod hex;
+pub mod room_bridge;
+pub mod language;
+pub mod activity;
+pub mod abuse;
+pub mod ip_geo;
+pub mod legal_hold;
+pub mod room_metrics;
+pub mod self_check;
+pub mod fanout;
+pub mod webhooks;
+pub mod room_events;
+pub mod rsvp;
+pub mod read_cursors;
+pub mod shared_rooms;
+pub mod subscription_filter;
+pub mod pubsub;
+pub mod send_buffer;
+pub mod dm_keys;
+pub mod rate_limit;
+pub mod presence;
+pub mod content_filter;
+pub mod permissions;
+pub mod room_mentions;
+pub mod heartbeat;
+pub mod delivery_trace;
+pub mod sessions;
+pub mod migrations;
+pub mod wire_format;
+pub mod topics;
+pub mod identity;
+pub mod idempotency;
+pub mod streams;
+pub mod notifications;
+pub mod announcements;
+pub mod user_service;
+pub mod frame_limits;
+pub mod preferences;
+pub mod pubsub_lag;
+pub mod bulk_messages;
+pub mod user_history;
+pub mod uploads;
+pub mod connection_state;
+pub mod reactions;
+pub mod room_ranking;
+pub mod canary;
+pub mod room_cache;
+pub mod ws_ticket;
+pub mod threads;
+pub mod service_metrics;
+pub mod room_quota;
+pub mod telemetry;
+pub mod health;
+pub mod message_body;
+pub mod shutdown;
+pub mod instances;
+pub mod low_data;
+pub mod room_markers;
+pub mod admin_stats;
+pub mod badges;
+pub mod message_ttl;
+pub mod room_bans;
+pub mod history_page;
+pub mod timeouts;
+pub mod user_presence;
+pub mod user_blocks;
+pub mod shadow_bans;
+pub mod room_roles;
+pub mod room_invites;
+pub mod room_invite_links;
+pub mod message_filters;
+pub mod reports;
+pub mod socket_abuse;
+pub mod event_log;
+pub mod message_search;
+pub mod message_retention;
+pub mod user_erasure;
 
 pub use models::*;
 pub use handlers::*;
@@ -13,7 +90,7 @@ pub use websocket::*;
 pub use db::*;
 pub use errors::*;
 
-use mongodb::Client;
+use mongodb::{options::ClientOptions, Client};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -24,13 +101,66 @@ pub struct AppState {
     pub connections: Arc<RwLock<ConnectionManager>>,
     pub redis: Arc<redis::Client>,
     pub redis_pool: deadpool_redis::Pool,
+    pub room_bridge: room_bridge::BridgeMode,
+    pub ip_geo: ip_geo::IpGeoConfig,
+    pub room_metrics: Arc<room_metrics::RoomMetrics>,
+    // How participants and spectators weigh in room listings
+    pub room_ranking: room_ranking::RankingWeights,
+    // Daily room creations per user and when new rooms go to review
+    pub room_quota: room_quota::RoomQuota,
+    pub fanout: Arc<fanout::LocalFanout>,
+    // The one Redis pub/sub connection every socket listens through
+    pub pubsub: Arc<pubsub::PubSubMultiplexer>,
+    // How room broadcasts reach other instances, from BROADCAST_BACKEND
+    pub broadcast_backend: streams::BroadcastBackend,
+    // Reads the broadcast stream into `pubsub`; only with the Streams backend
+    pub stream_reader: Option<Arc<streams::StreamReader>>,
+    pub send_buffers: Arc<send_buffer::SendBuffers>,
+    pub heartbeat: heartbeat::HeartbeatConfig,
+    // How much history joining sockets get, by room activity
+    pub history: history_page::HistoryPaging,
+    // Bounds on inbound frames for every socket
+    pub frame_limits: frame_limits::FrameLimits,
+    // Tarpitting and IP blocks for sockets that keep breaking the protocol
+    pub tarpit: socket_abuse::TarpitConfig,
+    // Retention of the event log downstream services replay
+    pub event_log: event_log::EventLogConfig,
+    pub delivery_trace: Arc<delivery_trace::DeliveryTracer>,
+    // Publish-to-delivery lag of broadcasts reaching this instance
+    pub pubsub_lag: Arc<pubsub_lag::PubSubLag>,
+    // Users with sockets on this instance, kept online in Redis
+    pub online_users: Arc<user_presence::OnlineUsers>,
+    // Reaction limits and batching, local to this instance
+    pub reactions: Arc<reactions::Reactions>,
+    // Canary cohort routing and its separate metrics
+    pub canary: Arc<canary::Canary>,
+    // Renders the process-wide recorder for /metrics
+    pub metrics: metrics_exporter_prometheus::PrometheusHandle,
+    // None when room topic summaries are off
+    pub topic_summarizer: Option<Arc<dyn topics::Summarizer>>,
+    // Profiles and conversation sync, with a degraded mode while it's down
+    pub user_service: Arc<user_service::UserServiceClient>,
+    // Presigned attachment uploads; None when no bucket is configured
+    pub uploads: Option<Arc<uploads::UploadConfig>>,
+    // Set on SIGTERM; sockets are told to reconnect elsewhere
+    pub shutdown: Arc<shutdown::Shutdown>,
+    // Identifies this process among the instances sharing Redis
+    pub instance_id: String,
+    // When this process started, as listed in the instance registry
+    pub started_at: chrono::DateTime<chrono::Utc>,
 }
 
 impl AppState {
     pub async fn new(mongodb_uri: &str, redis_uri: &str, db_name: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        // Initialize MongoDB
-        let mongo_client = Client::with_uri_str(mongodb_uri).await?;
+        // Initialize MongoDB; fail fast during elections so sends can be queued
+        let mut mongo_options = ClientOptions::parse(mongodb_uri).await?;
+        if mongo_options.server_selection_timeout.is_none() {
+            mongo_options.server_selection_timeout = Some(std::time::Duration::from_secs(5));
+        }
+        let mongo_client = Client::with_options(mongo_options)?;
         let database = mongo_client.database(db_name);
+        let db = Arc::new(MongoDb::new(database.clone()));
+        db.clone().spawn_pending_flush();
 
         // Initialize Redis
         let redis_client = redis::Client::open(redis_uri)?;
@@ -39,15 +169,64 @@ impl AppState {
         let redis_config = deadpool_redis::Config::from_url(redis_uri);
         let redis_pool = redis_config.create_pool(Some(deadpool_redis::Runtime::Tokio1))?;
         
+        let redis_client = Arc::new(redis_client);
+        let instance_id = uuid::Uuid::new_v4().to_string();
+        let broadcast_backend = streams::BroadcastBackend::from_env();
+        let fanout = Arc::new(fanout::LocalFanout::new());
+        fanout.clone().spawn_replay(redis_client.clone(), broadcast_backend);
+        // Pub/sub is always read: DMs and instances still on pub/sub use it
+        let pubsub = Arc::new(pubsub::PubSubMultiplexer::new());
+        pubsub.clone().spawn(redis_client.clone());
+        let canary = Arc::new(canary::Canary::from_env());
+        let stream_reader = canary.config.uses_streams(broadcast_backend).then(|| {
+            let reader = Arc::new(streams::StreamReader::new(&instance_id));
+            reader.clone().spawn(redis_client.clone(), pubsub.clone());
+            reader.clone().spawn_cleanup(redis_client.clone());
+            reader
+        });
+        
+        let delivery_trace = Arc::new(delivery_trace::DeliveryTracer::new(&instance_id));
+        delivery_trace.clone().spawn_flush(redis_pool.clone());
+        
+        let user_service = Arc::new(user_service::UserServiceClient::from_env());
+        user_service.clone().spawn_probe();
+
         // Initialize connection manager
         let connections = Arc::new(RwLock::new(ConnectionManager::new()));
 
         Ok(AppState {
-            db: Arc::new(MongoDb::new(database.clone())),
+            db,
             database,
             connections,
-            redis: Arc::new(redis_client),
+            redis: redis_client,
             redis_pool,
+            room_bridge: room_bridge::BridgeMode::from_env(),
+            ip_geo: ip_geo::IpGeoConfig::from_env(),
+            room_metrics: Arc::new(room_metrics::RoomMetrics::from_env()),
+            room_ranking: room_ranking::RankingWeights::from_env(),
+            room_quota: room_quota::RoomQuota::from_env(),
+            fanout,
+            pubsub,
+            broadcast_backend,
+            stream_reader,
+            send_buffers: Arc::new(send_buffer::SendBuffers::from_env()),
+            heartbeat: heartbeat::HeartbeatConfig::from_env(),
+            history: history_page::HistoryPaging::from_env(),
+            frame_limits: frame_limits::FrameLimits::from_env(),
+            tarpit: socket_abuse::TarpitConfig::from_env(),
+            event_log: event_log::EventLogConfig::from_env(),
+            delivery_trace,
+            pubsub_lag: Arc::new(pubsub_lag::PubSubLag::new()),
+            online_users: Arc::new(user_presence::OnlineUsers::default()),
+            reactions: Arc::new(reactions::Reactions::default()),
+            canary,
+            metrics: service_metrics::install(),
+            topic_summarizer: topics::summarizer_from_env(),
+            user_service,
+            uploads: uploads::UploadConfig::from_env().map(Arc::new),
+            shutdown: Arc::new(shutdown::Shutdown::from_env()),
+            instance_id,
+            started_at: chrono::Utc::now(),
         })
     }
 }
\ No newline at end of file
@@ -1,5 +1,4 @@
 use serde::{Deserialize, Serialize};
-use chrono::{DateTime, Utc};
 
 // Local chat specific message types
 #[derive(Debug, Serialize, Deserialize)]
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use crate::models::{Message, WsMessage};
+
+// Messages in a low data socket's history pages, newest kept
+pub const HISTORY_LIMIT: usize = 20;
+// How long a low data socket's count updates are held and merged
+pub const BATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Drops what a client on a poor connection can do without: the structured
+/// body (the flat fields say the same), per-user reaction lists, attachment
+/// dimensions used to lay out inline previews, and history past the newest
+/// `HISTORY_LIMIT` messages.
+pub fn strip(message: WsMessage) -> WsMessage {
+    match message {
+        WsMessage::NewMessage(message) => WsMessage::NewMessage(strip_message(message)),
+        WsMessage::NeighborMessage { h3_index, message } => {
+            WsMessage::NeighborMessage { h3_index, message: strip_message(message) }
+        }
+        WsMessage::MessageHistory { messages, since } => {
+            let (messages, truncated) = truncate(messages);
+            // A truncated delta would leave a gap, so it replaces the cache
+            WsMessage::MessageHistory { messages, since: if truncated { None } else { since } }
+        }
+        WsMessage::Resumed { session_id, user_count, messages, complete } => {
+            let (messages, truncated) = truncate(messages);
+            WsMessage::Resumed { session_id, user_count, messages, complete: complete && !truncated }
+        }
+        message => message,
+    }
+}
+
+fn strip_message(mut message: Message) -> Message {
+    message.body = None;
+    message.reactions.clear();
+    for attachment in &mut message.attachments {
+        attachment.width = None;
+        attachment.height = None;
+    }
+    message
+}
+
+// Keeps the newest messages of a chronological page
+fn truncate(messages: Vec<Message>) -> (Vec<Message>, bool) {
+    let truncated = messages.len() > HISTORY_LIMIT;
+    let messages = messages
+        .into_iter()
+        .rev()
+        .take(HISTORY_LIMIT)
+        .map(strip_message)
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    (messages, truncated)
+}
+
+// Which update a batched message is, so a newer one replaces it
+fn batch_key(message: &WsMessage) -> Option<(&'static str, &str)> {
+    match message {
+        WsMessage::ReactionsUpdated { message_id, .. } => Some(("reactions", message_id)),
+        WsMessage::RsvpUpdated { message_id, .. } => Some(("rsvp", message_id)),
+        WsMessage::ThreadUpdated { parent_id, .. } => Some(("thread", parent_id)),
+        _ => None,
+    }
+}
+
+pub fn is_batched(message: &WsMessage) -> bool {
+    batch_key(message).is_some()
+}
+
+/// Count updates held for a low data socket. Each carries the full new
+/// totals, so only the latest per message is sent.
+#[derive(Debug, Default)]
+pub struct EventBatch {
+    pending: Vec<WsMessage>,
+}
+
+impl EventBatch {
+    pub fn add(&mut self, message: WsMessage) {
+        let key = batch_key(&message);
+        match self.pending.iter_mut().find(|pending| key.is_some() && batch_key(pending) == key) {
+            Some(pending) => *pending = message,
+            None => self.pending.push(message),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// The held updates, in the order they were first added.
+    pub fn take(&mut self) -> Vec<WsMessage> {
+        std::mem::take(&mut self.pending)
+    }
+}
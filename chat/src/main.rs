@@ -1,50 +1,179 @@
 use axum::{
-    extract::{ws::WebSocket, Extension, Path, Query, State, WebSocketUpgrade},
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
-    Json, Router,
+    routing::{delete, get, patch, post, put},
+    Router,
 };
-use mongodb::{Client, Database};
-use std::{net::SocketAddr, sync::Arc};
-use tokio::sync::RwLock;
 use tower_http::cors::CorsLayer;
-use tracing::{info, error};
+use tracing::{error, info, warn};
 
-use chat_service::{AppState, handlers::*, websocket::*, dm::*};
+use chat_service::{AppState, abuse::*, admin_stats, announcements::*, user_service::user_profile_handler, preferences::*, handlers::*, dm::*, bulk_messages, delivery_trace, event_log, health, instances, message_filters, message_retention, message_search, message_ttl, uploads, user_history, pubsub_lag, dm_keys::*, legal_hold::*, migrations::*, presence, reactions, reports, room_bans, room_markers, room_invites, room_invite_links, room_quota, room_roles, shadow_bans, timeouts, user_blocks, user_erasure, user_presence, service_metrics, telemetry, read_cursors::*, room_cache, topics, room_events::*, rsvp::*, self_check, shared_rooms::*, shutdown, webhooks::*, ws_ticket::*};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tracing_subscriber::fmt::init();
+    let tracer_provider = telemetry::init();
 
     let mongodb_uri = std::env::var("MONGODB_URI")
         .unwrap_or_else(|_| "mongodb://localhost:27017".to_string());
     let redis_uri = std::env::var("REDIS_URI")
         .unwrap_or_else(|_| "redis://localhost:6379".to_string());
     
+    // `--check` validates configuration and dependencies, then exits
+    let check_only = std::env::args().any(|arg| arg == "--check");
+    
     let app_state = AppState::new(&mongodb_uri, &redis_uri, "chat_db").await?;
+    if !check_only {
+        if let Err(e) = app_state.db.ensure_indexes().await {
+            error!("Failed to create MongoDB indexes: {}", e);
+        }
+    }
+    
+    let findings = self_check::run(&app_state).await;
+    for finding in &findings {
+        match finding.severity {
+            self_check::Severity::Error => error!("{}", finding),
+            self_check::Severity::Warning => warn!("{}", finding),
+        }
+    }
+    if self_check::has_errors(&findings) {
+        error!("Self-check failed; fix the errors above before starting the service");
+        std::process::exit(1);
+    }
+    if check_only {
+        info!("Self-check passed with {} warning(s)", findings.len());
+        return Ok(());
+    }
 
+    spawn_scheduler(app_state.clone());
+    presence::spawn_heartbeat(app_state.clone());
+    instances::spawn_heartbeat(app_state.clone());
+    user_presence::spawn_heartbeat(app_state.clone());
+    admin_stats::spawn_publish(app_state.clone());
+    message_ttl::spawn_sweeper(app_state.clone());
+    message_retention::spawn_purge(app_state.clone());
+    user_erasure::spawn_listener(app_state.clone());
+    topics::spawn_topic_summaries(app_state.clone());
+    reactions::spawn_flush(app_state.clone());
+    room_cache::spawn_warm(app_state.clone());
+    service_metrics::spawn_refresh(app_state.clone());
+    
     let app = Router::new()
         // Health check
         .route("/health", get(|| async { "OK" }))
+        .route("/health/live", get(health::live_handler))
+        .route("/health/ready", get(health::ready_handler))
+        .route("/metrics", get(metrics_handler))
+        .route("/metrics/rooms", get(room_metrics_handler))
+        .route("/metrics/fanout", get(fanout_metrics_handler))
+        .route("/metrics/canary", get(canary_metrics_handler))
+        // Server clock, for clients correcting their skew
+        .route("/api/time", get(get_time))
         // WebSocket endpoints
         .route("/ws/:location_id", get(websocket_handler))
+        .route("/ws/hex", get(hex_auto_websocket_handler))
         .route("/ws/hex/:h3_index", get(hex_websocket_handler))
         .route("/ws/dm/:conversation_id", get(dm_websocket_handler))
         // REST endpoints
         .route("/api/messages/:location_id", get(get_messages))
         .route("/api/messages", post(send_message))
+        .route("/api/uploads", post(uploads::create_upload_handler))
+        .route("/api/messages/:message_id/rsvp", put(rsvp_handler))
+        .route("/api/messages/:message_id/rsvps", get(list_rsvps_handler))
+        .route("/api/messages/:message_id/report", post(reports::report_message_handler))
+        .route("/api/rooms", get(list_rooms))
         .route("/api/rooms/:location_id", get(get_room_info))
+        .route("/api/rooms/:location_id/language", put(set_room_language))
+        .route("/api/rooms/:location_id/settings", patch(update_room_settings))
         .route("/api/rooms/:location_id/join", post(join_room))
+        .route("/api/rooms/:location_id/ws-ticket", post(ws_ticket_handler))
+        .route("/api/rooms/:location_id/hex", get(get_room_hex_mapping))
+        .route("/api/hex/active", get(active_hexes))
+        .route("/api/hex/:h3_index/messages", get(get_hex_messages))
+        .route("/api/hex/:h3_index/threads/:message_id", get(get_hex_thread))
+        .route("/api/rooms/:location_id/top", get(get_top_messages))
+        .route("/api/rooms/:location_id/unread", get(get_unread_count))
+        .route("/api/rooms/:location_id/read", put(mark_read_handler))
+        .route("/api/rooms/:location_id/mute", put(mute_room_handler).delete(unmute_room_handler))
+        .route("/api/rooms/:location_id/users", get(presence::room_users_handler))
+        .route("/api/presence/bulk", post(user_presence::bulk_presence_handler))
+        .route("/api/rooms/:location_id/webhooks", get(list_webhooks_handler).post(create_webhook_handler))
+        .route("/api/rooms/:location_id/webhooks/:webhook_id", delete(delete_webhook_handler))
+        .route("/api/rooms/:location_id/events", get(list_events_handler).post(create_event_handler))
+        .route("/api/rooms/:location_id/markers", post(room_markers::create_marker_handler))
+        .route("/api/rooms/:location_id/markers/:marker_id", put(room_markers::update_marker_handler).delete(room_markers::delete_marker_handler))
+        .route("/api/rooms/:location_id/roles", get(room_roles::room_roles_handler))
+        .route("/api/rooms/:location_id/visibility", put(room_invites::set_visibility_handler))
+        .route("/api/rooms/:location_id/invitations", get(room_invites::list_invitations_handler).post(room_invites::invite_user_handler))
+        .route("/api/rooms/:location_id/invitations/:user_id", delete(room_invites::remove_invitation_handler))
+        .route("/api/rooms/:location_id/invites", post(room_invite_links::create_invite_link_handler))
+        .route("/api/rooms/:location_id/invites/:jti", delete(room_invite_links::revoke_invite_link_handler))
+        .route("/api/invites/:token/redeem", post(room_invite_links::redeem_invite_link_handler))
+        .route("/api/search/messages", get(message_search::search_messages_handler))
+        .route("/api/rooms/:location_id/moderators/:user_id", put(room_roles::grant_moderator_handler).delete(room_roles::revoke_moderator_handler))
+        .route("/api/rooms/:location_id/ban", post(room_bans::ban_user_handler))
+        .route("/api/rooms/:location_id/ban/:user_id", delete(room_bans::unban_user_handler))
+        .route("/api/rooms/:location_id/mute", post(timeouts::mute_user_handler))
+        .route("/api/rooms/:location_id/mute/:user_id", delete(timeouts::unmute_user_handler))
+        .route("/api/rooms/:location_id/events/:event_id", delete(cancel_event_handler))
+        .route("/api/rooms/:location_id/events/:event_id/rsvp", post(event_rsvp_handler))
         .route("/api/dm/:conversation_id/messages", get(get_dm_messages_handler))
+        .route("/api/dm/:conversation_id/messages/:message_id", patch(edit_dm_message_handler).delete(delete_dm_message_handler))
+        .route("/api/dm/:conversation_id/key-events", get(key_events_handler))
+        .route("/api/users/me/messages", get(user_history::my_messages_handler))
+        .route("/api/users/me/blocks", get(user_blocks::list_blocks_handler).post(user_blocks::block_user_handler))
+        .route("/api/users/me/blocks/:user_id", delete(user_blocks::unblock_user_handler))
+        .route("/api/users/:user_id/profile", get(user_profile_handler))
+        .route("/api/announcements", get(list_announcements_handler).post(create_announcement_handler))
+        .route("/api/dm/unread", get(dm_unread_handler))
+        .route("/api/dm/conversations", get(list_conversations_handler).post(create_conversation_handler))
+        .route("/api/dm/conversations/:conversation_id", get(get_conversation_handler))
+        .route("/api/dm/:conversation_id/participants", post(add_participant_handler))
+        .route("/api/dm/:conversation_id/participants/:user_id", delete(remove_participant_handler))
+        .route("/api/users/:user_id/shared-rooms", get(shared_rooms_handler))
+        .route("/api/users/:user_id/privacy", put(update_privacy_handler))
+        .route("/api/users/:user_id/location", delete(purge_location_handler))
+        .route("/api/users/:user_id/data", delete(user_erasure::erase_user_data_handler))
+        .route("/api/users/:user_id/preferences", get(get_preferences_handler))
+        // Inbound webhooks (HMAC-signed)
+        .route("/hooks/rooms/:token", post(receive_webhook))
+        // Moderation
+        .route("/api/admin/messages/:message_id/trace", get(delivery_trace::message_trace_handler))
+        .route("/api/admin/pubsub-lag", get(pubsub_lag::pubsub_lag_handler))
+        .route("/api/admin/instances", get(instances::instances_handler))
+        .route("/api/moderation/flagged", get(message_filters::flagged_messages_handler))
+        .route("/api/moderation/flagged/:message_id", delete(message_filters::dismiss_flagged_handler))
+        .route("/api/admin/stats", get(admin_stats::admin_stats_handler))
+        .route("/api/admin/rooms/:location_id/messages/bulk", post(bulk_messages::bulk_messages_handler))
+        .route("/api/admin/reports", get(reports::list_reports_handler))
+        .route("/api/admin/shadow-bans", get(shadow_bans::list_shadow_bans_handler))
+        .route("/api/admin/shadow-bans/:user_id", put(shadow_bans::shadow_ban_handler).delete(shadow_bans::lift_shadow_ban_handler))
+        .route("/api/admin/reports/:report_id/resolve", post(reports::resolve_report_handler))
+        .route("/api/admin/rooms/review", get(room_quota::review_queue_handler))
+        .route("/api/admin/rooms/:location_id/approve", post(room_quota::approve_room_handler))
+        .route("/api/admin/rooms/:location_id/migrate-to-hex", post(migrate_room_to_hex_handler))
+        .route("/api/admin/rooms/:location_id/owner/:user_id", put(room_roles::grant_owner_handler))
+        .route("/api/admin/migrations/:name", get(migration_status_handler).post(start_migration_handler))
+        .route("/api/moderation/users/:user_id/abuse-score", get(get_abuse_score_handler))
+        .route("/api/moderation/users/:user_id/abuse-signals", post(record_signal_handler))
+        // Internal compliance endpoints (X-Internal-Token)
+        .route("/internal/events", get(event_log::event_log_handler))
+        .route("/internal/legal-holds", get(list_holds_handler).post(create_hold_handler))
+        .route("/internal/legal-holds/:hold_id/release", post(release_hold_handler))
+        .route("/internal/legal-holds/:hold_id/messages", get(held_messages_handler))
+        .route("/internal/legal-holds/:hold_id/access-log", get(hold_access_log_handler))
         .layer(CorsLayer::permissive())
-        .with_state(app_state);
+        .with_state(app_state.clone());
 
     let port = std::env::var("PORT").unwrap_or_else(|_| "3001".to_string());
     let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
     info!("Chat service listening on {}", listener.local_addr()?);
     
-    axum::serve(listener, app).await?;
+    // On SIGTERM, sockets are closed before the server stops, then queued
+    // writes are flushed
+    let served = axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>())
+        .with_graceful_shutdown(shutdown::drain(app_state.clone()))
+        .await;
+    shutdown::finish(&app_state).await;
+    telemetry::shutdown(tracer_provider);
+    served?;
 
     Ok(())
 }
\ No newline at end of file
@@ -0,0 +1,100 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{
+    models::{Message, MessageKind},
+    uploads::Attachment,
+};
+
+/// Bumped when an existing kind's fields change in a way older instances
+/// can't read. New kinds don't need a bump: older instances read them as
+/// `Unknown`.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// What a message says, by kind. Stored with every new message next to the
+/// flat `content`/`kind`/`event` fields, which stay for clients and for
+/// instances that predate the body.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MessageBody {
+    Text {
+        text: String,
+    },
+    Media {
+        #[serde(default)]
+        caption: String,
+        attachments: Vec<Attachment>,
+    },
+    Poll {
+        question: String,
+        #[serde(default)]
+        options: Vec<String>,
+    },
+    Event {
+        title: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        starts_at: Option<DateTime<Utc>>,
+    },
+    System {
+        text: String,
+    },
+    // A kind from a newer instance, or fields this instance can't read
+    #[serde(other)]
+    Unknown,
+}
+
+impl MessageBody {
+    /// The body of a message that was stored without one.
+    pub fn of(message: &Message) -> Self {
+        match message.kind {
+            MessageKind::Event => MessageBody::Event {
+                title: message.event.as_ref().map(|event| event.title.clone()).unwrap_or_default(),
+                starts_at: message.event.as_ref().and_then(|event| event.starts_at),
+            },
+            MessageKind::Announcement => MessageBody::System { text: message.content.clone() },
+            MessageKind::Other => MessageBody::Unknown,
+            MessageKind::Text if !message.attachments.is_empty() => MessageBody::Media {
+                caption: message.content.clone(),
+                attachments: message.attachments.clone(),
+            },
+            MessageKind::Text => MessageBody::Text { text: message.content.clone() },
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        *self != MessageBody::Unknown
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionedBody {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub body: MessageBody,
+}
+
+impl VersionedBody {
+    pub fn new(body: MessageBody) -> Self {
+        VersionedBody { schema_version: SCHEMA_VERSION, body }
+    }
+
+    /// Reads a stored body, falling back to `Unknown` when it is from a
+    /// newer schema or doesn't parse, rather than failing the whole message.
+    pub fn from_value(value: serde_json::Value) -> Self {
+        let schema_version = value
+            .get("schema_version")
+            .and_then(|version| version.as_u64())
+            .unwrap_or(0) as u32;
+        let body = match serde_json::from_value::<VersionedBody>(value) {
+            Ok(versioned) if schema_version <= SCHEMA_VERSION => versioned.body,
+            _ => MessageBody::Unknown,
+        };
+        VersionedBody { schema_version, body }
+    }
+}
+
+/// Deserializes `Message::body` without ever failing the message.
+pub fn tolerant<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<VersionedBody>, D::Error> {
+    let value = Option::<serde_json::Value>::deserialize(deserializer)?;
+    Ok(value.map(VersionedBody::from_value))
+}
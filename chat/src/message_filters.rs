@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::FindOptions,
+    Collection,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{error, warn};
+
+use crate::{
+    auth::AuthUser,
+    content_filter::{filter_content, ContentRating},
+    models::{Message, RoomSettings},
+    AppError, AppState,
+};
+
+const MAX_BLOCKLIST_RULES: usize = 50;
+const MAX_PATTERN_CHARS: usize = 200;
+// Keeps rule patterns from compiling into huge automata
+const PATTERN_SIZE_LIMIT: usize = 1 << 16;
+const MAX_COMPILED_PATTERNS: usize = 1000;
+const LINK_REMOVED: &str = "[link removed]";
+const DEFAULT_QUEUE_LIMIT: i64 = 50;
+const MAX_QUEUE_LIMIT: i64 = 200;
+
+/// What a room rule does with a message it matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    // Refuse the message
+    #[default]
+    Reject,
+    // Post it, and queue it for moderators
+    Flag,
+    // Post it with the matching text blanked out
+    Mask,
+}
+
+/// A filter's verdict on a message's content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterOutcome {
+    Pass,
+    // The content to post instead
+    Mask(String),
+    // Why moderators should look at it
+    Flag(String),
+    // Why the sender's message was refused
+    Reject(String),
+}
+
+/// One stage of the pipeline messages go through before they are stored.
+pub trait MessageFilter: Send + Sync {
+    fn check(&self, content: &str) -> FilterOutcome;
+}
+
+/// Masks language the room's rating doesn't allow.
+pub struct Profanity(pub ContentRating);
+
+impl MessageFilter for Profanity {
+    fn check(&self, content: &str) -> FilterOutcome {
+        match filter_content(content, self.0) {
+            (_, 0) => FilterOutcome::Pass,
+            (filtered, _) => FilterOutcome::Mask(filtered),
+        }
+    }
+}
+
+/// A room's blocked pattern.
+pub struct Blocklist {
+    pub pattern: Regex,
+    pub action: FilterAction,
+}
+
+impl MessageFilter for Blocklist {
+    fn check(&self, content: &str) -> FilterOutcome {
+        if !self.pattern.is_match(content) {
+            return FilterOutcome::Pass;
+        }
+        match self.action {
+            FilterAction::Reject => FilterOutcome::Reject("Message contains blocked content".to_string()),
+            FilterAction::Flag => FilterOutcome::Flag(format!("matched blocked pattern {}", self.pattern.as_str())),
+            FilterAction::Mask => FilterOutcome::Mask(
+                self.pattern
+                    .replace_all(content, |caps: &regex::Captures| "*".repeat(caps[0].chars().count()))
+                    .into_owned(),
+            ),
+        }
+    }
+}
+
+/// Holds messages to at most `max` links.
+pub struct MaxUrls {
+    pub max: usize,
+    pub action: FilterAction,
+}
+
+fn is_link(word: &str) -> bool {
+    let word = word.to_lowercase();
+    word.starts_with("http://") || word.starts_with("https://") || word.starts_with("www.")
+}
+
+impl MessageFilter for MaxUrls {
+    fn check(&self, content: &str) -> FilterOutcome {
+        let links = content.split_whitespace().filter(|word| is_link(word)).count();
+        if links <= self.max {
+            return FilterOutcome::Pass;
+        }
+        match self.action {
+            FilterAction::Reject => FilterOutcome::Reject(format!("Messages may contain at most {} links", self.max)),
+            FilterAction::Flag => FilterOutcome::Flag(format!("{} links, more than {}", links, self.max)),
+            FilterAction::Mask => {
+                // Keeps the first `max` links and the text's spacing
+                let mut kept = 0;
+                let mut masked = String::with_capacity(content.len());
+                let mut rest = content;
+                while let Some(start) = rest.find(|c: char| !c.is_whitespace()) {
+                    masked.push_str(&rest[..start]);
+                    rest = &rest[start..];
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    let word = &rest[..end];
+                    if is_link(word) && kept == self.max {
+                        masked.push_str(LINK_REMOVED);
+                    } else {
+                        kept += is_link(word) as usize;
+                        masked.push_str(word);
+                    }
+                    rest = &rest[end..];
+                }
+                masked.push_str(rest);
+                FilterOutcome::Mask(masked)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlocklistRule {
+    pub pattern: String,
+    #[serde(default)]
+    pub action: FilterAction,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UrlRule {
+    pub max: u32,
+    #[serde(default)]
+    pub action: FilterAction,
+}
+
+/// A room's own filters, run after the content rating's.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FilterRules {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub blocklist: Vec<BlocklistRule>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_urls: Option<UrlRule>,
+}
+
+fn compile(pattern: &str) -> Result<Regex, regex::Error> {
+    regex::RegexBuilder::new(pattern).case_insensitive(true).size_limit(PATTERN_SIZE_LIMIT).build()
+}
+
+// Patterns are compiled once per instance rather than per message
+fn compiled(pattern: &str) -> Option<Regex> {
+    static COMPILED: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+    let mut compiled = COMPILED.get_or_init(Default::default).lock().unwrap();
+    if let Some(regex) = compiled.get(pattern) {
+        return Some(regex.clone());
+    }
+    match compile(pattern) {
+        Ok(regex) => {
+            if compiled.len() >= MAX_COMPILED_PATTERNS {
+                compiled.clear();
+            }
+            compiled.insert(pattern.to_string(), regex.clone());
+            Some(regex)
+        }
+        Err(e) => {
+            warn!("Skipping blocklist pattern {:?}: {}", pattern, e);
+            None
+        }
+    }
+}
+
+impl FilterRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.blocklist.len() > MAX_BLOCKLIST_RULES {
+            return Err(format!("At most {} blocklist rules", MAX_BLOCKLIST_RULES));
+        }
+        for rule in &self.blocklist {
+            if rule.pattern.is_empty() || rule.pattern.chars().count() > MAX_PATTERN_CHARS {
+                return Err(format!("Blocklist patterns must be 1-{} characters", MAX_PATTERN_CHARS));
+            }
+            compile(&rule.pattern).map_err(|e| format!("Invalid blocklist pattern {:?}: {}", rule.pattern, e))?;
+        }
+        Ok(())
+    }
+}
+
+/// The filters a room's messages go through, in order.
+pub fn pipeline(rating: ContentRating, rules: &FilterRules) -> Vec<Box<dyn MessageFilter>> {
+    let mut filters: Vec<Box<dyn MessageFilter>> = vec![Box::new(Profanity(rating))];
+    for rule in &rules.blocklist {
+        if let Some(pattern) = compiled(&rule.pattern) {
+            filters.push(Box::new(Blocklist { pattern, action: rule.action }));
+        }
+    }
+    if let Some(rule) = &rules.max_urls {
+        filters.push(Box::new(MaxUrls { max: rule.max as usize, action: rule.action }));
+    }
+    filters
+}
+
+/// What the pipeline did to a message it let through.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verdict {
+    pub masked: bool,
+    // Why the message goes to the flagged queue, if it does
+    pub flags: Vec<String>,
+}
+
+impl Verdict {
+    /// Whether the sender tripped a filter, for abuse signals.
+    pub fn hit(&self) -> bool {
+        self.masked || !self.flags.is_empty()
+    }
+}
+
+/// Runs the message through the filters, masking its content as they ask.
+/// Stops at the first rejection and returns its reason.
+pub fn run(filters: &[Box<dyn MessageFilter>], message: &mut Message) -> Result<Verdict, String> {
+    let mut verdict = Verdict::default();
+    for filter in filters {
+        match filter.check(&message.content) {
+            FilterOutcome::Pass => {}
+            FilterOutcome::Mask(content) => {
+                message.content = content;
+                verdict.masked = true;
+            }
+            FilterOutcome::Flag(reason) => verdict.flags.push(reason),
+            FilterOutcome::Reject(reason) => return Err(reason),
+        }
+    }
+    Ok(verdict)
+}
+
+/// Runs a room message through its room's pipeline.
+pub fn screen(message: &mut Message, settings: &RoomSettings) -> Result<Verdict, String> {
+    run(&pipeline(settings.content_rating, &settings.filters), message)
+}
+
+/// A posted message a room rule flagged, waiting for a moderator.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FlaggedMessage {
+    #[serde(rename = "_id")]
+    pub message_id: ObjectId,
+    pub room_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub content: String,
+    pub reasons: Vec<String>,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub flagged_at: DateTime<Utc>,
+}
+
+fn flagged(state: &AppState) -> Collection<FlaggedMessage> {
+    state.database.collection("flagged_messages")
+}
+
+/// Queues a stored message that was flagged. Failures are logged; the
+/// message stays posted either way.
+pub async fn queue_flagged(state: &AppState, message: &Message, reasons: &[String]) {
+    let Some(message_id) = message.id else {
+        return;
+    };
+    let entry = FlaggedMessage {
+        message_id,
+        room_id: message.room_id.clone(),
+        user_id: message.user_id.clone(),
+        username: message.username.clone(),
+        content: message.content.clone(),
+        reasons: reasons.to_vec(),
+        flagged_at: Utc::now(),
+    };
+    if let Err(e) = flagged(state).insert_one(&entry, None).await {
+        error!("Failed to queue flagged message {}: {}", message_id, e);
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FlaggedQuery {
+    pub room_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FlaggedResponse {
+    pub messages: Vec<FlaggedMessage>,
+}
+
+// GET /api/moderation/flagged?room_id=&limit= - flagged messages waiting for
+// review, oldest first; moderators only
+pub async fn flagged_messages_handler(
+    Query(query): Query<FlaggedQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<FlaggedResponse>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_QUEUE_LIMIT).clamp(1, MAX_QUEUE_LIMIT);
+    let filter = match &query.room_id {
+        Some(room_id) => doc! { "room_id": room_id },
+        None => doc! {},
+    };
+    let options = FindOptions::builder().sort(doc! { "flagged_at": 1 }).limit(limit).build();
+    let messages = flagged(&state).find(filter, options).await?.try_collect().await?;
+    Ok(Json(FlaggedResponse { messages }))
+}
+
+// DELETE /api/moderation/flagged/:message_id - take a reviewed message off
+// the queue; deleting the message itself is separate
+pub async fn dismiss_flagged_handler(
+    Path(message_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let message_id = ObjectId::parse_str(&message_id).map_err(|_| AppError::BadRequest("Invalid message id".to_string()))?;
+    let result = flagged(&state).delete_one(doc! { "_id": message_id }, None).await?;
+    if result.deleted_count == 0 {
+        return Err(AppError::NotFound);
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -0,0 +1,118 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    options::FindOptions,
+};
+use tracing::{error, info};
+
+use crate::{models::RoomSettings, shared_rooms::is_location_room, AppState};
+
+pub const MAX_RETENTION_DAYS: u32 = 3650;
+pub const DEFAULT_LOCATION_RETENTION_DAYS: u32 = 30;
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+// Only one instance purges per interval
+const PURGE_LOCK_KEY: &str = "message_retention:purge";
+
+/// Checks a room's retention; `0` keeps its messages forever.
+pub fn validate_retention(days: u32) -> Result<u32, String> {
+    if days > MAX_RETENTION_DAYS {
+        return Err(format!("retention_days must be at most {}", MAX_RETENTION_DAYS));
+    }
+    Ok(days)
+}
+
+/// How long rooms that don't set `retention_days` keep their messages.
+/// Location rooms come and go with the people nearby, so by default they
+/// keep a month; other rooms keep everything.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    // None keeps location room messages forever
+    pub location_room_days: Option<u32>,
+}
+
+impl RetentionPolicy {
+    pub fn from_env() -> Self {
+        let days = std::env::var("LOCATION_ROOM_RETENTION_DAYS")
+            .ok()
+            .and_then(|value| value.parse::<u32>().ok())
+            .unwrap_or(DEFAULT_LOCATION_RETENTION_DAYS);
+        RetentionPolicy { location_room_days: Some(days.min(MAX_RETENTION_DAYS)).filter(|days| *days > 0) }
+    }
+
+    /// How many days the room's messages are kept, None for forever.
+    pub fn retention_days(&self, room_id: &str, settings: &RoomSettings) -> Option<u32> {
+        match settings.retention_days {
+            Some(days) => Some(days).filter(|days| *days > 0),
+            None if is_location_room(room_id) => self.location_room_days,
+            None => None,
+        }
+    }
+}
+
+/// Messages sent before this are past the room's retention.
+pub fn cutoff(now: DateTime<Utc>, days: u32) -> DateTime<Utc> {
+    now - chrono::Duration::days(days as i64)
+}
+
+async fn claim_purge(state: &AppState) -> bool {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        // Without Redis every instance purges; deletes are safe to repeat
+        return true;
+    };
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(PURGE_LOCK_KEY)
+        .arg(&state.instance_id)
+        .arg("NX")
+        .arg("PX")
+        .arg(PURGE_INTERVAL.as_millis() as u64 - 1000)
+        .query_async(&mut conn)
+        .await;
+    !matches!(claimed, Ok(None))
+}
+
+// Goes room by room so each room's own retention applies. Held messages are
+// kept past it
+async fn purge(state: &AppState, policy: RetentionPolicy, now: DateTime<Utc>) -> mongodb::error::Result<u64> {
+    let exclusion: Document = crate::legal_hold::retention_exclusion(&state.database).await?;
+    let options = FindOptions::builder().projection(doc! { "_id": 1, "settings": 1 }).build();
+    let mut rooms = state.database.collection::<Document>("rooms").find(doc! {}, options).await?;
+    let mut deleted = 0;
+    while let Some(room) = rooms.try_next().await? {
+        let Ok(room_id) = room.get_str("_id") else {
+            continue;
+        };
+        let settings: RoomSettings = room
+            .get_document("settings")
+            .ok()
+            .and_then(|settings| mongodb::bson::from_document(settings.clone()).ok())
+            .unwrap_or_default();
+        let Some(days) = policy.retention_days(room_id, &settings) else {
+            continue;
+        };
+        let expired = doc! { "room_id": room_id, "timestamp": { "$lt": mongodb::bson::DateTime::from_chrono(cutoff(now, days)) } };
+        deleted += crate::message_ttl::delete_matching(state, doc! { "$and": [&exclusion, expired] }).await?;
+    }
+    Ok(deleted)
+}
+
+/// Deletes messages past their room's retention every interval.
+pub fn spawn_purge(state: AppState) -> tokio::task::JoinHandle<()> {
+    let policy = RetentionPolicy::from_env();
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            tick.tick().await;
+            if !claim_purge(&state).await {
+                continue;
+            }
+            match purge(&state, policy, Utc::now()).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("Deleted {} messages past their room's retention", deleted),
+                Err(e) => error!("Failed to purge messages past retention: {}", e),
+            }
+        }
+    })
+}
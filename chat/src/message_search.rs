@@ -0,0 +1,178 @@
+use std::collections::BTreeSet;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::FindOptions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, models::Message, AppError, AppState};
+
+pub const DEFAULT_SEARCH_LIMIT: i64 = 20;
+pub const MAX_SEARCH_LIMIT: i64 = 50;
+pub const MAX_QUERY_CHARS: usize = 200;
+// Deep pages of a relevance sort skip over everything before them
+pub const MAX_SEARCH_PAGE: u64 = 20;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchQuery {
+    #[serde(default)]
+    pub q: String,
+    pub room_id: Option<String>,
+    pub user_id: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    // 1-based
+    pub page: Option<u64>,
+    pub limit: Option<i64>,
+}
+
+impl SearchQuery {
+    pub fn validate(&self) -> Result<(), String> {
+        let q = self.q.trim();
+        if q.is_empty() {
+            return Err("q is required".to_string());
+        }
+        if q.chars().count() > MAX_QUERY_CHARS {
+            return Err(format!("q must be at most {} characters", MAX_QUERY_CHARS));
+        }
+        if let (Some(from), Some(to)) = (self.from, self.to) {
+            if from > to {
+                return Err("`from` must not be after `to`".to_string());
+            }
+        }
+        if self.page.is_some_and(|page| page == 0 || page > MAX_SEARCH_PAGE) {
+            return Err(format!("page must be between 1 and {}", MAX_SEARCH_PAGE));
+        }
+        Ok(())
+    }
+
+    pub fn page(&self) -> u64 {
+        self.page.unwrap_or(1)
+    }
+
+    pub fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).clamp(1, MAX_SEARCH_LIMIT)
+    }
+
+    /// The text search over `rooms` as `viewer` sees them, narrowed by the
+    /// query's filters.
+    pub fn filter(&self, rooms: &[String], viewer: &str) -> Document {
+        let mut filter = doc! {
+            "$text": { "$search": self.q.trim() },
+            "room_id": { "$in": rooms },
+            "deleted": false,
+        };
+        filter.extend(crate::shadow_bans::visible_to("user_id", Some(viewer)));
+        if let Some(user_id) = &self.user_id {
+            filter.insert("user_id", user_id);
+        }
+        let mut timestamp = Document::new();
+        if let Some(from) = self.from {
+            timestamp.insert("$gte", bson::DateTime::from_millis(from.timestamp_millis()));
+        }
+        if let Some(to) = self.to {
+            timestamp.insert("$lte", bson::DateTime::from_millis(to.timestamp_millis()));
+        }
+        if !timestamp.is_empty() {
+            filter.insert("timestamp", timestamp);
+        }
+        filter
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+    pub message: Message,
+    // MongoDB's text score; higher is more relevant
+    pub score: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    pub page: u64,
+    pub has_more: bool,
+}
+
+/// The rooms the user belongs to: rooms they're a member of and rooms
+/// they've had open, less those they couldn't join now, private rooms they
+/// aren't in and rooms they're banned from.
+pub async fn rooms_of(state: &AppState, user: &AuthUser) -> Result<Vec<String>, AppError> {
+    let mut candidates: BTreeSet<String> = crate::read_cursors::rooms_read_by(state, &user.user_id).await?.into_iter().collect();
+    candidates.extend(crate::room_invites::memberships_of(state, &user.user_id).await?);
+    let mut rooms = Vec::with_capacity(candidates.len());
+    for room_id in candidates {
+        let Some(room) = state.db.get_room(&room_id).await? else {
+            continue;
+        };
+        if crate::room_invites::require_access(state, &room, Some(user)).await.is_err() {
+            continue;
+        }
+        if crate::room_bans::find_ban(state, &room_id, &user.user_id).await.is_some() {
+            continue;
+        }
+        rooms.push(room_id);
+    }
+    Ok(rooms)
+}
+
+// GET /api/search/messages?q=&room_id=&user_id=&from=&to=&page=&limit= -
+// messages matching `q` in the caller's rooms, most relevant first
+pub async fn search_messages_handler(
+    Query(query): Query<SearchQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<SearchResponse>, AppError> {
+    query.validate().map_err(AppError::BadRequest)?;
+    let rooms = match &query.room_id {
+        // Service moderators can search any room they name
+        Some(room_id) if user.is_moderator() => vec![room_id.clone()],
+        Some(room_id) => {
+            if !rooms_of(&state, &user).await?.contains(room_id) {
+                return Err(AppError::Forbidden);
+            }
+            vec![room_id.clone()]
+        }
+        None => rooms_of(&state, &user).await?,
+    };
+    let page = query.page();
+    if rooms.is_empty() {
+        return Ok(Json(SearchResponse { results: Vec::new(), page, has_more: false }));
+    }
+
+    let limit = query.limit();
+    // One extra tells whether there's another page
+    let options = FindOptions::builder()
+        .projection(doc! { "score": { "$meta": "textScore" } })
+        .sort(doc! { "score": { "$meta": "textScore" }, "timestamp": -1 })
+        .skip((page - 1) * limit as u64)
+        .limit(limit + 1)
+        .build();
+    let documents: Vec<Document> = state
+        .database
+        .collection::<Document>("messages")
+        .find(query.filter(&rooms, &user.user_id), options)
+        .await?
+        .try_collect()
+        .await?;
+
+    let has_more = documents.len() > limit as usize;
+    let results = documents
+        .into_iter()
+        .take(limit as usize)
+        .filter_map(|mut document| {
+            let score = document.remove("score").and_then(|score| score.as_f64()).unwrap_or_default();
+            let mut message = bson::from_document::<Message>(document).ok()?;
+            message.shadow = false;
+            Some(SearchResult { message, score })
+        })
+        .collect();
+    Ok(Json(SearchResponse { results, page, has_more }))
+}
@@ -0,0 +1,184 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId, Document},
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{event_log::LogEvent, models::{Message, WsMessage}, user_history::HistorySource, websocket::publish_to_room, AppState};
+
+pub const MIN_TTL_SECONDS: u32 = 60;
+pub const MAX_TTL_SECONDS: u32 = 7 * 24 * 3600;
+// How long before deletion rooms hear that a message is going
+pub const EXPIRING_SOON_WINDOW: Duration = Duration::from_secs(30);
+const SWEEP_INTERVAL: Duration = Duration::from_secs(10);
+// Only one instance sweeps per interval
+const SWEEP_LOCK_KEY: &str = "message_ttl:sweep";
+const SWEEP_BATCH_SIZE: i64 = 500;
+
+/// Checks a room's message lifetime; `0` turns expiry off.
+pub fn validate_ttl(ttl_seconds: u32) -> Result<Option<u32>, String> {
+    match ttl_seconds {
+        0 => Ok(None),
+        ttl if (MIN_TTL_SECONDS..=MAX_TTL_SECONDS).contains(&ttl) => Ok(Some(ttl)),
+        _ => Err(format!("message_ttl_seconds must be 0 or between {} and {}", MIN_TTL_SECONDS, MAX_TTL_SECONDS)),
+    }
+}
+
+/// Sets when a message in a room with a message lifetime is deleted.
+pub fn stamp(message: &mut Message, ttl_seconds: Option<u32>) {
+    message.expires_at = ttl_seconds.map(|ttl| message.timestamp + chrono::Duration::seconds(ttl as i64));
+}
+
+/// A message about to be deleted, as sent in `ExpiringSoon`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExpiringMessage {
+    pub message_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Groups expiring messages by room, soonest first within each room.
+pub fn by_room(messages: &[Message]) -> BTreeMap<String, Vec<ExpiringMessage>> {
+    let mut rooms: BTreeMap<String, Vec<ExpiringMessage>> = BTreeMap::new();
+    for message in messages {
+        let (Some(id), Some(expires_at)) = (message.id, message.expires_at) else {
+            continue;
+        };
+        rooms
+            .entry(message.room_id.clone())
+            .or_default()
+            .push(ExpiringMessage { message_id: id.to_hex(), expires_at });
+    }
+    for expiring in rooms.values_mut() {
+        expiring.sort_by_key(|message| message.expires_at);
+    }
+    rooms
+}
+
+/// Stores `expires_at` as a BSON date so the sweeper can range over it.
+pub(crate) mod bson_datetime_option {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &Option<DateTime<Utc>>, serializer: S) -> Result<S::Ok, S::Error> {
+        value.map(mongodb::bson::DateTime::from_chrono).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Ok(Option::<mongodb::bson::DateTime>::deserialize(deserializer)?.map(|value| value.to_chrono()))
+    }
+}
+
+fn messages(state: &AppState) -> Collection<Message> {
+    state.database.collection("messages")
+}
+
+fn bson_date(at: DateTime<Utc>) -> mongodb::bson::DateTime {
+    mongodb::bson::DateTime::from_chrono(at)
+}
+
+async fn claim_sweep(state: &AppState) -> bool {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        // Without Redis every instance sweeps; both steps are safe to repeat
+        return true;
+    };
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(SWEEP_LOCK_KEY)
+        .arg(&state.instance_id)
+        .arg("NX")
+        .arg("PX")
+        .arg(SWEEP_INTERVAL.as_millis() as u64 - 500)
+        .query_async(&mut conn)
+        .await;
+    !matches!(claimed, Ok(None))
+}
+
+// Warns rooms about messages expiring within the window, once each
+async fn announce_expiring(state: &AppState, now: DateTime<Utc>) -> mongodb::error::Result<usize> {
+    let until = now + chrono::Duration::from_std(EXPIRING_SOON_WINDOW).unwrap_or_default();
+    let options = FindOptions::builder().sort(doc! { "expires_at": 1 }).limit(SWEEP_BATCH_SIZE).build();
+    let expiring: Vec<Message> = messages(state)
+        .find(doc! { "expires_at": { "$lte": bson_date(until) }, "expiry_announced": { "$ne": true } }, options)
+        .await?
+        .try_collect()
+        .await?;
+    if expiring.is_empty() {
+        return Ok(0);
+    }
+    let ids: Vec<ObjectId> = expiring.iter().filter_map(|message| message.id).collect();
+    messages(state)
+        .update_many(doc! { "_id": { "$in": &ids } }, doc! { "$set": { "expiry_announced": true } }, None)
+        .await?;
+    for (room_id, messages) in by_room(&expiring) {
+        publish_to_room(state, &room_id, WsMessage::ExpiringSoon { room_id: room_id.clone(), messages }).await;
+    }
+    Ok(ids.len())
+}
+
+// Held messages are kept past their expiry
+async fn delete_expired(state: &AppState, now: DateTime<Utc>) -> mongodb::error::Result<u64> {
+    let mut filter: Document = crate::legal_hold::retention_exclusion(&state.database).await?;
+    filter.insert("expires_at", doc! { "$lte": bson_date(now) });
+    delete_matching(state, filter).await
+}
+
+/// Deletes the room messages matching `filter` in batches of ids, which are
+/// written to the event log. Callers AND in the legal hold exclusion.
+pub(crate) async fn delete_matching(state: &AppState, filter: Document) -> mongodb::error::Result<u64> {
+    let options = FindOptions::builder().projection(doc! { "_id": 1, "room_id": 1 }).limit(SWEEP_BATCH_SIZE).build();
+    let mut deleted = 0;
+    loop {
+        let expired: Vec<Document> = state
+            .database
+            .collection::<Document>("messages")
+            .find(filter.clone(), options.clone())
+            .await?
+            .try_collect()
+            .await?;
+        let mut rooms: BTreeMap<String, Vec<ObjectId>> = BTreeMap::new();
+        for message in &expired {
+            if let (Ok(id), Ok(room_id)) = (message.get_object_id("_id"), message.get_str("room_id")) {
+                rooms.entry(room_id.to_string()).or_default().push(id);
+            }
+        }
+        for (room_id, ids) in rooms {
+            deleted += messages(state).delete_many(doc! { "_id": { "$in": &ids } }, None).await?.deleted_count;
+            crate::event_log::append(state, LogEvent::MessageDeleted {
+                source: HistorySource::Room,
+                room_id,
+                message_ids: ids.iter().map(|id| id.to_hex()).collect(),
+            }).await;
+        }
+        if (expired.len() as i64) < SWEEP_BATCH_SIZE {
+            return Ok(deleted);
+        }
+    }
+}
+
+/// Announces and deletes expiring messages every interval.
+pub fn spawn_sweeper(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            tick.tick().await;
+            if !claim_sweep(&state).await {
+                continue;
+            }
+            let now = Utc::now();
+            if let Err(e) = announce_expiring(&state, now).await {
+                error!("Failed to announce expiring messages: {}", e);
+            }
+            match delete_expired(&state, now).await {
+                Ok(0) => {}
+                Ok(deleted) => info!("Deleted {} expired messages", deleted),
+                Err(e) => error!("Failed to delete expired messages: {}", e),
+            }
+        }
+    })
+}
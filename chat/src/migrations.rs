@@ -0,0 +1,299 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    error::{ErrorKind, WriteFailure},
+    options::{FindOneAndUpdateOptions, FindOptions, ReturnDocument},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    auth::AuthUser,
+    identity::{known_users, KnownUser},
+    AppError, AppState,
+};
+
+// Documents fixed per batch; progress is recorded after each one
+const BATCH_SIZE: i64 = 1000;
+// A run that hasn't reported progress for this long is assumed to have died
+// with its instance and may be started again
+const STALE_RUN_MINUTES: i64 = 10;
+
+/// Data migrations that admins can run against a live database. Every
+/// migration must be safe to run again, since a restarted run starts over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Migration {
+    // Adds fields that old message documents predate
+    BackfillMessageFields,
+    // Re-attributes messages to the username their author authenticated with
+    FixMessageUsernames,
+}
+
+impl Migration {
+    pub const ALL: [Migration; 2] = [Migration::BackfillMessageFields, Migration::FixMessageUsernames];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Migration::BackfillMessageFields => "backfill_message_fields",
+            Migration::FixMessageUsernames => "fix_message_usernames",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|migration| migration.name() == name)
+    }
+
+    async fn run(self, state: &AppState, progress: &Progress<'_>) -> mongodb::error::Result<()> {
+        match self {
+            Migration::BackfillMessageFields => backfill_message_fields(state, progress).await,
+            Migration::FixMessageUsernames => fix_message_usernames(state, progress).await,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// The latest run of a migration.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationRun {
+    #[serde(rename = "_id")]
+    pub name: String,
+    pub status: MigrationStatus,
+    pub started_by: String,
+    // Documents looked at and documents changed so far
+    pub processed: u64,
+    pub modified: u64,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub started_at: DateTime<Utc>,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn runs(state: &AppState) -> Collection<MigrationRun> {
+    state.database.collection("migrations")
+}
+
+fn now_bson() -> bson::DateTime {
+    bson::DateTime::from_millis(Utc::now().timestamp_millis())
+}
+
+struct Progress<'a> {
+    state: &'a AppState,
+    name: &'static str,
+}
+
+impl Progress<'_> {
+    async fn record(&self, processed: u64, modified: u64) -> mongodb::error::Result<()> {
+        runs(self.state)
+            .update_one(
+                doc! { "_id": self.name },
+                doc! {
+                    "$inc": { "processed": processed as i64, "modified": modified as i64 },
+                    "$set": { "updated_at": now_bson() },
+                },
+                None,
+            )
+            .await?;
+        info!("Migration {}: {} more documents processed, {} modified", self.name, processed, modified);
+        Ok(())
+    }
+}
+
+/// Fields old message documents may lack, and the value they default to.
+/// New non-optional `Message` fields belong here.
+pub fn message_field_defaults() -> Vec<(&'static str, Bson)> {
+    vec![
+        ("reactions", Bson::Array(vec![])),
+        ("deleted", Bson::Boolean(false)),
+    ]
+}
+
+// Ids of the next batch of messages matching `filter`
+async fn next_batch(messages: &Collection<Document>, filter: Document) -> mongodb::error::Result<Vec<Bson>> {
+    let options = FindOptions::builder().projection(doc! { "_id": 1 }).limit(BATCH_SIZE).build();
+    let docs: Vec<Document> = messages.find(filter, options).await?.try_collect().await?;
+    Ok(docs.into_iter().filter_map(|doc| doc.get("_id").cloned()).collect())
+}
+
+async fn backfill_message_fields(state: &AppState, progress: &Progress<'_>) -> mongodb::error::Result<()> {
+    let messages: Collection<Document> = state.database.collection("messages");
+
+    for (field, default) in message_field_defaults() {
+        loop {
+            let missing = doc! { field: { "$exists": false } };
+            let ids = next_batch(&messages, missing.clone()).await?;
+            if ids.is_empty() {
+                break;
+            }
+            let mut filter = missing;
+            filter.insert("_id", doc! { "$in": &ids });
+            let result = messages.update_many(filter, doc! { "$set": { field: default.clone() } }, None).await?;
+            progress.record(ids.len() as u64, result.modified_count).await?;
+        }
+    }
+
+    // Messages without a timestamp take the creation time of their ObjectId
+    loop {
+        let missing = doc! { "timestamp": { "$exists": false } };
+        let ids = next_batch(&messages, missing.clone()).await?;
+        if ids.is_empty() {
+            break;
+        }
+        let mut filter = missing;
+        filter.insert("_id", doc! { "$in": &ids });
+        let from_id = vec![doc! { "$set": { "timestamp": { "$toDate": "$_id" } } }];
+        let result = messages.update_many(filter, from_id, None).await?;
+        progress.record(ids.len() as u64, result.modified_count).await?;
+    }
+    Ok(())
+}
+
+// Users with an authenticated username are taken in batches, by id. Each
+// user's messages under any other name are renamed.
+async fn fix_message_usernames(state: &AppState, progress: &Progress<'_>) -> mongodb::error::Result<()> {
+    let messages: Collection<Document> = state.database.collection("messages");
+    let mut after: Option<String> = None;
+    loop {
+        let filter = match &after {
+            Some(user_id) => doc! { "_id": { "$gt": user_id } },
+            None => doc! {},
+        };
+        let options = FindOptions::builder().sort(doc! { "_id": 1 }).limit(BATCH_SIZE).build();
+        let users: Vec<KnownUser> = known_users(state).find(filter, options).await?.try_collect().await?;
+        let Some(last) = users.last() else {
+            break;
+        };
+        after = Some(last.user_id.clone());
+
+        let mut modified = 0;
+        for user in &users {
+            let result = messages
+                .update_many(
+                    doc! { "user_id": &user.user_id, "username": { "$ne": &user.username } },
+                    doc! { "$set": { "username": &user.username } },
+                    None,
+                )
+                .await?;
+            modified += result.modified_count;
+        }
+        progress.record(users.len() as u64, modified).await?;
+    }
+    Ok(())
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(&*e.kind, ErrorKind::Write(WriteFailure::WriteError(write_error)) if write_error.code == 11000)
+        || matches!(&*e.kind, ErrorKind::Command(command_error) if command_error.code == 11000)
+}
+
+/// Marks a migration as running unless a live run already is. Returns the
+/// new run, or `None` if it is already running.
+async fn claim_run(state: &AppState, migration: Migration, started_by: &str) -> mongodb::error::Result<Option<MigrationRun>> {
+    let stale_before = bson::DateTime::from_millis((Utc::now() - Duration::minutes(STALE_RUN_MINUTES)).timestamp_millis());
+    let now = now_bson();
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    let result = runs(state)
+        .find_one_and_update(
+            doc! {
+                "_id": migration.name(),
+                "$or": [
+                    { "status": { "$ne": "running" } },
+                    { "updated_at": { "$lt": stale_before } },
+                ],
+            },
+            doc! {
+                "$set": {
+                    "status": "running",
+                    "started_by": started_by,
+                    "processed": 0_i64,
+                    "modified": 0_i64,
+                    "started_at": now,
+                    "updated_at": now,
+                },
+                "$unset": { "error": "" },
+            },
+            options,
+        )
+        .await;
+    match result {
+        Ok(run) => Ok(run),
+        // The upsert collides with the running migration's document
+        Err(e) if is_duplicate_key(&e) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+async fn finish_run(state: &AppState, migration: Migration, result: mongodb::error::Result<()>) {
+    let update = match &result {
+        Ok(()) => doc! { "$set": { "status": "completed", "updated_at": now_bson() } },
+        Err(e) => doc! { "$set": { "status": "failed", "updated_at": now_bson(), "error": e.to_string() } },
+    };
+    if let Err(e) = runs(state).update_one(doc! { "_id": migration.name() }, update, None).await {
+        error!("Failed to record the end of migration {}: {}", migration.name(), e);
+    }
+    match result {
+        Ok(()) => info!("Migration {} completed", migration.name()),
+        Err(e) => error!("Migration {} failed: {}", migration.name(), e),
+    }
+}
+
+fn parse_migration(name: &str) -> Result<Migration, AppError> {
+    Migration::parse(name).ok_or_else(|| AppError::BadRequest(format!("Unknown migration: {}", name)))
+}
+
+// POST /api/admin/migrations/:name - starts a run in the background, admins only
+pub async fn start_migration_handler(
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<MigrationRun>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    let migration = parse_migration(&name)?;
+    let run = claim_run(&state, migration, &user.user_id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(format!("Migration {} is already running", name)))?;
+    info!("User {} started migration {}", user.username, name);
+
+    tokio::spawn(async move {
+        let progress = Progress { state: &state, name: migration.name() };
+        let result = migration.run(&state, &progress).await;
+        finish_run(&state, migration, result).await;
+    });
+    Ok(Json(run))
+}
+
+// GET /api/admin/migrations/:name - progress of the latest run, admins only
+pub async fn migration_status_handler(
+    Path(name): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<MigrationRun>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    let migration = parse_migration(&name)?;
+    let run = runs(&state)
+        .find_one(doc! { "_id": migration.name() }, None)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    Ok(Json(run))
+}
@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
-use uuid::Uuid;
+use std::collections::HashMap;
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Message {
@@ -19,6 +19,164 @@ pub struct Message {
     pub deleted: bool,
     #[serde(default)]
     pub reactions: Vec<Reaction>,
+    // Detected language, only set in rooms that declare a primary language
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub off_language: bool,
+    // Id of the message this one replies to
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub parent_id: Option<String>,
+    // Replies to this message, maintained by the server
+    #[serde(default)]
+    pub reply_count: i32,
+    #[serde(skip_serializing_if = "MessageKind::is_text", default)]
+    pub kind: MessageKind,
+    // Set on Event messages
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub event: Option<EventDetails>,
+    // Pings the whole room with @everyone or @here
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub mentions_everyone: bool,
+    // Set on broadcasts only, never stored
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub notification: Option<crate::notifications::NotificationHint>,
+    // Set on broadcasts to members who muted the room, never stored
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub muted: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub attachments: Vec<crate::uploads::Attachment>,
+    // Sender's clock when sent, in epoch milliseconds, echoed back so the
+    // sender can match its pending copy and measure clock skew
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub client_ts: Option<i64>,
+    // Typed, versioned copy of the content, set when the message is stored
+    #[serde(skip_serializing_if = "Option::is_none", default, deserialize_with = "crate::message_body::tolerant")]
+    pub body: Option<crate::message_body::VersionedBody>,
+    // Sender's badge when the message was sent
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub badge: Option<crate::badges::Badge>,
+    // How clients should set the message apart, per the room's settings
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub highlight: Option<crate::badges::Highlight>,
+    // Set in rooms with a message lifetime; the message is deleted after it
+    #[serde(skip_serializing_if = "Option::is_none", default, with = "crate::message_ttl::bson_datetime_option")]
+    pub expires_at: Option<DateTime<Utc>>,
+    // Sent while the author was shadow-banned; only the author is shown it,
+    // and the marker is cleared before it is
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub shadow: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageKind {
+    #[default]
+    Text,
+    // Something members can RSVP to
+    Event,
+    // A notice cross-posted to every room in an area, see announcements
+    Announcement,
+    // A kind added by a newer instance
+    #[serde(other)]
+    Other,
+}
+
+impl MessageKind {
+    pub fn is_text(&self) -> bool {
+        *self == MessageKind::Text
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventDetails {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub starts_at: Option<DateTime<Utc>>,
+    // Maintained by the server from individual RSVPs
+    #[serde(default)]
+    pub rsvp_counts: RsvpCounts,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RsvpCounts {
+    pub going: u32,
+    pub maybe: u32,
+    pub no: u32,
+}
+
+impl Message {
+    pub fn new(room_id: String, user_id: String, username: String, content: String) -> Self {
+        Message {
+            id: None,
+            room_id,
+            user_id,
+            username,
+            content,
+            timestamp: Utc::now(),
+            edited_at: None,
+            deleted: false,
+            reactions: vec![],
+            language: None,
+            off_language: false,
+            parent_id: None,
+            reply_count: 0,
+            kind: MessageKind::Text,
+            event: None,
+            mentions_everyone: false,
+            notification: None,
+            muted: false,
+            attachments: vec![],
+            client_ts: None,
+            body: None,
+            badge: None,
+            highlight: None,
+            expires_at: None,
+            shadow: false,
+        }
+    }
+
+    /// The stored body, or one built from the flat fields for messages
+    /// stored before bodies were.
+    pub fn body(&self) -> crate::message_body::MessageBody {
+        match &self.body {
+            Some(versioned) => versioned.body.clone(),
+            None => crate::message_body::MessageBody::of(self),
+        }
+    }
+}
+
+// DMs are sent to clients in the room message shape
+impl From<DirectMessage> for Message {
+    fn from(dm: DirectMessage) -> Self {
+        Message {
+            id: dm.id,
+            room_id: dm.conversation_id,
+            user_id: dm.sender_id,
+            username: dm.sender_username,
+            content: dm.content,
+            timestamp: dm.timestamp,
+            edited_at: dm.edited_at,
+            deleted: dm.deleted,
+            reactions: vec![],
+            language: None,
+            off_language: false,
+            parent_id: None,
+            reply_count: 0,
+            kind: MessageKind::Text,
+            event: None,
+            mentions_everyone: false,
+            notification: None,
+            muted: false,
+            attachments: dm.attachments,
+            client_ts: None,
+            body: None,
+            badge: None,
+            highlight: None,
+            expires_at: None,
+            shadow: false,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -38,12 +196,104 @@ pub struct ChatRoom {
     #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub created_at: DateTime<Utc>,
     pub settings: RoomSettings,
+    // Hex containing a legacy lat_lng room, see room_bridge
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub h3_index: Option<String>,
+    #[serde(default)]
+    pub migrated_to_hex: bool,
+    // "Currently discussing" blurb, refreshed while the room is active
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub topic: Option<crate::topics::RoomTopic>,
+    // Whose join or post created the room, for its quota and review only;
+    // None for rooms created otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_by: Option<String>,
+    // Granted by an admin, see room_roles; rooms have no owner otherwise
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub review: crate::room_quota::ReviewState,
+    // Meeting points, entrances and hazards pinned by moderators
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub markers: Vec<crate::room_markers::RoomMarker>,
+    // Users the owner made room moderators, see room_roles
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub moderators: Vec<String>,
+    #[serde(default)]
+    pub visibility: crate::room_invites::RoomVisibility,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ChatRoom {
+    pub fn new(location_id: &str) -> Self {
+        ChatRoom {
+            id: location_id.to_string(),
+            location_id: location_id.to_string(),
+            active_users: 0,
+            last_message_at: Utc::now(),
+            created_at: Utc::now(),
+            settings: RoomSettings::default(),
+            h3_index: crate::room_bridge::legacy_room_cell(location_id).map(|cell| cell.to_string()),
+            migrated_to_hex: false,
+            topic: None,
+            created_by: None,
+            owner: None,
+            review: Default::default(),
+            markers: Vec::new(),
+            moderators: Vec::new(),
+            visibility: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RoomSettings {
     pub max_users: i32,
     pub rate_limit: i32, // messages per minute
+    // Primary language as an ISO 639-3 code, e.g. "eng"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    // Keep out users whose GPS claim is far from their IP location
+    #[serde(default)]
+    pub exclude_location_mismatch: bool,
+    // Audience rating; picks the content filter level and gates mature rooms
+    #[serde(default)]
+    pub content_rating: crate::content_filter::ContentRating,
+    // What regular members may post
+    #[serde(default)]
+    pub permissions: crate::permissions::RoomPermissions,
+    #[serde(default)]
+    pub notifications: crate::notifications::NotificationSettings,
+    // How messages from official accounts stand out
+    #[serde(default)]
+    pub official_messages: crate::badges::OfficialMessages,
+    // Messages are deleted this long after they are sent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message_ttl_seconds: Option<u32>,
+    // Messages older than this many days are purged; 0 keeps them forever,
+    // unset follows the service's default for the kind of room
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u32>,
+    // Blocklist and link rules checked before messages are stored
+    #[serde(default)]
+    pub filters: crate::message_filters::FilterRules,
+}
+
+impl Default for RoomSettings {
+    fn default() -> Self {
+        RoomSettings {
+            max_users: 1000,
+            rate_limit: 10,
+            language: None,
+            exclude_location_mismatch: false,
+            content_rating: Default::default(),
+            permissions: Default::default(),
+            notifications: Default::default(),
+            official_messages: Default::default(),
+            message_ttl_seconds: None,
+            retention_days: None,
+            filters: Default::default(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -52,20 +302,99 @@ pub struct User {
     pub username: String,
     pub socket_id: String,
     pub location_id: String,
+    // Claimed location disagrees with the IP geolocation
+    #[serde(default)]
+    pub location_flagged: bool,
+    #[serde(default = "Utc::now")]
+    pub joined_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub badge: Option<crate::badges::Badge>,
+}
+
+// How far a sent message got; `Queued` ones aren't durable yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SendStatus {
+    Sent,
+    Queued,
 }
 
 // WebSocket message types
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum WsMessage {
-    Join { user_id: String, username: String, token: String },
-    Message { content: String },
+    Join {
+        user_id: String,
+        username: String,
+        // May be left out by sockets opened with a join ticket
+        #[serde(default)]
+        token: String,
+        // Timestamp of the newest message the client already has cached
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        have_until: Option<DateTime<Utc>>,
+        // Trims what this socket receives for poor mobile networks
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        low_data: bool,
+        // Token of an invitation to a private room
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        invite: Option<String>,
+    },
+    Message {
+        content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        parent_id: Option<String>,
+        // Makes this an Event message; only the title and start time are read
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        event: Option<EventDetails>,
+        // Uploaded with POST /api/uploads first
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<crate::uploads::Attachment>,
+        // Client clock in epoch milliseconds, echoed on the NewMessage
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_ts: Option<i64>,
+    },
+    // Client confirms it displayed a message; recorded in its delivery trace
+    Ack { message_id: String },
+    // Shorthand for Activity { kind: typing }; relayed to the room as UserActivity
     Typing { is_typing: bool },
+    // Composer activity: client sends Activity, room receives UserActivity
+    Activity { kind: crate::activity::ActivityKind, active: bool },
+    UserActivity {
+        user_id: String,
+        username: String,
+        kind: crate::activity::ActivityKind,
+        active: bool,
+        // Receivers should clear the indicator if no refresh arrives in time
+        expires_in_ms: u64,
+    },
     UserJoined { username: String, #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")] timestamp: DateTime<Utc> },
     UserLeft { username: String, #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")] timestamp: DateTime<Utc> },
     NewMessage(Message),
-    MessageHistory { messages: Vec<Message> },
+    MessageHistory {
+        messages: Vec<Message>,
+        // Set when this only holds messages newer than the client's cache
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        since: Option<DateTime<Utc>>,
+    },
+    // After Join; a reconnecting client sends Resume with it instead of Join
+    SessionStarted { session_id: String },
+    // Rejoin as the session's user and receive messages after last_message_id
+    Resume { session_id: String, last_message_id: String },
+    // `complete` is false when too much was missed and `messages` is the
+    // latest page, to replace the client's cache
+    Resumed { session_id: String, user_count: i32, messages: Vec<Message>, complete: bool },
+    // The session has expired; the client should send Join
+    ResumeFailed { reason: String },
     Error { message: String },
+    // Sending too fast; the client may retry after this many seconds
+    RateLimited { retry_after: u64 },
+    // The message was refused: a moderator muted the sender in this room
+    // until `until`
+    Muted { until: DateTime<Utc> },
+    // To the sender once its message is saved, or queued while MongoDB fails over
+    MessageAccepted { message_id: String, status: SendStatus },
+    // Join refused because the room is at max_users; suggestions are nearby rooms with space
+    RoomFull { room_id: String, max_users: i32, suggestions: Vec<crate::presence::RoomSuggestion> },
     // Local chat specific
     RoomJoined { 
         room_id: String, 
@@ -73,16 +402,121 @@ pub enum WsMessage {
         is_new_room: bool, 
         user_count: i32,
         location: crate::local_chat::Location,
+        permissions: crate::permissions::RoomPermissions,
     },
     // Hex chat specific
-    JoinHex { h3_index: String, user_info: HexUserInfo },
-    HexJoined { h3_index: String, user_count: i32 },
+    JoinHex {
+        // Either a precomputed index or a raw GPS fix for the server to resolve
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        h3_index: Option<String>,
+        user_info: HexUserInfo,
+        // Only required to join mature rooms
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        token: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        location: Option<crate::hex::GpsFix>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        have_until: Option<DateTime<Utc>>,
+        // Also listen to new messages in the hexes this many rings around (at most 1)
+        #[serde(default)]
+        include_neighbors: u8,
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        low_data: bool,
+    },
+    // Moves a joined hex socket to the parent or child cell at `resolution`;
+    // answered with HexJoined for the new cell
+    ChangeResolution { resolution: u8 },
+    // A reply was posted in a thread; `parent_id` is the thread's first message
+    ThreadUpdated { parent_id: String, reply_count: i32, last_reply_at: DateTime<Utc> },
+    // A message posted in a neighbouring hex the socket listens to
+    NeighborMessage { h3_index: String, message: Message },
+    // `center` and `boundary` are [longitude, latitude] points of the cell
+    HexJoined {
+        h3_index: String,
+        resolution: u8,
+        center: [f64; 2],
+        boundary: Vec<[f64; 2]>,
+        // Neighbouring hexes whose new messages arrive as NeighborMessage
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        neighbors: Vec<String>,
+        user_count: i32,
+        permissions: crate::permissions::RoomPermissions,
+    },
+    // Legacy room has moved to its containing hex; reconnect to /ws/hex/:h3_index
+    RoomRedirect { room_id: String, h3_index: String },
+    // RSVP to an Event message; room receives RsvpUpdated with the new totals
+    // Changes what this socket receives from its room, e.g. when backgrounded
+    SetFilter { filter: crate::subscription_filter::FanoutFilter },
+    Rsvp { message_id: String, status: crate::rsvp::RsvpStatus },
+    RsvpUpdated { message_id: String, counts: RsvpCounts },
+    // Adds (or with `remove`, takes back) a reaction; the room receives
+    // ReactionsUpdated, batched for busy messages
+    React {
+        message_id: String,
+        emoji: String,
+        #[serde(default)]
+        remove: bool,
+    },
+    ReactionsUpdated { message_id: String, counts: Vec<crate::reactions::ReactionCount> },
+    // Moderator removed every message the selection matches; clients drop
+    // the ones they have loaded
+    BulkDelete { room_id: String, selection: crate::bulk_messages::BulkSelection, deleted_count: u64 },
+    // Sent to users who RSVP'd to a scheduled event shortly before it starts
+    EventReminder { event_id: String, room_id: String, title: String, starts_at: DateTime<Utc> },
     // DM specific
     JoinDM { conversation_id: String, user_id: String, username: String, token: String },
     DMJoined { conversation_id: String, participant_count: i32 },
-    DMMessage { conversation_id: String, content: String },
+    DMMessage {
+        conversation_id: String,
+        content: String,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        attachments: Vec<crate::uploads::Attachment>,
+    },
     DMTyping { conversation_id: String, is_typing: bool },
     DMRead { conversation_id: String, user_id: String },
+    // Senders change their own messages; the conversation gets DMEdited/DMDeleted
+    DMEdit { conversation_id: String, message_id: String, content: String },
+    DMDelete { conversation_id: String, message_id: String },
+    DMEdited { conversation_id: String, message_id: String, content: String, edited_at: DateTime<Utc> },
+    DMDeleted { conversation_id: String, message_id: String },
+    // E2EE key announcements/rotations; the server relays and stores them but can't read keys
+    DMKeyAnnounce { conversation_id: String, event: crate::dm_keys::NewKeyEvent },
+    DMKeyEvent { event: crate::dm_keys::KeyEvent },
+    // Group conversation membership changes, sent to everyone in the conversation
+    DMParticipantAdded { conversation_id: String, user_id: String, added_by: String, participant_count: i32 },
+    DMParticipantRemoved { conversation_id: String, user_id: String, removed_by: String, participant_count: i32 },
+    // Receipts: every message up to `up_to` reached or was read by `user_id`
+    DMDelivered { conversation_id: String, user_id: String, up_to: String },
+    DMReadUpdated { conversation_id: String, user_id: String, up_to: String },
+    // This instance is shutting down and closes the socket next; reconnect
+    // after `reconnect_after` seconds to reach another instance
+    ServerShutdown { reconnect_after: u64 },
+    // A map marker was pinned or changed; `marker` is left out when it was removed
+    MarkerUpdated {
+        room_id: String,
+        marker_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        marker: Option<crate::room_markers::RoomMarker>,
+    },
+    // A moderator banned `user_id` from the room; that user's sockets in it
+    // get this and are closed, other sockets never see it
+    RoomBanned {
+        room_id: String,
+        user_id: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+    // `user_id` lost their membership of the private room; that user's
+    // sockets in it get this and are closed, other sockets never see it
+    RoomAccessRevoked {
+        room_id: String,
+        user_id: String,
+    },
+    // Messages about to be deleted in a room with a message lifetime
+    ExpiringSoon {
+        room_id: String,
+        messages: Vec<crate::message_ttl::ExpiringMessage>,
+    },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -107,6 +541,14 @@ pub struct DirectMessage {
     pub deleted: bool,
     #[serde(default)]
     pub read_by: Vec<String>, // User IDs who have read this message
+    // User IDs whose socket has received this message
+    #[serde(default)]
+    pub delivered_to: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub attachments: Vec<crate::uploads::Attachment>,
+    // Sent while the sender was shadow-banned, as on `Message`
+    #[serde(skip_serializing_if = "std::ops::Not::not", default)]
+    pub shadow: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -119,4 +561,34 @@ pub struct DMConversation {
     pub created_at: DateTime<Utc>,
     #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub updated_at: DateTime<Utc>,
+    // Participant -> id of the newest message they have read
+    #[serde(default)]
+    pub last_read: HashMap<String, String>,
+    #[serde(default)]
+    pub notifications: crate::notifications::NotificationSettings,
+}
+
+impl DMConversation {
+    pub fn is_participant(&self, user_id: &str) -> bool {
+        self.participants.iter().any(|participant| participant == user_id)
+    }
+
+    pub fn participant_count(&self) -> i32 {
+        self.participants.len() as i32
+    }
+
+    /// Whether the conversation is between two people, whose history no one
+    /// else should be let into.
+    pub fn is_one_to_one(&self) -> bool {
+        self.participants.len() <= 2
+    }
+
+    /// Participants other than the sender who haven't read `message` yet.
+    pub fn unread_by<'a>(&'a self, message: &DirectMessage) -> Vec<&'a str> {
+        self.participants
+            .iter()
+            .filter(|participant| **participant != message.sender_id && !message.read_by.contains(participant))
+            .map(String::as_str)
+            .collect()
+    }
 }
\ No newline at end of file
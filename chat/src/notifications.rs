@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use crate::badges::Highlight;
+use crate::models::{DirectMessage, Message, MessageKind};
+
+// Sound clients play when a room or conversation doesn't pick one
+pub const DEFAULT_SOUND: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationCategory {
+    Message,
+    Reply,
+    RoomMention,
+    Event,
+    DirectMessage,
+    Announcement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationPriority {
+    // Badge only
+    Low,
+    Normal,
+    // Alert even when the app is backgrounded
+    High,
+}
+
+/// Notification preferences of a room or DM conversation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    // Replaces the default sound of categories that play one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound: Option<String>,
+    // Never plays a sound and never goes above normal priority
+    #[serde(default)]
+    pub silent: bool,
+}
+
+/// How clients and the push dispatcher should present a broadcast message,
+/// so both render it the same way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationHint {
+    pub category: NotificationCategory,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sound: Option<String>,
+    // Notifications with the same key replace each other on the device
+    pub collapse_key: String,
+    pub priority: NotificationPriority,
+}
+
+impl NotificationHint {
+    pub fn for_room_message(message: &Message, settings: &NotificationSettings) -> Self {
+        let (category, priority, collapse_key) = if message.kind == MessageKind::Announcement {
+            (NotificationCategory::Announcement, NotificationPriority::High, format!("announcement:{}", message.room_id))
+        } else if message.highlight == Some(Highlight::Priority) {
+            // Rooms that prioritize official messages push them as urgent
+            (NotificationCategory::Message, NotificationPriority::High, format!("room:{}", message.room_id))
+        } else if message.mentions_everyone {
+            (NotificationCategory::RoomMention, NotificationPriority::High, format!("room_mention:{}", message.room_id))
+        } else if message.kind == MessageKind::Event {
+            let id = message.id.map(|id| id.to_hex()).unwrap_or_default();
+            (NotificationCategory::Event, NotificationPriority::Normal, format!("event:{}", id))
+        } else if message.parent_id.is_some() {
+            (NotificationCategory::Reply, NotificationPriority::Normal, format!("room:{}", message.room_id))
+        } else {
+            (NotificationCategory::Message, NotificationPriority::Low, format!("room:{}", message.room_id))
+        };
+        Self::new(category, priority, collapse_key, settings)
+    }
+
+    pub fn for_direct_message(message: &DirectMessage, settings: &NotificationSettings) -> Self {
+        Self::new(
+            NotificationCategory::DirectMessage,
+            NotificationPriority::High,
+            format!("dm:{}", message.conversation_id),
+            settings,
+        )
+    }
+
+    fn new(
+        category: NotificationCategory,
+        priority: NotificationPriority,
+        collapse_key: String,
+        settings: &NotificationSettings,
+    ) -> Self {
+        if settings.silent {
+            return NotificationHint {
+                category,
+                sound: None,
+                collapse_key,
+                priority: priority.min(NotificationPriority::Normal),
+            };
+        }
+        // Ordinary room chatter only badges
+        let sound = (priority > NotificationPriority::Low)
+            .then(|| settings.sound.clone().unwrap_or_else(|| DEFAULT_SOUND.to_string()));
+        NotificationHint { category, sound, collapse_key, priority }
+    }
+}
@@ -0,0 +1,114 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message_body::MessageBody,
+    models::Message,
+    room_mentions::is_room_mention,
+};
+
+// Links to these are shown inline, so they count as media rather than links
+const MEDIA_EXTENSIONS: [&str; 10] = ["png", "jpg", "jpeg", "gif", "webp", "heic", "mp4", "mov", "webm", "mp3"];
+
+/// What regular members of a room may post. Moderators can always post
+/// anything. Sent to clients on join so they can disable actions up front.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoomPermissions {
+    pub post_links: bool,
+    pub post_media: bool,
+    // Event messages that members RSVP to
+    pub create_polls: bool,
+    // @everyone and @here
+    pub mention_everyone: bool,
+}
+
+impl Default for RoomPermissions {
+    fn default() -> Self {
+        RoomPermissions {
+            post_links: true,
+            post_media: true,
+            create_polls: true,
+            mention_everyone: false,
+        }
+    }
+}
+
+/// Something in a message that a room can restrict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoomAction {
+    PostLinks,
+    PostMedia,
+    CreatePolls,
+    MentionEveryone,
+}
+
+impl RoomAction {
+    pub fn denied_reason(self) -> &'static str {
+        match self {
+            RoomAction::PostLinks => "Links are turned off in this room",
+            RoomAction::PostMedia => "Media is turned off in this room",
+            RoomAction::CreatePolls => "Only moderators can create polls in this room",
+            RoomAction::MentionEveryone => "Only moderators can mention @everyone in this room",
+        }
+    }
+}
+
+impl RoomPermissions {
+    pub fn allows(&self, action: RoomAction) -> bool {
+        match action {
+            RoomAction::PostLinks => self.post_links,
+            RoomAction::PostMedia => self.post_media,
+            RoomAction::CreatePolls => self.create_polls,
+            RoomAction::MentionEveryone => self.mention_everyone,
+        }
+    }
+
+    /// Returns the first restricted action the message takes, if the sender
+    /// isn't allowed it.
+    pub fn check(&self, message: &Message, is_moderator: bool) -> Result<(), RoomAction> {
+        if is_moderator {
+            return Ok(());
+        }
+        match message_actions(message).into_iter().find(|action| !self.allows(*action)) {
+            Some(action) => Err(action),
+            None => Ok(()),
+        }
+    }
+}
+
+fn is_url(word: &str) -> bool {
+    let word = word.to_lowercase();
+    word.starts_with("http://") || word.starts_with("https://") || word.starts_with("www.")
+}
+
+fn is_media_url(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_lowercase();
+    path.rsplit_once('.')
+        .is_some_and(|(_, extension)| MEDIA_EXTENSIONS.contains(&extension))
+}
+
+/// The restricted actions a message takes.
+pub fn message_actions(message: &Message) -> Vec<RoomAction> {
+    let mut actions = Vec::new();
+    let mut push = |action| {
+        if !actions.contains(&action) {
+            actions.push(action);
+        }
+    };
+
+    if matches!(message.body(), MessageBody::Event { .. } | MessageBody::Poll { .. }) {
+        push(RoomAction::CreatePolls);
+    }
+    if !message.attachments.is_empty() {
+        push(RoomAction::PostMedia);
+    }
+    for word in message.content.split_whitespace() {
+        let trimmed = word.trim_end_matches(|c: char| c.is_ascii_punctuation() && c != '/');
+        if is_url(trimmed) {
+            push(if is_media_url(trimmed) { RoomAction::PostMedia } else { RoomAction::PostLinks });
+        } else if is_room_mention(trimmed) {
+            push(RoomAction::MentionEveryone);
+        }
+    }
+    actions
+}
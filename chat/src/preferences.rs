@@ -0,0 +1,109 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::doc,
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+// Keeps the preferences document small enough to load on every join
+pub const MAX_MUTED_ROOMS: usize = 500;
+
+/// Per-user chat preferences. A muted room keeps its membership and unread
+/// count, but its messages never push and reach the user marked `muted`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UserPreferences {
+    #[serde(rename = "_id")]
+    pub user_id: String,
+    #[serde(default)]
+    pub muted_rooms: Vec<String>,
+}
+
+impl UserPreferences {
+    pub fn is_muted(&self, room_id: &str) -> bool {
+        self.muted_rooms.iter().any(|room| room == room_id)
+    }
+}
+
+fn preferences(state: &AppState) -> Collection<UserPreferences> {
+    state.database.collection("user_preferences")
+}
+
+async fn load(state: &AppState, user_id: &str) -> mongodb::error::Result<UserPreferences> {
+    let stored = preferences(state).find_one(doc! { "_id": user_id }, None).await?;
+    Ok(stored.unwrap_or_else(|| UserPreferences { user_id: user_id.to_string(), ..Default::default() }))
+}
+
+/// Whether the user muted the room. Unmuted when preferences can't be read,
+/// so a database hiccup never hides anything.
+pub async fn is_muted(state: &AppState, room_id: &str, user_id: &str) -> bool {
+    match preferences(state).find_one(doc! { "_id": user_id, "muted_rooms": room_id }, None).await {
+        Ok(found) => found.is_some(),
+        Err(e) => {
+            error!("Failed to check whether {} muted {}: {}", user_id, room_id, e);
+            false
+        }
+    }
+}
+
+/// Which of `user_ids` muted the room.
+pub async fn muted_members(state: &AppState, room_id: &str, user_ids: &[String]) -> mongodb::error::Result<HashSet<String>> {
+    let muted: Vec<UserPreferences> = preferences(state)
+        .find(doc! { "_id": { "$in": user_ids }, "muted_rooms": room_id }, None)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(muted.into_iter().map(|preferences| preferences.user_id).collect())
+}
+
+// GET /api/users/:user_id/preferences - users can only read their own
+pub async fn get_preferences_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<UserPreferences>, AppError> {
+    if user_id != user.user_id {
+        return Err(AppError::Forbidden);
+    }
+    Ok(Json(load(&state, &user_id).await?))
+}
+
+// PUT /api/rooms/:location_id/mute
+pub async fn mute_room_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<UserPreferences>, AppError> {
+    let current = load(&state, &user.user_id).await?;
+    if current.is_muted(&location_id) {
+        return Ok(Json(current));
+    }
+    if current.muted_rooms.len() >= MAX_MUTED_ROOMS {
+        return Err(AppError::BadRequest(format!("At most {} rooms can be muted", MAX_MUTED_ROOMS)));
+    }
+    update_mutes(&state, &user.user_id, doc! { "$addToSet": { "muted_rooms": &location_id } }).await
+}
+
+// DELETE /api/rooms/:location_id/mute
+pub async fn unmute_room_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<UserPreferences>, AppError> {
+    update_mutes(&state, &user.user_id, doc! { "$pull": { "muted_rooms": &location_id } }).await
+}
+
+async fn update_mutes(state: &AppState, user_id: &str, update: mongodb::bson::Document) -> Result<Json<UserPreferences>, AppError> {
+    let options = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build();
+    let updated = preferences(state).find_one_and_update(doc! { "_id": user_id }, update, options).await?;
+    Ok(Json(updated.unwrap_or_else(|| UserPreferences { user_id: user_id.to_string(), ..Default::default() })))
+}
@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use h3o::CellIndex;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{auth::AuthUser, models::User, room_bridge::legacy_room_cell, AppError, AppState};
+
+// Sockets not refreshed by their instance within this long are dropped, so a
+// crashed instance's users stop counting against room caps
+const PRESENCE_TTL_MS: i64 = 90_000;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+const MAX_SUGGESTIONS: usize = 6;
+
+// Evicts stale sockets, then admits this one unless the room is full.
+// Sockets already present (rejoins) are always admitted. Returns the new
+// occupancy, or -1 when the room is full.
+const JOIN_SCRIPT: &str = r#"
+local stale = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1])
+for _, socket_id in ipairs(stale) do
+    redis.call('ZREM', KEYS[1], socket_id)
+    redis.call('HDEL', KEYS[2], socket_id)
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[3], '-inf', ARGV[1])
+local max_users = tonumber(ARGV[3])
+if not redis.call('ZSCORE', KEYS[1], ARGV[4]) then
+    if max_users > 0 and redis.call('ZCARD', KEYS[1]) >= max_users then
+        return -1
+    end
+end
+redis.call('ZADD', KEYS[1], ARGV[2], ARGV[4])
+redis.call('HSET', KEYS[2], ARGV[4], ARGV[6])
+redis.call('PEXPIRE', KEYS[1], ARGV[5])
+redis.call('PEXPIRE', KEYS[2], ARGV[5])
+return redis.call('ZCARD', KEYS[1])
+"#;
+
+// Moves a socket between rooms in one step, unless the target is full.
+// Keys 1-3 are the source room's, 4-6 the target's, each presence, users and
+// participants; participants stay participants. Returns the target's new
+// occupancy, or -1 when it is full.
+const MOVE_SCRIPT: &str = r#"
+local stale = redis.call('ZRANGEBYSCORE', KEYS[4], '-inf', ARGV[1])
+for _, socket_id in ipairs(stale) do
+    redis.call('ZREM', KEYS[4], socket_id)
+    redis.call('HDEL', KEYS[5], socket_id)
+end
+redis.call('ZREMRANGEBYSCORE', KEYS[6], '-inf', ARGV[1])
+local max_users = tonumber(ARGV[3])
+if max_users > 0 and redis.call('ZCARD', KEYS[4]) >= max_users then
+    return -1
+end
+local participant = redis.call('ZSCORE', KEYS[3], ARGV[4])
+redis.call('ZREM', KEYS[1], ARGV[4])
+redis.call('HDEL', KEYS[2], ARGV[4])
+redis.call('ZREM', KEYS[3], ARGV[4])
+redis.call('ZADD', KEYS[4], ARGV[2], ARGV[4])
+redis.call('HSET', KEYS[5], ARGV[4], ARGV[6])
+if participant then
+    redis.call('ZADD', KEYS[6], ARGV[2], ARGV[4])
+    redis.call('PEXPIRE', KEYS[6], ARGV[5])
+end
+redis.call('PEXPIRE', KEYS[4], ARGV[5])
+redis.call('PEXPIRE', KEYS[5], ARGV[5])
+return redis.call('ZCARD', KEYS[4])
+"#;
+
+/// Sorted set of the sockets in a room across all instances, scored by
+/// their last heartbeat.
+fn presence_key(room_id: &str) -> String {
+    format!("presence:{}", room_id)
+}
+
+/// Hash of socket id to the `RoomUser` behind it, kept alongside the
+/// sorted set.
+fn presence_users_key(room_id: &str) -> String {
+    format!("presence_users:{}", room_id)
+}
+
+/// Sorted set of the sockets in a room that have posted since joining,
+/// scored like `presence_key`. The rest of the room is only watching.
+fn participants_key(room_id: &str) -> String {
+    format!("presence_participants:{}", room_id)
+}
+
+/// Someone currently in a room, on any instance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomUser {
+    pub id: String,
+    pub username: String,
+    pub joined_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub badge: Option<crate::badges::Badge>,
+}
+
+impl From<&User> for RoomUser {
+    fn from(user: &User) -> Self {
+        RoomUser {
+            id: user.id.clone(),
+            username: user.username.clone(),
+            joined_at: user.joined_at,
+            badge: user.badge,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinOutcome {
+    Joined,
+    Full,
+}
+
+/// Admits a user's socket into a room's cross-instance presence if the room
+/// has space. Fails open when Redis is unavailable.
+pub async fn try_join(state: &AppState, room_id: &str, user: &User, max_users: i32) -> JoinOutcome {
+    let now = Utc::now().timestamp_millis();
+    let mut conn = match state.redis_pool.get().await {
+        Ok(conn) => conn,
+        Err(_) => return JoinOutcome::Joined,
+    };
+    let room_user = serde_json::to_string(&RoomUser::from(user)).unwrap_or_default();
+    let result: redis::RedisResult<i64> = redis::Script::new(JOIN_SCRIPT)
+        .key(presence_key(room_id))
+        .key(presence_users_key(room_id))
+        .key(participants_key(room_id))
+        .arg(now - PRESENCE_TTL_MS)
+        .arg(now)
+        .arg(max_users)
+        .arg(&user.socket_id)
+        .arg(PRESENCE_TTL_MS)
+        .arg(room_user)
+        .invoke_async(&mut conn)
+        .await;
+    match result {
+        Ok(-1) => JoinOutcome::Full,
+        Ok(_) => JoinOutcome::Joined,
+        Err(e) => {
+            error!("Failed to record presence in room {}: {}", room_id, e);
+            JoinOutcome::Joined
+        }
+    }
+}
+
+/// Moves a socket from watching to participating, on its first post.
+pub async fn mark_participant(state: &AppState, room_id: &str, socket_id: &str) {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::pipe()
+        .cmd("ZADD")
+        .arg(participants_key(room_id))
+        .arg(Utc::now().timestamp_millis())
+        .arg(socket_id)
+        .ignore()
+        .cmd("PEXPIRE")
+        .arg(participants_key(room_id))
+        .arg(PRESENCE_TTL_MS)
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to record participant in room {}: {}", room_id, e);
+    }
+}
+
+/// Moves a socket from `from_room` into `user.location_id` if that room has
+/// space, so it never counts in both or neither. Fails open like `try_join`.
+pub async fn try_move(state: &AppState, from_room: &str, user: &User, max_users: i32) -> JoinOutcome {
+    let now = Utc::now().timestamp_millis();
+    let mut conn = match state.redis_pool.get().await {
+        Ok(conn) => conn,
+        Err(_) => return JoinOutcome::Joined,
+    };
+    let room_user = serde_json::to_string(&RoomUser::from(user)).unwrap_or_default();
+    let result: redis::RedisResult<i64> = redis::Script::new(MOVE_SCRIPT)
+        .key(presence_key(from_room))
+        .key(presence_users_key(from_room))
+        .key(participants_key(from_room))
+        .key(presence_key(&user.location_id))
+        .key(presence_users_key(&user.location_id))
+        .key(participants_key(&user.location_id))
+        .arg(now - PRESENCE_TTL_MS)
+        .arg(now)
+        .arg(max_users)
+        .arg(&user.socket_id)
+        .arg(PRESENCE_TTL_MS)
+        .arg(room_user)
+        .invoke_async(&mut conn)
+        .await;
+    match result {
+        Ok(-1) => JoinOutcome::Full,
+        Ok(_) => JoinOutcome::Joined,
+        Err(e) => {
+            error!("Failed to move presence from {} to {}: {}", from_room, user.location_id, e);
+            JoinOutcome::Joined
+        }
+    }
+}
+
+pub async fn leave(state: &AppState, room_id: &str, socket_id: &str) {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::pipe()
+        .cmd("ZREM")
+        .arg(presence_key(room_id))
+        .arg(socket_id)
+        .ignore()
+        .cmd("ZREM")
+        .arg(participants_key(room_id))
+        .arg(socket_id)
+        .ignore()
+        .cmd("HDEL")
+        .arg(presence_users_key(room_id))
+        .arg(socket_id)
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to clear presence in room {}: {}", room_id, e);
+    }
+}
+
+/// Live socket counts for several rooms across all instances.
+pub async fn occupancy(state: &AppState, room_ids: &[String]) -> redis::RedisResult<Vec<usize>> {
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    let cutoff = Utc::now().timestamp_millis() - PRESENCE_TTL_MS;
+    let mut pipe = redis::pipe();
+    for room_id in room_ids {
+        pipe.cmd("ZCOUNT").arg(presence_key(room_id)).arg(cutoff).arg("+inf");
+    }
+    pipe.query_async(&mut conn).await
+}
+
+/// A room's live socket count across all instances. Falls back to `local`,
+/// this instance's count, which it never drops below, when Redis is
+/// unavailable or missed some of this instance's joins.
+pub async fn room_count(state: &AppState, room_id: &str, local: usize) -> usize {
+    match occupancy(state, &[room_id.to_string()]).await {
+        Ok(counts) => counts.first().map_or(local, |&count| count.max(local)),
+        Err(e) => {
+            error!("Failed to count presence in room {}: {}", room_id, e);
+            local
+        }
+    }
+}
+
+/// Sockets in a room split by whether they have posted since joining.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RoomCounts {
+    pub participants: usize,
+    pub spectators: usize,
+}
+
+impl RoomCounts {
+    /// From live socket and participant counts; a participant whose socket
+    /// has already gone stale isn't counted twice.
+    pub fn new(sockets: usize, participants: usize) -> Self {
+        let participants = participants.min(sockets);
+        RoomCounts { participants, spectators: sockets - participants }
+    }
+}
+
+/// Participants and spectators of several rooms across all instances.
+pub async fn room_counts(state: &AppState, room_ids: &[String]) -> redis::RedisResult<Vec<RoomCounts>> {
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    let cutoff = Utc::now().timestamp_millis() - PRESENCE_TTL_MS;
+    let mut pipe = redis::pipe();
+    for room_id in room_ids {
+        pipe.cmd("ZCOUNT").arg(presence_key(room_id)).arg(cutoff).arg("+inf");
+        pipe.cmd("ZCOUNT").arg(participants_key(room_id)).arg(cutoff).arg("+inf");
+    }
+    let counts: Vec<usize> = pipe.query_async(&mut conn).await?;
+    Ok(counts.chunks(2).map(|pair| RoomCounts::new(pair[0], pair[1])).collect())
+}
+
+/// Everyone in a room across all instances.
+pub async fn room_users(state: &AppState, room_id: &str) -> redis::RedisResult<Vec<RoomUser>> {
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    let cutoff = Utc::now().timestamp_millis() - PRESENCE_TTL_MS;
+    let socket_ids: Vec<String> = redis::cmd("ZRANGEBYSCORE")
+        .arg(presence_key(room_id))
+        .arg(cutoff)
+        .arg("+inf")
+        .query_async(&mut conn)
+        .await?;
+    if socket_ids.is_empty() {
+        return Ok(vec![]);
+    }
+    let entries: Vec<Option<String>> = redis::cmd("HMGET")
+        .arg(presence_users_key(room_id))
+        .arg(&socket_ids)
+        .query_async(&mut conn)
+        .await?;
+    let users = entries.iter().flatten().filter_map(|entry| serde_json::from_str(entry).ok());
+    Ok(dedupe_users(users))
+}
+
+/// One entry per user however many sockets they have open, keeping their
+/// earliest join, oldest first.
+pub fn dedupe_users(users: impl IntoIterator<Item = RoomUser>) -> Vec<RoomUser> {
+    let mut by_id: HashMap<String, RoomUser> = HashMap::new();
+    for user in users {
+        match by_id.get(&user.id) {
+            Some(existing) if existing.joined_at <= user.joined_at => {}
+            _ => {
+                by_id.insert(user.id.clone(), user);
+            }
+        }
+    }
+    let mut users: Vec<RoomUser> = by_id.into_values().collect();
+    users.sort_by(|a, b| a.joined_at.cmp(&b.joined_at).then_with(|| a.id.cmp(&b.id)));
+    users
+}
+
+#[derive(Serialize)]
+pub struct RoomUsersResponse {
+    users: Vec<RoomUser>,
+    // Open sockets across all instances, as in RoomJoined
+    user_count: usize,
+    // Redis was unavailable, so only this instance's users are listed
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    local_only: bool,
+}
+
+// GET /api/rooms/:location_id/users - who's in the room; private rooms
+// list their users to members only
+pub async fn room_users_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<RoomUsersResponse>, AppError> {
+    crate::room_invites::require_read_access(&state, &location_id, user.as_ref()).await?;
+    let local = state.connections.read().await.get_room_users(&location_id);
+    match room_users(&state, &location_id).await {
+        Ok(users) => {
+            let user_count = room_count(&state, &location_id, local.len()).await;
+            Ok(Json(RoomUsersResponse { users, user_count, local_only: false }))
+        }
+        Err(e) => {
+            error!("Failed to load presence for room {}: {}", location_id, e);
+            Ok(Json(RoomUsersResponse {
+                user_count: local.len(),
+                users: dedupe_users(local.iter().map(RoomUser::from)),
+                local_only: true,
+            }))
+        }
+    }
+}
+
+/// Refreshes this instance's sockets so other instances keep counting them.
+pub fn spawn_heartbeat(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tick.tick().await;
+            let rooms = state.connections.read().await.socket_ids_by_room();
+            if rooms.is_empty() {
+                continue;
+            }
+            let Ok(mut conn) = state.redis_pool.get().await else {
+                continue;
+            };
+            let now = Utc::now().timestamp_millis();
+            let mut pipe = redis::pipe();
+            for (room_id, socket_ids) in &rooms {
+                let key = presence_key(room_id);
+                let participants = participants_key(room_id);
+                for socket_id in socket_ids {
+                    pipe.cmd("ZADD").arg(&key).arg("XX").arg(now).arg(socket_id).ignore();
+                    pipe.cmd("ZADD").arg(&participants).arg("XX").arg(now).arg(socket_id).ignore();
+                }
+                pipe.cmd("PEXPIRE").arg(&key).arg(PRESENCE_TTL_MS).ignore();
+                pipe.cmd("PEXPIRE").arg(presence_users_key(room_id)).arg(PRESENCE_TTL_MS).ignore();
+                pipe.cmd("PEXPIRE").arg(&participants).arg(PRESENCE_TTL_MS).ignore();
+            }
+            if let Err(e) = pipe.query_async::<_, ()>(&mut conn).await {
+                error!("Failed to refresh room presence: {}", e);
+            }
+        }
+    })
+}
+
+/// Rooms next to a full one: the neighbouring hexes of a hex room, or the
+/// hex containing a legacy coordinate room and its neighbours.
+pub fn nearby_rooms(room_id: &str) -> Vec<String> {
+    let (center, include_center) = match room_id.parse::<CellIndex>() {
+        Ok(cell) => (cell, false),
+        Err(_) => match legacy_room_cell(room_id) {
+            Some(cell) => (cell, true),
+            None => return vec![],
+        },
+    };
+    center
+        .grid_disk::<Vec<_>>(1)
+        .into_iter()
+        .filter(|cell| include_center || *cell != center)
+        .map(|cell| cell.to_string())
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSuggestion {
+    pub room_id: String,
+    pub user_count: usize,
+}
+
+/// Nearby rooms with room to spare, emptiest first.
+pub async fn suggest_rooms(state: &AppState, room_id: &str, max_users: i32) -> Vec<RoomSuggestion> {
+    let candidates = nearby_rooms(room_id);
+    if candidates.is_empty() {
+        return vec![];
+    }
+    let counts = match occupancy(state, &candidates).await {
+        Ok(counts) => counts,
+        Err(e) => {
+            error!("Failed to load occupancy near room {}: {}", room_id, e);
+            return vec![];
+        }
+    };
+    let mut suggestions: Vec<RoomSuggestion> = candidates
+        .into_iter()
+        .zip(counts)
+        .filter(|(_, count)| max_users <= 0 || (*count as i64) < max_users as i64)
+        .map(|(room_id, user_count)| RoomSuggestion { room_id, user_count })
+        .collect();
+    suggestions.sort_by_key(|suggestion| suggestion.user_count);
+    suggestions.truncate(MAX_SUGGESTIONS);
+    suggestions
+}
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use futures::StreamExt;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::Notify;
+use tracing::{error, info, warn};
+
+// Every channel a socket can listen on. redis 0.24 can't change a pub/sub
+// connection's subscriptions while its message stream is being read, so the
+// shared connection subscribes to these patterns once and messages for
+// channels with no local sockets are dropped here.
+const CHANNEL_PATTERNS: [&str; 4] = ["room:*", "hex:*", "user:*", "dm:*"];
+// Messages a slow socket can fall behind by before it starts missing them
+const CHANNEL_CAPACITY: usize = 256;
+// Delay before a dropped Redis connection is retried
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+
+/// One socket's view of a channel. Dropping it (including when the socket's
+/// task is aborted) unsubscribes.
+pub struct Subscription {
+    pubsub: Arc<PubSubMultiplexer>,
+    channel: String,
+    receiver: Option<broadcast::Receiver<Arc<str>>>,
+}
+
+impl Subscription {
+    pub async fn recv(&mut self) -> Result<Arc<str>, RecvError> {
+        match self.receiver.as_mut() {
+            Some(receiver) => receiver.recv().await,
+            None => Err(RecvError::Closed),
+        }
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.receiver.take();
+        self.pubsub.release(&self.channel);
+    }
+}
+
+/// The process's single Redis pub/sub connection, shared by every socket.
+/// Sockets subscribe to a channel here and receive its raw payloads through
+/// a `tokio::sync::broadcast` channel.
+#[derive(Default)]
+pub struct PubSubMultiplexer {
+    // channel -> sender with one receiver per subscribed socket
+    channels: RwLock<HashMap<String, broadcast::Sender<Arc<str>>>>,
+    connected: AtomicBool,
+    received: AtomicU64,
+    dispatched: AtomicU64,
+    reconnects: AtomicU64,
+    // Set at shutdown; the connection is dropped and not retried
+    stopped: AtomicBool,
+    stop: Notify,
+}
+
+impl PubSubMultiplexer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(self: &Arc<Self>, channel: &str) -> Subscription {
+        let existing = self.channels.read().unwrap().get(channel).map(|sender| sender.subscribe());
+        let receiver = existing.unwrap_or_else(|| {
+            self.channels
+                .write()
+                .unwrap()
+                .entry(channel.to_string())
+                .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+                .subscribe()
+        });
+        Subscription {
+            pubsub: self.clone(),
+            channel: channel.to_string(),
+            receiver: Some(receiver),
+        }
+    }
+
+    // Forgets a channel once its last receiver has been dropped
+    fn release(&self, channel: &str) {
+        let mut channels = self.channels.write().unwrap();
+        if channels.get(channel).is_some_and(|sender| sender.receiver_count() == 0) {
+            channels.remove(channel);
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.channels.read().unwrap().len()
+    }
+
+    /// Drops the shared connection, unsubscribing from every channel, and
+    /// doesn't reconnect.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+        self.stop.notify_one();
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    pub fn dispatch(&self, channel: &str, payload: Arc<str>) {
+        let _span = crate::telemetry::fanout_span(channel, &payload).map(tracing::Span::entered);
+        self.received.fetch_add(1, Ordering::Relaxed);
+        if let Some(sender) = self.channels.read().unwrap().get(channel) {
+            if sender.send(payload).is_ok() {
+                self.dispatched.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn spawn(self: Arc<Self>, redis: Arc<redis::Client>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            while !self.stopped.load(Ordering::Relaxed) {
+                self.run(&redis).await;
+                self.connected.store(false, Ordering::Relaxed);
+                if self.stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                self.reconnects.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        })
+    }
+
+    // Reads the shared connection until Redis drops it
+    async fn run(&self, redis: &redis::Client) {
+        let mut pubsub = match redis.get_async_connection().await {
+            Ok(conn) => conn.into_pubsub(),
+            Err(e) => {
+                error!("Failed to create Redis pub/sub connection: {}", e);
+                return;
+            }
+        };
+        for pattern in CHANNEL_PATTERNS {
+            if let Err(e) = pubsub.psubscribe(pattern).await {
+                error!("Failed to subscribe to Redis pattern {}: {}", pattern, e);
+                return;
+            }
+        }
+        self.connected.store(true, Ordering::Relaxed);
+        info!("Shared Redis pub/sub connection subscribed to {:?}", CHANNEL_PATTERNS);
+
+        let mut messages = pubsub.into_on_message();
+        loop {
+            let msg = tokio::select! {
+                msg = messages.next() => msg,
+                _ = self.stop.notified() => {
+                    info!("Leaving Redis pub/sub channels");
+                    return;
+                }
+            };
+            let Some(msg) = msg else {
+                break;
+            };
+            match msg.get_payload::<String>() {
+                Ok(payload) => self.dispatch(msg.get_channel_name(), payload.into()),
+                Err(e) => error!("Failed to parse Redis message: {}", e),
+            }
+        }
+        warn!("Shared Redis pub/sub connection dropped");
+    }
+
+    /// Prometheus text, served with the fan-out metrics.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, u64); 5] = [
+            ("chat_pubsub_connected", "gauge", "1 while the shared Redis pub/sub connection is up", self.is_connected() as u64),
+            ("chat_pubsub_channels", "gauge", "Channels with at least one local socket", self.channel_count() as u64),
+            ("chat_pubsub_received_total", "counter", "Messages read from the shared connection", self.received.load(Ordering::Relaxed)),
+            ("chat_pubsub_dispatched_total", "counter", "Messages handed to local sockets", self.dispatched.load(Ordering::Relaxed)),
+            ("chat_pubsub_reconnects_total", "counter", "Times the shared connection was re-established", self.reconnects.load(Ordering::Relaxed)),
+        ];
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+// Recent deliveries kept per room; percentiles are over this window
+pub const LAG_WINDOW: usize = 256;
+const INSTANCE_WINDOW: usize = 4096;
+const MAX_TRACKED_ROOMS: usize = 1000;
+// Rooms labelled individually in the metrics, worst p99 first
+const METRICS_TOP_ROOMS: usize = 10;
+const DEFAULT_REPORT_LIMIT: usize = 50;
+const MAX_REPORT_LIMIT: usize = 500;
+
+/// The `q` quantile (0 to 1) of ascending samples, by nearest rank.
+pub fn percentile(sorted: &[u64], q: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (q.clamp(0.0, 1.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.saturating_sub(1).min(sorted.len() - 1)]
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LagSummary {
+    pub samples: usize,
+    pub p50_ms: u64,
+    pub p90_ms: u64,
+    pub p99_ms: u64,
+    pub max_ms: u64,
+}
+
+/// The latest publish-to-delivery lags, oldest dropped first.
+#[derive(Debug, Clone)]
+pub struct LagWindow {
+    capacity: usize,
+    samples: VecDeque<u64>,
+    // Sequence number of the newest sample, for evicting quiet rooms
+    last_recorded: u64,
+}
+
+impl LagWindow {
+    pub fn new(capacity: usize) -> Self {
+        LagWindow { capacity, samples: VecDeque::with_capacity(capacity), last_recorded: 0 }
+    }
+
+    pub fn record(&mut self, lag_ms: u64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(lag_ms);
+    }
+
+    pub fn summary(&self) -> LagSummary {
+        let mut sorted: Vec<u64> = self.samples.iter().copied().collect();
+        sorted.sort_unstable();
+        LagSummary {
+            samples: sorted.len(),
+            p50_ms: percentile(&sorted, 0.5),
+            p90_ms: percentile(&sorted, 0.9),
+            p99_ms: percentile(&sorted, 0.99),
+            max_ms: sorted.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// The room a broadcast channel belongs to. Per-user channels aren't rooms.
+pub fn room_of_channel(channel: &str) -> Option<&str> {
+    if channel.starts_with("user:") {
+        return None;
+    }
+    Some(channel.strip_prefix("room:").unwrap_or(channel))
+}
+
+#[derive(Debug)]
+struct Windows {
+    instance: LagWindow,
+    rooms: HashMap<String, LagWindow>,
+    sequence: u64,
+}
+
+/// How long broadcasts take from publishing, on any instance, to reaching a
+/// socket on this one. Lags between instances include their clock skew;
+/// negative ones are counted as zero.
+#[derive(Debug)]
+pub struct PubSubLag {
+    windows: Mutex<Windows>,
+}
+
+impl Default for PubSubLag {
+    fn default() -> Self {
+        PubSubLag {
+            windows: Mutex::new(Windows {
+                instance: LagWindow::new(INSTANCE_WINDOW),
+                rooms: HashMap::new(),
+                sequence: 0,
+            }),
+        }
+    }
+}
+
+impl PubSubLag {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, channel: &str, published_at_ms: i64, delivered_at_ms: i64) {
+        let Some(room_id) = room_of_channel(channel) else {
+            return;
+        };
+        // Payloads from instances that don't stamp them yet
+        if published_at_ms <= 0 {
+            return;
+        }
+        let lag_ms = delivered_at_ms.saturating_sub(published_at_ms).max(0) as u64;
+
+        let mut windows = self.windows.lock().unwrap();
+        windows.sequence += 1;
+        let sequence = windows.sequence;
+        windows.instance.record(lag_ms);
+        if !windows.rooms.contains_key(room_id) && windows.rooms.len() >= MAX_TRACKED_ROOMS {
+            // Forget the room that has been quiet longest
+            let quietest = windows.rooms.iter().min_by_key(|(_, window)| window.last_recorded).map(|(id, _)| id.clone());
+            if let Some(quietest) = quietest {
+                windows.rooms.remove(&quietest);
+            }
+        }
+        let window = windows.rooms.entry(room_id.to_string()).or_insert_with(|| LagWindow::new(LAG_WINDOW));
+        window.record(lag_ms);
+        window.last_recorded = sequence;
+    }
+
+    pub fn instance_summary(&self) -> LagSummary {
+        self.windows.lock().unwrap().instance.summary()
+    }
+
+    /// Rooms by p99 lag, worst first.
+    pub fn worst_rooms(&self, limit: usize) -> Vec<RoomLag> {
+        let windows = self.windows.lock().unwrap();
+        let mut rooms: Vec<RoomLag> = windows
+            .rooms
+            .iter()
+            .map(|(room_id, window)| RoomLag { room_id: room_id.clone(), lag: window.summary() })
+            .collect();
+        rooms.sort_by(|a, b| b.lag.p99_ms.cmp(&a.lag.p99_ms).then_with(|| a.room_id.cmp(&b.room_id)));
+        rooms.truncate(limit);
+        rooms
+    }
+
+    /// Prometheus text, served with the fan-out metrics. Only the worst rooms
+    /// get their own series so the label set stays bounded.
+    pub fn render(&self, instance_id: &str) -> String {
+        let instance = self.instance_summary();
+        let mut out = String::new();
+        let _ = writeln!(out, "# HELP chat_pubsub_lag_ms Publish-to-delivery lag of broadcasts on this instance");
+        let _ = writeln!(out, "# TYPE chat_pubsub_lag_ms summary");
+        for (quantile, value) in [("0.5", instance.p50_ms), ("0.9", instance.p90_ms), ("0.99", instance.p99_ms)] {
+            let _ = writeln!(out, "chat_pubsub_lag_ms{{instance=\"{}\",quantile=\"{}\"}} {}", instance_id, quantile, value);
+        }
+        let _ = writeln!(out, "chat_pubsub_lag_ms_count{{instance=\"{}\"}} {}", instance_id, instance.samples);
+
+        let _ = writeln!(
+            out,
+            "# HELP chat_pubsub_room_lag_p99_ms p99 publish-to-delivery lag of the {} slowest rooms",
+            METRICS_TOP_ROOMS
+        );
+        let _ = writeln!(out, "# TYPE chat_pubsub_room_lag_p99_ms gauge");
+        for room in self.worst_rooms(METRICS_TOP_ROOMS) {
+            let _ = writeln!(
+                out,
+                "chat_pubsub_room_lag_p99_ms{{instance=\"{}\",room=\"{}\"}} {}",
+                instance_id,
+                room.room_id.replace('\\', "\\\\").replace('"', "\\\""),
+                room.lag.p99_ms
+            );
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomLag {
+    pub room_id: String,
+    #[serde(flatten)]
+    pub lag: LagSummary,
+}
+
+#[derive(Deserialize)]
+pub struct PubSubLagQuery {
+    limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct PubSubLagResponse {
+    instance_id: String,
+    instance: LagSummary,
+    rooms: Vec<RoomLag>,
+}
+
+// GET /api/admin/pubsub-lag?limit= - this instance's lag and its slowest
+// rooms; admins only. Each instance answers for itself.
+pub async fn pubsub_lag_handler(
+    Query(params): Query<PubSubLagQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<PubSubLagResponse>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_REPORT_LIMIT).min(MAX_REPORT_LIMIT);
+    Ok(Json(PubSubLagResponse {
+        instance_id: state.instance_id.clone(),
+        instance: state.pubsub_lag.instance_summary(),
+        rooms: state.pubsub_lag.worst_rooms(limit),
+    }))
+}
@@ -0,0 +1,71 @@
+use chrono::Utc;
+use tracing::{error, warn};
+
+// Token bucket kept in Redis so every instance draws from the same bucket.
+// Refills continuously at `per_minute` and holds at most `per_minute` tokens,
+// so a quiet user can send a short burst. Returns 0 when a token was taken,
+// otherwise the milliseconds until the next one.
+const TOKEN_BUCKET_SCRIPT: &str = r#"
+local capacity = tonumber(ARGV[1])
+local refill_per_ms = tonumber(ARGV[2])
+local now = tonumber(ARGV[3])
+local bucket = redis.call('HMGET', KEYS[1], 'tokens', 'ts')
+local tokens = tonumber(bucket[1]) or capacity
+local ts = tonumber(bucket[2]) or now
+tokens = math.min(capacity, tokens + math.max(0, now - ts) * refill_per_ms)
+local retry_ms = 0
+if tokens >= 1 then
+    tokens = tokens - 1
+else
+    retry_ms = math.ceil((1 - tokens) / refill_per_ms)
+end
+redis.call('HSET', KEYS[1], 'tokens', tostring(tokens), 'ts', now)
+redis.call('PEXPIRE', KEYS[1], math.ceil(capacity / refill_per_ms) + 1000)
+return retry_ms
+"#;
+
+/// Seconds to tell a client to wait, rounding partial seconds up.
+pub fn retry_after_seconds(retry_ms: u64) -> u64 {
+    retry_ms.div_ceil(1000).max(1)
+}
+
+/// Enforces a room's `rate_limit` (messages per minute) for one user.
+/// Returns the seconds until the user may send again when they are over it.
+/// A limit of zero or less means the room is unlimited.
+pub async fn check_room_rate_limit(
+    pool: &deadpool_redis::Pool,
+    room_id: &str,
+    user_id: &str,
+    per_minute: i32,
+) -> Result<(), u64> {
+    if per_minute <= 0 {
+        return Ok(());
+    }
+
+    let key = format!("room_rl:{}:{}", room_id, user_id);
+    let refill_per_ms = per_minute as f64 / 60_000.0;
+    let mut conn = match pool.get().await {
+        Ok(conn) => conn,
+        Err(_) => return Ok(()),
+    };
+    let result: redis::RedisResult<u64> = redis::Script::new(TOKEN_BUCKET_SCRIPT)
+        .key(&key)
+        .arg(per_minute)
+        .arg(refill_per_ms)
+        .arg(Utc::now().timestamp_millis())
+        .invoke_async(&mut conn)
+        .await;
+
+    match result {
+        Ok(0) => Ok(()),
+        Ok(retry_ms) => {
+            warn!("Rate limiting user {} in room {} ({} messages/minute)", user_id, room_id, per_minute);
+            Err(retry_after_seconds(retry_ms))
+        }
+        Err(e) => {
+            // Fail open: a Redis hiccup shouldn't stop the room talking
+            error!("Failed to check room rate limit for {} in {}: {}", user_id, room_id, e);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    models::{Reaction, WsMessage},
+    rate_limit::retry_after_seconds,
+    websocket::publish_to_room,
+    AppError, AppState,
+};
+
+// Reactions one user may add or remove in a room per window
+pub const REACTIONS_PER_WINDOW: u32 = 30;
+pub const REACTION_WINDOW_MS: i64 = 10_000;
+// Changes to a message this soon after its last broadcast wait for the next
+// flush, which sends its totals once
+pub const COALESCE_INTERVAL_MS: i64 = 1_000;
+const FLUSH_TICK: Duration = Duration::from_millis(250);
+const MAX_EMOJI_BYTES: usize = 32;
+// Windows kept before stale ones are swept
+const MAX_TRACKED_WINDOWS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReactionCount {
+    pub emoji: String,
+    pub count: u32,
+}
+
+/// Totals per emoji, most used first.
+pub fn reaction_counts(reactions: &[Reaction]) -> Vec<ReactionCount> {
+    let mut totals: HashMap<&str, u32> = HashMap::new();
+    for reaction in reactions {
+        *totals.entry(reaction.emoji.as_str()).or_default() += 1;
+    }
+    let mut counts: Vec<ReactionCount> = totals
+        .into_iter()
+        .map(|(emoji, count)| ReactionCount { emoji: emoji.to_string(), count })
+        .collect();
+    counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.emoji.cmp(&b.emoji)));
+    counts
+}
+
+pub fn validate_emoji(emoji: &str) -> Result<(), String> {
+    if emoji.is_empty() || emoji.len() > MAX_EMOJI_BYTES {
+        return Err(format!("Reactions must be 1 to {} bytes", MAX_EMOJI_BYTES));
+    }
+    if emoji.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return Err("Reactions can't contain spaces or control characters".to_string());
+    }
+    Ok(())
+}
+
+/// Fixed-window reaction counts per room and user. Kept in memory instead of
+/// Redis like the message limits: a user's socket stays on one instance, and
+/// a reaction slipping through a restart costs little.
+#[derive(Debug, Default)]
+pub struct ReactionLimiter {
+    // (room, user) -> (window start, reactions in it)
+    windows: Mutex<HashMap<(String, String), (i64, u32)>>,
+}
+
+impl ReactionLimiter {
+    /// Takes one reaction from the user's window, or returns the seconds
+    /// until the next window opens.
+    pub fn check(&self, room_id: &str, user_id: &str, now_ms: i64) -> Result<(), u64> {
+        let window_start = now_ms - now_ms.rem_euclid(REACTION_WINDOW_MS);
+        let mut windows = self.windows.lock().unwrap();
+        if windows.len() >= MAX_TRACKED_WINDOWS {
+            windows.retain(|_, (start, _)| *start == window_start);
+        }
+        let window = windows.entry((room_id.to_string(), user_id.to_string())).or_insert((window_start, 0));
+        if window.0 != window_start {
+            *window = (window_start, 0);
+        }
+        if window.1 >= REACTIONS_PER_WINDOW {
+            return Err(retry_after_seconds((window_start + REACTION_WINDOW_MS - now_ms) as u64));
+        }
+        window.1 += 1;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct Tracked {
+    room_id: String,
+    last_sent_ms: i64,
+    // A change arrived since `last_sent_ms`
+    pending: bool,
+}
+
+/// Holds back reaction broadcasts for busy messages. The first change to a
+/// message goes out straight away; later ones within `COALESCE_INTERVAL_MS`
+/// are folded into one update of its totals.
+#[derive(Debug, Default)]
+pub struct ReactionCoalescer {
+    messages: Mutex<HashMap<String, Tracked>>,
+}
+
+impl ReactionCoalescer {
+    /// Whether to broadcast this change now; otherwise a flush will.
+    pub fn record(&self, room_id: &str, message_id: &str, now_ms: i64) -> bool {
+        let mut messages = self.messages.lock().unwrap();
+        match messages.get_mut(message_id) {
+            Some(tracked) if now_ms - tracked.last_sent_ms < COALESCE_INTERVAL_MS => {
+                tracked.pending = true;
+                false
+            }
+            _ => {
+                let tracked = Tracked { room_id: room_id.to_string(), last_sent_ms: now_ms, pending: false };
+                messages.insert(message_id.to_string(), tracked);
+                true
+            }
+        }
+    }
+
+    /// (room, message) pairs whose held-back changes are due, marked as sent.
+    /// Quiet messages are forgotten.
+    pub fn due(&self, now_ms: i64) -> Vec<(String, String)> {
+        let mut messages = self.messages.lock().unwrap();
+        let mut due = Vec::new();
+        for (message_id, tracked) in messages.iter_mut() {
+            if tracked.pending && now_ms - tracked.last_sent_ms >= COALESCE_INTERVAL_MS {
+                tracked.pending = false;
+                tracked.last_sent_ms = now_ms;
+                due.push((tracked.room_id.clone(), message_id.clone()));
+            }
+        }
+        messages.retain(|_, tracked| tracked.pending || now_ms - tracked.last_sent_ms < COALESCE_INTERVAL_MS);
+        due
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Reactions {
+    pub limiter: ReactionLimiter,
+    pub coalescer: ReactionCoalescer,
+}
+
+/// Adds or removes the user's reaction on a message in `room_id`.
+pub async fn react(
+    state: &AppState,
+    room_id: &str,
+    user_id: &str,
+    message_id: &str,
+    emoji: &str,
+    remove: bool,
+) -> Result<(), AppError> {
+    validate_emoji(emoji).map_err(AppError::BadRequest)?;
+    let now_ms = Utc::now().timestamp_millis();
+    state
+        .reactions
+        .limiter
+        .check(room_id, user_id, now_ms)
+        .map_err(|retry_after| AppError::TooManyRequests { retry_after })?;
+
+    let id = ObjectId::parse_str(message_id).map_err(|_| AppError::NotFound)?;
+    let matched = if remove {
+        state.db.remove_reaction(&id, room_id, user_id, emoji).await?
+    } else {
+        state.db.add_reaction(&id, room_id, user_id, emoji).await?
+    };
+    if !matched {
+        return Err(AppError::NotFound);
+    }
+
+    if state.reactions.coalescer.record(room_id, message_id, now_ms) {
+        broadcast_counts(state, room_id, &id).await;
+    }
+    Ok(())
+}
+
+async fn broadcast_counts(state: &AppState, room_id: &str, id: &ObjectId) {
+    match state.db.get_message(id).await {
+        Ok(Some(message)) => {
+            let update = WsMessage::ReactionsUpdated {
+                message_id: id.to_hex(),
+                counts: reaction_counts(&message.reactions),
+            };
+            publish_to_room(state, room_id, update).await;
+        }
+        Ok(None) => {}
+        Err(e) => error!("Failed to load reactions of {}: {}", id, e),
+    }
+}
+
+/// Sends the totals of busy messages whose updates were held back.
+pub fn spawn_flush(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(FLUSH_TICK);
+        loop {
+            tick.tick().await;
+            for (room_id, message_id) in state.reactions.coalescer.due(Utc::now().timestamp_millis()) {
+                if let Ok(id) = ObjectId::parse_str(&message_id) {
+                    broadcast_counts(&state, &room_id, &id).await;
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,172 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::{
+    bson::{self, doc},
+    options::{CountOptions, FindOptions, UpdateOptions},
+    Collection,
+};
+use futures::TryStreamExt;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{
+    auth::AuthUser,
+    models::Message,
+    shared_rooms::{is_location_room, load_privacy},
+    AppError, AppState,
+};
+
+// Badges show "99+" past this, so there's no point counting further
+pub const MAX_UNREAD_COUNT: u64 = 100;
+
+/// How far a user has read in a room. Advanced when they join or leave a
+/// room socket, since everything in between was delivered live.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReadCursor {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub room_id: String,
+    pub user_id: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub last_read_at: DateTime<Utc>,
+}
+
+fn cursors(state: &AppState) -> Collection<ReadCursor> {
+    state.database.collection("room_read_cursors")
+}
+
+fn cursor_id(room_id: &str, user_id: &str) -> String {
+    format!("{}:{}", room_id, user_id)
+}
+
+/// Moves the cursor forward to `at`; it never moves backwards.
+pub async fn mark_read(state: &AppState, room_id: &str, user_id: &str, at: DateTime<Utc>) -> mongodb::error::Result<()> {
+    advance(state, room_id, user_id, at, false).await
+}
+
+// Socket visits also move `visited_at`, which only the server sets, so shared
+// rooms can't be made up with `PUT /api/rooms/:location_id/read`
+async fn advance(state: &AppState, room_id: &str, user_id: &str, at: DateTime<Utc>, visited: bool) -> mongodb::error::Result<()> {
+    let at = bson::DateTime::from_millis(at.timestamp_millis());
+    let mut latest = doc! { "last_read_at": at };
+    if visited {
+        latest.insert("visited_at", at);
+    }
+    cursors(state)
+        .update_one(
+            doc! { "_id": cursor_id(room_id, user_id) },
+            doc! {
+                "$max": latest,
+                "$setOnInsert": { "room_id": room_id, "user_id": user_id },
+            },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Users who have had the room open since `since`, at most `limit` of them.
+pub(crate) async fn recent_readers(
+    state: &AppState,
+    room_id: &str,
+    since: DateTime<Utc>,
+    limit: i64,
+) -> mongodb::error::Result<Vec<String>> {
+    let since = bson::DateTime::from_millis(since.timestamp_millis());
+    let options = FindOptions::builder().limit(limit).build();
+    let readers: Vec<ReadCursor> = cursors(state)
+        .find(doc! { "room_id": room_id, "last_read_at": { "$gte": since } }, options)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(readers.into_iter().map(|cursor| cursor.user_id).collect())
+}
+
+/// Every room the user has had open, which is how far their rooms are known.
+pub(crate) async fn rooms_read_by(state: &AppState, user_id: &str) -> mongodb::error::Result<Vec<String>> {
+    let cursors: Vec<ReadCursor> = cursors(state).find(doc! { "user_id": user_id }, None).await?.try_collect().await?;
+    Ok(cursors.into_iter().map(|cursor| cursor.room_id).collect())
+}
+
+// Socket join/leave shouldn't fail on a cursor write. Visits to location
+// rooms aren't recorded for users who turned location history off.
+pub(crate) async fn advance_cursor(state: &AppState, room_id: &str, user_id: &str) {
+    if is_location_room(room_id) && !load_privacy(state, user_id).await.store_location_history {
+        return;
+    }
+    if let Err(e) = advance(state, room_id, user_id, Utc::now(), true).await {
+        error!("Failed to update read cursor for {} in {}: {}", user_id, room_id, e);
+    }
+}
+
+#[derive(Deserialize)]
+pub struct UnreadQuery {
+    user_id: String,
+}
+
+#[derive(Serialize)]
+pub struct UnreadResponse {
+    room_id: String,
+    user_id: String,
+    // Capped at MAX_UNREAD_COUNT
+    unread: u64,
+    last_read_at: Option<String>,
+}
+
+// GET /api/rooms/:location_id/unread?user_id=... - members only in private
+// rooms
+pub async fn get_unread_count(
+    Path(location_id): Path<String>,
+    Query(params): Query<UnreadQuery>,
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<UnreadResponse>, AppError> {
+    crate::room_invites::require_read_access(&state, &location_id, user.as_ref()).await?;
+    let cursor = cursors(&state)
+        .find_one(doc! { "_id": cursor_id(&location_id, &params.user_id) }, None)
+        .await?;
+
+    // Own messages never count as unread
+    let mut filter = doc! {
+        "room_id": &location_id,
+        "deleted": false,
+        "user_id": { "$ne": &params.user_id },
+    };
+    if let Some(cursor) = &cursor {
+        filter.insert(
+            "timestamp",
+            doc! { "$gt": bson::DateTime::from_millis(cursor.last_read_at.timestamp_millis()) },
+        );
+    }
+    let unread = state
+        .database
+        .collection::<Message>("messages")
+        .count_documents(filter, CountOptions::builder().limit(MAX_UNREAD_COUNT).build())
+        .await?;
+
+    Ok(Json(UnreadResponse {
+        room_id: location_id,
+        user_id: params.user_id,
+        unread,
+        last_read_at: cursor.map(|cursor| cursor.last_read_at.to_rfc3339()),
+    }))
+}
+
+// PUT /api/rooms/:location_id/read - e.g. after reading history over REST;
+// only in rooms the user may read
+pub async fn mark_read_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    crate::room_invites::require_access(&state, &room, Some(&user)).await?;
+    if crate::room_bans::find_ban(&state, &location_id, &user.user_id).await.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    mark_read(&state, &location_id, &user.user_id, Utc::now()).await?;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
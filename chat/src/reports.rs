@@ -0,0 +1,204 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    abuse::{record_signal, AbuseSignal},
+    auth::AuthUser,
+    models::Message,
+    AppError, AppState,
+};
+
+const MAX_REASON_CHARS: usize = 500;
+const MAX_NOTE_CHARS: usize = 500;
+const DEFAULT_QUEUE_LIMIT: i64 = 50;
+const MAX_QUEUE_LIMIT: i64 = 200;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportStatus {
+    #[default]
+    Open,
+    Resolved,
+}
+
+/// How a moderator settled a report.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportResolution {
+    // Nothing wrong with the message
+    Dismissed,
+    // The message or its author was dealt with
+    Actioned,
+}
+
+/// One user's report of a room message. A user reports a message at most
+/// once.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MessageReport {
+    // "message_id:reporter_id"
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub message_id: ObjectId,
+    pub room_id: String,
+    pub author_id: String,
+    // The message as it was reported, in case it is edited or deleted
+    pub content: String,
+    pub reporter_id: String,
+    pub reason: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub status: ReportStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolution: Option<ReportResolution>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resolved_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "crate::message_ttl::bson_datetime_option")]
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+fn reports(state: &AppState) -> Collection<MessageReport> {
+    state.database.collection("message_reports")
+}
+
+fn report_id(message_id: &ObjectId, reporter_id: &str) -> String {
+    format!("{}:{}", message_id.to_hex(), reporter_id)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReportRequest {
+    pub reason: String,
+}
+
+impl ReportRequest {
+    /// The report of `message` by `reporter_id` this request describes.
+    pub fn into_report(self, message: &Message, reporter_id: &str) -> Result<MessageReport, String> {
+        let message_id = message.id.ok_or_else(|| "Message has no id".to_string())?;
+        if message.user_id == reporter_id {
+            return Err("You can't report your own message".to_string());
+        }
+        let reason = self.reason.trim().to_string();
+        if reason.is_empty() || reason.chars().count() > MAX_REASON_CHARS {
+            return Err(format!("reason must be 1-{} characters", MAX_REASON_CHARS));
+        }
+        Ok(MessageReport {
+            id: report_id(&message_id, reporter_id),
+            message_id,
+            room_id: message.room_id.clone(),
+            author_id: message.user_id.clone(),
+            content: message.content.clone(),
+            reporter_id: reporter_id.to_string(),
+            reason,
+            created_at: Utc::now(),
+            status: ReportStatus::Open,
+            resolution: None,
+            resolved_by: None,
+            note: None,
+            resolved_at: None,
+        })
+    }
+}
+
+// POST /api/messages/:message_id/report - report a room message to moderators
+pub async fn report_message_handler(
+    Path(message_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<ReportRequest>,
+) -> Result<Json<MessageReport>, AppError> {
+    let message_id = ObjectId::parse_str(&message_id).map_err(|_| AppError::BadRequest("Invalid message id".to_string()))?;
+    let message = state.db.get_message(&message_id).await?.filter(|message| !message.deleted).ok_or(AppError::NotFound)?;
+    let report = req.into_report(&message, &user.user_id).map_err(AppError::BadRequest)?;
+    match reports(&state).insert_one(&report, None).await {
+        Ok(_) => {}
+        Err(e) if crate::db::is_duplicate_key(&e) => {
+            return Err(AppError::Conflict("You have already reported this message".to_string()));
+        }
+        Err(e) => return Err(e.into()),
+    }
+    record_signal(&state.redis_pool, &report.author_id, AbuseSignal::ReportReceived).await;
+    info!("User {} reported message {} in room {}", user.user_id, message_id, report.room_id);
+    Ok(Json(report))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQueueQuery {
+    // Open by default
+    pub status: Option<ReportStatus>,
+    pub room_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+// GET /api/admin/reports?status=&room_id=&limit= - reports oldest first;
+// moderators only
+pub async fn list_reports_handler(
+    Query(query): Query<ReportQueueQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<MessageReport>>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let limit = query.limit.unwrap_or(DEFAULT_QUEUE_LIMIT).clamp(1, MAX_QUEUE_LIMIT);
+    let status = mongodb::bson::to_bson(&query.status.unwrap_or_default()).map_err(|_| AppError::InternalServerError)?;
+    let mut filter = doc! { "status": status };
+    if let Some(room_id) = &query.room_id {
+        filter.insert("room_id", room_id);
+    }
+    let options = FindOptions::builder().sort(doc! { "created_at": 1 }).limit(limit).build();
+    Ok(Json(reports(&state).find(filter, options).await?.try_collect().await?))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveReportRequest {
+    pub resolution: ReportResolution,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+// POST /api/admin/reports/:report_id/resolve - close a report
+pub async fn resolve_report_handler(
+    Path(report_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<ResolveReportRequest>,
+) -> Result<Json<MessageReport>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let note = req.note.map(|note| note.trim().to_string()).filter(|note| !note.is_empty());
+    if note.as_ref().is_some_and(|note| note.chars().count() > MAX_NOTE_CHARS) {
+        return Err(AppError::BadRequest(format!("note must be at most {} characters", MAX_NOTE_CHARS)));
+    }
+    let mut update = doc! {
+        "status": mongodb::bson::to_bson(&ReportStatus::Resolved).map_err(|_| AppError::InternalServerError)?,
+        "resolution": mongodb::bson::to_bson(&req.resolution).map_err(|_| AppError::InternalServerError)?,
+        "resolved_by": &user.user_id,
+        "resolved_at": mongodb::bson::DateTime::now(),
+    };
+    if let Some(note) = note {
+        update.insert("note", note);
+    }
+    let options = mongodb::options::FindOneAndUpdateOptions::builder()
+        .return_document(mongodb::options::ReturnDocument::After)
+        .build();
+    let report = reports(&state)
+        .find_one_and_update(doc! { "_id": &report_id }, doc! { "$set": update }, options)
+        .await?
+        .ok_or(AppError::NotFound)?;
+    info!("Moderator {} resolved report {} as {:?}", user.user_id, report_id, req.resolution);
+    Ok(Json(report))
+}
@@ -0,0 +1,117 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::{bson::doc, Collection};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{auth::AuthUser, models::WsMessage, room_roles::require_moderator, websocket::publish_to_room, AppError, AppState};
+
+const MAX_REASON_CHARS: usize = 200;
+
+/// A user kept out of one room until a moderator lifts it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomBan {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub room_id: String,
+    pub user_id: String,
+    pub banned_by: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn ban_id(room_id: &str, user_id: &str) -> String {
+    format!("{}:{}", room_id, user_id)
+}
+
+fn bans(state: &AppState) -> Collection<RoomBan> {
+    state.database.collection("room_bans")
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BanRequest {
+    pub user_id: String,
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl BanRequest {
+    /// The ban this request describes, with an empty reason left out.
+    pub fn into_ban(self, room_id: &str, banned_by: &str) -> Result<RoomBan, String> {
+        if self.user_id.trim().is_empty() {
+            return Err("user_id is required".to_string());
+        }
+        let reason = self.reason.map(|reason| reason.trim().to_string()).filter(|reason| !reason.is_empty());
+        if reason.as_ref().is_some_and(|reason| reason.chars().count() > MAX_REASON_CHARS) {
+            return Err(format!("reason must be at most {} characters", MAX_REASON_CHARS));
+        }
+        Ok(RoomBan {
+            id: ban_id(room_id, &self.user_id),
+            room_id: room_id.to_string(),
+            user_id: self.user_id,
+            banned_by: banned_by.to_string(),
+            reason,
+            created_at: Utc::now(),
+        })
+    }
+}
+
+/// The user's ban from the room, if any. Fails open if MongoDB can't be
+/// read, like other join checks.
+pub async fn find_ban(state: &AppState, room_id: &str, user_id: &str) -> Option<RoomBan> {
+    match bans(state).find_one(doc! { "_id": ban_id(room_id, user_id) }, None).await {
+        Ok(ban) => ban,
+        Err(e) => {
+            error!("Failed to check bans of {} in room {}: {}", user_id, room_id, e);
+            None
+        }
+    }
+}
+
+/// What a banned user's sockets are sent before being closed, and what a
+/// banned join is refused with.
+pub fn banned_notice(ban: &RoomBan) -> WsMessage {
+    WsMessage::RoomBanned { room_id: ban.room_id.clone(), user_id: ban.user_id.clone(), reason: ban.reason.clone() }
+}
+
+// POST /api/rooms/:location_id/ban - ban a user from the room and close
+// their sockets in it; moderators only, and room moderators can't ban the
+// owner or each other
+pub async fn ban_user_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<BanRequest>,
+) -> Result<Json<RoomBan>, AppError> {
+    let ban = req.into_ban(&location_id, &user.user_id).map_err(AppError::BadRequest)?;
+    require_moderator(&state, &location_id, &user, Some(&ban.user_id)).await?;
+    if ban.user_id == user.user_id {
+        return Err(AppError::BadRequest("Moderators can't ban themselves".to_string()));
+    }
+    let options = mongodb::options::ReplaceOptions::builder().upsert(true).build();
+    bans(&state).replace_one(doc! { "_id": &ban.id }, &ban, options).await?;
+    info!("Moderator {} banned {} from room {}", user.username, ban.user_id, location_id);
+    // Every instance closes the user's sockets subscribed to the room
+    publish_to_room(&state, &location_id, banned_notice(&ban)).await;
+    Ok(Json(ban))
+}
+
+// DELETE /api/rooms/:location_id/ban/:user_id - lift a ban
+pub async fn unban_user_handler(
+    Path((location_id, user_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_moderator(&state, &location_id, &user, Some(&user_id)).await?;
+    let result = bans(&state).delete_one(doc! { "_id": ban_id(&location_id, &user_id) }, None).await?;
+    if result.deleted_count == 0 {
+        return Err(AppError::NotFound);
+    }
+    info!("Moderator {} lifted the ban of {} from room {}", user.username, user_id, location_id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -0,0 +1,74 @@
+use crate::{
+    hex::{lat_lng, DEFAULT_RESOLUTION},
+    local_chat::parse_coordinates_from_location_id,
+};
+use h3o::CellIndex;
+use redis::AsyncCommands;
+use serde::Serialize;
+use tracing::error;
+
+// Legacy rooms registered against a hex stay linked for a day after their last join
+const BRIDGE_TTL_SECONDS: i64 = 24 * 60 * 60;
+
+/// How legacy `lat_lng` rooms relate to the hex room containing them.
+///
+/// The intended migration path is `off` -> `crosspost` (both audiences see
+/// each other's messages) -> `redirect` (legacy joins are sent to the hex and
+/// an admin moves the legacy history over).
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BridgeMode {
+    Off,
+    CrossPost,
+    Redirect,
+}
+
+impl BridgeMode {
+    pub fn from_env() -> Self {
+        match std::env::var("LEGACY_ROOM_BRIDGE").as_deref() {
+            Ok("crosspost") => BridgeMode::CrossPost,
+            Ok("redirect") => BridgeMode::Redirect,
+            _ => BridgeMode::Off,
+        }
+    }
+}
+
+/// The hex a legacy coordinate room falls into, if its id is a `lat_lng` pair.
+pub fn legacy_room_cell(location_id: &str) -> Option<CellIndex> {
+    let (lat, lon) = parse_coordinates_from_location_id(location_id)?;
+    let coordinates = lat_lng(lat, lon).ok()?;
+    Some(coordinates.to_cell(DEFAULT_RESOLUTION))
+}
+
+fn bridge_key(h3_index: &str) -> String {
+    format!("hex_bridge:{}", h3_index)
+}
+
+/// Records that a legacy room is live inside a hex so hex messages can be
+/// cross-posted back to it.
+pub async fn register_legacy_room(pool: &deadpool_redis::Pool, h3_index: &str, location_id: &str) {
+    let key = bridge_key(h3_index);
+    match pool.get().await {
+        Ok(mut conn) => {
+            let result: redis::RedisResult<()> = redis::pipe()
+                .sadd(&key, location_id)
+                .expire(&key, BRIDGE_TTL_SECONDS)
+                .query_async(&mut conn)
+                .await;
+            if let Err(e) = result {
+                error!("Failed to register legacy room {} for hex {}: {}", location_id, h3_index, e);
+            }
+        }
+        Err(e) => error!("Failed to get Redis connection for room bridge: {}", e),
+    }
+}
+
+pub async fn legacy_rooms_for_hex(pool: &deadpool_redis::Pool, h3_index: &str) -> Vec<String> {
+    match pool.get().await {
+        Ok(mut conn) => conn.smembers(bridge_key(h3_index)).await.unwrap_or_default(),
+        Err(e) => {
+            error!("Failed to get Redis connection for room bridge: {}", e);
+            Vec::new()
+        }
+    }
+}
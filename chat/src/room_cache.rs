@@ -0,0 +1,257 @@
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    models::{ChatRoom, Message, WsMessage},
+    AppError, AppState,
+};
+
+/// Rooms by their latest join or message, so a booting instance knows which
+/// rooms its reconnecting clients are about to ask for.
+pub const HOT_ROOMS_KEY: &str = "hot_rooms";
+const MAX_HOT_ROOMS: isize = 1000;
+// Long enough to cover a reconnect storm after a restart
+pub const CACHE_TTL_SECONDS: u64 = 120;
+// Generations outlive any load that could still be writing a stale copy
+const GENERATION_TTL_SECONDS: u64 = 3600;
+const DEFAULT_WARM_ROOMS: usize = 100;
+// Rooms loaded from MongoDB at once while warming
+const WARM_CONCURRENCY: usize = 8;
+
+fn room_key(room_id: &str) -> String {
+    format!("room_cache:{}", room_id)
+}
+
+fn history_key(room_id: &str) -> String {
+    format!("room_history:{}", room_id)
+}
+
+fn generation_key(key: &str) -> String {
+    format!("{}:gen", key)
+}
+
+// Caches a value only if the key wasn't invalidated while it was loaded,
+// so a slow load can't put back what a newer write just dropped
+const SET_IF_CURRENT_SCRIPT: &str = r#"
+if (redis.call('GET', KEYS[2]) or '0') == ARGV[2] then
+    redis.call('SET', KEYS[1], ARGV[1], 'EX', ARGV[3])
+end
+return 1
+"#;
+
+/// Broadcasts that change what a room's latest page of history looks like.
+pub fn changes_history(message: &WsMessage) -> bool {
+    matches!(
+        message,
+        WsMessage::NewMessage(_)
+            | WsMessage::ReactionsUpdated { .. }
+            | WsMessage::RsvpUpdated { .. }
+            | WsMessage::ThreadUpdated { .. }
+            | WsMessage::BulkDelete { .. }
+    )
+}
+
+/// The room or hex a broadcast channel belongs to.
+pub fn room_of_channel(channel: &str) -> Option<&str> {
+    channel.strip_prefix("room:").or_else(|| channel.strip_prefix("hex:"))
+}
+
+/// Rooms to warm on boot, from ROOM_WARM_CACHE_SIZE; 0 turns warming off.
+pub fn warm_rooms_from_env() -> usize {
+    std::env::var("ROOM_WARM_CACHE_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_WARM_ROOMS)
+}
+
+// The cached value, or the key's generation to cache a fresh load under.
+// Neither when Redis is unavailable.
+async fn get_cached<T: DeserializeOwned>(state: &AppState, key: &str) -> Result<T, Option<String>> {
+    let mut conn = state.redis_pool.get().await.map_err(|_| None)?;
+    let (payload, generation): (Option<String>, Option<String>) = redis::pipe()
+        .cmd("GET")
+        .arg(key)
+        .cmd("GET")
+        .arg(generation_key(key))
+        .query_async(&mut conn)
+        .await
+        .map_err(|_| None)?;
+    match payload.and_then(|payload| serde_json::from_str(&payload).ok()) {
+        Some(value) => Ok(value),
+        None => Err(Some(generation.unwrap_or_else(|| "0".to_string()))),
+    }
+}
+
+async fn set_cached<T: Serialize>(state: &AppState, key: &str, generation: Option<String>, value: &T) {
+    let (Some(generation), Ok(mut conn), Ok(payload)) = (generation, state.redis_pool.get().await, serde_json::to_string(value)) else {
+        return;
+    };
+    let result: redis::RedisResult<i64> = redis::Script::new(SET_IF_CURRENT_SCRIPT)
+        .key(key)
+        .key(generation_key(key))
+        .arg(payload)
+        .arg(generation)
+        .arg(CACHE_TTL_SECONDS)
+        .invoke_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to cache {}: {}", key, e);
+    }
+}
+
+async fn invalidate(state: &AppState, key: &str) {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::pipe()
+        .cmd("INCR")
+        .arg(generation_key(key))
+        .ignore()
+        .cmd("EXPIRE")
+        .arg(generation_key(key))
+        .arg(GENERATION_TTL_SECONDS)
+        .ignore()
+        .cmd("DEL")
+        .arg(key)
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to drop cached {}: {}", key, e);
+    }
+}
+
+/// The room as sockets joining it see it. Served from the cache, so its
+/// `active_users` and `last_message_at` may lag by up to the TTL; settings
+/// changes drop the cached copy. Rooms are only created by
+/// `room_quota::room_for`, so a missing one is `NotFound`.
+pub async fn room(state: &AppState, room_id: &str) -> Result<ChatRoom, AppError> {
+    room_with(state, room_id, async { state.db.get_room(room_id).await?.ok_or(AppError::NotFound) }).await
+}
+
+/// As `room`, with `load` getting or creating the room on a cache miss.
+pub async fn room_with<E>(
+    state: &AppState,
+    room_id: &str,
+    load: impl std::future::Future<Output = Result<ChatRoom, E>>,
+) -> Result<ChatRoom, E> {
+    let generation = match get_cached(state, &room_key(room_id)).await {
+        Ok(room) => return Ok(room),
+        Err(generation) => generation,
+    };
+    let room = load.await?;
+    set_cached(state, &room_key(room_id), generation, &room).await;
+    Ok(room)
+}
+
+/// The room's latest page of history, oldest first, as sent to joining
+/// sockets and sized for the room's activity. The largest page is cached,
+/// so the size follows the clock between loads.
+pub async fn latest_page(state: &AppState, room_id: &str) -> mongodb::error::Result<Vec<Message>> {
+    let messages = match get_cached(state, &history_key(room_id)).await {
+        Ok(messages) => messages,
+        Err(generation) => {
+            let messages = state.db.get_messages(room_id, state.history.max_page as i64, None, None).await?;
+            set_cached(state, &history_key(room_id), generation, &messages).await;
+            messages
+        }
+    };
+    Ok(state.history.fit(messages, Utc::now()))
+}
+
+/// `latest_page` as `viewer` sees it. The cached page leaves out every
+/// shadow-banned message, so shadow-banned viewers get theirs from MongoDB.
+pub async fn latest_page_for(state: &AppState, room_id: &str, viewer: &str) -> mongodb::error::Result<Vec<Message>> {
+    if !crate::shadow_bans::is_shadow_banned(&state.redis_pool, viewer).await {
+        return latest_page(state, room_id).await;
+    }
+    let messages = state.db.get_messages(room_id, state.history.max_page as i64, None, Some(viewer)).await?;
+    Ok(state.history.fit(messages, Utc::now()))
+}
+
+pub async fn invalidate_room(state: &AppState, room_id: &str) {
+    invalidate(state, &room_key(room_id)).await;
+}
+
+pub async fn invalidate_history(state: &AppState, room_id: &str) {
+    invalidate(state, &history_key(room_id)).await;
+}
+
+/// Marks the room as just active in the hot rooms set.
+pub async fn touch(state: &AppState, room_id: &str) {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::pipe()
+        .cmd("ZADD")
+        .arg(HOT_ROOMS_KEY)
+        .arg(Utc::now().timestamp_millis())
+        .arg(room_id)
+        .ignore()
+        .cmd("ZREMRANGEBYRANK")
+        .arg(HOT_ROOMS_KEY)
+        .arg(0)
+        .arg(-(MAX_HOT_ROOMS + 1))
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to mark room {} as hot: {}", room_id, e);
+    }
+}
+
+// Keeps cached history in step with what the broadcast just changed
+pub(crate) async fn on_broadcast(state: &AppState, channel: &str, message: &WsMessage) {
+    let Some(room_id) = room_of_channel(channel) else {
+        return;
+    };
+    if changes_history(message) {
+        invalidate_history(state, room_id).await;
+    }
+    if matches!(message, WsMessage::NewMessage(_)) {
+        touch(state, room_id).await;
+    }
+}
+
+/// Loads the `limit` hottest rooms that aren't cached yet into the cache.
+/// Instances booting together mostly skip the rooms the first one warmed.
+pub async fn warm(state: &AppState, limit: usize) -> redis::RedisResult<usize> {
+    if limit == 0 {
+        return Ok(0);
+    }
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    let hot: Vec<String> = redis::cmd("ZREVRANGE").arg(HOT_ROOMS_KEY).arg(0).arg(limit as isize - 1).query_async(&mut conn).await?;
+    if hot.is_empty() {
+        return Ok(0);
+    }
+    let mut pipe = redis::pipe();
+    for room_id in &hot {
+        pipe.cmd("EXISTS").arg(history_key(room_id));
+    }
+    let cached: Vec<bool> = pipe.query_async(&mut conn).await?;
+    drop(conn);
+
+    let cold: Vec<String> = hot.into_iter().zip(cached).filter(|(_, cached)| !cached).map(|(room_id, _)| room_id).collect();
+    let mut warmed = 0;
+    for batch in cold.chunks(WARM_CONCURRENCY) {
+        let loaded = futures::future::join_all(batch.iter().map(|room_id| async move {
+            room(state, room_id).await.is_ok() && latest_page(state, room_id).await.is_ok()
+        }))
+        .await;
+        warmed += loaded.into_iter().filter(|loaded| *loaded).count();
+    }
+    Ok(warmed)
+}
+
+/// Warms the cache once, right after boot.
+pub fn spawn_warm(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        match warm(&state, warm_rooms_from_env()).await {
+            Ok(warmed) => info!("Warmed the cache with {} hot rooms", warmed),
+            Err(e) => error!("Failed to warm the room cache: {}", e),
+        }
+    })
+}
@@ -0,0 +1,306 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc},
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    models::{Message, WsMessage},
+    websocket::{broadcast_new_message, send_to_user},
+    AppError, AppState,
+};
+
+// How often the scheduler looks for due events and reminders
+const SCHEDULER_TICK: std::time::Duration = std::time::Duration::from_secs(30);
+const DEFAULT_REMIND_MINUTES: i64 = 60;
+pub const EVENTS_USER_ID: &str = "system:events";
+
+/// Repeats are computed in UTC.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Recurrence {
+    #[default]
+    Once,
+    Daily,
+    Weekly,
+}
+
+impl Recurrence {
+    fn interval(self) -> Option<Duration> {
+        match self {
+            Recurrence::Once => None,
+            Recurrence::Daily => Some(Duration::days(1)),
+            Recurrence::Weekly => Some(Duration::weeks(1)),
+        }
+    }
+
+    /// The first occurrence after `now`, skipping any missed while the
+    /// scheduler was down. `None` once a one-off event has happened.
+    pub fn next_occurrence(self, current: DateTime<Utc>, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let interval = self.interval()?;
+        let mut next = current + interval;
+        if next <= now {
+            let missed = (now - next).num_seconds() / interval.num_seconds() + 1;
+            next += interval * missed as i32;
+        }
+        Some(next)
+    }
+}
+
+/// A moderator-scheduled announcement in a room, possibly recurring.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledEvent {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub room_id: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub description: Option<String>,
+    pub recurrence: Recurrence,
+    // Next occurrence
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub starts_at: DateTime<Utc>,
+    pub remind_minutes_before: i64,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub remind_at: DateTime<Utc>,
+    // Whether the reminder for the current occurrence went out
+    pub reminder_sent: bool,
+    // Users who RSVP'd and get reminders
+    #[serde(default)]
+    pub attendees: Vec<String>,
+    pub active: bool,
+    pub created_by: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn events(state: &AppState) -> Collection<ScheduledEvent> {
+    state.database.collection("room_events")
+}
+
+fn bson_time(time: DateTime<Utc>) -> bson::DateTime {
+    bson::DateTime::from_millis(time.timestamp_millis())
+}
+
+pub fn spawn_scheduler(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SCHEDULER_TICK);
+        loop {
+            tick.tick().await;
+            if let Err(e) = send_due_reminders(&state).await {
+                error!("Failed to send event reminders: {}", e);
+            }
+            if let Err(e) = post_due_events(&state).await {
+                error!("Failed to post scheduled events: {}", e);
+            }
+        }
+    })
+}
+
+async fn send_due_reminders(state: &AppState) -> mongodb::error::Result<()> {
+    let due: Vec<ScheduledEvent> = events(state)
+        .find(
+            doc! { "active": true, "reminder_sent": false, "remind_at": { "$lte": bson_time(Utc::now()) } },
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+
+    for event in due {
+        // Only the instance that flips the flag sends the reminders
+        let claimed = events(state)
+            .update_one(
+                doc! { "_id": &event.id, "reminder_sent": false },
+                doc! { "$set": { "reminder_sent": true } },
+                None,
+            )
+            .await?;
+        if claimed.modified_count == 0 {
+            continue;
+        }
+
+        info!("Reminding {} attendees of event {} in room {}", event.attendees.len(), event.id, event.room_id);
+        for user_id in &event.attendees {
+            send_to_user(
+                state,
+                user_id,
+                WsMessage::EventReminder {
+                    event_id: event.id.clone(),
+                    room_id: event.room_id.clone(),
+                    title: event.title.clone(),
+                    starts_at: event.starts_at,
+                },
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+
+async fn post_due_events(state: &AppState) -> mongodb::error::Result<()> {
+    let now = Utc::now();
+    let due: Vec<ScheduledEvent> = events(state)
+        .find(doc! { "active": true, "starts_at": { "$lte": bson_time(now) } }, None)
+        .await?
+        .try_collect()
+        .await?;
+
+    for event in due {
+        let update = match event.recurrence.next_occurrence(event.starts_at, now) {
+            Some(next) => doc! { "$set": {
+                "starts_at": bson_time(next),
+                "remind_at": bson_time(next - Duration::minutes(event.remind_minutes_before)),
+                "reminder_sent": false,
+            } },
+            None => doc! { "$set": { "active": false } },
+        };
+        // Moving starts_at claims this occurrence against other instances
+        let claimed = events(state)
+            .update_one(doc! { "_id": &event.id, "starts_at": bson_time(event.starts_at) }, update, None)
+            .await?;
+        if claimed.modified_count == 0 {
+            continue;
+        }
+
+        let content = match &event.description {
+            Some(description) => format!("Starting now: {}\n{}", event.title, description),
+            None => format!("Starting now: {}", event.title),
+        };
+        let mut message = Message::new(event.room_id.clone(), EVENTS_USER_ID.to_string(), "Events".to_string(), content);
+        let id = state.db.create_message(&message).await?.id();
+        state.room_metrics.record_message(&message.room_id);
+        message.id = Some(id);
+        crate::event_log::append(state, crate::event_log::LogEvent::room_message(&message)).await;
+        info!("Posted scheduled event {} to room {}", event.id, event.room_id);
+        broadcast_new_message(state, message).await;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct CreateEventRequest {
+    title: String,
+    description: Option<String>,
+    starts_at: DateTime<Utc>,
+    #[serde(default)]
+    recurrence: Recurrence,
+    remind_minutes_before: Option<i64>,
+}
+
+// POST /api/rooms/:location_id/events
+pub async fn create_event_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateEventRequest>,
+) -> Result<Json<ScheduledEvent>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    if req.title.trim().is_empty() {
+        return Err(AppError::BadRequest("title is required".to_string()));
+    }
+    if req.starts_at <= Utc::now() {
+        return Err(AppError::BadRequest("starts_at must be in the future".to_string()));
+    }
+
+    let remind_minutes_before = req.remind_minutes_before.unwrap_or(DEFAULT_REMIND_MINUTES).max(0);
+    let event = ScheduledEvent {
+        id: Uuid::new_v4().to_string(),
+        room_id: location_id,
+        title: req.title.trim().to_string(),
+        description: req.description,
+        recurrence: req.recurrence,
+        starts_at: req.starts_at,
+        remind_minutes_before,
+        remind_at: req.starts_at - Duration::minutes(remind_minutes_before),
+        reminder_sent: false,
+        attendees: vec![],
+        active: true,
+        created_by: user.user_id.clone(),
+        created_at: Utc::now(),
+    };
+    events(&state).insert_one(&event, None).await?;
+    info!("User {} scheduled {:?} event {} in room {}", user.username, event.recurrence, event.id, event.room_id);
+
+    Ok(Json(event))
+}
+
+// GET /api/rooms/:location_id/events - upcoming events, soonest first;
+// members only in private rooms
+pub async fn list_events_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: Option<AuthUser>,
+) -> Result<Json<Vec<ScheduledEvent>>, AppError> {
+    crate::room_invites::require_read_access(&state, &location_id, user.as_ref()).await?;
+    let options = FindOptions::builder().sort(doc! { "starts_at": 1 }).limit(100).build();
+    let upcoming = events(&state)
+        .find(doc! { "room_id": &location_id, "active": true }, options)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(Json(upcoming))
+}
+
+// DELETE /api/rooms/:location_id/events/:event_id
+pub async fn cancel_event_handler(
+    Path((location_id, event_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+
+    let result = events(&state)
+        .update_one(
+            doc! { "_id": &event_id, "room_id": &location_id },
+            doc! { "$set": { "active": false } },
+            None,
+        )
+        .await?;
+    if result.matched_count == 0 {
+        return Err(AppError::NotFound);
+    }
+    info!("User {} cancelled event {} in room {}", user.username, event_id, location_id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+#[derive(Deserialize)]
+pub struct EventRsvpRequest {
+    attending: bool,
+}
+
+// POST /api/rooms/:location_id/events/:event_id/rsvp
+pub async fn event_rsvp_handler(
+    Path((location_id, event_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<EventRsvpRequest>,
+) -> Result<Json<ScheduledEvent>, AppError> {
+    let filter = doc! { "_id": &event_id, "room_id": &location_id, "active": true };
+    let update = if req.attending {
+        doc! { "$addToSet": { "attendees": &user.user_id } }
+    } else {
+        doc! { "$pull": { "attendees": &user.user_id } }
+    };
+    let result = events(&state).update_one(filter.clone(), update, None).await?;
+    if result.matched_count == 0 {
+        return Err(AppError::NotFound);
+    }
+
+    let event = events(&state).find_one(filter, None).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(event))
+}
@@ -0,0 +1,200 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::{
+    auth::AuthUser,
+    models::ChatRoom,
+    room_invites::RoomInvitation,
+    room_roles::require_moderator,
+    AppError, AppState,
+};
+
+pub const DEFAULT_LINK_TTL_SECONDS: i64 = 24 * 60 * 60;
+pub const MIN_LINK_TTL_SECONDS: i64 = 5 * 60;
+pub const MAX_LINK_TTL_SECONDS: i64 = 7 * 24 * 60 * 60;
+// Tells invite links apart from join tickets and the auth service's JWTs
+const LINK_KIND: &str = "room_invite";
+
+fn revoked_key(jti: &str) -> String {
+    format!("room_invite_revoked:{}", jti)
+}
+
+/// A shareable link into one room. Whoever redeems it before it expires
+/// becomes a member, which lets them into the room when it is private or
+/// when their location wouldn't otherwise pass its checks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InviteLink {
+    pub kind: String,
+    pub jti: String,
+    pub room_id: String,
+    pub created_by: String,
+    pub exp: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InviteLinkError {
+    #[error("Invalid or expired invite link")]
+    Invalid,
+    #[error("Invite link has been revoked")]
+    Revoked,
+}
+
+impl InviteLink {
+    pub fn issue(room_id: &str, created_by: &str, ttl_seconds: i64) -> Self {
+        InviteLink {
+            kind: LINK_KIND.to_string(),
+            jti: uuid::Uuid::new_v4().simple().to_string(),
+            room_id: room_id.to_string(),
+            created_by: created_by.to_string(),
+            exp: (Utc::now().timestamp() + ttl_seconds) as usize,
+        }
+    }
+
+    pub fn sign(&self) -> Result<String, jsonwebtoken::errors::Error> {
+        encode(&Header::default(), self, &EncodingKey::from_secret(crate::ws_ticket::secret().as_bytes()))
+    }
+
+    /// Checks the signature and expiry. Revocation is separate, see
+    /// `is_revoked`.
+    pub fn verify(token: &str) -> Result<Self, InviteLinkError> {
+        let decoded = decode::<InviteLink>(
+            token,
+            &DecodingKey::from_secret(crate::ws_ticket::secret().as_bytes()),
+            &Validation::default(),
+        )
+        .map_err(|_| InviteLinkError::Invalid)?;
+        if decoded.claims.kind != LINK_KIND {
+            return Err(InviteLinkError::Invalid);
+        }
+        Ok(decoded.claims)
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.exp as i64, 0).unwrap_or_else(Utc::now)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct InviteLinkRequest {
+    #[serde(default)]
+    pub expires_in_seconds: Option<i64>,
+}
+
+impl InviteLinkRequest {
+    /// How long the link lasts, a day unless asked otherwise.
+    pub fn ttl_seconds(&self) -> Result<i64, String> {
+        let ttl = self.expires_in_seconds.unwrap_or(DEFAULT_LINK_TTL_SECONDS);
+        if !(MIN_LINK_TTL_SECONDS..=MAX_LINK_TTL_SECONDS).contains(&ttl) {
+            return Err(format!(
+                "expires_in_seconds must be between {} and {}",
+                MIN_LINK_TTL_SECONDS, MAX_LINK_TTL_SECONDS
+            ));
+        }
+        Ok(ttl)
+    }
+}
+
+/// Whether the link was revoked. Fails closed while Redis is unavailable,
+/// like membership checks, so revoked links stay unusable.
+pub async fn is_revoked(state: &AppState, link: &InviteLink) -> Result<bool, AppError> {
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        error!("Redis unavailable for invite links: {}", e);
+        AppError::ServiceUnavailable { retry_after: 5 }
+    })?;
+    redis::cmd("EXISTS").arg(revoked_key(&link.jti)).query_async(&mut conn).await.map_err(|e| {
+        error!("Failed to check whether invite link {} was revoked: {}", link.jti, e);
+        AppError::ServiceUnavailable { retry_after: 5 }
+    })
+}
+
+/// Whether only room moderators may create links to the room. Membership
+/// gets past the room's restrictions, so rooms with any are moderators' to
+/// share.
+pub fn link_needs_moderator(room: &ChatRoom) -> bool {
+    !room.visibility.is_public() || room.settings.exclude_location_mismatch
+}
+
+#[derive(Debug, Serialize)]
+pub struct InviteLinkResponse {
+    pub token: String,
+    pub jti: String,
+    pub room_id: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+// POST /api/rooms/:location_id/invites - create an expiring invite link;
+// anyone signed in for open rooms, room moderators for private rooms and
+// rooms that exclude flagged locations
+pub async fn create_invite_link_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<InviteLinkRequest>,
+) -> Result<Json<InviteLinkResponse>, AppError> {
+    let ttl = req.ttl_seconds().map_err(AppError::BadRequest)?;
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    if link_needs_moderator(&room) {
+        require_moderator(&state, &location_id, &user, None).await?;
+    }
+    let link = InviteLink::issue(&location_id, &user.user_id, ttl);
+    let token = link.sign().map_err(|e| {
+        error!("Failed to sign invite link for room {}: {}", location_id, e);
+        AppError::InternalServerError
+    })?;
+    info!("{} created an invite link to room {}", user.username, location_id);
+    Ok(Json(InviteLinkResponse { token, jti: link.jti.clone(), room_id: location_id, expires_at: link.expires_at() }))
+}
+
+// DELETE /api/rooms/:location_id/invites/:jti - revoke an invite link; room
+// moderators only
+pub async fn revoke_invite_link_handler(
+    Path((location_id, jti)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_moderator(&state, &location_id, &user, None).await?;
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        error!("Redis unavailable for invite links: {}", e);
+        AppError::ServiceUnavailable { retry_after: 5 }
+    })?;
+    // Links outlive their revocation by at most the longest TTL
+    let _: () = redis::cmd("SET")
+        .arg(revoked_key(&jti))
+        .arg(&location_id)
+        .arg("EX")
+        .arg(MAX_LINK_TTL_SECONDS)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to revoke invite link {}: {}", jti, e);
+            AppError::InternalServerError
+        })?;
+    info!("{} revoked invite link {} to room {}", user.username, jti, location_id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+// POST /api/invites/:token/redeem - become a member of the link's room
+pub async fn redeem_invite_link_handler(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RoomInvitation>, AppError> {
+    let link = InviteLink::verify(&token).map_err(|e| AppError::BadRequest(e.to_string()))?;
+    if is_revoked(&state, &link).await? {
+        return Err(AppError::BadRequest(InviteLinkError::Revoked.to_string()));
+    }
+    state.db.get_room(&link.room_id).await?.ok_or(AppError::NotFound)?;
+    if crate::room_bans::find_ban(&state, &link.room_id, &user.user_id).await.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    let membership = crate::room_invites::grant_membership(&state, &link.room_id, &user.user_id, &link.created_by).await?;
+    info!("{} joined room {} through an invite link from {}", user.username, link.room_id, link.created_by);
+    Ok(Json(membership))
+}
@@ -0,0 +1,323 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{bson::doc, options::FindOptions, Collection};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    auth::AuthUser,
+    models::{ChatRoom, WsMessage},
+    room_roles::{acting_role, require_moderator, role_in, RoomRole},
+    websocket::publish_to_room,
+    AppError, AppState,
+};
+
+/// Who may join a room.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomVisibility {
+    // Anyone can join
+    #[default]
+    Public,
+    // Only members and the invited; left out of room lists
+    Private,
+}
+
+impl RoomVisibility {
+    pub fn is_public(&self) -> bool {
+        *self == RoomVisibility::Public
+    }
+}
+
+/// An invitation to a private room. The invitee becomes a member by joining
+/// with its token once; accepted invitations are the room's membership.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomInvitation {
+    // "room_id:user_id"
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub room_id: String,
+    pub user_id: String,
+    pub invited_by: String,
+    pub token: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "crate::message_ttl::bson_datetime_option")]
+    pub accepted_at: Option<DateTime<Utc>>,
+}
+
+fn invitations(state: &AppState) -> Collection<RoomInvitation> {
+    state.database.collection("room_invitations")
+}
+
+fn invitation_id(room_id: &str, user_id: &str) -> String {
+    format!("{}:{}", room_id, user_id)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct InviteRequest {
+    pub user_id: String,
+}
+
+impl InviteRequest {
+    pub fn into_invitation(self, room_id: &str, invited_by: &str) -> Result<RoomInvitation, String> {
+        let user_id = self.user_id.trim().to_string();
+        if user_id.is_empty() {
+            return Err("user_id is required".to_string());
+        }
+        Ok(RoomInvitation {
+            id: invitation_id(room_id, &user_id),
+            room_id: room_id.to_string(),
+            user_id,
+            invited_by: invited_by.to_string(),
+            token: uuid::Uuid::new_v4().simple().to_string(),
+            created_at: Utc::now(),
+            accepted_at: None,
+        })
+    }
+}
+
+/// What a join needs before it is let in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Open,
+    // Private room: the user needs membership or an invite token
+    Invitation,
+    Denied,
+}
+
+/// How a join by `user_id` into a room with `visibility`, where they have
+/// `role`, is decided. Private rooms need the caller signed in as the user.
+pub fn admission(visibility: RoomVisibility, role: RoomRole, caller: Option<&AuthUser>, user_id: &str) -> Admission {
+    if visibility.is_public() {
+        return Admission::Open;
+    }
+    match caller {
+        Some(caller) if caller.user_id == user_id => {
+            if caller.is_moderator() || role >= RoomRole::Moderator {
+                Admission::Open
+            } else {
+                Admission::Invitation
+            }
+        }
+        _ => Admission::Denied,
+    }
+}
+
+/// How a resumed session is decided. The session was let in when it joined;
+/// in a private room it carries on only while the user still moderates the
+/// room or is still a member.
+pub fn resume_admission(visibility: RoomVisibility, role: RoomRole, session_moderator: bool) -> Admission {
+    if visibility.is_public() || session_moderator || role >= RoomRole::Moderator {
+        Admission::Open
+    } else {
+        Admission::Invitation
+    }
+}
+
+/// Whether the user is a member of the room, accepting their invitation if
+/// `invite` is its token. Fails closed, so private rooms stay private while
+/// MongoDB is unavailable.
+pub async fn is_member(state: &AppState, room_id: &str, user_id: &str, invite: Option<&str>) -> bool {
+    let invitation = match invitations(state).find_one(doc! { "_id": invitation_id(room_id, user_id) }, None).await {
+        Ok(Some(invitation)) => invitation,
+        Ok(None) => return false,
+        Err(e) => {
+            error!("Failed to check membership of {} in room {}: {}", user_id, room_id, e);
+            return false;
+        }
+    };
+    if invitation.accepted_at.is_some() {
+        return true;
+    }
+    if invite != Some(invitation.token.as_str()) {
+        return false;
+    }
+    let accepted = invitations(state)
+        .update_one(doc! { "_id": &invitation.id }, doc! { "$set": { "accepted_at": mongodb::bson::DateTime::now() } }, None)
+        .await;
+    match accepted {
+        Ok(_) => {
+            info!("User {} accepted their invitation to room {}", user_id, room_id);
+            true
+        }
+        Err(e) => {
+            error!("Failed to accept invitation of {} to room {}: {}", user_id, room_id, e);
+            false
+        }
+    }
+}
+
+/// Makes the user a member of the room, as redeeming an invite link does.
+/// Keeps an existing invitation, accepting it if it wasn't yet.
+pub async fn grant_membership(
+    state: &AppState,
+    room_id: &str,
+    user_id: &str,
+    invited_by: &str,
+) -> Result<RoomInvitation, AppError> {
+    let options = mongodb::options::FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(mongodb::options::ReturnDocument::After)
+        .build();
+    let update = doc! {
+        "$setOnInsert": {
+            "room_id": room_id,
+            "user_id": user_id,
+            "invited_by": invited_by,
+            "token": uuid::Uuid::new_v4().simple().to_string(),
+            "created_at": mongodb::bson::DateTime::now(),
+        },
+        "$min": { "accepted_at": mongodb::bson::DateTime::now() },
+    };
+    let membership = invitations(state).find_one_and_update(doc! { "_id": invitation_id(room_id, user_id) }, update, options).await?;
+    membership.ok_or(AppError::InternalServerError)
+}
+
+/// The rooms the user is a member of through an accepted invitation.
+pub(crate) async fn memberships_of(state: &AppState, user_id: &str) -> mongodb::error::Result<Vec<String>> {
+    let filter = doc! { "user_id": user_id, "accepted_at": { "$ne": null } };
+    let memberships: Vec<RoomInvitation> = invitations(state).find(filter, None).await?.try_collect().await?;
+    Ok(memberships.into_iter().map(|membership| membership.room_id).collect())
+}
+
+/// Whether the caller may read or post in the room over REST, as they could
+/// join it. Private rooms need the caller signed in and a member.
+pub async fn require_access(state: &AppState, room: &ChatRoom, caller: Option<&AuthUser>) -> Result<(), AppError> {
+    let user_id = caller.map(|caller| caller.user_id.as_str()).unwrap_or_default();
+    if may_join(state, &room.id, room.visibility, role_in(room, user_id), caller, user_id, None).await {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden)
+    }
+}
+
+/// `require_access` for a room by id. Rooms that don't exist yet are public.
+pub async fn require_read_access(state: &AppState, room_id: &str, caller: Option<&AuthUser>) -> Result<(), AppError> {
+    match state.db.get_room(room_id).await? {
+        Some(room) => require_access(state, &room, caller).await,
+        None => Ok(()),
+    }
+}
+
+/// What a removed member's sockets in a private room are sent before being
+/// closed.
+pub fn removed_notice(room_id: &str, user_id: &str) -> WsMessage {
+    WsMessage::RoomAccessRevoked { room_id: room_id.to_string(), user_id: user_id.to_string() }
+}
+
+/// Whether the join may go ahead.
+pub async fn may_join(
+    state: &AppState,
+    room_id: &str,
+    visibility: RoomVisibility,
+    role: RoomRole,
+    caller: Option<&AuthUser>,
+    user_id: &str,
+    invite: Option<&str>,
+) -> bool {
+    match admission(visibility, role, caller, user_id) {
+        Admission::Open => true,
+        Admission::Invitation => is_member(state, room_id, user_id, invite).await,
+        Admission::Denied => false,
+    }
+}
+
+/// Whether a resumed session may carry on in the room; see `resume_admission`.
+pub async fn may_resume(state: &AppState, room: &ChatRoom, user_id: &str, session_moderator: bool) -> bool {
+    match resume_admission(room.visibility, role_in(room, user_id), session_moderator) {
+        Admission::Open => true,
+        Admission::Invitation => is_member(state, &room.id, user_id, None).await,
+        Admission::Denied => false,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VisibilityRequest {
+    pub visibility: RoomVisibility,
+}
+
+// PUT /api/rooms/:location_id/visibility - make a room private or public;
+// the owner only
+pub async fn set_visibility_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<VisibilityRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    if acting_role(&room, &user) < RoomRole::Owner {
+        return Err(AppError::Forbidden);
+    }
+    let visibility = mongodb::bson::to_bson(&req.visibility).map_err(|_| AppError::InternalServerError)?;
+    state.db.update_room_settings(&location_id, doc! { "visibility": visibility }).await?;
+    crate::room_cache::invalidate_room(&state, &location_id).await;
+    info!("{} made room {} {:?}", user.username, location_id, req.visibility);
+    Ok(Json(serde_json::json!({ "room_id": location_id, "visibility": req.visibility })))
+}
+
+// GET /api/rooms/:location_id/invitations - invitations and members, newest
+// first; room moderators only
+pub async fn list_invitations_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<RoomInvitation>>, AppError> {
+    require_moderator(&state, &location_id, &user, None).await?;
+    let options = FindOptions::builder().sort(doc! { "created_at": -1 }).build();
+    Ok(Json(invitations(&state).find(doc! { "room_id": &location_id }, options).await?.try_collect().await?))
+}
+
+// POST /api/rooms/:location_id/invitations - invite a user; inviting them
+// again returns the same invitation
+pub async fn invite_user_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<InviteRequest>,
+) -> Result<Json<RoomInvitation>, AppError> {
+    require_moderator(&state, &location_id, &user, None).await?;
+    let invitation = req.into_invitation(&location_id, &user.user_id).map_err(AppError::BadRequest)?;
+    let mut insert = mongodb::bson::to_document(&invitation).map_err(|_| AppError::InternalServerError)?;
+    insert.remove("_id");
+    let options = mongodb::options::FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(mongodb::options::ReturnDocument::After)
+        .build();
+    let invitation = invitations(&state)
+        .find_one_and_update(doc! { "_id": &invitation.id }, doc! { "$setOnInsert": insert }, options)
+        .await?
+        .ok_or(AppError::InternalServerError)?;
+    info!("{} invited {} to room {}", user.username, invitation.user_id, location_id);
+    Ok(Json(invitation))
+}
+
+// DELETE /api/rooms/:location_id/invitations/:user_id - withdraw an
+// invitation or membership and close the user's sockets in the private
+// room; room moderators, or the user leaving
+pub async fn remove_invitation_handler(
+    Path((location_id, user_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if user_id != user.user_id {
+        require_moderator(&state, &location_id, &user, None).await?;
+    }
+    let result = invitations(&state).delete_one(doc! { "_id": invitation_id(&location_id, &user_id) }, None).await?;
+    if result.deleted_count == 0 {
+        return Err(AppError::NotFound);
+    }
+    info!("{} removed the invitation of {} to room {}", user.username, user_id, location_id);
+    // Every instance closes the user's sockets subscribed to the room, unless
+    // they can stay without the membership
+    if let Some(room) = state.db.get_room(&location_id).await? {
+        if !room.visibility.is_public() && role_in(&room, &user_id) < RoomRole::Moderator {
+            publish_to_room(&state, &location_id, removed_notice(&location_id, &user_id)).await;
+        }
+    }
+    Ok(Json(serde_json::json!({ "success": true })))
+}
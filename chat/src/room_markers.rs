@@ -0,0 +1,122 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{auth::AuthUser, models::WsMessage, room_roles::require_moderator, websocket::publish_to_room, AppError, AppState};
+
+pub const MAX_MARKERS: usize = 20;
+const MAX_LABEL_CHARS: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkerKind {
+    MeetingPoint,
+    Entrance,
+    Hazard,
+    Other,
+}
+
+/// A point moderators pinned on the room's map.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomMarker {
+    pub id: String,
+    pub kind: MarkerKind,
+    #[serde(default)]
+    pub label: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    pub created_by: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarkerRequest {
+    pub kind: MarkerKind,
+    #[serde(default)]
+    pub label: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl MarkerRequest {
+    /// The marker this request describes, checked and with its label trimmed.
+    pub fn into_marker(self, id: String, created_by: &str) -> Result<RoomMarker, String> {
+        if !(-90.0..=90.0).contains(&self.latitude) || !(-180.0..=180.0).contains(&self.longitude) {
+            return Err("latitude must be within ±90 and longitude within ±180".to_string());
+        }
+        let label = self.label.trim().to_string();
+        if label.chars().count() > MAX_LABEL_CHARS {
+            return Err(format!("label must be at most {} characters", MAX_LABEL_CHARS));
+        }
+        Ok(RoomMarker {
+            id,
+            kind: self.kind,
+            label,
+            latitude: self.latitude,
+            longitude: self.longitude,
+            created_by: created_by.to_string(),
+            updated_at: Utc::now(),
+        })
+    }
+}
+
+async fn announce(state: &AppState, room_id: &str, marker_id: &str, marker: Option<RoomMarker>) {
+    crate::room_cache::invalidate_room(state, room_id).await;
+    let event = WsMessage::MarkerUpdated { room_id: room_id.to_string(), marker_id: marker_id.to_string(), marker };
+    publish_to_room(state, room_id, event).await;
+}
+
+// POST /api/rooms/:location_id/markers - pin a marker; moderators only
+pub async fn create_marker_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<MarkerRequest>,
+) -> Result<Json<RoomMarker>, AppError> {
+    require_moderator(&state, &location_id, &user, None).await?;
+    let marker = req.into_marker(uuid::Uuid::new_v4().to_string(), &user.user_id).map_err(AppError::BadRequest)?;
+    crate::room_quota::room_for(&state, &location_id, Some(&user)).await?;
+    if !state.db.add_marker(&location_id, &marker, MAX_MARKERS).await? {
+        return Err(AppError::Conflict(format!("Rooms can have at most {} markers", MAX_MARKERS)));
+    }
+    info!("Moderator {} pinned a {:?} marker in room {}", user.username, marker.kind, location_id);
+    announce(&state, &location_id, &marker.id, Some(marker.clone())).await;
+    Ok(Json(marker))
+}
+
+// PUT /api/rooms/:location_id/markers/:marker_id - move or relabel a marker
+pub async fn update_marker_handler(
+    Path((location_id, marker_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<MarkerRequest>,
+) -> Result<Json<RoomMarker>, AppError> {
+    require_moderator(&state, &location_id, &user, None).await?;
+    let marker = req.into_marker(marker_id.clone(), &user.user_id).map_err(AppError::BadRequest)?;
+    if !state.db.replace_marker(&location_id, &marker).await? {
+        return Err(AppError::NotFound);
+    }
+    info!("Moderator {} updated marker {} in room {}", user.username, marker_id, location_id);
+    announce(&state, &location_id, &marker_id, Some(marker.clone())).await;
+    Ok(Json(marker))
+}
+
+// DELETE /api/rooms/:location_id/markers/:marker_id - unpin a marker
+pub async fn delete_marker_handler(
+    Path((location_id, marker_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_moderator(&state, &location_id, &user, None).await?;
+    if !state.db.remove_marker(&location_id, &marker_id).await? {
+        return Err(AppError::NotFound);
+    }
+    info!("Moderator {} removed marker {} from room {}", user.username, marker_id, location_id);
+    announce(&state, &location_id, &marker_id, None).await;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
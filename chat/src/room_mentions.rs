@@ -0,0 +1,156 @@
+use std::collections::HashSet;
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{
+    models::Message,
+    notifications::{NotificationHint, NotificationSettings},
+    read_cursors::recent_readers,
+    AppState,
+};
+
+// A room can be pinged as a whole at most this often
+pub const ROOM_MENTION_COOLDOWN_SECONDS: u64 = 60 * 60;
+// Members are people who have had the room open recently
+const MEMBER_WINDOW_DAYS: i64 = 30;
+const MAX_PUSH_RECIPIENTS: i64 = 1000;
+const DEFAULT_PUSH_QUEUE: &str = "push_notifications";
+const PUSH_BODY_CHARS: usize = 140;
+
+/// Whether a word is a room-wide mention, i.e. `@everyone` or `@here`.
+pub fn is_room_mention(word: &str) -> bool {
+    let word = word.trim_end_matches(|c: char| c.is_ascii_punctuation());
+    word.eq_ignore_ascii_case("@everyone") || word.eq_ignore_ascii_case("@here")
+}
+
+pub fn mentions_everyone(content: &str) -> bool {
+    content.split_whitespace().any(is_room_mention)
+}
+
+/// Takes the room's room-wide mention for the next hour. Returns the seconds
+/// until the next one is allowed when it has already been used. Fails open.
+pub async fn claim_room_mention(pool: &deadpool_redis::Pool, room_id: &str, user_id: &str) -> Result<(), u64> {
+    let key = format!("room_mention:{}", room_id);
+    let Ok(mut conn) = pool.get().await else {
+        return Ok(());
+    };
+    let claimed: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(&key)
+        .arg(user_id)
+        .arg("NX")
+        .arg("EX")
+        .arg(ROOM_MENTION_COOLDOWN_SECONDS)
+        .query_async(&mut conn)
+        .await;
+    match claimed {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => {
+            let ttl: i64 = redis::cmd("TTL").arg(&key).query_async(&mut conn).await.unwrap_or(0);
+            warn!("Refusing room-wide mention from {} in {}: used within the hour", user_id, room_id);
+            Err(ttl.max(1) as u64)
+        }
+        Err(e) => {
+            error!("Failed to check room-wide mention cooldown in {}: {}", room_id, e);
+            Ok(())
+        }
+    }
+}
+
+/// A job for the push worker, queued on the `PUSH_QUEUE` Redis list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushNotification {
+    pub user_id: String,
+    pub kind: String,
+    pub room_id: String,
+    pub message_id: String,
+    pub title: String,
+    pub body: String,
+    // The same hint connected members got with the message
+    pub notification: NotificationHint,
+}
+
+impl PushNotification {
+    pub fn room_mention(user_id: &str, message: &Message) -> Self {
+        PushNotification {
+            user_id: user_id.to_string(),
+            kind: "room_mention".to_string(),
+            room_id: message.room_id.clone(),
+            message_id: message.id.map(|id| id.to_hex()).unwrap_or_default(),
+            title: format!("{} mentioned everyone", message.username),
+            body: message.content.chars().take(PUSH_BODY_CHARS).collect(),
+            notification: message
+                .notification
+                .clone()
+                .unwrap_or_else(|| NotificationHint::for_room_message(message, &NotificationSettings::default())),
+        }
+    }
+}
+
+/// Sends the offline pushes for a saved room-wide mention in the background.
+pub fn spawn_room_mention_pushes(state: &AppState, message: &Message) {
+    if !message.mentions_everyone {
+        return;
+    }
+    let state = state.clone();
+    let message = message.clone();
+    tokio::spawn(async move {
+        // Pushes would give a shadow-ban away
+        if !crate::shadow_bans::is_shadow_banned(&state.redis_pool, &message.user_id).await {
+            notify_offline_members(&state, &message).await;
+        }
+    });
+}
+
+/// Queues a push for every recent member of the room who isn't connected.
+/// Connected members already got the message, flagged, over their socket.
+pub async fn notify_offline_members(state: &AppState, message: &Message) {
+    let since = Utc::now() - Duration::days(MEMBER_WINDOW_DAYS);
+    let members = match recent_readers(state, &message.room_id, since, MAX_PUSH_RECIPIENTS).await {
+        Ok(members) => members,
+        Err(e) => {
+            error!("Failed to load members of room {}: {}", message.room_id, e);
+            return;
+        }
+    };
+
+    let online: HashSet<String> = match crate::presence::room_users(state, &message.room_id).await {
+        Ok(users) => users.into_iter().map(|user| user.id).collect(),
+        Err(_) => state
+            .connections
+            .read()
+            .await
+            .get_room_users(&message.room_id)
+            .into_iter()
+            .map(|user| user.id)
+            .collect(),
+    };
+    // Muted members keep the room but never get pushed about it
+    let muted = match crate::preferences::muted_members(state, &message.room_id, &members).await {
+        Ok(muted) => muted,
+        Err(e) => {
+            error!("Failed to load mutes of room {}, skipping pushes: {}", message.room_id, e);
+            return;
+        }
+    };
+    let jobs: Vec<String> = members
+        .iter()
+        .filter(|member| **member != message.user_id && !online.contains(*member) && !muted.contains(*member))
+        .filter_map(|member| serde_json::to_string(&PushNotification::room_mention(member, message)).ok())
+        .collect();
+    if jobs.is_empty() {
+        return;
+    }
+
+    let queue = std::env::var("PUSH_QUEUE").unwrap_or_else(|_| DEFAULT_PUSH_QUEUE.to_string());
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        error!("Redis unavailable, dropped {} room-wide mention pushes for {}", jobs.len(), message.room_id);
+        return;
+    };
+    let queued: redis::RedisResult<()> = redis::cmd("LPUSH").arg(&queue).arg(&jobs).query_async(&mut conn).await;
+    match queued {
+        Ok(()) => info!("Queued {} room-wide mention pushes for room {}", jobs.len(), message.room_id),
+        Err(e) => error!("Failed to queue room-wide mention pushes for {}: {}", message.room_id, e),
+    }
+}
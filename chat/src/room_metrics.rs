@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+// Label used for every room outside the top N
+pub const OTHER_ROOMS_LABEL: &str = "other";
+// Rooms tracked individually before idle ones are folded into "other"
+const MAX_TRACKED_ROOMS: usize = 10_000;
+
+type CounterFn = fn(&RoomCounters) -> u64;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RoomCounters {
+    pub messages: u64,
+    pub joins: u64,
+    pub active_users: u64,
+}
+
+impl RoomCounters {
+    fn add(&mut self, other: &RoomCounters) {
+        self.messages += other.messages;
+        self.joins += other.joins;
+        self.active_users += other.active_users;
+    }
+}
+
+/// Per-room counters exported with a bounded label set: the `top_n` busiest
+/// rooms keep their own `room` label and the rest are summed under "other".
+#[derive(Debug)]
+pub struct RoomMetrics {
+    top_n: usize,
+    rooms: Mutex<HashMap<String, RoomCounters>>,
+    // Totals of rooms dropped from tracking, so "other" never goes backwards
+    evicted: Mutex<RoomCounters>,
+    // Messages not yet added to the shared per-minute counter
+    unpublished: AtomicU64,
+}
+
+impl RoomMetrics {
+    pub fn new(top_n: usize) -> Self {
+        RoomMetrics {
+            top_n,
+            rooms: Mutex::new(HashMap::new()),
+            evicted: Mutex::new(RoomCounters::default()),
+            unpublished: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let top_n = std::env::var("ROOM_METRICS_TOP_N")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(20);
+        Self::new(top_n)
+    }
+
+    /// Rooms exported with their own label.
+    pub fn top_n(&self) -> usize {
+        self.top_n
+    }
+
+    pub fn record_message(&self, room_id: &str) {
+        self.update(room_id, |counters| counters.messages += 1);
+        self.unpublished.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Messages recorded since the last call.
+    pub fn take_unpublished(&self) -> u64 {
+        self.unpublished.swap(0, Ordering::Relaxed)
+    }
+
+    pub fn record_join(&self, room_id: &str, active_users: usize) {
+        self.update(room_id, |counters| {
+            counters.joins += 1;
+            counters.active_users = active_users as u64;
+        });
+    }
+
+    pub fn set_active_users(&self, room_id: &str, active_users: usize) {
+        self.update(room_id, |counters| counters.active_users = active_users as u64);
+    }
+
+    fn update(&self, room_id: &str, f: impl FnOnce(&mut RoomCounters)) {
+        let mut rooms = self.rooms.lock().unwrap();
+        f(rooms.entry(room_id.to_string()).or_default());
+        if rooms.len() > MAX_TRACKED_ROOMS {
+            self.evict_idle(&mut rooms);
+        }
+    }
+
+    // Folds empty rooms into the evicted totals, quietest first
+    fn evict_idle(&self, rooms: &mut HashMap<String, RoomCounters>) {
+        let mut idle: Vec<(String, u64)> = rooms
+            .iter()
+            .filter(|(_, counters)| counters.active_users == 0)
+            .map(|(room_id, counters)| (room_id.clone(), counters.messages))
+            .collect();
+        idle.sort_by_key(|(_, messages)| *messages);
+
+        let mut evicted = self.evicted.lock().unwrap();
+        for (room_id, _) in idle.into_iter().take(MAX_TRACKED_ROOMS / 10) {
+            if let Some(counters) = rooms.remove(&room_id) {
+                evicted.add(&counters);
+            }
+        }
+    }
+
+    /// Counters bucketed into at most `top_n` rooms plus "other", busiest first.
+    pub fn snapshot(&self) -> Vec<(String, RoomCounters)> {
+        let rooms = self.rooms.lock().unwrap();
+        let mut ranked: Vec<(&String, &RoomCounters)> = rooms.iter().collect();
+        ranked.sort_by(|(a_id, a), (b_id, b)| {
+            (b.messages, b.active_users)
+                .cmp(&(a.messages, a.active_users))
+                .then_with(|| a_id.cmp(b_id))
+        });
+
+        let mut other = self.evicted.lock().unwrap().clone();
+        let mut buckets = Vec::with_capacity(self.top_n + 1);
+        for (index, (room_id, counters)) in ranked.into_iter().enumerate() {
+            if index < self.top_n {
+                buckets.push((room_id.clone(), counters.clone()));
+            } else {
+                other.add(counters);
+            }
+        }
+        if other != RoomCounters::default() {
+            buckets.push((OTHER_ROOMS_LABEL.to_string(), other));
+        }
+        buckets
+    }
+
+    /// Prometheus text exposition of the bucketed counters.
+    pub fn render(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+        let series: [(&str, &str, &str, CounterFn); 3] = [
+            ("chat_room_messages_total", "counter", "Messages sent per room", |c| c.messages),
+            ("chat_room_joins_total", "counter", "Room joins per room", |c| c.joins),
+            ("chat_room_active_users", "gauge", "Connected users per room", |c| c.active_users),
+        ];
+        for (name, kind, help, value) in series {
+            let _ = writeln!(out, "# HELP {} {} (top {} rooms, rest as \"{}\")", name, help, self.top_n, OTHER_ROOMS_LABEL);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (room_id, counters) in &snapshot {
+                let _ = writeln!(out, "{}{{room=\"{}\"}} {}", name, escape_label(room_id), value(counters));
+            }
+        }
+        out
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,230 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use crate::{auth::AuthUser, models::ChatRoom, AppError, AppState};
+
+const DEFAULT_DAILY_LIMIT: u32 = 20;
+const DEFAULT_REVIEW_AFTER: u32 = 5;
+const DEFAULT_ORGANIC_POSTERS: usize = 3;
+// Posters are only counted towards approval while they're this recent
+const POSTERS_TTL_SECONDS: i64 = 7 * 24 * 3600;
+
+fn creations_key(user_id: &str, now: DateTime<Utc>) -> String {
+    format!("room_creations:{}:{}", user_id, now.format("%Y%m%d"))
+}
+
+fn posters_key(room_id: &str) -> String {
+    format!("room_review:{}", room_id)
+}
+
+/// Whether a room is listed for everyone. Rooms in review are only listed
+/// for the user who created them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReviewState {
+    #[default]
+    Approved,
+    Pending,
+}
+
+/// How many rooms one user may create per UTC day, how many of those are
+/// listed right away, and how many other posters take a room out of review.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoomQuota {
+    pub daily_limit: u32,
+    pub review_after: u32,
+    pub organic_posters: usize,
+}
+
+impl Default for RoomQuota {
+    fn default() -> Self {
+        RoomQuota {
+            daily_limit: DEFAULT_DAILY_LIMIT,
+            review_after: DEFAULT_REVIEW_AFTER,
+            organic_posters: DEFAULT_ORGANIC_POSTERS,
+        }
+    }
+}
+
+impl RoomQuota {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok();
+        RoomQuota {
+            daily_limit: var("ROOM_CREATION_DAILY_LIMIT").and_then(|v| v.parse().ok()).unwrap_or(defaults.daily_limit),
+            review_after: var("ROOM_CREATION_REVIEW_AFTER").and_then(|v| v.parse().ok()).unwrap_or(defaults.review_after),
+            organic_posters: var("ROOM_REVIEW_ORGANIC_POSTERS")
+                .and_then(|v| v.parse().ok())
+                .filter(|posters| *posters > 0)
+                .unwrap_or(defaults.organic_posters),
+        }
+    }
+
+    /// The review state of a user's `created_today`th room of the day, or
+    /// None once they are over the daily limit.
+    pub fn review_for(&self, created_today: u32) -> Option<ReviewState> {
+        if created_today > self.daily_limit {
+            None
+        } else if created_today > self.review_after {
+            Some(ReviewState::Pending)
+        } else {
+            Some(ReviewState::Approved)
+        }
+    }
+}
+
+/// Seconds until the daily quota resets at UTC midnight.
+pub fn seconds_until_reset(now: DateTime<Utc>) -> u64 {
+    (86_400 - now.num_seconds_from_midnight()) as u64
+}
+
+// Counts a creation against the user's quota. Fails open without Redis.
+async fn claim(state: &AppState, user_id: &str) -> Result<ReviewState, AppError> {
+    let now = Utc::now();
+    let key = creations_key(user_id, now);
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return Ok(ReviewState::Approved);
+    };
+    let result: redis::RedisResult<(u32,)> = redis::pipe()
+        .incr(&key, 1)
+        .expire(&key, 86_400)
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    let created_today = match result {
+        Ok((count,)) => count,
+        Err(e) => {
+            error!("Failed to count room creations for {}: {}", user_id, e);
+            return Ok(ReviewState::Approved);
+        }
+    };
+    state.room_quota.review_for(created_today).ok_or_else(|| {
+        warn!("User {} is over the daily room creation limit", user_id);
+        AppError::TooManyRequests { retry_after: seconds_until_reset(now) }
+    })
+}
+
+/// Who a new room is charged to: the signed-in caller. Anyone can claim a
+/// user id, so anonymous callers can't create rooms.
+pub fn creator(caller: Option<&AuthUser>) -> Result<&str, AppError> {
+    caller.map(|caller| caller.user_id.as_str()).ok_or(AppError::Unauthorized)
+}
+
+async fn get_or_create(state: &AppState, room_id: &str, caller: Option<&AuthUser>) -> Result<ChatRoom, AppError> {
+    if let Some(room) = state.db.get_room(room_id).await? {
+        return Ok(room);
+    }
+    let creator = creator(caller)?;
+    let review = claim(state, creator).await?;
+    let mut room = ChatRoom::new(room_id);
+    room.created_by = Some(creator.to_string());
+    room.review = review;
+    let room = state.db.create_room(room).await?;
+    if room.review == ReviewState::Pending {
+        info!("Room {} created by {} is in review", room_id, creator);
+    }
+    Ok(room)
+}
+
+/// The room the caller is joining or posting to, created on their quota if
+/// it doesn't exist yet. Anonymous callers get `Unauthorized` for a room
+/// that doesn't exist.
+pub async fn room_for(state: &AppState, room_id: &str, caller: Option<&AuthUser>) -> Result<ChatRoom, AppError> {
+    crate::room_cache::room_with(state, room_id, get_or_create(state, room_id, caller)).await
+}
+
+/// The creator of a room still in review, whose own posts don't count
+/// towards its approval.
+pub fn pending_creator(room: &ChatRoom) -> Option<String> {
+    match room.review {
+        ReviewState::Pending => Some(room.created_by.clone().unwrap_or_default()),
+        ReviewState::Approved => None,
+    }
+}
+
+/// Counts a post in a room in review. Returns true once enough people
+/// besides its creator have posted and the room was approved.
+pub async fn record_post(state: &AppState, room_id: &str, creator: &str, user_id: &str) -> bool {
+    if user_id == creator {
+        return false;
+    }
+    let key = posters_key(room_id);
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return false;
+    };
+    let result: redis::RedisResult<(usize,)> = redis::pipe()
+        .sadd(&key, user_id)
+        .ignore()
+        .expire(&key, POSTERS_TTL_SECONDS)
+        .ignore()
+        .scard(&key)
+        .query_async(&mut conn)
+        .await;
+    match result {
+        Ok((posters,)) if posters >= state.room_quota.organic_posters => {
+            info!("Room {} has {} posters; approving it", room_id, posters);
+            match approve(state, room_id).await {
+                Ok(_) => true,
+                Err(e) => {
+                    error!("Failed to approve room {}: {}", room_id, e);
+                    false
+                }
+            }
+        }
+        Ok(_) => false,
+        Err(e) => {
+            error!("Failed to count posters in room {}: {}", room_id, e);
+            false
+        }
+    }
+}
+
+async fn approve(state: &AppState, room_id: &str) -> Result<bool, AppError> {
+    let approved = state.db.approve_room(room_id).await?;
+    crate::room_cache::invalidate_room(state, room_id).await;
+    if let Ok(mut conn) = state.redis_pool.get().await {
+        let _: redis::RedisResult<()> = redis::cmd("DEL").arg(posters_key(room_id)).query_async(&mut conn).await;
+    }
+    Ok(approved)
+}
+
+#[derive(Deserialize)]
+pub struct ReviewQueueQuery {
+    limit: Option<i64>,
+}
+
+// GET /api/admin/rooms/review?limit= - rooms awaiting review, oldest first
+pub async fn review_queue_handler(
+    Query(params): Query<ReviewQueueQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<ChatRoom>>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let limit = params.limit.unwrap_or(50).clamp(1, 200);
+    Ok(Json(state.db.rooms_in_review(limit).await?))
+}
+
+// POST /api/admin/rooms/:location_id/approve - list a room in review for everyone
+pub async fn approve_room_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<ChatRoom>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    if room.review == ReviewState::Pending {
+        approve(&state, &location_id).await?;
+        info!("Moderator {} approved room {}", user.user_id, location_id);
+    }
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(room))
+}
@@ -0,0 +1,69 @@
+use serde::Serialize;
+
+use crate::{models::ChatRoom, presence::RoomCounts};
+
+/// How much a participant and a spectator count towards a room's place in
+/// `GET /api/rooms`. People talking make a room livelier than people
+/// watching it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RankingWeights {
+    pub participant: f64,
+    pub spectator: f64,
+}
+
+impl Default for RankingWeights {
+    fn default() -> Self {
+        RankingWeights { participant: 1.0, spectator: 0.25 }
+    }
+}
+
+impl RankingWeights {
+    pub fn from_env() -> Self {
+        let weight = |name: &str, default: f64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<f64>().ok())
+                .filter(|weight| weight.is_finite() && *weight >= 0.0)
+                .unwrap_or(default)
+        };
+        let defaults = Self::default();
+        RankingWeights {
+            participant: weight("ROOM_RANK_PARTICIPANT_WEIGHT", defaults.participant),
+            spectator: weight("ROOM_RANK_SPECTATOR_WEIGHT", defaults.spectator),
+        }
+    }
+
+    pub fn score(&self, counts: RoomCounts) -> f64 {
+        counts.participants as f64 * self.participant + counts.spectators as f64 * self.spectator
+    }
+}
+
+/// A room with who is in it right now, as listed and shown to clients.
+#[derive(Debug, Serialize)]
+pub struct RoomInfo {
+    #[serde(flatten)]
+    pub room: ChatRoom,
+    pub participants: usize,
+    pub spectators: usize,
+}
+
+impl RoomInfo {
+    pub fn new(room: ChatRoom, counts: RoomCounts) -> Self {
+        RoomInfo { room, participants: counts.participants, spectators: counts.spectators }
+    }
+
+    fn counts(&self) -> RoomCounts {
+        RoomCounts { participants: self.participants, spectators: self.spectators }
+    }
+}
+
+/// Highest score first; ties go to the room with the latest message.
+pub fn rank_rooms(mut rooms: Vec<RoomInfo>, weights: RankingWeights) -> Vec<RoomInfo> {
+    rooms.sort_by(|a, b| {
+        weights
+            .score(b.counts())
+            .total_cmp(&weights.score(a.counts()))
+            .then_with(|| b.room.last_message_at.cmp(&a.room.last_message_at))
+    });
+    rooms
+}
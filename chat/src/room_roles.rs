@@ -0,0 +1,156 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{auth::AuthUser, models::ChatRoom, AppError, AppState};
+
+pub const MAX_ROOM_MODERATORS: usize = 20;
+
+/// A user's standing in one room, weakest first. Owners are granted by an
+/// admin, never by creating the room; the owner names moderators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomRole {
+    Member,
+    Moderator,
+    Owner,
+}
+
+pub fn role_in(room: &ChatRoom, user_id: &str) -> RoomRole {
+    if room.owner.as_deref() == Some(user_id) {
+        RoomRole::Owner
+    } else if room.moderators.iter().any(|moderator| moderator == user_id) {
+        RoomRole::Moderator
+    } else {
+        RoomRole::Member
+    }
+}
+
+/// The role the caller acts with in the room. Service moderators and admins
+/// act as owners of every room.
+pub fn acting_role(room: &ChatRoom, user: &AuthUser) -> RoomRole {
+    if user.is_moderator() {
+        RoomRole::Owner
+    } else {
+        role_in(room, &user.user_id)
+    }
+}
+
+/// Whether the caller acts as `user_id`, whose role in the room is
+/// `room_role`, and moderates the service or the room. Moderators post past
+/// the room's permissions, over sockets and REST alike.
+pub fn caller_is_moderator(caller: Option<&AuthUser>, user_id: &str, room_role: RoomRole) -> bool {
+    caller.is_some_and(|caller| caller.user_id == user_id && (caller.is_moderator() || room_role >= RoomRole::Moderator))
+}
+
+/// Whether the caller may moderate the room, and, when the action is aimed
+/// at `target`, act on them. Room moderators can't act on the owner or each
+/// other.
+pub fn authorize(room: &ChatRoom, user: &AuthUser, target: Option<&str>) -> Result<(), AppError> {
+    let role = acting_role(room, user);
+    if role < RoomRole::Moderator {
+        return Err(AppError::Forbidden);
+    }
+    if !user.is_moderator() && target.is_some_and(|target| role_in(room, target) >= role) {
+        return Err(AppError::Forbidden);
+    }
+    Ok(())
+}
+
+/// `authorize` for a room by id. Service moderators pass without the room
+/// being loaded; rooms that don't exist have no moderators.
+pub async fn require_moderator(state: &AppState, room_id: &str, user: &AuthUser, target: Option<&str>) -> Result<(), AppError> {
+    if user.is_moderator() {
+        return Ok(());
+    }
+    let room = state.db.get_room(room_id).await?.ok_or(AppError::Forbidden)?;
+    authorize(&room, user, target)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RoomRolesResponse {
+    pub room_id: String,
+    pub owner: Option<String>,
+    pub moderators: Vec<String>,
+}
+
+impl From<ChatRoom> for RoomRolesResponse {
+    fn from(room: ChatRoom) -> Self {
+        RoomRolesResponse { room_id: room.id, owner: room.owner, moderators: room.moderators }
+    }
+}
+
+// GET /api/rooms/:location_id/roles - the room's owner and moderators
+pub async fn room_roles_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    _user: AuthUser,
+) -> Result<Json<RoomRolesResponse>, AppError> {
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(room.into()))
+}
+
+// PUT /api/rooms/:location_id/moderators/:user_id - make a user a room
+// moderator; the owner only
+pub async fn grant_moderator_handler(
+    Path((location_id, user_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RoomRolesResponse>, AppError> {
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    if acting_role(&room, &user) < RoomRole::Owner {
+        return Err(AppError::Forbidden);
+    }
+    if role_in(&room, &user_id) == RoomRole::Owner {
+        return Err(AppError::BadRequest("The owner already moderates the room".to_string()));
+    }
+    if !state.db.add_room_moderator(&location_id, &user_id, MAX_ROOM_MODERATORS).await? {
+        return Err(AppError::Conflict(format!("Rooms can have at most {} moderators", MAX_ROOM_MODERATORS)));
+    }
+    crate::room_cache::invalidate_room(&state, &location_id).await;
+    info!("{} made {} a moderator of room {}", user.username, user_id, location_id);
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(room.into()))
+}
+
+// DELETE /api/rooms/:location_id/moderators/:user_id - take a user's room
+// moderator role; the owner, or a moderator stepping down
+pub async fn revoke_moderator_handler(
+    Path((location_id, user_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RoomRolesResponse>, AppError> {
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    if user_id != user.user_id && acting_role(&room, &user) < RoomRole::Owner {
+        return Err(AppError::Forbidden);
+    }
+    if role_in(&room, &user_id) != RoomRole::Moderator || !state.db.remove_room_moderator(&location_id, &user_id).await? {
+        return Err(AppError::NotFound);
+    }
+    crate::room_cache::invalidate_room(&state, &location_id).await;
+    info!("{} removed {} as a moderator of room {}", user.username, user_id, location_id);
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(room.into()))
+}
+
+// PUT /api/admin/rooms/:location_id/owner/:user_id - hand a room to a new
+// owner; admins only
+pub async fn grant_owner_handler(
+    Path((location_id, user_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RoomRolesResponse>, AppError> {
+    if !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    if !state.db.set_room_owner(&location_id, &user_id).await? {
+        return Err(AppError::NotFound);
+    }
+    crate::room_cache::invalidate_room(&state, &location_id).await;
+    info!("Admin {} made {} the owner of room {}", user.username, user_id, location_id);
+    let room = state.db.get_room(&location_id).await?.ok_or(AppError::NotFound)?;
+    Ok(Json(room.into()))
+}
@@ -0,0 +1,177 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, oid::ObjectId},
+    options::{FindOptions, ReplaceOptions},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    auth::AuthUser,
+    models::{EventDetails, Message, MessageKind, RsvpCounts, WsMessage},
+    websocket::publish_to_room,
+    AppError, AppState,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RsvpStatus {
+    Going,
+    Maybe,
+    No,
+}
+
+/// One user's answer to an Event message; a user has at most one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rsvp {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub message_id: String,
+    pub room_id: String,
+    pub user_id: String,
+    pub username: String,
+    pub status: RsvpStatus,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+fn rsvps(state: &AppState) -> Collection<Rsvp> {
+    state.database.collection("event_rsvps")
+}
+
+/// Turns a message into an Event message when the client attached one.
+pub fn apply_event(message: &mut Message, event: Option<EventDetails>) -> Result<(), AppError> {
+    let Some(mut event) = event else {
+        return Ok(());
+    };
+    event.title = event.title.trim().to_string();
+    if event.title.is_empty() {
+        return Err(AppError::BadRequest("Event title is required".to_string()));
+    }
+    // Counts only ever come from recorded RSVPs
+    event.rsvp_counts = RsvpCounts::default();
+    message.kind = MessageKind::Event;
+    message.event = Some(event);
+    Ok(())
+}
+
+async fn load_event_message(state: &AppState, message_id: &str) -> Result<(ObjectId, Message), AppError> {
+    let id = ObjectId::parse_str(message_id).map_err(|_| AppError::BadRequest("Invalid message id".to_string()))?;
+    let message = state.db.get_message(&id).await?.filter(|m| !m.deleted).ok_or(AppError::NotFound)?;
+    if message.kind != MessageKind::Event {
+        return Err(AppError::BadRequest("Message is not an event".to_string()));
+    }
+    Ok((id, message))
+}
+
+/// Records a user's RSVP and broadcasts the new totals to the room. Socket
+/// callers pass their room so RSVPs can't reach into other rooms.
+pub async fn record_rsvp(
+    state: &AppState,
+    message_id: &str,
+    room_id: Option<&str>,
+    user_id: &str,
+    username: &str,
+    status: RsvpStatus,
+) -> Result<RsvpCounts, AppError> {
+    let (id, message) = load_event_message(state, message_id).await?;
+    if room_id.is_some_and(|room_id| room_id != message.room_id) {
+        return Err(AppError::NotFound);
+    }
+
+    let rsvp = Rsvp {
+        id: format!("{}:{}", message_id, user_id),
+        message_id: message_id.to_string(),
+        room_id: message.room_id.clone(),
+        user_id: user_id.to_string(),
+        username: username.to_string(),
+        status,
+        updated_at: Utc::now(),
+    };
+    rsvps(state)
+        .replace_one(doc! { "_id": &rsvp.id }, &rsvp, ReplaceOptions::builder().upsert(true).build())
+        .await?;
+
+    // Recount rather than increment so concurrent changes can't drift
+    let mut counts = RsvpCounts::default();
+    for (status, count) in [
+        ("going", &mut counts.going),
+        ("maybe", &mut counts.maybe),
+        ("no", &mut counts.no),
+    ] {
+        *count = rsvps(state)
+            .count_documents(doc! { "message_id": message_id, "status": status }, None)
+            .await? as u32;
+    }
+    state.db.set_rsvp_counts(&id, &counts).await?;
+    info!("User {} RSVP'd {:?} to event {}", user_id, status, message_id);
+
+    publish_to_room(
+        state,
+        &message.room_id,
+        WsMessage::RsvpUpdated {
+            message_id: message_id.to_string(),
+            counts,
+        },
+    )
+    .await;
+    Ok(counts)
+}
+
+#[derive(Deserialize)]
+pub struct RsvpRequest {
+    status: RsvpStatus,
+}
+
+// PUT /api/messages/:message_id/rsvp
+pub async fn rsvp_handler(
+    Path(message_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<RsvpRequest>,
+) -> Result<Json<RsvpCounts>, AppError> {
+    let (_, message) = load_event_message(&state, &message_id).await?;
+    crate::room_invites::require_read_access(&state, &message.room_id, Some(&user)).await?;
+    if crate::room_bans::find_ban(&state, &message.room_id, &user.user_id).await.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    let counts = record_rsvp(&state, &message_id, Some(&message.room_id), &user.user_id, &user.username, req.status).await?;
+    Ok(Json(counts))
+}
+
+#[derive(Serialize)]
+pub struct RsvpListResponse {
+    message_id: String,
+    counts: RsvpCounts,
+    rsvps: Vec<Rsvp>,
+}
+
+// GET /api/messages/:message_id/rsvps - organizer (or moderators) only
+pub async fn list_rsvps_handler(
+    Path(message_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RsvpListResponse>, AppError> {
+    let (_, message) = load_event_message(&state, &message_id).await?;
+    if message.user_id != user.user_id && !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+
+    let options = FindOptions::builder().sort(doc! { "updated_at": 1 }).build();
+    let all = rsvps(&state)
+        .find(doc! { "message_id": &message_id }, options)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(Json(RsvpListResponse {
+        message_id,
+        counts: message.event.map(|event| event.rsvp_counts).unwrap_or_default(),
+        rsvps: all,
+    }))
+}
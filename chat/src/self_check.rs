@@ -0,0 +1,183 @@
+use crate::AppState;
+use std::fmt;
+
+// Secret that `auth` falls back to when JWT_SECRET is unset
+const DEVELOPMENT_JWT_SECRET: &str = "your-secret-key-here";
+const MIN_SECRET_BYTES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// One problem found by the startup self-check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub component: &'static str,
+    pub message: String,
+}
+
+impl Finding {
+    fn error(component: &'static str, message: impl Into<String>) -> Self {
+        Finding { severity: Severity::Error, component, message: message.into() }
+    }
+
+    fn warning(component: &'static str, message: impl Into<String>) -> Self {
+        Finding { severity: Severity::Warning, component, message: message.into() }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "[{}] {}: {}", severity, self.component, self.message)
+    }
+}
+
+pub fn has_errors(findings: &[Finding]) -> bool {
+    findings.iter().any(|finding| finding.severity == Severity::Error)
+}
+
+/// Validates configuration without touching the network. `env` looks up a
+/// variable so tests can supply their own environment.
+pub fn check_config(env: impl Fn(&str) -> Option<String>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    match env("JWT_SECRET") {
+        None => findings.push(Finding::error("jwt", "JWT_SECRET is not set; tokens would be checked against the built-in development secret")),
+        Some(secret) if secret == DEVELOPMENT_JWT_SECRET => {
+            findings.push(Finding::error("jwt", "JWT_SECRET is the development placeholder; set it to the secret shared with the auth service"))
+        }
+        Some(secret) if secret.len() < MIN_SECRET_BYTES => findings.push(Finding::warning(
+            "jwt",
+            format!("JWT_SECRET is only {} bytes; use at least {} random bytes", secret.len(), MIN_SECRET_BYTES),
+        )),
+        Some(_) => {}
+    }
+
+    if let Some(token) = env("INTERNAL_API_TOKEN") {
+        if token.len() < MIN_SECRET_BYTES {
+            findings.push(Finding::warning(
+                "internal",
+                format!("INTERNAL_API_TOKEN is only {} bytes; use at least {} random bytes", token.len(), MIN_SECRET_BYTES),
+            ));
+        }
+    }
+
+    if env("REDIS_URL").is_some() && env("REDIS_URI").is_none() {
+        findings.push(Finding::warning("redis", "REDIS_URL is set but this service reads REDIS_URI; using redis://localhost:6379"));
+    }
+
+    if let Some(mode) = env("LEGACY_ROOM_BRIDGE") {
+        if !["off", "crosspost", "redirect"].contains(&mode.as_str()) {
+            findings.push(Finding::error("config", format!("LEGACY_ROOM_BRIDGE={} is not one of off, crosspost, redirect", mode)));
+        }
+    }
+
+    if let Some(enabled) = env("IP_GEO_CHECK") {
+        if enabled != "true" && enabled != "false" {
+            findings.push(Finding::warning("config", format!("IP_GEO_CHECK={} is treated as false; use true or false", enabled)));
+        }
+    }
+
+    if let Some(policy) = env("SLOW_CONSUMER_POLICY") {
+        if crate::send_buffer::SlowConsumerPolicy::parse(&policy).is_none() {
+            findings.push(Finding::warning("config", format!("SLOW_CONSUMER_POLICY={} is treated as drop; use drop or disconnect", policy)));
+        }
+    }
+
+    if let Some(backend) = env("BROADCAST_BACKEND") {
+        if crate::streams::BroadcastBackend::parse(&backend).is_none() {
+            findings.push(Finding::warning("config", format!("BROADCAST_BACKEND={} is treated as pubsub; use pubsub or streams", backend)));
+        }
+    }
+
+    if let Some(backend) = env("CANARY_BROADCAST_BACKEND") {
+        if crate::streams::BroadcastBackend::parse(&backend).is_none() {
+            findings.push(Finding::warning("config", format!("CANARY_BROADCAST_BACKEND={} is ignored; use pubsub or streams", backend)));
+        }
+    }
+
+    if let Some(enabled) = env("CANARY_MSGPACK") {
+        if enabled != "true" && enabled != "false" {
+            findings.push(Finding::warning("config", format!("CANARY_MSGPACK={} is treated as false; use true or false", enabled)));
+        }
+    }
+
+    if let Some(summarizer) = env("TOPIC_SUMMARIZER") {
+        if summarizer != "keywords" && summarizer != "off" {
+            findings.push(Finding::warning("config", format!("TOPIC_SUMMARIZER={} is treated as keywords; use keywords or off", summarizer)));
+        }
+    }
+
+    let mut expect_number = |name: &str, valid: fn(&str) -> bool, expected: &str| {
+        if let Some(value) = env(name) {
+            if !valid(&value) {
+                findings.push(Finding::error("config", format!("{}={} is not {}", name, value, expected)));
+            }
+        }
+    };
+    expect_number("PORT", |v| v.parse::<u16>().is_ok(), "a valid port");
+    expect_number("ROOM_METRICS_TOP_N", |v| v.parse::<usize>().is_ok(), "a non-negative integer");
+    expect_number("WS_SEND_BUFFER", |v| v.parse::<usize>().is_ok_and(|n| n > 0), "a positive integer");
+    expect_number("WS_PING_INTERVAL_SECS", |v| v.parse::<u64>().is_ok_and(|n| n > 0), "a positive number of seconds");
+    expect_number("WS_IDLE_TIMEOUT_SECS", |v| v.parse::<u64>().is_ok_and(|n| n > 0), "a positive number of seconds");
+    expect_number("WS_MAX_FRAME_BYTES", |v| v.parse::<usize>().is_ok_and(|n| n > 0), "a positive number of bytes");
+    expect_number("WS_MAX_JSON_DEPTH", |v| v.parse::<usize>().is_ok_and(|n| n > 0), "a positive integer");
+    expect_number("WS_MAX_FIELD_BYTES", |v| v.parse::<usize>().is_ok_and(|n| n > 0), "a positive number of bytes");
+    expect_number("ROOM_RANK_PARTICIPANT_WEIGHT", |v| v.parse::<f64>().is_ok_and(|w| w.is_finite() && w >= 0.0), "a non-negative number");
+    expect_number("ROOM_RANK_SPECTATOR_WEIGHT", |v| v.parse::<f64>().is_ok_and(|w| w.is_finite() && w >= 0.0), "a non-negative number");
+    expect_number("ROOM_WARM_CACHE_SIZE", |v| v.parse::<usize>().is_ok(), "a non-negative integer");
+    expect_number("ROOM_CREATION_DAILY_LIMIT", |v| v.parse::<u32>().is_ok(), "a non-negative integer");
+    expect_number("ROOM_CREATION_REVIEW_AFTER", |v| v.parse::<u32>().is_ok(), "a non-negative integer");
+    expect_number("ROOM_REVIEW_ORGANIC_POSTERS", |v| v.parse::<usize>().is_ok_and(|n| n > 0), "a positive integer");
+    expect_number("CANARY_PERCENT", |v| v.parse::<u8>().is_ok_and(|n| n <= 100), "a percentage from 0 to 100");
+    expect_number("UPLOAD_MAX_BYTES", |v| v.parse::<u64>().is_ok_and(|n| n > 0), "a positive number of bytes");
+    expect_number("USER_SERVICE_TIMEOUT_MS", |v| v.parse::<u64>().is_ok_and(|n| n > 0), "a positive number of milliseconds");
+    expect_number("TOPIC_SUMMARY_INTERVAL_SECS", |v| v.parse::<u64>().is_ok_and(|n| n >= 60), "at least 60 seconds");
+    expect_number("IP_GEO_MAX_DISTANCE_KM", |v| v.parse::<f64>().is_ok_and(|km| km > 0.0), "a positive number of kilometres");
+    expect_number("SHUTDOWN_DRAIN_SECS", |v| v.parse::<u64>().is_ok(), "a number of seconds");
+    expect_number("SHUTDOWN_RECONNECT_SPREAD_SECS", |v| v.parse::<u64>().is_ok_and(|n| n > 0), "a positive number of seconds");
+
+    findings
+}
+
+/// Checks the backing services the state was built against.
+pub async fn check_services(state: &AppState) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    match state.db.ping().await {
+        Ok(()) => {
+            let missing = state.db.missing_indexes().await;
+            if !missing.is_empty() {
+                findings.push(Finding::warning(
+                    "mongodb",
+                    format!("missing indexes {} (created automatically when the service starts)", missing.join(", ")),
+                ));
+            }
+        }
+        Err(e) => findings.push(Finding::error("mongodb", format!("cannot reach MongoDB, check MONGODB_URI: {}", e))),
+    }
+
+    match state.redis_pool.get().await {
+        Ok(mut conn) => {
+            if let Err(e) = redis::cmd("PING").query_async::<_, String>(&mut conn).await {
+                findings.push(Finding::error("redis", format!("PING failed: {}", e)));
+            }
+        }
+        Err(e) => findings.push(Finding::error("redis", format!("cannot reach Redis, check REDIS_URI: {}", e))),
+    }
+
+    findings
+}
+
+pub async fn run(state: &AppState) -> Vec<Finding> {
+    let mut findings = check_config(|name| std::env::var(name).ok());
+    findings.extend(check_services(state).await);
+    findings
+}
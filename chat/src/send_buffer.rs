@@ -0,0 +1,272 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use thiserror::Error;
+use tokio::sync::mpsc::{self, error::{TryRecvError, TrySendError}};
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::models::WsMessage;
+
+const DEFAULT_CAPACITY: usize = 256;
+
+/// What happens when a socket's send buffer is full because the client
+/// isn't reading fast enough.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlowConsumerPolicy {
+    // Drop new messages until the client catches up
+    Drop,
+    // Close the socket; the client reconnects and reloads history
+    Disconnect,
+}
+
+impl SlowConsumerPolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "drop" => Some(SlowConsumerPolicy::Drop),
+            "disconnect" => Some(SlowConsumerPolicy::Disconnect),
+            _ => None,
+        }
+    }
+}
+
+/// Outbound lanes, highest priority first. Each lane is buffered separately
+/// and drained before the next, so chat messages never wait behind a flood
+/// of typing or presence events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    // Chat messages and everything the client is waiting on, e.g. history,
+    // join replies and errors
+    Message,
+    Receipt,
+    Typing,
+    Presence,
+}
+
+const LANES: usize = 4;
+
+impl Priority {
+    pub fn of(message: &WsMessage) -> Self {
+        match message {
+            WsMessage::DMRead { .. }
+            | WsMessage::DMDelivered { .. }
+            | WsMessage::DMReadUpdated { .. }
+            | WsMessage::RsvpUpdated { .. }
+            | WsMessage::ReactionsUpdated { .. } => Priority::Receipt,
+            WsMessage::Typing { .. }
+            | WsMessage::DMTyping { .. }
+            | WsMessage::Activity { .. }
+            | WsMessage::UserActivity { .. } => Priority::Typing,
+            WsMessage::UserJoined { .. } | WsMessage::UserLeft { .. } => Priority::Presence,
+            _ => Priority::Message,
+        }
+    }
+
+    fn lane(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("Socket is closed")]
+pub struct SocketClosed;
+
+/// Per-socket outbound buffer settings, shared with counters for
+/// `/metrics/fanout`.
+pub struct SendBuffers {
+    capacity: usize,
+    policy: SlowConsumerPolicy,
+    dropped: AtomicU64,
+    disconnects: AtomicU64,
+}
+
+impl SendBuffers {
+    pub fn new(capacity: usize, policy: SlowConsumerPolicy) -> Self {
+        SendBuffers {
+            capacity: capacity.max(1),
+            policy,
+            dropped: AtomicU64::new(0),
+            disconnects: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("WS_SEND_BUFFER")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        let policy = std::env::var("SLOW_CONSUMER_POLICY")
+            .ok()
+            .and_then(|value| SlowConsumerPolicy::parse(&value))
+            .unwrap_or(SlowConsumerPolicy::Drop);
+        Self::new(capacity, policy)
+    }
+
+    /// A socket's buffer; every priority lane holds up to `capacity`
+    /// messages.
+    pub fn channel(self: &Arc<Self>) -> (SocketSender, SocketReceiver) {
+        let (tx, rx): (Vec<_>, Vec<_>) = (0..LANES).map(|_| mpsc::channel(self.capacity)).unzip();
+        let low_data = Arc::new(AtomicBool::new(false));
+        let user_id = Arc::new(OnceLock::new());
+        let sender = SocketSender {
+            lanes: tx.into(),
+            buffers: self.clone(),
+            overloaded: Arc::new(Notify::new()),
+            dropping: Arc::new(AtomicBool::new(false)),
+            low_data: low_data.clone(),
+            user_id: user_id.clone(),
+        };
+        let receiver = SocketReceiver {
+            lanes: rx.try_into().unwrap_or_else(|_| unreachable!()),
+            low_data,
+            user_id,
+        };
+        (sender, receiver)
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, u64); 2] = [
+            ("chat_ws_send_dropped_total", "counter", "Messages dropped because a socket's send buffer was full", self.dropped.load(Ordering::Relaxed)),
+            ("chat_ws_slow_consumer_disconnects_total", "counter", "Sockets closed because their send buffer was full", self.disconnects.load(Ordering::Relaxed)),
+        ];
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
+
+/// Sending half of a socket's bounded buffer. Sends never wait; a full
+/// buffer is handled by the configured `SlowConsumerPolicy`.
+#[derive(Clone)]
+pub struct SocketSender {
+    // Indexed by `Priority`
+    lanes: Arc<[mpsc::Sender<WsMessage>]>,
+    buffers: Arc<SendBuffers>,
+    overloaded: Arc<Notify>,
+    // Set while messages are being dropped, so the warning is logged once
+    dropping: Arc<AtomicBool>,
+    // The client asked for low data mode at join; shared with the receiver
+    low_data: Arc<AtomicBool>,
+    // Who the socket joined as, so room-wide control messages can single
+    // out its user
+    user_id: Arc<OnceLock<String>>,
+}
+
+impl SocketSender {
+    pub fn send(&self, message: WsMessage) -> Result<(), SocketClosed> {
+        let priority = Priority::of(&message);
+        // Low data sockets get no typing or presence events at all
+        if self.is_low_data() && matches!(priority, Priority::Typing | Priority::Presence) {
+            return Ok(());
+        }
+        match self.lanes[priority.lane()].try_send(message) {
+            Ok(()) => {
+                self.dropping.store(false, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(SocketClosed),
+            Err(TrySendError::Full(_)) => match self.buffers.policy {
+                SlowConsumerPolicy::Drop => {
+                    if !self.dropping.swap(true, Ordering::Relaxed) {
+                        warn!("Socket send buffer full, dropping messages until the client catches up");
+                    }
+                    self.buffers.dropped.fetch_add(1, Ordering::Relaxed);
+                    Ok(())
+                }
+                SlowConsumerPolicy::Disconnect => {
+                    if !self.dropping.swap(true, Ordering::Relaxed) {
+                        warn!("Socket send buffer full, disconnecting slow client");
+                        self.buffers.disconnects.fetch_add(1, Ordering::Relaxed);
+                        self.overloaded.notify_one();
+                    }
+                    Err(SocketClosed)
+                }
+            },
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lanes[Priority::Message.lane()].is_closed()
+    }
+
+    pub fn set_low_data(&self, low_data: bool) {
+        self.low_data.store(low_data, Ordering::Relaxed);
+    }
+
+    pub fn is_low_data(&self) -> bool {
+        self.low_data.load(Ordering::Relaxed)
+    }
+
+    /// Records who the socket joined as. A socket joins as one user only,
+    /// so later calls are ignored.
+    pub fn set_user(&self, user_id: &str) {
+        let _ = self.user_id.set(user_id.to_string());
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        self.user_id.get().map(String::as_str)
+    }
+
+    pub async fn closed(&self) {
+        self.lanes[Priority::Message.lane()].closed().await
+    }
+
+    /// Resolves once the socket should be closed as a slow consumer.
+    pub async fn overloaded(&self) {
+        self.overloaded.notified().await
+    }
+}
+
+/// Receiving half of a socket's buffer, handing out queued messages highest
+/// priority first.
+pub struct SocketReceiver {
+    lanes: [mpsc::Receiver<WsMessage>; LANES],
+    low_data: Arc<AtomicBool>,
+    user_id: Arc<OnceLock<String>>,
+}
+
+impl SocketReceiver {
+    pub fn is_low_data(&self) -> bool {
+        self.low_data.load(Ordering::Relaxed)
+    }
+
+    pub fn user_id(&self) -> Option<&str> {
+        self.user_id.get().map(String::as_str)
+    }
+
+    /// The next message, or `None` once every sender is gone and the lanes
+    /// are drained.
+    pub async fn recv(&mut self) -> Option<WsMessage> {
+        let [messages, receipts, typing, presence] = &mut self.lanes;
+        tokio::select! {
+            biased;
+            Some(message) = messages.recv() => Some(message),
+            Some(message) = receipts.recv() => Some(message),
+            Some(message) = typing.recv() => Some(message),
+            Some(message) = presence.recv() => Some(message),
+            else => None,
+        }
+    }
+
+    /// Messages waiting across all lanes.
+    pub fn queued(&self) -> usize {
+        self.lanes.iter().map(|lane| lane.len()).sum()
+    }
+
+    pub fn try_recv(&mut self) -> Result<WsMessage, TryRecvError> {
+        let mut result = Err(TryRecvError::Disconnected);
+        for lane in &mut self.lanes {
+            match lane.try_recv() {
+                Ok(message) => return Ok(message),
+                Err(TryRecvError::Empty) => result = Err(TryRecvError::Empty),
+                Err(TryRecvError::Disconnected) => {}
+            }
+        }
+        result
+    }
+}
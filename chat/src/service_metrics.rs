@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, gauge, histogram, Unit};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use metrics_util::MetricKindMask;
+use tracing::warn;
+
+use crate::{room_metrics::OTHER_ROOMS_LABEL, AppState};
+
+pub const ACTIVE_SOCKETS: &str = "chat_active_sockets";
+pub const ROOM_SOCKETS: &str = "chat_room_sockets";
+pub const MESSAGES_RECEIVED: &str = "chat_messages_received_total";
+pub const MESSAGES_SENT: &str = "chat_messages_sent_total";
+pub const REDIS_PUBLISH_SECONDS: &str = "chat_redis_publish_duration_seconds";
+pub const MONGO_INSERT_SECONDS: &str = "chat_mongo_insert_duration_seconds";
+pub const SEND_QUEUE_DEPTH: &str = "chat_ws_send_queue_depth";
+pub const SOCKET_VIOLATIONS: &str = "chat_socket_violations_total";
+pub const IP_BLOCKS: &str = "chat_socket_ip_blocks_total";
+
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5];
+const QUEUE_DEPTH_BUCKETS: &[f64] = &[0.0, 1.0, 2.0, 4.0, 8.0, 16.0, 32.0, 64.0, 128.0, 256.0];
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+// Room gauges not refreshed for this long are dropped, so rooms that empty
+// out or fall out of the top N stop being exported
+const GAUGE_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Installs the process-wide Prometheus recorder behind `/metrics`. Later
+/// calls, e.g. a second `AppState` in one process, get a handle to a
+/// recorder that isn't installed and renders nothing.
+pub fn install() -> PrometheusHandle {
+    let builder = PrometheusBuilder::new()
+        .idle_timeout(MetricKindMask::GAUGE, Some(GAUGE_IDLE_TIMEOUT))
+        .set_buckets_for_metric(Matcher::Suffix("_duration_seconds".to_string()), LATENCY_BUCKETS)
+        .and_then(|builder| builder.set_buckets_for_metric(Matcher::Full(SEND_QUEUE_DEPTH.to_string()), QUEUE_DEPTH_BUCKETS))
+        .expect("histogram buckets are not empty");
+    let recorder = builder.build_recorder();
+    let handle = recorder.handle();
+    if metrics::set_global_recorder(recorder).is_err() {
+        warn!("A metrics recorder is already installed; /metrics will be empty");
+    }
+    describe();
+    handle
+}
+
+fn describe() {
+    describe_gauge!(ACTIVE_SOCKETS, "Room and hex sockets open on this instance");
+    describe_gauge!(ROOM_SOCKETS, "Sockets per room on this instance (top rooms, rest as \"other\")");
+    describe_counter!(MESSAGES_RECEIVED, "WebSocket messages received from clients");
+    describe_counter!(MESSAGES_SENT, "WebSocket messages written to clients");
+    describe_histogram!(REDIS_PUBLISH_SECONDS, Unit::Seconds, "Time to publish a broadcast to Redis");
+    describe_histogram!(MONGO_INSERT_SECONDS, Unit::Seconds, "Time to insert a message into MongoDB, retries included");
+    describe_histogram!(SEND_QUEUE_DEPTH, "Messages still queued for a socket after one is written");
+    describe_counter!(SOCKET_VIOLATIONS, "Protocol violations by sockets, by kind");
+    describe_counter!(IP_BLOCKS, "IPs blocked from opening sockets after too many violations");
+}
+
+pub fn message_received() {
+    counter!(MESSAGES_RECEIVED).increment(1);
+}
+
+pub fn message_sent(queued: usize) {
+    counter!(MESSAGES_SENT).increment(1);
+    histogram!(SEND_QUEUE_DEPTH).record(queued as f64);
+}
+
+pub fn socket_violation(kind: &'static str) {
+    counter!(SOCKET_VIOLATIONS, "kind" => kind).increment(1);
+}
+
+pub fn ip_blocked() {
+    counter!(IP_BLOCKS).increment(1);
+}
+
+pub fn redis_publish(elapsed: Duration) {
+    histogram!(REDIS_PUBLISH_SECONDS).record(elapsed.as_secs_f64());
+}
+
+pub fn mongo_insert(elapsed: Duration) {
+    histogram!(MONGO_INSERT_SECONDS).record(elapsed.as_secs_f64());
+}
+
+/// The `top_n` fullest rooms by socket count, fullest first, with the rest
+/// summed under "other".
+pub fn bucket_rooms(mut rooms: Vec<(String, usize)>, top_n: usize) -> Vec<(String, usize)> {
+    rooms.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let other: usize = rooms.iter().skip(top_n).map(|(_, sockets)| sockets).sum();
+    rooms.truncate(top_n);
+    if other > 0 {
+        rooms.push((OTHER_ROOMS_LABEL.to_string(), other));
+    }
+    rooms
+}
+
+/// Refreshes the socket gauges from this instance's connections and keeps
+/// the recorder's histograms and idle gauges tidy.
+pub fn spawn_refresh(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            tick.tick().await;
+            let rooms = state.connections.read().await.socket_counts();
+            gauge!(ACTIVE_SOCKETS).set(rooms.iter().map(|(_, sockets)| *sockets).sum::<usize>() as f64);
+            for (room_id, sockets) in bucket_rooms(rooms, state.room_metrics.top_n()) {
+                gauge!(ROOM_SOCKETS, "room" => room_id).set(sockets as f64);
+            }
+            state.metrics.run_upkeep();
+        }
+    })
+}
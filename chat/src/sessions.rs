@@ -0,0 +1,119 @@
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::models::User;
+
+// How long after a disconnect the client can resume instead of rejoining
+pub const RESUME_WINDOW_SECONDS: u64 = 2 * 60;
+// Upper bound for a connected socket's session, in case its instance dies
+// before the disconnect shortens it
+const LIVE_SESSION_SECONDS: u64 = 24 * 60 * 60;
+
+fn session_key(session_id: &str) -> String {
+    format!("ws_session:{}", session_id)
+}
+
+/// What a joined socket had been admitted as, so a reconnecting client can
+/// pick up where it left off with `Resume` instead of a full `Join`. The
+/// session id is the id of the socket that started it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SocketSession {
+    pub user_id: String,
+    pub username: String,
+    pub room_id: String,
+    #[serde(default)]
+    pub is_moderator: bool,
+    #[serde(default)]
+    pub location_flagged: bool,
+    // Neighbour rings a hex socket listened to, restored on resume
+    #[serde(default)]
+    pub include_neighbors: u8,
+    // Low data mode the socket joined with, restored on resume
+    #[serde(default)]
+    pub low_data: bool,
+    // Whether the socket joined with a token or ticket for `user_id`; only
+    // verified sessions may write
+    #[serde(default)]
+    pub verified: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub badge: Option<crate::badges::Badge>,
+}
+
+impl SocketSession {
+    pub fn new(user: &User, is_moderator: bool) -> Self {
+        SocketSession {
+            user_id: user.id.clone(),
+            username: user.username.clone(),
+            room_id: user.location_id.clone(),
+            is_moderator,
+            location_flagged: user.location_flagged,
+            include_neighbors: 0,
+            low_data: false,
+            verified: false,
+            badge: user.badge,
+        }
+    }
+
+    /// The user as seen on the resuming socket.
+    pub fn user(&self, socket_id: &str) -> User {
+        User {
+            id: self.user_id.clone(),
+            username: self.username.clone(),
+            socket_id: socket_id.to_string(),
+            location_id: self.room_id.clone(),
+            location_flagged: self.location_flagged,
+            joined_at: Utc::now(),
+            badge: self.badge,
+        }
+    }
+}
+
+pub async fn start(pool: &deadpool_redis::Pool, session_id: &str, session: &SocketSession) {
+    let Ok(mut conn) = pool.get().await else {
+        return;
+    };
+    let Ok(payload) = serde_json::to_string(session) else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::cmd("SET")
+        .arg(session_key(session_id))
+        .arg(payload)
+        .arg("EX")
+        .arg(LIVE_SESSION_SECONDS)
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to store session {}: {}", session_id, e);
+    }
+}
+
+/// Keeps a disconnected socket's session resumable for `retention_secs`, at
+/// most the resume window. Zero deletes it straight away.
+pub async fn end(pool: &deadpool_redis::Pool, session_id: &str, retention_secs: u64) {
+    let Ok(mut conn) = pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = match retention_secs.min(RESUME_WINDOW_SECONDS) {
+        0 => redis::cmd("DEL").arg(session_key(session_id)).query_async(&mut conn).await,
+        secs => redis::cmd("EXPIRE").arg(session_key(session_id)).arg(secs).query_async(&mut conn).await,
+    };
+    if let Err(e) = result {
+        error!("Failed to end session {}: {}", session_id, e);
+    }
+}
+
+/// Claims a session for resuming. Each session can only be resumed once;
+/// the resumed socket starts a session of its own.
+pub async fn take(pool: &deadpool_redis::Pool, session_id: &str) -> Option<SocketSession> {
+    let mut conn = pool.get().await.ok()?;
+    let payload: redis::RedisResult<Option<String>> =
+        redis::cmd("GETDEL").arg(session_key(session_id)).query_async(&mut conn).await;
+    match payload {
+        Ok(payload) => payload.and_then(|payload| serde_json::from_str(&payload).ok()),
+        Err(e) => {
+            error!("Failed to load session {}: {}", session_id, e);
+            None
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Document};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    auth::AuthUser,
+    models::{Message, WsMessage},
+    AppError, AppState,
+};
+
+// Hash of user_id -> ShadowBan JSON
+const SHADOW_BANS_KEY: &str = "shadow_bans";
+const MAX_REASON_CHARS: usize = 200;
+
+/// A user whose messages are stored and shown back to them, but reach no
+/// one else. Unlike a ban, nothing tells them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShadowBan {
+    pub user_id: String,
+    pub banned_by: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ShadowBanRequest {
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+impl ShadowBanRequest {
+    pub fn into_ban(self, user_id: &str, banned_by: &str) -> Result<ShadowBan, String> {
+        let reason = self.reason.map(|reason| reason.trim().to_string()).filter(|reason| !reason.is_empty());
+        if reason.as_ref().is_some_and(|reason| reason.chars().count() > MAX_REASON_CHARS) {
+            return Err(format!("reason must be at most {} characters", MAX_REASON_CHARS));
+        }
+        Ok(ShadowBan { user_id: user_id.to_string(), banned_by: banned_by.to_string(), reason, created_at: Utc::now() })
+    }
+}
+
+/// Whether the user is shadow-banned. Fails open while Redis is
+/// unavailable, so their messages go out as usual.
+pub async fn is_shadow_banned(pool: &deadpool_redis::Pool, user_id: &str) -> bool {
+    let Ok(mut conn) = pool.get().await else {
+        return false;
+    };
+    let banned: redis::RedisResult<bool> = redis::cmd("HEXISTS").arg(SHADOW_BANS_KEY).arg(user_id).query_async(&mut conn).await;
+    banned.unwrap_or_else(|e| {
+        error!("Failed to check whether {} is shadow-banned: {}", user_id, e);
+        false
+    })
+}
+
+/// The author of a broadcast whose delivery a shadow-ban would limit: new
+/// messages only, so reactions and typing still reach the room.
+pub fn author_of(message: &WsMessage) -> Option<&str> {
+    match message {
+        WsMessage::NewMessage(message) => Some(&message.user_id),
+        _ => None,
+    }
+}
+
+/// Narrows a message query to what `viewer` may see: messages sent while
+/// their author was shadow-banned are left out, except the viewer's own.
+/// `author_field` holds the author's id.
+pub fn visible_to(author_field: &str, viewer: Option<&str>) -> Document {
+    match viewer {
+        Some(viewer) => doc! { "$or": [{ "shadow": { "$ne": true } }, { author_field: viewer }] },
+        None => doc! { "shadow": { "$ne": true } },
+    }
+}
+
+/// Clears the marker from messages about to be shown, so it never gives a
+/// shadow-ban away to the author.
+pub fn unmark(messages: &mut [Message]) {
+    for message in messages {
+        message.shadow = false;
+    }
+}
+
+/// `unmark` for a new message echoed back to its shadow-banned author.
+pub fn unmark_broadcast(message: WsMessage) -> WsMessage {
+    match message {
+        WsMessage::NewMessage(mut message) => {
+            message.shadow = false;
+            WsMessage::NewMessage(message)
+        }
+        other => other,
+    }
+}
+
+/// The channel a shadow-banned sender's DMs are published to instead of the
+/// conversation's, which only their own sockets read.
+pub fn dm_echo_channel(conversation_id: &str, user_id: &str) -> String {
+    format!("dm:{}:echo:{}", conversation_id, user_id)
+}
+
+async fn connection(state: &AppState) -> Result<deadpool_redis::Connection, AppError> {
+    state.redis_pool.get().await.map_err(|e| {
+        error!("Redis unavailable for shadow bans: {}", e);
+        AppError::ServiceUnavailable { retry_after: 5 }
+    })
+}
+
+// GET /api/admin/shadow-bans - every shadow-banned user; moderators only
+pub async fn list_shadow_bans_handler(State(state): State<AppState>, user: AuthUser) -> Result<Json<Vec<ShadowBan>>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let mut conn = connection(&state).await?;
+    let bans: Vec<String> = redis::cmd("HVALS").arg(SHADOW_BANS_KEY).query_async(&mut conn).await.map_err(|e| {
+        error!("Failed to list shadow bans: {}", e);
+        AppError::InternalServerError
+    })?;
+    let mut bans: Vec<ShadowBan> = bans.iter().filter_map(|ban| serde_json::from_str(ban).ok()).collect();
+    bans.sort_by_key(|ban| std::cmp::Reverse(ban.created_at));
+    Ok(Json(bans))
+}
+
+// PUT /api/admin/shadow-bans/:user_id - shadow-ban a user; moderators only
+pub async fn shadow_ban_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<ShadowBanRequest>,
+) -> Result<Json<ShadowBan>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    if user_id == user.user_id {
+        return Err(AppError::BadRequest("Moderators can't shadow-ban themselves".to_string()));
+    }
+    let ban = req.into_ban(&user_id, &user.user_id).map_err(AppError::BadRequest)?;
+    let payload = serde_json::to_string(&ban).map_err(|_| AppError::InternalServerError)?;
+    let mut conn = connection(&state).await?;
+    let _: () = redis::cmd("HSET").arg(SHADOW_BANS_KEY).arg(&user_id).arg(payload).query_async(&mut conn).await.map_err(|e| {
+        error!("Failed to shadow-ban {}: {}", user_id, e);
+        AppError::InternalServerError
+    })?;
+    info!("Moderator {} shadow-banned {}", user.username, user_id);
+    Ok(Json(ban))
+}
+
+// DELETE /api/admin/shadow-bans/:user_id - lift a shadow-ban
+pub async fn lift_shadow_ban_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    let mut conn = connection(&state).await?;
+    let removed: i64 = redis::cmd("HDEL").arg(SHADOW_BANS_KEY).arg(&user_id).query_async(&mut conn).await.map_err(|e| {
+        error!("Failed to lift the shadow-ban of {}: {}", user_id, e);
+        AppError::InternalServerError
+    })?;
+    if removed == 0 {
+        return Err(AppError::NotFound);
+    }
+    info!("Moderator {} lifted the shadow-ban of {}", user.username, user_id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use futures::TryStreamExt;
+use h3o::{CellIndex, Resolution};
+use mongodb::{
+    bson::{self, doc},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth::AuthUser, read_cursors::ReadCursor, room_bridge::legacy_room_cell, sessions::RESUME_WINDOW_SECONDS, AppError, AppState};
+
+// Overlaps are reported at roughly neighbourhood-of-a-city scale (~36 km²)
+pub const SHARED_AREA_RESOLUTION: Resolution = Resolution::Six;
+// How far back "recently active" reaches
+pub const SHARED_ROOMS_WINDOW_DAYS: i64 = 30;
+
+/// Per-user privacy settings. Crossed paths is off unless the user turns it
+/// on; location history is kept unless they turn it off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PrivacySettings {
+    #[serde(rename = "_id")]
+    pub user_id: String,
+    #[serde(default)]
+    pub share_crossed_paths: bool,
+    // Off stops recording visits to location rooms
+    #[serde(default = "default_true")]
+    pub store_location_history: bool,
+    // Seconds a disconnected socket's room is kept for resuming; None is
+    // the full resume window
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location_retention_secs: Option<u64>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl PrivacySettings {
+    pub fn new(user_id: &str) -> Self {
+        PrivacySettings {
+            user_id: user_id.to_string(),
+            share_crossed_paths: false,
+            store_location_history: true,
+            location_retention_secs: None,
+        }
+    }
+
+    /// How long the user's room outlives a disconnect, never longer than
+    /// the resume window.
+    pub fn retention_seconds(&self) -> u64 {
+        self.location_retention_secs.unwrap_or(RESUME_WINDOW_SECONDS).min(RESUME_WINDOW_SECONDS)
+    }
+}
+
+fn privacy(state: &AppState) -> Collection<PrivacySettings> {
+    state.database.collection("user_privacy")
+}
+
+/// A coarse area both users were active in, never the room itself.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SharedArea {
+    pub h3_index: String,
+    // Day granularity so the overlap can't be timed precisely
+    pub last_seen: NaiveDate,
+}
+
+/// The coarse cell a room lies in, for hex rooms and legacy `lat_lng` rooms.
+pub fn coarse_area(room_id: &str) -> Option<CellIndex> {
+    let cell = room_id.parse::<CellIndex>().ok().or_else(|| legacy_room_cell(room_id))?;
+    if cell.resolution() <= SHARED_AREA_RESOLUTION {
+        return Some(cell);
+    }
+    cell.parent(SHARED_AREA_RESOLUTION)
+}
+
+/// Areas containing a room both users were active in, most recent first.
+/// An area's date is the later of its overlaps, each being the earlier of
+/// the two users' last visits.
+pub fn shared_areas(mine: &[(String, DateTime<Utc>)], theirs: &[(String, DateTime<Utc>)]) -> Vec<SharedArea> {
+    let theirs: HashMap<&str, DateTime<Utc>> = theirs.iter().map(|(room, at)| (room.as_str(), *at)).collect();
+    let mut areas: HashMap<CellIndex, DateTime<Utc>> = HashMap::new();
+    for (room, my_time) in mine {
+        let Some(their_time) = theirs.get(room.as_str()) else {
+            continue;
+        };
+        let Some(area) = coarse_area(room) else {
+            continue;
+        };
+        let overlap = (*my_time).min(*their_time);
+        let latest = areas.entry(area).or_insert(overlap);
+        *latest = (*latest).max(overlap);
+    }
+
+    let mut areas: Vec<SharedArea> = areas
+        .into_iter()
+        .map(|(cell, at)| SharedArea { h3_index: cell.to_string(), last_seen: at.date_naive() })
+        .collect();
+    areas.sort_by(|a, b| b.last_seen.cmp(&a.last_seen).then_with(|| a.h3_index.cmp(&b.h3_index)));
+    areas
+}
+
+/// Rooms tied to a place, whose visits make up a user's location history.
+pub fn is_location_room(room_id: &str) -> bool {
+    coarse_area(room_id).is_some()
+}
+
+/// The user's settings. Settings that can't be read count as opted out of
+/// location history and retention, erring towards storing less.
+pub async fn load_privacy(state: &AppState, user_id: &str) -> PrivacySettings {
+    match privacy(state).find_one(doc! { "_id": user_id }, None).await {
+        Ok(settings) => settings.unwrap_or_else(|| PrivacySettings::new(user_id)),
+        Err(e) => {
+            tracing::error!("Failed to load privacy settings of {}: {}", user_id, e);
+            PrivacySettings { store_location_history: false, location_retention_secs: Some(0), ..PrivacySettings::new(user_id) }
+        }
+    }
+}
+
+async fn shares_crossed_paths(state: &AppState, user_id: &str) -> Result<bool, AppError> {
+    let settings = privacy(state).find_one(doc! { "_id": user_id }, None).await?;
+    Ok(settings.is_some_and(|settings| settings.share_crossed_paths))
+}
+
+async fn recent_rooms(state: &AppState, user_id: &str) -> Result<Vec<(String, DateTime<Utc>)>, AppError> {
+    let since = Utc::now() - Duration::days(SHARED_ROOMS_WINDOW_DAYS);
+    let cursors: Vec<ReadCursor> = state
+        .database
+        .collection::<ReadCursor>("room_read_cursors")
+        .find(
+            // Only rooms the user was in on a socket, not ones merely marked read
+            doc! { "user_id": user_id, "visited_at": { "$gte": bson::DateTime::from_millis(since.timestamp_millis()) } },
+            None,
+        )
+        .await?
+        .try_collect()
+        .await?;
+    Ok(cursors.into_iter().map(|cursor| (cursor.room_id, cursor.last_read_at)).collect())
+}
+
+#[derive(Serialize)]
+pub struct SharedRoomsResponse {
+    user_id: String,
+    areas: Vec<SharedArea>,
+}
+
+// GET /api/users/:user_id/shared-rooms
+// Both users must have opted in; otherwise it looks like there is no overlap
+pub async fn shared_rooms_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<SharedRoomsResponse>, AppError> {
+    if user_id == user.user_id {
+        return Err(AppError::BadRequest("Cannot compare a user with themselves".to_string()));
+    }
+
+    let areas = if shares_crossed_paths(&state, &user.user_id).await? && shares_crossed_paths(&state, &user_id).await? {
+        let mine = recent_rooms(&state, &user.user_id).await?;
+        let theirs = recent_rooms(&state, &user_id).await?;
+        shared_areas(&mine, &theirs)
+    } else {
+        vec![]
+    };
+    Ok(Json(SharedRoomsResponse { user_id, areas }))
+}
+
+#[derive(Deserialize)]
+pub struct UpdatePrivacyRequest {
+    share_crossed_paths: Option<bool>,
+    store_location_history: Option<bool>,
+    location_retention_secs: Option<u64>,
+}
+
+// PUT /api/users/:user_id/privacy - users can only change their own
+pub async fn update_privacy_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<UpdatePrivacyRequest>,
+) -> Result<Json<PrivacySettings>, AppError> {
+    if user_id != user.user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    if req.location_retention_secs.is_some_and(|secs| secs > RESUME_WINDOW_SECONDS) {
+        return Err(AppError::BadRequest(format!("location_retention_secs can be at most {}", RESUME_WINDOW_SECONDS)));
+    }
+
+    let mut update = doc! {};
+    if let Some(share) = req.share_crossed_paths {
+        update.insert("share_crossed_paths", share);
+    }
+    if let Some(store) = req.store_location_history {
+        update.insert("store_location_history", store);
+    }
+    if let Some(secs) = req.location_retention_secs {
+        update.insert("location_retention_secs", secs as i64);
+    }
+    if update.is_empty() {
+        return Err(AppError::BadRequest("Nothing to update".to_string()));
+    }
+    let options = FindOneAndUpdateOptions::builder().upsert(true).return_document(ReturnDocument::After).build();
+    let settings = privacy(&state)
+        .find_one_and_update(doc! { "_id": &user_id }, doc! { "$set": update }, options)
+        .await?
+        .unwrap_or_else(|| PrivacySettings::new(&user_id));
+    Ok(Json(settings))
+}
+
+#[derive(Serialize)]
+pub struct PurgeLocationResponse {
+    deleted_visits: u64,
+}
+
+// DELETE /api/users/:user_id/location - forgets which location rooms the
+// user visited and when, right away; users can only purge their own
+pub async fn purge_location_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<PurgeLocationResponse>, AppError> {
+    if user_id != user.user_id {
+        return Err(AppError::Forbidden);
+    }
+
+    let cursors = state.database.collection::<ReadCursor>("room_read_cursors");
+    let visits: Vec<ReadCursor> = cursors.find(doc! { "user_id": &user_id }, None).await?.try_collect().await?;
+    let ids: Vec<String> = visits.into_iter().filter(|cursor| is_location_room(&cursor.room_id)).map(|cursor| cursor.id).collect();
+    let deleted_visits = if ids.is_empty() {
+        0
+    } else {
+        cursors.delete_many(doc! { "_id": { "$in": ids } }, None).await?.deleted_count
+    };
+    tracing::info!("Purged {} location room visits of {}", deleted_visits, user_id);
+    Ok(Json(PurgeLocationResponse { deleted_visits }))
+}
@@ -0,0 +1,156 @@
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::{AppError, AppState};
+
+const DEFAULT_DRAIN_SECS: u64 = 10;
+const DEFAULT_RECONNECT_SPREAD_SECS: u64 = 5;
+// How often draining checks whether the sockets have gone
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(200);
+// Longest wait for queued MongoDB writes once sockets are closed
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Draining on SIGTERM: new sockets are refused, open ones are told to
+/// reconnect elsewhere and closed, and queued writes are flushed before exit.
+pub struct Shutdown {
+    draining: AtomicBool,
+    notice: watch::Sender<bool>,
+    pub drain_timeout: Duration,
+    // Clients are told to reconnect within this many seconds, spread out
+    // so they don't all land on the remaining instances at once
+    pub reconnect_spread: u64,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new(Duration::from_secs(DEFAULT_DRAIN_SECS), DEFAULT_RECONNECT_SPREAD_SECS)
+    }
+}
+
+impl Shutdown {
+    pub fn new(drain_timeout: Duration, reconnect_spread: u64) -> Self {
+        Shutdown {
+            draining: AtomicBool::new(false),
+            notice: watch::channel(false).0,
+            drain_timeout,
+            reconnect_spread,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let secs = |name: &str, default: u64| std::env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        Shutdown::new(
+            Duration::from_secs(secs("SHUTDOWN_DRAIN_SECS", DEFAULT_DRAIN_SECS)),
+            secs("SHUTDOWN_RECONNECT_SPREAD_SECS", DEFAULT_RECONNECT_SPREAD_SECS),
+        )
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn begin(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+        self.notice.send_replace(true);
+    }
+
+    /// Refuses new sockets once draining, asking clients to retry elsewhere.
+    pub fn admit(&self) -> Result<(), AppError> {
+        if self.is_draining() {
+            return Err(AppError::ServiceUnavailable { retry_after: self.reconnect_spread.max(1) });
+        }
+        Ok(())
+    }
+
+    /// What a socket waits on to learn the instance is shutting down.
+    pub fn notice(&self, socket_id: &str) -> Notice {
+        Notice { draining: self.notice.subscribe(), reconnect_after: self.reconnect_after(socket_id) }
+    }
+
+    /// Seconds the socket's client should wait before reconnecting, from 1
+    /// up to `reconnect_spread`, always the same for the same socket.
+    pub fn reconnect_after(&self, socket_id: &str) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        socket_id.hash(&mut hasher);
+        1 + hasher.finish() % self.reconnect_spread.max(1)
+    }
+}
+
+pub struct Notice {
+    draining: watch::Receiver<bool>,
+    pub reconnect_after: u64,
+}
+
+impl Notice {
+    /// Resolves once shutdown begins. Never resolves if it never does.
+    pub async fn draining(&mut self) {
+        if self.draining.wait_for(|draining| *draining).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+pub async fn signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            warn!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// Waits for a shutdown signal, then closes this instance's sockets, giving
+/// them up to the drain timeout. The server stops accepting connections once
+/// this returns.
+pub async fn drain(state: AppState) {
+    signal().await;
+    info!("Shutting down: refusing new sockets and asking clients to reconnect");
+    state.shutdown.begin();
+    let deadline = tokio::time::Instant::now() + state.shutdown.drain_timeout;
+    loop {
+        let open: usize = state.connections.read().await.socket_counts().iter().map(|(_, sockets)| sockets).sum();
+        if open == 0 {
+            info!("All sockets closed");
+            break;
+        }
+        if tokio::time::Instant::now() >= deadline {
+            warn!("{} sockets still open after {:?}; closing them", open, state.shutdown.drain_timeout);
+            break;
+        }
+        tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+    }
+}
+
+/// Writes what's still queued and leaves Redis, after the server stopped.
+pub async fn finish(state: &AppState) {
+    let pending = state.db.flush_queued(FLUSH_TIMEOUT).await;
+    if pending > 0 {
+        warn!("Exiting with {} messages still queued for MongoDB", pending);
+    }
+    crate::instances::deregister(state).await;
+    state.pubsub.stop();
+    info!("Shutdown complete");
+}
@@ -0,0 +1,205 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use axum::extract::ws::{close_code, CloseFrame};
+use axum::http::HeaderMap;
+use tracing::{error, info, warn};
+
+use crate::AppState;
+
+const DEFAULT_TARPIT_AFTER: u32 = 5;
+const DEFAULT_DISCONNECT_AFTER: u32 = 20;
+const DEFAULT_BLOCK_SECONDS: u64 = 600;
+const BASE_DELAY: Duration = Duration::from_millis(250);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+
+fn block_key(ip: &IpAddr) -> String {
+    format!("ip_block:{}", ip)
+}
+
+/// Something a socket did that a well-behaved client doesn't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SocketViolation {
+    // Text or binary frame that isn't a protocol message
+    MalformedFrame,
+    // Frame over the frame limits; the socket is closed on the first one
+    OversizedFrame,
+    // Join with a token that didn't verify, a ticket for another room or
+    // someone else's username
+    AuthFailure,
+    // Frame the socket's protocol state doesn't allow
+    ProtocolError,
+}
+
+impl SocketViolation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SocketViolation::MalformedFrame => "malformed_frame",
+            SocketViolation::OversizedFrame => "oversized_frame",
+            SocketViolation::AuthFailure => "auth_failure",
+            SocketViolation::ProtocolError => "protocol_error",
+        }
+    }
+
+    // Failed auth is the likelier sign of an attack than a confused client
+    fn weight(self) -> u32 {
+        match self {
+            SocketViolation::AuthFailure => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// How sockets are punished for violations. Past `tarpit_after` points each
+/// violation delays the socket's next frames, doubling from 250ms up to 5s;
+/// at `disconnect_after` the socket is closed and its IP refused new sockets
+/// for `block_for`. `client_ip_header`, when set, names the header the edge
+/// proxy puts the client's IP in; otherwise the peer address is used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TarpitConfig {
+    pub tarpit_after: u32,
+    pub disconnect_after: u32,
+    pub block_for: Duration,
+    pub client_ip_header: Option<String>,
+}
+
+impl Default for TarpitConfig {
+    fn default() -> Self {
+        TarpitConfig {
+            tarpit_after: DEFAULT_TARPIT_AFTER,
+            disconnect_after: DEFAULT_DISCONNECT_AFTER,
+            block_for: Duration::from_secs(DEFAULT_BLOCK_SECONDS),
+            client_ip_header: None,
+        }
+    }
+}
+
+impl TarpitConfig {
+    pub fn from_env() -> Self {
+        let number = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(default)
+        };
+        let tarpit_after = number("SOCKET_TARPIT_AFTER", DEFAULT_TARPIT_AFTER as u64) as u32;
+        TarpitConfig {
+            tarpit_after,
+            disconnect_after: (number("SOCKET_DISCONNECT_AFTER", DEFAULT_DISCONNECT_AFTER as u64) as u32).max(tarpit_after),
+            block_for: Duration::from_secs(number("SOCKET_IP_BLOCK_SECS", DEFAULT_BLOCK_SECONDS)),
+            client_ip_header: std::env::var("CLIENT_IP_HEADER").ok().filter(|header| !header.is_empty()),
+        }
+    }
+
+    /// The client's IP, from the configured header or the peer address.
+    pub fn client_ip(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> Option<IpAddr> {
+        match &self.client_ip_header {
+            // In X-Forwarded-For style lists only the last entry, appended by
+            // the proxy, can be trusted; the client writes the rest
+            Some(header) => headers.get_all(header).iter().next_back()?.to_str().ok()?.rsplit(',').next()?.trim().parse().ok(),
+            None => peer.map(|peer| peer.ip()),
+        }
+    }
+}
+
+/// What a socket gets for its latest violation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Penalty {
+    None,
+    Delay(Duration),
+    Disconnect,
+}
+
+/// Violations on one socket.
+#[derive(Debug, Clone, Default)]
+pub struct SocketAbuse {
+    pub ip: Option<IpAddr>,
+    points: u32,
+}
+
+impl SocketAbuse {
+    pub fn new(ip: Option<IpAddr>) -> Self {
+        SocketAbuse { ip, points: 0 }
+    }
+
+    pub fn points(&self) -> u32 {
+        self.points
+    }
+
+    pub fn record(&mut self, violation: SocketViolation, config: &TarpitConfig) -> Penalty {
+        self.points = self.points.saturating_add(violation.weight());
+        if self.points >= config.disconnect_after {
+            return Penalty::Disconnect;
+        }
+        if self.points <= config.tarpit_after {
+            return Penalty::None;
+        }
+        let doublings = (self.points - config.tarpit_after - 1).min(16);
+        Penalty::Delay((BASE_DELAY * 2u32.pow(doublings)).min(MAX_DELAY))
+    }
+}
+
+/// Counts a violation against the socket and applies its penalty: sleeps
+/// through a tarpit delay, or blocks the IP and returns the close frame the
+/// socket is to be shut with.
+pub async fn penalize(state: &AppState, abuse: &mut SocketAbuse, violation: SocketViolation) -> Option<CloseFrame<'static>> {
+    crate::service_metrics::socket_violation(violation.as_str());
+    match abuse.record(violation, &state.tarpit) {
+        Penalty::None => None,
+        Penalty::Delay(delay) => {
+            warn!("Tarpitting socket from {:?} for {:?} after {:?}", abuse.ip, delay, violation);
+            tokio::time::sleep(delay).await;
+            None
+        }
+        Penalty::Disconnect => {
+            if let Some(ip) = abuse.ip {
+                block(state, &ip).await;
+            }
+            Some(CloseFrame { code: close_code::POLICY, reason: "too many protocol violations".into() })
+        }
+    }
+}
+
+async fn block(state: &AppState, ip: &IpAddr) {
+    let seconds = state.tarpit.block_for.as_secs();
+    if seconds == 0 {
+        return;
+    }
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::cmd("SET").arg(block_key(ip)).arg(1).arg("EX").arg(seconds).query_async(&mut conn).await;
+    match result {
+        Ok(()) => {
+            info!("Blocked {} from opening sockets for {}s", ip, seconds);
+            crate::service_metrics::ip_blocked();
+        }
+        Err(e) => error!("Failed to block {}: {}", ip, e),
+    }
+}
+
+/// Seconds until the IP may open sockets again, while it is blocked. Fails
+/// open while Redis is unavailable.
+pub async fn blocked_for(pool: &deadpool_redis::Pool, ip: &IpAddr) -> Option<u64> {
+    let mut conn = pool.get().await.ok()?;
+    let ttl: redis::RedisResult<i64> = redis::cmd("TTL").arg(block_key(ip)).query_async(&mut conn).await;
+    match ttl {
+        Ok(ttl) if ttl > 0 => Some(ttl as u64),
+        Ok(_) => None,
+        Err(e) => {
+            error!("Failed to check whether {} is blocked: {}", ip, e);
+            None
+        }
+    }
+}
+
+/// Refuses the upgrade of a socket from a blocked IP.
+pub async fn admit(state: &AppState, ip: Option<IpAddr>) -> Result<(), crate::AppError> {
+    let Some(ip) = ip else {
+        return Ok(());
+    };
+    match blocked_for(&state.redis_pool, &ip).await {
+        Some(retry_after) => Err(crate::AppError::TooManyRequests { retry_after }),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::streams::{StreamInfoGroupsReply, StreamReadReply};
+use redis::AsyncCommands;
+use tracing::{error, info, warn};
+
+use crate::pubsub::PubSubMultiplexer;
+
+/// The one stream every instance broadcasts through. Entries carry the
+/// pub/sub channel name and the payload that would have been published.
+pub const BROADCAST_STREAM: &str = "broadcast";
+// Entries kept for instances catching up after a disconnect
+const STREAM_MAX_LEN: usize = 100_000;
+const READ_COUNT: usize = 100;
+const READ_BLOCK_MS: usize = 5_000;
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+// Consumer groups of instances that stop refreshing their liveness key are
+// destroyed, so the stream doesn't keep pending lists for dead instances
+const GROUP_TTL_SECONDS: u64 = 60;
+const GROUP_REFRESH: Duration = Duration::from_secs(20);
+
+/// How room broadcasts travel between instances.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BroadcastBackend {
+    // Redis pub/sub: fastest, but a subscriber that drops misses whatever
+    // was published until it reconnects
+    #[default]
+    PubSub,
+    // A Redis stream read through a consumer group per instance, so a
+    // reconnecting reader picks up where it left off
+    Streams,
+}
+
+impl BroadcastBackend {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pubsub" => Some(BroadcastBackend::PubSub),
+            "streams" => Some(BroadcastBackend::Streams),
+            _ => None,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        std::env::var("BROADCAST_BACKEND")
+            .ok()
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or_default()
+    }
+
+    /// Sends a broadcast payload to every instance listening on `channel`.
+    pub async fn publish<C: redis::aio::ConnectionLike + Send>(self, conn: &mut C, channel: &str, payload: &str) -> redis::RedisResult<()> {
+        match self {
+            BroadcastBackend::PubSub => conn.publish(channel, payload).await,
+            BroadcastBackend::Streams => {
+                redis::cmd("XADD")
+                    .arg(BROADCAST_STREAM)
+                    .arg("MAXLEN")
+                    .arg("~")
+                    .arg(STREAM_MAX_LEN)
+                    .arg("*")
+                    .arg("channel")
+                    .arg(channel)
+                    .arg("payload")
+                    .arg(payload)
+                    .query_async(conn)
+                    .await
+            }
+        }
+    }
+}
+
+pub fn group_name(instance_id: &str) -> String {
+    format!("instance:{}", instance_id)
+}
+
+fn group_liveness_key(group: &str) -> String {
+    format!("broadcast_group:{}", group)
+}
+
+/// Reads the broadcast stream for this instance and hands entries to the
+/// pub/sub multiplexer, so sockets receive them exactly like pub/sub
+/// messages. Entries are acknowledged once dispatched; after a dropped
+/// connection the reader first re-reads entries it never acknowledged.
+pub struct StreamReader {
+    group: String,
+    connected: AtomicBool,
+    read: AtomicU64,
+    recovered: AtomicU64,
+    reconnects: AtomicU64,
+}
+
+impl StreamReader {
+    pub fn new(instance_id: &str) -> Self {
+        StreamReader {
+            group: group_name(instance_id),
+            connected: AtomicBool::new(false),
+            read: AtomicU64::new(0),
+            recovered: AtomicU64::new(0),
+            reconnects: AtomicU64::new(0),
+        }
+    }
+
+    pub fn spawn(self: Arc<Self>, redis: Arc<redis::Client>, pubsub: Arc<PubSubMultiplexer>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                if let Err(e) = self.run(&redis, &pubsub).await {
+                    error!("Broadcast stream reader failed: {}", e);
+                }
+                self.connected.store(false, Ordering::Relaxed);
+                self.reconnects.fetch_add(1, Ordering::Relaxed);
+                tokio::time::sleep(RECONNECT_DELAY).await;
+            }
+        })
+    }
+
+    async fn run(&self, redis: &redis::Client, pubsub: &PubSubMultiplexer) -> redis::RedisResult<()> {
+        let mut conn = redis.get_async_connection().await?;
+        // Starting at the stream's end on first creation; an existing group
+        // keeps its position across reconnects
+        let created: redis::RedisResult<()> = conn.xgroup_create_mkstream(BROADCAST_STREAM, &self.group, "$").await;
+        if let Err(e) = created {
+            if e.code() != Some("BUSYGROUP") {
+                return Err(e);
+            }
+        }
+        self.connected.store(true, Ordering::Relaxed);
+        info!("Reading broadcast stream as consumer group {}", self.group);
+
+        // "0" re-reads entries delivered before a disconnect but never
+        // acknowledged; ">" reads new entries
+        let mut start = "0";
+        let mut refreshed_at: Option<tokio::time::Instant> = None;
+        loop {
+            if refreshed_at.is_none_or(|at| at.elapsed() >= GROUP_REFRESH) {
+                let _: () = conn.set_ex(group_liveness_key(&self.group), 1, GROUP_TTL_SECONDS).await?;
+                refreshed_at = Some(tokio::time::Instant::now());
+            }
+
+            let reply: Option<StreamReadReply> = redis::cmd("XREADGROUP")
+                .arg("GROUP")
+                .arg(&self.group)
+                .arg(&self.group)
+                .arg("COUNT")
+                .arg(READ_COUNT)
+                .arg("BLOCK")
+                .arg(READ_BLOCK_MS)
+                .arg("STREAMS")
+                .arg(BROADCAST_STREAM)
+                .arg(start)
+                .query_async(&mut conn)
+                .await?;
+            let entries: Vec<_> = reply.into_iter().flat_map(|reply| reply.keys).flat_map(|key| key.ids).collect();
+            if entries.is_empty() {
+                start = ">";
+                continue;
+            }
+
+            let mut ids = Vec::with_capacity(entries.len());
+            for entry in &entries {
+                if let (Some(channel), Some(payload)) = (entry.get::<String>("channel"), entry.get::<String>("payload")) {
+                    pubsub.dispatch(&channel, payload.into());
+                }
+                ids.push(entry.id.as_str());
+            }
+            let _: () = conn.xack(BROADCAST_STREAM, &self.group, &ids).await?;
+            if start == "0" {
+                self.recovered.fetch_add(entries.len() as u64, Ordering::Relaxed);
+                warn!("Recovered {} broadcast entries missed while disconnected", entries.len());
+            } else {
+                self.read.fetch_add(entries.len() as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Destroys the consumer groups of instances that are gone.
+    pub async fn remove_dead_groups(&self, redis: &redis::Client) -> redis::RedisResult<usize> {
+        let mut conn = redis.get_async_connection().await?;
+        let groups: StreamInfoGroupsReply = conn.xinfo_groups(BROADCAST_STREAM).await?;
+        let mut removed = 0;
+        for group in groups.groups {
+            if group.name == self.group || !group.name.starts_with("instance:") {
+                continue;
+            }
+            let alive: bool = conn.exists(group_liveness_key(&group.name)).await?;
+            if !alive {
+                let _: () = conn.xgroup_destroy(BROADCAST_STREAM, &group.name).await?;
+                info!("Removed broadcast consumer group {} of a stopped instance", group.name);
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    pub fn spawn_cleanup(self: Arc<Self>, redis: Arc<redis::Client>) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(Duration::from_secs(GROUP_TTL_SECONDS));
+            loop {
+                tick.tick().await;
+                if let Err(e) = self.remove_dead_groups(&redis).await {
+                    warn!("Failed to clean up broadcast consumer groups: {}", e);
+                }
+            }
+        })
+    }
+
+    /// Prometheus text, served with the fan-out metrics.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, u64); 4] = [
+            ("chat_streams_connected", "gauge", "1 while the broadcast stream reader is connected", self.connected.load(Ordering::Relaxed) as u64),
+            ("chat_streams_read_total", "counter", "Broadcast stream entries read", self.read.load(Ordering::Relaxed)),
+            ("chat_streams_recovered_total", "counter", "Unacknowledged entries re-read after a reconnect", self.recovered.load(Ordering::Relaxed)),
+            ("chat_streams_reconnects_total", "counter", "Times the broadcast stream reader reconnected", self.reconnects.load(Ordering::Relaxed)),
+        ];
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
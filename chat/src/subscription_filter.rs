@@ -0,0 +1,86 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{models::WsMessage, notifications::NotificationPriority};
+
+/// What a socket wants from its room subscription. Mobile clients switch a
+/// room they have in the background to `messages_only` or `mentions_only`
+/// so the server stops sending traffic they would discard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FanoutFilter {
+    #[default]
+    All,
+    // No typing, activity or join/leave notices
+    MessagesOnly,
+    // Only new messages that @mention the user or the whole room
+    MentionsOnly,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SubscriptionFilter {
+    pub mode: FanoutFilter,
+    // Known once the socket has joined; nothing matches a mention before that
+    pub username: Option<String>,
+    // The user muted the room, read from their preferences on join
+    pub muted: bool,
+}
+
+impl SubscriptionFilter {
+    pub fn new(mode: FanoutFilter) -> Self {
+        SubscriptionFilter { mode, username: None, muted: false }
+    }
+
+    /// Flags new messages in a muted room so the client can de-emphasize
+    /// them, and quiets their notification hint.
+    pub fn mark(&self, message: WsMessage) -> WsMessage {
+        match message {
+            WsMessage::NewMessage(mut message) if self.muted => {
+                message.muted = true;
+                if let Some(hint) = message.notification.as_mut() {
+                    hint.sound = None;
+                    hint.priority = NotificationPriority::Low;
+                }
+                WsMessage::NewMessage(message)
+            }
+            message => message,
+        }
+    }
+
+    pub fn allows(&self, message: &WsMessage) -> bool {
+        if self.mode == FanoutFilter::All {
+            return true;
+        }
+        match message {
+            WsMessage::Typing { .. }
+            | WsMessage::UserActivity { .. }
+            | WsMessage::UserJoined { .. }
+            | WsMessage::UserLeft { .. } => false,
+            WsMessage::NewMessage(message) if self.mode == FanoutFilter::MentionsOnly => {
+                message.mentions_everyone
+                    || self.username.as_deref().is_some_and(|username| mentions(&message.content, username))
+            }
+            WsMessage::RsvpUpdated { .. } | WsMessage::ReactionsUpdated { .. } | WsMessage::ThreadUpdated { .. } => {
+                self.mode != FanoutFilter::MentionsOnly
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Whether `content` contains `@username` as a whole word, ignoring case.
+pub fn mentions(content: &str, username: &str) -> bool {
+    if username.is_empty() {
+        return false;
+    }
+    let content = content.to_lowercase();
+    let mention = format!("@{}", username.to_lowercase());
+    content.match_indices(&mention).any(|(start, _)| {
+        let preceded_ok = content[..start].chars().next_back().is_none_or(|c| !is_name_char(c));
+        let followed_ok = content[start + mention.len()..].chars().next().is_none_or(|c| !is_name_char(c));
+        preceded_ok && followed_ok
+    })
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use opentelemetry::{global, trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::TracerProvider, Resource};
+use serde::Deserialize;
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+const DEFAULT_SERVICE_NAME: &str = "chat-service";
+
+// Set once spans are exported, so fan-out only looks for trace context then
+static EXPORTING: AtomicBool = AtomicBool::new(false);
+
+/// Trace context carried with a broadcast, as W3C `traceparent` and
+/// `tracestate` entries.
+pub type TraceContext = HashMap<String, String>;
+
+/// Installs the log output and, when `OTEL_EXPORTER_OTLP_ENDPOINT` is set,
+/// an OTLP exporter for spans. The returned provider flushes on `shutdown`.
+pub fn init() -> Option<TracerProvider> {
+    let provider = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+        .and_then(|endpoint| match tracer_provider(&endpoint) {
+            Ok(provider) => Some(provider),
+            Err(e) => {
+                eprintln!("Not exporting traces to {}: {}", endpoint, e);
+                None
+            }
+        });
+    let otel = provider
+        .as_ref()
+        .map(|provider| tracing_opentelemetry::layer().with_tracer(provider.tracer(DEFAULT_SERVICE_NAME)));
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(LevelFilter::INFO))
+        .with(otel.map(|layer| layer.with_filter(LevelFilter::INFO)))
+        .init();
+    if provider.is_some() {
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        EXPORTING.store(true, Ordering::Relaxed);
+    }
+    provider
+}
+
+fn tracer_provider(endpoint: &str) -> Result<TracerProvider, opentelemetry::trace::TraceError> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| DEFAULT_SERVICE_NAME.to_string());
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new("service.name", service_name)]))
+        .build())
+}
+
+/// Exports the spans still buffered.
+pub fn shutdown(provider: Option<TracerProvider>) {
+    if let Some(provider) = provider {
+        if let Err(e) = provider.shutdown() {
+            tracing::error!("Failed to flush traces: {}", e);
+        }
+    }
+}
+
+pub fn is_exporting() -> bool {
+    EXPORTING.load(Ordering::Relaxed)
+}
+
+/// The current span's context, to send along with a broadcast. Empty when
+/// spans aren't exported.
+pub fn current_context() -> TraceContext {
+    let mut carrier = TraceContext::new();
+    if is_exporting() {
+        let context = Span::current().context();
+        global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut carrier));
+    }
+    carrier
+}
+
+/// Continues the trace a broadcast was published in.
+pub fn set_parent(span: &Span, carrier: &TraceContext) {
+    if carrier.is_empty() {
+        return;
+    }
+    let context = global::get_text_map_propagator(|propagator| propagator.extract(carrier));
+    span.set_parent(context);
+}
+
+// Only the trace context of a broadcast payload
+#[derive(Deserialize)]
+struct TracedPayload {
+    #[serde(default)]
+    trace_context: TraceContext,
+}
+
+/// The span for handing a broadcast from Redis to this instance's sockets,
+/// in the trace of the message it carries. None unless spans are exported.
+pub fn fanout_span(channel: &str, payload: &str) -> Option<Span> {
+    if !is_exporting() {
+        return None;
+    }
+    let carrier = serde_json::from_str::<TracedPayload>(payload).ok()?.trace_context;
+    if carrier.is_empty() {
+        return None;
+    }
+    let span = info_span!("fanout", channel = %channel);
+    set_parent(&span, &carrier);
+    Some(span)
+}
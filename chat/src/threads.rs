@@ -0,0 +1,59 @@
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use thiserror::Error;
+use tracing::error;
+
+use crate::{models::Message, AppState};
+
+/// Why a reply can't be posted under the message it names.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ThreadError {
+    #[error("The message you're replying to doesn't exist")]
+    ParentNotFound,
+    #[error("Replies must be posted in the same room as the message")]
+    WrongRoom,
+}
+
+/// The thread a reply to `parent` belongs to. Threads are one level deep,
+/// so replying to a reply continues its parent's thread.
+pub fn thread_root(parent: &Message) -> Option<String> {
+    match &parent.parent_id {
+        Some(root) => Some(root.clone()),
+        None => parent.id.map(|id| id.to_hex()),
+    }
+}
+
+/// The id of the thread a reply in `room_id` to `parent_id` goes into.
+pub async fn resolve_parent(state: &AppState, room_id: &str, parent_id: &str) -> Result<String, ThreadError> {
+    let parent_id = ObjectId::parse_str(parent_id).map_err(|_| ThreadError::ParentNotFound)?;
+    let parent = match state.db.get_message(&parent_id).await {
+        Ok(Some(parent)) if !parent.deleted => parent,
+        Ok(_) => return Err(ThreadError::ParentNotFound),
+        Err(e) => {
+            error!("Failed to load parent message {}: {}", parent_id, e);
+            return Err(ThreadError::ParentNotFound);
+        }
+    };
+    if parent.room_id != room_id {
+        return Err(ThreadError::WrongRoom);
+    }
+    thread_root(&parent).ok_or(ThreadError::ParentNotFound)
+}
+
+/// Counts a saved reply on its thread. Returns the thread's new reply
+/// count, for the `ThreadUpdated` broadcast.
+pub async fn record_reply(state: &AppState, reply: &Message) -> Option<(String, i32, DateTime<Utc>)> {
+    let parent_id = reply.parent_id.as_deref()?;
+    // Only the author sees a shadow-banned reply, so it doesn't count
+    if reply.shadow {
+        return None;
+    }
+    let parent = ObjectId::parse_str(parent_id).ok()?;
+    match state.db.increment_reply_count(&parent).await {
+        Ok(count) => count.map(|count| (parent_id.to_string(), count, reply.timestamp)),
+        Err(e) => {
+            error!("Failed to count reply to {}: {}", parent_id, e);
+            None
+        }
+    }
+}
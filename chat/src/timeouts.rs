@@ -0,0 +1,113 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{abuse::{record_signal, AbuseSignal}, auth::AuthUser, room_roles::require_moderator, AppError, AppState};
+
+pub const MIN_TIMEOUT_SECONDS: u64 = 10;
+pub const MAX_TIMEOUT_SECONDS: u64 = 7 * 24 * 3600;
+
+// Holds when the timeout ends, in Unix millis, and expires with it
+fn timeout_key(room_id: &str, user_id: &str) -> String {
+    format!("room_timeout:{}:{}", room_id, user_id)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeoutRequest {
+    pub user_id: String,
+    pub duration_seconds: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TimeoutResponse {
+    pub room_id: String,
+    pub user_id: String,
+    pub until: DateTime<Utc>,
+}
+
+/// When a timeout of `duration_seconds` starting at `now` ends.
+pub fn timeout_until(duration_seconds: u64, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    if !(MIN_TIMEOUT_SECONDS..=MAX_TIMEOUT_SECONDS).contains(&duration_seconds) {
+        return Err(format!(
+            "duration_seconds must be between {} and {}",
+            MIN_TIMEOUT_SECONDS, MAX_TIMEOUT_SECONDS
+        ));
+    }
+    Ok(now + chrono::Duration::seconds(duration_seconds as i64))
+}
+
+/// When the user's timeout in the room ends, if they have one. Fails open
+/// while Redis is unavailable.
+pub async fn muted_until(pool: &deadpool_redis::Pool, room_id: &str, user_id: &str) -> Option<DateTime<Utc>> {
+    let mut conn = pool.get().await.ok()?;
+    let until: redis::RedisResult<Option<i64>> = redis::cmd("GET").arg(timeout_key(room_id, user_id)).query_async(&mut conn).await;
+    match until {
+        Ok(until) => until.and_then(DateTime::from_timestamp_millis).filter(|until| *until > Utc::now()),
+        Err(e) => {
+            error!("Failed to check timeout of {} in room {}: {}", user_id, room_id, e);
+            None
+        }
+    }
+}
+
+// POST /api/rooms/:location_id/mute - keep a user from posting in the room
+// for a while and record it against their abuse score; moderators only
+pub async fn mute_user_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<TimeoutRequest>,
+) -> Result<Json<TimeoutResponse>, AppError> {
+    require_moderator(&state, &location_id, &user, Some(&req.user_id)).await?;
+    let until = timeout_until(req.duration_seconds, Utc::now()).map_err(AppError::BadRequest)?;
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        error!("Redis unavailable for timeouts: {}", e);
+        AppError::InternalServerError
+    })?;
+    // Redis lifts the timeout by expiring the key
+    let _: () = redis::cmd("SET")
+        .arg(timeout_key(&location_id, &req.user_id))
+        .arg(until.timestamp_millis())
+        .arg("EX")
+        .arg(req.duration_seconds)
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to mute {} in room {}: {}", req.user_id, location_id, e);
+            AppError::InternalServerError
+        })?;
+    info!("Moderator {} muted {} in room {} until {}", user.username, req.user_id, location_id, until);
+    // Timeouts count towards the user's abuse score in every room
+    record_signal(&state.redis_pool, &req.user_id, AbuseSignal::Muted).await;
+    Ok(Json(TimeoutResponse { room_id: location_id, user_id: req.user_id, until }))
+}
+
+// DELETE /api/rooms/:location_id/mute/:user_id - lift a timeout early
+pub async fn unmute_user_handler(
+    Path((location_id, user_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    require_moderator(&state, &location_id, &user, Some(&user_id)).await?;
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        error!("Redis unavailable for timeouts: {}", e);
+        AppError::InternalServerError
+    })?;
+    let removed: i64 = redis::cmd("DEL")
+        .arg(timeout_key(&location_id, &user_id))
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to lift the timeout of {} in room {}: {}", user_id, location_id, e);
+            AppError::InternalServerError
+        })?;
+    if removed == 0 {
+        return Err(AppError::NotFound);
+    }
+    info!("Moderator {} lifted the timeout of {} in room {}", user.username, user_id, location_id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::FindOptions,
+    Collection,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{models::Message, AppState};
+
+const DEFAULT_INTERVAL_SECS: u64 = 5 * 60;
+const SUMMARY_TICK: StdDuration = StdDuration::from_secs(60);
+// Empty rooms idle for longer keep their last topic until they pick up again
+const ACTIVE_WINDOW_MINUTES: i64 = 30;
+const ROOMS_PER_TICK: i64 = 100;
+const RECENT_MESSAGES: i64 = 50;
+// Too little chat to say what it's about
+const MIN_MESSAGES: usize = 5;
+const MAX_KEYWORDS: usize = 3;
+
+/// What a room is currently talking about, shown in room discovery.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomTopic {
+    pub summary: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Produces a short "currently discussing" blurb from a room's recent
+/// messages, oldest first. An LLM-backed summarizer implements this too.
+#[async_trait::async_trait]
+pub trait Summarizer: Send + Sync {
+    async fn summarize(&self, messages: &[Message]) -> Option<String>;
+}
+
+/// The summarizer picked by `TOPIC_SUMMARIZER`: `keywords` (default) or
+/// `off`.
+pub fn summarizer_from_env() -> Option<Arc<dyn Summarizer>> {
+    match std::env::var("TOPIC_SUMMARIZER").as_deref() {
+        Ok("off") => None,
+        _ => Some(Arc::new(KeywordSummarizer)),
+    }
+}
+
+pub fn summary_interval() -> StdDuration {
+    let secs = std::env::var("TOPIC_SUMMARY_INTERVAL_SECS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_INTERVAL_SECS);
+    StdDuration::from_secs(secs.max(60))
+}
+
+const STOP_WORDS: &[&str] = &[
+    "about", "after", "again", "all", "also", "and", "any", "anyone", "are", "around", "back", "because", "been",
+    "before", "but", "can", "could", "did", "does", "doing", "don't", "dont", "for", "from", "get", "going", "gonna",
+    "got", "had", "has", "have", "hello", "her", "here", "hey", "him", "his", "how", "i'm", "into", "its", "it's",
+    "just", "know", "like", "lol", "look", "make", "more", "much", "near", "not", "now", "off", "okay", "one", "only",
+    "our", "out", "over", "really", "right", "said", "see", "she", "should", "some", "still", "than", "that", "that's",
+    "the", "their", "them", "then", "there", "they", "thing", "think", "this", "today", "too", "want", "was", "way",
+    "well", "were", "what", "when", "where", "which", "who", "why", "will", "with", "would", "yeah", "yes", "you",
+    "your",
+];
+
+/// Picks the words that come up in the most messages. Words count once per
+/// message, so one chatty user can't set the topic on their own.
+pub struct KeywordSummarizer;
+
+impl KeywordSummarizer {
+    pub fn keywords(messages: &[Message]) -> Vec<String> {
+        // word -> (messages it appears in, first appearance)
+        let mut counts: HashMap<String, (usize, usize)> = HashMap::new();
+        let mut order = 0;
+        for message in messages.iter().filter(|message| !message.deleted) {
+            let mut seen = HashSet::new();
+            for word in message.content.split_whitespace() {
+                if word.starts_with('@') || word.contains("://") {
+                    continue;
+                }
+                let word = word
+                    .trim_matches(|c: char| !c.is_alphanumeric() && c != '\'')
+                    .trim_end_matches("'s")
+                    .to_lowercase();
+                if word.chars().count() < 3 || word.chars().all(|c| c.is_numeric()) || STOP_WORDS.contains(&word.as_str()) {
+                    continue;
+                }
+                if seen.insert(word.clone()) {
+                    order += 1;
+                    counts.entry(word).or_insert((0, order)).0 += 1;
+                }
+            }
+        }
+
+        let mut ranked: Vec<(String, (usize, usize))> = counts.into_iter().filter(|(_, (count, _))| *count >= 2).collect();
+        ranked.sort_by(|(_, (a_count, a_first)), (_, (b_count, b_first))| b_count.cmp(a_count).then(a_first.cmp(b_first)));
+        ranked.into_iter().take(MAX_KEYWORDS).map(|(word, _)| word).collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl Summarizer for KeywordSummarizer {
+    async fn summarize(&self, messages: &[Message]) -> Option<String> {
+        match Self::keywords(messages).as_slice() {
+            [] => None,
+            [only] => Some(only.clone()),
+            [rest @ .., last] => Some(format!("{} and {}", rest.join(", "), last)),
+        }
+    }
+}
+
+fn rooms(state: &AppState) -> Collection<Document> {
+    state.database.collection("rooms")
+}
+
+fn bson_time(time: DateTime<Utc>) -> bson::DateTime {
+    bson::DateTime::from_millis(time.timestamp_millis())
+}
+
+/// Refreshes the topic of active rooms every `TOPIC_SUMMARY_INTERVAL_SECS`.
+/// Does nothing when summaries are off.
+pub fn spawn_topic_summaries(state: AppState) -> Option<tokio::task::JoinHandle<()>> {
+    let summarizer = state.topic_summarizer.clone()?;
+    let interval = summary_interval();
+    Some(tokio::spawn(async move {
+        let mut tick = tokio::time::interval(SUMMARY_TICK);
+        loop {
+            tick.tick().await;
+            if let Err(e) = summarize_active_rooms(&state, summarizer.as_ref(), interval).await {
+                error!("Failed to refresh room topics: {}", e);
+            }
+        }
+    }))
+}
+
+async fn summarize_active_rooms(
+    state: &AppState,
+    summarizer: &dyn Summarizer,
+    interval: StdDuration,
+) -> mongodb::error::Result<()> {
+    let now = Utc::now();
+    let due_before = bson_time(now - Duration::from_std(interval).unwrap_or(Duration::minutes(5)));
+    let due = doc! {
+        "$or": [
+            { "topic_checked_at": { "$exists": false } },
+            { "topic_checked_at": { "$lt": due_before } },
+        ],
+    };
+    // last_message_at moves with joins and leaves
+    let active = doc! {
+        "$or": [
+            { "active_users": { "$gt": 0 } },
+            { "last_message_at": { "$gte": bson_time(now - Duration::minutes(ACTIVE_WINDOW_MINUTES)) } },
+        ],
+    };
+    let filter = doc! { "$and": [due.clone(), active] };
+    let options = FindOptions::builder().projection(doc! { "_id": 1 }).limit(ROOMS_PER_TICK).build();
+    let active: Vec<Document> = rooms(state).find(filter, options).await?.try_collect().await?;
+
+    for room in active {
+        let Ok(room_id) = room.get_str("_id") else {
+            continue;
+        };
+        // Only the instance that moves the check time summarizes the room
+        let mut claim = due.clone();
+        claim.insert("_id", room_id);
+        let claimed = rooms(state)
+            .update_one(claim, doc! { "$set": { "topic_checked_at": bson_time(now) } }, None)
+            .await?;
+        if claimed.modified_count == 0 {
+            continue;
+        }
+
+        let mut messages = state.db.get_messages(room_id, RECENT_MESSAGES, None, None).await?;
+        messages.retain(|message| !message.deleted);
+        if messages.len() < MIN_MESSAGES {
+            continue;
+        }
+        messages.sort_by_key(|message| message.timestamp);
+        let Some(summary) = summarizer.summarize(&messages).await else {
+            continue;
+        };
+
+        let topic = RoomTopic { summary, updated_at: now };
+        rooms(state)
+            .update_one(
+                doc! { "_id": room_id },
+                doc! { "$set": { "topic": bson::to_bson(&topic).unwrap_or(bson::Bson::Null) } },
+                None,
+            )
+            .await?;
+        info!("Room {} is now talking about {}", room_id, topic.summary);
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Duration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::info;
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+pub const MAX_ATTACHMENTS: usize = 10;
+pub const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_UPLOAD_BYTES: u64 = 25 * 1024 * 1024;
+const MAX_DIMENSION: u32 = 20_000;
+// How long a client has to start its upload
+pub const UPLOAD_URL_TTL_SECONDS: i64 = 15 * 60;
+const DEFAULT_REGION: &str = "us-east-1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttachmentKind {
+    Image,
+    Video,
+    Audio,
+    File,
+}
+
+/// The attachment kind and file extension of an accepted content type.
+pub fn classify(content_type: &str) -> Option<(AttachmentKind, &'static str)> {
+    let accepted = match content_type.to_ascii_lowercase().as_str() {
+        "image/jpeg" => (AttachmentKind::Image, "jpg"),
+        "image/png" => (AttachmentKind::Image, "png"),
+        "image/gif" => (AttachmentKind::Image, "gif"),
+        "image/webp" => (AttachmentKind::Image, "webp"),
+        "video/mp4" => (AttachmentKind::Video, "mp4"),
+        "video/quicktime" => (AttachmentKind::Video, "mov"),
+        "audio/mpeg" => (AttachmentKind::Audio, "mp3"),
+        "audio/mp4" => (AttachmentKind::Audio, "m4a"),
+        "audio/ogg" => (AttachmentKind::Audio, "ogg"),
+        "application/pdf" => (AttachmentKind::File, "pdf"),
+        "text/plain" => (AttachmentKind::File, "txt"),
+        "application/zip" => (AttachmentKind::File, "zip"),
+        _ => return None,
+    };
+    Some(accepted)
+}
+
+/// A file sent with a room message or DM. The bytes live in object storage;
+/// messages only carry where they are and what they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub kind: AttachmentKind,
+    pub url: String,
+    pub content_type: String,
+    pub size: u64,
+    // Images and video only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+/// Object storage that issues presigned uploads: S3, or GCS through its
+/// S3-compatible API with HMAC keys.
+#[derive(Debug, Clone)]
+pub struct UploadConfig {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    // Where uploaded objects are read from, e.g. a CDN in front of the bucket
+    pub public_url: String,
+    pub max_bytes: u64,
+}
+
+impl UploadConfig {
+    /// Configured by `UPLOAD_BUCKET`, `UPLOAD_ACCESS_KEY_ID` and
+    /// `UPLOAD_SECRET_ACCESS_KEY`, with optional `UPLOAD_ENDPOINT`,
+    /// `UPLOAD_REGION`, `UPLOAD_PUBLIC_URL` and `UPLOAD_MAX_BYTES`. None
+    /// turns attachments off.
+    pub fn from_env() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.trim().is_empty());
+        let bucket = var("UPLOAD_BUCKET")?;
+        let region = var("UPLOAD_REGION").unwrap_or_else(|| DEFAULT_REGION.to_string());
+        let endpoint = var("UPLOAD_ENDPOINT")
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region))
+            .trim_end_matches('/')
+            .to_string();
+        let public_url = var("UPLOAD_PUBLIC_URL")
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| format!("{}/{}", endpoint, bucket));
+        Some(UploadConfig {
+            access_key_id: var("UPLOAD_ACCESS_KEY_ID")?,
+            secret_access_key: var("UPLOAD_SECRET_ACCESS_KEY")?,
+            max_bytes: var("UPLOAD_MAX_BYTES")
+                .and_then(|value| value.parse().ok())
+                .unwrap_or(DEFAULT_MAX_UPLOAD_BYTES),
+            endpoint,
+            bucket,
+            region,
+            public_url,
+        })
+    }
+
+    pub fn max_size(&self, kind: AttachmentKind) -> u64 {
+        match kind {
+            AttachmentKind::Image => MAX_IMAGE_BYTES.min(self.max_bytes),
+            _ => self.max_bytes,
+        }
+    }
+
+    /// Checks an upload's declared type, size and dimensions.
+    pub fn validate(&self, content_type: &str, size: u64, width: Option<u32>, height: Option<u32>) -> Result<AttachmentKind, String> {
+        let (kind, _) = classify(content_type).ok_or_else(|| format!("Files of type {} can't be attached", content_type))?;
+        if size == 0 || size > self.max_size(kind) {
+            return Err(format!("Attachments of this type must be 1 to {} bytes", self.max_size(kind)));
+        }
+        let has_dimensions = width.is_some() || height.is_some();
+        if has_dimensions && !matches!(kind, AttachmentKind::Image | AttachmentKind::Video) {
+            return Err("Only images and video have dimensions".to_string());
+        }
+        if [width, height].into_iter().flatten().any(|pixels| pixels == 0 || pixels > MAX_DIMENSION) {
+            return Err(format!("Dimensions must be 1 to {} pixels", MAX_DIMENSION));
+        }
+        Ok(kind)
+    }
+
+    /// Checks attachments sent with a message: they must have been uploaded
+    /// by the sender through this service and still describe a valid file.
+    pub fn check_attachments(&self, attachments: &[Attachment], user_id: &str) -> Result<(), String> {
+        if attachments.len() > MAX_ATTACHMENTS {
+            return Err(format!("A message can have at most {} attachments", MAX_ATTACHMENTS));
+        }
+        let own_uploads = format!("{}/{}", self.public_url, upload_prefix(user_id));
+        for attachment in attachments {
+            let own = attachment.url.strip_prefix(&own_uploads).is_some_and(is_upload_name);
+            if !own {
+                return Err("Attachments must be uploaded with POST /api/uploads first".to_string());
+            }
+            let kind = self.validate(&attachment.content_type, attachment.size, attachment.width, attachment.height)?;
+            if kind != attachment.kind {
+                return Err(format!("{} is not a {:?} attachment", attachment.content_type, attachment.kind));
+            }
+        }
+        Ok(())
+    }
+
+    /// A SigV4 presigned PUT for `key`, binding its content type and length.
+    pub fn presign_put(&self, key: &str, content_type: &str, size: u64, now: DateTime<Utc>) -> Result<String, String> {
+        let endpoint = reqwest::Url::parse(&self.endpoint).map_err(|e| format!("Invalid upload endpoint: {}", e))?;
+        let host = match (endpoint.host_str(), endpoint.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => return Err("Upload endpoint has no host".to_string()),
+        };
+        let path = format!("{}/{}/{}", endpoint.path().trim_end_matches('/'), self.bucket, key);
+        let canonical_path = uri_encode(&path, true);
+
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let signed_headers = "content-length;content-type;host";
+        let query: BTreeMap<&str, String> = BTreeMap::from([
+            ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+            ("X-Amz-Credential", format!("{}/{}", self.access_key_id, scope)),
+            ("X-Amz-Date", timestamp.clone()),
+            ("X-Amz-Expires", UPLOAD_URL_TTL_SECONDS.to_string()),
+            ("X-Amz-SignedHeaders", signed_headers.to_string()),
+        ]);
+        let canonical_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name, false), uri_encode(value, false)))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let canonical_request = format!(
+            "PUT\n{}\n{}\ncontent-length:{}\ncontent-type:{}\nhost:{}\n\n{}\nUNSIGNED-PAYLOAD",
+            canonical_path, canonical_query, size, content_type, host, signed_headers
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let mut key_material = format!("AWS4{}", self.secret_access_key).into_bytes();
+        for part in [date.as_str(), self.region.as_str(), "s3", "aws4_request"] {
+            key_material = hmac_sha256(&key_material, part.as_bytes());
+        }
+        let signature = hex::encode(hmac_sha256(&key_material, string_to_sign.as_bytes()));
+
+        Ok(format!(
+            "{}://{}{}?{}&X-Amz-Signature={}",
+            endpoint.scheme(),
+            host,
+            canonical_path,
+            canonical_query,
+            signature
+        ))
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but unreserved characters, as SigV4 expects.
+pub fn uri_encode(value: &str, keep_slashes: bool) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => encoded.push(byte as char),
+            b'/' if keep_slashes => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Object key prefix for a user's uploads. Ids are hex encoded, so every
+/// user gets their own prefix and keys and URLs need no escaping.
+pub fn upload_prefix(user_id: &str) -> String {
+    format!("uploads/{}/", hex::encode(user_id))
+}
+
+// Names given to uploads are `<uuid>.<extension>`; anything else after the
+// prefix, such as `..` or an escaped segment, could point elsewhere
+fn is_upload_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.contains("..")
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+}
+
+/// Refuses attachments on messages when uploads aren't configured.
+pub fn check_attachments(config: Option<&UploadConfig>, attachments: &[Attachment], user_id: &str) -> Result<(), String> {
+    match config {
+        _ if attachments.is_empty() => Ok(()),
+        Some(config) => config.check_attachments(attachments, user_id),
+        None => Err("Attachments are not enabled".to_string()),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UploadRequest {
+    pub content_type: String,
+    pub size: u64,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadResponse {
+    pub upload_url: String,
+    pub method: &'static str,
+    // Must be sent exactly, they are part of the signature
+    pub headers: BTreeMap<&'static str, String>,
+    pub expires_at: DateTime<Utc>,
+    // Send this with the message once the upload has finished
+    pub attachment: Attachment,
+}
+
+// POST /api/uploads - presigned URL for uploading one attachment
+pub async fn create_upload_handler(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<UploadRequest>,
+) -> Result<Json<UploadResponse>, AppError> {
+    let config: &Arc<UploadConfig> = state
+        .uploads
+        .as_ref()
+        .ok_or_else(|| AppError::BadRequest("Attachments are not enabled".to_string()))?;
+    let kind = config
+        .validate(&req.content_type, req.size, req.width, req.height)
+        .map_err(AppError::BadRequest)?;
+    let content_type = req.content_type.to_ascii_lowercase();
+    let (_, extension) = classify(&content_type).ok_or(AppError::InternalServerError)?;
+
+    let key = format!("{}{}.{}", upload_prefix(&user.user_id), uuid::Uuid::new_v4(), extension);
+    let now = Utc::now();
+    let upload_url = config.presign_put(&key, &content_type, req.size, now).map_err(|e| {
+        tracing::error!("Failed to presign upload: {}", e);
+        AppError::InternalServerError
+    })?;
+    info!("Issued upload {} ({} bytes) to {}", key, req.size, user.user_id);
+
+    Ok(Json(UploadResponse {
+        upload_url,
+        method: "PUT",
+        headers: BTreeMap::from([("Content-Type", content_type.clone()), ("Content-Length", req.size.to_string())]),
+        expires_at: now + Duration::seconds(UPLOAD_URL_TTL_SECONDS),
+        attachment: Attachment {
+            kind,
+            url: format!("{}/{}", config.public_url, key),
+            content_type,
+            size: req.size,
+            width: req.width,
+            height: req.height,
+        },
+    }))
+}
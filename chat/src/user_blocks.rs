@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{bson::doc, options::FindOptions, Collection};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{auth::AuthUser, models::WsMessage, AppError, AppState};
+
+/// How often open DM sockets reload their user's blocks.
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// One user blocking another. Blocks are one-way: the blocked user can't
+/// open sockets to conversations with the blocker, and the blocker stops
+/// receiving their messages.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserBlock {
+    // "blocker_id:blocked_id"
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub blocker_id: String,
+    pub blocked_id: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn blocks(state: &AppState) -> Collection<UserBlock> {
+    state.database.collection("user_blocks")
+}
+
+fn block_id(blocker_id: &str, blocked_id: &str) -> String {
+    format!("{}:{}", blocker_id, blocked_id)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockRequest {
+    pub user_id: String,
+}
+
+impl BlockRequest {
+    /// The block of the requested user by `blocker_id`.
+    pub fn into_block(self, blocker_id: &str) -> Result<UserBlock, String> {
+        let blocked_id = self.user_id.trim().to_string();
+        if blocked_id.is_empty() {
+            return Err("user_id is required".to_string());
+        }
+        if blocked_id == blocker_id {
+            return Err("You can't block yourself".to_string());
+        }
+        Ok(UserBlock {
+            id: block_id(blocker_id, &blocked_id),
+            blocker_id: blocker_id.to_string(),
+            blocked_id,
+            created_at: Utc::now(),
+        })
+    }
+}
+
+/// The users `user_id` has blocked. Empty if MongoDB can't be read, so a
+/// hiccup doesn't cut a socket off.
+pub async fn blocked_by(state: &AppState, user_id: &str) -> HashSet<String> {
+    let result = async {
+        let cursor = blocks(state).find(doc! { "blocker_id": user_id }, None).await?;
+        cursor.map_ok(|block| block.blocked_id).try_collect::<HashSet<String>>().await
+    }
+    .await;
+    result.unwrap_or_else(|e| {
+        error!("Failed to load the users {} blocked: {}", user_id, e);
+        HashSet::new()
+    })
+}
+
+/// Whether any of `participants` has blocked `user_id`. Fails open, like
+/// other join checks.
+pub async fn is_blocked_by_any(state: &AppState, user_id: &str, participants: &[String]) -> bool {
+    let ids: Vec<String> = participants
+        .iter()
+        .filter(|participant| *participant != user_id)
+        .map(|participant| block_id(participant, user_id))
+        .collect();
+    if ids.is_empty() {
+        return false;
+    }
+    match blocks(state).count_documents(doc! { "_id": { "$in": ids } }, None).await {
+        Ok(count) => count > 0,
+        Err(e) => {
+            error!("Failed to check blocks of {}: {}", user_id, e);
+            false
+        }
+    }
+}
+
+/// Whether a published DM payload is a message from a user in `blocked`.
+pub fn is_from_blocked(payload: &str, blocked: &HashSet<String>) -> bool {
+    if blocked.is_empty() || !payload.starts_with(r#"{"type":"NewMessage""#) {
+        return false;
+    }
+    matches!(
+        serde_json::from_str::<WsMessage>(payload),
+        Ok(WsMessage::NewMessage(message)) if blocked.contains(&message.user_id)
+    )
+}
+
+// GET /api/users/me/blocks - the users the caller has blocked, newest first
+pub async fn list_blocks_handler(State(state): State<AppState>, user: AuthUser) -> Result<Json<Vec<UserBlock>>, AppError> {
+    let options = FindOptions::builder().sort(doc! { "created_at": -1 }).build();
+    Ok(Json(blocks(&state).find(doc! { "blocker_id": &user.user_id }, options).await?.try_collect().await?))
+}
+
+// POST /api/users/me/blocks - block a user; blocking them again is a no-op
+pub async fn block_user_handler(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<BlockRequest>,
+) -> Result<Json<UserBlock>, AppError> {
+    let block = req.into_block(&user.user_id).map_err(AppError::BadRequest)?;
+    match blocks(&state).insert_one(&block, None).await {
+        Ok(_) => info!("User {} blocked {}", block.blocker_id, block.blocked_id),
+        Err(e) if crate::db::is_duplicate_key(&e) => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(Json(block))
+}
+
+// DELETE /api/users/me/blocks/:user_id - unblock a user
+pub async fn unblock_user_handler(
+    Path(blocked_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let result = blocks(&state).delete_one(doc! { "_id": block_id(&user.user_id, &blocked_id) }, None).await?;
+    if result.deleted_count == 0 {
+        return Err(AppError::NotFound);
+    }
+    info!("User {} unblocked {}", user.user_id, blocked_id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -0,0 +1,187 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use mongodb::bson::{doc, Bson, Document};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    auth::AuthUser,
+    event_log::LogEvent,
+    legal_hold::{active_holds, exclusion_filter, HoldScope},
+    AppError, AppState,
+};
+
+/// Every instance listens here to drop what it has cached about erased users.
+pub const ERASED_CHANNEL: &str = "user:erased";
+// What erased DMs are left showing
+pub const ERASED_USERNAME: &str = "Deleted user";
+
+/// Records that are the user's alone and are deleted outright, as
+/// (collection, field holding the user id).
+pub const ERASED_RECORDS: [(&str, &str); 9] = [
+    ("room_read_cursors", "user_id"),
+    ("room_invitations", "user_id"),
+    ("user_blocks", "blocker_id"),
+    ("user_blocks", "blocked_id"),
+    ("user_privacy", "_id"),
+    ("user_preferences", "_id"),
+    ("usernames", "_id"),
+    ("event_rsvps", "user_id"),
+    ("dm_key_events", "sender_id"),
+];
+
+/// Published on `ERASED_CHANNEL` once a user's data is gone.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserErased {
+    pub user_id: String,
+    pub erased_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ErasureReport {
+    pub user_id: String,
+    pub room_messages_deleted: u64,
+    pub direct_messages_anonymized: u64,
+    pub reactions_removed: u64,
+    pub records_deleted: u64,
+}
+
+/// What the user's own DMs become: emptied and marked deleted, so the other
+/// participants' conversations keep their shape.
+pub fn dm_tombstone() -> Document {
+    doc! {
+        "content": "",
+        "sender_username": ERASED_USERNAME,
+        "deleted": true,
+        "attachments": [],
+    }
+}
+
+/// `dm_tombstone` for the conversation's `last_message`.
+pub fn last_message_tombstone() -> Document {
+    dm_tombstone().into_iter().map(|(field, value)| (format!("last_message.{}", field), value)).collect()
+}
+
+// Room messages under a legal hold are kept; the rest are deleted in
+// batches written to the event log. The rooms' cached history is dropped
+async fn delete_room_messages(state: &AppState, user_id: &str, exclusion: Document) -> mongodb::error::Result<u64> {
+    let messages = state.database.collection::<Document>("messages");
+    let rooms = messages.distinct("room_id", doc! { "user_id": user_id }, None).await?;
+    let deleted = crate::message_ttl::delete_matching(state, doc! { "$and": [exclusion, { "user_id": user_id }] }).await?;
+    for room_id in rooms.iter().filter_map(Bson::as_str) {
+        crate::room_cache::invalidate_history(state, room_id).await;
+    }
+    Ok(deleted)
+}
+
+async fn anonymize_direct_messages(state: &AppState, user_id: &str) -> mongodb::error::Result<u64> {
+    let direct_messages = state.database.collection::<Document>("direct_messages");
+    let anonymized = direct_messages
+        .update_many(doc! { "sender_id": user_id }, doc! { "$set": dm_tombstone() }, None)
+        .await?
+        .modified_count;
+    direct_messages
+        .update_many(
+            doc! { "$or": [{ "read_by": user_id }, { "delivered_to": user_id }] },
+            doc! { "$pull": { "read_by": user_id, "delivered_to": user_id } },
+            None,
+        )
+        .await?;
+    state
+        .database
+        .collection::<Document>("dm_conversations")
+        .update_many(doc! { "last_message.sender_id": user_id }, doc! { "$set": last_message_tombstone() }, None)
+        .await?;
+    Ok(anonymized)
+}
+
+async fn delete_records(state: &AppState, user_id: &str) -> mongodb::error::Result<u64> {
+    let mut deleted = 0;
+    for (collection, field) in ERASED_RECORDS {
+        deleted += state
+            .database
+            .collection::<Document>(collection)
+            .delete_many(doc! { field: user_id }, None)
+            .await?
+            .deleted_count;
+    }
+    state
+        .database
+        .collection::<Document>("rooms")
+        .update_many(doc! { "moderators": user_id }, doc! { "$pull": { "moderators": user_id } }, None)
+        .await?;
+    Ok(deleted)
+}
+
+async fn publish_erased(state: &AppState, user_id: &str) {
+    let event = UserErased { user_id: user_id.to_string(), erased_at: Utc::now() };
+    let Ok(payload) = serde_json::to_string(&event) else {
+        return;
+    };
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        error!("Redis unavailable; other instances weren't told {} was erased", user_id);
+        return;
+    };
+    let published: redis::RedisResult<i64> = redis::cmd("PUBLISH").arg(ERASED_CHANNEL).arg(payload).query_async(&mut conn).await;
+    if let Err(e) = published {
+        error!("Failed to publish the erasure of {}: {}", user_id, e);
+    }
+}
+
+// DELETE /api/users/:user_id/data - erase a user's messages, DMs, reactions
+// and presence; the user themselves or admins. Refused while the user is
+// under a legal hold
+pub async fn erase_user_data_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<ErasureReport>, AppError> {
+    if user_id != user.user_id && !user.is_admin() {
+        return Err(AppError::Forbidden);
+    }
+    let holds = active_holds(&state.database).await?;
+    if holds.iter().any(|hold| hold.scope == HoldScope::User && hold.target_id == user_id) {
+        return Err(AppError::Conflict("The user's data is under a legal hold".to_string()));
+    }
+
+    let exclusion = exclusion_filter(&holds);
+    let room_messages_deleted = delete_room_messages(&state, &user_id, exclusion.clone()).await?;
+    let reactions_removed = state
+        .database
+        .collection::<Document>("messages")
+        .update_many(
+            doc! { "$and": [exclusion, { "reactions.user_id": &user_id }] },
+            doc! { "$pull": { "reactions": { "user_id": &user_id } } },
+            None,
+        )
+        .await?
+        .modified_count;
+    let direct_messages_anonymized = anonymize_direct_messages(&state, &user_id).await?;
+    let records_deleted = delete_records(&state, &user_id).await?;
+    crate::user_presence::forget(&state, &user_id).await;
+
+    crate::event_log::append(&state, LogEvent::UserErased { user_id: user_id.clone() }).await;
+    publish_erased(&state, &user_id).await;
+    info!("{} erased the data of {}", user.username, user_id);
+    Ok(Json(ErasureReport { user_id, room_messages_deleted, direct_messages_anonymized, reactions_removed, records_deleted }))
+}
+
+/// Drops this instance's cached profile of users erased anywhere.
+pub fn spawn_listener(state: AppState) -> tokio::task::JoinHandle<()> {
+    let mut erasures = state.pubsub.subscribe(ERASED_CHANNEL);
+    tokio::spawn(async move {
+        loop {
+            match erasures.recv().await {
+                Ok(payload) => match serde_json::from_str::<UserErased>(&payload) {
+                    Ok(erased) => state.user_service.forget_profile(&erased.user_id),
+                    Err(e) => error!("Failed to parse user erasure {}: {}", payload, e),
+                },
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
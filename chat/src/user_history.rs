@@ -0,0 +1,131 @@
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{self, doc, Document},
+    options::FindOptions,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    auth::AuthUser,
+    models::{DirectMessage, Message},
+    AppError, AppState,
+};
+
+const DEFAULT_HISTORY_LIMIT: i64 = 50;
+const MAX_HISTORY_LIMIT: i64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HistorySource {
+    Room,
+    Dm,
+}
+
+/// One of the caller's own messages, from a room or a DM conversation.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HistoryItem {
+    pub source: HistorySource,
+    pub id: String,
+    // Room id, or conversation id for DMs
+    pub room_id: String,
+    pub content: String,
+    pub timestamp: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edited_at: Option<DateTime<Utc>>,
+}
+
+impl From<Message> for HistoryItem {
+    fn from(message: Message) -> Self {
+        HistoryItem {
+            source: HistorySource::Room,
+            id: message.id.map(|id| id.to_hex()).unwrap_or_default(),
+            room_id: message.room_id,
+            content: message.content,
+            timestamp: message.timestamp,
+            edited_at: message.edited_at,
+        }
+    }
+}
+
+impl From<DirectMessage> for HistoryItem {
+    fn from(message: DirectMessage) -> Self {
+        HistoryItem {
+            source: HistorySource::Dm,
+            id: message.id.map(|id| id.to_hex()).unwrap_or_default(),
+            room_id: message.conversation_id,
+            content: message.content,
+            timestamp: message.timestamp,
+            edited_at: message.edited_at,
+        }
+    }
+}
+
+/// Room and DM messages interleaved newest first, at most `limit` of them.
+pub fn merge_history(mut rooms: Vec<HistoryItem>, dms: Vec<HistoryItem>, limit: usize) -> Vec<HistoryItem> {
+    rooms.extend(dms);
+    rooms.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.id.cmp(&a.id)));
+    rooms.truncate(limit);
+    rooms
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserHistoryQuery {
+    pub since: Option<DateTime<Utc>>,
+    // A room or DM conversation id
+    pub room_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserHistoryResponse {
+    pub messages: Vec<HistoryItem>,
+}
+
+fn history_filter(author_field: &str, user_id: &str, room_field: &str, query: &UserHistoryQuery) -> Document {
+    let mut filter = doc! { author_field: user_id, "deleted": false };
+    if let Some(since) = query.since {
+        filter.insert("timestamp", doc! { "$gte": bson::DateTime::from_millis(since.timestamp_millis()) });
+    }
+    if let Some(room_id) = &query.room_id {
+        filter.insert(room_field, room_id);
+    }
+    filter
+}
+
+// GET /api/users/me/messages?since=&room_id=&limit= - the caller's own
+// messages across rooms and DMs, newest first
+pub async fn my_messages_handler(
+    Query(query): Query<UserHistoryQuery>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<UserHistoryResponse>, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT);
+    let newest = || FindOptions::builder().sort(doc! { "timestamp": -1 }).limit(limit).build();
+
+    let rooms: Vec<Message> = state
+        .database
+        .collection::<Message>("messages")
+        .find(history_filter("user_id", &user.user_id, "room_id", &query), newest())
+        .await?
+        .try_collect()
+        .await?;
+    let dms: Vec<DirectMessage> = state
+        .database
+        .collection::<DirectMessage>("direct_messages")
+        .find(history_filter("sender_id", &user.user_id, "conversation_id", &query), newest())
+        .await?
+        .try_collect()
+        .await?;
+
+    let messages = merge_history(
+        rooms.into_iter().map(HistoryItem::from).collect(),
+        dms.into_iter().map(HistoryItem::from).collect(),
+        limit as usize,
+    );
+    Ok(Json(UserHistoryResponse { messages }))
+}
@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+pub const MAX_BULK_USERS: usize = 500;
+// Online keys not refreshed within this long lapse, so a crashed
+// instance's users go offline on their own
+const ONLINE_TTL_MS: i64 = 90_000;
+const LAST_SEEN_TTL_SECONDS: u64 = 30 * 24 * 3600;
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+fn online_key(user_id: &str) -> String {
+    format!("user_online:{}", user_id)
+}
+
+fn last_seen_key(user_id: &str) -> String {
+    format!("user_seen:{}", user_id)
+}
+
+/// Users with a room, hex or DM socket open on this instance.
+#[derive(Debug, Default)]
+pub struct OnlineUsers {
+    // socket_id -> user_id
+    sockets: Mutex<HashMap<String, String>>,
+}
+
+impl OnlineUsers {
+    /// Records the socket; true when it is the user's first here.
+    pub fn connect(&self, socket_id: &str, user_id: &str) -> bool {
+        let mut sockets = self.sockets.lock().unwrap();
+        if sockets.contains_key(socket_id) {
+            return false;
+        }
+        let first = !sockets.values().any(|id| id == user_id);
+        sockets.insert(socket_id.to_string(), user_id.to_string());
+        first
+    }
+
+    /// Forgets the socket. Returns its user when that was their last socket
+    /// here.
+    pub fn disconnect(&self, socket_id: &str) -> Option<String> {
+        let mut sockets = self.sockets.lock().unwrap();
+        let user_id = sockets.remove(socket_id)?;
+        (!sockets.values().any(|id| *id == user_id)).then_some(user_id)
+    }
+
+    pub fn is_online(&self, user_id: &str) -> bool {
+        self.sockets.lock().unwrap().values().any(|id| id == user_id)
+    }
+
+    pub fn user_ids(&self) -> Vec<String> {
+        let sockets = self.sockets.lock().unwrap();
+        let users: HashSet<&String> = sockets.values().collect();
+        users.into_iter().cloned().collect()
+    }
+}
+
+async fn mark_online(state: &AppState, user_ids: &[String]) {
+    if user_ids.is_empty() {
+        return;
+    }
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let now = Utc::now().timestamp_millis();
+    let mut pipe = redis::pipe();
+    for user_id in user_ids {
+        pipe.cmd("SET").arg(online_key(user_id)).arg(now).arg("PX").arg(ONLINE_TTL_MS).ignore();
+        pipe.cmd("SET").arg(last_seen_key(user_id)).arg(now).arg("EX").arg(LAST_SEEN_TTL_SECONDS).ignore();
+    }
+    if let Err(e) = pipe.query_async::<_, ()>(&mut conn).await {
+        error!("Failed to refresh user presence: {}", e);
+    }
+}
+
+/// Counts a newly open socket towards its user being online.
+pub async fn connect(state: &AppState, socket_id: &str, user_id: &str) {
+    if state.online_users.connect(socket_id, user_id) {
+        mark_online(state, &[user_id.to_string()]).await;
+    }
+}
+
+/// Takes a closed socket off; the user goes offline with their last one.
+/// Sockets on other instances put them back online at their next heartbeat.
+pub async fn disconnect(state: &AppState, socket_id: &str) {
+    let Some(user_id) = state.online_users.disconnect(socket_id) else {
+        return;
+    };
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::pipe()
+        .del(online_key(&user_id))
+        .ignore()
+        .cmd("SET")
+        .arg(last_seen_key(&user_id))
+        .arg(Utc::now().timestamp_millis())
+        .arg("EX")
+        .arg(LAST_SEEN_TTL_SECONDS)
+        .ignore()
+        .query_async(&mut conn)
+        .await;
+    if let Err(e) = result {
+        error!("Failed to record {} going offline: {}", user_id, e);
+    }
+}
+
+/// Drops the user's online and last seen keys, as when their data is erased.
+pub async fn forget(state: &AppState, user_id: &str) {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return;
+    };
+    let result: redis::RedisResult<()> = redis::cmd("DEL").arg(online_key(user_id)).arg(last_seen_key(user_id)).query_async(&mut conn).await;
+    if let Err(e) = result {
+        error!("Failed to drop the presence of {}: {}", user_id, e);
+    }
+}
+
+/// Keeps this instance's online users marked online.
+pub fn spawn_heartbeat(state: AppState) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(HEARTBEAT_INTERVAL);
+        loop {
+            tick.tick().await;
+            mark_online(&state, &state.online_users.user_ids()).await;
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UserPresence {
+    pub user_id: String,
+    pub online: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_seen: Option<DateTime<Utc>>,
+}
+
+/// Each user's presence from their online and last seen values, as read
+/// from Redis in the same order.
+pub fn presence_of(user_ids: Vec<String>, online: Vec<Option<i64>>, last_seen: Vec<Option<i64>>) -> Vec<UserPresence> {
+    user_ids
+        .into_iter()
+        .zip(online.into_iter().zip(last_seen))
+        .map(|(user_id, (online, last_seen))| UserPresence {
+            user_id,
+            online: online.is_some(),
+            last_seen: last_seen.and_then(DateTime::from_timestamp_millis),
+        })
+        .collect()
+}
+
+/// Request ids with blanks and repeats dropped, first seen first.
+pub fn unique_ids(user_ids: Vec<String>) -> Vec<String> {
+    let mut seen = HashSet::new();
+    user_ids
+        .into_iter()
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty() && seen.insert(id.clone()))
+        .collect()
+}
+
+async fn load(state: &AppState, user_ids: &[String]) -> redis::RedisResult<(Vec<Option<i64>>, Vec<Option<i64>>)> {
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        redis::RedisError::from((redis::ErrorKind::IoError, "Redis pool unavailable", e.to_string()))
+    })?;
+    redis::pipe()
+        .cmd("MGET")
+        .arg(user_ids.iter().map(|id| online_key(id)).collect::<Vec<_>>())
+        .cmd("MGET")
+        .arg(user_ids.iter().map(|id| last_seen_key(id)).collect::<Vec<_>>())
+        .query_async(&mut conn)
+        .await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkPresenceRequest {
+    pub user_ids: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct BulkPresenceResponse {
+    users: Vec<UserPresence>,
+    // Redis was unavailable, so only users on this instance show as online
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    local_only: bool,
+}
+
+// POST /api/presence/bulk - online status and last seen time of up to
+// MAX_BULK_USERS users, in the order asked
+pub async fn bulk_presence_handler(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Json(req): Json<BulkPresenceRequest>,
+) -> Result<Json<BulkPresenceResponse>, AppError> {
+    let user_ids = unique_ids(req.user_ids);
+    if user_ids.len() > MAX_BULK_USERS {
+        return Err(AppError::BadRequest(format!("At most {} user_ids per request", MAX_BULK_USERS)));
+    }
+    if user_ids.is_empty() {
+        return Ok(Json(BulkPresenceResponse { users: Vec::new(), local_only: false }));
+    }
+    match load(&state, &user_ids).await {
+        Ok((online, last_seen)) => Ok(Json(BulkPresenceResponse { users: presence_of(user_ids, online, last_seen), local_only: false })),
+        Err(e) => {
+            error!("Failed to load presence of {} users: {}", user_ids.len(), e);
+            let users = user_ids
+                .into_iter()
+                .map(|user_id| UserPresence { online: state.online_users.is_online(&user_id), user_id, last_seen: None })
+                .collect();
+            Ok(Json(BulkPresenceResponse { users, local_only: true }))
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{auth::AuthUser, AppError, AppState};
+
+const DEFAULT_TIMEOUT_MS: u64 = 2000;
+// Failed calls in a row before the service is treated as down
+pub const FAILURE_THRESHOLD: u32 = 3;
+const PROBE_INTERVAL: Duration = Duration::from_secs(10);
+// Profiles newer than this are served without asking the user service
+pub const PROFILE_FRESH_FOR: Duration = Duration::from_secs(5 * 60);
+const MAX_CACHED_PROFILES: usize = 10_000;
+const MAX_QUEUED_UPDATES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UserProfile {
+    pub user_id: String,
+    pub username: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub badge: Option<crate::badges::Badge>,
+}
+
+/// A conversation's new last message, for the user service's conversation
+/// list.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationUpdate {
+    pub conversation_id: String,
+    pub message_id: String,
+    pub sender_id: String,
+    pub content: String,
+    pub sent_at: DateTime<Utc>,
+}
+
+/// Whether the user service is answering. It is degraded after
+/// `FAILURE_THRESHOLD` failures in a row and stays degraded until a call or
+/// health probe succeeds.
+#[derive(Debug, Default)]
+pub struct ServiceHealth {
+    consecutive_failures: AtomicU32,
+    degraded: AtomicBool,
+    failures: AtomicU64,
+}
+
+impl ServiceHealth {
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
+
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        if self.degraded.swap(false, Ordering::Relaxed) {
+            info!("User service is reachable again, leaving degraded mode");
+        }
+    }
+
+    pub fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures >= FAILURE_THRESHOLD && !self.degraded.swap(true, Ordering::Relaxed) {
+            warn!("User service failed {} times in a row, serving cached data", failures);
+        }
+    }
+}
+
+/// Profiles by user id, with when they were fetched. Stale entries are kept
+/// to be served while the user service is down.
+#[derive(Debug, Default)]
+pub struct ProfileCache {
+    entries: HashMap<String, (UserProfile, Instant)>,
+}
+
+impl ProfileCache {
+    pub fn fresh(&self, user_id: &str, now: Instant) -> Option<&UserProfile> {
+        self.entries
+            .get(user_id)
+            .filter(|(_, fetched_at)| now.duration_since(*fetched_at) < PROFILE_FRESH_FOR)
+            .map(|(profile, _)| profile)
+    }
+
+    pub fn any_age(&self, user_id: &str) -> Option<&UserProfile> {
+        self.entries.get(user_id).map(|(profile, _)| profile)
+    }
+
+    pub fn remove(&mut self, user_id: &str) {
+        self.entries.remove(user_id);
+    }
+
+    pub fn insert(&mut self, profile: UserProfile, now: Instant) {
+        if self.entries.len() >= MAX_CACHED_PROFILES && !self.entries.contains_key(&profile.user_id) {
+            // Dropping the oldest entry keeps the cache bounded
+            if let Some(oldest) = self.entries.iter().min_by_key(|(_, (_, at))| *at).map(|(id, _)| id.clone()) {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(profile.user_id.clone(), (profile, now));
+    }
+}
+
+/// Profile lookup result, flagged when it came from the cache because the
+/// user service couldn't be reached.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProfileLookup {
+    #[serde(flatten)]
+    pub profile: UserProfile,
+    pub stale: bool,
+}
+
+/// Calls to the user service that never fail chat: lookups fall back to
+/// cached profiles and conversation updates queue until it is back.
+pub struct UserServiceClient {
+    // None when USER_SERVICE_URL isn't set
+    base_url: Option<String>,
+    http: reqwest::Client,
+    health: ServiceHealth,
+    profiles: Mutex<ProfileCache>,
+    pending_updates: Mutex<VecDeque<ConversationUpdate>>,
+    stale_served: AtomicU64,
+    dropped_updates: AtomicU64,
+}
+
+impl UserServiceClient {
+    pub fn new(base_url: Option<String>, timeout: Duration) -> Self {
+        UserServiceClient {
+            base_url: base_url.map(|url| url.trim_end_matches('/').to_string()),
+            http: reqwest::Client::builder().timeout(timeout).build().unwrap_or_default(),
+            health: ServiceHealth::default(),
+            profiles: Mutex::new(ProfileCache::default()),
+            pending_updates: Mutex::new(VecDeque::new()),
+            stale_served: AtomicU64::new(0),
+            dropped_updates: AtomicU64::new(0),
+        }
+    }
+
+    /// Configured by `USER_SERVICE_URL` and `USER_SERVICE_TIMEOUT_MS`.
+    pub fn from_env() -> Self {
+        let base_url = std::env::var("USER_SERVICE_URL").ok().filter(|url| !url.trim().is_empty());
+        let timeout_ms = std::env::var("USER_SERVICE_TIMEOUT_MS")
+            .ok()
+            .and_then(|value| value.parse().ok())
+            .filter(|ms| *ms > 0)
+            .unwrap_or(DEFAULT_TIMEOUT_MS);
+        Self::new(base_url, Duration::from_millis(timeout_ms))
+    }
+
+    pub fn is_degraded(&self) -> bool {
+        self.health.is_degraded()
+    }
+
+    /// Drops the user's cached profile, e.g. once their data is erased.
+    pub fn forget_profile(&self, user_id: &str) {
+        self.profiles.lock().unwrap().remove(user_id);
+    }
+
+    /// A user's profile: from the cache while fresh, else from the user
+    /// service, else whatever the cache still has.
+    pub async fn profile(&self, user_id: &str) -> Option<ProfileLookup> {
+        let now = Instant::now();
+        if let Some(profile) = self.profiles.lock().unwrap().fresh(user_id, now) {
+            return Some(ProfileLookup { profile: profile.clone(), stale: false });
+        }
+
+        if let (Some(base_url), false) = (&self.base_url, self.health.is_degraded()) {
+            match self.fetch_profile(base_url, user_id).await {
+                Ok(Some(profile)) => {
+                    self.health.record_success();
+                    self.profiles.lock().unwrap().insert(profile.clone(), now);
+                    return Some(ProfileLookup { profile, stale: false });
+                }
+                Ok(None) => {
+                    self.health.record_success();
+                    return None;
+                }
+                Err(e) => {
+                    warn!("Failed to fetch profile of {} from the user service: {}", user_id, e);
+                    self.health.record_failure();
+                }
+            }
+        }
+
+        let stale = self.profiles.lock().unwrap().any_age(user_id).cloned()?;
+        self.stale_served.fetch_add(1, Ordering::Relaxed);
+        Some(ProfileLookup { profile: stale, stale: true })
+    }
+
+    async fn fetch_profile(&self, base_url: &str, user_id: &str) -> reqwest::Result<Option<UserProfile>> {
+        let response = self.http.get(format!("{}/api/users/{}", base_url, user_id)).send().await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        response.error_for_status()?.json().await.map(Some)
+    }
+
+    /// Tells the user service about a conversation's new last message, or
+    /// queues it while the service is down.
+    pub async fn sync_conversation(&self, update: ConversationUpdate) {
+        let Some(base_url) = &self.base_url else {
+            return;
+        };
+        if self.health.is_degraded() {
+            self.queue_update(update);
+            return;
+        }
+        match self.send_update(base_url, &update).await {
+            Ok(()) => self.health.record_success(),
+            Err(e) => {
+                warn!("Failed to sync conversation {} to the user service: {}", update.conversation_id, e);
+                self.health.record_failure();
+                self.queue_update(update);
+            }
+        }
+    }
+
+    async fn send_update(&self, base_url: &str, update: &ConversationUpdate) -> reqwest::Result<()> {
+        self.http
+            .post(format!("{}/api/conversations/{}/last-message", base_url, update.conversation_id))
+            .json(update)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn queue_update(&self, update: ConversationUpdate) {
+        let mut queue = self.pending_updates.lock().unwrap();
+        // Only the newest update per conversation matters
+        queue.retain(|queued| queued.conversation_id != update.conversation_id);
+        if queue.len() >= MAX_QUEUED_UPDATES {
+            queue.pop_front();
+            self.dropped_updates.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(update);
+    }
+
+    // Sends queued updates in order, stopping at the first failure
+    async fn flush_updates(&self, base_url: &str) {
+        loop {
+            let Some(update) = self.pending_updates.lock().unwrap().pop_front() else {
+                return;
+            };
+            if let Err(e) = self.send_update(base_url, &update).await {
+                warn!("Failed to replay conversation update for {}: {}", update.conversation_id, e);
+                self.health.record_failure();
+                self.pending_updates.lock().unwrap().push_front(update);
+                return;
+            }
+        }
+    }
+
+    /// Probes the user service's health endpoint. Recovery ends degraded
+    /// mode and replays queued conversation updates.
+    pub fn spawn_probe(self: Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let base_url = self.base_url.clone()?;
+        Some(tokio::spawn(async move {
+            let mut tick = tokio::time::interval(PROBE_INTERVAL);
+            loop {
+                tick.tick().await;
+                let healthy = match self.http.get(format!("{}/health", base_url)).send().await {
+                    Ok(response) => response.status().is_success(),
+                    Err(_) => false,
+                };
+                if healthy {
+                    self.health.record_success();
+                    self.flush_updates(&base_url).await;
+                } else {
+                    self.health.record_failure();
+                }
+            }
+        }))
+    }
+
+    /// Prometheus text, served with the fan-out metrics.
+    pub fn render(&self) -> String {
+        if self.base_url.is_none() {
+            return String::new();
+        }
+        let mut out = String::new();
+        let metrics: [(&str, &str, &str, u64); 5] = [
+            ("chat_user_service_degraded", "gauge", "1 while the user service is treated as down", self.health.is_degraded() as u64),
+            ("chat_user_service_failures_total", "counter", "Failed calls to the user service", self.health.failures.load(Ordering::Relaxed)),
+            ("chat_user_service_stale_profiles_total", "counter", "Cached profiles served because the user service was down", self.stale_served.load(Ordering::Relaxed)),
+            ("chat_user_service_queued_updates", "gauge", "Conversation updates waiting for the user service", self.pending_updates.lock().unwrap().len() as u64),
+            ("chat_user_service_dropped_updates_total", "counter", "Conversation updates dropped because the queue was full", self.dropped_updates.load(Ordering::Relaxed)),
+        ];
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+}
+
+// GET /api/users/:user_id/profile - `stale` is set when served from the
+// cache while the user service is down
+pub async fn user_profile_handler(
+    Path(user_id): Path<String>,
+    State(state): State<AppState>,
+    _user: AuthUser,
+) -> Result<Json<ProfileLookup>, AppError> {
+    state.user_service.profile(&user_id).await.map(Json).ok_or(AppError::NotFound)
+}
@@ -0,0 +1,279 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::HeaderMap,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use hmac::{Hmac, Mac};
+use mongodb::{bson::doc, Collection};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::{auth::AuthUser, language::tag_message, models::Message, websocket::broadcast_new_message, AppError, AppState};
+
+// Signed requests older (or newer) than this are rejected as replays
+pub const SIGNATURE_TOLERANCE_SECONDS: i64 = 5 * 60;
+const DEFAULT_RATE_LIMIT: u32 = 10;
+const MAX_CONTENT_LENGTH: usize = 4000;
+
+/// An inbound webhook that lets an external system post into one room.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomWebhook {
+    #[serde(rename = "_id")]
+    pub id: String,
+    pub room_id: String,
+    pub name: String,
+    // Path segment of the hook URL
+    pub token: String,
+    // HMAC-SHA256 key the sender signs requests with
+    pub secret: String,
+    pub rate_limit_per_minute: u32,
+    pub created_by: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub created_at: DateTime<Utc>,
+}
+
+fn webhooks(state: &AppState) -> Collection<RoomWebhook> {
+    state.database.collection("room_webhooks")
+}
+
+/// `sha256=<hex>` signature over `"{timestamp}.{body}"`.
+pub fn sign(secret: &str, timestamp: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+pub fn verify_signature(secret: &str, timestamp: &str, body: &[u8], signature: &str, now: i64) -> bool {
+    let Ok(sent_at) = timestamp.parse::<i64>() else {
+        return false;
+    };
+    if (now - sent_at).abs() > SIGNATURE_TOLERANCE_SECONDS {
+        return false;
+    }
+    let Some(signature) = signature.strip_prefix("sha256=").and_then(|hex_sig| hex::decode(hex_sig).ok()) else {
+        return false;
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    // Constant-time comparison
+    mac.verify_slice(&signature).is_ok()
+}
+
+// Fixed one-minute window per hook
+async fn check_rate_limit(state: &AppState, hook: &RoomWebhook) -> Result<(), AppError> {
+    let now = Utc::now().timestamp();
+    let key = format!("webhook_rl:{}:{}", hook.id, now / 60);
+    let mut conn = state.redis_pool.get().await.map_err(|e| {
+        error!("Failed to get Redis connection for webhook rate limit: {}", e);
+        AppError::InternalServerError
+    })?;
+    let (count,): (u32,) = redis::pipe()
+        .incr(&key, 1)
+        .expire(&key, 60)
+        .ignore()
+        .query_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!("Failed to update webhook rate limit: {}", e);
+            AppError::InternalServerError
+        })?;
+
+    if count > hook.rate_limit_per_minute {
+        warn!("Webhook {} for room {} is over its rate limit", hook.id, hook.room_id);
+        return Err(AppError::TooManyRequests { retry_after: (60 - now % 60) as u64 });
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+pub struct WebhookPayload {
+    content: String,
+    // Display name for this post; defaults to the hook's name
+    username: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct WebhookPostResponse {
+    message_id: String,
+}
+
+// POST /hooks/rooms/:token
+// Headers: X-Webhook-Timestamp (unix seconds), X-Webhook-Signature (sha256=<hex>)
+pub async fn receive_webhook(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<WebhookPostResponse>, AppError> {
+    let hook = webhooks(&state)
+        .find_one(doc! { "token": &token }, None)
+        .await?
+        .ok_or(AppError::NotFound)?;
+
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or("");
+    if !verify_signature(
+        &hook.secret,
+        header("x-webhook-timestamp"),
+        &body,
+        header("x-webhook-signature"),
+        Utc::now().timestamp(),
+    ) {
+        warn!("Rejected webhook {} with an invalid signature", hook.id);
+        return Err(AppError::Unauthorized);
+    }
+
+    check_rate_limit(&state, &hook).await?;
+
+    let payload: WebhookPayload =
+        serde_json::from_slice(&body).map_err(|e| AppError::BadRequest(format!("Invalid payload: {}", e)))?;
+    let content = payload.content.trim();
+    if content.is_empty() || content.len() > MAX_CONTENT_LENGTH {
+        return Err(AppError::BadRequest(format!("content must be 1-{} bytes", MAX_CONTENT_LENGTH)));
+    }
+
+    let room = state.db.get_room(&hook.room_id).await?.ok_or(AppError::NotFound)?;
+    let mut message = Message::new(
+        hook.room_id.clone(),
+        format!("webhook:{}", hook.id),
+        payload.username.unwrap_or_else(|| hook.name.clone()),
+        content.to_string(),
+    );
+    let verdict = crate::message_filters::screen(&mut message, &room.settings).map_err(AppError::BadRequest)?;
+    tag_message(&mut message, room.settings.language.as_deref());
+
+    let id = state.db.create_message(&message).await?.id();
+    state.room_metrics.record_message(&message.room_id);
+    message.id = Some(id);
+    state.delivery_trace.persisted(&id);
+    crate::event_log::append(&state, crate::event_log::LogEvent::room_message(&message)).await;
+    if !verdict.flags.is_empty() {
+        crate::message_filters::queue_flagged(&state, &message, &verdict.flags).await;
+    }
+    info!("Webhook {} posted message {} to room {}", hook.id, id, hook.room_id);
+
+    broadcast_new_message(&state, message).await;
+    Ok(Json(WebhookPostResponse { message_id: id.to_string() }))
+}
+
+#[derive(Deserialize)]
+pub struct CreateWebhookRequest {
+    name: String,
+    rate_limit_per_minute: Option<u32>,
+}
+
+/// Returned once at creation; the secret is not shown again.
+#[derive(Serialize)]
+pub struct CreatedWebhook {
+    id: String,
+    name: String,
+    url: String,
+    secret: String,
+    rate_limit_per_minute: u32,
+}
+
+#[derive(Serialize)]
+pub struct WebhookSummary {
+    id: String,
+    name: String,
+    url: String,
+    rate_limit_per_minute: u32,
+    created_by: String,
+    created_at: String,
+}
+
+impl From<RoomWebhook> for WebhookSummary {
+    fn from(hook: RoomWebhook) -> Self {
+        WebhookSummary {
+            id: hook.id,
+            name: hook.name,
+            url: format!("/hooks/rooms/{}", hook.token),
+            rate_limit_per_minute: hook.rate_limit_per_minute,
+            created_by: hook.created_by,
+            created_at: hook.created_at.to_rfc3339(),
+        }
+    }
+}
+
+// POST /api/rooms/:location_id/webhooks
+pub async fn create_webhook_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(req): Json<CreateWebhookRequest>,
+) -> Result<Json<CreatedWebhook>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+    if req.name.trim().is_empty() {
+        return Err(AppError::BadRequest("name is required".to_string()));
+    }
+
+    let hook = RoomWebhook {
+        id: Uuid::new_v4().to_string(),
+        room_id: location_id,
+        name: req.name.trim().to_string(),
+        token: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+        secret: format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple()),
+        rate_limit_per_minute: req.rate_limit_per_minute.unwrap_or(DEFAULT_RATE_LIMIT).max(1),
+        created_by: user.user_id.clone(),
+        created_at: Utc::now(),
+    };
+    webhooks(&state).insert_one(&hook, None).await?;
+    info!("User {} created webhook {} for room {}", user.username, hook.id, hook.room_id);
+
+    Ok(Json(CreatedWebhook {
+        url: format!("/hooks/rooms/{}", hook.token),
+        id: hook.id,
+        name: hook.name,
+        secret: hook.secret,
+        rate_limit_per_minute: hook.rate_limit_per_minute,
+    }))
+}
+
+// GET /api/rooms/:location_id/webhooks
+pub async fn list_webhooks_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<Vec<WebhookSummary>>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+
+    let hooks: Vec<RoomWebhook> = webhooks(&state)
+        .find(doc! { "room_id": &location_id }, None)
+        .await?
+        .try_collect()
+        .await?;
+    Ok(Json(hooks.into_iter().map(WebhookSummary::from).collect()))
+}
+
+// DELETE /api/rooms/:location_id/webhooks/:webhook_id
+pub async fn delete_webhook_handler(
+    Path((location_id, webhook_id)): Path<(String, String)>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !user.is_moderator() {
+        return Err(AppError::Forbidden);
+    }
+
+    let result = webhooks(&state)
+        .delete_one(doc! { "_id": &webhook_id, "room_id": &location_id }, None)
+        .await?;
+    if result.deleted_count == 0 {
+        return Err(AppError::NotFound);
+    }
+    info!("User {} deleted webhook {} from room {}", user.username, webhook_id, location_id);
+    Ok(Json(serde_json::json!({ "success": true })))
+}
@@ -1,13 +1,14 @@
-use crate::{models::*, local_chat::*, AppState};
-use axum::extract::ws::{Message as WsMsg, WebSocket};
+use crate::{abuse::{check_rate_limit, record_signal, AbuseSignal}, activity::*, canary::{Canary, Cohort}, connection_state::{ConnectionState, ProtocolError}, auth::{verify_token, AuthUser}, fanout::{removed_user, Delivery, Subscriber}, heartbeat::{HeartbeatConfig, Liveness}, models::*, notifications::NotificationHint, rate_limit::check_room_rate_limit, room_invites::RoomVisibility, room_roles::{caller_is_moderator, RoomRole}, send_buffer::{SocketReceiver, SocketSender}, sessions::SocketSession, socket_abuse::{penalize, SocketAbuse, SocketViolation}, language::tag_message, local_chat::*, room_bridge::*, room_mentions::{claim_room_mention, mentions_everyone, spawn_room_mention_pushes}, subscription_filter::{FanoutFilter, SubscriptionFilter}, wire_format::WireFormat, ws_ticket::{JoinTicket, TicketError}, AppError, AppState};
+use axum::extract::ws::{close_code, CloseFrame, Message as WsMsg, WebSocket};
 use futures::{sink::SinkExt, stream::StreamExt};
-use redis::aio::PubSub;
-use redis::AsyncCommands;
 use std::collections::HashMap;
-use tokio::sync::RwLock;
-use tracing::{error, info};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info, info_span, warn, Instrument};
 use uuid::Uuid;
 
+#[derive(Default)]
 pub struct ConnectionManager {
     // location_id -> HashMap<socket_id, User>
     rooms: HashMap<String, HashMap<String, User>>,
@@ -23,7 +24,7 @@ impl ConnectionManager {
     pub fn add_user(&mut self, location_id: String, socket_id: String, user: User) {
         self.rooms
             .entry(location_id)
-            .or_insert_with(HashMap::new)
+            .or_default()
             .insert(socket_id, user);
     }
 
@@ -42,6 +43,26 @@ impl ConnectionManager {
             .unwrap_or_default()
     }
 
+    pub fn get_user(&self, location_id: &str, socket_id: &str) -> Option<User> {
+        self.rooms.get(location_id)?.get(socket_id).cloned()
+    }
+
+    pub fn socket_counts(&self) -> Vec<(String, usize)> {
+        self.rooms
+            .iter()
+            .filter(|(_, sockets)| !sockets.is_empty())
+            .map(|(location_id, sockets)| (location_id.clone(), sockets.len()))
+            .collect()
+    }
+
+    pub fn socket_ids_by_room(&self) -> Vec<(String, Vec<String>)> {
+        self.rooms
+            .iter()
+            .filter(|(_, sockets)| !sockets.is_empty())
+            .map(|(room_id, sockets)| (room_id.clone(), sockets.keys().cloned().collect()))
+            .collect()
+    }
+
     pub fn get_user_count(&self, location_id: &str) -> usize {
         self.rooms
             .get(location_id)
@@ -54,15 +75,461 @@ impl ConnectionManager {
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct BroadcastMessage {
     from_socket_id: String,
+    // Instance that published the message
+    #[serde(default)]
+    origin: String,
+    // Re-published after a Redis outage; the origin already delivered it locally
+    #[serde(default)]
+    replayed: bool,
+    // Unix millis when published, for lag tracking; 0 from older instances
+    #[serde(default)]
+    published_at_ms: i64,
+    // Trace of the publishing span, so fan-out continues it on every instance
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    trace_context: crate::telemetry::TraceContext,
     message: WsMessage,
 }
 
-pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppState) {
-    let (mut sender, mut receiver) = socket.split();
+/// Channel for events addressed to a single user on any socket, such as
+/// event reminders.
+pub fn user_channel(user_id: &str) -> String {
+    format!("user:{}", user_id)
+}
+
+pub(crate) async fn send_to_user(state: &AppState, user_id: &str, message: WsMessage) {
+    publish_to_channel(state, &user_channel(user_id), message, None).await;
+}
+
+// Subscribes a joined socket to its user's channel; ends with the socket
+fn subscribe_user_channel(state: &AppState, user_id: &str, socket_id: &str, tx: &SocketSender) {
+    let channel = user_channel(user_id);
+    let subscriber = Subscriber::unfiltered(tx.clone());
+    state.fanout.subscribe(&channel, socket_id, subscriber.clone());
+    tokio::spawn(forward_channel(state.clone(), channel, socket_id.to_string(), subscriber));
+}
+
+// Forwards a channel from the shared Redis connection to one socket until
+// the socket goes away. Local fan-out covers any gap while Redis is down.
+async fn forward_channel(
+    state: AppState,
+    channel: String,
+    socket_id: String,
+    tx: Subscriber,
+) {
+    let mut messages = state.pubsub.subscribe(&channel);
+    debug!(channel = %channel, socket_id = %socket_id, "Subscribed to channel");
+    loop {
+        let payload = tokio::select! {
+            _ = tx.closed() => break,
+            received = messages.recv() => match received {
+                Ok(payload) => payload,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Socket {} fell behind on {} and missed {} messages", socket_id, channel, skipped);
+                    continue;
+                }
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+            },
+        };
+        let Ok(broadcast_msg) = serde_json::from_str::<BroadcastMessage>(&payload) else {
+            continue;
+        };
+        // Skip messages from the same socket and replays already seen locally
+        let delivered_locally = broadcast_msg.replayed && broadcast_msg.origin == state.instance_id;
+        if broadcast_msg.from_socket_id == socket_id || delivered_locally {
+            continue;
+        }
+        let traced_id = new_message_id(&broadcast_msg.message);
+        let removed = tx.is_removed_by(&broadcast_msg.message);
+        debug!(channel = %channel, socket_id = %socket_id, "Forwarding message to socket");
+        match tx.deliver(broadcast_msg.message) {
+            Delivery::Closed => break,
+            Delivery::Sent => {
+                state.pubsub_lag.record(&channel, broadcast_msg.published_at_ms, chrono::Utc::now().timestamp_millis());
+                if let Some(message_id) = traced_id {
+                    state.delivery_trace.delivered(&message_id, 1);
+                }
+            }
+            Delivery::Filtered => {}
+        }
+        // A banned or removed user hears nothing more from the room, even
+        // before the socket has finished closing
+        if removed {
+            state.fanout.unsubscribe(&channel, &socket_id);
+            break;
+        }
+    }
+}
+
+/// Request-level details captured at upgrade time.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionInfo {
+    // Coarse location of the client's IP, when the IP/geo check is enabled
+    pub ip_location: Option<h3o::LatLng>,
+    // Initial room subscription filter, from `?filter=`
+    pub filter: FanoutFilter,
+    // Encoding of outgoing frames, from `?encoding=` or the subprotocol
+    pub format: WireFormat,
+    // Rollout cohort, from the `?user_id=` the socket was opened with
+    pub cohort: Cohort,
+    // Redeemed `?ticket=` standing in for the join's token
+    pub ticket: Option<JoinTicket>,
+    // The client's IP, blocked after too many protocol violations
+    pub ip: Option<std::net::IpAddr>,
+}
+
+impl ConnectionInfo {
+    pub fn from_headers(state: &AppState, headers: &axum::http::HeaderMap, peer: Option<std::net::SocketAddr>) -> Self {
+        ConnectionInfo {
+            ip_location: state.ip_geo.ip_location(headers),
+            ip: state.tarpit.client_ip(headers, peer),
+            filter: FanoutFilter::All,
+            format: WireFormat::Json,
+            cohort: Cohort::Stable,
+            ticket: None,
+        }
+    }
+}
+
+// Refuses a join when the room is at max_users across all instances
+async fn room_full(state: &AppState, tx: &SocketSender, room_id: &str, user: &User, settings: &RoomSettings) -> bool {
+    if crate::presence::try_join(state, room_id, user, settings.max_users).await == crate::presence::JoinOutcome::Joined {
+        return false;
+    }
+    warn!("Refusing join to full room {} ({} users)", room_id, settings.max_users);
+    let _ = tx.send(WsMessage::RoomFull {
+        room_id: room_id.to_string(),
+        max_users: settings.max_users,
+        suggestions: crate::presence::suggest_rooms(state, room_id, settings.max_users).await,
+    });
+    true
+}
+
+// Reports a frame or transition the socket's protocol state doesn't allow
+fn protocol_violation(tx: &SocketSender, result: Result<(), ProtocolError>) -> bool {
+    match result {
+        Ok(()) => false,
+        Err(e) => {
+            let _ = tx.send(WsMessage::Error { message: e.to_string() });
+            true
+        }
+    }
+}
+
+// Tells the socket why its reaction was refused
+fn report_reaction(tx: &SocketSender, result: Result<(), AppError>) {
+    match result {
+        Ok(()) => {}
+        Err(AppError::TooManyRequests { retry_after }) => {
+            let _ = tx.send(WsMessage::RateLimited { retry_after });
+        }
+        Err(e) => {
+            let _ = tx.send(WsMessage::Error { message: format!("Reaction failed: {}", e) });
+        }
+    }
+}
+
+// Refuses a join when the room excludes users whose location looks spoofed,
+// unless the caller joins as themselves and is a member, e.g. through an
+// invite link
+async fn location_check_failed(state: &AppState, tx: &SocketSender, settings: &RoomSettings, user: &User, caller: Option<&AuthUser>) -> bool {
+    if settings.exclude_location_mismatch && user.location_flagged {
+        if caller.is_some_and(|caller| caller.user_id == user.id)
+            && crate::room_invites::is_member(state, &user.location_id, &user.id, None).await
+        {
+            return false;
+        }
+        warn!("Refusing user {} in location-restricted room {}", user.id, user.location_id);
+        let _ = tx.send(WsMessage::Error {
+            message: "Your location could not be verified for this room".to_string(),
+        });
+        return true;
+    }
+    false
+}
+
+// Who a join is signed in as: the socket's ticket holder, otherwise the
+// join's token. Refuses a ticket issued for another room.
+fn join_caller(tx: &SocketSender, info: &ConnectionInfo, room_id: &str, token: Option<&str>) -> Result<Option<AuthUser>, TicketError> {
+    match &info.ticket {
+        Some(ticket) if ticket.room_id != room_id => {
+            let _ = tx.send(WsMessage::Error { message: TicketError::WrongRoom.to_string() });
+            Err(TicketError::WrongRoom)
+        }
+        Some(ticket) => Ok(Some(ticket.caller())),
+        None => Ok(token.and_then(|token| verify_token(token).ok()).map(AuthUser::from)),
+    }
+}
+
+// A token the client sent that didn't verify. The join goes ahead without
+// it, as before, but counts against the socket.
+fn rejected_token(caller: Option<&AuthUser>, token: Option<&str>) -> bool {
+    caller.is_none() && token.is_some_and(|token| !token.is_empty())
+}
+
+// Refuses a join under someone else's name: the caller's identity when
+// signed in, otherwise the username the user id last authenticated with
+async fn identity_check_failed(state: &AppState, tx: &SocketSender, user: &User, caller: Option<&AuthUser>) -> bool {
+    let mismatch = match caller {
+        Some(caller) if crate::identity::is_impersonation(caller, Some(&user.id), Some(&user.username)) => true,
+        Some(caller) => {
+            crate::identity::remember(state, caller).await;
+            false
+        }
+        None => crate::identity::known_username(state, &user.id)
+            .await
+            .is_some_and(|known| known != user.username),
+    };
+    if mismatch {
+        warn!("Refusing join by {} as {:?}: username does not match the account", user.id, user.username);
+        let _ = tx.send(WsMessage::Error {
+            message: "Your username does not match your account".to_string(),
+        });
+    }
+    mismatch
+}
+
+// Refuses a join to a mature room unless the caller is age verified
+fn age_check_failed(tx: &SocketSender, settings: &RoomSettings, user: &User, caller: Option<&AuthUser>) -> bool {
+    if may_view_rating(settings, &user.id, caller) {
+        return false;
+    }
+    warn!("Refusing unverified user {} in mature room {}", user.id, user.location_id);
+    let _ = tx.send(WsMessage::Error {
+        message: "This room is rated mature and needs an age-verified account".to_string(),
+    });
+    true
+}
+
+// Whether the room's content rating lets the user in
+fn may_view_rating(settings: &RoomSettings, user_id: &str, caller: Option<&AuthUser>) -> bool {
+    !settings.content_rating.requires_age_verification()
+        || caller.is_some_and(|caller| caller.user_id == user_id && caller.age_verified)
+}
+
+/// The neighbouring hexes a socket may listen to: those the user could join
+/// themselves. Hexes nobody has joined yet are open; ones that fail to load
+/// are left out.
+pub async fn joinable_neighbors(state: &AppState, neighbors: Vec<String>, user_id: &str, caller: Option<&AuthUser>) -> Vec<String> {
+    let mut joinable = Vec::with_capacity(neighbors.len());
+    for neighbor in neighbors {
+        let room = match crate::room_cache::room(state, &neighbor).await {
+            Ok(room) => room,
+            Err(AppError::NotFound) => ChatRoom::new(&neighbor),
+            Err(e) => {
+                warn!("Leaving neighbour hex {} out for {}: {}", neighbor, user_id, e);
+                continue;
+            }
+        };
+        if !may_view_rating(&room.settings, user_id, caller) || crate::room_bans::find_ban(state, &neighbor, user_id).await.is_some() {
+            continue;
+        }
+        let role = crate::room_roles::role_in(&room, user_id);
+        if crate::room_invites::may_join(state, &neighbor, room.visibility, role, caller, user_id, None).await {
+            joinable.push(neighbor);
+        }
+    }
+    joinable
+}
+
+// Refuses a join to a room the user is banned from
+async fn ban_check_failed(state: &AppState, tx: &SocketSender, room_id: &str, user: &User) -> bool {
+    if crate::room_bans::find_ban(state, room_id, &user.id).await.is_none() {
+        return false;
+    }
+    warn!("Refusing user {} banned from room {}", user.id, room_id);
+    let _ = tx.send(WsMessage::Error { message: "You are banned from this room".to_string() });
+    true
+}
+
+// Refuses a join to a private room the user isn't a member of and has no
+// invite token for. The room is the one `user` is joining
+async fn private_check_failed(
+    state: &AppState,
+    tx: &SocketSender,
+    visibility: RoomVisibility,
+    role: RoomRole,
+    user: &User,
+    caller: Option<&AuthUser>,
+    invite: Option<&str>,
+) -> bool {
+    if crate::room_invites::may_join(state, &user.location_id, visibility, role, caller, &user.id, invite).await {
+        return false;
+    }
+    warn!("Refusing user {} from private room {}", user.id, user.location_id);
+    let _ = tx.send(WsMessage::Error { message: "This room is private".to_string() });
+    true
+}
+
+// Why a socket can't enter a room that couldn't be loaded or created for it
+fn room_refusal(error: &AppError) -> String {
+    match error {
+        AppError::TooManyRequests { .. } => "You've created too many rooms today",
+        AppError::Unauthorized => "Sign in to open a new room",
+        _ => "Failed to load room",
+    }
+    .to_string()
+}
+
+// Refuses a resume into a private room the user was removed from since the
+// session started. A room that can't be loaded refuses too
+async fn resume_private_check_failed(
+    state: &AppState,
+    tx: &SocketSender,
+    room: Option<&ChatRoom>,
+    user: &User,
+    session_moderator: bool,
+) -> bool {
+    if let Some(room) = room {
+        if crate::room_invites::may_resume(state, room, &user.id, session_moderator).await {
+            return false;
+        }
+    }
+    warn!("Refusing to resume user {} into room {}", user.id, user.location_id);
+    let _ = tx.send(WsMessage::ResumeFailed { reason: "This room is private; join again".to_string() });
+    true
+}
+
+// Whether the caller's token or ticket is for the user joining
+fn caller_is_user(caller: Option<&AuthUser>, user_id: &str) -> bool {
+    caller.is_some_and(|caller| caller.user_id == user_id)
+}
+
+// Refuses a write from a socket that joined without a token or ticket for
+// its user id; anyone can claim an id, so only verified sockets post, react
+// or RSVP under one
+fn unverified_write(tx: &SocketSender, verified: bool) -> bool {
+    if verified {
+        return false;
+    }
+    let _ = tx.send(WsMessage::Error { message: "Sign in to post in this room".to_string() });
+    true
+}
+
+// How long a socket's send side gets to deliver its close frame once the
+// receive side has finished
+const CLOSE_GRACE: std::time::Duration = std::time::Duration::from_secs(1);
+
+// Writes queued messages to the client. Pings it every interval and closes
+// the socket once nothing, not even a pong, has come back for the idle timeout.
+// Stops when the receive side drops `closing`, after sending its close frame
+// if it sent one, or once the instance starts shutting down, after telling
+// the client when to reconnect.
+async fn send_loop(
+    mut sender: futures::stream::SplitSink<WebSocket, WsMsg>,
+    mut rx: SocketReceiver,
+    heartbeat: HeartbeatConfig,
+    liveness: Liveness,
+    format: WireFormat,
+    canary: (Arc<Canary>, Cohort),
+    closing: (tokio::sync::oneshot::Receiver<CloseFrame<'static>>, crate::shutdown::Notice),
+) {
+    let (canary, cohort) = canary;
+    let (mut closing, mut shutdown) = closing;
+    let metrics = canary.metrics(cohort);
+    let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + heartbeat.ping_interval, heartbeat.ping_interval);
+    // Count updates held back from a low data socket
+    let mut batch = crate::low_data::EventBatch::default();
+    let mut flush = tokio::time::interval_at(
+        tokio::time::Instant::now() + crate::low_data::BATCH_INTERVAL,
+        crate::low_data::BATCH_INTERVAL,
+    );
+    loop {
+        tokio::select! {
+            close = &mut closing => {
+                if let Ok(frame) = close {
+                    let _ = sender.send(WsMsg::Close(Some(frame))).await;
+                }
+                break;
+            }
+            _ = shutdown.draining() => {
+                let notice = WsMessage::ServerShutdown { reconnect_after: shutdown.reconnect_after };
+                if let Some(frame) = format.encode(&notice) {
+                    let _ = sender.send(frame).await;
+                }
+                let frame = CloseFrame { code: close_code::RESTART, reason: "server shutting down".into() };
+                let _ = sender.send(WsMsg::Close(Some(frame))).await;
+                break;
+            }
+            msg = rx.recv() => {
+                let Some(msg) = msg else { break };
+                // Bans and removals from a private room go out to the whole
+                // room but only close the user's own sockets
+                if let Some(user_id) = removed_user(&msg) {
+                    if rx.user_id() != Some(user_id) {
+                        continue;
+                    }
+                    send_frame(&mut sender, format, metrics, &msg).await;
+                    let reason = match msg {
+                        WsMessage::RoomBanned { .. } => "banned from room",
+                        _ => "removed from room",
+                    };
+                    let frame = CloseFrame { code: close_code::POLICY, reason: reason.into() };
+                    let _ = sender.send(WsMsg::Close(Some(frame))).await;
+                    break;
+                }
+                let msg = if rx.is_low_data() {
+                    if crate::low_data::is_batched(&msg) {
+                        batch.add(msg);
+                        continue;
+                    }
+                    crate::low_data::strip(msg)
+                } else {
+                    msg
+                };
+                if !send_frame(&mut sender, format, metrics, &msg).await {
+                    break;
+                }
+                crate::service_metrics::message_sent(rx.queued());
+            }
+            _ = flush.tick(), if !batch.is_empty() => {
+                for msg in batch.take() {
+                    if !send_frame(&mut sender, format, metrics, &msg).await {
+                        return;
+                    }
+                }
+            }
+            _ = ping.tick() => {
+                if liveness.is_idle(&heartbeat) {
+                    info!("Closing socket idle for {:?}", liveness.idle_for());
+                    let _ = sender.send(WsMsg::Close(None)).await;
+                    break;
+                }
+                if sender.send(WsMsg::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Encodes and writes one message; false once the socket is gone
+async fn send_frame(
+    sender: &mut futures::stream::SplitSink<WebSocket, WsMsg>,
+    format: WireFormat,
+    metrics: &crate::canary::CohortMetrics,
+    msg: &WsMessage,
+) -> bool {
+    let Some(frame) = format.encode(msg) else {
+        metrics.encode_failures.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        return true;
+    };
+    if sender.send(frame).await.is_err() {
+        return false;
+    }
+    metrics.frames_sent.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    true
+}
+
+#[tracing::instrument(name = "socket", skip_all, fields(room_id = %location_id))]
+pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppState, info: ConnectionInfo) {
+    let (sender, mut receiver) = socket.split();
     let socket_id = Uuid::new_v4().to_string();
+    let cohort = info.cohort;
+    state.canary.connect(&socket_id, cohort);
     
-    // Channel for sending messages to this client
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<WsMessage>();
+    // Bounded channel for sending messages to this client
+    let (tx, rx) = state.send_buffers.channel();
+    let overload_signal = tx.clone();
     
     // Clone necessary data for tasks
     let socket_id_clone = socket_id.clone();
@@ -70,83 +537,180 @@ pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppSta
     let state_clone = state.clone();
     let tx_clone = tx.clone();
     let socket_id_for_redis = socket_id.clone();
+    let activity = Arc::new(Mutex::new(SocketActivity::default()));
+    let activity_clone = activity.clone();
+    let activity_task = spawn_activity_expiry(state.clone(), activity.clone(), socket_id.clone());
     
-    // Create Redis pub/sub connection for this client
-    let redis_client = state.redis.clone();
-    let channel_name = format!("room:{}", location_id);
+    let filter = Arc::new(std::sync::RwLock::new(SubscriptionFilter::new(info.filter)));
+    let filter_clone = filter.clone();
     
-    // Spawn task to handle Redis pub/sub messages
+    // Subscribe this socket to its room, locally and through Redis, once a
+    // Join or Resume lets it in; refused sockets hear nothing from the room
+    let channel_name = format!("room:{}", location_id);
+    let (joined_tx, joined_rx) = tokio::sync::oneshot::channel::<()>();
+    let mut room_joined = Some(joined_tx);
+    let room_subscriber = Subscriber::filtered(tx_clone, filter);
+    let state_for_redis = state.clone();
+    let channel_for_redis = channel_name.clone();
     let mut redis_task = tokio::spawn(async move {
-        let mut pubsub: PubSub = match redis_client.get_async_connection().await {
-            Ok(conn) => conn.into_pubsub(),
-            Err(e) => {
-                error!("Failed to create Redis pub/sub connection: {}", e);
-                return;
-            }
-        };
-        
-        // Subscribe to room channel
-        if let Err(e) = pubsub.subscribe(&channel_name).await {
-            error!("Failed to subscribe to channel {}: {}", channel_name, e);
+        if joined_rx.await.is_err() {
             return;
         }
-        
-        info!("Socket {} subscribed to Redis channel: {}", socket_id_for_redis, channel_name);
-        
-        // Listen for messages
-        let mut pubsub_stream = pubsub.on_message();
-        while let Some(msg) = pubsub_stream.next().await {
-            match msg.get_payload::<String>() {
-                Ok(payload) => {
-                    if let Ok(broadcast_msg) = serde_json::from_str::<BroadcastMessage>(&payload) {
-                        // Skip messages from the same socket
-                        if broadcast_msg.from_socket_id != socket_id_for_redis {
-                            let _ = tx_clone.send(broadcast_msg.message);
-                        }
-                    }
-                }
-                Err(e) => error!("Failed to parse Redis message: {}", e),
-            }
-        }
+        state_for_redis.fanout.subscribe(&channel_for_redis, &socket_id_for_redis, room_subscriber.clone());
+        forward_channel(state_for_redis, channel_for_redis, socket_id_for_redis, room_subscriber).await;
     });
     
-    // Spawn task to forward messages to client
-    let mut send_task = tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            if let Ok(json) = serde_json::to_string(&msg) {
-                if sender.send(WsMsg::Text(json)).await.is_err() {
-                    break;
-                }
-            }
-        }
-    });
+    // Spawn task to forward messages to client, pinging it while it's quiet
+    let liveness = Liveness::new();
+    let (close_tx, close_rx) = tokio::sync::oneshot::channel();
+    let mut send_task = tokio::spawn(send_loop(sender, rx, state.heartbeat, liveness.clone(), info.format, (state.canary.clone(), info.cohort), (close_rx, state.shutdown.notice(&socket_id))));
     
     // Handle incoming messages
     let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(WsMsg::Text(text))) = receiver.next().await {
-            if let Ok(msg) = serde_json::from_str::<WsMessage>(&text) {
+        let mut room_settings = RoomSettings::default();
+        // Set while the room is in review; posts by others count towards approval
+        let mut review_creator: Option<String> = None;
+        // Moderators bypass the room's permissions
+        let mut is_moderator = false;
+        // Whether the join proved the user id; see `unverified_write`
+        let mut verified = false;
+        let mut connection = ConnectionState::default();
+        // Spectators until their first post
+        let mut participating = false;
+        let mut abuse = SocketAbuse::new(info.ip);
+        let channel_for_join = format!("room:{}", location_id_clone);
+        
+        while let Some(Ok(frame)) = receiver.next().await {
+            liveness.touch();
+            if let WsMsg::Close(_) = frame {
+                break;
+            }
+            if let Err(violation) = state_clone.frame_limits.check(&frame) {
+                warn!("Closing socket {} after an invalid frame: {}", socket_id_clone, violation);
+                crate::service_metrics::socket_violation(SocketViolation::OversizedFrame.as_str());
+                let _ = close_tx.send(violation.close_frame());
+                break;
+            }
+            let decoded = WireFormat::decode(&frame);
+            if decoded.is_none() && matches!(frame, WsMsg::Text(_) | WsMsg::Binary(_)) {
+                if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::MalformedFrame).await {
+                    let _ = close_tx.send(close);
+                    break;
+                }
+            }
+            if let Some(msg) = decoded {
+                crate::service_metrics::message_received();
+                if protocol_violation(&tx, connection.admit(&msg)) {
+                    if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::ProtocolError).await {
+                        let _ = close_tx.send(close);
+                        break;
+                    }
+                    continue;
+                }
                 match msg {
-                    WsMessage::Join { user_id, username, token: _ } => {
-                        // TODO: Verify token
+                    WsMessage::Join { user_id, username, token, have_until, low_data, invite } => {
+                        // Legacy coordinate rooms may be bridged to their containing hex
+                        let bridged_hex = match state_clone.room_bridge {
+                            BridgeMode::Off => None,
+                            _ => legacy_room_cell(&location_id_clone).map(|cell| cell.to_string()),
+                        };
+                        if let Some(h3_index) = &bridged_hex {
+                            // History is only moved by an admin, see migrate_room_to_hex_handler
+                            if state_clone.room_bridge == BridgeMode::Redirect {
+                                let _ = tx.send(WsMessage::RoomRedirect {
+                                    room_id: location_id_clone.clone(),
+                                    h3_index: h3_index.clone(),
+                                });
+                                continue;
+                            }
+                            register_legacy_room(&state_clone.redis_pool, h3_index, &location_id_clone).await;
+                        }
+                        
+                        // Coordinate rooms carry the location the client claims to be at
+                        let location_flagged = parse_coordinates_from_location_id(&location_id_clone)
+                            .and_then(|(lat, lon)| crate::hex::lat_lng(lat, lon).ok())
+                            .is_some_and(|claimed| state_clone.ip_geo.is_mismatch(claimed, info.ip_location));
+                        if location_flagged {
+                            warn!("User {} claims room {} far from their IP location", user_id, location_id_clone);
+                        }
                         
                         // Add user to room
-                        let user = User {
+                        let mut user = User {
                             id: user_id.clone(),
                             username: username.clone(),
                             socket_id: socket_id_clone.clone(),
                             location_id: location_id_clone.clone(),
+                            location_flagged,
+                            joined_at: chrono::Utc::now(),
+                            badge: None,
                         };
                         
-                        let mut connections = state_clone.connections.write().await;
-                        connections.add_user(location_id_clone.clone(), socket_id_clone.clone(), user.clone());
-                        let user_count = connections.get_user_count(&location_id_clone);
-                        info!("User {} joined room {} (total users: {})", username, location_id_clone, user_count);
-                        drop(connections);
-                        
-                        // Update room activity
-                        if let Err(e) = state_clone.db.update_room_activity(&location_id_clone, user_count as i32).await {
-                            error!("Failed to update room activity: {}", e);
+                        let Ok(caller) = join_caller(&tx, &info, &location_id_clone, Some(&token)) else {
+                            if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::AuthFailure).await {
+                                let _ = close_tx.send(close);
+                                break;
+                            }
+                            continue;
+                        };
+                        let impersonating = identity_check_failed(&state_clone, &tx, &user, caller.as_ref()).await;
+                        if impersonating || rejected_token(caller.as_ref(), Some(&token)) {
+                            if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::AuthFailure).await {
+                                let _ = close_tx.send(close);
+                                break;
+                            }
+                            if impersonating {
+                                continue;
+                            }
+                        }
+                        user.badge = crate::badges::badge_for(&state_clone, caller.as_ref(), &user.id).await;
+                        if ban_check_failed(&state_clone, &tx, &location_id_clone, &user).await {
+                            continue;
+                        }
+                        // Room settings apply to every message sent on this socket.
+                        // A room that doesn't exist yet is created on the caller's quota
+                        let room = match crate::room_quota::room_for(&state_clone, &location_id_clone, caller.as_ref()).await {
+                            Ok(room) => room,
+                            // Without the room its private, rating and location checks can't run
+                            Err(e) => {
+                                warn!("Refusing join to room {}: {}", location_id_clone, e);
+                                let _ = tx.send(WsMessage::Error { message: room_refusal(&e) });
+                                continue;
+                            }
+                        };
+                        review_creator = crate::room_quota::pending_creator(&room);
+                        let room_role = crate::room_roles::role_in(&room, &user.id);
+                        let room_visibility = room.visibility;
+                        room_settings = room.settings;
+                        if location_check_failed(&state_clone, &tx, &room_settings, &user, caller.as_ref()).await {
+                            continue;
                         }
+                        if protocol_violation(&tx, connection.authenticate(&user.id)) {
+                            continue;
+                        }
+                        if age_check_failed(&tx, &room_settings, &user, caller.as_ref()) {
+                            continue;
+                        }
+                        is_moderator = caller_is_moderator(caller.as_ref(), &user.id, room_role);
+                        if private_check_failed(&state_clone, &tx, room_visibility, room_role, &user, caller.as_ref(), invite.as_deref()).await {
+                            continue;
+                        }
+                        if room_full(&state_clone, &tx, &location_id_clone, &user, &room_settings).await {
+                            continue;
+                        }
+                        if protocol_violation(&tx, connection.join(&location_id_clone)) {
+                            continue;
+                        }
+                        if let Some(joined) = room_joined.take() {
+                            let _ = joined.send(());
+                        }
+
+                        tx.set_low_data(low_data);
+                        let user_count = register_user(&state_clone, &channel_for_join, &user, &tx, &filter_clone, &activity_clone).await;
+                        info!("User {} joined room {} (total users: {})", username, location_id_clone, user_count);
+                        verified = caller_is_user(caller.as_ref(), &user.id);
+                        let session = SocketSession { low_data, verified, ..SocketSession::new(&user, is_moderator) };
+                        crate::sessions::start(&state_clone.redis_pool, &socket_id_clone, &session).await;
+                        let _ = tx.send(WsMessage::SessionStarted { session_id: socket_id_clone.clone() });
                         
                         // Check if this is a local chat room
                         if is_local_chat_room(&location_id_clone) {
@@ -154,18 +718,6 @@ pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppSta
                             
                             // Send RoomJoined message for local chat
                             if let Some((lat, lon)) = parse_coordinates_from_location_id(&location_id_clone) {
-                                let room_joined_msg = serde_json::json!({
-                                    "type": "RoomJoined",
-                                    "room_id": location_id_clone,
-                                    "room_name": generate_room_name(lat, lon),
-                                    "is_new_room": user_count == 1,
-                                    "user_count": user_count,
-                                    "location": {
-                                        "type": "Point",
-                                        "coordinates": [lon, lat]
-                                    }
-                                });
-                                
                                 // Send via the tx channel which will be forwarded to the client
                                 let _ = tx.send(WsMessage::RoomJoined {
                                     room_id: location_id_clone.clone(),
@@ -173,16 +725,16 @@ pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppSta
                                     is_new_room: user_count == 1,
                                     user_count: user_count as i32,
                                     location: Location::from_coordinates(lat, lon),
+                                    permissions: room_settings.permissions,
                                 });
                             }
                         }
                         
                         // Send message history
                         info!("Fetching message history for room: {}", location_id_clone);
-                        match state_clone.db.get_messages(&location_id_clone, 50, None).await {
-                            Ok(messages) => {
-                                info!("Sending {} messages in history to user", messages.len());
-                                let _ = tx.send(WsMessage::MessageHistory { messages });
+                        match load_history(&state_clone, &location_id_clone, &user_id, have_until).await {
+                            Ok(history) => {
+                                let _ = tx.send(history);
                             },
                             Err(e) => {
                                 error!("Failed to get message history: {}", e);
@@ -201,68 +753,263 @@ pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppSta
                         ).await;
                     }
                     
-                    WsMessage::Message { content } => {
+                    WsMessage::Message { content, parent_id, event, attachments, client_ts } => {
                         info!("Received message from socket {}: {}", socket_id_clone, content);
-                        // Get user info
-                        let connections = state_clone.connections.read().await;
-                        if let Some(users) = connections.rooms.get(&location_id_clone) {
-                            if let Some(user) = users.get(&socket_id_clone) {
-                                info!("Found user {} in room {}", user.username, location_id_clone);
-                                let message = Message {
-                                    id: None,
-                                    room_id: location_id_clone.clone(),
-                                    user_id: user.id.clone(),
-                                    username: user.username.clone(),
-                                    content,
-                                    timestamp: chrono::Utc::now(),
-                                    edited_at: None,
-                                    deleted: false,
-                                    reactions: vec![],
-                                };
+                        if unverified_write(&tx, verified) {
+                            continue;
+                        }
+                        // Root of the message's trace: persist, publish and fan-out hang off it
+                        let message_span = info_span!(parent: None, "message", room_id = %location_id_clone, socket_id = %socket_id_clone);
+                        // Copy the user out; no lock is held while the message is saved
+                        let user = state_clone.connections.read().await.get_user(&location_id_clone, &socket_id_clone);
+                        let Some(user) = user else {
+                            error!("User {} not found in room {}", socket_id_clone, location_id_clone);
+                            continue;
+                        };
+                        info!("Found user {} in room {}", user.username, location_id_clone);
+                        if let Some(until) = crate::timeouts::muted_until(&state_clone.redis_pool, &location_id_clone, &user.id).await {
+                            let _ = tx.send(WsMessage::Muted { until });
+                            continue;
+                        }
+                        let limited = match check_rate_limit(&state_clone.redis_pool, &user.id).await {
+                            Ok(()) => check_room_rate_limit(&state_clone.redis_pool, &location_id_clone, &user.id, room_settings.rate_limit).await,
+                            Err(retry_after) => Err(retry_after),
+                        };
+                        if let Err(retry_after) = limited {
+                            let _ = tx.send(WsMessage::RateLimited { retry_after });
+                            continue;
+                        }
+                        let mut message = Message::new(
+                            location_id_clone.clone(),
+                            user.id.clone(),
+                            user.username.clone(),
+                            content,
+                        );
+                        if let Some(parent_id) = parent_id {
+                            match crate::threads::resolve_parent(&state_clone, &location_id_clone, &parent_id).await {
+                                Ok(thread_id) => message.parent_id = Some(thread_id),
+                                Err(e) => {
+                                    let _ = tx.send(WsMessage::Error { message: e.to_string() });
+                                    continue;
+                                }
+                            }
+                        }
+                        message.client_ts = client_ts;
+                        if let Err(e) = crate::uploads::check_attachments(state_clone.uploads.as_deref(), &attachments, &user.id) {
+                            let _ = tx.send(WsMessage::Error { message: e });
+                            continue;
+                        }
+                        message.attachments = attachments;
+                        if let Err(e) = crate::rsvp::apply_event(&mut message, event) {
+                            let _ = tx.send(WsMessage::Error { message: e.to_string() });
+                            continue;
+                        }
+                        if let Err(action) = room_settings.permissions.check(&message, is_moderator) {
+                            let _ = tx.send(WsMessage::Error { message: action.denied_reason().to_string() });
+                            continue;
+                        }
+                        if mentions_everyone(&message.content) {
+                            if let Err(retry_after) = claim_room_mention(&state_clone.redis_pool, &message.room_id, &user.id).await {
+                                let _ = tx.send(WsMessage::RateLimited { retry_after });
+                                continue;
+                            }
+                            message.mentions_everyone = true;
+                        }
+                        let verdict = match crate::message_filters::screen(&mut message, &room_settings) {
+                            Ok(verdict) => verdict,
+                            Err(reason) => {
+                                record_signal(&state_clone.redis_pool, &user.id, AbuseSignal::FilterHit).await;
+                                let _ = tx.send(WsMessage::Error { message: reason });
+                                continue;
+                            }
+                        };
+                        if verdict.hit() {
+                            record_signal(&state_clone.redis_pool, &user.id, AbuseSignal::FilterHit).await;
+                        }
+                        tag_message(&mut message, room_settings.language.as_deref());
+                        crate::badges::apply(&mut message, user.badge, room_settings.official_messages);
+                        crate::message_ttl::stamp(&mut message, room_settings.message_ttl_seconds);
+                        message.shadow = crate::shadow_bans::is_shadow_banned(&state_clone.redis_pool, &user.id).await;
+                        
+                        // Save to database
+                        match state_clone.db.create_message(&message).instrument(message_span.clone()).await {
+                            Ok(persisted) => {
+                                let id = persisted.id();
+                                let _ = tx.send(WsMessage::MessageAccepted { message_id: id.to_hex(), status: persisted.status() });
+                                state_clone.room_metrics.record_message(&message.room_id);
+                                if !participating {
+                                    participating = true;
+                                    crate::presence::mark_participant(&state_clone, &message.room_id, &socket_id_clone).await;
+                                }
+                                if let Some(creator) = &review_creator {
+                                    if crate::room_quota::record_post(&state_clone, &location_id_clone, creator, &user.id).await {
+                                        review_creator = None;
+                                    }
+                                }
+                                let mut saved_message = message.clone();
+                                saved_message.id = Some(id);
+                                saved_message.notification = Some(NotificationHint::for_room_message(&saved_message, &room_settings.notifications));
+                                state_clone.delivery_trace.persisted(&id);
+                                crate::event_log::append(&state_clone, crate::event_log::LogEvent::room_message(&saved_message)).await;
+                                if !verdict.flags.is_empty() {
+                                    crate::message_filters::queue_flagged(&state_clone, &saved_message, &verdict.flags).await;
+                                }
+                                spawn_room_mention_pushes(&state_clone, &saved_message);
                                 
-                                // Save to database
-                                match state_clone.db.create_message(&message).await {
-                                    Ok(id) => {
-                                        let mut saved_message = message.clone();
-                                        saved_message.id = Some(id);
-                                        
-                                        // Broadcast to all users in room
-                                        broadcast_to_room(
+                                // Cross-post into the containing hex while bridged
+                                if state_clone.room_bridge == BridgeMode::CrossPost {
+                                    if let Some(cell) = legacy_room_cell(&location_id_clone) {
+                                        broadcast_to_hex(
                                             &state_clone,
-                                            &location_id_clone,
-                                            WsMessage::NewMessage(saved_message),
+                                            &cell.to_string(),
+                                            WsMessage::NewMessage(saved_message.clone()),
                                             None,
-                                        ).await;
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to save message: {}", e);
-                                        let _ = tx.send(WsMessage::Error {
-                                            message: "Failed to send message".to_string(),
-                                        });
+                                        ).instrument(message_span.clone()).await;
                                     }
                                 }
-                            } else {
-                                error!("User {} not found in room {}", socket_id_clone, location_id_clone);
+                                
+                                // Broadcast to all users in room
+                                broadcast_to_room(
+                                    &state_clone,
+                                    &location_id_clone,
+                                    WsMessage::NewMessage(saved_message),
+                                    None,
+                                ).instrument(message_span.clone()).await;
                             }
-                        } else {
-                            error!("Room {} not found in connections", location_id_clone);
+                            Err(e) => {
+                                error!("Failed to save message: {}", e);
+                                let _ = tx.send(WsMessage::Error {
+                                    message: "Failed to send message".to_string(),
+                                });
+                            }
+                        }
+                    }
+                    
+                    WsMessage::Activity { kind, active } => {
+                        handle_activity(&state_clone, &activity_clone, &socket_id_clone, kind, active).await;
+                    }
+                    
+                    // Older clients only know Typing; it is the Typing activity
+                    WsMessage::Typing { is_typing } => {
+                        handle_activity(&state_clone, &activity_clone, &socket_id_clone, ActivityKind::Typing, is_typing).await;
+                    }
+                    
+                    WsMessage::SetFilter { filter } => {
+                        filter_clone.write().unwrap().mode = filter;
+                    }
+                    
+                    WsMessage::Resume { session_id, last_message_id } => {
+                        let session = crate::sessions::take(&state_clone.redis_pool, &session_id)
+                            .await
+                            .filter(|session| session.room_id == location_id_clone);
+                        let Some(session) = session else {
+                            let _ = tx.send(WsMessage::ResumeFailed { reason: "Session expired; join again".to_string() });
+                            continue;
+                        };
+                        let user = session.user(&socket_id_clone);
+                        let room = crate::room_cache::room(&state_clone, &location_id_clone)
+                            .await
+                            .map_err(|e| error!("Failed to load room {}: {}", location_id_clone, e))
+                            .ok();
+                        if resume_private_check_failed(&state_clone, &tx, room.as_ref(), &user, session.is_moderator).await {
+                            continue;
+                        }
+                        if let Some(room) = room {
+                            room_settings = room.settings;
+                        }
+                        if ban_check_failed(&state_clone, &tx, &location_id_clone, &user).await {
+                            continue;
+                        }
+                        if room_full(&state_clone, &tx, &location_id_clone, &user, &room_settings).await {
+                            continue;
+                        }
+                        // The session stands in for the token it was started with
+                        if protocol_violation(&tx, connection.authenticate(&user.id).and_then(|()| connection.join(&location_id_clone))) {
+                            continue;
+                        }
+                        if let Some(joined) = room_joined.take() {
+                            let _ = joined.send(());
+                        }
+                        is_moderator = session.is_moderator;
+                        verified = session.verified;
+                        
+                        tx.set_low_data(session.low_data);
+                        let user_count = register_user(&state_clone, &channel_for_join, &user, &tx, &filter_clone, &activity_clone).await;
+                        info!("User {} resumed session {} in room {} (total users: {})", user.username, session_id, location_id_clone, user_count);
+                        crate::sessions::start(&state_clone.redis_pool, &socket_id_clone, &session).await;
+                        match resume_reply(&state_clone, &location_id_clone, &socket_id_clone, &user.id, user_count, &last_message_id).await {
+                            Ok(reply) => {
+                                let _ = tx.send(reply);
+                            }
+                            Err(e) => error!("Failed to load missed messages for room {}: {}", location_id_clone, e),
+                        }
+                        
+                        broadcast_to_room(
+                            &state_clone,
+                            &location_id_clone,
+                            WsMessage::UserJoined {
+                                username: user.username,
+                                timestamp: chrono::Utc::now(),
+                            },
+                            Some(&socket_id_clone),
+                        ).await;
+                    }
+                    
+                    WsMessage::Ack { message_id } => {
+                        if let Some(user) = state_clone.connections.read().await.get_user(&location_id_clone, &socket_id_clone) {
+                            state_clone.delivery_trace.acked(&message_id, &user.id);
                         }
                     }
                     
+                    WsMessage::Rsvp { message_id, status } => {
+                        if unverified_write(&tx, verified) {
+                            continue;
+                        }
+                        let user = state_clone.connections.read().await.get_user(&location_id_clone, &socket_id_clone);
+                        let Some(user) = user else {
+                            continue;
+                        };
+                        if let Err(e) = crate::rsvp::record_rsvp(&state_clone, &message_id, Some(&location_id_clone), &user.id, &user.username, status).await {
+                            let _ = tx.send(WsMessage::Error { message: format!("RSVP failed: {}", e) });
+                        }
+                    }
+                    
+                    WsMessage::React { message_id, emoji, remove } => {
+                        if unverified_write(&tx, verified) {
+                            continue;
+                        }
+                        let user = state_clone.connections.read().await.get_user(&location_id_clone, &socket_id_clone);
+                        let Some(user) = user else {
+                            continue;
+                        };
+                        let result = crate::reactions::react(&state_clone, &location_id_clone, &user.id, &message_id, &emoji, remove).await;
+                        report_reaction(&tx, result);
+                    }
+                    
                     _ => {}
                 }
             }
         }
     });
     
-    // Wait for any task to finish
+    // Wait for any task to finish, or for the client to fall too far behind
     tokio::select! {
-        _ = (&mut send_task) => {
+        _ = overload_signal.overloaded() => {
+            send_task.abort();
             recv_task.abort();
             redis_task.abort();
         },
+        _ = (&mut send_task) => {
+            // While draining, let the receive side finish what it's saving
+            if !state.shutdown.is_draining() || tokio::time::timeout(CLOSE_GRACE, &mut recv_task).await.is_err() {
+                recv_task.abort();
+            }
+            redis_task.abort();
+        },
         _ = (&mut recv_task) => {
-            send_task.abort();
+            if tokio::time::timeout(CLOSE_GRACE, &mut send_task).await.is_err() {
+                send_task.abort();
+            }
             redis_task.abort();
         },
         _ = (&mut redis_task) => {
@@ -270,14 +1017,26 @@ pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppSta
             recv_task.abort();
         }
     }
+    activity_task.abort();
+    state.canary.disconnect(&socket_id, cohort);
+    clear_activity(&state, &activity, &socket_id).await;
+    state.fanout.unsubscribe(&channel_name, &socket_id);
     
     // Clean up on disconnect
+    crate::presence::leave(&state, &location_id, &socket_id).await;
+    crate::user_presence::disconnect(&state, &socket_id).await;
     let mut connections = state.connections.write().await;
     if let Some(user) = connections.remove_user(&location_id, &socket_id) {
-        let user_count = connections.get_user_count(&location_id);
+        let local_count = connections.get_user_count(&location_id);
         drop(connections);
+        let user_count = crate::presence::room_count(&state, &location_id, local_count).await;
+        state.fanout.unsubscribe(&user_channel(&user.id), &socket_id);
+        let retention = crate::shared_rooms::load_privacy(&state, &user.id).await.retention_seconds();
+        crate::sessions::end(&state.redis_pool, &socket_id, retention).await;
+        crate::read_cursors::advance_cursor(&state, &location_id, &user.id).await;
         
         // Update room activity
+        state.room_metrics.set_active_users(&location_id, local_count);
         let _ = state.db.update_room_activity(&location_id, user_count as i32).await;
         
         // Notify others
@@ -293,151 +1052,557 @@ pub async fn handle_socket(socket: WebSocket, location_id: String, state: AppSta
     }
 }
 
+// Messages a resumed socket can catch up on before it gets a fresh page instead
+const MAX_RESUME_MESSAGES: i64 = 200;
+
+// Adds an admitted socket's user to its room, after a Join or a Resume.
+// Returns the room's user count across all instances.
+async fn register_user(
+    state: &AppState,
+    channel: &str,
+    user: &User,
+    tx: &SocketSender,
+    filter: &std::sync::RwLock<SubscriptionFilter>,
+    activity: &Mutex<SocketActivity>,
+) -> usize {
+    tx.set_user(&user.id);
+    crate::user_presence::connect(state, &user.socket_id, &user.id).await;
+    let mut connections = state.connections.write().await;
+    connections.add_user(user.location_id.clone(), user.socket_id.clone(), user.clone());
+    let local_count = connections.get_user_count(&user.location_id);
+    drop(connections);
+    let user_count = crate::presence::room_count(state, &user.location_id, local_count).await;
+    subscribe_user_channel(state, &user.id, &user.socket_id, tx);
+    let muted = crate::preferences::is_muted(state, &user.location_id, &user.id).await;
+    {
+        let mut filter = filter.write().unwrap();
+        filter.username = Some(user.username.clone());
+        filter.muted = muted;
+    }
+    crate::read_cursors::advance_cursor(state, &user.location_id, &user.id).await;
+    activity.lock().await.target = Some((channel.to_string(), user.clone()));
+    
+    // Update room activity
+    state.room_metrics.record_join(&user.location_id, local_count);
+    crate::room_cache::touch(state, &user.location_id).await;
+    if let Err(e) = state.db.update_room_activity(&user.location_id, user_count as i32).await {
+        error!("Failed to update room activity: {}", e);
+    }
+    user_count
+}
+
+// Messages after the client's last seen one. When too many were missed the
+// latest page is sent as a replacement, as for a fresh join.
+async fn resume_reply(
+    state: &AppState,
+    room_id: &str,
+    session_id: &str,
+    viewer: &str,
+    user_count: usize,
+    last_message_id: &str,
+) -> mongodb::error::Result<WsMessage> {
+    let missed = match mongodb::bson::oid::ObjectId::parse_str(last_message_id) {
+        Ok(after) => Some(state.db.get_messages_after(room_id, &after, MAX_RESUME_MESSAGES, Some(viewer)).await?),
+        Err(_) => None,
+    };
+    let (messages, complete) = match missed {
+        Some(messages) if (messages.len() as i64) < MAX_RESUME_MESSAGES => (messages, true),
+        _ => (crate::room_cache::latest_page_for(state, room_id, viewer).await?, false),
+    };
+    info!("Resuming socket {} in room {} with {} messages", session_id, room_id, messages.len());
+    Ok(WsMessage::Resumed {
+        session_id: session_id.to_string(),
+        user_count: user_count as i32,
+        messages,
+        complete,
+    })
+}
+
+// Only messages newer than the client's cache when it reports one. If more
+// than the largest page arrived since, the gap can't be filled, so the
+// latest page is sent as a full replacement instead.
+async fn load_history(state: &AppState, room_id: &str, viewer: &str, have_until: Option<chrono::DateTime<chrono::Utc>>) -> mongodb::error::Result<WsMessage> {
+    if let Some(since) = have_until {
+        let limit = state.history.max_page as i64;
+        let messages = state.db.get_messages_since(room_id, since, limit, Some(viewer)).await?;
+        if (messages.len() as i64) < limit {
+            info!("Sending {} messages newer than the client's cache for room {}", messages.len(), room_id);
+            return Ok(WsMessage::MessageHistory { messages, since: Some(since) });
+        }
+    }
+    let messages = crate::room_cache::latest_page_for(state, room_id, viewer).await?;
+    info!("Sending {} messages in history for room {}", messages.len(), room_id);
+    Ok(WsMessage::MessageHistory { messages, since: None })
+}
+
+/// Publishes a message saved outside a socket (e.g. by a webhook) to
+/// everyone in its room or hex.
+pub(crate) async fn broadcast_new_message(state: &AppState, mut message: Message) {
+    let room_id = message.room_id.clone();
+    let settings = match state.db.get_room(&room_id).await {
+        Ok(room) => room.map(|room| room.settings.notifications).unwrap_or_default(),
+        Err(e) => {
+            error!("Failed to load notification settings of room {}: {}", room_id, e);
+            Default::default()
+        }
+    };
+    message.notification = Some(NotificationHint::for_room_message(&message, &settings));
+    let message = WsMessage::NewMessage(message);
+    if let Some(author) = shadow_banned_author(state, &message).await {
+        send_to_user(state, &author, crate::shadow_bans::unmark_broadcast(message)).await;
+        return;
+    }
+    publish_to_room(state, &room_id, message).await;
+}
+
+/// Publishes to a room by id, whether it is a hex or a location room.
+pub(crate) async fn publish_to_room(state: &AppState, room_id: &str, message: WsMessage) {
+    let channel = if room_id.parse::<h3o::CellIndex>().is_ok() {
+        format!("hex:{}", room_id)
+    } else {
+        format!("room:{}", room_id)
+    };
+    publish_to_channel(state, &channel, message, None).await;
+}
+
+#[tracing::instrument(skip_all, fields(room_id = %location_id))]
 async fn broadcast_to_room(
     state: &AppState,
     location_id: &str,
     message: WsMessage,
     exclude_socket: Option<&str>,
 ) {
-    let channel = format!("room:{}", location_id);
-    let broadcast_msg = BroadcastMessage {
-        from_socket_id: exclude_socket.unwrap_or("").to_string(),
-        message,
+    if let Some(author) = shadow_banned_author(state, &message).await {
+        send_to_user(state, &author, crate::shadow_bans::unmark_broadcast(message)).await;
+        return;
+    }
+    publish_to_channel(state, &format!("room:{}", location_id), message, exclude_socket).await;
+}
+
+// A shadow-banned author's new messages only go to their own sockets, so
+// they look sent
+async fn shadow_banned_author(state: &AppState, message: &WsMessage) -> Option<String> {
+    let author = crate::shadow_bans::author_of(message)?;
+    crate::shadow_bans::is_shadow_banned(&state.redis_pool, author).await.then(|| author.to_string())
+}
+
+// Id of a new chat message, the only broadcasts that are delivery traced
+fn new_message_id(message: &WsMessage) -> Option<String> {
+    match message {
+        WsMessage::NewMessage(message) => message.id.map(|id| id.to_hex()),
+        _ => None,
+    }
+}
+
+#[tracing::instrument(name = "publish", skip_all, fields(channel = %channel))]
+async fn publish_to_channel(
+    state: &AppState,
+    channel: &str,
+    message: WsMessage,
+    exclude_socket: Option<&str>,
+) {
+    crate::room_cache::on_broadcast(state, channel, &message).await;
+    let mut broadcast_msg = BroadcastMessage {
+        from_socket_id: exclude_socket.unwrap_or("").to_string(),
+        origin: state.instance_id.clone(),
+        replayed: false,
+        published_at_ms: chrono::Utc::now().timestamp_millis(),
+        trace_context: crate::telemetry::current_context(),
+        message,
+    };
+    
+    let Ok(payload) = serde_json::to_string(&broadcast_msg) else {
+        error!("Failed to serialize broadcast message");
+        return;
+    };
+    
+    // Canary sockets publish through the backend being rolled out
+    let cohort = state.canary.cohort_of(exclude_socket);
+    let backend = state.canary.config.broadcast_backend(cohort, state.broadcast_backend);
+    
+    // Publishes queue behind any pending replay so other instances see them in order
+    let result = if state.fanout.replay_queue_len() > 0 {
+        Err(None)
+    } else {
+        let started = std::time::Instant::now();
+        let published = match state.redis.get_async_connection().await {
+            Ok(mut conn) => backend.publish(&mut conn, channel, &payload).await.map_err(Some),
+            Err(e) => Err(Some(e)),
+        };
+        crate::service_metrics::redis_publish(started.elapsed());
+        published
+    };
+    state.canary.record_publish(cohort, result.is_ok());
+    
+    match result {
+        Ok(()) => {
+            state.fanout.mark_redis_up();
+            info!("Published message to Redis channel: {} ({:?})", channel, backend);
+            if let Some(message_id) = new_message_id(&broadcast_msg.message) {
+                state.delivery_trace.published(&message_id);
+            }
+        }
+        Err(e) => {
+            if let Some(e) = e {
+                error!("Failed to publish to Redis channel {}: {}", channel, e);
+                state.fanout.mark_redis_down();
+            }
+            
+            // Reach this instance's sockets directly, other instances on replay
+            let mut delivered = 0;
+            let mut traced = 0;
+            for (socket_id, tx) in state.fanout.local_subscribers(channel) {
+                if socket_id == broadcast_msg.from_socket_id {
+                    continue;
+                }
+                if let Ok(local) = serde_json::from_str::<BroadcastMessage>(&payload) {
+                    if tx.is_removed_by(&local.message) {
+                        state.fanout.unsubscribe(channel, &socket_id);
+                    }
+                    match tx.deliver(local.message) {
+                        Delivery::Sent => {
+                            delivered += 1;
+                            traced += 1;
+                        }
+                        Delivery::Filtered => delivered += 1,
+                        Delivery::Closed => {}
+                    }
+                }
+            }
+            state.fanout.record_local_deliveries(delivered);
+            if let Some(message_id) = new_message_id(&broadcast_msg.message) {
+                state.delivery_trace.delivered(&message_id, traced);
+            }
+            
+            broadcast_msg.replayed = true;
+            if let Ok(replay_payload) = serde_json::to_string(&broadcast_msg) {
+                state.fanout.queue_for_replay(channel, replay_payload);
+            }
+        }
+    }
+}
+
+// Composer activity for one socket, shared with its expiry task
+#[derive(Default)]
+struct SocketActivity {
+    tracker: ActivityTracker,
+    // Redis channel and user, known once the socket has joined
+    target: Option<(String, User)>,
+}
+
+fn activity_message(user: &User, kind: ActivityKind, active: bool) -> WsMessage {
+    WsMessage::UserActivity {
+        user_id: user.id.clone(),
+        username: user.username.clone(),
+        kind,
+        active,
+        expires_in_ms: ACTIVITY_TIMEOUT.as_millis() as u64,
+    }
+}
+
+async fn handle_activity(
+    state: &AppState,
+    activity: &Mutex<SocketActivity>,
+    socket_id: &str,
+    kind: ActivityKind,
+    active: bool,
+) {
+    let mut activity = activity.lock().await;
+    let Some((channel, user)) = activity.target.clone() else {
+        return;
     };
-    
-    if let Ok(payload) = serde_json::to_string(&broadcast_msg) {
-        match state.redis.get_async_connection().await {
-            Ok(mut conn) => {
-                match conn.publish::<_, _, ()>(&channel, &payload).await {
-                    Ok(_) => {
-                        info!("Published message to Redis channel: {}", channel);
-                    }
-                    Err(e) => {
-                        error!("Failed to publish to Redis channel {}: {}", channel, e);
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to get Redis connection for broadcasting: {}", e);
+    if let Some(active) = activity.tracker.update(kind, active, Instant::now()) {
+        drop(activity);
+        publish_to_channel(state, &channel, activity_message(&user, kind, active), Some(socket_id)).await;
+    }
+}
+
+// Broadcasts "stopped" for activities the client stopped refreshing
+fn spawn_activity_expiry(
+    state: AppState,
+    activity: Arc<Mutex<SocketActivity>>,
+    socket_id: String,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(ACTIVITY_TICK);
+        loop {
+            tick.tick().await;
+            let mut activity = activity.lock().await;
+            let Some((channel, user)) = activity.target.clone() else {
+                continue;
+            };
+            let expired = activity.tracker.expire(Instant::now());
+            drop(activity);
+            for kind in expired {
+                publish_to_channel(&state, &channel, activity_message(&user, kind, false), Some(&socket_id)).await;
             }
         }
-    } else {
-        error!("Failed to serialize broadcast message");
+    })
+}
+
+// Clears any activity still shown for a closing socket
+async fn clear_activity(state: &AppState, activity: &Mutex<SocketActivity>, socket_id: &str) {
+    let mut activity = activity.lock().await;
+    let Some((channel, user)) = activity.target.take() else {
+        return;
+    };
+    let active = activity.tracker.drain();
+    drop(activity);
+    for kind in active {
+        publish_to_channel(state, &channel, activity_message(&user, kind, false), Some(socket_id)).await;
     }
 }
 
-pub async fn handle_hex_socket(socket: WebSocket, h3_index: String, state: AppState) {
-    let (mut sender, mut receiver) = socket.split();
+pub async fn handle_hex_socket(socket: WebSocket, h3_index: Option<String>, state: AppState, info: ConnectionInfo) {
+    let (sender, mut receiver) = socket.split();
     let socket_id = Uuid::new_v4().to_string();
+    let cohort = info.cohort;
+    state.canary.connect(&socket_id, cohort);
     
-    // Channel for sending messages to this client
-    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<WsMessage>();
+    // Bounded channel for sending messages to this client
+    let (tx, rx) = state.send_buffers.channel();
+    let overload_signal = tx.clone();
+    
+    // The hex is only known once JoinHex is resolved when the client connects
+    // without an index in the path, so the subscriber waits for it.
+    // Resolution changes send the next hex the same way.
+    let (hex_tx, mut hex_rx) = tokio::sync::mpsc::unbounded_channel::<(String, Vec<String>)>();
+    let joined_hex: Arc<RwLock<Option<String>>> = Arc::new(RwLock::new(None));
+    // Channels the socket listens to: its hex's, then its neighbours'
+    let hex_channels: Arc<std::sync::Mutex<Vec<String>>> = Arc::default();
+    let hex_channels_for_redis = hex_channels.clone();
     
     // Clone necessary data for tasks
     let socket_id_clone = socket_id.clone();
-    let h3_index_clone = h3_index.clone();
     let state_clone = state.clone();
     let tx_clone = tx.clone();
     let socket_id_for_redis = socket_id.clone();
+    let joined_hex_clone = joined_hex.clone();
+    let activity = Arc::new(Mutex::new(SocketActivity::default()));
+    let activity_clone = activity.clone();
+    let activity_task = spawn_activity_expiry(state.clone(), activity.clone(), socket_id.clone());
     
-    // Create Redis pub/sub connection for this client
-    let redis_client = state.redis.clone();
-    let channel_name = format!("hex:{}", h3_index);
+    let filter = Arc::new(std::sync::RwLock::new(SubscriptionFilter::new(info.filter)));
+    let filter_clone = filter.clone();
     
-    // Spawn task to handle Redis pub/sub messages
+    // Subscribe once the hex is known, locally and through Redis
+    let state_for_redis = state.clone();
     let mut redis_task = tokio::spawn(async move {
-        let mut pubsub: PubSub = match redis_client.get_async_connection().await {
-            Ok(conn) => conn.into_pubsub(),
-            Err(e) => {
-                error!("Failed to create Redis pub/sub connection: {}", e);
-                return;
-            }
-        };
-        
-        // Subscribe to hex channel
-        if let Err(e) = pubsub.subscribe(&channel_name).await {
-            error!("Failed to subscribe to channel {}: {}", channel_name, e);
+        let Some(mut joined) = hex_rx.recv().await else {
             return;
-        }
-        
-        info!("Socket {} subscribed to Redis channel: {}", socket_id_for_redis, channel_name);
-        
-        // Listen for messages
-        let mut pubsub_stream = pubsub.on_message();
-        while let Some(msg) = pubsub_stream.next().await {
-            match msg.get_payload::<String>() {
-                Ok(payload) => {
-                    if let Ok(broadcast_msg) = serde_json::from_str::<BroadcastMessage>(&payload) {
-                        // Skip messages from the same socket
-                        if broadcast_msg.from_socket_id != socket_id_for_redis {
-                            let _ = tx_clone.send(broadcast_msg.message);
-                        }
+        };
+        loop {
+            let (h3_index, neighbors) = joined;
+            let mut channels = vec![(format!("hex:{}", h3_index), Subscriber::filtered(tx_clone.clone(), filter.clone()))];
+            for neighbor in neighbors {
+                channels.push((format!("hex:{}", neighbor), Subscriber::neighbor(tx_clone.clone(), filter.clone(), neighbor)));
+            }
+            *hex_channels_for_redis.lock().unwrap() = channels.iter().map(|(channel, _)| channel.clone()).collect();
+            
+            // The socket closes once any of its channels stops forwarding
+            let forwards = channels.into_iter().map(|(channel, subscriber)| {
+                state_for_redis.fanout.subscribe(&channel, &socket_id_for_redis, subscriber.clone());
+                Box::pin(forward_channel(state_for_redis.clone(), channel, socket_id_for_redis.clone(), subscriber))
+            });
+            tokio::select! {
+                _ = futures::future::select_all(forwards) => return,
+                next = hex_rx.recv() => {
+                    let Some(next) = next else {
+                        return;
+                    };
+                    for channel in hex_channels_for_redis.lock().unwrap().drain(..) {
+                        state_for_redis.fanout.unsubscribe(&channel, &socket_id_for_redis);
                     }
+                    joined = next;
                 }
-                Err(e) => error!("Failed to parse Redis message: {}", e),
             }
         }
     });
     
-    // Spawn task to forward messages to client
-    let mut send_task = tokio::spawn(async move {
-        while let Some(msg) = rx.recv().await {
-            if let Ok(json) = serde_json::to_string(&msg) {
-                if sender.send(WsMsg::Text(json)).await.is_err() {
-                    break;
-                }
-            }
-        }
-    });
+    // Spawn task to forward messages to client, pinging it while it's quiet
+    let liveness = Liveness::new();
+    let (close_tx, close_rx) = tokio::sync::oneshot::channel();
+    let mut send_task = tokio::spawn(send_loop(sender, rx, state.heartbeat, liveness.clone(), info.format, (state.canary.clone(), info.cohort), (close_rx, state.shutdown.notice(&socket_id))));
     
     // Handle incoming messages
     let mut recv_task = tokio::spawn(async move {
-        while let Some(Ok(WsMsg::Text(text))) = receiver.next().await {
-            if let Ok(msg) = serde_json::from_str::<WsMessage>(&text) {
+        let mut room_settings = RoomSettings::default();
+        // Kept from JoinHex for resolution changes
+        let mut joined_as: Option<AuthUser> = None;
+        let mut last_fix: Option<h3o::LatLng> = None;
+        let mut neighbor_rings = 0;
+        // Moderators bypass the room's permissions
+        let mut is_moderator = false;
+        // Whether the join proved the user id; see `unverified_write`
+        let mut verified = false;
+        let mut connection = ConnectionState::default();
+        // Spectators until their first post
+        let mut participating = false;
+        let mut abuse = SocketAbuse::new(info.ip);
+        
+        while let Some(Ok(frame)) = receiver.next().await {
+            liveness.touch();
+            if let WsMsg::Close(_) = frame {
+                break;
+            }
+            if let Err(violation) = state_clone.frame_limits.check(&frame) {
+                warn!("Closing socket {} after an invalid frame: {}", socket_id_clone, violation);
+                crate::service_metrics::socket_violation(SocketViolation::OversizedFrame.as_str());
+                let _ = close_tx.send(violation.close_frame());
+                break;
+            }
+            let decoded = WireFormat::decode(&frame);
+            if decoded.is_none() && matches!(frame, WsMsg::Text(_) | WsMsg::Binary(_)) {
+                if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::MalformedFrame).await {
+                    let _ = close_tx.send(close);
+                    break;
+                }
+            }
+            if let Some(msg) = decoded {
+                crate::service_metrics::message_received();
+                if protocol_violation(&tx, connection.admit(&msg)) {
+                    if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::ProtocolError).await {
+                        let _ = close_tx.send(close);
+                        break;
+                    }
+                    continue;
+                }
                 match msg {
-                    WsMessage::JoinHex { h3_index: incoming_h3_index, user_info } => {
-                        info!("User {} joining hex {}", user_info.username, incoming_h3_index);
+                    WsMessage::JoinHex { h3_index: incoming_h3_index, user_info, token, location, have_until, include_neighbors, low_data } => {
+                        let claimed_location = location
+                            .as_ref()
+                            .and_then(|fix| crate::hex::lat_lng(fix.latitude, fix.longitude).ok());
+                        // Resolve the target hex from the explicit index or the GPS fix
+                        let resolved = match (incoming_h3_index, location) {
+                            (Some(index), _) => crate::hex::parse_cell(&index),
+                            (None, Some(fix)) => crate::hex::cell_for_fix(&fix),
+                            (None, None) => {
+                                let _ = tx.send(WsMessage::Error {
+                                    message: "JoinHex requires h3_index or location".to_string(),
+                                });
+                                continue;
+                            }
+                        };
+                        let cell = match resolved {
+                            Ok(cell) => cell,
+                            Err(e) => {
+                                let _ = tx.send(WsMessage::Error { message: e.to_string() });
+                                continue;
+                            }
+                        };
+                        let resolved_h3_index = cell.to_string();
                         
-                        // Verify the h3_index matches
-                        if incoming_h3_index != h3_index_clone {
-                            error!("H3 index mismatch: {} != {}", incoming_h3_index, h3_index_clone);
-                            let _ = tx.send(WsMessage::Error {
-                                message: "H3 index mismatch".to_string(),
-                            });
-                            continue;
+                        info!("User {} joining hex {}", user_info.username, resolved_h3_index);
+                        
+                        // Verify the h3_index matches the one in the URL, if any
+                        if let Some(path_h3_index) = &h3_index {
+                            if &resolved_h3_index != path_h3_index {
+                                error!("H3 index mismatch: {} != {}", resolved_h3_index, path_h3_index);
+                                let _ = tx.send(WsMessage::Error {
+                                    message: "H3 index mismatch".to_string(),
+                                });
+                                continue;
+                            }
                         }
                         
-                        // Add user to hex room
-                        let user = User {
+                        // Without a GPS fix the hex centre stands in for the claimed location
+                        let location_flagged = claimed_location
+                            .or_else(|| Some(h3o::LatLng::from(cell)))
+                            .is_some_and(|claimed| state_clone.ip_geo.is_mismatch(claimed, info.ip_location));
+                        if location_flagged {
+                            warn!("User {} claims hex {} far from their IP location", user_info.user_id, resolved_h3_index);
+                        }
+                        
+                        let mut user = User {
                             id: user_info.user_id.clone(),
                             username: user_info.username.clone(),
                             socket_id: socket_id_clone.clone(),
-                            location_id: h3_index_clone.clone(), // Use h3_index as location_id for hex rooms
+                            location_id: resolved_h3_index.clone(), // Use h3_index as location_id for hex rooms
+                            location_flagged,
+                            joined_at: chrono::Utc::now(),
+                            badge: None,
                         };
                         
-                        let mut connections = state_clone.connections.write().await;
-                        connections.add_user(h3_index_clone.clone(), socket_id_clone.clone(), user.clone());
-                        let user_count = connections.get_user_count(&h3_index_clone);
-                        info!("User {} joined hex {} (total users: {})", user_info.username, h3_index_clone, user_count);
-                        drop(connections);
-                        
-                        // Update room activity
-                        if let Err(e) = state_clone.db.update_room_activity(&h3_index_clone, user_count as i32).await {
-                            error!("Failed to update room activity: {}", e);
+                        let Ok(caller) = join_caller(&tx, &info, &resolved_h3_index, token.as_deref()) else {
+                            if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::AuthFailure).await {
+                                let _ = close_tx.send(close);
+                                break;
+                            }
+                            continue;
+                        };
+                        let impersonating = identity_check_failed(&state_clone, &tx, &user, caller.as_ref()).await;
+                        if impersonating || rejected_token(caller.as_ref(), token.as_deref()) {
+                            if let Some(close) = penalize(&state_clone, &mut abuse, SocketViolation::AuthFailure).await {
+                                let _ = close_tx.send(close);
+                                break;
+                            }
+                            if impersonating {
+                                continue;
+                            }
+                        }
+                        user.badge = crate::badges::badge_for(&state_clone, caller.as_ref(), &user.id).await;
+                        // A hex nobody has joined yet is created on the caller's quota
+                        let room = match crate::room_quota::room_for(&state_clone, &resolved_h3_index, caller.as_ref()).await {
+                            Ok(room) => room,
+                            // Without the room its private, rating and location checks can't run
+                            Err(e) => {
+                                warn!("Refusing join to hex room {}: {}", resolved_h3_index, e);
+                                let _ = tx.send(WsMessage::Error { message: room_refusal(&e) });
+                                continue;
+                            }
+                        };
+                        let room_role = crate::room_roles::role_in(&room, &user.id);
+                        let room_visibility = room.visibility;
+                        room_settings = room.settings;
+                        if location_check_failed(&state_clone, &tx, &room_settings, &user, caller.as_ref()).await {
+                            continue;
+                        }
+                        if ban_check_failed(&state_clone, &tx, &resolved_h3_index, &user).await {
+                            continue;
+                        }
+                        if protocol_violation(&tx, connection.authenticate(&user.id)) {
+                            continue;
+                        }
+                        if age_check_failed(&tx, &room_settings, &user, caller.as_ref()) {
+                            continue;
                         }
+                        is_moderator = caller_is_moderator(caller.as_ref(), &user.id, room_role);
+                        if private_check_failed(&state_clone, &tx, room_visibility, room_role, &user, caller.as_ref(), None).await {
+                            continue;
+                        }
+                        if room_full(&state_clone, &tx, &resolved_h3_index, &user, &room_settings).await {
+                            continue;
+                        }
+                        if protocol_violation(&tx, connection.join(&resolved_h3_index)) {
+                            continue;
+                        }
+                        
+                        *joined_hex_clone.write().await = Some(resolved_h3_index.clone());
+                        joined_as = caller;
+                        last_fix = claimed_location;
+                        neighbor_rings = include_neighbors.min(crate::hex::MAX_NEIGHBOR_RING);
+                        let neighbors: Vec<String> = crate::hex::neighbors(cell, neighbor_rings).iter().map(ToString::to_string).collect();
+                        let neighbors = joinable_neighbors(&state_clone, neighbors, &user.id, joined_as.as_ref()).await;
+                        let _ = hex_tx.send((resolved_h3_index.clone(), neighbors.clone()));
+                        let h3_index_clone = resolved_h3_index;
+                        
+                        // Add user to hex room
+                        tx.set_low_data(low_data);
+                        let user_count = register_user(&state_clone, &format!("hex:{}", h3_index_clone), &user, &tx, &filter_clone, &activity_clone).await;
+                        info!("User {} joined hex {} (total users: {})", user_info.username, h3_index_clone, user_count);
+                        verified = caller_is_user(joined_as.as_ref(), &user.id);
+                        let session = SocketSession { include_neighbors: neighbor_rings, low_data, verified, ..SocketSession::new(&user, is_moderator) };
+                        crate::sessions::start(&state_clone.redis_pool, &socket_id_clone, &session).await;
+                        let _ = tx.send(WsMessage::SessionStarted { session_id: socket_id_clone.clone() });
                         
                         // Send hex join confirmation
-                        let _ = tx.send(WsMessage::HexJoined {
-                            h3_index: h3_index_clone.clone(),
-                            user_count: user_count as i32,
-                        });
+                        let _ = tx.send(hex_joined(cell, neighbors, user_count, room_settings.permissions));
                         
                         // Send message history
                         info!("Fetching hex message history for room: {}", h3_index_clone);
-                        match state_clone.db.get_messages(&h3_index_clone, 50, None).await {
-                            Ok(messages) => {
-                                info!("Sending {} hex messages in history to user", messages.len());
-                                let _ = tx.send(WsMessage::MessageHistory { messages });
+                        match load_history(&state_clone, &h3_index_clone, &user.id, have_until).await {
+                            Ok(history) => {
+                                let _ = tx.send(history);
                             },
                             Err(e) => {
                                 error!("Failed to get hex message history: {}", e);
@@ -456,52 +1621,350 @@ pub async fn handle_hex_socket(socket: WebSocket, h3_index: String, state: AppSt
                         ).await;
                     }
                     
-                    WsMessage::Message { content } => {
+                    WsMessage::Message { content, parent_id, event, attachments, client_ts } => {
                         info!("Received hex message from socket {}: {}", socket_id_clone, content);
-                        // Get user info
-                        let connections = state_clone.connections.read().await;
-                        if let Some(users) = connections.rooms.get(&h3_index_clone) {
-                            if let Some(user) = users.get(&socket_id_clone) {
-                                info!("Found user {} in hex {}", user.username, h3_index_clone);
-                                let message = Message {
-                                    id: None,
-                                    room_id: h3_index_clone.clone(),
-                                    user_id: user.id.clone(),
-                                    username: user.username.clone(),
-                                    content,
-                                    timestamp: chrono::Utc::now(),
-                                    edited_at: None,
-                                    deleted: false,
-                                    reactions: vec![],
-                                };
+                        let Some(h3_index_clone) = connection.room_id().map(str::to_string) else {
+                            continue;
+                        };
+                        if unverified_write(&tx, verified) {
+                            continue;
+                        }
+                        // Root of the message's trace: persist, publish and fan-out hang off it
+                        let message_span = info_span!(parent: None, "message", room_id = %h3_index_clone, socket_id = %socket_id_clone);
+                        // Copy the user out; no lock is held while the message is saved
+                        let user = state_clone.connections.read().await.get_user(&h3_index_clone, &socket_id_clone);
+                        let Some(user) = user else {
+                            error!("User {} not found in hex {}", socket_id_clone, h3_index_clone);
+                            continue;
+                        };
+                        info!("Found user {} in hex {}", user.username, h3_index_clone);
+                        if let Some(until) = crate::timeouts::muted_until(&state_clone.redis_pool, &h3_index_clone, &user.id).await {
+                            let _ = tx.send(WsMessage::Muted { until });
+                            continue;
+                        }
+                        let limited = match check_rate_limit(&state_clone.redis_pool, &user.id).await {
+                            Ok(()) => check_room_rate_limit(&state_clone.redis_pool, &h3_index_clone, &user.id, room_settings.rate_limit).await,
+                            Err(retry_after) => Err(retry_after),
+                        };
+                        if let Err(retry_after) = limited {
+                            let _ = tx.send(WsMessage::RateLimited { retry_after });
+                            continue;
+                        }
+                        let mut message = Message::new(
+                            h3_index_clone.clone(),
+                            user.id.clone(),
+                            user.username.clone(),
+                            content,
+                        );
+                        if let Some(parent_id) = parent_id {
+                            match crate::threads::resolve_parent(&state_clone, &h3_index_clone, &parent_id).await {
+                                Ok(thread_id) => message.parent_id = Some(thread_id),
+                                Err(e) => {
+                                    let _ = tx.send(WsMessage::Error { message: e.to_string() });
+                                    continue;
+                                }
+                            }
+                        }
+                        message.client_ts = client_ts;
+                        if let Err(e) = crate::uploads::check_attachments(state_clone.uploads.as_deref(), &attachments, &user.id) {
+                            let _ = tx.send(WsMessage::Error { message: e });
+                            continue;
+                        }
+                        message.attachments = attachments;
+                        if let Err(e) = crate::rsvp::apply_event(&mut message, event) {
+                            let _ = tx.send(WsMessage::Error { message: e.to_string() });
+                            continue;
+                        }
+                        if let Err(action) = room_settings.permissions.check(&message, is_moderator) {
+                            let _ = tx.send(WsMessage::Error { message: action.denied_reason().to_string() });
+                            continue;
+                        }
+                        if mentions_everyone(&message.content) {
+                            if let Err(retry_after) = claim_room_mention(&state_clone.redis_pool, &message.room_id, &user.id).await {
+                                let _ = tx.send(WsMessage::RateLimited { retry_after });
+                                continue;
+                            }
+                            message.mentions_everyone = true;
+                        }
+                        let verdict = match crate::message_filters::screen(&mut message, &room_settings) {
+                            Ok(verdict) => verdict,
+                            Err(reason) => {
+                                record_signal(&state_clone.redis_pool, &user.id, AbuseSignal::FilterHit).await;
+                                let _ = tx.send(WsMessage::Error { message: reason });
+                                continue;
+                            }
+                        };
+                        if verdict.hit() {
+                            record_signal(&state_clone.redis_pool, &user.id, AbuseSignal::FilterHit).await;
+                        }
+                        tag_message(&mut message, room_settings.language.as_deref());
+                        crate::badges::apply(&mut message, user.badge, room_settings.official_messages);
+                        crate::message_ttl::stamp(&mut message, room_settings.message_ttl_seconds);
+                        message.shadow = crate::shadow_bans::is_shadow_banned(&state_clone.redis_pool, &user.id).await;
+                        
+                        // Save to database
+                        match state_clone.db.create_message(&message).instrument(message_span.clone()).await {
+                            Ok(persisted) => {
+                                let id = persisted.id();
+                                let _ = tx.send(WsMessage::MessageAccepted { message_id: id.to_hex(), status: persisted.status() });
+                                state_clone.room_metrics.record_message(&message.room_id);
+                                if !participating {
+                                    participating = true;
+                                    crate::presence::mark_participant(&state_clone, &message.room_id, &socket_id_clone).await;
+                                }
+                                let mut saved_message = message.clone();
+                                saved_message.id = Some(id);
+                                saved_message.notification = Some(NotificationHint::for_room_message(&saved_message, &room_settings.notifications));
+                                state_clone.delivery_trace.persisted(&id);
+                                crate::event_log::append(&state_clone, crate::event_log::LogEvent::room_message(&saved_message)).await;
+                                if !verdict.flags.is_empty() {
+                                    crate::message_filters::queue_flagged(&state_clone, &saved_message, &verdict.flags).await;
+                                }
+                                spawn_room_mention_pushes(&state_clone, &saved_message);
                                 
-                                // Save to database
-                                match state_clone.db.create_message(&message).await {
-                                    Ok(id) => {
-                                        let mut saved_message = message.clone();
-                                        saved_message.id = Some(id);
-                                        
-                                        // Broadcast to all users in hex
-                                        broadcast_to_hex(
+                                // Cross-post to legacy rooms bridged into this hex
+                                if state_clone.room_bridge == BridgeMode::CrossPost {
+                                    for legacy_room in legacy_rooms_for_hex(&state_clone.redis_pool, &h3_index_clone).await {
+                                        broadcast_to_room(
                                             &state_clone,
-                                            &h3_index_clone,
-                                            WsMessage::NewMessage(saved_message),
+                                            &legacy_room,
+                                            WsMessage::NewMessage(saved_message.clone()),
                                             None,
-                                        ).await;
-                                    }
-                                    Err(e) => {
-                                        error!("Failed to save message: {}", e);
-                                        let _ = tx.send(WsMessage::Error {
-                                            message: "Failed to send message".to_string(),
-                                        });
+                                        ).instrument(message_span.clone()).await;
                                     }
                                 }
-                            } else {
-                                error!("User {} not found in hex {}", socket_id_clone, h3_index_clone);
+                                
+                                let thread = crate::threads::record_reply(&state_clone, &saved_message).instrument(message_span.clone()).await;
+                                
+                                // Broadcast to all users in hex
+                                broadcast_to_hex(
+                                    &state_clone,
+                                    &h3_index_clone,
+                                    WsMessage::NewMessage(saved_message),
+                                    None,
+                                ).instrument(message_span.clone()).await;
+                                if let Some((parent_id, reply_count, last_reply_at)) = thread {
+                                    broadcast_to_hex(
+                                        &state_clone,
+                                        &h3_index_clone,
+                                        WsMessage::ThreadUpdated { parent_id, reply_count, last_reply_at },
+                                        None,
+                                    ).instrument(message_span.clone()).await;
+                                }
+                            }
+                            Err(e) => {
+                                error!("Failed to save message: {}", e);
+                                let _ = tx.send(WsMessage::Error {
+                                    message: "Failed to send message".to_string(),
+                                });
+                            }
+                        }
+                    }
+                    
+                    WsMessage::Activity { kind, active } => {
+                        handle_activity(&state_clone, &activity_clone, &socket_id_clone, kind, active).await;
+                    }
+                    
+                    // Older clients only know Typing; it is the Typing activity
+                    WsMessage::Typing { is_typing } => {
+                        handle_activity(&state_clone, &activity_clone, &socket_id_clone, ActivityKind::Typing, is_typing).await;
+                    }
+                    
+                    WsMessage::SetFilter { filter } => {
+                        filter_clone.write().unwrap().mode = filter;
+                    }
+                    
+                    WsMessage::Resume { session_id, last_message_id } => {
+                        let session = crate::sessions::take(&state_clone.redis_pool, &session_id)
+                            .await
+                            .filter(|session| h3_index.as_ref().is_none_or(|path_h3_index| *path_h3_index == session.room_id));
+                        let Some(session) = session else {
+                            let _ = tx.send(WsMessage::ResumeFailed { reason: "Session expired; join again".to_string() });
+                            continue;
+                        };
+                        let h3_index_clone = session.room_id.clone();
+                        let user = session.user(&socket_id_clone);
+                        let room = crate::room_cache::room(&state_clone, &h3_index_clone)
+                            .await
+                            .map_err(|e| error!("Failed to load hex room {}: {}", h3_index_clone, e))
+                            .ok();
+                        if resume_private_check_failed(&state_clone, &tx, room.as_ref(), &user, session.is_moderator).await {
+                            continue;
+                        }
+                        if let Some(room) = room {
+                            room_settings = room.settings;
+                        }
+                        if ban_check_failed(&state_clone, &tx, &h3_index_clone, &user).await {
+                            continue;
+                        }
+                        if room_full(&state_clone, &tx, &h3_index_clone, &user, &room_settings).await {
+                            continue;
+                        }
+                        if protocol_violation(&tx, connection.authenticate(&user.id).and_then(|()| connection.join(&h3_index_clone))) {
+                            continue;
+                        }
+                        is_moderator = session.is_moderator;
+                        verified = session.verified;
+                        *joined_hex_clone.write().await = Some(h3_index_clone.clone());
+                        neighbor_rings = session.include_neighbors.min(crate::hex::MAX_NEIGHBOR_RING);
+                        let neighbors = h3_index_clone
+                            .parse::<h3o::CellIndex>()
+                            .map(|cell| crate::hex::neighbors(cell, neighbor_rings).iter().map(ToString::to_string).collect())
+                            .unwrap_or_default();
+                        let neighbors = joinable_neighbors(&state_clone, neighbors, &user.id, joined_as.as_ref()).await;
+                        let _ = hex_tx.send((h3_index_clone.clone(), neighbors));
+                        
+                        tx.set_low_data(session.low_data);
+                        let user_count = register_user(&state_clone, &format!("hex:{}", h3_index_clone), &user, &tx, &filter_clone, &activity_clone).await;
+                        info!("User {} resumed session {} in hex {} (total users: {})", user.username, session_id, h3_index_clone, user_count);
+                        crate::sessions::start(&state_clone.redis_pool, &socket_id_clone, &session).await;
+                        match resume_reply(&state_clone, &h3_index_clone, &socket_id_clone, &user.id, user_count, &last_message_id).await {
+                            Ok(reply) => {
+                                let _ = tx.send(reply);
+                            }
+                            Err(e) => error!("Failed to load missed messages for hex {}: {}", h3_index_clone, e),
+                        }
+                        
+                        broadcast_to_hex(
+                            &state_clone,
+                            &h3_index_clone,
+                            WsMessage::UserJoined {
+                                username: user.username,
+                                timestamp: chrono::Utc::now(),
+                            },
+                            Some(&socket_id_clone),
+                        ).await;
+                    }
+                    
+                    WsMessage::Ack { message_id } => {
+                        let Some(h3_index) = connection.room_id() else {
+                            continue;
+                        };
+                        if let Some(user) = state_clone.connections.read().await.get_user(h3_index, &socket_id_clone) {
+                            state_clone.delivery_trace.acked(&message_id, &user.id);
+                        }
+                    }
+                    
+                    WsMessage::Rsvp { message_id, status } => {
+                        let Some(h3_index_clone) = connection.room_id().map(str::to_string) else {
+                            continue;
+                        };
+                        if unverified_write(&tx, verified) {
+                            continue;
+                        }
+                        let user = state_clone.connections.read().await.get_user(&h3_index_clone, &socket_id_clone);
+                        let Some(user) = user else {
+                            continue;
+                        };
+                        if let Err(e) = crate::rsvp::record_rsvp(&state_clone, &message_id, Some(&h3_index_clone), &user.id, &user.username, status).await {
+                            let _ = tx.send(WsMessage::Error { message: format!("RSVP failed: {}", e) });
+                        }
+                    }
+                    
+                    WsMessage::React { message_id, emoji, remove } => {
+                        let Some(h3_index_clone) = connection.room_id().map(str::to_string) else {
+                            continue;
+                        };
+                        if unverified_write(&tx, verified) {
+                            continue;
+                        }
+                        let user = state_clone.connections.read().await.get_user(&h3_index_clone, &socket_id_clone);
+                        let Some(user) = user else {
+                            continue;
+                        };
+                        let result = crate::reactions::react(&state_clone, &h3_index_clone, &user.id, &message_id, &emoji, remove).await;
+                        report_reaction(&tx, result);
+                    }
+                    
+                    WsMessage::ChangeResolution { resolution } => {
+                        let Some(current) = connection.room_id().map(str::to_string) else {
+                            continue;
+                        };
+                        let target = match crate::hex::parse_cell(&current).and_then(|cell| crate::hex::zoom(cell, resolution, last_fix)) {
+                            Ok(target) => target,
+                            Err(e) => {
+                                let _ = tx.send(WsMessage::Error { message: e.to_string() });
+                                continue;
+                            }
+                        };
+                        let target_index = target.to_string();
+                        if target_index == current {
+                            let _ = tx.send(WsMessage::Error { message: format!("Already in hex {}", current) });
+                            continue;
+                        }
+                        let user = state_clone.connections.read().await.get_user(&current, &socket_id_clone);
+                        let Some(mut user) = user else {
+                            continue;
+                        };
+                        user.location_id = target_index.clone();
+                        user.joined_at = chrono::Utc::now();
+                        
+                        let target_room = match crate::room_quota::room_for(&state_clone, &target_index, joined_as.as_ref()).await {
+                            Ok(room) => room,
+                            Err(e @ (AppError::TooManyRequests { .. } | AppError::Unauthorized)) => {
+                                let _ = tx.send(WsMessage::Error { message: room_refusal(&e) });
+                                continue;
+                            }
+                            Err(e) => {
+                                error!("Failed to load hex room {}: {}", target_index, e);
+                                let _ = tx.send(WsMessage::Error { message: "Failed to change resolution".to_string() });
+                                continue;
+                            }
+                        };
+                        let target_role = crate::room_roles::role_in(&target_room, &user.id);
+                        let target_visibility = target_room.visibility;
+                        let target_settings = target_room.settings;
+                        if location_check_failed(&state_clone, &tx, &target_settings, &user, joined_as.as_ref()).await || age_check_failed(&tx, &target_settings, &user, joined_as.as_ref()) {
+                            continue;
+                        }
+                        if private_check_failed(&state_clone, &tx, target_visibility, target_role, &user, joined_as.as_ref(), None).await {
+                            continue;
+                        }
+                        if ban_check_failed(&state_clone, &tx, &target_index, &user).await {
+                            continue;
+                        }
+                        // Presence moves in one step, so the socket never counts in both hexes or neither
+                        if crate::presence::try_move(&state_clone, &current, &user, target_settings.max_users).await == crate::presence::JoinOutcome::Full {
+                            let _ = tx.send(WsMessage::RoomFull {
+                                room_id: target_index.clone(),
+                                max_users: target_settings.max_users,
+                                suggestions: crate::presence::suggest_rooms(&state_clone, &target_index, target_settings.max_users).await,
+                            });
+                            continue;
+                        }
+                        if protocol_violation(&tx, connection.move_to(&target_index)) {
+                            continue;
+                        }
+                        room_settings = target_settings;
+                        is_moderator = caller_is_moderator(joined_as.as_ref(), &user.id, target_role);
+                        clear_activity(&state_clone, &activity_clone, &socket_id_clone).await;
+                        leave_hex(&state_clone, &current, &socket_id_clone).await;
+                        
+                        *joined_hex_clone.write().await = Some(target_index.clone());
+                        let neighbors: Vec<String> = crate::hex::neighbors(target, neighbor_rings).iter().map(ToString::to_string).collect();
+                        let neighbors = joinable_neighbors(&state_clone, neighbors, &user.id, joined_as.as_ref()).await;
+                        let _ = hex_tx.send((target_index.clone(), neighbors.clone()));
+                        let user_count = register_user(&state_clone, &format!("hex:{}", target_index), &user, &tx, &filter_clone, &activity_clone).await;
+                        info!("User {} moved from hex {} to {} (total users: {})", user.username, current, target_index, user_count);
+                        let session = SocketSession { include_neighbors: neighbor_rings, low_data: tx.is_low_data(), verified, ..SocketSession::new(&user, is_moderator) };
+                        crate::sessions::start(&state_clone.redis_pool, &socket_id_clone, &session).await;
+                        
+                        let _ = tx.send(hex_joined(target, neighbors, user_count, room_settings.permissions));
+                        match load_history(&state_clone, &target_index, &user.id, None).await {
+                            Ok(history) => {
+                                let _ = tx.send(history);
                             }
-                        } else {
-                            error!("Hex {} not found in connections", h3_index_clone);
+                            Err(e) => error!("Failed to get hex message history: {}", e),
                         }
+                        broadcast_to_hex(
+                            &state_clone,
+                            &target_index,
+                            WsMessage::UserJoined {
+                                username: user.username,
+                                timestamp: chrono::Utc::now(),
+                            },
+                            Some(&socket_id_clone),
+                        ).await;
                     }
                     
                     _ => {}
@@ -510,14 +1973,24 @@ pub async fn handle_hex_socket(socket: WebSocket, h3_index: String, state: AppSt
         }
     });
     
-    // Wait for any task to finish
+    // Wait for any task to finish, or for the client to fall too far behind
     tokio::select! {
-        _ = (&mut send_task) => {
+        _ = overload_signal.overloaded() => {
+            send_task.abort();
             recv_task.abort();
             redis_task.abort();
         },
+        _ = (&mut send_task) => {
+            // While draining, let the receive side finish what it's saving
+            if !state.shutdown.is_draining() || tokio::time::timeout(CLOSE_GRACE, &mut recv_task).await.is_err() {
+                recv_task.abort();
+            }
+            redis_task.abort();
+        },
         _ = (&mut recv_task) => {
-            send_task.abort();
+            if tokio::time::timeout(CLOSE_GRACE, &mut send_task).await.is_err() {
+                send_task.abort();
+            }
             redis_task.abort();
         },
         _ = (&mut redis_task) => {
@@ -525,58 +1998,77 @@ pub async fn handle_hex_socket(socket: WebSocket, h3_index: String, state: AppSt
             recv_task.abort();
         }
     }
+    activity_task.abort();
+    state.canary.disconnect(&socket_id, cohort);
+    clear_activity(&state, &activity, &socket_id).await;
     
     // Clean up on disconnect
-    let mut connections = state.connections.write().await;
-    if let Some(user) = connections.remove_user(&h3_index, &socket_id) {
-        let user_count = connections.get_user_count(&h3_index);
-        drop(connections);
-        
-        // Update room activity
-        let _ = state.db.update_room_activity(&h3_index, user_count as i32).await;
-        
-        // Notify others
-        broadcast_to_hex(
-            &state,
-            &h3_index,
-            WsMessage::UserLeft {
-                username: user.username,
-                timestamp: chrono::Utc::now(),
-            },
-            Some(&socket_id),
-        ).await;
+    let hex_channels = std::mem::take(&mut *hex_channels.lock().unwrap());
+    for channel in hex_channels {
+        state.fanout.unsubscribe(&channel, &socket_id);
+    }
+    crate::user_presence::disconnect(&state, &socket_id).await;
+    let Some(h3_index) = joined_hex.read().await.clone() else {
+        return;
+    };
+    crate::presence::leave(&state, &h3_index, &socket_id).await;
+    if let Some(user) = leave_hex(&state, &h3_index, &socket_id).await {
+        state.fanout.unsubscribe(&user_channel(&user.id), &socket_id);
+        let retention = crate::shared_rooms::load_privacy(&state, &user.id).await.retention_seconds();
+        crate::sessions::end(&state.redis_pool, &socket_id, retention).await;
+    }
+}
+
+fn hex_joined(cell: h3o::CellIndex, neighbors: Vec<String>, user_count: usize, permissions: crate::permissions::RoomPermissions) -> WsMessage {
+    let joined = crate::hex::HexCell::from(cell);
+    WsMessage::HexJoined {
+        h3_index: joined.h3_index,
+        resolution: joined.resolution,
+        center: joined.center,
+        boundary: joined.boundary,
+        neighbors,
+        user_count: user_count as i32,
+        permissions,
     }
 }
 
+// Takes the socket out of a hex it's leaving, on disconnect or a resolution
+// change, and tells the hex. Presence is left to the caller.
+async fn leave_hex(state: &AppState, h3_index: &str, socket_id: &str) -> Option<User> {
+    state.fanout.unsubscribe(&format!("hex:{}", h3_index), socket_id);
+    let mut connections = state.connections.write().await;
+    let user = connections.remove_user(h3_index, socket_id)?;
+    let local_count = connections.get_user_count(h3_index);
+    drop(connections);
+    let user_count = crate::presence::room_count(state, h3_index, local_count).await;
+    crate::read_cursors::advance_cursor(state, h3_index, &user.id).await;
+    
+    // Update room activity
+    state.room_metrics.set_active_users(h3_index, local_count);
+    let _ = state.db.update_room_activity(h3_index, user_count as i32).await;
+    
+    // Notify others
+    broadcast_to_hex(
+        state,
+        h3_index,
+        WsMessage::UserLeft {
+            username: user.username.clone(),
+            timestamp: chrono::Utc::now(),
+        },
+        Some(socket_id),
+    ).await;
+    Some(user)
+}
+
 async fn broadcast_to_hex(
     state: &AppState,
     h3_index: &str,
     message: WsMessage,
     exclude_socket: Option<&str>,
 ) {
-    let channel = format!("hex:{}", h3_index);
-    let broadcast_msg = BroadcastMessage {
-        from_socket_id: exclude_socket.unwrap_or("").to_string(),
-        message,
-    };
-    
-    if let Ok(payload) = serde_json::to_string(&broadcast_msg) {
-        match state.redis.get_async_connection().await {
-            Ok(mut conn) => {
-                match conn.publish::<_, _, ()>(&channel, &payload).await {
-                    Ok(_) => {
-                        info!("Published message to Redis channel: {}", channel);
-                    }
-                    Err(e) => {
-                        error!("Failed to publish to Redis channel {}: {}", channel, e);
-                    }
-                }
-            }
-            Err(e) => {
-                error!("Failed to get Redis connection for broadcasting: {}", e);
-            }
-        }
-    } else {
-        error!("Failed to serialize broadcast message");
+    if let Some(author) = shadow_banned_author(state, &message).await {
+        send_to_user(state, &author, crate::shadow_bans::unmark_broadcast(message)).await;
+        return;
     }
-}
\ No newline at end of file
+    publish_to_channel(state, &format!("hex:{}", h3_index), message, exclude_socket).await;
+}
@@ -0,0 +1,77 @@
+use axum::extract::ws::Message as WsMsg;
+use axum::http::{header::SEC_WEBSOCKET_PROTOCOL, HeaderMap};
+use chrono::Utc;
+use serde::Serialize;
+use tracing::error;
+
+use crate::models::WsMessage;
+
+/// Subprotocol a client offers to get MessagePack frames.
+pub const MSGPACK_PROTOCOL: &str = "tapin.msgpack";
+
+/// An outgoing frame: the message's `type` and `data`, plus the server's
+/// clock when it was written, in epoch milliseconds.
+#[derive(Serialize)]
+struct Frame<'a> {
+    #[serde(flatten)]
+    message: &'a WsMessage,
+    server_ts: i64,
+}
+
+/// Encoding of `WsMessage` frames on a socket. JSON goes out as text frames
+/// and MessagePack, which is smaller, as binary frames. Incoming frames are
+/// decoded by their frame type, whatever the socket's format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WireFormat {
+    #[default]
+    Json,
+    MessagePack,
+}
+
+impl WireFormat {
+    /// Picks MessagePack when the client asks for it with `?encoding=msgpack`
+    /// or by offering the `tapin.msgpack` subprotocol.
+    pub fn negotiate(encoding: Option<&str>, headers: &HeaderMap) -> Self {
+        let offered = headers
+            .get_all(SEC_WEBSOCKET_PROTOCOL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|protocol| protocol.trim() == MSGPACK_PROTOCOL);
+        if offered || encoding.is_some_and(|encoding| encoding.eq_ignore_ascii_case("msgpack")) {
+            WireFormat::MessagePack
+        } else {
+            WireFormat::Json
+        }
+    }
+
+    pub fn encode(self, message: &WsMessage) -> Option<WsMsg> {
+        self.encode_at(message, Utc::now().timestamp_millis())
+    }
+
+    /// Encodes the frame stamped with `server_ts`.
+    pub fn encode_at(self, message: &WsMessage, server_ts: i64) -> Option<WsMsg> {
+        let frame = Frame { message, server_ts };
+        match self {
+            WireFormat::Json => serde_json::to_string(&frame).ok().map(WsMsg::Text),
+            // Named fields, since tagged enums can't be read back from arrays
+            WireFormat::MessagePack => match rmp_serde::to_vec_named(&frame) {
+                Ok(bytes) => Some(WsMsg::Binary(bytes)),
+                Err(e) => {
+                    error!("Failed to encode MessagePack frame: {}", e);
+                    None
+                }
+            },
+        }
+    }
+
+    /// The message in a client frame; `None` for control frames and frames
+    /// that aren't a valid `WsMessage`.
+    pub fn decode(frame: &WsMsg) -> Option<WsMessage> {
+        match frame {
+            WsMsg::Text(text) => serde_json::from_str(text).ok(),
+            WsMsg::Binary(bytes) => rmp_serde::from_slice(bytes).ok(),
+            _ => None,
+        }
+    }
+}
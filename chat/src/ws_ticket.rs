@@ -0,0 +1,167 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tracing::{error, info};
+
+use crate::{auth::AuthUser, badges::Badge, AppError, AppState};
+
+// Long enough to open the socket right after asking for the ticket
+pub const TICKET_TTL_SECONDS: i64 = 60;
+// Tells tickets apart from the auth service's JWTs, which share the secret
+const TICKET_KIND: &str = "ws_ticket";
+
+fn redeemed_key(jti: &str) -> String {
+    format!("ws_ticket:{}", jti)
+}
+
+pub(crate) fn secret() -> String {
+    std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key-here".to_string())
+}
+
+/// Lets a socket into one room as one user without putting the user's JWT
+/// in the URL. Issued over authenticated HTTP, where the room's permission
+/// checks run, and good for a single upgrade within a minute.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JoinTicket {
+    pub kind: String,
+    pub jti: String,
+    pub user_id: String,
+    pub username: String,
+    pub room_id: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub age_verified: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub badge: Option<Badge>,
+    pub exp: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum TicketError {
+    #[error("Invalid or expired join ticket")]
+    Invalid,
+    #[error("Join ticket has already been used")]
+    Used,
+    #[error("Join ticket is for another room")]
+    WrongRoom,
+}
+
+impl JoinTicket {
+    pub fn issue(user: &AuthUser, room_id: &str) -> Self {
+        JoinTicket {
+            kind: TICKET_KIND.to_string(),
+            jti: uuid::Uuid::new_v4().to_string(),
+            user_id: user.user_id.clone(),
+            username: user.username.clone(),
+            room_id: room_id.to_string(),
+            roles: user.roles.clone(),
+            age_verified: user.age_verified,
+            badge: user.badge,
+            exp: (Utc::now().timestamp() + TICKET_TTL_SECONDS) as usize,
+        }
+    }
+
+    pub fn sign(&self) -> Result<String, jsonwebtoken::errors::Error> {
+        encode(&Header::default(), self, &EncodingKey::from_secret(secret().as_bytes()))
+    }
+
+    /// Checks the signature and expiry. Redeeming is separate, see `redeem`.
+    pub fn verify(ticket: &str) -> Result<Self, TicketError> {
+        let decoded = decode::<JoinTicket>(ticket, &DecodingKey::from_secret(secret().as_bytes()), &Validation::default())
+            .map_err(|_| TicketError::Invalid)?;
+        if decoded.claims.kind != TICKET_KIND {
+            return Err(TicketError::Invalid);
+        }
+        Ok(decoded.claims)
+    }
+
+    /// The ticket's holder as a signed-in caller. Tickets carry no email.
+    pub fn caller(&self) -> AuthUser {
+        AuthUser {
+            user_id: self.user_id.clone(),
+            email: String::new(),
+            username: self.username.clone(),
+            roles: self.roles.clone(),
+            age_verified: self.age_verified,
+            badge: self.badge,
+        }
+    }
+
+    pub fn expires_at(&self) -> DateTime<Utc> {
+        DateTime::from_timestamp(self.exp as i64, 0).unwrap_or_else(Utc::now)
+    }
+}
+
+/// Marks the ticket as used, refusing it if it already was. Fails open when
+/// Redis is down; the ticket's expiry still bounds how long it can be replayed.
+pub async fn redeem(state: &AppState, ticket: &JoinTicket) -> Result<(), TicketError> {
+    let Ok(mut conn) = state.redis_pool.get().await else {
+        return Ok(());
+    };
+    let result: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(redeemed_key(&ticket.jti))
+        .arg(&ticket.user_id)
+        .arg("NX")
+        .arg("EX")
+        .arg(TICKET_TTL_SECONDS)
+        .query_async(&mut conn)
+        .await;
+    match result {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err(TicketError::Used),
+        Err(e) => {
+            error!("Failed to redeem join ticket {}: {}", ticket.jti, e);
+            Ok(())
+        }
+    }
+}
+
+/// The ticket a socket was opened with, checked and redeemed. `room_id` is
+/// the room in the socket's URL, when it names one.
+pub async fn admit(state: &AppState, ticket: Option<&str>, room_id: Option<&str>) -> Result<Option<JoinTicket>, AppError> {
+    let Some(ticket) = ticket else {
+        return Ok(None);
+    };
+    let ticket = JoinTicket::verify(ticket).map_err(|_| AppError::Unauthorized)?;
+    if room_id.is_some_and(|room_id| room_id != ticket.room_id) {
+        return Err(AppError::Forbidden);
+    }
+    redeem(state, &ticket).await.map_err(|_| AppError::Unauthorized)?;
+    Ok(Some(ticket))
+}
+
+#[derive(Debug, Serialize)]
+pub struct WsTicketResponse {
+    pub ticket: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+// POST /api/rooms/:location_id/ws-ticket - Issue a join ticket for the room's socket
+pub async fn ws_ticket_handler(
+    Path(location_id): Path<String>,
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<WsTicketResponse>, AppError> {
+    let room = crate::room_quota::room_for(&state, &location_id, Some(&user)).await?;
+    if room.settings.content_rating.requires_age_verification() && !user.age_verified {
+        return Err(AppError::Forbidden);
+    }
+    if crate::room_bans::find_ban(&state, &location_id, &user.user_id).await.is_some() {
+        return Err(AppError::Forbidden);
+    }
+    crate::room_invites::require_access(&state, &room, Some(&user)).await?;
+
+    let ticket = JoinTicket::issue(&user, &location_id);
+    let signed = ticket.sign().map_err(|e| {
+        error!("Failed to sign join ticket for {}: {}", user.user_id, e);
+        AppError::InternalServerError
+    })?;
+    info!("Issued join ticket for room {} to {}", location_id, user.user_id);
+    Ok(Json(WsTicketResponse { ticket: signed, expires_at: ticket.expires_at() }))
+}
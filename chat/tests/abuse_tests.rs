@@ -0,0 +1,35 @@
+use chat_service::abuse::{AbuseScore, AbuseTier};
+
+#[test]
+fn test_score_weights_signals() {
+    let score = AbuseScore::from_counts("user123", 2, 3, 1);
+
+    // 2 reports * 3 + 3 filter hits * 1 + 1 mute * 10
+    assert_eq!(score.score, 19);
+    assert_eq!(score.tier(), AbuseTier::Elevated);
+}
+
+#[test]
+fn test_clean_record_is_normal() {
+    let score = AbuseScore::from_counts("user123", 0, 0, 0);
+
+    assert_eq!(score.score, 0);
+    assert_eq!(score.tier(), AbuseTier::Normal);
+}
+
+#[test]
+fn test_tier_thresholds() {
+    assert_eq!(AbuseTier::from_score(9), AbuseTier::Normal);
+    assert_eq!(AbuseTier::from_score(10), AbuseTier::Elevated);
+    assert_eq!(AbuseTier::from_score(29), AbuseTier::Elevated);
+    assert_eq!(AbuseTier::from_score(30), AbuseTier::High);
+}
+
+#[test]
+fn test_tiers_tighten_rate_limits() {
+    assert_eq!(AbuseTier::Normal.apply_to_rate_limit(30), 30);
+    assert_eq!(AbuseTier::Elevated.apply_to_rate_limit(30), 15);
+    assert_eq!(AbuseTier::High.apply_to_rate_limit(30), 6);
+    // Never drops to zero
+    assert_eq!(AbuseTier::High.apply_to_rate_limit(2), 1);
+}
@@ -0,0 +1,69 @@
+use chat_service::activity::{ActivityKind, ActivityTracker, ACTIVITY_DEBOUNCE, ACTIVITY_TIMEOUT};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_start_and_stop_are_broadcast() {
+    let mut tracker = ActivityTracker::new();
+    let now = Instant::now();
+
+    assert_eq!(tracker.update(ActivityKind::Typing, true, now), Some(true));
+    assert_eq!(tracker.update(ActivityKind::Typing, false, now), Some(false));
+}
+
+#[test]
+fn test_stop_without_start_is_ignored() {
+    let mut tracker = ActivityTracker::new();
+
+    assert_eq!(tracker.update(ActivityKind::RecordingAudio, false, Instant::now()), None);
+}
+
+#[test]
+fn test_refreshes_are_debounced() {
+    let mut tracker = ActivityTracker::new();
+    let start = Instant::now();
+
+    assert_eq!(tracker.update(ActivityKind::Typing, true, start), Some(true));
+    assert_eq!(tracker.update(ActivityKind::Typing, true, start + Duration::from_millis(500)), None);
+    assert_eq!(tracker.update(ActivityKind::Typing, true, start + ACTIVITY_DEBOUNCE), Some(true));
+}
+
+#[test]
+fn test_kinds_are_tracked_independently() {
+    let mut tracker = ActivityTracker::new();
+    let now = Instant::now();
+
+    assert_eq!(tracker.update(ActivityKind::Typing, true, now), Some(true));
+    assert_eq!(tracker.update(ActivityKind::UploadingPhoto, true, now), Some(true));
+    assert_eq!(tracker.update(ActivityKind::Typing, false, now), Some(false));
+    assert_eq!(tracker.drain(), vec![ActivityKind::UploadingPhoto]);
+}
+
+#[test]
+fn test_stale_activities_expire() {
+    let mut tracker = ActivityTracker::new();
+    let start = Instant::now();
+
+    tracker.update(ActivityKind::ChoosingGif, true, start);
+    assert!(tracker.expire(start + Duration::from_secs(1)).is_empty());
+
+    let expired = tracker.expire(start + ACTIVITY_TIMEOUT);
+    assert_eq!(expired, vec![ActivityKind::ChoosingGif]);
+    assert!(tracker.drain().is_empty());
+}
+
+#[test]
+fn test_refresh_postpones_expiry() {
+    let mut tracker = ActivityTracker::new();
+    let start = Instant::now();
+
+    tracker.update(ActivityKind::Typing, true, start);
+    tracker.update(ActivityKind::Typing, true, start + Duration::from_secs(4));
+
+    assert!(tracker.expire(start + ACTIVITY_TIMEOUT).is_empty());
+}
+
+#[test]
+fn test_activity_kind_serialization() {
+    let json = serde_json::to_string(&ActivityKind::RecordingAudio).unwrap();
+    assert_eq!(json, "\"recording_audio\"");
+}
@@ -0,0 +1,22 @@
+use chat_service::admin_stats::{busiest_rooms, rolling_rate};
+use chrono::{TimeZone, Utc};
+
+#[test]
+fn test_rolling_rate_weights_the_previous_minute_by_what_is_left_of_it() {
+    let start = Utc.with_ymd_and_hms(2025, 7, 7, 12, 0, 0).unwrap();
+    assert_eq!(rolling_rate(60, 0, start), 60.0);
+    let half = Utc.with_ymd_and_hms(2025, 7, 7, 12, 0, 30).unwrap();
+    assert_eq!(rolling_rate(60, 10, half), 40.0);
+}
+
+#[test]
+fn test_busiest_rooms_are_ranked_by_sockets() {
+    let counts = vec![("a".to_string(), 2), ("b".to_string(), 9), ("c".to_string(), 5), ("d".to_string(), 5)];
+    let top = busiest_rooms(counts, 3);
+    assert_eq!(top, vec![("b".to_string(), 9), ("c".to_string(), 5), ("d".to_string(), 5)]);
+}
+
+#[test]
+fn test_busiest_rooms_of_an_idle_instance_are_empty() {
+    assert!(busiest_rooms(Vec::new(), 10).is_empty());
+}
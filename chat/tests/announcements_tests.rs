@@ -0,0 +1,42 @@
+use chat_service::announcements::{polygon_contains, AnnouncementArea, AreaPoint};
+use h3o::{LatLng, Resolution};
+
+fn square() -> Vec<AreaPoint> {
+    // Roughly downtown San Francisco
+    vec![
+        AreaPoint { latitude: 37.77, longitude: -122.43 },
+        AreaPoint { latitude: 37.77, longitude: -122.39 },
+        AreaPoint { latitude: 37.80, longitude: -122.39 },
+        AreaPoint { latitude: 37.80, longitude: -122.43 },
+    ]
+}
+
+#[test]
+fn test_polygon_contains_points_inside_only() {
+    assert!(polygon_contains(&square(), 37.785, -122.41));
+    assert!(!polygon_contains(&square(), 37.76, -122.41));
+    assert!(!polygon_contains(&square(), 37.785, -122.45));
+    assert!(!polygon_contains(&[], 37.785, -122.41));
+}
+
+#[test]
+fn test_cell_areas_cover_their_descendants() {
+    let cell = LatLng::new(37.785, -122.41).unwrap().to_cell(Resolution::Eight);
+    let parent = cell.parent(Resolution::Five).unwrap();
+    let area = AnnouncementArea::Cell { h3_index: parent.to_string() };
+    assert!(area.covers(cell));
+
+    let elsewhere = LatLng::new(40.71, -74.0).unwrap().to_cell(Resolution::Eight);
+    assert!(!area.covers(elsewhere));
+    assert!(AnnouncementArea::Polygon { points: square() }.covers(cell));
+}
+
+#[test]
+fn test_areas_are_validated() {
+    assert!(AnnouncementArea::Cell { h3_index: "not-a-cell".to_string() }.validate().is_err());
+    assert!(AnnouncementArea::Polygon { points: square()[..2].to_vec() }.validate().is_err());
+    let mut bad = square();
+    bad[0].latitude = 95.0;
+    assert!(AnnouncementArea::Polygon { points: bad }.validate().is_err());
+    assert!(AnnouncementArea::Polygon { points: square() }.validate().is_ok());
+}
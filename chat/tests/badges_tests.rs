@@ -0,0 +1,46 @@
+use chat_service::auth::Claims;
+use chat_service::badges::{apply, Badge, Highlight, OfficialMessages};
+use chat_service::models::Message;
+use chat_service::notifications::{NotificationHint, NotificationPriority, NotificationSettings};
+
+fn message(content: &str) -> Message {
+    Message::new("room-1".to_string(), "city".to_string(), "City Alerts".to_string(), content.to_string())
+}
+
+#[test]
+fn test_official_messages_follow_the_room_setting() {
+    let mut plain = message("Road closed");
+    apply(&mut plain, Some(Badge::Official), OfficialMessages::Plain);
+    assert_eq!(plain.badge, Some(Badge::Official));
+    assert_eq!(plain.highlight, None);
+
+    let mut highlighted = message("Road closed");
+    apply(&mut highlighted, Some(Badge::Official), OfficialMessages::default());
+    assert_eq!(highlighted.highlight, Some(Highlight::Highlight));
+
+    let mut verified = message("Road closed");
+    apply(&mut verified, Some(Badge::Verified), OfficialMessages::Prioritize);
+    assert_eq!(verified.badge, Some(Badge::Verified));
+    assert_eq!(verified.highlight, None);
+}
+
+#[test]
+fn test_prioritized_official_messages_push_as_high_priority() {
+    let mut alert = message("Evacuate the waterfront");
+    apply(&mut alert, Some(Badge::Official), OfficialMessages::Prioritize);
+    let hint = NotificationHint::for_room_message(&alert, &NotificationSettings::default());
+    assert_eq!(hint.priority, NotificationPriority::High);
+    assert_eq!(hint.collapse_key, "room:room-1");
+}
+
+#[test]
+fn test_badge_claim_is_optional() {
+    let claims: Claims = serde_json::from_str(
+        r#"{"user_id":"u1","email":"a@example.com","username":"alice","exp":1,"badge":"verified"}"#,
+    )
+    .unwrap();
+    assert_eq!(claims.badge, Some(Badge::Verified));
+
+    let claims: Claims = serde_json::from_str(r#"{"user_id":"u1","email":"a@example.com","username":"alice","exp":1}"#).unwrap();
+    assert_eq!(claims.badge, None);
+}
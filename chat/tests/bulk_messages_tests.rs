@@ -0,0 +1,46 @@
+use chat_service::bulk_messages::{BulkAction, BulkSelection};
+use chat_service::models::Message;
+use chrono::{Duration, Utc};
+
+fn message_by(user_id: &str, minutes_ago: i64) -> Message {
+    let mut message = Message::new("room1".to_string(), user_id.to_string(), user_id.to_string(), "spam".to_string());
+    message.timestamp = Utc::now() - Duration::minutes(minutes_ago);
+    message
+}
+
+#[test]
+fn test_deletes_need_a_user_or_time_range() {
+    let everything = BulkSelection::default();
+    assert!(everything.validate(BulkAction::Delete).is_err());
+    assert!(everything.validate(BulkAction::Export).is_ok());
+
+    let by_user = BulkSelection { user_id: Some("u1".to_string()), ..Default::default() };
+    assert!(by_user.validate(BulkAction::Delete).is_ok());
+
+    let backwards = BulkSelection { from: Some(Utc::now()), to: Some(Utc::now() - Duration::hours(1)), ..Default::default() };
+    assert!(backwards.validate(BulkAction::Export).is_err());
+}
+
+#[test]
+fn test_filter_is_scoped_to_the_room_and_live_messages() {
+    let selection = BulkSelection { user_id: Some("u1".to_string()), from: Some(Utc::now()), to: None };
+    let filter = selection.filter("room1");
+    assert_eq!(filter.get_str("room_id").unwrap(), "room1");
+    assert!(!filter.get_bool("deleted").unwrap());
+    assert_eq!(filter.get_str("user_id").unwrap(), "u1");
+    let timestamp = filter.get_document("timestamp").unwrap();
+    assert!(timestamp.contains_key("$gte"));
+    assert!(!timestamp.contains_key("$lte"));
+}
+
+#[test]
+fn test_clients_match_messages_by_user_and_time() {
+    let selection = BulkSelection {
+        user_id: Some("u1".to_string()),
+        from: Some(Utc::now() - Duration::minutes(30)),
+        to: Some(Utc::now()),
+    };
+    assert!(selection.matches(&message_by("u1", 10)));
+    assert!(!selection.matches(&message_by("u1", 60)));
+    assert!(!selection.matches(&message_by("u2", 10)));
+}
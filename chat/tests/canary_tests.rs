@@ -0,0 +1,56 @@
+use chat_service::canary::{bucket, Canary, CanaryConfig, Cohort};
+use chat_service::streams::BroadcastBackend;
+use chat_service::wire_format::WireFormat;
+
+fn config(percent: u8) -> CanaryConfig {
+    CanaryConfig { percent, broadcast_backend: Some(BroadcastBackend::Streams), msgpack_only: true }
+}
+
+#[test]
+fn test_cohorts_follow_the_user_hash() {
+    let users: Vec<String> = (0..1000).map(|i| format!("user-{}", i)).collect();
+    let canaries = users.iter().filter(|user| config(10).cohort(Some(user)) == Cohort::Canary).count();
+    // Roughly a tenth, and the same users every time
+    assert!((50..150).contains(&canaries), "{}", canaries);
+    assert!(users.iter().all(|user| bucket(user) == bucket(user) && bucket(user) < 100));
+
+    assert_eq!(config(0).cohort(Some("user-1")), Cohort::Stable);
+    assert_eq!(config(100).cohort(Some("user-1")), Cohort::Canary);
+    assert_eq!(config(100).cohort(None), Cohort::Stable);
+}
+
+#[test]
+fn test_canaries_get_the_new_paths() {
+    let config = config(100);
+    assert_eq!(config.broadcast_backend(Cohort::Canary, BroadcastBackend::PubSub), BroadcastBackend::Streams);
+    assert_eq!(config.broadcast_backend(Cohort::Stable, BroadcastBackend::PubSub), BroadcastBackend::PubSub);
+    assert_eq!(config.wire_format(Cohort::Canary, WireFormat::MessagePack), WireFormat::MessagePack);
+    assert_eq!(config.wire_format(Cohort::Stable, WireFormat::MessagePack), WireFormat::Json);
+    assert!(config.uses_streams(BroadcastBackend::PubSub));
+
+    // Turned off, nothing changes
+    let off = CanaryConfig::default();
+    assert_eq!(off.wire_format(Cohort::Stable, WireFormat::MessagePack), WireFormat::MessagePack);
+    assert!(!off.uses_streams(BroadcastBackend::PubSub));
+}
+
+#[test]
+fn test_metrics_are_kept_per_cohort() {
+    let canary = Canary::new(config(100));
+    canary.connect("s1", Cohort::Canary);
+    canary.connect("s2", Cohort::Stable);
+    assert_eq!(canary.cohort_of(Some("s1")), Cohort::Canary);
+    assert_eq!(canary.cohort_of(Some("s2")), Cohort::Stable);
+    assert_eq!(canary.cohort_of(None), Cohort::Stable);
+
+    canary.record_publish(Cohort::Canary, false);
+    canary.disconnect("s1", Cohort::Canary);
+    assert_eq!(canary.cohort_of(Some("s1")), Cohort::Stable);
+
+    let rendered = canary.render();
+    assert!(rendered.contains("chat_cohort_connections_total{cohort=\"canary\"} 1"));
+    assert!(rendered.contains("chat_cohort_open_connections{cohort=\"canary\"} 0"));
+    assert!(rendered.contains("chat_cohort_open_connections{cohort=\"stable\"} 1"));
+    assert!(rendered.contains("chat_cohort_publish_failures_total{cohort=\"canary\"} 1"));
+    assert!(rendered.contains("chat_cohort_publish_failures_total{cohort=\"stable\"} 0"));
+}
@@ -0,0 +1,44 @@
+use chat_service::connection_state::{ConnectionState, ProtocolError};
+use chat_service::models::WsMessage;
+
+fn join() -> WsMessage {
+    WsMessage::Join { user_id: "u1".to_string(), username: "alice".to_string(), token: "t".to_string(), have_until: None, low_data: false, invite: None }
+}
+
+fn typing() -> WsMessage {
+    WsMessage::Typing { is_typing: true }
+}
+
+#[test]
+fn test_room_frames_are_refused_before_joining() {
+    let mut state = ConnectionState::default();
+    assert_eq!(state.admit(&typing()), Err(ProtocolError::NotJoined("activity")));
+    assert!(state.admit(&join()).is_ok());
+
+    state.authenticate("u1").unwrap();
+    assert_eq!(state.admit(&typing()), Err(ProtocolError::NotJoined("activity")));
+    state.join("room1").unwrap();
+    assert!(state.admit(&typing()).is_ok());
+    assert_eq!(state.room_id(), Some("room1"));
+    assert_eq!(state.user_id(), Some("u1"));
+}
+
+#[test]
+fn test_a_socket_joins_once() {
+    let mut state = ConnectionState::default();
+    assert_eq!(state.join("room1"), Err(ProtocolError::NotAuthenticated("room1".to_string())));
+    state.authenticate("u1").unwrap();
+    state.join("room1").unwrap();
+
+    assert_eq!(state.admit(&join()), Err(ProtocolError::AlreadyJoined("room1".to_string())));
+    assert_eq!(state.authenticate("u2"), Err(ProtocolError::AlreadyJoined("room1".to_string())));
+    assert_eq!(state.join("room2"), Err(ProtocolError::AlreadyJoined("room1".to_string())));
+}
+
+#[test]
+fn test_server_frames_are_refused_from_clients() {
+    let state = ConnectionState::default();
+    let error = state.admit(&WsMessage::Error { message: "spoofed".to_string() }).unwrap_err();
+    assert_eq!(error, ProtocolError::NotAClientFrame("Error".to_string()));
+    assert_eq!(error.to_string(), "Error can't be sent by clients");
+}
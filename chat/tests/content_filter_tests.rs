@@ -0,0 +1,28 @@
+use chat_service::content_filter::{filter_content, ContentRating};
+use chat_service::models::RoomSettings;
+
+#[test]
+fn test_rating_sets_filter_strictness() {
+    let content = "damn, that's fucking great";
+
+    assert_eq!(filter_content(content, ContentRating::Everyone), ("d***, that's f****** great".to_string(), 2));
+    assert_eq!(filter_content(content, ContentRating::Teen), ("damn, that's f****** great".to_string(), 1));
+    assert_eq!(filter_content(content, ContentRating::Mature), (content.to_string(), 0));
+}
+
+#[test]
+fn test_filter_matches_whole_words_only() {
+    let (filtered, hits) = filter_content("Hello shell, HELL no", ContentRating::Everyone);
+
+    assert_eq!(filtered, "Hello shell, H*** no");
+    assert_eq!(hits, 1);
+}
+
+#[test]
+fn test_existing_rooms_default_to_everyone() {
+    let settings: RoomSettings = serde_json::from_value(serde_json::json!({ "max_users": 10, "rate_limit": 5 })).unwrap();
+
+    assert_eq!(settings.content_rating, ContentRating::Everyone);
+    assert!(ContentRating::Mature.requires_age_verification());
+    assert!(!ContentRating::Teen.requires_age_verification());
+}
@@ -0,0 +1,16 @@
+use chat_service::models::{Message, SendStatus};
+use chat_service::{AppState, Persisted};
+
+#[tokio::test]
+async fn test_messages_sent_while_mongo_is_down_are_queued() {
+    // Nothing is listening, so every insert fails with a transient error
+    let state = AppState::new("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=50", "redis://127.0.0.1:1", "db_tests")
+        .await
+        .unwrap();
+    let message = Message::new("room-1".into(), "u1".into(), "alice".into(), "hello while the primary is away".into());
+
+    let persisted = state.db.create_message(&message).await.unwrap();
+    assert!(matches!(persisted, Persisted::Queued(_)));
+    assert_eq!(persisted.status(), SendStatus::Queued);
+    assert_eq!(Persisted::Stored(persisted.id()).status(), SendStatus::Sent);
+}
@@ -0,0 +1,41 @@
+use std::collections::HashMap;
+
+use chat_service::delivery_trace::parse_trace;
+
+fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+}
+
+#[test]
+fn test_trace_groups_publish_and_delivery_by_instance() {
+    let trace = parse_trace(&fields(&[
+        ("persisted_at", "1700000000000"),
+        ("published_at:a", "1700000000010"),
+        ("delivered:a", "3"),
+        ("delivered:b", "5"),
+    ]));
+
+    assert_eq!(trace.persisted_at.unwrap().timestamp_millis(), 1_700_000_000_000);
+    assert_eq!(trace.instances.len(), 2);
+    assert_eq!(trace.instances[0].instance_id, "a");
+    assert_eq!(trace.instances[0].published_at.unwrap().timestamp_millis(), 1_700_000_000_010);
+    assert!(trace.instances[1].published_at.is_none());
+    assert_eq!(trace.delivered_total, 8);
+}
+
+#[test]
+fn test_acks_are_listed_in_order() {
+    let trace = parse_trace(&fields(&[("ack:bob", "1700000000200"), ("ack:alice", "1700000000100")]));
+    let users: Vec<&str> = trace.acks.iter().map(|ack| ack.user_id.as_str()).collect();
+
+    assert_eq!(users, vec!["alice", "bob"]);
+}
+
+#[test]
+fn test_empty_trace() {
+    let trace = parse_trace(&HashMap::new());
+
+    assert!(trace.persisted_at.is_none());
+    assert!(trace.instances.is_empty());
+    assert_eq!(trace.delivered_total, 0);
+}
@@ -0,0 +1,32 @@
+use chat_service::dm::may_access;
+use chat_service::models::DMConversation;
+use chrono::Utc;
+
+fn pair() -> DMConversation {
+    DMConversation {
+        id: "dm_u1_u2".to_string(),
+        participants: vec!["u1".to_string(), "u2".to_string()],
+        last_message: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        last_read: Default::default(),
+        notifications: Default::default(),
+    }
+}
+
+#[test]
+fn test_participants_have_access() {
+    assert!(may_access(Some(&pair()), "u1"));
+    assert!(may_access(Some(&pair()), "u2"));
+}
+
+#[test]
+fn test_other_users_are_denied() {
+    assert!(!may_access(Some(&pair()), "u3"));
+    assert!(!may_access(Some(&pair()), ""));
+}
+
+#[test]
+fn test_unknown_conversations_are_denied() {
+    assert!(!may_access(None, "u1"));
+}
@@ -0,0 +1,28 @@
+use chat_service::dm::{conversation_id_for, conversation_participants, MAX_PARTICIPANTS};
+
+fn ids(ids: &[&str]) -> Vec<String> {
+    ids.iter().map(|id| id.to_string()).collect()
+}
+
+#[test]
+fn test_creator_is_added_and_duplicates_dropped() {
+    let participants = conversation_participants("u1", &ids(&["u2", "u1", "u2", "u3"])).unwrap();
+    assert_eq!(participants, ids(&["u1", "u2", "u3"]));
+}
+
+#[test]
+fn test_conversations_need_another_participant_and_a_size_limit() {
+    assert!(conversation_participants("u1", &ids(&["u1"])).is_err());
+    assert!(conversation_participants("u1", &ids(&[" "])).is_err());
+    let crowd: Vec<String> = (0..MAX_PARTICIPANTS).map(|i| format!("u{}", i + 2)).collect();
+    assert!(conversation_participants("u1", &crowd).is_err());
+}
+
+#[test]
+fn test_pairs_share_an_id_regardless_of_who_creates_it() {
+    assert_eq!(conversation_id_for(&ids(&["u1", "u2"])), "dm_u1_u2");
+    assert_eq!(conversation_id_for(&ids(&["u2", "u1"])), "dm_u1_u2");
+    let group = ids(&["u1", "u2", "u3"]);
+    assert!(conversation_id_for(&group).starts_with("group_"));
+    assert_ne!(conversation_id_for(&group), conversation_id_for(&group));
+}
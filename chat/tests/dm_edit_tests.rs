@@ -0,0 +1,41 @@
+use chat_service::dm::may_change;
+use chat_service::models::{DirectMessage, WsMessage};
+use chrono::Utc;
+
+fn message_from(sender_id: &str) -> DirectMessage {
+    DirectMessage {
+        id: None,
+        conversation_id: "dm_u1_u2".to_string(),
+        sender_id: sender_id.to_string(),
+        sender_username: sender_id.to_string(),
+        content: "hi".to_string(),
+        timestamp: Utc::now(),
+        edited_at: None,
+        deleted: false,
+        read_by: vec![],
+        delivered_to: vec![],
+        attachments: vec![],
+        shadow: false,
+    }
+}
+
+#[test]
+fn test_only_the_sender_may_change_a_message() {
+    assert!(may_change(&message_from("u1"), "u1"));
+    assert!(!may_change(&message_from("u1"), "u2"));
+}
+
+#[test]
+fn test_deleted_messages_cannot_be_changed() {
+    let mut message = message_from("u1");
+    message.deleted = true;
+    assert!(!may_change(&message, "u1"));
+}
+
+#[test]
+fn test_edit_and_delete_frames_parse() {
+    let edit = r#"{"type":"DMEdit","data":{"conversation_id":"dm_u1_u2","message_id":"abc","content":"fixed"}}"#;
+    assert!(matches!(serde_json::from_str(edit), Ok(WsMessage::DMEdit { content, .. }) if content == "fixed"));
+    let delete = r#"{"type":"DMDelete","data":{"conversation_id":"dm_u1_u2","message_id":"abc"}}"#;
+    assert!(matches!(serde_json::from_str(delete), Ok(WsMessage::DMDelete { .. })));
+}
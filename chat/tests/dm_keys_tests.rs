@@ -0,0 +1,47 @@
+use chat_service::dm_keys::{validate, KeyDistribution, KeyEventKind, NewKeyEvent};
+
+fn event(kind: KeyEventKind) -> NewKeyEvent {
+    NewKeyEvent {
+        kind,
+        device_id: "phone-1".to_string(),
+        key_id: None,
+        public_key: None,
+        members: vec![],
+        distributions: vec![],
+    }
+}
+
+#[test]
+fn test_device_announcements_need_a_public_key() {
+    let mut announce = event(KeyEventKind::DeviceAdded);
+    assert!(validate(&announce).is_err());
+
+    announce.public_key = Some("MCowBQYDK2VuAyEA".to_string());
+    assert!(validate(&announce).is_ok());
+}
+
+#[test]
+fn test_rotations_need_sealed_keys() {
+    let mut rotation = event(KeyEventKind::Rotation);
+    rotation.key_id = Some("epoch-2".to_string());
+    assert!(validate(&rotation).is_err());
+
+    rotation.distributions.push(KeyDistribution {
+        recipient_id: "user2".to_string(),
+        recipient_device_id: "laptop".to_string(),
+        ciphertext: "c2VhbGVk".to_string(),
+    });
+    assert!(validate(&rotation).is_ok());
+}
+
+#[test]
+fn test_oversized_key_material_is_rejected() {
+    let mut announce = event(KeyEventKind::DeviceAdded);
+    announce.public_key = Some("A".repeat(9 * 1024));
+    assert!(validate(&announce).is_err());
+
+    let mut members = event(KeyEventKind::MembersChanged);
+    assert!(validate(&members).is_err());
+    members.members = vec!["user1".to_string(), "user3".to_string()];
+    assert!(validate(&members).is_ok());
+}
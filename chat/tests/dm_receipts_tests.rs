@@ -0,0 +1,32 @@
+use chat_service::dm::delivered_message_id;
+use chat_service::models::{Message, WsMessage};
+use mongodb::bson::oid::ObjectId;
+
+fn new_message_from(sender: &str, id: ObjectId) -> String {
+    let mut message = Message::new("c1".to_string(), sender.to_string(), "sender".to_string(), "hi".to_string());
+    message.id = Some(id);
+    serde_json::to_string(&WsMessage::NewMessage(message)).unwrap()
+}
+
+#[test]
+fn test_messages_from_others_are_delivered() {
+    let id = ObjectId::new();
+    assert_eq!(delivered_message_id(&new_message_from("u1", id), "u2"), Some(id));
+}
+
+#[test]
+fn test_own_messages_are_not_delivered() {
+    assert_eq!(delivered_message_id(&new_message_from("u1", ObjectId::new()), "u1"), None);
+}
+
+#[test]
+fn test_other_events_are_not_deliveries() {
+    let receipt = serde_json::to_string(&WsMessage::DMReadUpdated {
+        conversation_id: "c1".to_string(),
+        user_id: "u1".to_string(),
+        up_to: ObjectId::new().to_hex(),
+    })
+    .unwrap();
+    assert_eq!(delivered_message_id(&receipt, "u2"), None);
+    assert_eq!(delivered_message_id("not json", "u2"), None);
+}
@@ -0,0 +1,24 @@
+use chat_service::dm::summarize_unread;
+use chat_service::read_cursors::MAX_UNREAD_COUNT;
+
+#[test]
+fn test_conversations_are_ordered_by_unread() {
+    let (conversations, total) = summarize_unread(vec![("a".to_string(), 2), ("b".to_string(), 5), ("c".to_string(), 2)]);
+    let order: Vec<&str> = conversations.iter().map(|c| c.conversation_id.as_str()).collect();
+    assert_eq!(order, vec!["b", "a", "c"]);
+    assert_eq!(total, 9);
+}
+
+#[test]
+fn test_counts_are_capped_before_the_total() {
+    let (conversations, total) = summarize_unread(vec![("a".to_string(), 500), ("b".to_string(), 1)]);
+    assert_eq!(conversations[0].unread, MAX_UNREAD_COUNT);
+    assert_eq!(total, MAX_UNREAD_COUNT + 1);
+}
+
+#[test]
+fn test_read_conversations_are_left_out() {
+    let (conversations, total) = summarize_unread(vec![("a".to_string(), 0)]);
+    assert!(conversations.is_empty());
+    assert_eq!(total, 0);
+}
@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use chat_service::event_log::{cursor_millis, EventLogConfig, LogEvent};
+use chat_service::user_history::HistorySource;
+
+#[test]
+fn test_cursor_millis() {
+    assert_eq!(cursor_millis("1700000000000-3"), Some(1_700_000_000_000));
+    assert_eq!(cursor_millis("5"), Some(5));
+    assert_eq!(cursor_millis("abc"), None);
+    assert_eq!(cursor_millis("1700000000000-x"), None);
+}
+
+#[test]
+fn test_events_are_tagged_by_type() {
+    let event = LogEvent::MessageDeleted {
+        source: HistorySource::Dm,
+        room_id: "conversation-1".to_string(),
+        message_ids: vec!["a".to_string(), "b".to_string()],
+    };
+    let json = serde_json::to_value(&event).unwrap();
+    assert_eq!(json["type"], "message_deleted");
+    assert_eq!(json["source"], "dm");
+    assert_eq!(json["message_ids"], serde_json::json!(["a", "b"]));
+
+    let joined = LogEvent::MemberJoined { conversation_id: "c".to_string(), user_id: "u".to_string() };
+    assert_eq!(serde_json::to_value(&joined).unwrap()["type"], "member_joined");
+}
+
+#[test]
+fn test_zero_retention_turns_the_log_off() {
+    assert!(EventLogConfig::default().enabled());
+    assert_eq!(EventLogConfig::default().retention, Duration::from_secs(72 * 3600));
+    assert!(!EventLogConfig { retention: Duration::ZERO }.enabled());
+}
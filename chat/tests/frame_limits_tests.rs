@@ -0,0 +1,33 @@
+use axum::extract::ws::{close_code, Message as WsMsg};
+use chat_service::frame_limits::{FrameLimits, FrameViolation};
+
+fn limits() -> FrameLimits {
+    FrameLimits { max_frame_bytes: 256, max_depth: 3, max_field_bytes: 16 }
+}
+
+#[test]
+fn test_ordinary_messages_pass() {
+    let frame = r#"{"type":"Message","data":{"content":"hello \"there\""}}"#;
+    assert_eq!(limits().check_json(frame), Ok(()));
+    // Brackets inside strings don't count as nesting
+    assert_eq!(limits().check_json(r#"{"a":{"b":"[[[{{{"}}}"#), Ok(()));
+}
+
+#[test]
+fn test_oversized_deep_and_long_frames_are_rejected() {
+    assert_eq!(limits().check_json(&"x".repeat(300)), Err(FrameViolation::TooLarge { size: 300, max: 256 }));
+    assert_eq!(limits().check_json(r#"{"a":[{"b":[1]}]}"#), Err(FrameViolation::TooDeep { max: 3 }));
+    let long_field = format!(r#"{{"content":"{}"}}"#, "y".repeat(17));
+    assert_eq!(limits().check_json(&long_field), Err(FrameViolation::FieldTooLong { max: 16 }));
+    assert_eq!(
+        limits().check(&WsMsg::Binary(vec![0; 300])),
+        Err(FrameViolation::TooLarge { size: 300, max: 256 })
+    );
+}
+
+#[test]
+fn test_violations_close_with_a_protocol_error() {
+    let frame = FrameViolation::TooDeep { max: 3 }.close_frame();
+    assert_eq!(frame.code, close_code::PROTOCOL);
+    assert!(limits().check(&WsMsg::Ping(vec![0; 300])).is_ok());
+}
@@ -0,0 +1,69 @@
+use chat_service::dm::is_removal_of;
+use chat_service::models::{DMConversation, DirectMessage, WsMessage};
+use chrono::Utc;
+
+fn group() -> DMConversation {
+    DMConversation {
+        id: "c1".to_string(),
+        participants: vec!["u1".to_string(), "u2".to_string(), "u3".to_string()],
+        last_message: None,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        last_read: Default::default(),
+        notifications: Default::default(),
+    }
+}
+
+fn message_read_by(read_by: &[&str]) -> DirectMessage {
+    DirectMessage {
+        id: None,
+        conversation_id: "c1".to_string(),
+        sender_id: "u1".to_string(),
+        sender_username: "alice".to_string(),
+        content: "hi all".to_string(),
+        timestamp: Utc::now(),
+        edited_at: None,
+        deleted: false,
+        read_by: read_by.iter().map(|id| id.to_string()).collect(),
+        delivered_to: vec![],
+        attachments: vec![],
+        shadow: false,
+    }
+}
+
+#[test]
+fn test_participants_are_counted_from_the_conversation() {
+    let conversation = group();
+    assert_eq!(conversation.participant_count(), 3);
+    assert!(conversation.is_participant("u3"));
+    assert!(!conversation.is_participant("u4"));
+}
+
+#[test]
+fn test_unread_excludes_the_sender_and_readers() {
+    let conversation = group();
+    assert_eq!(conversation.unread_by(&message_read_by(&["u1"])), vec!["u2", "u3"]);
+    assert_eq!(conversation.unread_by(&message_read_by(&["u1", "u3"])), vec!["u2"]);
+    assert!(conversation.unread_by(&message_read_by(&["u2", "u3"])).is_empty());
+}
+
+#[test]
+fn test_removal_events_only_match_the_removed_user() {
+    let removed = serde_json::to_string(&WsMessage::DMParticipantRemoved {
+        conversation_id: "c1".to_string(),
+        user_id: "u2".to_string(),
+        removed_by: "u2".to_string(),
+        participant_count: 2,
+    })
+    .unwrap();
+    assert!(is_removal_of(&removed, "u2"));
+    assert!(!is_removal_of(&removed, "u3"));
+    assert!(!is_removal_of(r#"{"type":"Typing","data":{"is_typing":true}}"#, "u2"));
+}
+
+#[test]
+fn test_only_groups_can_be_added_to() {
+    assert!(!group().is_one_to_one());
+    let pair = DMConversation { participants: vec!["u1".to_string(), "u2".to_string()], ..group() };
+    assert!(pair.is_one_to_one());
+}
@@ -0,0 +1,33 @@
+use axum::http::StatusCode;
+use chat_service::health::{DependencyStatus, HealthReport, Status};
+use std::time::Duration;
+
+#[test]
+fn test_report_is_up_only_when_every_dependency_is() {
+    let report = HealthReport::new([
+        ("mongodb", DependencyStatus::up(Duration::from_millis(3))),
+        ("redis", DependencyStatus::up(Duration::from_millis(1))),
+    ]);
+    assert_eq!(report.status, Status::Up);
+    assert_eq!(report.status_code(), StatusCode::OK);
+}
+
+#[test]
+fn test_one_dependency_down_makes_the_probe_fail() {
+    let report = HealthReport::new([
+        ("mongodb", DependencyStatus::up(Duration::from_millis(3))),
+        ("redis", DependencyStatus::down(Duration::from_secs(2), "no answer within 2s")),
+    ]);
+    assert_eq!(report.status, Status::Down);
+    assert_eq!(report.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[test]
+fn test_report_json_lists_dependencies_by_name() {
+    let report = HealthReport::new([("redis", DependencyStatus::down(Duration::from_millis(5), "connection refused"))]);
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["status"], "down");
+    assert_eq!(json["dependencies"]["redis"]["status"], "down");
+    assert_eq!(json["dependencies"]["redis"]["latency_ms"], 5);
+    assert_eq!(json["dependencies"]["redis"]["error"], "connection refused");
+}
@@ -0,0 +1,28 @@
+use std::time::Duration;
+
+use chat_service::heartbeat::{HeartbeatConfig, Liveness};
+
+#[test]
+fn test_idle_timeout_is_never_shorter_than_the_ping_interval() {
+    let config = HeartbeatConfig::new(Duration::from_secs(30), Duration::from_secs(10));
+
+    assert_eq!(config.idle_timeout, Duration::from_secs(30));
+    assert_eq!(HeartbeatConfig::new(Duration::ZERO, Duration::ZERO).ping_interval, Duration::from_secs(1));
+}
+
+#[test]
+fn test_defaults_reap_after_three_missed_pings() {
+    let config = HeartbeatConfig::default();
+
+    assert_eq!(config.ping_interval, Duration::from_secs(30));
+    assert_eq!(config.idle_timeout, Duration::from_secs(90));
+}
+
+#[test]
+fn test_fresh_socket_is_not_idle() {
+    let liveness = Liveness::new();
+    liveness.touch();
+
+    assert!(!liveness.is_idle(&HeartbeatConfig::default()));
+    assert!(liveness.idle_for() < Duration::from_secs(1));
+}
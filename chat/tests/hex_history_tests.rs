@@ -0,0 +1,44 @@
+use axum::extract::{Path, Query, State};
+use axum::http::{StatusCode, Uri};
+use axum::response::IntoResponse;
+use chat_service::handlers::{get_hex_messages, hex_page_limit, next_before, GetMessagesQuery, MAX_HEX_PAGE};
+use chat_service::models::Message;
+use chat_service::AppState;
+use chrono::{Duration, Utc};
+
+fn page(len: usize) -> Vec<Message> {
+    let start = Utc::now() - Duration::hours(1);
+    (0..len)
+        .map(|i| {
+            let mut message = Message::new("882a100d63fffff".to_string(), "u1".to_string(), "alice".to_string(), format!("#{}", i));
+            message.timestamp = start + Duration::minutes(i as i64);
+            message
+        })
+        .collect()
+}
+
+#[test]
+fn test_page_limit_is_clamped() {
+    assert_eq!(hex_page_limit(None), 50);
+    assert_eq!(hex_page_limit(Some(0)), 1);
+    assert_eq!(hex_page_limit(Some(-5)), 1);
+    assert_eq!(hex_page_limit(Some(MAX_HEX_PAGE + 1)), MAX_HEX_PAGE);
+}
+
+#[test]
+fn test_full_pages_point_at_their_oldest_message() {
+    let full = page(3);
+    assert_eq!(next_before(&full, 3), Some(full[0].timestamp));
+    // A short page is the last one
+    assert_eq!(next_before(&page(2), 3), None);
+    assert_eq!(next_before(&[], 3), None);
+}
+
+#[tokio::test]
+async fn test_bad_index_is_a_bad_request() {
+    // Nothing is listening; the index is refused before either store is used
+    let state = AppState::new("mongodb://127.0.0.1:1", "redis://127.0.0.1:1", "hex_history_tests").await.unwrap();
+    let query = Query::<GetMessagesQuery>::try_from_uri(&Uri::from_static("/api/hex/x/messages?limit=10")).unwrap();
+    let response = get_hex_messages(Path("not-a-cell".to_string()), query, State(state), None).await;
+    assert_eq!(response.err().unwrap().into_response().status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,47 @@
+use chat_service::hex::HexCell;
+use chat_service::models::WsMessage;
+use chat_service::permissions::RoomPermissions;
+
+#[test]
+fn test_join_hex_message_format() {
+    let msg: WsMessage = serde_json::from_value(serde_json::json!({
+        "type": "JoinHex",
+        "data": {
+            "h3_index": "882a1072cffffff",
+            "user_info": { "user_id": "user123", "username": "testuser" }
+        }
+    }))
+    .unwrap();
+
+    match msg {
+        WsMessage::JoinHex { h3_index, user_info, token, location, .. } => {
+            assert_eq!(h3_index.as_deref(), Some("882a1072cffffff"));
+            assert_eq!(user_info.user_id, "user123");
+            assert_eq!(user_info.username, "testuser");
+            assert!(token.is_none());
+            assert!(location.is_none());
+        }
+        _ => panic!("Expected JoinHex message"),
+    }
+}
+
+#[test]
+fn test_hex_joined_reports_the_cell_resolution() {
+    let cell: h3o::CellIndex = "882a100d63fffff".parse().unwrap();
+    let drawn = HexCell::from(cell);
+    let joined = WsMessage::HexJoined {
+        h3_index: cell.to_string(),
+        resolution: u8::from(cell.resolution()),
+        center: drawn.center,
+        boundary: drawn.boundary,
+        neighbors: vec![],
+        user_count: 3,
+        permissions: RoomPermissions::default(),
+    };
+
+    let json = serde_json::to_value(&joined).unwrap();
+    assert_eq!(json["type"], "HexJoined");
+    assert_eq!(json["data"]["h3_index"], "882a100d63fffff");
+    assert_eq!(json["data"]["resolution"], 8);
+    assert_eq!(json["data"]["user_count"], 3);
+}
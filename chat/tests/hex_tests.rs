@@ -0,0 +1,139 @@
+use chat_service::hex::{
+    cell_for_fix, chat_resolution, disk, parse_cell, resolution_for_accuracy, GpsFix, HexCell, HexError,
+    COARSEST_AUTO_RESOLUTION, DEFAULT_RESOLUTION, FINEST_AUTO_RESOLUTION,
+};
+use axum::extract::{Path, State};
+use chat_service::auth::AuthUser;
+use chat_service::handlers::migrate_room_to_hex_handler;
+use chat_service::room_bridge::{legacy_room_cell, BridgeMode};
+use chat_service::{AppError, AppState};
+use h3o::Resolution;
+
+#[test]
+fn test_precise_fix_uses_finest_resolution() {
+    assert_eq!(resolution_for_accuracy(Some(5.0)), FINEST_AUTO_RESOLUTION);
+}
+
+#[test]
+fn test_poor_fix_uses_coarser_resolution() {
+    // ~300m accuracy doesn't fit in a res 9 cell (~200m edge) but fits res 8
+    assert_eq!(resolution_for_accuracy(Some(300.0)), Resolution::Eight);
+    // ~2km accuracy needs a res 6 cell
+    assert_eq!(resolution_for_accuracy(Some(2_000.0)), Resolution::Six);
+}
+
+#[test]
+fn test_very_poor_fix_is_clamped() {
+    assert_eq!(resolution_for_accuracy(Some(100_000.0)), COARSEST_AUTO_RESOLUTION);
+}
+
+#[test]
+fn test_missing_or_invalid_accuracy_uses_default() {
+    assert_eq!(resolution_for_accuracy(None), DEFAULT_RESOLUTION);
+    assert_eq!(resolution_for_accuracy(Some(0.0)), DEFAULT_RESOLUTION);
+    assert_eq!(resolution_for_accuracy(Some(-10.0)), DEFAULT_RESOLUTION);
+    assert_eq!(resolution_for_accuracy(Some(f64::NAN)), DEFAULT_RESOLUTION);
+}
+
+#[test]
+fn test_cell_for_fix() {
+    let fix = GpsFix {
+        latitude: 40.730610,
+        longitude: -73.935242,
+        accuracy: Some(300.0),
+    };
+
+    let cell = cell_for_fix(&fix).unwrap();
+    assert_eq!(cell.resolution(), Resolution::Eight);
+}
+
+#[test]
+fn test_cell_for_fix_rejects_invalid_coordinates() {
+    let fix = GpsFix {
+        latitude: f64::NAN,
+        longitude: -73.935242,
+        accuracy: None,
+    };
+
+    assert_eq!(cell_for_fix(&fix), Err(HexError::InvalidCoordinates));
+}
+
+#[test]
+fn test_legacy_room_cell() {
+    let cell = legacy_room_cell("40.730610_-73.935242").unwrap();
+    assert_eq!(cell.resolution(), DEFAULT_RESOLUTION);
+
+    // Nearby coordinates in the same neighborhood share the hex
+    assert_eq!(legacy_room_cell("40.730620_-73.935250"), Some(cell));
+}
+
+#[test]
+fn test_legacy_room_cell_ignores_non_coordinate_rooms() {
+    assert_eq!(legacy_room_cell("test-room"), None);
+    assert_eq!(legacy_room_cell("95.0_-73.9"), None);
+}
+
+fn user_with(roles: &[&str]) -> AuthUser {
+    AuthUser {
+        user_id: "u1".to_string(),
+        email: "u1@example.com".to_string(),
+        username: "u1".to_string(),
+        roles: roles.iter().map(ToString::to_string).collect(),
+        age_verified: false,
+        badge: None,
+    }
+}
+
+#[tokio::test]
+async fn test_only_admins_migrate_rooms_and_only_while_redirecting() {
+    let mut state = AppState::new("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=50", "redis://127.0.0.1:1", "hex_tests")
+        .await
+        .unwrap();
+    let room = "40.730610_-73.935242".to_string();
+
+    // Joining no longer moves history, so nobody but an admin can
+    state.room_bridge = BridgeMode::Redirect;
+    for roles in [&[][..], &["moderator"][..]] {
+        let result = migrate_room_to_hex_handler(Path(room.clone()), State(state.clone()), user_with(roles)).await;
+        assert!(matches!(result, Err(AppError::Forbidden)));
+    }
+
+    for mode in [BridgeMode::Off, BridgeMode::CrossPost] {
+        state.room_bridge = mode;
+        let result = migrate_room_to_hex_handler(Path(room.clone()), State(state.clone()), user_with(&["admin"])).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+}
+
+#[test]
+fn test_malformed_indices_are_rejected() {
+    assert_eq!(parse_cell("not-a-hex"), Err(HexError::InvalidIndex("not-a-hex".to_string())));
+    assert!(parse_cell("").is_err());
+    assert_eq!(parse_cell("85283473fffffff").unwrap().resolution(), Resolution::Five);
+}
+
+#[test]
+fn test_hex_cell_has_center_and_boundary() {
+    let cell = parse_cell("882a100d63fffff").unwrap();
+    let drawn = HexCell::from(cell);
+
+    assert_eq!(drawn.h3_index, "882a100d63fffff");
+    assert_eq!(drawn.resolution, 8);
+    assert_eq!(drawn.boundary.len(), 6);
+    // [longitude, latitude] near New York
+    assert!((drawn.center[0] + 73.9).abs() < 0.5 && (drawn.center[1] - 40.7).abs() < 0.5);
+}
+
+#[test]
+fn test_active_disk_starts_with_the_cell() {
+    let cell = parse_cell("882a100d63fffff").unwrap();
+    let cells = disk(cell, 1);
+    assert_eq!(cells.len(), 7);
+    assert_eq!(cells[0], cell);
+    assert_eq!(disk(cell, 0), vec![cell]);
+    // Rings are capped at three
+    assert_eq!(disk(cell, 9).len(), 37);
+
+    assert_eq!(chat_resolution(7), Ok(Resolution::Seven));
+    assert_eq!(chat_resolution(11), Err(HexError::InvalidResolution(11)));
+}
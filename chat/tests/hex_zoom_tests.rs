@@ -0,0 +1,39 @@
+use chat_service::connection_state::{ConnectionState, ProtocolError};
+use chat_service::hex::{parse_cell, zoom, HexError};
+use h3o::{LatLng, Resolution};
+
+#[test]
+fn test_zooming_out_moves_to_the_parent() {
+    let cell = parse_cell("882a100d63fffff").unwrap();
+    let parent = zoom(cell, 6, None).unwrap();
+    assert_eq!(parent.resolution(), Resolution::Six);
+    assert_eq!(cell.parent(Resolution::Six), Some(parent));
+}
+
+#[test]
+fn test_zooming_in_follows_the_fix() {
+    let cell = LatLng::new(40.7128, -74.0060).unwrap().to_cell(Resolution::Seven);
+    let off_centre = cell.children(Resolution::Nine).nth(3).unwrap();
+    let fix = LatLng::from(off_centre);
+
+    assert_eq!(zoom(cell, 9, Some(fix)).unwrap(), off_centre);
+    // Without a fix, or with one outside the cell, the centre child is used
+    assert_eq!(zoom(cell, 9, None).unwrap(), cell.center_child(Resolution::Nine).unwrap());
+    let elsewhere = LatLng::new(51.5074, -0.1278).unwrap();
+    assert_eq!(zoom(cell, 9, Some(elsewhere)).unwrap(), cell.center_child(Resolution::Nine).unwrap());
+}
+
+#[test]
+fn test_zooms_stay_within_bounds() {
+    let cell = parse_cell("882a100d63fffff").unwrap();
+    assert_eq!(zoom(cell, 2, None), Err(HexError::InvalidResolution(2)));
+    assert_eq!(zoom(cell, 12, None), Err(HexError::InvalidResolution(12)));
+    assert_eq!(zoom(cell, 16, None), Err(HexError::InvalidResolution(16)));
+
+    let mut state = ConnectionState::default();
+    assert_eq!(state.move_to("872a100d6ffffff"), Err(ProtocolError::NotJoined("resolution changes")));
+    state.authenticate("u1").unwrap();
+    state.join("882a100d63fffff").unwrap();
+    state.move_to("872a100d6ffffff").unwrap();
+    assert_eq!(state.room_id(), Some("872a100d6ffffff"));
+}
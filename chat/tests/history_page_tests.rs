@@ -0,0 +1,42 @@
+use chat_service::history_page::HistoryPaging;
+use chat_service::models::Message;
+use chrono::{Duration, Utc};
+
+// Messages sent `minutes_ago`, oldest first
+fn history(minutes_ago: &[i64]) -> Vec<Message> {
+    let now = Utc::now();
+    minutes_ago
+        .iter()
+        .map(|minutes| {
+            let mut message = Message::new("room-1".to_string(), "u1".to_string(), "alice".to_string(), format!("{}m ago", minutes));
+            message.timestamp = now - Duration::minutes(*minutes);
+            message
+        })
+        .collect()
+}
+
+#[test]
+fn test_quiet_rooms_get_the_smallest_page() {
+    let paging = HistoryPaging::new(3, 6, Duration::minutes(15));
+    let page = paging.fit(history(&[300, 240, 200, 120, 90, 60, 30]), Utc::now());
+    let contents: Vec<&str> = page.iter().map(|message| message.content.as_str()).collect();
+    assert_eq!(contents, vec!["90m ago", "60m ago", "30m ago"]);
+}
+
+#[test]
+fn test_busy_rooms_get_their_recent_conversation_up_to_the_maximum() {
+    let paging = HistoryPaging::new(2, 6, Duration::minutes(15));
+    let now = Utc::now();
+    assert_eq!(paging.page_size(&history(&[60, 14, 10, 5, 1]), now), 4);
+    assert_eq!(paging.page_size(&history(&[9, 8, 7, 6, 5, 4, 3, 2, 1]), now), 6);
+}
+
+#[test]
+fn test_page_bounds_stay_consistent() {
+    let paging = HistoryPaging::new(0, 0, Duration::minutes(15));
+    assert_eq!((paging.min_page, paging.max_page), (1, 1));
+
+    let paging = HistoryPaging::new(50, 20, Duration::minutes(15));
+    assert_eq!(paging.max_page, 50);
+    assert_eq!(paging.fit(history(&[3, 2, 1]), Utc::now()).len(), 3);
+}
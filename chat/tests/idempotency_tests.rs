@@ -0,0 +1,29 @@
+use axum::http::HeaderMap;
+use chat_service::idempotency::{parse_claim, request_key, Claim};
+use mongodb::bson::oid::ObjectId;
+
+#[test]
+fn test_header_key_wins_over_body_key() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(request_key(&headers, None), Ok(None));
+    assert_eq!(request_key(&headers, Some("body-key")), Ok(Some("body-key".to_string())));
+
+    headers.insert("idempotency-key", " header-key ".parse().unwrap());
+    assert_eq!(request_key(&headers, Some("body-key")), Ok(Some("header-key".to_string())));
+}
+
+#[test]
+fn test_empty_and_oversized_keys_are_rejected() {
+    let headers = HeaderMap::new();
+    assert!(request_key(&headers, Some("  ")).is_err());
+    assert!(request_key(&headers, Some(&"k".repeat(256))).is_err());
+    assert!(request_key(&headers, Some(&"k".repeat(255))).is_ok());
+}
+
+#[test]
+fn test_stored_claims() {
+    let id = ObjectId::new();
+    assert_eq!(parse_claim(None), Claim::New);
+    assert_eq!(parse_claim(Some("pending")), Claim::InProgress);
+    assert_eq!(parse_claim(Some(&id.to_hex())), Claim::Completed(id));
+}
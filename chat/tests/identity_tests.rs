@@ -0,0 +1,31 @@
+use chat_service::auth::AuthUser;
+use chat_service::identity::is_impersonation;
+
+fn alice() -> AuthUser {
+    AuthUser {
+        user_id: "u1".to_string(),
+        email: "alice@example.com".to_string(),
+        username: "alice".to_string(),
+        roles: vec![],
+        age_verified: false,
+        badge: None,
+    }
+}
+
+#[test]
+fn test_matching_or_omitted_author_is_accepted() {
+    assert!(!is_impersonation(&alice(), Some("u1"), Some("alice")));
+    assert!(!is_impersonation(&alice(), None, None));
+    assert!(!is_impersonation(&alice(), Some("u1"), None));
+}
+
+#[test]
+fn test_other_usernames_are_refused() {
+    assert!(is_impersonation(&alice(), Some("u1"), Some("bob")));
+    assert!(is_impersonation(&alice(), None, Some("Alice")));
+}
+
+#[test]
+fn test_other_user_ids_are_refused() {
+    assert!(is_impersonation(&alice(), Some("u2"), Some("alice")));
+}
@@ -0,0 +1,31 @@
+use chat_service::instances::InstanceInfo;
+use chrono::{Duration, Utc};
+
+#[test]
+fn instance_info_sums_sockets_and_counts_occupied_rooms() {
+    let now = Utc::now();
+    let counts = vec![("room-a".to_string(), 3), ("room-b".to_string(), 1), ("room-c".to_string(), 0)];
+    let info = InstanceInfo::new("instance-1", now - Duration::hours(1), &counts, now);
+    assert_eq!(info.instance_id, "instance-1");
+    assert_eq!(info.sockets, 4);
+    assert_eq!(info.rooms, 2);
+    assert!(!info.draining);
+    assert_eq!(info.last_seen, now);
+}
+
+#[test]
+fn instances_not_heard_from_recently_are_not_live() {
+    let now = Utc::now();
+    let info = InstanceInfo::new("instance-1", now, &[], now - Duration::seconds(30));
+    assert!(info.is_live(now));
+    let stale = InstanceInfo::new("instance-2", now, &[], now - Duration::seconds(120));
+    assert!(!stale.is_live(now));
+}
+
+#[test]
+fn registry_entries_from_older_instances_read_as_not_draining() {
+    let json = r#"{"instance_id":"a","version":"0.1.0","started_at":"2025-07-07T12:00:00Z","last_seen":"2025-07-07T12:01:00Z","sockets":2,"rooms":1}"#;
+    let info: InstanceInfo = serde_json::from_str(json).unwrap();
+    assert_eq!(info.sockets, 2);
+    assert!(!info.draining);
+}
@@ -0,0 +1,9 @@
+use chat_service::auth::secrets_match;
+
+#[test]
+fn test_internal_tokens_must_match_exactly() {
+    assert!(secrets_match("s3cret-token", "s3cret-token"));
+    assert!(!secrets_match("s3cret-tokeN", "s3cret-token"));
+    assert!(!secrets_match("s3cret", "s3cret-token"));
+    assert!(!secrets_match("", "s3cret-token"));
+}
@@ -0,0 +1,48 @@
+use axum::http::HeaderMap;
+use chat_service::{hex::lat_lng, ip_geo::IpGeoConfig};
+
+fn config() -> IpGeoConfig {
+    IpGeoConfig {
+        enabled: true,
+        latitude_header: "cf-iplatitude".to_string(),
+        longitude_header: "cf-iplongitude".to_string(),
+        max_distance_km: 500.0,
+    }
+}
+
+fn headers(lat: &str, lon: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("cf-iplatitude", lat.parse().unwrap());
+    headers.insert("cf-iplongitude", lon.parse().unwrap());
+    headers
+}
+
+#[test]
+fn test_ip_location_from_headers() {
+    let config = config();
+    let nyc = headers("40.7128", "-74.0060");
+
+    assert!(config.ip_location(&nyc).is_some());
+    assert!(config.ip_location(&HeaderMap::new()).is_none());
+    assert!(config.ip_location(&headers("not-a-number", "-74.0")).is_none());
+
+    let disabled = IpGeoConfig { enabled: false, ..config };
+    assert!(disabled.ip_location(&nyc).is_none());
+}
+
+#[test]
+fn test_mismatch_detection() {
+    let config = config();
+    let ip_location = config.ip_location(&headers("40.7128", "-74.0060"));
+
+    // Brooklyn is well within IP geolocation error, Los Angeles is not
+    assert!(!config.is_mismatch(lat_lng(40.6782, -73.9442).unwrap(), ip_location));
+    assert!(config.is_mismatch(lat_lng(34.0522, -118.2437).unwrap(), ip_location));
+}
+
+#[test]
+fn test_unknown_ip_location_is_never_flagged() {
+    let config = config();
+
+    assert!(!config.is_mismatch(lat_lng(34.0522, -118.2437).unwrap(), None));
+}
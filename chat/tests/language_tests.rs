@@ -0,0 +1,84 @@
+use axum::{http::StatusCode, response::IntoResponse};
+use chat_service::{
+    auth::AuthUser,
+    language::{normalize_language, tag_message},
+    models::{ChatRoom, Message},
+    room_roles::authorize,
+};
+
+fn message(content: &str) -> Message {
+    Message::new(
+        "test-room".to_string(),
+        "user123".to_string(),
+        "testuser".to_string(),
+        content.to_string(),
+    )
+}
+
+#[test]
+fn test_normalize_language() {
+    assert_eq!(normalize_language("eng"), Some("eng".to_string()));
+    assert_eq!(normalize_language(" SPA "), Some("spa".to_string()));
+    assert_eq!(normalize_language("klingon"), None);
+}
+
+#[test]
+fn test_tag_message_without_room_language() {
+    let mut msg = message("Bonjour tout le monde, comment allez-vous aujourd'hui ?");
+    tag_message(&mut msg, None);
+
+    assert_eq!(msg.language, None);
+    assert!(!msg.off_language);
+}
+
+#[test]
+fn test_tag_message_matching_language() {
+    let mut msg = message("Does anyone know if the farmers market is open this weekend?");
+    tag_message(&mut msg, Some("eng"));
+
+    assert_eq!(msg.language.as_deref(), Some("eng"));
+    assert!(!msg.off_language);
+}
+
+#[test]
+fn test_tag_message_off_language() {
+    let mut msg = message("¿Alguien sabe si el mercado de agricultores abre este fin de semana?");
+    tag_message(&mut msg, Some("eng"));
+
+    assert_eq!(msg.language.as_deref(), Some("spa"));
+    assert!(msg.off_language);
+}
+
+#[test]
+fn test_short_messages_are_not_tagged() {
+    let mut msg = message("ok lol");
+    tag_message(&mut msg, Some("eng"));
+
+    assert_eq!(msg.language, None);
+    assert!(!msg.off_language);
+}
+
+fn user(user_id: &str, roles: &[&str]) -> AuthUser {
+    AuthUser {
+        user_id: user_id.to_string(),
+        email: format!("{}@example.com", user_id),
+        username: user_id.to_string(),
+        roles: roles.iter().map(|role| role.to_string()).collect(),
+        age_verified: true,
+        badge: None,
+    }
+}
+
+// The check set_room_language runs on the room before writing
+#[test]
+fn test_only_moderators_set_the_room_language() {
+    let mut room = ChatRoom::new("test-room");
+    room.owner = Some("owner".to_string());
+    room.moderators = vec!["mod".to_string()];
+
+    let refused = authorize(&room, &user("member", &[]), None).unwrap_err();
+    assert_eq!(refused.into_response().status(), StatusCode::FORBIDDEN);
+    for allowed in [user("owner", &[]), user("mod", &[]), user("staff", &["moderator"])] {
+        assert!(authorize(&room, &allowed, None).is_ok());
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::Arc;
+
+use chat_service::low_data::{self, EventBatch, HISTORY_LIMIT};
+use chat_service::models::{Message, RsvpCounts, WsMessage};
+use chat_service::send_buffer::{SendBuffers, SlowConsumerPolicy};
+
+fn message(content: &str) -> Message {
+    Message::new("room1".into(), "u1".into(), "alice".into(), content.into())
+}
+
+#[test]
+fn test_history_keeps_the_newest_page_and_drops_the_delta_marker() {
+    let messages: Vec<Message> = (0..HISTORY_LIMIT + 5).map(|i| message(&i.to_string())).collect();
+    let since = Some(chrono::Utc::now());
+    match low_data::strip(WsMessage::MessageHistory { messages, since }) {
+        WsMessage::MessageHistory { messages, since } => {
+            assert_eq!(messages.len(), HISTORY_LIMIT);
+            assert_eq!(messages.first().unwrap().content, "5");
+            assert_eq!(messages.last().unwrap().content, (HISTORY_LIMIT + 4).to_string());
+            assert!(since.is_none());
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn test_batch_keeps_only_the_latest_update_per_message() {
+    let mut batch = EventBatch::default();
+    let rsvp = |going| WsMessage::RsvpUpdated { message_id: "m1".into(), counts: RsvpCounts { going, maybe: 0, no: 0 } };
+    batch.add(rsvp(1));
+    batch.add(WsMessage::RsvpUpdated { message_id: "m2".into(), counts: RsvpCounts::default() });
+    batch.add(rsvp(3));
+    let held = batch.take();
+    assert_eq!(held.len(), 2);
+    assert!(matches!(&held[0], WsMessage::RsvpUpdated { message_id, counts } if message_id == "m1" && counts.going == 3));
+    assert!(batch.is_empty());
+    assert!(!low_data::is_batched(&WsMessage::NewMessage(message("hi"))));
+}
+
+#[test]
+fn test_low_data_sockets_get_no_typing_or_presence() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, mut rx) = buffers.channel();
+    tx.set_low_data(true);
+    tx.send(WsMessage::Typing { is_typing: true }).unwrap();
+    tx.send(WsMessage::UserLeft { username: "bob".into(), timestamp: chrono::Utc::now() }).unwrap();
+    tx.send(WsMessage::NewMessage(message("hi"))).unwrap();
+    assert!(matches!(rx.try_recv(), Ok(WsMessage::NewMessage(_))));
+    assert!(rx.try_recv().is_err());
+    assert!(rx.is_low_data());
+}
@@ -0,0 +1,48 @@
+use chat_service::message_body::{MessageBody, VersionedBody, SCHEMA_VERSION};
+use chat_service::models::{EventDetails, Message, MessageKind, RsvpCounts};
+use mongodb::bson::{self, doc};
+
+fn message(content: &str) -> Message {
+    Message::new("room1".into(), "u1".into(), "alice".into(), content.into())
+}
+
+#[test]
+fn test_bodies_are_stored_as_tagged_versioned_documents() {
+    let mut event = message("Picnic at noon");
+    event.kind = MessageKind::Event;
+    event.event = Some(EventDetails { title: "Picnic".into(), starts_at: None, rsvp_counts: RsvpCounts::default() });
+    event.body = Some(VersionedBody::new(event.body()));
+
+    let stored = bson::to_document(&event).unwrap();
+    let body = stored.get_document("body").unwrap();
+    assert_eq!(body.get_str("kind").unwrap(), "event");
+    assert_eq!(body.get("schema_version").and_then(bson::Bson::as_i64), Some(SCHEMA_VERSION as i64));
+
+    let read: Message = bson::from_document(stored).unwrap();
+    assert_eq!(read.body(), MessageBody::Event { title: "Picnic".into(), starts_at: None });
+}
+
+#[test]
+fn test_unknown_kinds_and_newer_schemas_still_read() {
+    let mut stored = bson::to_document(&message("What's for lunch?")).unwrap();
+    stored.insert("kind", "quiz");
+    stored.insert("body", doc! { "schema_version": 1, "kind": "quiz", "question": "What's for lunch?" });
+    let read: Message = bson::from_document(stored.clone()).unwrap();
+    assert_eq!(read.kind, MessageKind::Other);
+    assert_eq!(read.body(), MessageBody::Unknown);
+
+    stored.insert("kind", "text");
+    stored.insert("body", doc! { "schema_version": SCHEMA_VERSION + 1, "kind": "text", "text": 42 });
+    let read: Message = bson::from_document(stored).unwrap();
+    assert_eq!(read.body.map(|body| body.schema_version), Some(SCHEMA_VERSION + 1));
+    assert_eq!(read.content, "What's for lunch?");
+}
+
+#[test]
+fn test_messages_stored_without_a_body_get_one_from_their_fields() {
+    assert_eq!(message("hi").body(), MessageBody::Text { text: "hi".into() });
+    let mut announcement = message("Road closed");
+    announcement.kind = MessageKind::Announcement;
+    assert_eq!(announcement.body(), MessageBody::System { text: "Road closed".into() });
+    assert!(announcement.body().is_known());
+}
@@ -0,0 +1,56 @@
+use chat_service::content_filter::ContentRating;
+use chat_service::message_filters::{pipeline, run, BlocklistRule, FilterAction, FilterRules, UrlRule};
+use chat_service::models::Message;
+
+fn message(content: &str) -> Message {
+    Message::new("room-1".to_string(), "u1".to_string(), "alice".to_string(), content.to_string())
+}
+
+fn blocklist(pattern: &str, action: FilterAction) -> FilterRules {
+    FilterRules { blocklist: vec![BlocklistRule { pattern: pattern.to_string(), action }], max_urls: None }
+}
+
+#[test]
+fn test_rules_reject_flag_or_mask() {
+    let mut rejected = message("Get FREE   crypto now");
+    let rules = blocklist(r"free\s+crypto", FilterAction::Reject);
+    assert!(run(&pipeline(ContentRating::Mature, &rules), &mut rejected).is_err());
+
+    let mut flagged = message("free crypto");
+    let rules = blocklist(r"free\s+crypto", FilterAction::Flag);
+    let verdict = run(&pipeline(ContentRating::Mature, &rules), &mut flagged).unwrap();
+    assert_eq!(verdict.flags.len(), 1);
+    assert_eq!(flagged.content, "free crypto");
+
+    let mut masked = message("call 555-1234 now");
+    let rules = blocklist(r"\d{3}-\d{4}", FilterAction::Mask);
+    let verdict = run(&pipeline(ContentRating::Mature, &rules), &mut masked).unwrap();
+    assert!(verdict.masked && verdict.flags.is_empty());
+    assert_eq!(masked.content, "call ******** now");
+}
+
+#[test]
+fn test_links_beyond_the_limit_are_removed() {
+    let rules = FilterRules { blocklist: Vec::new(), max_urls: Some(UrlRule { max: 1, action: FilterAction::Mask }) };
+    let mut masked = message("see https://a.example and  www.b.example\nor http://c.example");
+    run(&pipeline(ContentRating::Mature, &rules), &mut masked).unwrap();
+    assert_eq!(masked.content, "see https://a.example and  [link removed]\nor [link removed]");
+
+    let rules = FilterRules { blocklist: Vec::new(), max_urls: Some(UrlRule { max: 1, action: FilterAction::Reject }) };
+    let mut within = message("just https://a.example");
+    assert!(!run(&pipeline(ContentRating::Mature, &rules), &mut within).unwrap().hit());
+}
+
+#[test]
+fn test_profanity_runs_first_and_rules_are_checked() {
+    let rules = blocklist("shit", FilterAction::Reject);
+    let mut masked = message("oh shit");
+    // The rating masks the word before the room's rule can see it
+    let verdict = run(&pipeline(ContentRating::Everyone, &rules), &mut masked).unwrap();
+    assert!(verdict.masked);
+    assert_eq!(masked.content, "oh s***");
+
+    assert!(blocklist("(unclosed", FilterAction::Reject).validate().is_err());
+    assert!(blocklist("", FilterAction::Reject).validate().is_err());
+    assert!(blocklist("fine", FilterAction::Flag).validate().is_ok());
+}
@@ -0,0 +1,33 @@
+use chat_service::message_retention::{cutoff, validate_retention, RetentionPolicy, MAX_RETENTION_DAYS};
+use chat_service::models::RoomSettings;
+use chrono::{Duration, Utc};
+
+fn settings(retention_days: Option<u32>) -> RoomSettings {
+    RoomSettings { retention_days, ..Default::default() }
+}
+
+#[test]
+fn test_retention_is_bounded() {
+    assert_eq!(validate_retention(0), Ok(0));
+    assert_eq!(validate_retention(30), Ok(30));
+    assert!(validate_retention(MAX_RETENTION_DAYS + 1).is_err());
+}
+
+#[test]
+fn test_location_rooms_follow_the_default() {
+    let policy = RetentionPolicy { location_room_days: Some(30) };
+    let hex = "882a100d63fffff";
+    assert_eq!(policy.retention_days(hex, &settings(None)), Some(30));
+    assert_eq!(policy.retention_days("40.7580_-73.9855", &settings(None)), Some(30));
+    assert_eq!(policy.retention_days("book-club", &settings(None)), None);
+    assert_eq!(policy.retention_days(hex, &settings(Some(7))), Some(7));
+    assert_eq!(policy.retention_days(hex, &settings(Some(0))), None);
+    assert_eq!(policy.retention_days("book-club", &settings(Some(7))), Some(7));
+    assert_eq!(RetentionPolicy { location_room_days: None }.retention_days(hex, &settings(None)), None);
+}
+
+#[test]
+fn test_cutoff_is_days_before_now() {
+    let now = Utc::now();
+    assert_eq!(cutoff(now, 14), now - Duration::days(14));
+}
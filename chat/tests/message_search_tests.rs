@@ -0,0 +1,40 @@
+use chat_service::message_search::{SearchQuery, DEFAULT_SEARCH_LIMIT, MAX_SEARCH_LIMIT, MAX_SEARCH_PAGE};
+use chrono::{Duration, Utc};
+use mongodb::bson::doc;
+
+fn query(q: &str) -> SearchQuery {
+    SearchQuery { q: q.to_string(), ..Default::default() }
+}
+
+#[test]
+fn test_search_needs_a_query() {
+    assert!(query("coffee").validate().is_ok());
+    assert!(query("   ").validate().is_err());
+    assert!(query(&"a".repeat(201)).validate().is_err());
+
+    let now = Utc::now();
+    let backwards = SearchQuery { from: Some(now), to: Some(now - Duration::hours(1)), ..query("coffee") };
+    assert!(backwards.validate().is_err());
+}
+
+#[test]
+fn test_pages_and_limits_are_bounded() {
+    assert_eq!(query("coffee").page(), 1);
+    assert_eq!(query("coffee").limit(), DEFAULT_SEARCH_LIMIT);
+    assert_eq!(SearchQuery { limit: Some(1000), ..query("coffee") }.limit(), MAX_SEARCH_LIMIT);
+    assert!(SearchQuery { page: Some(0), ..query("coffee") }.validate().is_err());
+    assert!(SearchQuery { page: Some(MAX_SEARCH_PAGE + 1), ..query("coffee") }.validate().is_err());
+}
+
+#[test]
+fn test_filter_is_limited_to_the_callers_rooms() {
+    let rooms = vec!["room-1".to_string(), "room-2".to_string()];
+    let filter = SearchQuery { user_id: Some("alice".to_string()), ..query(" coffee ") }.filter(&rooms, "bob");
+    assert_eq!(filter.get_document("$text").unwrap(), &doc! { "$search": "coffee" });
+    assert_eq!(filter.get_document("room_id").unwrap(), &doc! { "$in": ["room-1", "room-2"] });
+    assert_eq!(filter.get_str("user_id").unwrap(), "alice");
+    assert!(!filter.get_bool("deleted").unwrap());
+    assert!(!filter.contains_key("timestamp"));
+    // Shadow-banned messages are left out, except the searcher's own
+    assert_eq!(filter.get_array("$or").unwrap().len(), 2);
+}
@@ -0,0 +1,47 @@
+use chat_service::message_ttl::{by_room, stamp, validate_ttl, MAX_TTL_SECONDS, MIN_TTL_SECONDS};
+use chat_service::models::Message;
+use chrono::Duration;
+use mongodb::bson::oid::ObjectId;
+
+fn message(room_id: &str, ttl_seconds: u32) -> Message {
+    let mut message = Message::new(room_id.to_string(), "u1".to_string(), "alice".to_string(), "gone soon".to_string());
+    message.id = Some(ObjectId::new());
+    stamp(&mut message, Some(ttl_seconds));
+    message
+}
+
+#[test]
+fn test_message_lifetime_bounds() {
+    assert_eq!(validate_ttl(0), Ok(None));
+    assert_eq!(validate_ttl(MIN_TTL_SECONDS), Ok(Some(MIN_TTL_SECONDS)));
+    assert!(validate_ttl(MIN_TTL_SECONDS - 1).is_err());
+    assert!(validate_ttl(MAX_TTL_SECONDS + 1).is_err());
+}
+
+#[test]
+fn test_expiry_survives_the_broadcast_round_trip() {
+    let sent = message("room-1", 300);
+    assert_eq!(sent.expires_at, Some(sent.timestamp + Duration::seconds(300)));
+
+    let received: Message = serde_json::from_str(&serde_json::to_string(&sent).unwrap()).unwrap();
+    assert_eq!(
+        received.expires_at.map(|at| at.timestamp_millis()),
+        sent.expires_at.map(|at| at.timestamp_millis())
+    );
+
+    let mut lasting = message("room-1", 300);
+    stamp(&mut lasting, None);
+    assert!(!serde_json::to_string(&lasting).unwrap().contains("expires_at"));
+}
+
+#[test]
+fn test_expiring_messages_are_grouped_by_room_soonest_first() {
+    let later = message("room-1", 120);
+    let sooner = message("room-1", 60);
+    let other = message("room-2", 90);
+
+    let rooms = by_room(&[later.clone(), other, sooner.clone()]);
+    assert_eq!(rooms.len(), 2);
+    let ids: Vec<String> = rooms["room-1"].iter().map(|expiring| expiring.message_id.clone()).collect();
+    assert_eq!(ids, vec![sooner.id.unwrap().to_hex(), later.id.unwrap().to_hex()]);
+}
@@ -0,0 +1,39 @@
+use chat_service::migrations::{message_field_defaults, Migration};
+use chat_service::models::Message;
+use mongodb::bson::{doc, oid::ObjectId};
+
+#[test]
+fn test_migrations_are_found_by_name() {
+    for migration in Migration::ALL {
+        assert_eq!(Migration::parse(migration.name()), Some(migration));
+    }
+    assert_eq!(Migration::parse("backfill_message_fields"), Some(Migration::BackfillMessageFields));
+    assert_eq!(Migration::parse("drop_everything"), None);
+}
+
+#[test]
+fn test_backfilled_legacy_message_matches_new_messages() {
+    let mut legacy = doc! {
+        "_id": ObjectId::new(),
+        "room_id": "room-1",
+        "user_id": "u1",
+        "username": "alice",
+        "content": "hello",
+        "timestamp": mongodb::bson::DateTime::now(),
+    };
+    for (field, default) in message_field_defaults() {
+        legacy.insert(field, default);
+    }
+    let backfilled: Message = mongodb::bson::from_document(legacy).unwrap();
+
+    assert!(!backfilled.deleted);
+    assert!(backfilled.reactions.is_empty());
+}
+
+#[test]
+fn test_backfill_covers_fields_queried_by_value() {
+    // Queries such as `deleted: false` skip documents that lack the field
+    let fields: Vec<&str> = message_field_defaults().into_iter().map(|(field, _)| field).collect();
+    assert!(fields.contains(&"deleted"));
+    assert!(fields.contains(&"reactions"));
+}
@@ -0,0 +1,60 @@
+use std::sync::{Arc, RwLock};
+
+use chat_service::fanout::{Delivery, Subscriber};
+use chat_service::hex::{neighbors, parse_cell};
+use chat_service::models::{Message, WsMessage};
+use chat_service::send_buffer::{SendBuffers, SlowConsumerPolicy};
+use chat_service::subscription_filter::{FanoutFilter, SubscriptionFilter};
+use chat_service::{joinable_neighbors, AppState};
+
+#[test]
+fn test_neighbors_are_the_first_ring() {
+    let cell = parse_cell("882a100d63fffff").unwrap();
+    let ring = neighbors(cell, 1);
+    assert_eq!(ring.len(), 6);
+    assert!(!ring.contains(&cell));
+    assert!(neighbors(cell, 0).is_empty());
+    // Wider rings are capped at one
+    assert_eq!(neighbors(cell, 3), ring);
+}
+
+#[tokio::test]
+async fn test_neighbor_messages_are_tagged_with_their_hex() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, mut rx) = buffers.channel();
+    let filter = Arc::new(RwLock::new(SubscriptionFilter::new(FanoutFilter::All)));
+    let subscriber = Subscriber::neighbor(tx, filter, "882a100d67fffff".to_string());
+
+    let message = Message::new("882a100d67fffff".to_string(), "u1".to_string(), "alice".to_string(), "hi".to_string());
+    assert_eq!(subscriber.deliver(WsMessage::NewMessage(message)), Delivery::Sent);
+    match rx.recv().await {
+        Some(WsMessage::NeighborMessage { h3_index, message }) => {
+            assert_eq!(h3_index, "882a100d67fffff");
+            assert_eq!(message.content, "hi");
+        }
+        other => panic!("expected a NeighborMessage, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_neighbor_presence_and_typing_are_not_forwarded() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, mut rx) = buffers.channel();
+    let filter = Arc::new(RwLock::new(SubscriptionFilter::new(FanoutFilter::All)));
+    let subscriber = Subscriber::neighbor(tx, filter, "882a100d67fffff".to_string());
+
+    assert_eq!(subscriber.deliver(WsMessage::Typing { is_typing: true }), Delivery::Filtered);
+    let joined = WsMessage::UserJoined { username: "bob".to_string(), timestamp: chrono::Utc::now() };
+    assert_eq!(subscriber.deliver(joined), Delivery::Filtered);
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_neighbours_that_cant_be_checked_are_left_out() {
+    // Without MongoDB no neighbour's bans, visibility or rating can be read
+    let state = AppState::new("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=50", "redis://127.0.0.1:1", "neighbor_hex_tests")
+        .await
+        .unwrap();
+    let ring: Vec<String> = neighbors(parse_cell("882a100d63fffff").unwrap(), 1).iter().map(ToString::to_string).collect();
+    assert!(joinable_neighbors(&state, ring, "u1", None).await.is_empty());
+}
@@ -0,0 +1,37 @@
+use chat_service::models::Message;
+use chat_service::notifications::{NotificationCategory, NotificationHint, NotificationPriority, NotificationSettings};
+
+fn message(content: &str) -> Message {
+    Message::new("room-1".to_string(), "u1".to_string(), "alice".to_string(), content.to_string())
+}
+
+#[test]
+fn test_plain_room_messages_only_badge() {
+    let hint = NotificationHint::for_room_message(&message("hi"), &NotificationSettings::default());
+    assert_eq!(hint.category, NotificationCategory::Message);
+    assert_eq!(hint.priority, NotificationPriority::Low);
+    assert_eq!(hint.sound, None);
+    assert_eq!(hint.collapse_key, "room:room-1");
+}
+
+#[test]
+fn test_room_mentions_use_the_room_sound() {
+    let mut mention = message("@everyone meet at the fountain");
+    mention.mentions_everyone = true;
+    let settings = NotificationSettings { sound: Some("chime".to_string()), silent: false };
+    let hint = NotificationHint::for_room_message(&mention, &settings);
+    assert_eq!(hint.category, NotificationCategory::RoomMention);
+    assert_eq!(hint.priority, NotificationPriority::High);
+    assert_eq!(hint.sound.as_deref(), Some("chime"));
+    assert_eq!(hint.collapse_key, "room_mention:room-1");
+}
+
+#[test]
+fn test_silent_rooms_never_alert() {
+    let mut mention = message("@here");
+    mention.mentions_everyone = true;
+    let settings = NotificationSettings { sound: Some("chime".to_string()), silent: true };
+    let hint = NotificationHint::for_room_message(&mention, &settings);
+    assert_eq!(hint.sound, None);
+    assert_eq!(hint.priority, NotificationPriority::Normal);
+}
@@ -0,0 +1,61 @@
+use chat_service::auth::AuthUser;
+use chat_service::models::{ChatRoom, Message, MessageKind};
+use chat_service::permissions::{message_actions, RoomAction, RoomPermissions};
+use chat_service::room_roles::authorize;
+
+fn message(content: &str) -> Message {
+    Message::new("room".to_string(), "user".to_string(), "alice".to_string(), content.to_string())
+}
+
+#[test]
+fn test_message_actions_are_detected() {
+    let msg = message("see https://example.com/a, https://cdn.example.com/cat.GIF?w=2 @everyone!");
+
+    assert_eq!(
+        message_actions(&msg),
+        vec![RoomAction::PostLinks, RoomAction::PostMedia, RoomAction::MentionEveryone]
+    );
+    assert!(message_actions(&message("email me at alice@here.com")).is_empty());
+}
+
+#[test]
+fn test_members_are_held_to_room_permissions() {
+    let permissions = RoomPermissions { post_links: false, ..Default::default() };
+    let link = message("www.example.com");
+
+    assert_eq!(permissions.check(&link, false), Err(RoomAction::PostLinks));
+    assert_eq!(permissions.check(&link, true), Ok(()));
+    assert_eq!(RoomPermissions::default().check(&message("@here lunch?"), false), Err(RoomAction::MentionEveryone));
+}
+
+#[test]
+fn test_events_count_as_polls() {
+    let mut event = message("Picnic");
+    event.kind = MessageKind::Event;
+    let permissions = RoomPermissions { create_polls: false, ..Default::default() };
+
+    assert_eq!(permissions.check(&event, false), Err(RoomAction::CreatePolls));
+}
+
+fn user(user_id: &str) -> AuthUser {
+    AuthUser {
+        user_id: user_id.to_string(),
+        email: format!("{}@example.com", user_id),
+        username: user_id.to_string(),
+        roles: vec![],
+        age_verified: true,
+        badge: None,
+    }
+}
+
+// The check update_room_settings runs on the room before editing it
+#[test]
+fn test_room_owners_edit_their_rooms_permissions() {
+    let mut room = ChatRoom::new("room");
+    room.owner = Some("owner".to_string());
+
+    assert!(authorize(&room, &user("owner"), None).is_ok());
+    assert!(authorize(&room, &user("member"), None).is_err());
+    // Owning one room doesn't reach another
+    assert!(authorize(&ChatRoom::new("other-room"), &user("owner"), None).is_err());
+}
@@ -0,0 +1,46 @@
+use chat_service::presence::nearby_rooms;
+use h3o::{CellIndex, LatLng, Resolution};
+
+#[test]
+fn test_full_hex_suggests_its_neighbours() {
+    let cell = LatLng::new(40.7580, -73.9855).unwrap().to_cell(Resolution::Eight);
+    let nearby = nearby_rooms(&cell.to_string());
+
+    assert_eq!(nearby.len(), 6);
+    assert!(!nearby.contains(&cell.to_string()));
+    for room in &nearby {
+        let neighbour: CellIndex = room.parse().unwrap();
+        assert_eq!(cell.grid_distance(neighbour), Ok(1));
+    }
+}
+
+#[test]
+fn test_full_legacy_room_suggests_its_hex_too() {
+    let nearby = nearby_rooms("40.758_-73.9855");
+    let containing = LatLng::new(40.758, -73.9855).unwrap().to_cell(Resolution::Eight);
+
+    assert_eq!(nearby.len(), 7);
+    assert!(nearby.contains(&containing.to_string()));
+}
+
+#[test]
+fn test_unknown_rooms_have_no_suggestions() {
+    assert!(nearby_rooms("general").is_empty());
+}
+
+#[test]
+fn test_room_users_lists_each_user_once_by_earliest_join() {
+    use chat_service::presence::{dedupe_users, RoomUser};
+    use chrono::{Duration, Utc};
+
+    let now = Utc::now();
+    let user = |id: &str, minutes_ago: i64| RoomUser {
+        id: id.to_string(),
+        username: id.to_string(),
+        joined_at: now - Duration::minutes(minutes_ago),
+        badge: None,
+    };
+    let users = dedupe_users(vec![user("bob", 1), user("alice", 5), user("bob", 10)]);
+
+    assert_eq!(users, vec![user("bob", 10), user("alice", 5)]);
+}
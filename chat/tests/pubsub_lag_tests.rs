@@ -0,0 +1,46 @@
+use chat_service::pubsub_lag::{percentile, room_of_channel, LagWindow, PubSubLag, LAG_WINDOW};
+
+#[test]
+fn test_percentiles_use_nearest_rank() {
+    let sorted: Vec<u64> = (1..=100).collect();
+    assert_eq!(percentile(&sorted, 0.5), 50);
+    assert_eq!(percentile(&sorted, 0.99), 99);
+    assert_eq!(percentile(&sorted, 1.0), 100);
+    assert_eq!(percentile(&[], 0.5), 0);
+}
+
+#[test]
+fn test_windows_keep_only_recent_samples() {
+    let mut window = LagWindow::new(LAG_WINDOW);
+    for _ in 0..LAG_WINDOW {
+        window.record(1000);
+    }
+    for _ in 0..LAG_WINDOW {
+        window.record(5);
+    }
+    let summary = window.summary();
+    assert_eq!(summary.samples, LAG_WINDOW);
+    assert_eq!(summary.max_ms, 5);
+}
+
+#[test]
+fn test_lag_is_tracked_per_room() {
+    assert_eq!(room_of_channel("room:40.7_-74.0"), Some("40.7_-74.0"));
+    assert_eq!(room_of_channel("hex:8a2a1072b59ffff"), Some("hex:8a2a1072b59ffff"));
+    assert_eq!(room_of_channel("user:u1"), None);
+
+    let lag = PubSubLag::new();
+    lag.record("room:slow", 1_000, 1_400);
+    lag.record("room:fast", 1_000, 1_010);
+    // Clock skew can put delivery before publishing
+    lag.record("room:fast", 1_000, 990);
+    lag.record("user:u1", 1_000, 9_000);
+    lag.record("room:unstamped", 0, 1_000);
+
+    let rooms = lag.worst_rooms(10);
+    let ids: Vec<&str> = rooms.iter().map(|room| room.room_id.as_str()).collect();
+    assert_eq!(ids, vec!["slow", "fast"]);
+    assert_eq!(rooms[0].lag.p99_ms, 400);
+    assert_eq!(rooms[1].lag.p50_ms, 0);
+    assert_eq!(lag.instance_summary().samples, 3);
+}
@@ -0,0 +1,49 @@
+use std::sync::Arc;
+
+use chat_service::pubsub::PubSubMultiplexer;
+
+#[tokio::test]
+async fn test_payloads_reach_every_socket_on_the_channel() {
+    let pubsub = Arc::new(PubSubMultiplexer::new());
+    let mut first = pubsub.subscribe("room:40.7_-74.0");
+    let mut second = pubsub.subscribe("room:40.7_-74.0");
+    assert_eq!(pubsub.channel_count(), 1);
+
+    pubsub.dispatch("room:40.7_-74.0", "hello".into());
+    assert_eq!(&*first.recv().await.unwrap(), "hello");
+    assert_eq!(&*second.recv().await.unwrap(), "hello");
+}
+
+#[tokio::test]
+async fn test_other_channels_are_not_delivered() {
+    let pubsub = Arc::new(PubSubMultiplexer::new());
+    let mut room = pubsub.subscribe("room:a");
+
+    pubsub.dispatch("room:b", "not for you".into());
+    pubsub.dispatch("room:a", "for you".into());
+    assert_eq!(&*room.recv().await.unwrap(), "for you");
+}
+
+// Hex broadcasts from other instances arrive through the same connection
+#[tokio::test]
+async fn test_hex_payloads_reach_hex_sockets_only() {
+    let pubsub = Arc::new(PubSubMultiplexer::new());
+    let mut hex = pubsub.subscribe("hex:8a2a1072b59ffff");
+    let mut room = pubsub.subscribe("room:8a2a1072b59ffff");
+
+    pubsub.dispatch("hex:8a2a1072b59ffff", "from another instance".into());
+    assert_eq!(&*hex.recv().await.unwrap(), "from another instance");
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(20), room.recv()).await.is_err());
+}
+
+#[test]
+fn test_channel_is_forgotten_after_last_socket_leaves() {
+    let pubsub = Arc::new(PubSubMultiplexer::new());
+    let first = pubsub.subscribe("hex:8a2a1072b59ffff");
+    let second = pubsub.subscribe("hex:8a2a1072b59ffff");
+
+    drop(first);
+    assert_eq!(pubsub.channel_count(), 1);
+    drop(second);
+    assert_eq!(pubsub.channel_count(), 0);
+}
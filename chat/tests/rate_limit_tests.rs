@@ -0,0 +1,16 @@
+use chat_service::models::WsMessage;
+use chat_service::rate_limit::retry_after_seconds;
+
+#[test]
+fn test_retry_after_rounds_up_to_whole_seconds() {
+    assert_eq!(retry_after_seconds(1), 1);
+    assert_eq!(retry_after_seconds(1000), 1);
+    assert_eq!(retry_after_seconds(5_001), 6);
+}
+
+#[test]
+fn test_rate_limited_is_a_structured_message() {
+    let json = serde_json::to_value(WsMessage::RateLimited { retry_after: 6 }).unwrap();
+
+    assert_eq!(json, serde_json::json!({ "type": "RateLimited", "data": { "retry_after": 6 } }));
+}
@@ -0,0 +1,53 @@
+use chat_service::models::Reaction;
+use chat_service::reactions::{
+    reaction_counts, validate_emoji, ReactionCoalescer, ReactionCount, ReactionLimiter, COALESCE_INTERVAL_MS,
+    REACTIONS_PER_WINDOW, REACTION_WINDOW_MS,
+};
+
+fn reaction(user_id: &str, emoji: &str) -> Reaction {
+    Reaction { user_id: user_id.to_string(), emoji: emoji.to_string() }
+}
+
+#[test]
+fn test_reaction_limit_is_per_user_and_window() {
+    let limiter = ReactionLimiter::default();
+    let start = 5 * REACTION_WINDOW_MS;
+    for _ in 0..REACTIONS_PER_WINDOW {
+        assert!(limiter.check("room1", "u1", start).is_ok());
+    }
+    assert_eq!(limiter.check("room1", "u1", start + 2_500), Err(8));
+    assert!(limiter.check("room1", "u2", start).is_ok());
+    assert!(limiter.check("room2", "u1", start).is_ok());
+    assert!(limiter.check("room1", "u1", start + REACTION_WINDOW_MS).is_ok());
+}
+
+#[test]
+fn test_busy_messages_are_coalesced() {
+    let coalescer = ReactionCoalescer::default();
+    assert!(coalescer.record("room1", "m1", 0));
+    assert!(!coalescer.record("room1", "m1", 100));
+    assert!(!coalescer.record("room1", "m1", 200));
+    assert!(coalescer.due(500).is_empty());
+
+    assert_eq!(coalescer.due(COALESCE_INTERVAL_MS + 100), vec![("room1".to_string(), "m1".to_string())]);
+    assert!(coalescer.due(COALESCE_INTERVAL_MS + 200).is_empty());
+    // Quiet for an interval, so the next change goes straight out
+    assert!(coalescer.record("room1", "m1", 3 * COALESCE_INTERVAL_MS));
+}
+
+#[test]
+fn test_counts_and_emoji_validation() {
+    let counts = reaction_counts(&[reaction("u1", "👍"), reaction("u2", "🎉"), reaction("u3", "👍")]);
+    assert_eq!(
+        counts,
+        vec![
+            ReactionCount { emoji: "👍".to_string(), count: 2 },
+            ReactionCount { emoji: "🎉".to_string(), count: 1 },
+        ]
+    );
+
+    assert!(validate_emoji("👍").is_ok());
+    assert!(validate_emoji("").is_err());
+    assert!(validate_emoji("a b").is_err());
+    assert!(validate_emoji(&"x".repeat(33)).is_err());
+}
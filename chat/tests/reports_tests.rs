@@ -0,0 +1,44 @@
+use chat_service::models::Message;
+use chat_service::reports::{ReportRequest, ReportStatus};
+use mongodb::bson::oid::ObjectId;
+
+fn message() -> Message {
+    let mut message = Message::new("room-1".to_string(), "author".to_string(), "alice".to_string(), "buy now".to_string());
+    message.id = Some(ObjectId::new());
+    message
+}
+
+fn request(reason: &str) -> ReportRequest {
+    ReportRequest { reason: reason.to_string() }
+}
+
+#[test]
+fn test_reports_copy_the_message() {
+    let message = message();
+    let report = request("  spam  ").into_report(&message, "reporter").unwrap();
+    assert_eq!(report.id, format!("{}:reporter", message.id.unwrap().to_hex()));
+    assert_eq!(report.author_id, "author");
+    assert_eq!(report.content, "buy now");
+    assert_eq!(report.reason, "spam");
+    assert_eq!(report.status, ReportStatus::Open);
+    assert_eq!(report.resolution, None);
+}
+
+#[test]
+fn test_report_requests_are_checked() {
+    let message = message();
+    assert!(request("spam").into_report(&message, "author").is_err());
+    assert!(request("   ").into_report(&message, "reporter").is_err());
+    assert!(request(&"x".repeat(501)).into_report(&message, "reporter").is_err());
+
+    let mut unsaved = message.clone();
+    unsaved.id = None;
+    assert!(request("spam").into_report(&unsaved, "reporter").is_err());
+}
+
+#[test]
+fn test_statuses_filter_as_snake_case() {
+    assert_eq!(serde_json::to_string(&ReportStatus::default()).unwrap(), "\"open\"");
+    let status: ReportStatus = serde_json::from_str("\"resolved\"").unwrap();
+    assert_eq!(status, ReportStatus::Resolved);
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use chat_service::fanout::Subscriber;
+use chat_service::models::WsMessage;
+use chat_service::room_bans::{banned_notice, BanRequest};
+use chat_service::room_invites::removed_notice;
+use chat_service::send_buffer::{SendBuffers, SlowConsumerPolicy};
+
+fn request(user_id: &str, reason: Option<&str>) -> BanRequest {
+    BanRequest { user_id: user_id.to_string(), reason: reason.map(str::to_string) }
+}
+
+#[test]
+fn test_bans_are_keyed_by_room_and_user() {
+    let ban = request("u2", Some("  spamming links  ")).into_ban("room-1", "mod-1").unwrap();
+    assert_eq!(ban.id, "room-1:u2");
+    assert_eq!(ban.reason.as_deref(), Some("spamming links"));
+
+    let ban = request("u2", Some("   ")).into_ban("room-1", "mod-1").unwrap();
+    assert_eq!(ban.reason, None);
+}
+
+#[test]
+fn test_ban_requests_are_checked() {
+    assert!(request(" ", None).into_ban("room-1", "mod-1").is_err());
+    assert!(request("u2", Some(&"x".repeat(201))).into_ban("room-1", "mod-1").is_err());
+}
+
+#[test]
+fn test_sockets_know_who_they_joined_as() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, rx) = buffers.channel();
+    assert_eq!(rx.user_id(), None);
+
+    tx.set_user("u2");
+    tx.set_user("u3");
+    assert_eq!(rx.user_id(), Some("u2"));
+
+    let ban = request("u2", None).into_ban("room-1", "mod-1").unwrap();
+    let WsMessage::RoomBanned { user_id, reason, .. } = banned_notice(&ban) else {
+        panic!("expected RoomBanned");
+    };
+    assert_eq!(Some(user_id.as_str()), rx.user_id());
+    assert_eq!(reason, None);
+}
+
+#[test]
+fn test_bans_end_the_users_room_subscription() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, _rx) = buffers.channel();
+    let subscriber = Subscriber::unfiltered(tx.clone());
+    let ban = request("u2", None).into_ban("room-1", "mod-1").unwrap();
+    // Not joined yet, so not the banned user
+    assert!(!subscriber.is_removed_by(&banned_notice(&ban)));
+
+    tx.set_user("u2");
+    assert!(subscriber.is_removed_by(&banned_notice(&ban)));
+    assert!(subscriber.is_removed_by(&removed_notice("room-1", "u2")));
+    assert!(!subscriber.is_removed_by(&removed_notice("room-1", "u3")));
+    assert!(!subscriber.is_removed_by(&WsMessage::Typing { is_typing: true }));
+
+    // A ban in a neighbouring hex leaves the socket's own hex alone
+    let neighbor = Subscriber::neighbor(tx, Default::default(), "882a100d67fffff".to_string());
+    assert!(!neighbor.is_removed_by(&banned_notice(&ban)));
+}
@@ -0,0 +1,50 @@
+use chat_service::models::{ChatRoom, Message, RoomSettings, WsMessage};
+use chat_service::room_cache::{changes_history, room_of_channel};
+use chrono::{TimeZone, Utc};
+use mongodb::bson::oid::ObjectId;
+
+#[test]
+fn test_history_changing_broadcasts() {
+    let message = Message::new("room1".into(), "u1".into(), "alice".into(), "hi".into());
+    assert!(changes_history(&WsMessage::NewMessage(message)));
+    assert!(!changes_history(&WsMessage::Typing { is_typing: true }));
+}
+
+#[test]
+fn test_channels_map_to_rooms() {
+    assert_eq!(room_of_channel("room:40.7,-74.0"), Some("40.7,-74.0"));
+    assert_eq!(room_of_channel("hex:882a100d63fffff"), Some("882a100d63fffff"));
+    assert_eq!(room_of_channel("user:u1"), None);
+}
+
+#[test]
+fn test_cached_rooms_and_history_round_trip() {
+    let created_at = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap();
+    let room = ChatRoom {
+        id: "882a100d63fffff".into(),
+        location_id: "882a100d63fffff".into(),
+        active_users: 3,
+        last_message_at: created_at,
+        created_at,
+        settings: RoomSettings::default(),
+        h3_index: None,
+        migrated_to_hex: false,
+        topic: None,
+        created_by: None,
+        owner: None,
+        review: Default::default(),
+        markers: Vec::new(),
+        moderators: Vec::new(),
+        visibility: Default::default(),
+    };
+    let cached: ChatRoom = serde_json::from_str(&serde_json::to_string(&room).unwrap()).unwrap();
+    assert_eq!(cached.created_at, created_at);
+    assert_eq!(cached.active_users, 3);
+
+    let mut message = Message::new("room1".into(), "u1".into(), "alice".into(), "hi".into());
+    message.id = Some(ObjectId::new());
+    let messages = vec![message];
+    let cached: Vec<Message> = serde_json::from_str(&serde_json::to_string(&messages).unwrap()).unwrap();
+    assert_eq!(cached[0].id, messages[0].id);
+    assert_eq!(cached[0].timestamp.timestamp_millis(), messages[0].timestamp.timestamp_millis());
+}
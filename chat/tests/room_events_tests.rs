@@ -0,0 +1,28 @@
+use chat_service::room_events::Recurrence;
+use chrono::{Duration, TimeZone, Utc};
+
+#[test]
+fn test_one_off_events_do_not_repeat() {
+    let start = Utc.with_ymd_and_hms(2025, 6, 7, 10, 0, 0).unwrap();
+
+    assert_eq!(Recurrence::Once.next_occurrence(start, start), None);
+}
+
+#[test]
+fn test_next_occurrence_follows_the_interval() {
+    let saturday = Utc.with_ymd_and_hms(2025, 6, 7, 10, 0, 0).unwrap();
+
+    assert_eq!(Recurrence::Weekly.next_occurrence(saturday, saturday), Some(saturday + Duration::weeks(1)));
+    assert_eq!(Recurrence::Daily.next_occurrence(saturday, saturday), Some(saturday + Duration::days(1)));
+}
+
+#[test]
+fn test_missed_occurrences_are_skipped() {
+    let saturday = Utc.with_ymd_and_hms(2025, 6, 7, 10, 0, 0).unwrap();
+    // Scheduler was down for three weeks and a day
+    let now = saturday + Duration::weeks(3) + Duration::days(1);
+
+    let next = Recurrence::Weekly.next_occurrence(saturday, now).unwrap();
+    assert_eq!(next, saturday + Duration::weeks(4));
+    assert!(next > now);
+}
@@ -0,0 +1,45 @@
+use chat_service::models::ChatRoom;
+use chat_service::room_invite_links::{
+    link_needs_moderator, InviteLink, InviteLinkError, InviteLinkRequest, DEFAULT_LINK_TTL_SECONDS, MAX_LINK_TTL_SECONDS,
+};
+use chat_service::room_invites::RoomVisibility;
+
+#[test]
+fn test_invite_links_round_trip() {
+    let link = InviteLink::issue("room-1", "alice", DEFAULT_LINK_TTL_SECONDS);
+    let verified = InviteLink::verify(&link.sign().unwrap()).unwrap();
+    assert_eq!(verified, link);
+    assert_eq!(verified.room_id, "room-1");
+    assert_eq!(verified.created_by, "alice");
+}
+
+#[test]
+fn test_expired_or_foreign_tokens_are_refused() {
+    let expired = InviteLink::issue("room-1", "alice", -3600);
+    assert_eq!(InviteLink::verify(&expired.sign().unwrap()), Err(InviteLinkError::Invalid));
+
+    let mut other_kind = InviteLink::issue("room-1", "alice", DEFAULT_LINK_TTL_SECONDS);
+    other_kind.kind = "ws_ticket".to_string();
+    assert_eq!(InviteLink::verify(&other_kind.sign().unwrap()), Err(InviteLinkError::Invalid));
+    assert_eq!(InviteLink::verify("not-a-token"), Err(InviteLinkError::Invalid));
+}
+
+#[test]
+fn test_link_lifetimes_are_bounded() {
+    assert_eq!(InviteLinkRequest::default().ttl_seconds(), Ok(DEFAULT_LINK_TTL_SECONDS));
+    let week = InviteLinkRequest { expires_in_seconds: Some(MAX_LINK_TTL_SECONDS) };
+    assert_eq!(week.ttl_seconds(), Ok(MAX_LINK_TTL_SECONDS));
+    assert!(InviteLinkRequest { expires_in_seconds: Some(60) }.ttl_seconds().is_err());
+    assert!(InviteLinkRequest { expires_in_seconds: Some(MAX_LINK_TTL_SECONDS + 1) }.ttl_seconds().is_err());
+}
+
+#[test]
+fn test_links_to_restricted_rooms_need_a_moderator() {
+    let mut room = ChatRoom::new("room-1");
+    assert!(!link_needs_moderator(&room));
+    room.settings.exclude_location_mismatch = true;
+    assert!(link_needs_moderator(&room));
+    room.settings.exclude_location_mismatch = false;
+    room.visibility = RoomVisibility::Private;
+    assert!(link_needs_moderator(&room));
+}
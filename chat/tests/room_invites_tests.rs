@@ -0,0 +1,82 @@
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use chat_service::auth::AuthUser;
+use chat_service::models::{ChatRoom, WsMessage};
+use chat_service::room_invites::{admission, removed_notice, require_access, resume_admission, Admission, InviteRequest, RoomVisibility};
+use chat_service::room_roles::RoomRole;
+use chat_service::AppState;
+
+fn user(user_id: &str, roles: &[&str]) -> AuthUser {
+    AuthUser {
+        user_id: user_id.to_string(),
+        email: format!("{}@example.com", user_id),
+        username: user_id.to_string(),
+        roles: roles.iter().map(|role| role.to_string()).collect(),
+        age_verified: true,
+        badge: None,
+    }
+}
+
+#[test]
+fn test_rooms_are_public_unless_made_private() {
+    assert_eq!(ChatRoom::new("room-1").visibility, RoomVisibility::Public);
+    let stored: RoomVisibility = serde_json::from_str(r#""private""#).unwrap();
+    assert_eq!(stored, RoomVisibility::Private);
+}
+
+#[test]
+fn test_private_rooms_need_an_invitation() {
+    let alice = user("alice", &[]);
+    assert_eq!(admission(RoomVisibility::Public, RoomRole::Member, None, "alice"), Admission::Open);
+    assert_eq!(admission(RoomVisibility::Private, RoomRole::Member, Some(&alice), "alice"), Admission::Invitation);
+    assert_eq!(admission(RoomVisibility::Private, RoomRole::Moderator, Some(&alice), "alice"), Admission::Open);
+    assert_eq!(admission(RoomVisibility::Private, RoomRole::Member, Some(&user("staff", &["moderator"])), "staff"), Admission::Open);
+    // Anonymous, or signed in as someone else
+    assert_eq!(admission(RoomVisibility::Private, RoomRole::Owner, None, "alice"), Admission::Denied);
+    assert_eq!(admission(RoomVisibility::Private, RoomRole::Member, Some(&alice), "bob"), Admission::Denied);
+}
+
+#[test]
+fn test_invitations_are_keyed_by_room_and_user() {
+    let invitation = InviteRequest { user_id: " bob ".to_string() }.into_invitation("room-1", "alice").unwrap();
+    assert_eq!(invitation.id, "room-1:bob");
+    assert_eq!(invitation.invited_by, "alice");
+    assert!(invitation.accepted_at.is_none());
+    assert_eq!(invitation.token.len(), 32);
+    assert!(InviteRequest { user_id: "  ".to_string() }.into_invitation("room-1", "alice").is_err());
+}
+
+#[test]
+fn test_resumes_into_private_rooms_need_membership_again() {
+    assert_eq!(resume_admission(RoomVisibility::Public, RoomRole::Member, false), Admission::Open);
+    assert_eq!(resume_admission(RoomVisibility::Private, RoomRole::Member, false), Admission::Invitation);
+    assert_eq!(resume_admission(RoomVisibility::Private, RoomRole::Member, true), Admission::Open);
+    assert_eq!(resume_admission(RoomVisibility::Private, RoomRole::Owner, false), Admission::Open);
+}
+
+#[test]
+fn test_removed_members_are_told_which_room() {
+    match removed_notice("room-1", "bob") {
+        WsMessage::RoomAccessRevoked { room_id, user_id } => assert_eq!((room_id.as_str(), user_id.as_str()), ("room-1", "bob")),
+        other => panic!("unexpected notice: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_non_members_cant_read_private_room_details() {
+    // Nothing is listening; membership checks fail closed
+    let state = AppState::new("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=50", "redis://127.0.0.1:1", "room_invites_tests")
+        .await
+        .unwrap();
+    let mut room = ChatRoom::new("room-1");
+    room.owner = Some("alice".to_string());
+    room.visibility = RoomVisibility::Private;
+
+    for caller in [None, Some(user("bob", &[]))] {
+        let refused = require_access(&state, &room, caller.as_ref()).await.unwrap_err();
+        assert_eq!(refused.into_response().status(), StatusCode::FORBIDDEN);
+    }
+    assert!(require_access(&state, &room, Some(&user("alice", &[]))).await.is_ok());
+    room.visibility = RoomVisibility::Public;
+    assert!(require_access(&state, &room, None).await.is_ok());
+}
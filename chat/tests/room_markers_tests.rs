@@ -0,0 +1,31 @@
+use chat_service::models::WsMessage;
+use chat_service::room_markers::{MarkerKind, MarkerRequest};
+
+fn request(kind: &str, label: &str, latitude: f64, longitude: f64) -> MarkerRequest {
+    serde_json::from_value(serde_json::json!({ "kind": kind, "label": label, "latitude": latitude, "longitude": longitude })).unwrap()
+}
+
+#[test]
+fn test_markers_are_checked_and_labels_trimmed() {
+    let marker = request("meeting_point", "  North gate ", 52.52, 13.405).into_marker("m1".into(), "mod1").unwrap();
+    assert_eq!(marker.kind, MarkerKind::MeetingPoint);
+    assert_eq!(marker.label, "North gate");
+    assert_eq!(marker.created_by, "mod1");
+    assert!(request("hazard", "", 91.0, 0.0).into_marker("m2".into(), "mod1").is_err());
+    assert!(request("entrance", &"x".repeat(61), 0.0, 0.0).into_marker("m3".into(), "mod1").is_err());
+}
+
+#[test]
+fn test_unknown_marker_kinds_are_rejected() {
+    let parsed = serde_json::from_value::<MarkerRequest>(serde_json::json!({ "kind": "bonfire", "latitude": 0.0, "longitude": 0.0 }));
+    assert!(parsed.is_err());
+}
+
+#[test]
+fn test_removed_markers_are_sent_without_the_marker() {
+    let event = WsMessage::MarkerUpdated { room_id: "room1".into(), marker_id: "m1".into(), marker: None };
+    assert_eq!(
+        serde_json::to_value(event).unwrap(),
+        serde_json::json!({ "type": "MarkerUpdated", "data": { "room_id": "room1", "marker_id": "m1" } })
+    );
+}
@@ -0,0 +1,32 @@
+use chat_service::models::{Message, WsMessage};
+use chat_service::room_mentions::{mentions_everyone, PushNotification};
+use chat_service::subscription_filter::{FanoutFilter, SubscriptionFilter};
+
+#[test]
+fn test_room_wide_mentions_are_detected() {
+    assert!(mentions_everyone("@everyone meeting moved"));
+    assert!(mentions_everyone("lunch? @HERE!"));
+    assert!(!mentions_everyone("everyone@here.com"));
+    assert!(!mentions_everyone("@everyoneelse"));
+}
+
+#[test]
+fn test_room_wide_mentions_reach_mentions_only_sockets() {
+    let mut filter = SubscriptionFilter::new(FanoutFilter::MentionsOnly);
+    filter.username = Some("bob".to_string());
+    let mut message = Message::new("room".to_string(), "u1".to_string(), "alice".to_string(), "@here fire drill".to_string());
+
+    assert!(!filter.allows(&WsMessage::NewMessage(message.clone())));
+    message.mentions_everyone = true;
+    assert!(filter.allows(&WsMessage::NewMessage(message)));
+}
+
+#[test]
+fn test_push_payload_is_marked_as_a_room_mention() {
+    let message = Message::new("room".to_string(), "u1".to_string(), "alice".to_string(), "@everyone ".repeat(30));
+    let push = PushNotification::room_mention("u2", &message);
+
+    assert_eq!(push.kind, "room_mention");
+    assert_eq!(push.title, "alice mentioned everyone");
+    assert_eq!(push.body.chars().count(), 140);
+}
@@ -0,0 +1,44 @@
+use chat_service::room_metrics::{RoomMetrics, OTHER_ROOMS_LABEL};
+
+#[test]
+fn test_busiest_rooms_keep_their_label() {
+    let metrics = RoomMetrics::new(2);
+    for (room, messages) in [("quiet", 1), ("busy", 5), ("medium", 3), ("idle", 0)] {
+        metrics.record_join(room, 1);
+        for _ in 0..messages {
+            metrics.record_message(room);
+        }
+    }
+
+    let snapshot = metrics.snapshot();
+    let labels: Vec<&str> = snapshot.iter().map(|(room, _)| room.as_str()).collect();
+    assert_eq!(labels, vec!["busy", "medium", OTHER_ROOMS_LABEL]);
+
+    let (_, other) = &snapshot[2];
+    assert_eq!(other.messages, 1);
+    assert_eq!(other.joins, 2);
+    assert_eq!(other.active_users, 2);
+}
+
+#[test]
+fn test_no_other_bucket_under_the_limit() {
+    let metrics = RoomMetrics::new(5);
+    metrics.record_message("room-a");
+    metrics.record_message("room-b");
+
+    assert!(metrics.snapshot().iter().all(|(room, _)| room != OTHER_ROOMS_LABEL));
+}
+
+#[test]
+fn test_render_prometheus_text() {
+    let metrics = RoomMetrics::new(1);
+    metrics.record_message("40.7128_-74.0060");
+    metrics.record_message("40.7128_-74.0060");
+    metrics.record_message("8828308281fffff");
+
+    let text = metrics.render();
+    assert!(text.contains("# TYPE chat_room_messages_total counter"));
+    assert!(text.contains("chat_room_messages_total{room=\"40.7128_-74.0060\"} 2"));
+    assert!(text.contains("chat_room_messages_total{room=\"other\"} 1"));
+    assert!(!text.contains("8828308281fffff"));
+}
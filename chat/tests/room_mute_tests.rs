@@ -0,0 +1,44 @@
+use chat_service::models::{Message, WsMessage};
+use chat_service::notifications::{NotificationHint, NotificationPriority, NotificationSettings};
+use chat_service::preferences::UserPreferences;
+use chat_service::subscription_filter::{FanoutFilter, SubscriptionFilter};
+
+fn mention() -> Message {
+    let mut message = Message::new("room1".to_string(), "u1".to_string(), "alice".to_string(), "@everyone hi".to_string());
+    message.mentions_everyone = true;
+    message.notification = Some(NotificationHint::for_room_message(&message, &NotificationSettings::default()));
+    message
+}
+
+fn muted_filter(muted: bool) -> SubscriptionFilter {
+    SubscriptionFilter { muted, ..SubscriptionFilter::new(FanoutFilter::All) }
+}
+
+#[test]
+fn test_muted_rooms_are_looked_up_by_id() {
+    let preferences = UserPreferences { user_id: "u2".to_string(), muted_rooms: vec!["room1".to_string()] };
+    assert!(preferences.is_muted("room1"));
+    assert!(!preferences.is_muted("room2"));
+}
+
+#[test]
+fn test_muted_sockets_get_quiet_flagged_messages() {
+    let WsMessage::NewMessage(message) = muted_filter(true).mark(WsMessage::NewMessage(mention())) else {
+        panic!("expected a new message");
+    };
+    assert!(message.muted);
+    let hint = message.notification.unwrap();
+    assert_eq!(hint.priority, NotificationPriority::Low);
+    assert_eq!(hint.sound, None);
+}
+
+#[test]
+fn test_unmuted_sockets_get_messages_unchanged() {
+    let WsMessage::NewMessage(message) = muted_filter(false).mark(WsMessage::NewMessage(mention())) else {
+        panic!("expected a new message");
+    };
+    assert!(!message.muted);
+    assert_eq!(message.notification.unwrap().priority, NotificationPriority::High);
+    let json = serde_json::to_string(&mention()).unwrap();
+    assert!(!json.contains("muted"));
+}
@@ -0,0 +1,56 @@
+use chat_service::auth::AuthUser;
+use chat_service::models::ChatRoom;
+use chat_service::room_quota::{creator, pending_creator, seconds_until_reset, ReviewState, RoomQuota};
+use chat_service::AppError;
+use chrono::{TimeZone, Utc};
+
+#[test]
+fn test_rooms_past_the_soft_threshold_go_to_review() {
+    let quota = RoomQuota { daily_limit: 4, review_after: 2, organic_posters: 3 };
+    assert_eq!(quota.review_for(1), Some(ReviewState::Approved));
+    assert_eq!(quota.review_for(2), Some(ReviewState::Approved));
+    assert_eq!(quota.review_for(3), Some(ReviewState::Pending));
+    assert_eq!(quota.review_for(4), Some(ReviewState::Pending));
+    assert_eq!(quota.review_for(5), None);
+}
+
+#[test]
+fn test_quota_resets_at_utc_midnight() {
+    let now = Utc.with_ymd_and_hms(2025, 3, 1, 23, 59, 30).unwrap();
+    assert_eq!(seconds_until_reset(now), 30);
+    let midnight = Utc.with_ymd_and_hms(2025, 3, 2, 0, 0, 0).unwrap();
+    assert_eq!(seconds_until_reset(midnight), 86_400);
+}
+
+#[test]
+fn test_rooms_created_before_review_existed_are_approved() {
+    let mut room = ChatRoom::new("40.7,-74.0");
+    let stored = serde_json::json!({
+        "_id": room.id, "location_id": room.location_id, "active_users": 0,
+        "last_message_at": { "$date": { "$numberLong": "0" } },
+        "created_at": { "$date": { "$numberLong": "0" } },
+        "settings": serde_json::to_value(&room.settings).unwrap(),
+    });
+    let old: ChatRoom = mongodb::bson::from_document(mongodb::bson::to_document(&stored).unwrap()).unwrap();
+    assert_eq!(old.review, ReviewState::Approved);
+    assert_eq!(pending_creator(&old), None);
+
+    room.created_by = Some("u1".into());
+    room.review = ReviewState::Pending;
+    assert_eq!(pending_creator(&room).as_deref(), Some("u1"));
+}
+
+#[test]
+fn test_only_signed_in_callers_are_charged_for_new_rooms() {
+    assert!(matches!(creator(None), Err(AppError::Unauthorized)));
+
+    let caller = AuthUser {
+        user_id: "u1".into(),
+        email: "u1@example.com".into(),
+        username: "u1".into(),
+        roles: vec![],
+        age_verified: false,
+        badge: None,
+    };
+    assert_eq!(creator(Some(&caller)).unwrap(), "u1");
+}
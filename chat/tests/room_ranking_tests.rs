@@ -0,0 +1,49 @@
+use chat_service::models::{ChatRoom, RoomSettings};
+use chat_service::presence::RoomCounts;
+use chat_service::room_ranking::{rank_rooms, RankingWeights, RoomInfo};
+use chrono::{Duration, Utc};
+
+fn room(id: &str, minutes_ago: i64, participants: usize, spectators: usize) -> RoomInfo {
+    let at = Utc::now() - Duration::minutes(minutes_ago);
+    let room = ChatRoom {
+        id: id.to_string(),
+        location_id: id.to_string(),
+        active_users: (participants + spectators) as i32,
+        last_message_at: at,
+        created_at: at,
+        settings: RoomSettings::default(),
+        h3_index: None,
+        migrated_to_hex: false,
+        topic: None,
+        created_by: None,
+        owner: None,
+        review: Default::default(),
+        markers: Vec::new(),
+        moderators: Vec::new(),
+        visibility: Default::default(),
+    };
+    RoomInfo::new(room, RoomCounts { participants, spectators })
+}
+
+#[test]
+fn test_counts_split_sockets_into_participants_and_spectators() {
+    assert_eq!(RoomCounts::new(10, 3), RoomCounts { participants: 3, spectators: 7 });
+    // Participants whose sockets went stale aren't counted twice
+    assert_eq!(RoomCounts::new(2, 5), RoomCounts { participants: 2, spectators: 0 });
+}
+
+#[test]
+fn test_participants_outweigh_spectators() {
+    let weights = RankingWeights::default();
+    let ranked = rank_rooms(vec![room("watched", 0, 1, 10), room("chatty", 0, 5, 0)], weights);
+    assert_eq!(ranked[0].room.id, "chatty");
+    assert_eq!(weights.score(RoomCounts { participants: 1, spectators: 4 }), 2.0);
+}
+
+#[test]
+fn test_ties_go_to_the_latest_message() {
+    let weights = RankingWeights { participant: 1.0, spectator: 1.0 };
+    let ranked = rank_rooms(vec![room("older", 30, 2, 2), room("newer", 1, 1, 3)], weights);
+    let ids: Vec<&str> = ranked.iter().map(|info| info.room.id.as_str()).collect();
+    assert_eq!(ids, ["newer", "older"]);
+}
@@ -0,0 +1,91 @@
+use axum::extract::{Path, State};
+use chat_service::auth::AuthUser;
+use chat_service::models::ChatRoom;
+use chat_service::room_roles::{acting_role, authorize, caller_is_moderator, grant_owner_handler, role_in, RoomRole};
+use chat_service::{AppError, AppState};
+
+fn room() -> ChatRoom {
+    let mut room = ChatRoom::new("room-1");
+    room.owner = Some("owner".to_string());
+    room.moderators = vec!["mod-a".to_string(), "mod-b".to_string()];
+    room
+}
+
+fn user(user_id: &str, roles: &[&str]) -> AuthUser {
+    AuthUser {
+        user_id: user_id.to_string(),
+        email: format!("{}@example.com", user_id),
+        username: user_id.to_string(),
+        roles: roles.iter().map(|role| role.to_string()).collect(),
+        age_verified: true,
+        badge: None,
+    }
+}
+
+#[test]
+fn test_roles_come_from_the_room() {
+    let room = room();
+    assert_eq!(role_in(&room, "owner"), RoomRole::Owner);
+    assert_eq!(role_in(&room, "mod-a"), RoomRole::Moderator);
+    assert_eq!(role_in(&room, "someone"), RoomRole::Member);
+    assert_eq!(acting_role(&room, &user("staff", &["moderator"])), RoomRole::Owner);
+    assert!(RoomRole::Owner > RoomRole::Moderator && RoomRole::Moderator > RoomRole::Member);
+}
+
+#[test]
+fn test_room_moderators_cant_act_on_their_peers() {
+    let room = room();
+    let moderator = user("mod-a", &[]);
+    assert!(authorize(&room, &moderator, Some("someone")).is_ok());
+    assert!(authorize(&room, &moderator, None).is_ok());
+    assert!(authorize(&room, &moderator, Some("mod-b")).is_err());
+    assert!(authorize(&room, &moderator, Some("owner")).is_err());
+
+    let owner = user("owner", &[]);
+    assert!(authorize(&room, &owner, Some("mod-b")).is_ok());
+    assert!(authorize(&room, &user("staff", &["moderator"]), Some("owner")).is_ok());
+}
+
+#[test]
+fn test_members_cant_moderate() {
+    let room = room();
+    assert!(authorize(&room, &user("someone", &[]), None).is_err());
+    assert!(authorize(&ChatRoom::new("room-2"), &user("mod-a", &[]), Some("someone")).is_err());
+}
+
+#[test]
+fn test_room_moderators_post_past_permissions_as_themselves() {
+    let room = room();
+    let moderator = user("mod-a", &[]);
+    assert!(caller_is_moderator(Some(&moderator), "mod-a", role_in(&room, "mod-a")));
+    assert!(caller_is_moderator(Some(&user("owner", &[])), "owner", role_in(&room, "owner")));
+    assert!(caller_is_moderator(Some(&user("staff", &["moderator"])), "staff", role_in(&room, "staff")));
+    assert!(!caller_is_moderator(Some(&user("someone", &[])), "someone", role_in(&room, "someone")));
+    // Only signed in as the user posting
+    assert!(!caller_is_moderator(None, "mod-a", role_in(&room, "mod-a")));
+    assert!(!caller_is_moderator(Some(&moderator), "someone", RoomRole::Moderator));
+}
+
+#[test]
+fn test_creating_a_room_doesnt_make_you_its_owner() {
+    let mut room = ChatRoom::new("40.7128_-74.0060");
+    room.created_by = Some("first-visitor".to_string());
+    assert_eq!(role_in(&room, "first-visitor"), RoomRole::Member);
+    assert!(authorize(&room, &user("first-visitor", &[]), None).is_err());
+    assert!(authorize(&room, &user("first-visitor", &[]), Some("someone")).is_err());
+
+    room.owner = Some("first-visitor".to_string());
+    assert_eq!(role_in(&room, "first-visitor"), RoomRole::Owner);
+}
+
+#[tokio::test]
+async fn test_only_admins_grant_ownership() {
+    let state = AppState::new("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=50", "redis://127.0.0.1:1", "room_roles_tests")
+        .await
+        .unwrap();
+    for roles in [&[][..], &["moderator"][..]] {
+        let path = Path(("40.7128_-74.0060".to_string(), "someone".to_string()));
+        let result = grant_owner_handler(path, State(state.clone()), user("caller", roles)).await;
+        assert!(matches!(result, Err(AppError::Forbidden)));
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use axum::extract::ws::WebSocketUpgrade;
+use axum::extract::State;
+use axum::routing::get;
+use axum::Router;
+use chat_service::auth::AuthUser;
+use chat_service::models::WsMessage;
+use chat_service::ws_ticket::JoinTicket;
+use chat_service::{handle_socket, AppState, ConnectionInfo};
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message as Frame;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+const ROOM: &str = "room-socket-tests";
+
+// Nothing is listening on either store, and Mongo gives up quickly, so
+// rooms can't be loaded and every join is refused
+async fn state() -> AppState {
+    AppState::new("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=50", "redis://127.0.0.1:1", "room_socket_tests")
+        .await
+        .unwrap()
+}
+
+fn ticket_for(room_id: &str) -> JoinTicket {
+    let user = AuthUser {
+        user_id: "mallory".to_string(),
+        email: "mallory@example.com".to_string(),
+        username: "mallory".to_string(),
+        roles: vec![],
+        age_verified: true,
+        badge: None,
+    };
+    JoinTicket::issue(&user, room_id)
+}
+
+// Serves ROOM; `/ticket` sockets are signed in as mallory, while
+// `/wrong-ticket` ones carry a ticket for another room so their joins are refused
+async fn serve(state: AppState) -> String {
+    async fn room(ws: WebSocketUpgrade, State(state): State<AppState>) -> axum::response::Response {
+        ws.on_upgrade(move |socket| handle_socket(socket, ROOM.to_string(), state, ConnectionInfo::default()))
+    }
+    async fn ticket(ws: WebSocketUpgrade, State(state): State<AppState>) -> axum::response::Response {
+        let info = ConnectionInfo { ticket: Some(ticket_for(ROOM)), ..Default::default() };
+        ws.on_upgrade(move |socket| handle_socket(socket, ROOM.to_string(), state, info))
+    }
+    async fn wrong_ticket(ws: WebSocketUpgrade, State(state): State<AppState>) -> axum::response::Response {
+        let info = ConnectionInfo { ticket: Some(ticket_for("another-room")), ..Default::default() };
+        ws.on_upgrade(move |socket| handle_socket(socket, ROOM.to_string(), state, info))
+    }
+    let app = Router::new().route("/room", get(room)).route("/ticket", get(ticket)).route("/wrong-ticket", get(wrong_ticket)).with_state(state);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("ws://{}", addr)
+}
+
+async fn connect(url: String) -> Client {
+    tokio_tungstenite::connect_async(url).await.unwrap().0
+}
+
+async fn join(client: &mut Client, user_id: &str) {
+    let join = WsMessage::Join {
+        user_id: user_id.to_string(),
+        username: user_id.to_string(),
+        token: String::new(),
+        have_until: None,
+        low_data: false,
+        invite: None,
+    };
+    client.send(Frame::Text(serde_json::to_string(&join).unwrap())).await.unwrap();
+}
+
+// The next message, skipping pings; None once nothing arrives for `wait`
+async fn next(client: &mut Client, wait: Duration) -> Option<WsMessage> {
+    loop {
+        match tokio::time::timeout(wait, client.next()).await {
+            Ok(Some(Ok(Frame::Text(text)))) => return serde_json::from_str(&text).ok(),
+            Ok(Some(Ok(Frame::Ping(_) | Frame::Pong(_)))) => continue,
+            _ => return None,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_joins_are_refused_while_the_room_cant_be_loaded() {
+    let state = state().await;
+    let url = serve(state.clone()).await;
+
+    // Signed in or not, nobody gets past the room's checks without the room
+    let mut member = connect(format!("{}/ticket", url)).await;
+    join(&mut member, "mallory").await;
+    let mut anonymous = connect(format!("{}/room", url)).await;
+    join(&mut anonymous, "alice").await;
+    let mut refused = connect(format!("{}/wrong-ticket", url)).await;
+    join(&mut refused, "mallory").await;
+    let mut idle = connect(format!("{}/room", url)).await;
+    for client in [&mut member, &mut anonymous, &mut refused] {
+        assert!(matches!(next(client, Duration::from_secs(10)).await, Some(WsMessage::Error { .. })));
+    }
+
+    // None of them is subscribed to the room or hears anything more
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(state.fanout.local_subscribers(&format!("room:{}", ROOM)).is_empty());
+    for client in [&mut member, &mut anonymous, &mut refused, &mut idle] {
+        assert!(next(client, Duration::from_millis(300)).await.is_none());
+    }
+}
@@ -0,0 +1,40 @@
+use chat_service::models::{EventDetails, Message, MessageKind, RsvpCounts};
+use chat_service::rsvp::apply_event;
+
+fn message() -> Message {
+    Message::new("room1".to_string(), "user1".to_string(), "alice".to_string(), "Picnic?".to_string())
+}
+
+#[test]
+fn test_plain_messages_stay_text() {
+    let mut message = message();
+
+    apply_event(&mut message, None).unwrap();
+    assert_eq!(message.kind, MessageKind::Text);
+    assert!(message.event.is_none());
+}
+
+#[test]
+fn test_event_messages_start_with_no_rsvps() {
+    let mut message = message();
+    let event = EventDetails {
+        title: "  Picnic in the park ".to_string(),
+        starts_at: None,
+        rsvp_counts: RsvpCounts { going: 40, maybe: 0, no: 0 },
+    };
+
+    apply_event(&mut message, Some(event)).unwrap();
+    assert_eq!(message.kind, MessageKind::Event);
+    let event = message.event.unwrap();
+    assert_eq!(event.title, "Picnic in the park");
+    assert_eq!(event.rsvp_counts, RsvpCounts::default());
+}
+
+#[test]
+fn test_events_need_a_title() {
+    let mut message = message();
+    let event = EventDetails { title: "  ".to_string(), starts_at: None, rsvp_counts: RsvpCounts::default() };
+
+    assert!(apply_event(&mut message, Some(event)).is_err());
+    assert_eq!(message.kind, MessageKind::Text);
+}
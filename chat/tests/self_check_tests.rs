@@ -0,0 +1,51 @@
+use chat_service::self_check::{check_config, has_errors, Severity};
+use std::collections::HashMap;
+
+fn check(vars: &[(&str, &str)]) -> Vec<chat_service::self_check::Finding> {
+    let env: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    check_config(|name| env.get(name).cloned())
+}
+
+const STRONG_SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+#[test]
+fn test_valid_config_has_no_findings() {
+    let findings = check(&[
+        ("JWT_SECRET", STRONG_SECRET),
+        ("REDIS_URI", "redis://redis:6379"),
+        ("LEGACY_ROOM_BRIDGE", "crosspost"),
+        ("PORT", "3001"),
+    ]);
+
+    assert!(findings.is_empty(), "{:?}", findings);
+}
+
+#[test]
+fn test_missing_or_placeholder_jwt_secret_is_an_error() {
+    assert!(has_errors(&check(&[])));
+    assert!(has_errors(&check(&[("JWT_SECRET", "your-secret-key-here")])));
+}
+
+#[test]
+fn test_short_jwt_secret_is_a_warning() {
+    let findings = check(&[("JWT_SECRET", "your-super-secret-jwt-key")]);
+
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].severity, Severity::Warning);
+    assert_eq!(findings[0].component, "jwt");
+}
+
+#[test]
+fn test_invalid_values_are_reported() {
+    let findings = check(&[
+        ("JWT_SECRET", STRONG_SECRET),
+        ("LEGACY_ROOM_BRIDGE", "sideways"),
+        ("PORT", "http"),
+        ("IP_GEO_MAX_DISTANCE_KM", "-5"),
+        ("REDIS_URL", "redis://redis:6379"),
+    ]);
+
+    let errors: Vec<_> = findings.iter().filter(|f| f.severity == Severity::Error).collect();
+    assert_eq!(errors.len(), 3, "{:?}", findings);
+    assert!(findings.iter().any(|f| f.component == "redis" && f.message.contains("REDIS_URI")));
+}
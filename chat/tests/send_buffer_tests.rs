@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chat_service::models::{Message, WsMessage};
+use chat_service::send_buffer::{Priority, SendBuffers, SlowConsumerPolicy, SocketClosed};
+
+fn typing() -> WsMessage {
+    WsMessage::Typing { is_typing: true }
+}
+
+fn chat(content: &str) -> WsMessage {
+    WsMessage::NewMessage(Message::new("room-1".to_string(), "u1".to_string(), "alice".to_string(), content.to_string()))
+}
+
+#[tokio::test]
+async fn test_full_buffer_drops_new_messages() {
+    let buffers = Arc::new(SendBuffers::new(2, SlowConsumerPolicy::Drop));
+    let (tx, mut rx) = buffers.channel();
+
+    for _ in 0..5 {
+        assert_eq!(tx.send(typing()), Ok(()));
+    }
+    assert!(rx.recv().await.is_some());
+    assert!(rx.recv().await.is_some());
+    assert!(rx.try_recv().is_err());
+    assert!(buffers.render().contains("chat_ws_send_dropped_total 3"));
+}
+
+#[tokio::test]
+async fn test_full_buffer_can_disconnect_the_client() {
+    let buffers = Arc::new(SendBuffers::new(1, SlowConsumerPolicy::Disconnect));
+    let (tx, _rx) = buffers.channel();
+
+    assert_eq!(tx.send(typing()), Ok(()));
+    assert_eq!(tx.send(typing()), Err(SocketClosed));
+    tokio::time::timeout(Duration::from_secs(1), tx.overloaded()).await.expect("overload signalled");
+}
+
+#[tokio::test]
+async fn test_sends_fail_once_the_socket_is_gone() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, rx) = buffers.channel();
+    drop(rx);
+
+    assert!(tx.is_closed());
+    assert_eq!(tx.send(typing()), Err(SocketClosed));
+}
+
+#[tokio::test]
+async fn test_messages_are_sent_before_queued_typing_and_presence() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, mut rx) = buffers.channel();
+
+    tx.send(WsMessage::UserLeft { username: "bob".to_string(), timestamp: chrono::Utc::now() }).unwrap();
+    tx.send(typing()).unwrap();
+    tx.send(chat("hello")).unwrap();
+
+    assert_eq!(Priority::of(&rx.recv().await.unwrap()), Priority::Message);
+    assert_eq!(Priority::of(&rx.recv().await.unwrap()), Priority::Typing);
+    assert_eq!(Priority::of(&rx.recv().await.unwrap()), Priority::Presence);
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_typing_flood_does_not_crowd_out_messages() {
+    let buffers = Arc::new(SendBuffers::new(2, SlowConsumerPolicy::Drop));
+    let (tx, mut rx) = buffers.channel();
+
+    for _ in 0..10 {
+        tx.send(typing()).unwrap();
+    }
+    tx.send(chat("still here")).unwrap();
+
+    match rx.recv().await {
+        Some(WsMessage::NewMessage(message)) => assert_eq!(message.content, "still here"),
+        _ => panic!("expected the chat message first"),
+    }
+    assert!(buffers.render().contains("chat_ws_send_dropped_total 8"));
+}
@@ -0,0 +1,31 @@
+use chat_service::room_metrics::OTHER_ROOMS_LABEL;
+use chat_service::send_buffer::{SendBuffers, SlowConsumerPolicy};
+use chat_service::models::WsMessage;
+use chat_service::service_metrics::bucket_rooms;
+use std::sync::Arc;
+
+fn rooms(counts: &[(&str, usize)]) -> Vec<(String, usize)> {
+    counts.iter().map(|(room_id, sockets)| (room_id.to_string(), *sockets)).collect()
+}
+
+#[test]
+fn test_fullest_rooms_keep_their_label() {
+    let bucketed = bucket_rooms(rooms(&[("a", 2), ("b", 9), ("c", 4), ("d", 1)]), 2);
+    assert_eq!(bucketed, rooms(&[("b", 9), ("c", 4), (OTHER_ROOMS_LABEL, 3)]));
+}
+
+#[test]
+fn test_other_is_left_out_when_every_room_fits() {
+    assert_eq!(bucket_rooms(rooms(&[("a", 2), ("b", 9)]), 5), rooms(&[("b", 9), ("a", 2)]));
+    assert!(bucket_rooms(vec![], 5).is_empty());
+}
+
+#[test]
+fn test_send_queue_depth_counts_every_lane() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, rx) = buffers.channel();
+    assert_eq!(rx.queued(), 0);
+    tx.send(WsMessage::Typing { is_typing: true }).unwrap();
+    tx.send(WsMessage::Error { message: "x".to_string() }).unwrap();
+    assert_eq!(rx.queued(), 2);
+}
@@ -0,0 +1,48 @@
+use chat_service::models::{User, WsMessage};
+use chat_service::sessions::SocketSession;
+
+fn user() -> User {
+    User {
+        id: "u1".to_string(),
+        username: "alice".to_string(),
+        socket_id: "socket-1".to_string(),
+        location_id: "882a100d63fffff".to_string(),
+        location_flagged: true,
+        joined_at: chrono::Utc::now(),
+        badge: None,
+    }
+}
+
+#[test]
+fn test_resumed_user_keeps_identity_on_the_new_socket() {
+    let session = SocketSession::new(&user(), true);
+    let resumed = session.user("socket-2");
+
+    assert_eq!(resumed.id, "u1");
+    assert_eq!(resumed.socket_id, "socket-2");
+    assert_eq!(resumed.location_id, "882a100d63fffff");
+    assert!(resumed.location_flagged);
+    assert!(session.is_moderator);
+}
+
+#[test]
+fn test_resume_message_format() {
+    let msg: WsMessage = serde_json::from_value(serde_json::json!({
+        "type": "Resume",
+        "data": { "session_id": "socket-1", "last_message_id": "65f000000000000000000000" }
+    }))
+    .unwrap();
+
+    assert!(matches!(msg, WsMessage::Resume { ref session_id, .. } if session_id == "socket-1"));
+}
+
+#[test]
+fn test_sessions_are_unverified_unless_marked() {
+    assert!(!SocketSession::new(&user(), false).verified);
+    // Sessions stored before `verified` existed can't write after a resume
+    let stored: SocketSession = serde_json::from_value(serde_json::json!({
+        "user_id": "u1", "username": "alice", "room_id": "882a100d63fffff"
+    }))
+    .unwrap();
+    assert!(!stored.verified);
+}
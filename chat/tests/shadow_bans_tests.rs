@@ -0,0 +1,49 @@
+use chat_service::models::{Message, WsMessage};
+use chat_service::shadow_bans::{author_of, dm_echo_channel, unmark_broadcast, visible_to, ShadowBanRequest};
+use mongodb::bson::doc;
+
+#[test]
+fn test_shadow_ban_reasons_are_trimmed_and_checked() {
+    let request = ShadowBanRequest { reason: Some("  link spam ".to_string()) };
+    let ban = request.into_ban("u2", "mod-1").unwrap();
+    assert_eq!(ban.user_id, "u2");
+    assert_eq!(ban.banned_by, "mod-1");
+    assert_eq!(ban.reason.as_deref(), Some("link spam"));
+
+    assert_eq!(ShadowBanRequest { reason: Some(" ".to_string()) }.into_ban("u2", "mod-1").unwrap().reason, None);
+    assert!(ShadowBanRequest { reason: Some("x".repeat(201)) }.into_ban("u2", "mod-1").is_err());
+}
+
+#[test]
+fn test_only_new_messages_are_held_back() {
+    let message = Message::new("room-1".to_string(), "u2".to_string(), "bob".to_string(), "buy now".to_string());
+    assert_eq!(author_of(&WsMessage::NewMessage(message)), Some("u2"));
+    assert_eq!(author_of(&WsMessage::Typing { is_typing: true }), None);
+}
+
+#[test]
+fn test_dm_echoes_use_the_dm_channel_space() {
+    let channel = dm_echo_channel("dm_a_b", "a");
+    assert_eq!(channel, "dm:dm_a_b:echo:a");
+    assert_ne!(channel, "dm:dm_a_b");
+}
+
+#[test]
+fn test_shadow_messages_are_only_visible_to_their_author() {
+    assert_eq!(visible_to("user_id", None), doc! { "shadow": { "$ne": true } });
+    assert_eq!(
+        visible_to("sender_id", Some("u2")),
+        doc! { "$or": [{ "shadow": { "$ne": true } }, { "sender_id": "u2" }] }
+    );
+}
+
+#[test]
+fn test_echoes_to_the_author_drop_the_marker() {
+    let mut message = Message::new("room-1".to_string(), "u2".to_string(), "bob".to_string(), "buy now".to_string());
+    message.shadow = true;
+    assert!(serde_json::to_value(&message).unwrap().get("shadow").is_some());
+    match unmark_broadcast(WsMessage::NewMessage(message)) {
+        WsMessage::NewMessage(message) => assert!(serde_json::to_value(&message).unwrap().get("shadow").is_none()),
+        other => panic!("unexpected message: {:?}", other),
+    }
+}
@@ -0,0 +1,57 @@
+use chat_service::sessions::RESUME_WINDOW_SECONDS;
+use chat_service::shared_rooms::{coarse_area, is_location_room, shared_areas, PrivacySettings, SHARED_AREA_RESOLUTION};
+use chrono::{TimeZone, Utc};
+use h3o::{LatLng, Resolution};
+
+#[test]
+fn test_rooms_are_reported_as_coarse_areas() {
+    let fine = LatLng::new(40.7580, -73.9855).unwrap().to_cell(Resolution::Nine);
+    let area = coarse_area(&fine.to_string()).unwrap();
+    assert_eq!(area.resolution(), SHARED_AREA_RESOLUTION);
+    assert_eq!(fine.parent(SHARED_AREA_RESOLUTION), Some(area));
+
+    // Legacy coordinate rooms land in the same area
+    assert_eq!(coarse_area("40.758_-73.9855"), Some(area));
+    assert_eq!(coarse_area("not-a-room"), None);
+}
+
+#[test]
+fn test_only_rooms_both_users_visited_are_shared() {
+    let times_square = LatLng::new(40.7580, -73.9855).unwrap().to_cell(Resolution::Eight).to_string();
+    let brooklyn = LatLng::new(40.6782, -73.9442).unwrap().to_cell(Resolution::Eight).to_string();
+    let monday = Utc.with_ymd_and_hms(2025, 6, 2, 18, 30, 0).unwrap();
+    let friday = Utc.with_ymd_and_hms(2025, 6, 6, 9, 15, 0).unwrap();
+
+    let mine = vec![(times_square.clone(), friday), (brooklyn, friday)];
+    let theirs = vec![(times_square.clone(), monday)];
+
+    let areas = shared_areas(&mine, &theirs);
+    assert_eq!(areas.len(), 1);
+    assert_eq!(areas[0].h3_index, coarse_area(&times_square).unwrap().to_string());
+    // The overlap is as old as the earlier of the two visits
+    assert_eq!(areas[0].last_seen, monday.date_naive());
+}
+
+#[test]
+fn test_no_overlap_means_no_areas() {
+    let monday = Utc.with_ymd_and_hms(2025, 6, 2, 18, 30, 0).unwrap();
+    let mine = vec![("8a2a1072b59ffff".to_string(), monday)];
+
+    assert!(shared_areas(&mine, &[]).is_empty());
+}
+
+#[test]
+fn test_location_privacy_defaults_keep_history() {
+    let stored: PrivacySettings = serde_json::from_str(r#"{"_id": "u1", "share_crossed_paths": true}"#).unwrap();
+    assert!(stored.store_location_history);
+    assert_eq!(stored.retention_seconds(), RESUME_WINDOW_SECONDS);
+
+    let shortened = PrivacySettings { location_retention_secs: Some(0), ..PrivacySettings::new("u1") };
+    assert_eq!(shortened.retention_seconds(), 0);
+    let too_long = PrivacySettings { location_retention_secs: Some(86_400), ..PrivacySettings::new("u1") };
+    assert_eq!(too_long.retention_seconds(), RESUME_WINDOW_SECONDS);
+
+    assert!(is_location_room("40.7580_-73.9855"));
+    assert!(is_location_room("882a100d63fffff"));
+    assert!(!is_location_room("test-room"));
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+
+use chat_service::{shutdown::Shutdown, WsMessage};
+
+#[test]
+fn reconnect_delay_is_spread_and_stable_per_socket() {
+    let shutdown = Shutdown::new(Duration::from_secs(10), 5);
+    for i in 0..50 {
+        let socket_id = format!("socket-{}", i);
+        let after = shutdown.reconnect_after(&socket_id);
+        assert!((1..=5).contains(&after));
+        assert_eq!(after, shutdown.reconnect_after(&socket_id));
+    }
+    assert_eq!(Shutdown::new(Duration::from_secs(10), 0).reconnect_after("socket"), 1);
+}
+
+#[tokio::test]
+async fn beginning_shutdown_refuses_sockets_and_notifies_open_ones() {
+    let shutdown = Shutdown::default();
+    let mut notice = shutdown.notice("socket");
+    assert!(shutdown.admit().is_ok());
+    shutdown.begin();
+    assert!(shutdown.is_draining());
+    assert!(shutdown.admit().is_err());
+    tokio::time::timeout(Duration::from_secs(1), notice.draining()).await.unwrap();
+    // Sockets that open after shutdown began are notified right away
+    tokio::time::timeout(Duration::from_secs(1), shutdown.notice("late").draining()).await.unwrap();
+}
+
+#[test]
+fn server_shutdown_serializes_with_reconnect_delay() {
+    let message = serde_json::to_value(WsMessage::ServerShutdown { reconnect_after: 3 }).unwrap();
+    assert_eq!(message, serde_json::json!({"type": "ServerShutdown", "data": {"reconnect_after": 3}}));
+}
@@ -16,7 +16,7 @@ async fn test_rest_api_basic() {
     
     // Test get room info
     let response = client
-        .get(&format!("{}/api/rooms/test-room", base_url))
+        .get(format!("{}/api/rooms/test-room", base_url))
         .send()
         .await;
     
@@ -52,7 +52,7 @@ async fn test_message_api_basic() {
     });
     
     let response = client
-        .post(&format!("{}/api/messages", base_url))
+        .post(format!("{}/api/messages", base_url))
         .json(&message_payload)
         .send()
         .await;
@@ -66,7 +66,7 @@ async fn test_message_api_basic() {
             
             // Test get messages
             let get_response = client
-                .get(&format!("{}/api/messages/test-room?limit=10", base_url))
+                .get(format!("{}/api/messages/test-room?limit=10", base_url))
                 .send()
                 .await;
             
@@ -111,7 +111,7 @@ async fn test_join_room_api() {
     });
     
     let response = client
-        .post(&format!("{}/api/rooms/test-room/join", base_url))
+        .post(format!("{}/api/rooms/test-room/join", base_url))
         .json(&join_payload)
         .send()
         .await;
@@ -156,7 +156,7 @@ async fn test_concurrent_messages() {
             });
             
             let response = client
-                .post(&format!("{}/api/messages", base_url))
+                .post(format!("{}/api/messages", base_url))
                 .json(&message)
                 .send()
                 .await;
@@ -182,7 +182,7 @@ async fn test_concurrent_messages() {
         sleep(Duration::from_millis(500)).await;
         
         let response = client
-            .get(&format!("{}/api/messages/{}?limit=50", base_url, room_id))
+            .get(format!("{}/api/messages/{}?limit=50", base_url, room_id))
             .send()
             .await;
         
@@ -221,7 +221,7 @@ async fn test_error_handling() {
     
     // Test invalid JSON in REST API
     let response = client
-        .post(&format!("{}/api/messages", base_url))
+        .post(format!("{}/api/messages", base_url))
         .header("content-type", "application/json")
         .body("invalid json")
         .send()
@@ -245,7 +245,7 @@ async fn test_error_handling() {
     });
     
     let response = client
-        .post(&format!("{}/api/messages", base_url))
+        .post(format!("{}/api/messages", base_url))
         .json(&invalid_message)
         .send()
         .await;
@@ -259,6 +259,46 @@ async fn test_error_handling() {
     }
 }
 
+#[tokio::test]
+async fn test_top_messages_api() {
+    let client = HttpClient::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to create HTTP client");
+    
+    let base_url = "http://localhost:3000";
+    
+    let response = client
+        .get(format!("{}/api/rooms/test-room/top?window=24h", base_url))
+        .send()
+        .await;
+    
+    if let Ok(response) = response {
+        if response.status().is_success() {
+            let top: serde_json::Value = response.json().await.unwrap();
+            assert!(top["most_reacted"].is_array());
+            assert!(top["most_replied"].is_array());
+            println!("✅ GET top messages works");
+        } else {
+            println!("ℹ️  GET top messages failed: status {}", response.status());
+        }
+    } else {
+        println!("ℹ️  Cannot connect to chat service. Make sure it's running on port 3000");
+        return;
+    }
+    
+    // Unparseable windows are rejected
+    let response = client
+        .get(format!("{}/api/rooms/test-room/top?window=forever", base_url))
+        .send()
+        .await;
+    
+    if let Ok(response) = response {
+        assert_eq!(response.status(), 400);
+        println!("✅ Invalid highlight window properly rejected");
+    }
+}
+
 // Helper function to check if service is running
 async fn is_service_running() -> bool {
     let client = HttpClient::builder()
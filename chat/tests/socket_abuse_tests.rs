@@ -0,0 +1,50 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use chat_service::socket_abuse::{Penalty, SocketAbuse, SocketViolation, TarpitConfig};
+
+fn config() -> TarpitConfig {
+    TarpitConfig { tarpit_after: 2, disconnect_after: 6, ..TarpitConfig::default() }
+}
+
+#[test]
+fn test_penalties_grow_until_disconnect() {
+    let config = config();
+    let mut abuse = SocketAbuse::new(None);
+    assert_eq!(abuse.record(SocketViolation::MalformedFrame, &config), Penalty::None);
+    assert_eq!(abuse.record(SocketViolation::ProtocolError, &config), Penalty::None);
+    assert_eq!(abuse.record(SocketViolation::MalformedFrame, &config), Penalty::Delay(Duration::from_millis(250)));
+    assert_eq!(abuse.record(SocketViolation::MalformedFrame, &config), Penalty::Delay(Duration::from_millis(500)));
+    // Auth failures weigh double
+    assert_eq!(abuse.record(SocketViolation::AuthFailure, &config), Penalty::Disconnect);
+    assert_eq!(abuse.points(), 6);
+}
+
+#[test]
+fn test_delays_are_capped() {
+    let config = TarpitConfig { tarpit_after: 0, disconnect_after: 100, ..TarpitConfig::default() };
+    let mut abuse = SocketAbuse::new(None);
+    let mut last = Penalty::None;
+    for _ in 0..50 {
+        last = abuse.record(SocketViolation::MalformedFrame, &config);
+    }
+    assert_eq!(last, Penalty::Delay(Duration::from_secs(5)));
+}
+
+#[test]
+fn test_client_ip_comes_from_the_configured_header() {
+    let peer: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+    let mut headers = HeaderMap::new();
+    // The client sent the first entry; the proxy appended the second
+    headers.insert("x-forwarded-for", "198.51.100.9, 203.0.113.7".parse().unwrap());
+
+    assert_eq!(config().client_ip(&headers, Some(peer)), Some("10.0.0.1".parse::<IpAddr>().unwrap()));
+    let behind_proxy = TarpitConfig { client_ip_header: Some("x-forwarded-for".to_string()), ..config() };
+    assert_eq!(behind_proxy.client_ip(&headers, Some(peer)), Some("203.0.113.7".parse::<IpAddr>().unwrap()));
+    assert_eq!(behind_proxy.client_ip(&HeaderMap::new(), Some(peer)), None);
+
+    let cloudflare = TarpitConfig { client_ip_header: Some("cf-connecting-ip".to_string()), ..config() };
+    headers.insert("cf-connecting-ip", "203.0.113.8".parse().unwrap());
+    assert_eq!(cloudflare.client_ip(&headers, Some(peer)), Some("203.0.113.8".parse::<IpAddr>().unwrap()));
+}
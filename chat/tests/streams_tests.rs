@@ -0,0 +1,22 @@
+use chat_service::streams::{group_name, BroadcastBackend, StreamReader};
+
+#[test]
+fn test_backend_defaults_to_pubsub() {
+    assert_eq!(BroadcastBackend::default(), BroadcastBackend::PubSub);
+    assert_eq!(BroadcastBackend::parse("pubsub"), Some(BroadcastBackend::PubSub));
+    assert_eq!(BroadcastBackend::parse("streams"), Some(BroadcastBackend::Streams));
+    assert_eq!(BroadcastBackend::parse("kafka"), None);
+}
+
+#[test]
+fn test_each_instance_reads_through_its_own_group() {
+    assert_eq!(group_name("abc"), "instance:abc");
+    assert_ne!(group_name("abc"), group_name("def"));
+}
+
+#[test]
+fn test_reader_metrics_start_disconnected() {
+    let metrics = StreamReader::new("abc").render();
+    assert!(metrics.contains("chat_streams_connected 0"));
+    assert!(metrics.contains("chat_streams_recovered_total 0"));
+}
@@ -0,0 +1,39 @@
+use chat_service::models::{Message, WsMessage};
+use chat_service::subscription_filter::{mentions, FanoutFilter, SubscriptionFilter};
+
+fn new_message(content: &str) -> WsMessage {
+    WsMessage::NewMessage(Message::new("room1".to_string(), "user2".to_string(), "bob".to_string(), content.to_string()))
+}
+
+fn filter(mode: FanoutFilter) -> SubscriptionFilter {
+    SubscriptionFilter { mode, username: Some("alice".to_string()), muted: false }
+}
+
+#[test]
+fn test_messages_only_drops_typing_and_presence() {
+    let filter = filter(FanoutFilter::MessagesOnly);
+
+    assert!(!filter.allows(&WsMessage::Typing { is_typing: true }));
+    assert!(!filter.allows(&WsMessage::UserLeft { username: "bob".to_string(), timestamp: chrono::Utc::now() }));
+    assert!(filter.allows(&new_message("hello")));
+    assert!(filter.allows(&WsMessage::Error { message: "oops".to_string() }));
+}
+
+#[test]
+fn test_mentions_only_keeps_messages_that_mention_the_user() {
+    let filter = filter(FanoutFilter::MentionsOnly);
+
+    assert!(filter.allows(&new_message("@Alice are you coming?")));
+    assert!(!filter.allows(&new_message("anyone around?")));
+    // Nothing is a mention before the socket has joined
+    assert!(!SubscriptionFilter::new(FanoutFilter::MentionsOnly).allows(&new_message("@alice hi")));
+}
+
+#[test]
+fn test_mentions_match_whole_names_only() {
+    assert!(mentions("thanks @alice!", "alice"));
+    assert!(mentions("@alice", "alice"));
+    assert!(!mentions("@alice_b said hi", "alice"));
+    assert!(!mentions("email alice@example.com", "example"));
+    assert!(!mentions("hello alice", "alice"));
+}
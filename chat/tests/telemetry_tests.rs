@@ -0,0 +1,21 @@
+use chat_service::telemetry::{current_context, fanout_span, is_exporting, TraceContext};
+
+#[test]
+fn test_nothing_is_propagated_without_an_exporter() {
+    assert!(!is_exporting());
+    assert!(current_context().is_empty());
+}
+
+#[test]
+fn test_fanout_spans_need_an_exporter() {
+    let payload = r#"{"from_socket_id":"","trace_context":{"traceparent":"00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"},"message":{"type":"Typing","is_typing":true}}"#;
+    assert!(fanout_span("room:r1", payload).is_none());
+}
+
+#[test]
+fn test_trace_context_round_trips_as_json() {
+    let mut context = TraceContext::new();
+    context.insert("traceparent".into(), "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".into());
+    let json = serde_json::to_string(&context).unwrap();
+    assert_eq!(serde_json::from_str::<TraceContext>(&json).unwrap(), context);
+}
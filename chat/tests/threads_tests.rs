@@ -0,0 +1,61 @@
+use std::sync::{Arc, RwLock};
+
+use chat_service::fanout::{Delivery, Subscriber};
+use chat_service::models::{Message, WsMessage};
+use chat_service::send_buffer::{SendBuffers, SlowConsumerPolicy};
+use chat_service::subscription_filter::{FanoutFilter, SubscriptionFilter};
+use chat_service::threads::{resolve_parent, thread_root, ThreadError};
+use chat_service::AppState;
+use mongodb::bson::oid::ObjectId;
+
+fn message(content: &str) -> Message {
+    let mut message = Message::new("882a100d63fffff".to_string(), "u1".to_string(), "alice".to_string(), content.to_string());
+    message.id = Some(ObjectId::new());
+    message
+}
+
+#[test]
+fn test_replies_to_replies_stay_in_the_thread() {
+    let parent = message("anyone at the market?");
+    let root = parent.id.unwrap().to_hex();
+    assert_eq!(thread_root(&parent), Some(root.clone()));
+
+    let mut reply = message("yes, busy today");
+    reply.parent_id = Some(root.clone());
+    assert_eq!(thread_root(&reply), Some(root));
+}
+
+#[test]
+fn test_replies_are_not_forwarded_to_neighbors() {
+    let buffers = Arc::new(SendBuffers::new(8, SlowConsumerPolicy::Drop));
+    let (tx, mut rx) = buffers.channel();
+    let filter = Arc::new(RwLock::new(SubscriptionFilter::new(FanoutFilter::All)));
+    let subscriber = Subscriber::neighbor(tx, filter, "882a100d67fffff".to_string());
+
+    let mut reply = message("yes, busy today");
+    reply.parent_id = Some(ObjectId::new().to_hex());
+    assert_eq!(subscriber.deliver(WsMessage::NewMessage(reply)), Delivery::Filtered);
+    let updated = WsMessage::ThreadUpdated { parent_id: ObjectId::new().to_hex(), reply_count: 2, last_reply_at: chrono::Utc::now() };
+    assert_eq!(subscriber.deliver(updated), Delivery::Filtered);
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn test_thread_updates_follow_the_subscription_filter() {
+    let updated = || WsMessage::ThreadUpdated { parent_id: ObjectId::new().to_hex(), reply_count: 1, last_reply_at: chrono::Utc::now() };
+    assert!(SubscriptionFilter::new(FanoutFilter::MessagesOnly).allows(&updated()));
+    assert!(!SubscriptionFilter::new(FanoutFilter::MentionsOnly).allows(&updated()));
+}
+
+#[tokio::test]
+async fn test_replies_to_parents_that_cant_be_checked_are_refused() {
+    // Room posts take the same check as hex ones, so a reply can't point at
+    // a message it can't be shown to be in the same room as
+    let state = AppState::new("mongodb://127.0.0.1:1/?serverSelectionTimeoutMS=50", "redis://127.0.0.1:1", "threads_tests")
+        .await
+        .unwrap();
+    for parent_id in ["not-an-id".to_string(), ObjectId::new().to_hex()] {
+        let result = resolve_parent(&state, "40.7128_-74.0060", &parent_id).await;
+        assert!(matches!(result, Err(ThreadError::ParentNotFound)));
+    }
+}
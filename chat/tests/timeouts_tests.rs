@@ -0,0 +1,36 @@
+use chat_service::abuse::{AbuseScore, AbuseSignal, AbuseTier};
+use chat_service::models::WsMessage;
+use chat_service::timeouts::{timeout_until, MAX_TIMEOUT_SECONDS, MIN_TIMEOUT_SECONDS};
+use chrono::{Duration, TimeZone, Utc};
+
+#[test]
+fn test_timeouts_end_after_their_duration() {
+    let now = Utc::now();
+    assert_eq!(timeout_until(600, now), Ok(now + Duration::minutes(10)));
+}
+
+#[test]
+fn test_timeout_durations_are_bounded() {
+    let now = Utc::now();
+    assert!(timeout_until(MIN_TIMEOUT_SECONDS, now).is_ok());
+    assert!(timeout_until(MAX_TIMEOUT_SECONDS, now).is_ok());
+    assert!(timeout_until(MIN_TIMEOUT_SECONDS - 1, now).is_err());
+    assert!(timeout_until(MAX_TIMEOUT_SECONDS + 1, now).is_err());
+}
+
+#[test]
+fn test_muted_refusal_says_until_when() {
+    let until = Utc.with_ymd_and_hms(2026, 10, 17, 12, 30, 0).unwrap();
+    let json = serde_json::to_value(WsMessage::Muted { until }).unwrap();
+    assert_eq!(json["type"], "Muted");
+    assert_eq!(json["data"]["until"], "2026-10-17T12:30:00Z");
+}
+
+// The mute handler records AbuseSignal::Muted for the muted user
+#[test]
+fn test_a_timeout_raises_the_abuse_score() {
+    let before = AbuseScore::from_counts("u2", 1, 2, 0);
+    let after = AbuseScore::from_counts("u2", 1, 2, 1);
+    assert_eq!(after.score, before.score + AbuseSignal::Muted.weight());
+    assert_eq!((before.tier(), after.tier()), (AbuseTier::Normal, AbuseTier::Elevated));
+}
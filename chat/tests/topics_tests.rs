@@ -0,0 +1,45 @@
+use chat_service::models::Message;
+use chat_service::topics::{KeywordSummarizer, Summarizer};
+
+fn message(user_id: &str, content: &str) -> Message {
+    Message::new("room-1".to_string(), user_id.to_string(), user_id.to_string(), content.to_string())
+}
+
+#[tokio::test]
+async fn test_keywords_summarize_what_the_room_repeats() {
+    let messages = vec![
+        message("u1", "Is the parade still on?"),
+        message("u2", "Yeah the parade just hit Main St, traffic is bad"),
+        message("u3", "Traffic on the bridge too"),
+        message("u1", "Anyone know where the parade ends?"),
+        message("u4", "Food trucks by the bridge!"),
+    ];
+
+    assert_eq!(KeywordSummarizer::keywords(&messages), vec!["parade", "traffic", "bridge"]);
+    assert_eq!(KeywordSummarizer.summarize(&messages).await.as_deref(), Some("parade, traffic and bridge"));
+}
+
+#[test]
+fn test_words_count_once_per_message() {
+    let messages = vec![
+        message("u1", "spam spam spam spam"),
+        message("u2", "coffee?"),
+        message("u3", "coffee at the corner place"),
+    ];
+
+    assert_eq!(KeywordSummarizer::keywords(&messages), vec!["coffee"]);
+}
+
+#[tokio::test]
+async fn test_no_topic_without_repeated_words() {
+    let mut deleted = message("u2", "concert concert");
+    deleted.deleted = true;
+    let messages = vec![
+        message("u1", "concert tonight @everyone https://tickets.example/concert"),
+        deleted,
+        message("u3", "hey hey what's up"),
+    ];
+
+    assert!(KeywordSummarizer::keywords(&messages).is_empty());
+    assert_eq!(KeywordSummarizer.summarize(&messages).await, None);
+}
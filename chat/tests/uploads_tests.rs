@@ -0,0 +1,76 @@
+use chat_service::uploads::{check_attachments, upload_prefix, uri_encode, Attachment, AttachmentKind, UploadConfig, MAX_IMAGE_BYTES};
+use chrono::{TimeZone, Utc};
+
+fn config() -> UploadConfig {
+    UploadConfig {
+        endpoint: "https://s3.us-east-1.amazonaws.com".to_string(),
+        bucket: "tapin-uploads".to_string(),
+        region: "us-east-1".to_string(),
+        access_key_id: "AKIDEXAMPLE".to_string(),
+        secret_access_key: "secret".to_string(),
+        public_url: "https://cdn.example.com".to_string(),
+        max_bytes: 25 * 1024 * 1024,
+    }
+}
+
+fn image(url: &str) -> Attachment {
+    Attachment {
+        kind: AttachmentKind::Image,
+        url: url.to_string(),
+        content_type: "image/png".to_string(),
+        size: 2048,
+        width: Some(640),
+        height: Some(480),
+    }
+}
+
+#[test]
+fn test_uploads_are_validated_by_type_and_size() {
+    let config = config();
+    assert_eq!(config.validate("image/PNG", 1024, Some(10), Some(10)), Ok(AttachmentKind::Image));
+    assert!(config.validate("application/x-msdownload", 1024, None, None).is_err());
+    assert!(config.validate("image/jpeg", MAX_IMAGE_BYTES + 1, None, None).is_err());
+    assert!(config.validate("video/mp4", MAX_IMAGE_BYTES + 1, None, None).is_ok());
+    assert!(config.validate("application/pdf", 1024, Some(10), None).is_err());
+    assert!(config.validate("text/plain", 0, None, None).is_err());
+}
+
+#[test]
+fn test_attachments_must_be_the_senders_own_uploads() {
+    let config = config();
+    let own = image(&format!("https://cdn.example.com/{}abc.png", upload_prefix("u1")));
+    assert!(check_attachments(Some(&config), std::slice::from_ref(&own), "u1").is_ok());
+    assert!(check_attachments(Some(&config), std::slice::from_ref(&own), "u2").is_err());
+    assert!(check_attachments(Some(&config), &[image("https://evil.example.com/x.png")], "u1").is_err());
+    // Paths that leave the user's prefix
+    for escape in ["../7532/abc.png", "%2e%2e/7532/abc.png", "sub/abc.png", ""] {
+        let url = format!("https://cdn.example.com/{}{}", upload_prefix("u1"), escape);
+        assert!(check_attachments(Some(&config), &[image(&url)], "u1").is_err(), "{}", url);
+    }
+
+    let mislabelled = Attachment { kind: AttachmentKind::File, ..own.clone() };
+    assert!(check_attachments(Some(&config), &[mislabelled], "u1").is_err());
+
+    assert!(check_attachments(None, &[own], "u1").is_err());
+    assert!(check_attachments(None, &[], "u1").is_ok());
+}
+
+#[test]
+fn test_presigned_urls_sign_the_upload() {
+    let config = config();
+    let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+    let url = config.presign_put("uploads/u1/abc.png", "image/png", 2048, now).unwrap();
+    assert!(url.starts_with("https://s3.us-east-1.amazonaws.com/tapin-uploads/uploads/u1/abc.png?"));
+    assert!(url.contains("X-Amz-Credential=AKIDEXAMPLE%2F20240501%2Fus-east-1%2Fs3%2Faws4_request"));
+    assert!(url.contains("X-Amz-SignedHeaders=content-length%3Bcontent-type%3Bhost"));
+    let signature = url.rsplit_once("X-Amz-Signature=").unwrap().1;
+    assert_eq!(signature.len(), 64);
+    // Any change to what was signed changes the signature
+    let other = config.presign_put("uploads/u1/abc.png", "image/png", 4096, now).unwrap();
+    assert_ne!(url, other);
+
+    assert_eq!(uri_encode("a b/c~", true), "a%20b/c~");
+    assert_eq!(upload_prefix("user@example.com"), "uploads/75736572406578616d706c652e636f6d/");
+    // Ids that used to share a prefix no longer do
+    assert_ne!(upload_prefix("a.b"), upload_prefix("a_b"));
+}
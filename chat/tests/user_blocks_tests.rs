@@ -0,0 +1,43 @@
+use std::collections::HashSet;
+
+use chat_service::models::{Message, WsMessage};
+use chat_service::user_blocks::{is_from_blocked, BlockRequest};
+
+fn request(user_id: &str) -> BlockRequest {
+    BlockRequest { user_id: user_id.to_string() }
+}
+
+fn new_message_from(user_id: &str) -> String {
+    let message = Message::new("dm_a_b".to_string(), user_id.to_string(), user_id.to_string(), "hi".to_string());
+    serde_json::to_string(&WsMessage::NewMessage(message)).unwrap()
+}
+
+#[test]
+fn test_blocks_are_keyed_by_blocker_and_blocked() {
+    let block = request("  u2 ").into_block("u1").unwrap();
+    assert_eq!(block.id, "u1:u2");
+    assert_eq!(block.blocker_id, "u1");
+    assert_eq!(block.blocked_id, "u2");
+}
+
+#[test]
+fn test_block_requests_are_checked() {
+    assert!(request("").into_block("u1").is_err());
+    assert!(request("u1").into_block("u1").is_err());
+}
+
+#[test]
+fn test_messages_from_blocked_users_are_dropped() {
+    let blocked: HashSet<String> = ["u2".to_string()].into();
+    assert!(is_from_blocked(&new_message_from("u2"), &blocked));
+    assert!(!is_from_blocked(&new_message_from("u3"), &blocked));
+    assert!(!is_from_blocked(&new_message_from("u2"), &HashSet::new()));
+
+    let removed = WsMessage::DMParticipantRemoved {
+        conversation_id: "c".to_string(),
+        user_id: "u2".to_string(),
+        removed_by: "u2".to_string(),
+        participant_count: 2,
+    };
+    assert!(!is_from_blocked(&serde_json::to_string(&removed).unwrap(), &blocked));
+}
@@ -0,0 +1,31 @@
+use chat_service::event_log::LogEvent;
+use chat_service::user_erasure::{dm_tombstone, last_message_tombstone, UserErased, ERASED_CHANNEL, ERASED_USERNAME};
+use chrono::Utc;
+
+#[test]
+fn test_erased_dms_are_emptied() {
+    let tombstone = dm_tombstone();
+    assert_eq!(tombstone.get_str("content").unwrap(), "");
+    assert_eq!(tombstone.get_str("sender_username").unwrap(), ERASED_USERNAME);
+    assert!(tombstone.get_bool("deleted").unwrap());
+    assert!(tombstone.get_array("attachments").unwrap().is_empty());
+
+    let last_message = last_message_tombstone();
+    assert_eq!(last_message.len(), tombstone.len());
+    assert_eq!(last_message.get_str("last_message.content").unwrap(), "");
+}
+
+#[test]
+fn test_erasures_are_published_on_the_user_channel_namespace() {
+    // The shared pub/sub connection only hears `user:*` and the like
+    assert!(ERASED_CHANNEL.starts_with("user:"));
+    let erased = UserErased { user_id: "alice".to_string(), erased_at: Utc::now() };
+    let payload = serde_json::to_string(&erased).unwrap();
+    assert_eq!(serde_json::from_str::<UserErased>(&payload).unwrap(), erased);
+}
+
+#[test]
+fn test_erasure_is_logged() {
+    let event = serde_json::to_value(LogEvent::UserErased { user_id: "alice".to_string() }).unwrap();
+    assert_eq!(event, serde_json::json!({ "type": "user_erased", "user_id": "alice" }));
+}
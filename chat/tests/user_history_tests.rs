@@ -0,0 +1,48 @@
+use chat_service::models::{DirectMessage, Message};
+use chat_service::user_history::{merge_history, HistoryItem, HistorySource};
+use chrono::{Duration, Utc};
+
+fn room_message(minutes_ago: i64) -> HistoryItem {
+    let mut message = Message::new("room1".to_string(), "u1".to_string(), "alice".to_string(), "room".to_string());
+    message.timestamp = Utc::now() - Duration::minutes(minutes_ago);
+    HistoryItem::from(message)
+}
+
+fn direct_message(minutes_ago: i64) -> HistoryItem {
+    HistoryItem::from(DirectMessage {
+        id: None,
+        conversation_id: "dm_u1_u2".to_string(),
+        sender_id: "u1".to_string(),
+        sender_username: "alice".to_string(),
+        content: "dm".to_string(),
+        timestamp: Utc::now() - Duration::minutes(minutes_ago),
+        edited_at: None,
+        deleted: false,
+        read_by: vec![],
+        delivered_to: vec![],
+        attachments: vec![],
+        shadow: false,
+    })
+}
+
+#[test]
+fn test_dms_are_labelled_with_their_conversation() {
+    let item = direct_message(0);
+    assert_eq!(item.source, HistorySource::Dm);
+    assert_eq!(item.room_id, "dm_u1_u2");
+    assert_eq!(room_message(0).source, HistorySource::Room);
+}
+
+#[test]
+fn test_history_is_interleaved_newest_first() {
+    let merged = merge_history(vec![room_message(1), room_message(30)], vec![direct_message(10)], 10);
+    let order: Vec<&str> = merged.iter().map(|item| item.content.as_str()).collect();
+    assert_eq!(order, vec!["room", "dm", "room"]);
+}
+
+#[test]
+fn test_history_is_capped_at_the_limit() {
+    let merged = merge_history(vec![room_message(1), room_message(2)], vec![direct_message(3)], 2);
+    assert_eq!(merged.len(), 2);
+    assert!(merged.iter().all(|item| item.source == HistorySource::Room));
+}
@@ -0,0 +1,35 @@
+use chat_service::user_presence::{presence_of, unique_ids, OnlineUsers};
+
+#[test]
+fn test_users_stay_online_until_their_last_socket_closes() {
+    let online = OnlineUsers::default();
+    assert!(online.connect("s1", "u1"));
+    assert!(!online.connect("s2", "u1"));
+    assert!(!online.connect("s1", "u1"));
+    assert!(online.connect("s3", "u2"));
+
+    assert_eq!(online.disconnect("s1"), None);
+    assert!(online.is_online("u1"));
+    assert_eq!(online.disconnect("s2"), Some("u1".to_string()));
+    assert!(!online.is_online("u1"));
+    assert_eq!(online.disconnect("s2"), None);
+    assert_eq!(online.user_ids(), vec!["u2".to_string()]);
+}
+
+#[test]
+fn test_presence_keeps_request_order() {
+    let ids = vec!["u2".to_string(), "u1".to_string(), "u3".to_string()];
+    let users = presence_of(ids, vec![Some(1_700_000_000_000), None, None], vec![Some(1_700_000_000_000), Some(1_600_000_000_000), None]);
+
+    assert_eq!(users.iter().map(|user| user.user_id.as_str()).collect::<Vec<_>>(), ["u2", "u1", "u3"]);
+    assert!(users[0].online);
+    assert!(!users[1].online);
+    assert_eq!(users[1].last_seen.map(|seen| seen.timestamp_millis()), Some(1_600_000_000_000));
+    assert_eq!(users[2].last_seen, None);
+}
+
+#[test]
+fn test_repeated_and_blank_ids_are_dropped() {
+    let ids = ["u1", " u2 ", "u1", "", "u2"].map(str::to_string).to_vec();
+    assert_eq!(unique_ids(ids), vec!["u1".to_string(), "u2".to_string()]);
+}
@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+use chat_service::user_service::{ProfileCache, ServiceHealth, UserProfile, FAILURE_THRESHOLD, PROFILE_FRESH_FOR};
+
+fn profile() -> UserProfile {
+    UserProfile {
+        user_id: "u1".to_string(),
+        username: "alice".to_string(),
+        display_name: None,
+        avatar_url: None,
+        badge: None,
+    }
+}
+
+#[test]
+fn test_degraded_after_repeated_failures_until_a_success() {
+    let health = ServiceHealth::default();
+    for _ in 1..FAILURE_THRESHOLD {
+        health.record_failure();
+    }
+    assert!(!health.is_degraded());
+    health.record_failure();
+    assert!(health.is_degraded());
+    health.record_success();
+    assert!(!health.is_degraded());
+}
+
+#[test]
+fn test_a_success_resets_the_failure_streak() {
+    let health = ServiceHealth::default();
+    for _ in 1..FAILURE_THRESHOLD {
+        health.record_failure();
+    }
+    health.record_success();
+    health.record_failure();
+    assert!(!health.is_degraded());
+}
+
+#[test]
+fn test_stale_profiles_are_kept_for_degraded_mode() {
+    let mut cache = ProfileCache::default();
+    let fetched_at = Instant::now();
+    cache.insert(profile(), fetched_at);
+    assert_eq!(cache.fresh("u1", fetched_at), Some(&profile()));
+
+    let later = fetched_at + PROFILE_FRESH_FOR + Duration::from_secs(1);
+    assert_eq!(cache.fresh("u1", later), None);
+    assert_eq!(cache.any_age("u1"), Some(&profile()));
+    assert_eq!(cache.any_age("u2"), None);
+}
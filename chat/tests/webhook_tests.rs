@@ -0,0 +1,32 @@
+use chat_service::webhooks::{sign, verify_signature, SIGNATURE_TOLERANCE_SECONDS};
+
+const SECRET: &str = "4f1c2a9e0b7d4e3f8a6c5b2d1e0f9a8b";
+const BODY: &[u8] = br#"{"content":"Road closed on 5th Ave until 6pm"}"#;
+
+#[test]
+fn test_valid_signature_is_accepted() {
+    let signature = sign(SECRET, "1700000000", BODY);
+
+    assert!(signature.starts_with("sha256="));
+    assert!(verify_signature(SECRET, "1700000000", BODY, &signature, 1700000030));
+}
+
+#[test]
+fn test_tampered_requests_are_rejected() {
+    let signature = sign(SECRET, "1700000000", BODY);
+
+    assert!(!verify_signature("other-secret", "1700000000", BODY, &signature, 1700000000));
+    assert!(!verify_signature(SECRET, "1700000000", b"{\"content\":\"spam\"}", &signature, 1700000000));
+    assert!(!verify_signature(SECRET, "1700000001", BODY, &signature, 1700000000));
+    assert!(!verify_signature(SECRET, "1700000000", BODY, "sha256=zz", 1700000000));
+    assert!(!verify_signature(SECRET, "1700000000", BODY, "", 1700000000));
+}
+
+#[test]
+fn test_stale_timestamps_are_rejected() {
+    let signature = sign(SECRET, "1700000000", BODY);
+    let too_late = 1700000000 + SIGNATURE_TOLERANCE_SECONDS + 1;
+
+    assert!(!verify_signature(SECRET, "1700000000", BODY, &signature, too_late));
+    assert!(!verify_signature(SECRET, "not-a-time", BODY, &signature, 1700000000));
+}
@@ -0,0 +1,58 @@
+use axum::extract::ws::Message as WsMsg;
+use axum::http::HeaderMap;
+use chat_service::models::{Message, WsMessage};
+use chat_service::wire_format::WireFormat;
+
+#[test]
+fn test_msgpack_is_negotiated_by_query_or_subprotocol() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(WireFormat::negotiate(None, &headers), WireFormat::Json);
+    assert_eq!(WireFormat::negotiate(Some("json"), &headers), WireFormat::Json);
+    assert_eq!(WireFormat::negotiate(Some("msgpack"), &headers), WireFormat::MessagePack);
+
+    headers.insert("sec-websocket-protocol", "chat, tapin.msgpack".parse().unwrap());
+    assert_eq!(WireFormat::negotiate(None, &headers), WireFormat::MessagePack);
+}
+
+#[test]
+fn test_msgpack_frames_round_trip() {
+    let mut message = Message::new("room-1".to_string(), "u1".to_string(), "alice".to_string(), "hello".to_string());
+    message.id = Some(mongodb::bson::oid::ObjectId::new());
+    let frame = WireFormat::MessagePack.encode(&WsMessage::NewMessage(message.clone())).unwrap();
+    assert!(matches!(frame, WsMsg::Binary(_)));
+
+    match WireFormat::decode(&frame) {
+        Some(WsMessage::NewMessage(decoded)) => {
+            assert_eq!(decoded.id, message.id);
+            assert_eq!(decoded.content, "hello");
+            assert_eq!(decoded.timestamp.timestamp_millis(), message.timestamp.timestamp_millis());
+        }
+        other => panic!("unexpected frame: {:?}", other),
+    }
+}
+
+#[test]
+fn test_client_frames_decode_by_frame_type() {
+    let text = WsMsg::Text(r#"{"type":"Typing","data":{"is_typing":true}}"#.to_string());
+    assert!(matches!(WireFormat::decode(&text), Some(WsMessage::Typing { is_typing: true })));
+
+    let binary = WireFormat::MessagePack.encode(&WsMessage::Typing { is_typing: false }).unwrap();
+    assert!(matches!(WireFormat::decode(&binary), Some(WsMessage::Typing { is_typing: false })));
+    assert!(WireFormat::decode(&WsMsg::Binary(vec![0xc1])).is_none());
+}
+
+#[test]
+fn test_frames_are_stamped_with_the_server_clock() {
+    let mut message = Message::new("room-1".to_string(), "u1".to_string(), "alice".to_string(), "hello".to_string());
+    message.client_ts = Some(1_700_000_000_000);
+    let WsMsg::Text(text) = WireFormat::Json.encode_at(&WsMessage::NewMessage(message), 1_700_000_000_250).unwrap() else {
+        panic!("expected a text frame");
+    };
+    let value: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(value["type"], "NewMessage");
+    assert_eq!(value["server_ts"], 1_700_000_000_250i64);
+    assert_eq!(value["data"]["client_ts"], 1_700_000_000_000i64);
+
+    // Clients sending the stamp back don't trip up decoding
+    assert!(matches!(WireFormat::decode(&WsMsg::Text(text)), Some(WsMessage::NewMessage(_))));
+}
@@ -0,0 +1,62 @@
+use chat_service::auth::{verify_token, AuthUser, Claims};
+use chat_service::ws_ticket::{JoinTicket, TicketError};
+use jsonwebtoken::{encode, EncodingKey, Header};
+
+fn moderator() -> AuthUser {
+    AuthUser {
+        user_id: "u1".into(),
+        email: "alice@example.com".into(),
+        username: "alice".into(),
+        roles: vec!["moderator".into()],
+        age_verified: true,
+        badge: None,
+    }
+}
+
+fn secret() -> String {
+    std::env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key-here".to_string())
+}
+
+#[test]
+fn test_tickets_carry_the_callers_permissions() {
+    let ticket = JoinTicket::issue(&moderator(), "882a100d63fffff");
+    let verified = JoinTicket::verify(&ticket.sign().unwrap()).unwrap();
+    assert_eq!(verified, ticket);
+
+    let caller = verified.caller();
+    assert!(caller.is_moderator() && caller.age_verified);
+    assert_eq!(caller.user_id, "u1");
+    // The ticket sits in a URL, so it leaves the email out
+    assert!(caller.email.is_empty());
+}
+
+#[test]
+fn test_tickets_and_jwts_are_not_interchangeable() {
+    let ticket = JoinTicket::issue(&moderator(), "882a100d63fffff").sign().unwrap();
+    assert!(verify_token(&ticket).is_err());
+
+    let claims = Claims {
+        user_id: "u1".into(),
+        email: "alice@example.com".into(),
+        username: "alice".into(),
+        exp: (chrono::Utc::now().timestamp() + 3600) as usize,
+        iat: None,
+        jti: None,
+        roles: vec![],
+        age_verified: false,
+        badge: None,
+    };
+    let jwt = encode(&Header::default(), &claims, &EncodingKey::from_secret(secret().as_bytes())).unwrap();
+    assert_eq!(JoinTicket::verify(&jwt), Err(TicketError::Invalid));
+}
+
+#[test]
+fn test_expired_or_forged_tickets_are_refused() {
+    let mut ticket = JoinTicket::issue(&moderator(), "882a100d63fffff");
+    ticket.exp = (chrono::Utc::now().timestamp() - 3600) as usize;
+    assert_eq!(JoinTicket::verify(&ticket.sign().unwrap()), Err(TicketError::Invalid));
+
+    let ticket = JoinTicket::issue(&moderator(), "882a100d63fffff");
+    let forged = encode(&Header::default(), &ticket, &EncodingKey::from_secret(b"not-the-secret")).unwrap();
+    assert_eq!(JoinTicket::verify(&forged), Err(TicketError::Invalid));
+}